@@ -3,6 +3,7 @@
 #include <fstream>
 #include <iostream>
 #include <iterator>
+#include <mutex>
 #include <set>
 #include <unordered_map>
 #include <unordered_set>
@@ -16,6 +17,7 @@
 #include "clang/Tooling/CommonOptionsParser.h"
 
 #include "clang/AST/DeclVisitor.h"
+#include "clang/AST/Mangle.h"
 #include "clang/AST/RecordLayout.h"
 #include "clang/AST/RecursiveASTVisitor.h"
 #include "clang/AST/StmtVisitor.h"
@@ -575,6 +577,12 @@ class TranslateASTVisitor final
     SmallVector<MacroInfo*, 1> curMacroExpansionStack;
     StringRef curMacroExpansionSource;
 
+    // Mangles the names of C++ functions and variables
+    std::unique_ptr<MangleContext> mangler;
+
+    // Instantiations of C++ class templates which were reported
+    std::unordered_set<Decl *> reportedInstantiations;
+
     // Returns true when a new entry is added to exportedTags
     bool markForExport(void *ptr, ASTEntryTag tag) {
         return exportedTags.emplace(ptr, tag).second;
@@ -812,7 +820,7 @@ class TranslateASTVisitor final
                                  Preprocessor &PP)
         : Context(Context), typeEncoder(Context, encoder, sugared, this),
           encoder(encoder), PP(PP),
-          files{{"", {}}} {}
+          files{{"", {}}}, mangler(Context->createMangleContext()) {}
 
     // Override the default behavior of the RecursiveASTVisitor
     bool shouldVisitImplicitCode() const { return true; }
@@ -865,6 +873,114 @@ class TranslateASTVisitor final
         }
     }
 
+    bool wasExported(Decl *D) {
+        auto search = exportedTags.lower_bound(
+            std::make_pair((void *)D, (ASTEntryTag)0));
+        return search != std::end(exportedTags) && search->first == D;
+    }
+
+    // Collect the exported declarations of a declaration context, looking
+    // through `extern "C"` blocks, and anonymous and inline namespaces. The
+    // member functions of C++ classes are collected after their class, and the
+    // declarations of named namespaces only if `intoNamespaces` is set.
+    void collectDecls(DeclContext *DC, bool intoNamespaces,
+                      std::vector<void *> &decls) {
+        for (auto d : DC->decls()) {
+            if (isa<LinkageSpecDecl>(d)) {
+                collectDecls(cast<DeclContext>(d), intoNamespaces, decls);
+                continue;
+            }
+            if (auto ND = dyn_cast<NamespaceDecl>(d)) {
+                if (intoNamespaces || ND->isAnonymousNamespace() ||
+                    ND->isInline())
+                    collectDecls(ND, intoNamespaces, decls);
+                continue;
+            }
+
+            if (!d->isCanonicalDecl() && isa<VarDecl>(d)) {
+                auto canonical_decl = d->getCanonicalDecl();
+                auto var_decl = cast<VarDecl>(canonical_decl);
+
+                // Non-Canonical Decls which don't have an extern local canonical decl
+                // should be skipped
+                if (!(var_decl->isExternC() && var_decl->isLocalVarDecl())) {
+                    continue;
+                }
+            }
+
+            // Empty-decls aren't exported. This avoids warnings during conversion.
+            // Neither are C++ declarations outside of the supported subset,
+            // like templates and `using` directives.
+            if (isa<EmptyDecl>(d) || !wasExported(d)) {
+                continue;
+            }
+
+            decls.push_back(d);
+
+            if (auto RD = dyn_cast<CXXRecordDecl>(d)) {
+                for (auto M : RD->methods()) {
+                    if (wasExported(M))
+                        decls.push_back(M);
+                }
+            }
+        }
+    }
+
+    /*
+     Represents a C++ namespace, for every `namespace` block outside of system
+     headers. Anonymous and inline namespaces aren't encoded, their
+     declarations belong to the enclosing namespace.
+     Children:
+     - declarations of the block, see `collectDecls`
+     Extras:
+     - path of the namespace, as an array of names
+     */
+    void encodeNamespaces(DeclContext *DC, std::vector<std::string> path) {
+        for (auto d : DC->decls()) {
+            if (isa<LinkageSpecDecl>(d)) {
+                encodeNamespaces(cast<DeclContext>(d), path);
+                continue;
+            }
+            auto ND = dyn_cast<NamespaceDecl>(d);
+            if (!ND || !isInUserCode(ND->getLocation()))
+                continue;
+
+            auto nested = path;
+            if (!ND->isAnonymousNamespace() && !ND->isInline()) {
+                nested.push_back(ND->getNameAsString());
+
+                std::vector<void *> childIds;
+                collectDecls(ND, false, childIds);
+                encode_entry(ND, TagNamespaceDecl, childIds, QualType(),
+                             [&nested](CborEncoder *local) {
+                                 cbor_encode_string_array(local, nested);
+                             });
+            }
+            encodeNamespaces(ND, nested);
+        }
+    }
+
+    // Encode the mangled name of a C++ function or variable, or null if it
+    // isn't mangled, like the names of C functions.
+    void encodeLinkName(CborEncoder *enc, NamedDecl *D) {
+        if (!Context->getLangOpts().CPlusPlus ||
+            !mangler->shouldMangleDeclName(D)) {
+            cbor_encode_null(enc);
+            return;
+        }
+
+        std::string name;
+        llvm::raw_string_ostream os(name);
+#if CLANG_VERSION_MAJOR < 11
+        mangler->mangleName(D, os);
+#else
+        auto FD = dyn_cast<FunctionDecl>(D);
+        mangler->mangleName(FD ? GlobalDecl(FD) : GlobalDecl(cast<VarDecl>(D)),
+                            os);
+#endif // CLANG_VERSION_MAJOR
+        cbor_encode_string(enc, os.str());
+    }
+
     void encodeSourcePos(CborEncoder *enc, SourceLocation loc,
                          bool isVaList = false) {
         auto &manager = Context->getSourceManager();
@@ -1364,10 +1480,39 @@ class TranslateASTVisitor final
 
         auto value = getIntegerConstantExpr(*E, *this->Context);
 
+        // A constant offsetof of a field, or of a field of a nested struct
+        // member, also gets its fields encoded, so that it can be translated
+        // to offset_of! instead of a target-specific number.
+        std::vector<FieldDecl *> fields;
+        if (value) {
+            for (unsigned i = 0; i < E->getNumComponents(); i++) {
+                auto component = E->getComponent(i);
+                if (component.getKind() != OffsetOfNode::Field) {
+                    fields.clear();
+                    break;
+                }
+                fields.push_back(component.getField()->getCanonicalDecl());
+            }
+        }
+
         encode_entry(
-            E, TagOffsetOfExpr, childIds, [this, E, value](CborEncoder *extras) {
+            E, TagOffsetOfExpr, childIds,
+            [this, E, value, &fields](CborEncoder *extras) {
                 if (value) {
                     cbor_encode_uint(extras, value->getZExtValue());
+                    if (!fields.empty()) {
+                        auto ty = E->getTypeSourceInfo()->getType();
+                        cbor_encode_uint(extras,
+                                         typeEncoder.encodeQualType(ty));
+
+                        CborEncoder array;
+                        cbor_encoder_create_array(extras, &array,
+                                                  fields.size());
+                        for (auto field : fields) {
+                            cbor_encode_uint(&array, uintptr_t(field));
+                        }
+                        cbor_encoder_close_container(extras, &array);
+                    }
                 } else {
                     // It's possible to get a non ICE in a field array like so:
                     // offsetof(S, field[idx]) so here we are encoding the type,
@@ -1398,7 +1543,7 @@ class TranslateASTVisitor final
 
         // If this is the only use of the struct type, we need to ensure that it
         // gets visited.
-        if (!value) {
+        if (!value || !fields.empty()) {
             auto ty = E->getTypeSourceInfo()->getType();
             typeEncoder.VisitQualType(ty);
         }
@@ -1421,6 +1566,10 @@ class TranslateASTVisitor final
      - true: is arrow; false: is dot
      */
     bool VisitMemberExpr(MemberExpr *E) {
+        // Member functions are encoded by the calls using them.
+        if (isa<CXXMethodDecl>(E->getMemberDecl()))
+            return true;
+
         std::vector<void *> childIds{E->getBase(),
                                      E->getMemberDecl()->getCanonicalDecl()};
         encode_entry(E, TagMemberExpr, childIds, [E](CborEncoder *extras) {
@@ -1605,7 +1754,13 @@ class TranslateASTVisitor final
         return true;
     }
 
-    bool VisitCStyleCastExpr(CStyleCastExpr *E) {
+    // C++ casts, like `static_cast<int>(x)` and `int(x)`, are encoded as C
+    // casts.
+    bool VisitExplicitCastExpr(ExplicitCastExpr *E) {
+        if (!isa<CStyleCastExpr>(E) && !isa<CXXFunctionalCastExpr>(E) &&
+            !isa<CXXNamedCastExpr>(E))
+            return true;
+
         std::vector<void *> childIds = {E->getSubExpr()};
 
         if (E->getCastKind() == CastKind::CK_ToUnion) {
@@ -1693,6 +1848,12 @@ class TranslateASTVisitor final
 
         auto decl = DRE->getDecl()->getCanonicalDecl();
 
+        auto FD = dyn_cast<FunctionDecl>(decl);
+        if (FD && FD->isTemplateInstantiation()) {
+            printUnsupported("C++ templates", DRE);
+            return true;
+        }
+
         std::vector<void *> childIds{decl};
         encode_entry(DRE, TagDeclRefExpr, childIds);
 
@@ -1704,6 +1865,9 @@ class TranslateASTVisitor final
     }
 
     bool VisitCallExpr(CallExpr *CE) {
+        if (isa<CXXMemberCallExpr>(CE) || isa<CXXOperatorCallExpr>(CE))
+            return true;
+
         std::vector<void *> childIds = {CE->getCallee()};
         for (auto x : CE->arguments()) {
             childIds.push_back(x);
@@ -1791,11 +1955,407 @@ class TranslateASTVisitor final
         return true;
     }
 
+    // `__null` is encoded as the literal 0 of its integer type.
     bool VisitGNUNullExpr(GNUNullExpr *E) {
-        printWarning("Encountered unsupported GNU extension: null expression", E);
+        encode_entry(E, TagIntegerLiteral, {}, [](CborEncoder *array) {
+            cbor_encode_uint(array, 0);
+            cbor_encode_uint(array, 10);
+        });
+        return true;
+    }
+
+    //
+    // C++
+    //
+    // A subset of C++ is supported: namespaces, classes without base classes
+    // or virtual member functions, member functions, references, `bool`, and
+    // `new` and `delete`. The rest of C++ is reported as an error outside of
+    // system headers, and isn't exported.
+    //
+
+    bool isInUserCode(SourceLocation loc) {
+        return loc.isValid() &&
+               !Context->getSourceManager().isInSystemHeader(loc);
+    }
+
+    void printUnsupported(std::string what, Decl *D) {
+        if (isInUserCode(D->getLocation()))
+            printError(what + " are not supported", D);
+    }
+
+    void printUnsupported(std::string what, Stmt *S) {
+#if CLANG_VERSION_MAJOR < 8
+        SourceLocation loc = S->getLocStart();
+#else
+        SourceLocation loc = S->getBeginLoc();
+#endif // CLANG_VERSION_MAJOR
+        if (isInUserCode(loc))
+            printError(what + " are not supported", S);
+    }
+
+    bool TraverseClassTemplateDecl(ClassTemplateDecl *D) {
+        printUnsupported("C++ templates", D);
+        return true;
+    }
+
+    bool TraverseClassTemplatePartialSpecializationDecl(
+        ClassTemplatePartialSpecializationDecl *D) {
+        printUnsupported("C++ templates", D);
+        return true;
+    }
+
+    // Instantiations of class templates, like the `std::vector<int>` of a
+    // variable, are reported where they're instantiated.
+    bool TraverseClassTemplateSpecializationDecl(
+        ClassTemplateSpecializationDecl *D) {
+        if (D->getSpecializationKind() == TSK_ExplicitSpecialization) {
+            printUnsupported("C++ templates", D);
+        } else if (isInUserCode(D->getPointOfInstantiation()) &&
+                   reportedInstantiations.insert(D).second) {
+            auto DiagBuilder = getDiagBuilder(D->getPointOfInstantiation(),
+                                              DiagnosticsEngine::Error);
+            DiagBuilder.AddString("C++ templates are not supported");
+        }
+        return true;
+    }
+
+    bool TraverseFunctionTemplateDecl(FunctionTemplateDecl *D) {
+        printUnsupported("C++ templates", D);
+        return true;
+    }
+
+    bool TraverseVarTemplateDecl(VarTemplateDecl *D) {
+        printUnsupported("C++ templates", D);
+        return true;
+    }
+
+    bool TraverseTypeAliasTemplateDecl(TypeAliasTemplateDecl *D) {
+        printUnsupported("C++ templates", D);
+        return true;
+    }
+
+    bool TraverseCXXMethodDecl(CXXMethodDecl *MD) {
+        // Implicit member functions, like copy assignment operators, are
+        // handled where they're called, if they're trivial.
+        if (MD->isImplicit())
+            return true;
+        if (MD->isVirtual()) {
+            printUnsupported("virtual member functions", MD);
+            return true;
+        }
+        if (MD->isOverloadedOperator()) {
+            printUnsupported("C++ operator overloads", MD);
+            return true;
+        }
+        return RecursiveASTVisitor::TraverseCXXMethodDecl(MD);
+    }
+
+    bool TraverseCXXConstructorDecl(CXXConstructorDecl *D) {
+        if (!D->isImplicit() && !D->isDefaulted())
+            printUnsupported("C++ constructors", D);
+        return true;
+    }
+
+    bool TraverseCXXDestructorDecl(CXXDestructorDecl *D) {
+        if (!D->isImplicit() && !D->isDefaulted())
+            printUnsupported("C++ destructors", D);
+        return true;
+    }
+
+    bool TraverseCXXConversionDecl(CXXConversionDecl *D) {
+        printUnsupported("C++ conversion functions", D);
+        return true;
+    }
+
+    bool TraverseCXXTryStmt(CXXTryStmt *S) {
+        printUnsupported("C++ exceptions", S);
+        return true;
+    }
+
+    bool TraverseCXXThrowExpr(CXXThrowExpr *E) {
+        printUnsupported("C++ exceptions", E);
+        return true;
+    }
+
+    bool TraverseLambdaExpr(LambdaExpr *E) {
+        printUnsupported("C++ lambdas", E);
+        return true;
+    }
+
+    bool TraverseCXXForRangeStmt(CXXForRangeStmt *S) {
+        printUnsupported("range-based for loops", S);
+        return true;
+    }
+
+    bool TraverseCXXDynamicCastExpr(CXXDynamicCastExpr *E) {
+        printUnsupported("dynamic casts", E);
+        return true;
+    }
+
+    bool TraverseCXXTypeidExpr(CXXTypeidExpr *E) {
+        printUnsupported("typeid expressions", E);
+        return true;
+    }
+
+    bool VisitCXXBoolLiteralExpr(CXXBoolLiteralExpr *E) {
+        auto value = E->getValue();
+        encode_entry(E, TagIntegerLiteral, {}, [value](CborEncoder *array) {
+            cbor_encode_uint(array, value);
+            cbor_encode_uint(array, 10);
+        });
+        return true;
+    }
+
+    // `nullptr` is only used converted to pointers, so it's encoded as the
+    // `int` literal 0.
+    bool VisitCXXNullPtrLiteralExpr(CXXNullPtrLiteralExpr *E) {
+        auto ty = Context->IntTy;
+        encode_entry_raw(E, TagIntegerLiteral, E->getSourceRange(), ty, true,
+                         false, false, {}, [](CborEncoder *array) {
+                             cbor_encode_uint(array, 0);
+                             cbor_encode_uint(array, 10);
+                         });
+        typeEncoder.VisitQualType(ty);
+        return true;
+    }
+
+    bool VisitCXXThisExpr(CXXThisExpr *E) {
+        encode_entry(E, TagCXXThisExpr, {});
         return true;
     }
 
+    /*
+     Call of a non-static member function
+     Children:
+     - member function declaration
+     - object expression
+     - arguments
+     Extras:
+     - true: the object is a pointer (`p->f()`); false: `x.f()`
+     */
+    bool VisitCXXMemberCallExpr(CXXMemberCallExpr *E) {
+        auto MD = E->getMethodDecl();
+        if (!MD) {
+            printUnsupported("pointers to member functions", E);
+            return true;
+        }
+        auto ME = dyn_cast<MemberExpr>(E->getCallee()->IgnoreParens());
+        bool isArrow = ME && ME->isArrow();
+
+        std::vector<void *> childIds{MD->getCanonicalDecl(),
+                                     E->getImplicitObjectArgument()};
+        for (auto x : E->arguments()) {
+            childIds.push_back(x);
+        }
+        encode_entry(E, TagCXXMemberCallExpr, childIds,
+                     [isArrow](CborEncoder *extras) {
+                         cbor_encode_boolean(extras, isArrow);
+                     });
+        TraverseDecl(MD->getCanonicalDecl());
+        return true;
+    }
+
+    // Assignments of classes are calls of their copy or move assignment
+    // operators, which are encoded as plain assignments when they're trivial.
+    bool VisitCXXOperatorCallExpr(CXXOperatorCallExpr *E) {
+        auto MD = dyn_cast_or_null<CXXMethodDecl>(E->getCalleeDecl());
+        if (E->getOperator() != OO_Equal || !MD || !MD->isTrivial()) {
+            printUnsupported("C++ operator overloads", E);
+            return true;
+        }
+
+        std::vector<void *> childIds{E->getArg(0), E->getArg(1)};
+        encode_entry(E, TagBinaryOperator, childIds, [this](CborEncoder *array) {
+            cbor_encode_string(array, "=");
+            encode_qualtype(array, QualType());
+            encode_qualtype(array, QualType());
+        });
+        return true;
+    }
+
+    // Only trivial constructors are supported: default construction is
+    // encoded as an implicit value initialization, and copies as their
+    // argument.
+    bool VisitCXXConstructExpr(CXXConstructExpr *E) {
+        auto CD = E->getConstructor();
+        if (CD->isTrivial() && CD->isDefaultConstructor()) {
+            encode_entry(E, TagImplicitValueInitExpr, {});
+        } else if (CD->isTrivial() && CD->isCopyOrMoveConstructor()) {
+            std::vector<void *> childIds{E->getArg(0)};
+            encode_entry(E, TagParenExpr, childIds);
+        } else {
+            printUnsupported("C++ constructors", E);
+        }
+        return true;
+    }
+
+    bool VisitCXXScalarValueInitExpr(CXXScalarValueInitExpr *E) {
+        encode_entry(E, TagImplicitValueInitExpr, {});
+        return true;
+    }
+
+    // Temporaries and default arguments are encoded as parenthesized
+    // expressions.
+    bool VisitMaterializeTemporaryExpr(MaterializeTemporaryExpr *E) {
+#if CLANG_VERSION_MAJOR < 10
+        std::vector<void *> childIds{E->GetTemporaryExpr()};
+#else
+        std::vector<void *> childIds{E->getSubExpr()};
+#endif // CLANG_VERSION_MAJOR
+        encode_entry(E, TagParenExpr, childIds);
+        return true;
+    }
+
+    bool VisitExprWithCleanups(ExprWithCleanups *E) {
+        std::vector<void *> childIds{E->getSubExpr()};
+        encode_entry(E, TagParenExpr, childIds);
+        return true;
+    }
+
+    bool VisitCXXDefaultArgExpr(CXXDefaultArgExpr *E) {
+        std::vector<void *> childIds{E->getExpr()};
+        encode_entry(E, TagParenExpr, childIds);
+        TraverseStmt(E->getExpr());
+        return true;
+    }
+
+    bool VisitCXXDefaultInitExpr(CXXDefaultInitExpr *E) {
+        std::vector<void *> childIds{E->getExpr()};
+        encode_entry(E, TagParenExpr, childIds);
+        TraverseStmt(E->getExpr());
+        return true;
+    }
+
+    /*
+     C++ new expression
+     Children:
+     - element count of an array new, or null
+     - initializer, or null
+     Extras:
+     - allocated type
+     */
+    bool VisitCXXNewExpr(CXXNewExpr *E) {
+        if (E->getNumPlacementArgs() > 0) {
+            printUnsupported("placement new expressions", E);
+            return true;
+        }
+
+#if CLANG_VERSION_MAJOR < 9
+        Expr *count = E->getArraySize();
+#else
+        Expr *count = E->isArray() ? *E->getArraySize() : nullptr;
+#endif // CLANG_VERSION_MAJOR
+        auto allocated = E->getAllocatedType();
+        std::vector<void *> childIds{count, E->getInitializer()};
+        encode_entry(E, TagCXXNewExpr, childIds,
+                     [this, allocated](CborEncoder *extras) {
+                         encode_qualtype(extras, allocated);
+                     });
+        typeEncoder.VisitQualType(allocated);
+        return true;
+    }
+
+    /*
+     C++ delete expression
+     Children:
+     - deleted pointer
+     Extras:
+     - true: `delete[]`; false: `delete`
+     */
+    bool VisitCXXDeleteExpr(CXXDeleteExpr *E) {
+        std::vector<void *> childIds{E->getArgument()};
+        encode_entry(E, TagCXXDeleteExpr, childIds, [E](CborEncoder *extras) {
+            cbor_encode_boolean(extras, E->isArrayForm());
+        });
+        return true;
+    }
+
+    //
+    // Objective-C
+    //
+    // Objective-C translation units are only exported for the C declarations
+    // they make, so that the C functions they define can be declared from
+    // Rust while the files themselves stay in Objective-C. Interfaces,
+    // protocols, categories and implementations are skipped, and so are the
+    // declarations whose types involve Objective-C objects, classes,
+    // selectors or blocks. Function definitions are exported with empty
+    // bodies.
+    //
+
+    bool isObjC() {
+#if CLANG_VERSION_MAJOR < 8
+        return Context->getLangOpts().ObjC1;
+#else
+        return Context->getLangOpts().ObjC;
+#endif // CLANG_VERSION_MAJOR
+    }
+
+    bool mentionsObjC(QualType QT,
+                      std::unordered_set<const RecordDecl *> &seen) {
+        if (QT.isNull())
+            return false;
+        auto T = QT.getCanonicalType().getTypePtr();
+        if (T->isObjCObjectOrInterfaceType() || T->isObjCObjectPointerType() ||
+            T->isBlockPointerType())
+            return true;
+        if (auto BT = dyn_cast<BuiltinType>(T)) {
+            auto kind = BT->getKind();
+            return kind == BuiltinType::ObjCId ||
+                   kind == BuiltinType::ObjCClass ||
+                   kind == BuiltinType::ObjCSel;
+        }
+        if (auto PT = dyn_cast<PointerType>(T))
+            return mentionsObjC(PT->getPointeeType(), seen);
+        if (auto AT = dyn_cast<ArrayType>(T))
+            return mentionsObjC(AT->getElementType(), seen);
+        if (auto FT = dyn_cast<FunctionType>(T)) {
+            if (mentionsObjC(FT->getReturnType(), seen))
+                return true;
+            if (auto FPT = dyn_cast<FunctionProtoType>(FT)) {
+                for (auto param : FPT->getParamTypes()) {
+                    if (mentionsObjC(param, seen))
+                        return true;
+                }
+            }
+            return false;
+        }
+        if (auto RT = dyn_cast<RecordType>(T)) {
+            auto RD = RT->getDecl()->getDefinition();
+            if (!RD || !seen.insert(RD).second)
+                return false;
+            for (auto field : RD->fields()) {
+                if (mentionsObjC(field->getType(), seen))
+                    return true;
+            }
+        }
+        return false;
+    }
+
+    bool isObjCOnly(Decl *D) {
+        if (isa<ObjCContainerDecl>(D) || isa<ObjCCompatibleAliasDecl>(D))
+            return true;
+        std::unordered_set<const RecordDecl *> seen;
+        if (auto TD = dyn_cast<TypedefNameDecl>(D))
+            return mentionsObjC(TD->getUnderlyingType(), seen);
+        if (auto RD = dyn_cast<RecordDecl>(D))
+            return mentionsObjC(Context->getRecordType(RD), seen);
+        if (auto VD = dyn_cast<ValueDecl>(D))
+            return mentionsObjC(VD->getType(), seen);
+        return false;
+    }
+
+    bool TraverseDecl(Decl *D) {
+        if (D && isObjC() && isObjCOnly(D))
+            return true;
+        return RecursiveASTVisitor::TraverseDecl(D);
+    }
+
+    bool TraverseFunctionDecl(FunctionDecl *FD) {
+        // The body is replaced by an empty one in `VisitFunctionDecl`.
+        if (isObjC())
+            return WalkUpFromFunctionDecl(FD);
+        return RecursiveASTVisitor::TraverseFunctionDecl(FD);
+    }
 
     //
     // Declarations
@@ -1804,6 +2364,10 @@ class TranslateASTVisitor final
     // Some function declarations are also function definitions.
     // This method handles both types of declarations.
     bool VisitFunctionDecl(FunctionDecl *FD) {
+        // Instantiations of templates are reported where they're used
+        if (FD->isTemplateInstantiation())
+            return true;
+
         if (!FD->isCanonicalDecl()) {
             // Emit non-canonical decl so we have a placeholder to attach comments to
             std::vector<void *> childIds = {FD->getCanonicalDecl()};
@@ -1825,6 +2389,8 @@ class TranslateASTVisitor final
         const FunctionDecl *paramsFD = FD;
         auto body =
             FD->getBody(paramsFD); // replaces its argument if body exists
+        if (body && isObjC())
+            encode_entry(body, TagCompoundStmt, {});
 
         std::vector<void *> childIds;
         for (auto x : paramsFD->parameters()) {
@@ -1837,9 +2403,21 @@ class TranslateASTVisitor final
 
         auto functionType = FD->getType();
         auto span = paramsFD->getSourceRange();
+
+        auto MD = dyn_cast<CXXMethodDecl>(FD);
+        QualType thisType;
+        if (MD && MD->isInstance()) {
+#if CLANG_VERSION_MAJOR < 8
+            thisType = MD->getThisType(*Context);
+#else
+            thisType = MD->getThisType();
+#endif // CLANG_VERSION_MAJOR
+            typeEncoder.VisitQualType(thisType);
+        }
+
         encode_entry(
             FD, TagFunctionDecl, span, childIds, functionType,
-            [this, FD](CborEncoder *array) {
+            [this, FD, MD, thisType](CborEncoder *array) {
                 auto name = FD->getNameAsString();
                 cbor_encode_string(array, name);
 
@@ -1890,11 +2468,25 @@ class TranslateASTVisitor final
                         } else if (auto *va = dyn_cast<VisibilityAttr>(attr)) {
                             const char *vis = VisibilityAttr::ConvertVisibilityTypeToStr(va->getVisibility());
                             cbor_encode_text_stringz(&attr_info, vis);
+                        } else if (auto *aa = dyn_cast<AnnotateAttr>(attr)) {
+                            cbor_encode_text_stringz(
+                                &attr_info, aa->getAnnotation().str().c_str());
                         }
                     }
                 }
 
                 cbor_encoder_close_container(array, &attr_info);
+
+                encodeLinkName(array, FD);
+
+                // The class of member functions, and the type of `this`
+                if (MD) {
+                    cbor_encode_uint(array,
+                                     uintptr_t(MD->getParent()->getCanonicalDecl()));
+                } else {
+                    cbor_encode_null(array);
+                }
+                encode_qualtype(array, thisType);
             });
         typeEncoder.VisitQualType(functionType);
 
@@ -1914,6 +2506,11 @@ class TranslateASTVisitor final
     }*/
 
     bool VisitVarDecl(VarDecl *VD) {
+        if (VD->isStaticDataMember()) {
+            printUnsupported("static data members", VD);
+            return true;
+        }
+
         // Skip non-canonical decls, as long as they aren't 'extern'.
         // Unfortunately, if there are two 'extern' variables in different
         // functions that should be the same at link time, Clang groups them.
@@ -1965,7 +2562,7 @@ class TranslateASTVisitor final
 
         encode_entry(
             VD, TagVarDecl, loc, childIds, T,
-            [VD, is_defn, def, is_externally_visible](CborEncoder *array) {
+            [this, VD, is_defn, def, is_externally_visible](CborEncoder *array) {
                 auto name = VD->getNameAsString();
                 cbor_encode_string(array, name);
 
@@ -2000,11 +2597,20 @@ class TranslateASTVisitor final
                         } else if (auto *aa = dyn_cast<AliasAttr>(attr)) {
                             cbor_encode_text_stringz(
                                 &attr_info, aa->getAliasee().str().c_str());
+                        } else if (auto *aa = dyn_cast<AnnotateAttr>(attr)) {
+                            cbor_encode_text_stringz(
+                                &attr_info, aa->getAnnotation().str().c_str());
                         }
                     }
                 }
 
                 cbor_encoder_close_container(array, &attr_info);
+
+                if (VD->isFileVarDecl()) {
+                    encodeLinkName(array, VD);
+                } else {
+                    cbor_encode_null(array);
+                }
             });
 
         typeEncoder.VisitQualType(T);
@@ -2066,7 +2672,13 @@ class TranslateASTVisitor final
             byteSize = layout.getSize().getQuantity();
         }
 
-        auto tag = D->isStruct() ? TagStructDecl : TagUnionDecl;
+        if (auto CRD = dyn_cast_or_null<CXXRecordDecl>(def)) {
+            if (CRD->getNumBases() > 0)
+                printUnsupported("base classes", D);
+        }
+
+        // C++ classes are structs
+        auto tag = D->isUnion() ? TagUnionDecl : TagStructDecl;
 
         encode_entry(
             D, tag, loc, childIds, QualType(),
@@ -2474,7 +3086,7 @@ void TypeEncoder::VisitRecordType(const RecordType *T) {
         VisitQualType(qt);
     }
 
-    auto tag = T->isStructureType() ? TagStructType : TagUnionType;
+    auto tag = T->isUnionType() ? TagUnionType : TagStructType;
 
     encodeType(T, tag, [T](CborEncoder *local) {
         cbor_encode_uint(local, uintptr_t(T->getDecl()->getCanonicalDecl()));
@@ -2564,27 +3176,16 @@ class TranslateConsumer : public clang::ASTConsumer {
             auto translation_unit = Context.getTranslationUnitDecl();
             visitor.TraverseDecl(translation_unit);
             visitor.encodeMacros();
+            visitor.encodeNamespaces(translation_unit, {});
             cbor_encoder_close_container(&outer, &array);
 
-            // 2. Track all of the top-level declarations
+            // 2. Track all of the top-level declarations, including the
+            // declarations of C++ namespaces and the member functions of C++
+            // classes
             cbor_encoder_create_array(&outer, &array, CborIndefiniteLength);
-            for (auto d : translation_unit->decls()) {
-                if (!d->isCanonicalDecl() && isa<VarDecl>(d)) {
-                    auto canonical_decl = d->getCanonicalDecl();
-                    auto var_decl = cast<VarDecl>(canonical_decl);
-
-                    // Non-Canonical Decls which don't have an extern local canonical decl
-                    // should be skipped
-                    if (!(var_decl->isExternC() && var_decl->isLocalVarDecl())) {
-                        continue;
-                    }
-                }
-
-                // Empty-decls aren't exported. This avoids warnings during conversion.
-                if (isa<EmptyDecl>(d)) {
-                    continue;
-                }
-
+            std::vector<void *> top_decls;
+            visitor.collectDecls(translation_unit, true, top_decls);
+            for (auto d : top_decls) {
                 cbor_encode_uint(&array, reinterpret_cast<std::uintptr_t>(d));
             }
             cbor_encoder_close_container(&outer, &array);
@@ -2692,10 +3293,17 @@ class TranslateAction : public clang::ASTFrontendAction {
 
 #if CLANG_VERSION_MAJOR < 10
         const InputKind::Language lang_c = InputKind::Language::C;
+        const InputKind::Language lang_cxx = InputKind::Language::CXX;
+        const InputKind::Language lang_objc = InputKind::Language::ObjC;
 #else
         const Language lang_c = Language::C;
+        const Language lang_cxx = Language::CXX;
+        const Language lang_objc = Language::ObjC;
 #endif // CLANG_VERSION_MAJOR
-        if (this->getCurrentFileKind().getLanguage() != lang_c) {
+        // C++ is accepted for the subset the visitor supports, and
+        // Objective-C for the C declarations it makes.
+        auto lang = this->getCurrentFileKind().getLanguage();
+        if (lang != lang_c && lang != lang_cxx && lang != lang_objc) {
             return nullptr;
         }
 
@@ -2795,12 +3403,18 @@ ExportResult *make_export_result(const Outputs &outputs) {
     return result;
 }
 
+// The command-line options are global, so they're parsed for one file at a
+// time when files are exported from several threads.
+static std::mutex options_mutex;
+
 // Extract clang AST for the source file specified in the argument vector.
 // Note: The arguments should only reference one source file at a time.
 Outputs process(int argc, const char *argv[], int *result) {
     auto argv_ = augment_argv(argc, argv);
     int argc_ = argv_.size() - 1; // ignore the extra nullptr
 
+    std::unique_lock<std::mutex> options_lock(options_mutex);
+
 #if CLANG_VERSION_MAJOR < 13
     CommonOptionsParser OptionsParser(argc_, argv_.data(), MyToolCategory);
 #else
@@ -2828,7 +3442,19 @@ Outputs process(int argc, const char *argv[], int *result) {
     std::string sourcePath = OptionsParser.getSourcePathList().back();
     // Make a new list with just the file we're currently translating
     std::vector<std::string> sourcePathList(1, sourcePath);
+#if CLANG_VERSION_MAJOR < 8
+    // Older versions of clang change the working directory of the process
+    // while running the tool, so they export one file at a time.
     ClangTool Tool(OptionsParser.getCompilations(), sourcePathList);
+#else
+    // A file system of its own keeps the tool from changing the working
+    // directory of the process.
+    IntrusiveRefCntPtr<llvm::vfs::FileSystem> FS(
+        llvm::vfs::createPhysicalFileSystem().release());
+    ClangTool Tool(OptionsParser.getCompilations(), sourcePathList,
+                   std::make_shared<PCHContainerOperations>(), FS);
+    options_lock.unlock();
+#endif // CLANG_VERSION_MAJOR
 
     Outputs outputs;
     MyFrontendActionFactory myFrontendActionFactory(&outputs);
@@ -28,6 +28,8 @@ enum ASTEntryTag {
     TagMacroObjectDef,
     TagMacroFunctionDef,
 
+    TagNamespaceDecl,
+
     TagCompoundStmt = 100,
     TagReturnStmt,
     TagIfStmt,
@@ -85,6 +87,12 @@ enum ASTEntryTag {
 
     TagAtomicExpr,
 
+    // C++ subset
+    TagCXXThisExpr,
+    TagCXXMemberCallExpr,
+    TagCXXNewExpr,
+    TagCXXDeleteExpr,
+
     TagIntegerLiteral = 300,
     TagStringLiteral,
     TagCharacterLiteral,
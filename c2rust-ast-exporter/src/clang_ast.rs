@@ -1,8 +1,10 @@
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
 use serde_bytes::ByteBuf;
 use serde_cbor::error;
 use std;
 use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto;
+use std::fmt;
 use std::path::{Path, PathBuf};
 
 pub use serde_cbor::value::{from_value, Value};
@@ -150,127 +152,177 @@ fn import_va_list_kind(tag: u64) -> BuiltinVaListKind {
     unsafe { std::mem::transmute::<u32, BuiltinVaListKind>(tag as u32) }
 }
 
-pub fn process(items: Value) -> error::Result<AstContext> {
-    let mut asts: HashMap<u64, AstNode> = HashMap::new();
-    let mut types: HashMap<u64, TypeNode> = HashMap::new();
-    let mut comments: Vec<CommentNode> = vec![];
-
-    type AllNode = VecDeque<Value>;
-    type TopNode = u64;
-    type File = (String, Option<(u64, u64, u64)>);
-    type RawComment = (u64, u64, u64, ByteBuf);
-    type VaListKind = u64;
-    type Target = String;
-    let (all_nodes, top_nodes, files, raw_comments, va_list_kind, target): (
-        Vec<AllNode>,
-        Vec<TopNode>,
-        Vec<File>,
-        Vec<RawComment>,
-        VaListKind,
-        Target,
-    ) = from_value(items)?;
-
-    let va_list_kind = import_va_list_kind(va_list_kind);
-
-    for (fileid, line, column, bytes) in raw_comments {
-        comments.push(CommentNode {
-            loc: SrcLoc {
-                fileid,
-                line,
-                column,
-            },
-            string: String::from_utf8_lossy(&bytes).to_string(),
-        })
+/// Import the AST exported to `cbor`.  The nodes are imported as they're read, so the whole
+/// encoding is never held as a `Value` at once, which bounds the memory used by large
+/// translation units.
+pub fn process(cbor: &[u8]) -> error::Result<AstContext> {
+    let mut deserializer = serde_cbor::Deserializer::from_slice(cbor);
+    let context = deserializer.deserialize_seq(AstContextVisitor)?;
+    deserializer.end()?;
+    Ok(context)
+}
+
+/// Visits the exported AST: its nodes, top-level declarations, files, comments, `va_list` kind,
+/// and target.
+struct AstContextVisitor;
+
+impl<'de> Visitor<'de> for AstContextVisitor {
+    type Value = AstContext;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an exported AST")
     }
 
-    let files = files
-        .into_iter()
-        .map(|(path, loc)| {
-            let path = match path.as_str() {
-                "" => None,
-                "?" => None,
-                path => Some(Path::new(path).to_path_buf()),
-            };
-            SrcFile {
-                path,
-                include_loc: loc.map(|(fileid, line, column)| SrcLoc {
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<AstContext, A::Error> {
+        type File = (String, Option<(u64, u64, u64)>);
+        type RawComment = (u64, u64, u64, ByteBuf);
+
+        let missing = |idx| <A::Error as de::Error>::invalid_length(idx, &self);
+        let (ast_nodes, type_nodes) = seq
+            .next_element_seed(NodesSeed)?
+            .ok_or_else(|| missing(0))?;
+        let top_nodes: Vec<u64> = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let files: Vec<File> = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let raw_comments: Vec<RawComment> = seq.next_element()?.ok_or_else(|| missing(3))?;
+        let va_list_kind: u64 = seq.next_element()?.ok_or_else(|| missing(4))?;
+        let target: String = seq.next_element()?.ok_or_else(|| missing(5))?;
+
+        let va_list_kind = import_va_list_kind(va_list_kind);
+
+        let comments = raw_comments
+            .into_iter()
+            .map(|(fileid, line, column, bytes)| CommentNode {
+                loc: SrcLoc {
                     fileid,
                     line,
                     column,
-                }),
-            }
-        })
-        .collect::<Vec<_>>();
-
-    for mut entry in all_nodes.into_iter() {
-        let entry_id: u64 = from_value(entry.pop_front().unwrap()).unwrap();
-        let tag = from_value(entry.pop_front().unwrap()).unwrap();
-
-        if tag < 400 {
-            let children = from_value::<Vec<Value>>(entry.pop_front().unwrap())
-                .unwrap()
-                .iter()
-                .map(|x| expect_opt_u64(x).unwrap())
-                .collect::<Vec<Option<u64>>>();
-
-            // entry[3]
-            let fileid = from_value(entry.pop_front().unwrap()).unwrap();
-            let begin_line = from_value(entry.pop_front().unwrap()).unwrap();
-            let begin_column = from_value(entry.pop_front().unwrap()).unwrap();
-            let end_line = from_value(entry.pop_front().unwrap()).unwrap();
-            let end_column = from_value(entry.pop_front().unwrap()).unwrap();
-
-            // entry[8]
-            let type_id: Option<u64> = expect_opt_u64(&entry.pop_front().unwrap()).unwrap();
-
-            // entry[9]
-            let rvalue = if from_value(entry.pop_front().unwrap()).unwrap() {
-                LRValue::RValue
-            } else {
-                LRValue::LValue
-            };
-
-            // entry[10]
-            let macro_expansions = from_value::<Vec<u64>>(entry.pop_front().unwrap()).unwrap();
-
-            let macro_expansion_text = expect_opt_str(&entry.pop_front().unwrap())
-                .unwrap()
-                .map(|s| s.to_string());
-
-            let node = AstNode {
-                tag: import_ast_tag(tag),
-                children,
-                loc: SrcSpan {
-                    fileid,
-                    begin_line,
-                    begin_column,
-                    end_line,
-                    end_column,
                 },
-                type_id,
-                rvalue,
-                macro_expansions,
-                macro_expansion_text,
-                extras: entry.into_iter().collect(),
-            };
-
-            asts.insert(entry_id, node);
-        } else {
-            let node = TypeNode {
-                tag: import_type_tag(tag),
-                extras: entry.into_iter().collect(),
-            };
+                string: String::from_utf8_lossy(&bytes).to_string(),
+            })
+            .collect();
+
+        let files = files
+            .into_iter()
+            .map(|(path, loc)| {
+                let path = match path.as_str() {
+                    "" => None,
+                    "?" => None,
+                    path => Some(Path::new(path).to_path_buf()),
+                };
+                SrcFile {
+                    path,
+                    include_loc: loc.map(|(fileid, line, column)| SrcLoc {
+                        fileid,
+                        line,
+                        column,
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(AstContext {
+            top_nodes,
+            ast_nodes,
+            type_nodes,
+            comments,
+            files,
+            va_list_kind,
+            target,
+        })
+    }
+}
+
+/// Imports the array of AST and type nodes one node at a time.
+struct NodesSeed;
+
+impl<'de> DeserializeSeed<'de> for NodesSeed {
+    type Value = (HashMap<u64, AstNode>, HashMap<u64, TypeNode>);
 
-            types.insert(entry_id, node);
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodesSeed {
+    type Value = (HashMap<u64, AstNode>, HashMap<u64, TypeNode>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of AST nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut asts: HashMap<u64, AstNode> = HashMap::new();
+        let mut types: HashMap<u64, TypeNode> = HashMap::new();
+        while let Some(entry) = seq.next_element::<VecDeque<Value>>()? {
+            import_node(entry, &mut asts, &mut types);
         }
+        Ok((asts, types))
+    }
+}
+
+fn import_node(
+    mut entry: VecDeque<Value>,
+    asts: &mut HashMap<u64, AstNode>,
+    types: &mut HashMap<u64, TypeNode>,
+) {
+    let entry_id: u64 = from_value(entry.pop_front().unwrap()).unwrap();
+    let tag = from_value(entry.pop_front().unwrap()).unwrap();
+
+    if tag < 400 {
+        let children = from_value::<Vec<Value>>(entry.pop_front().unwrap())
+            .unwrap()
+            .iter()
+            .map(|x| expect_opt_u64(x).unwrap())
+            .collect::<Vec<Option<u64>>>();
+
+        // entry[3]
+        let fileid = from_value(entry.pop_front().unwrap()).unwrap();
+        let begin_line = from_value(entry.pop_front().unwrap()).unwrap();
+        let begin_column = from_value(entry.pop_front().unwrap()).unwrap();
+        let end_line = from_value(entry.pop_front().unwrap()).unwrap();
+        let end_column = from_value(entry.pop_front().unwrap()).unwrap();
+
+        // entry[8]
+        let type_id: Option<u64> = expect_opt_u64(&entry.pop_front().unwrap()).unwrap();
+
+        // entry[9]
+        let rvalue = if from_value(entry.pop_front().unwrap()).unwrap() {
+            LRValue::RValue
+        } else {
+            LRValue::LValue
+        };
+
+        // entry[10]
+        let macro_expansions = from_value::<Vec<u64>>(entry.pop_front().unwrap()).unwrap();
+
+        let macro_expansion_text = expect_opt_str(&entry.pop_front().unwrap())
+            .unwrap()
+            .map(|s| s.to_string());
+
+        let node = AstNode {
+            tag: import_ast_tag(tag),
+            children,
+            loc: SrcSpan {
+                fileid,
+                begin_line,
+                begin_column,
+                end_line,
+                end_column,
+            },
+            type_id,
+            rvalue,
+            macro_expansions,
+            macro_expansion_text,
+            extras: entry.into_iter().collect(),
+        };
+
+        asts.insert(entry_id, node);
+    } else {
+        let node = TypeNode {
+            tag: import_type_tag(tag),
+            extras: entry.into_iter().collect(),
+        };
+
+        types.insert(entry_id, node);
     }
-    Ok(AstContext {
-        top_nodes,
-        ast_nodes: asts,
-        type_nodes: types,
-        comments,
-        files,
-        va_list_kind,
-        target,
-    })
 }
@@ -1,4 +1,3 @@
-use serde_cbor::{from_slice, Value};
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::io::{Error, ErrorKind};
@@ -24,31 +23,26 @@ pub fn get_untyped_ast(
     extra_args: &[&str],
     debug: bool,
 ) -> Result<clang_ast::AstContext, Error> {
-    let cbors = get_ast_cbors(file_path, cc_db, extra_args, debug);
-    let buffer = cbors
-        .values()
-        .next()
-        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Could not parse input file"))?;
-
-    // let cbor_path = file_path.with_extension("cbor");
-    // let mut cbor_file = File::create(&cbor_path)?;
-    // cbor_file.write_all(&buffer[..])?;
-    // eprintln!("Dumped CBOR to {}", cbor_path.to_string_lossy());
-
-    let items: Value = from_slice(&buffer[..]).unwrap();
-
-    match clang_ast::process(items) {
-        Ok(cxt) => Ok(cxt),
-        Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("{:}", e))),
-    }
+    with_ast_cbors(file_path, cc_db, extra_args, debug, |cbors| {
+        let buffer = cbors
+            .values()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Could not parse input file"))?;
+
+        clang_ast::process(buffer)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{:}", e)))
+    })
 }
 
-fn get_ast_cbors(
+/// Export the ASTs of `file_path` and pass their CBOR encodings, by output file, to `f`.  The
+/// encodings are borrowed from the exporter rather than copied, and freed once `f` returns.
+fn with_ast_cbors<T>(
     file_path: &Path,
     cc_db: &Path,
     extra_args: &[&str],
     debug: bool,
-) -> HashMap<String, Vec<u8>> {
+    f: impl FnOnce(&HashMap<String, &[u8]>) -> T,
+) -> T {
     let mut res = 0;
 
     let mut args_owned = vec![CString::new("ast_exporter").unwrap()];
@@ -62,7 +56,6 @@ fn get_ast_cbors(
 
     let args_ptrs: Vec<*const libc::c_char> = args_owned.iter().map(|x| x.as_ptr()).collect();
 
-    let hashmap;
     unsafe {
         let ptr = ast_exporter(
             args_ptrs.len() as libc::c_int,
@@ -70,10 +63,10 @@ fn get_ast_cbors(
             debug.into(),
             &mut res,
         );
-        hashmap = marshal_result(ptr);
+        let output = f(&marshal_result(ptr));
         drop_export_result(ptr);
+        output
     }
-    hashmap
 }
 
 #[allow(non_camel_case_types)]
@@ -98,7 +91,9 @@ extern "C" {
     fn clang_version() -> *const libc::c_char;
 }
 
-unsafe fn marshal_result(result: *const ffi::ExportResult) -> HashMap<String, Vec<u8>> {
+/// Borrow the CBOR encodings in `result` by output file.  They're only valid until `result` is
+/// dropped.
+unsafe fn marshal_result<'a>(result: *const ffi::ExportResult) -> HashMap<String, &'a [u8]> {
     let mut output = HashMap::new();
 
     let n = (*result).entries as isize;
@@ -113,10 +108,8 @@ unsafe fn marshal_result(result: *const ffi::ExportResult) -> HashMap<String, Ve
         let csize = *res.sizes.offset(i);
         let cbytes = *res.bytes.offset(i);
         let bytes = slice::from_raw_parts(cbytes, csize as usize);
-        let mut v = Vec::new();
-        v.extend_from_slice(bytes);
 
-        output.insert(name, v);
+        output.insert(name, bytes);
     }
     output
 }
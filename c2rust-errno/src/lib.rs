@@ -0,0 +1,84 @@
+//! A thread-local replacement for C's `errno`.
+//!
+//! C code reads and writes `errno` through a per-thread location that the
+//! C library returns from `__errno_location()` (or `__error()` on macOS).
+//! With `--errno-shim`, the transpiler calls [`errno_location`] instead, so
+//! translated code keeps working once it no longer calls into libc.  Note
+//! that libc functions still set the C library's own `errno`, not this one:
+//! code that checks `errno` after such a call should call
+//! [`update_from_os`] first, or be rewritten to use
+//! `std::io::Error::last_os_error()` directly.
+//!
+//! [`last_os_error`] and [`set_last_os_error`] convert between the current
+//! value and `std::io::Error`, which is what the `errno_to_io_error`
+//! refactoring command rewrites `errno` accesses into.
+
+use std::cell::Cell;
+use std::io;
+use std::os::raw::c_int;
+
+thread_local! {
+    static ERRNO: Cell<c_int> = Cell::new(0);
+}
+
+/// Get a pointer to the current thread's `errno`, for use in place of
+/// `__errno_location()`.  The pointer is valid until the thread exits.
+pub fn errno_location() -> *mut c_int {
+    ERRNO.with(|errno| errno.as_ptr())
+}
+
+/// Get the current thread's `errno`.
+pub fn errno() -> c_int {
+    ERRNO.with(|errno| errno.get())
+}
+
+/// Set the current thread's `errno`.
+pub fn set_errno(value: c_int) {
+    ERRNO.with(|errno| errno.set(value))
+}
+
+/// Get the current thread's `errno` as an `io::Error`.
+pub fn last_os_error() -> io::Error {
+    io::Error::from_raw_os_error(errno())
+}
+
+/// Set the current thread's `errno` from an `io::Error`.  Errors that don't
+/// come from the OS set it to 0.
+pub fn set_last_os_error(error: &io::Error) {
+    set_errno(error.raw_os_error().unwrap_or(0))
+}
+
+/// Copy the error code of the last OS call on this thread into `errno`.
+pub fn update_from_os() {
+    set_last_os_error(&io::Error::last_os_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errno_location() {
+        set_errno(0);
+        unsafe {
+            *errno_location() = 22;
+        }
+        assert_eq!(errno(), 22);
+        assert_eq!(last_os_error().raw_os_error(), Some(22));
+
+        set_last_os_error(&io::Error::from_raw_os_error(2));
+        assert_eq!(unsafe { *errno_location() }, 2);
+    }
+
+    #[test]
+    fn test_per_thread() {
+        set_errno(5);
+        std::thread::spawn(|| {
+            assert_eq!(errno(), 0);
+            set_errno(7);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(errno(), 5);
+    }
+}
@@ -5,10 +5,12 @@
     trace_macros,
 )]
 
+#[macro_use] extern crate c2rust_refactor;
+
 pub use c2rust_refactor::*;
 
-fn mk<T: transform::Transform + 'static>(t: T) -> Box<command::Command> {
-    Box::new(transform::TransformCommand(t))
+fn mk<T: transform::Transform + 'static>(t: T) -> Box<dyn command::Command> {
+    api::transform_command(t)
 }
 
 // Adjust these lines to control what part of `c2rust-refactor` gets built.
@@ -17,7 +19,4 @@ fn mk<T: transform::Transform + 'static>(t: T) -> Box<command::Command> {
 mod plugin;
 //use self::plugin as analysis;
 
-#[no_mangle]
-pub fn register_commands(reg: &mut command::Registry) {
-    plugin::register_commands(reg);
-}
+declare_plugin!(plugin::register_commands);
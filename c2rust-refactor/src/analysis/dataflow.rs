@@ -0,0 +1,198 @@
+//! Generic forward dataflow analysis over MIR, for analyses that need flow-sensitive results.
+//!
+//! An analysis implements the `Analysis` trait, giving its abstract domain, the state on entry
+//! to the function, how states are joined where control flow merges, and the effect of each
+//! statement and terminator.  `analyze_fn` runs it to a fixpoint over the optimized MIR of one
+//! function and returns the state on entry to each basic block, from which the state at any
+//! other point can be recomputed.
+//!
+//! Transforms work on the AST, so `Results` can also be queried by source span: the state on
+//! entry to the code lowered from an AST node is the join of the states at each point where
+//! control enters that node's span.  `local_for_pat` and `local_pat_span` map between MIR locals
+//! and the patterns that bind them, the same way the ownership analysis does.  Since the MIR is
+//! optimized, some AST nodes may have no code left to query.
+
+use std::collections::VecDeque;
+use rustc::hir::def_id::DefId;
+use rustc::mir::*;
+use rustc_index::bit_set::BitSet;
+use rustc_index::vec::IndexVec;
+use syntax::source_map::Span;
+
+use crate::RefactorCtxt;
+
+/// A forward dataflow analysis.
+pub trait Analysis<'tcx> {
+    /// The abstract state at a program point.
+    type Domain: Clone + PartialEq;
+
+    /// The state on entry to the function.
+    fn entry_state(&self, body: &Body<'tcx>) -> Self::Domain;
+
+    /// The state of blocks that control has not reached yet.  Joining this with any other state
+    /// should give the other state.
+    fn bottom(&self, body: &Body<'tcx>) -> Self::Domain;
+
+    /// Merge `other` into `state`.  Returns `true` if `state` changed.
+    fn join(&self, state: &mut Self::Domain, other: &Self::Domain) -> bool;
+
+    /// Update `state` for the effect of `stmt`, found at `loc`.
+    fn apply_statement(&self, state: &mut Self::Domain, stmt: &Statement<'tcx>, loc: Location);
+
+    /// Update `state` for the effect of `term`, found at `loc`.  The result is propagated to all
+    /// successors of the block.
+    fn apply_terminator(&self, state: &mut Self::Domain, term: &Terminator<'tcx>,
+                        loc: Location);
+
+    /// Update `state`, the state after the terminator of `from`, for taking the edge to `to`.
+    /// This lets an analysis learn from branch conditions.  The default does nothing.
+    fn apply_edge(&self, _state: &mut Self::Domain, _body: &Body<'tcx>, _from: BasicBlock,
+                  _to: BasicBlock) {
+    }
+}
+
+/// The result of running an `Analysis` on a function body.
+pub struct Results<'a, 'tcx, A: Analysis<'tcx>> {
+    body: &'a Body<'tcx>,
+    analysis: A,
+    entry_sets: IndexVec<BasicBlock, A::Domain>,
+}
+
+impl<'a, 'tcx, A: Analysis<'tcx>> Results<'a, 'tcx, A> {
+    pub fn body(&self) -> &'a Body<'tcx> {
+        self.body
+    }
+
+    pub fn analysis(&self) -> &A {
+        &self.analysis
+    }
+
+    /// Get the state on entry to the basic block `bb`.
+    pub fn entry_set(&self, bb: BasicBlock) -> &A::Domain {
+        &self.entry_sets[bb]
+    }
+
+    /// Get the state immediately before the statement or terminator at `loc`.
+    pub fn state_before(&self, loc: Location) -> A::Domain {
+        let data = &self.body.basic_blocks()[loc.block];
+        let mut state = self.entry_sets[loc.block].clone();
+        for (i, stmt) in data.statements[..loc.statement_index].iter().enumerate() {
+            let stmt_loc = Location { block: loc.block, statement_index: i };
+            self.analysis.apply_statement(&mut state, stmt, stmt_loc);
+        }
+        state
+    }
+
+    /// Call `f` with each location in the body and the state immediately before it.
+    pub fn visit_with_state<F>(&self, mut f: F)
+    where
+        F: FnMut(Location, &A::Domain),
+    {
+        for (bb, data) in self.body.basic_blocks().iter_enumerated() {
+            let mut state = self.entry_sets[bb].clone();
+            for (i, stmt) in data.statements.iter().enumerate() {
+                let loc = Location { block: bb, statement_index: i };
+                f(loc, &state);
+                self.analysis.apply_statement(&mut state, stmt, loc);
+            }
+            f(Location { block: bb, statement_index: data.statements.len() }, &state);
+        }
+    }
+
+    /// Get the state on entry to the code lowered from the AST node whose span is `span`.  This is
+    /// the join of the states before each location inside `span` that is not preceded, in the
+    /// same basic block, by another location inside `span`.  Returns `None` if no code in the body
+    /// comes from `span`.
+    pub fn state_before_span(&self, span: Span) -> Option<A::Domain> {
+        let mut result: Option<A::Domain> = None;
+        let mut prev_inside = false;
+        let mut prev_block = None;
+        self.visit_with_state(|loc, state| {
+            if prev_block != Some(loc.block) {
+                prev_inside = false;
+                prev_block = Some(loc.block);
+            }
+            let inside = span.contains(location_span(self.body, loc));
+            if inside && !prev_inside {
+                match result {
+                    Some(ref mut r) => {
+                        self.analysis.join(r, state);
+                    }
+                    None => result = Some(state.clone()),
+                }
+            }
+            prev_inside = inside;
+        });
+        result
+    }
+}
+
+/// Get the source span of the statement or terminator at `loc`.
+pub fn location_span(body: &Body, loc: Location) -> Span {
+    body.source_info(loc).span
+}
+
+/// Run `analysis` on `body` until it reaches a fixpoint.
+pub fn analyze_body<'a, 'tcx, A>(body: &'a Body<'tcx>, analysis: A) -> Results<'a, 'tcx, A>
+where
+    A: Analysis<'tcx>,
+{
+    let bottom = analysis.bottom(body);
+    let mut entry_sets = IndexVec::from_elem(bottom, body.basic_blocks());
+    entry_sets[START_BLOCK] = analysis.entry_state(body);
+
+    let num_blocks = body.basic_blocks().len();
+    let mut queue = VecDeque::with_capacity(num_blocks);
+    let mut queued = BitSet::new_empty(num_blocks);
+    queue.push_back(START_BLOCK);
+    queued.insert(START_BLOCK);
+
+    while let Some(bb) = queue.pop_front() {
+        queued.remove(bb);
+        let data = &body.basic_blocks()[bb];
+        let mut state = entry_sets[bb].clone();
+        for (i, stmt) in data.statements.iter().enumerate() {
+            analysis.apply_statement(&mut state, stmt, Location { block: bb, statement_index: i });
+        }
+        let term = data.terminator();
+        let term_loc = Location { block: bb, statement_index: data.statements.len() };
+        analysis.apply_terminator(&mut state, term, term_loc);
+
+        for &succ in term.successors() {
+            let mut edge_state = state.clone();
+            analysis.apply_edge(&mut edge_state, body, bb, succ);
+            if analysis.join(&mut entry_sets[succ], &edge_state) && queued.insert(succ) {
+                queue.push_back(succ);
+            }
+        }
+    }
+
+    Results { body, analysis, entry_sets }
+}
+
+/// Run `analysis` on the optimized MIR of the function `def_id`.  Returns `None` if no MIR is
+/// available for it.
+pub fn analyze_fn<'tcx, A>(cx: &RefactorCtxt<'_, 'tcx>, def_id: DefId, analysis: A)
+                           -> Option<Results<'tcx, 'tcx, A>>
+where
+    A: Analysis<'tcx>,
+{
+    let tcx = cx.ty_ctxt();
+    if !def_id.is_local() || !tcx.is_mir_available(def_id) {
+        return None;
+    }
+    Some(analyze_body(tcx.optimized_mir(def_id), analysis))
+}
+
+/// Get the span of the pattern that binds the user variable `local`, if it is one.
+pub fn local_pat_span(body: &Body, local: Local) -> Option<Span> {
+    match body.local_decls[local].local_info {
+        LocalInfo::User(ClearCrossCrate::Set(BindingForm::Var(ref var))) => Some(var.pat_span),
+        _ => None,
+    }
+}
+
+/// Find the local for the user variable bound by the pattern whose span is `pat_span`.
+pub fn local_for_pat(body: &Body, pat_span: Span) -> Option<Local> {
+    body.local_decls.indices().find(|&l| local_pat_span(body, l) == Some(pat_span))
+}
@@ -7,9 +7,19 @@ use crate::driver::Phase;
 use arena::SyncDroplessArena;
 use c2rust_ast_builder::IntoSymbol;
 
+pub mod dataflow;
 pub mod labeled_ty;
 pub mod ownership;
+pub mod pointer_roles;
+pub mod points_to;
+pub mod ranges;
+pub mod string_provenance;
+pub mod taint;
+pub mod thread_sharing;
 pub mod type_eq;
+pub mod uninit_reads;
+pub mod unsafety;
+pub mod unsafety_report;
 
 /// # `test_analysis_type_eq` Command
 ///
@@ -27,6 +37,38 @@ fn register_test_analysis_type_eq(reg: &mut Registry) {
     });
 }
 
+/// # `test_analysis_points_to` Command
+///
+/// Test command - not intended for general use.
+///
+/// Usage: `test_analysis_points_to`
+///
+/// Runs the `points_to` analysis and logs the result (at level `info`).
+fn register_test_analysis_points_to(reg: &mut Registry) {
+    reg.register("test_analysis_points_to", |_args| {
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            let result = points_to::analyze(&cx, &st.krate());
+            info!("{:?}", result);
+        }))
+    });
+}
+
+/// # `test_analysis_ranges` Command
+///
+/// Test command - not intended for general use.
+///
+/// Usage: `test_analysis_ranges`
+///
+/// Runs the `ranges` analysis on every function and logs the range of each integer variable on
+/// entry to each basic block (at level `info`).
+fn register_test_analysis_ranges(reg: &mut Registry) {
+    reg.register("test_analysis_ranges", |_args| {
+        Box::new(DriverCommand::new(Phase::Phase3, move |_st, cx| {
+            ranges::dump_ranges(&cx);
+        }))
+    });
+}
+
 /// # `test_analysis_ownership` Command
 ///
 /// Test command - not intended for general use.
@@ -91,8 +133,99 @@ fn register_mark_related_types(reg: &mut Registry) {
     });
 }
 
+/// # `analyze_string_provenance` Command
+///
+/// Usage: `analyze_string_provenance`
+///
+/// Marks: sets `utf8`, `binary`, and `unknown`
+///
+/// Track where the contents of each C string and byte buffer in the crate come from, and mark
+/// each local, argument, struct field, and static of type `*const c_char`, `*mut c_char`,
+/// `[c_char; N]`, or `Vec<u8>` with the result:
+///
+///  * `utf8` if every value stored into it is known to be valid UTF-8, such as a string literal
+///    or a copy of one.
+///  * `binary` if it may hold data read by `read`, `recv`, or `fread`, or a bytestring literal
+///    that isn't valid UTF-8.
+///  * `unknown` otherwise, for example if it holds text read with `fgets` or returned by a
+///    foreign function.
+///
+/// Locals and arguments are marked on their binding patterns.  `cstr_to_str auto` uses these
+/// marks to choose between `&str` and `&CStr`, and the format string conversions use them to
+/// print possibly non-UTF-8 strings with `to_string_lossy` instead of `to_str().unwrap()`.  See
+/// the `string_provenance` module for details of the analysis.
+fn register_analyze_string_provenance(reg: &mut Registry) {
+    reg.register("analyze_string_provenance", |_args| {
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            string_provenance::mark_provenance(st, cx, &st.krate());
+        }))
+    });
+}
+
+/// # `analyze_thread_sharing` Command
+///
+/// Usage: `analyze_thread_sharing`
+///
+/// Marks: sets `shared` and `unsync`
+///
+/// Find the data that may be accessed by more than one thread at once, and the accesses to it
+/// that aren't protected by a lock.  Threads are those started with `pthread_create` or
+/// `thrd_create`, and callbacks passed to foreign functions also count as running on a thread of
+/// their own.  This command marks:
+///
+///  * `shared` on each `static mut` accessed from more than one thread, and on each local or
+///    allocation that may be passed to a new thread as its argument.
+///  * `unsync` on each access to that data, by name or through a pointer, that isn't between a
+///    call to `pthread_mutex_lock` (or a similar function) and the matching unlock in the same
+///    function.  A warning is also printed for each one.
+///
+/// `fix_static_mut` uses the same analysis to avoid converting shared statics to atomics when
+/// their updates can't be done atomically.  See the `thread_sharing` module for details.
+fn register_analyze_thread_sharing(reg: &mut Registry) {
+    reg.register("analyze_thread_sharing", |_args| {
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            thread_sharing::mark_sharing(st, cx, &st.krate());
+        }))
+    });
+}
+
+/// # `analyze_taint` Command
+///
+/// Usage: `analyze_taint [FILE]`
+///
+/// Marks: sets `taint_source`, `tainted`, and `taint_sink`
+///
+/// Track data that an attacker may control through the crate, and report where it is used as a
+/// pointer offset, as the length of `memcpy` or a similar function, or as a `printf` format
+/// string.  Tainted data comes from the buffers filled by `read`, `recv`, `fgets`, `scanf`, and
+/// similar functions, from `getenv`, and from the arguments of the C `main` function.  This
+/// command marks:
+///
+///  * `taint_source` on each call that reads input, and on the arguments of `main`.
+///  * `tainted` on each local, argument, struct field, and static that may hold tainted data.
+///    Locals and arguments are marked on their binding patterns.
+///  * `taint_sink` on each pointer offset, copy length, and format string that may be tainted.
+///    A warning is also printed for each one.
+///
+/// If `FILE` is given, a JSON report listing each source and each sink, along with the sources
+/// that reach it, is written there.  See the `taint` module for details of the analysis.
+fn register_analyze_taint(reg: &mut Registry) {
+    reg.register("analyze_taint", |args| {
+        let path = args.get(0).cloned();
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            taint::mark_taint(st, cx, &st.krate(), path.as_ref().map(|s| s.as_str()));
+        }))
+    });
+}
+
 pub fn register_commands(reg: &mut Registry) {
     register_test_analysis_type_eq(reg);
+    register_test_analysis_points_to(reg);
+    register_test_analysis_ranges(reg);
     register_test_analysis_ownership(reg);
     register_mark_related_types(reg);
+    register_analyze_string_provenance(reg);
+    register_analyze_thread_sharing(reg);
+    register_analyze_taint(reg);
+    unsafety_report::register_commands(reg);
 }
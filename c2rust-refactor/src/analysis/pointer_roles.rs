@@ -0,0 +1,334 @@
+//! Classify pointer-typed function arguments, return values, and struct fields by the role they
+//! play in managing heap memory.
+//!
+//! Each pointer slot is assigned one of three roles:
+//!
+//!  * `Owned`: the slot is responsible for freeing the memory it points to.  A slot is owning if it
+//!    receives the result of `malloc`, `calloc`, or `realloc`, if it is passed to `free`, or if
+//!    ownership flows into or out of it through an assignment, call, or return.
+//!  * `Aliased`: the slot is not owning, but its value is stored somewhere longer-lived than a
+//!    single call, such as a struct field, a static, or a location behind another pointer.
+//!  * `Borrowed`: the slot is not owning and its value does not escape, so it can be treated as a
+//!    temporary reference.
+//!
+//! The analysis is interprocedural: ownership flows backward from callee arguments that take
+//! ownership into the caller's values, and forward from owning return values into the caller's
+//! locals and fields.  It is a syntactic approximation, based on the flow of values between
+//! locals, arguments, fields, and return values.  Flows through pointer dereferences, arrays, and
+//! method calls are not tracked.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+
+use crate::ast_manip::fn_edit::{visit_fns, FnKind};
+use crate::ast_manip::visit_nodes;
+use crate::transform::heap::{field_def_id, is_call_to, strip_casts};
+use crate::RefactorCtxt;
+
+
+/// The role a pointer plays in managing the memory it points to.  Roles are ordered from weakest
+/// to strongest.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum PointerRole {
+    Borrowed,
+    Aliased,
+    Owned,
+}
+
+impl fmt::Display for PointerRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            PointerRole::Borrowed => "borrowed",
+            PointerRole::Aliased => "aliased",
+            PointerRole::Owned => "owned",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A pointer-typed location whose role is reported by the analysis.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PointerSlot {
+    /// The argument at the given index of a function.
+    Arg(DefId, usize),
+    /// The return value of a function.
+    Return(DefId),
+    /// A struct or union field.
+    Field(DefId),
+}
+
+/// A location that values can flow into or out of.  This includes the reported `PointerSlot`s,
+/// along with some internal locations that are not reported.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Node {
+    Slot(PointerSlot),
+    Local(HirId),
+    /// Any location we don't track, such as `*p` or a static.
+    Escape,
+}
+
+/// Where the value of an expression comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Source {
+    Node(Node),
+    Alloc,
+}
+
+/// The results of the analysis.
+pub struct PointerRoles {
+    roles: HashMap<PointerSlot, PointerRole>,
+}
+
+impl PointerRoles {
+    pub fn get(&self, slot: PointerSlot) -> Option<PointerRole> {
+        self.roles.get(&slot).cloned()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (PointerSlot, PointerRole)> + 'a {
+        self.roles.iter().map(|(&k, &v)| (k, v))
+    }
+}
+
+struct Facts<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// Pointer arguments, keyed by the `HirId` of their bindings.
+    args: HashMap<HirId, PointerSlot>,
+    /// Value flows from a source node to a destination node.
+    flows: Vec<(Node, Node)>,
+    /// Nodes that receive freshly allocated memory.
+    allocs: Vec<Node>,
+    /// Nodes that are passed to `free`.
+    frees: Vec<Node>,
+}
+
+impl<'a, 'tcx> Facts<'a, 'tcx> {
+    fn expr_node(&self, e: &Expr) -> Option<Node> {
+        let e = strip_casts(e);
+        match e.kind {
+            ExprKind::Path(..) => {
+                if let Some(hid) = self.cx.try_resolve_expr_to_hid(e) {
+                    Some(self.args.get(&hid).map_or(Node::Local(hid), |&s| Node::Slot(s)))
+                } else if self.cx.try_resolve_expr(e)
+                        .map_or(false, |did| self.cx.ty_ctxt().static_mutability(did).is_some()) {
+                    Some(Node::Escape)
+                } else {
+                    None
+                }
+            }
+            ExprKind::Field(ref obj, ident) =>
+                field_def_id(self.cx, obj.id, ident).map(|did| Node::Slot(PointerSlot::Field(did))),
+            ExprKind::Unary(UnOp::Deref, _) | ExprKind::Index(..) => Some(Node::Escape),
+            _ => None,
+        }
+    }
+
+    fn expr_source(&self, e: &Expr) -> Option<Source> {
+        let e = strip_casts(e);
+        if is_call_to(e, "malloc") || is_call_to(e, "calloc") || is_call_to(e, "realloc") {
+            return Some(Source::Alloc);
+        }
+        if let ExprKind::Call(..) = e.kind {
+            let did = self.cx.opt_callee(e)?;
+            return Some(Source::Node(Node::Slot(PointerSlot::Return(did))));
+        }
+        match self.expr_node(e)? {
+            Node::Escape => None,
+            n => Some(Source::Node(n)),
+        }
+    }
+
+    fn add_flow(&mut self, src: &Expr, dest: Node) {
+        match self.expr_source(src) {
+            Some(Source::Node(n)) => self.flows.push((n, dest)),
+            Some(Source::Alloc) => self.allocs.push(dest),
+            None => {}
+        }
+    }
+
+    fn collect_expr(&mut self, e: &Expr) {
+        match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) => {
+                if let Some(dest) = self.expr_node(lhs) {
+                    self.add_flow(rhs, dest);
+                }
+            }
+
+            ExprKind::Call(_, ref args) => {
+                if is_call_to(e, "free") {
+                    if let Some(n) = args.get(0).and_then(|a| self.expr_node(a)) {
+                        self.frees.push(n);
+                    }
+                } else if is_call_to(e, "realloc") {
+                    // `realloc` frees its argument, and the result is handled as an allocation.
+                    if let Some(n) = args.get(0).and_then(|a| self.expr_node(a)) {
+                        self.frees.push(n);
+                    }
+                } else if let Some(did) = self.cx.opt_callee(e) {
+                    for (i, arg) in args.iter().enumerate() {
+                        self.add_flow(arg, Node::Slot(PointerSlot::Arg(did, i)));
+                    }
+                }
+            }
+
+            ExprKind::Struct(_, ref fields, _) => {
+                for f in fields {
+                    if let Some(did) = field_def_id(self.cx, e.id, f.ident) {
+                        self.add_flow(&f.expr, Node::Slot(PointerSlot::Field(did)));
+                    }
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn collect_output(&mut self, e: &Expr, ret: Node) {
+        match e.kind {
+            ExprKind::Block(ref b, _) => self.collect_block_output(b, ret),
+            ExprKind::If(_, ref then, ref els) => {
+                self.collect_block_output(then, ret);
+                if let Some(ref els) = *els {
+                    self.collect_output(els, ret);
+                }
+            }
+            _ => self.add_flow(e, ret),
+        }
+    }
+
+    fn collect_block_output(&mut self, b: &Block, ret: Node) {
+        if let Some(&Stmt { kind: StmtKind::Expr(ref e), .. }) = b.stmts.last() {
+            self.collect_output(e, ret);
+        }
+    }
+}
+
+fn is_raw_ptr(cx: &RefactorCtxt, id: NodeId) -> bool {
+    cx.opt_node_type(id).map_or(false, |ty| matches!([ty.kind] TyKind::RawPtr(..)))
+}
+
+/// Run the analysis on `krate`.  This must be run in phase 3.
+pub fn analyze(cx: &RefactorCtxt, krate: &Crate) -> PointerRoles {
+    let mut roles = HashMap::new();
+    let mut facts = Facts {
+        cx,
+        args: HashMap::new(),
+        flows: Vec::new(),
+        allocs: Vec::new(),
+        frees: Vec::new(),
+    };
+
+    // (1) Find the pointer slots.
+
+    visit_fns(krate, |fl| {
+        if fl.kind == FnKind::Foreign {
+            // We can't see how foreign functions use their arguments.  Allocator calls are
+            // handled specially instead.
+            return;
+        }
+        let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(fl.id)]
+                            Some(x) => x; return);
+        for (i, arg) in fl.decl.inputs.iter().enumerate() {
+            if is_raw_ptr(cx, arg.pat.id) {
+                let slot = PointerSlot::Arg(did, i);
+                facts.args.insert(cx.hir_map().node_to_hir_id(arg.pat.id), slot);
+                roles.insert(slot, PointerRole::Borrowed);
+            }
+        }
+        let sig = cx.ty_ctxt().fn_sig(did);
+        if let TyKind::RawPtr(..) = sig.skip_binder().output().kind {
+            roles.insert(PointerSlot::Return(did), PointerRole::Borrowed);
+        }
+    });
+
+    visit_nodes(krate, |i: &Item| {
+        let vd = match_or!([i.kind] ItemKind::Struct(ref vd, _) | ItemKind::Union(ref vd, _) => vd;
+                           return);
+        for f in vd.fields() {
+            if is_raw_ptr(cx, f.ty.id) {
+                let did = cx.node_def_id(f.id);
+                // Non-owning fields always outlive the function that stores into them.
+                roles.insert(PointerSlot::Field(did), PointerRole::Aliased);
+            }
+        }
+    });
+
+    // (2) Collect the flows between nodes.
+
+    visit_fns(krate, |fl| {
+        let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(fl.id)]
+                            Some(x) => x; return);
+        let block = match_or!([fl.block] Some(ref b) => b; return);
+        let ret = Node::Slot(PointerSlot::Return(did));
+
+        visit_nodes(&**block, |l: &Local| {
+            if let (PatKind::Ident(..), Some(init)) = (&l.pat.kind, &l.init) {
+                let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+                facts.add_flow(init, Node::Local(hid));
+            }
+        });
+        visit_nodes(&**block, |e: &Expr| {
+            facts.collect_expr(e);
+            if let ExprKind::Ret(Some(ref e)) = e.kind {
+                facts.add_flow(e, ret);
+            }
+        });
+        facts.collect_block_output(block, ret);
+    });
+
+    // (3) Propagate ownership.  A node that is freed, or whose value is later owned, owns the value
+    // it was given.  Ownership also flows forward out of allocations, out of return values, and
+    // into fields and locals, but not into arguments, since passing a pointer to a function
+    // doesn't transfer ownership unless the callee takes it.
+
+    let mut owned = facts.allocs.iter().chain(facts.frees.iter())
+        .cloned()
+        .collect::<HashSet<_>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(src, dest) in &facts.flows {
+            let forward = match dest {
+                Node::Slot(PointerSlot::Arg(..)) | Node::Escape => false,
+                _ => true,
+            };
+            if owned.contains(&dest) && owned.insert(src) {
+                changed = true;
+            } else if forward && owned.contains(&src) && owned.insert(dest) {
+                changed = true;
+            }
+        }
+    }
+
+    // (4) Propagate aliasing.  A non-owning value that flows into a long-lived location is
+    // aliased, as is anything it was copied from.
+
+    let mut aliased = roles.iter()
+        .filter(|&(_, &role)| role == PointerRole::Aliased)
+        .map(|(&slot, _)| Node::Slot(slot))
+        .chain(Some(Node::Escape))
+        .collect::<HashSet<_>>();
+    changed = true;
+    while changed {
+        changed = false;
+        for &(src, dest) in &facts.flows {
+            if aliased.contains(&dest) && !owned.contains(&src) && aliased.insert(src) {
+                changed = true;
+            }
+        }
+    }
+
+    for (&slot, role) in roles.iter_mut() {
+        let n = Node::Slot(slot);
+        if owned.contains(&n) {
+            *role = PointerRole::Owned;
+        } else if aliased.contains(&n) {
+            *role = PointerRole::Aliased;
+        }
+    }
+
+    PointerRoles { roles }
+}
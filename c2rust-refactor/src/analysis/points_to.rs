@@ -0,0 +1,592 @@
+//! Interprocedural points-to analysis, for answering "may these two pointers alias?" questions
+//! in transforms.
+//!
+//! This is an inclusion-based (Andersen-style) analysis over the AST.  It is flow-insensitive,
+//! context-insensitive, and field-insensitive: every local, argument, and static is a single
+//! abstract object, whose fields and array elements are not distinguished, and a pointer's
+//! points-to set is the union of everything it may point to at any point in the program.
+//!
+//! Abstract objects are:
+//!
+//!  * `Var`: the storage of a local, argument, or static.
+//!  * `Alloc`: memory allocated at a particular call to `malloc`, `calloc`, `realloc`, or
+//!    `strdup`, or the storage of a string literal or other temporary whose address is taken.
+//!  * `Unknown`: memory outside the crate, or reached through values the analysis doesn't
+//!    understand, such as integers cast to pointers.  Pointers passed to foreign or unknown
+//!    functions escape into `Unknown`, and `Unknown` may be written through them.
+//!
+//! Values flow through assignments, initializers, pattern bindings, pointer arithmetic, calls to
+//! and returns from functions in the crate, `memcpy`-like copies, and from the argument of
+//! `pthread_create` to the new thread's start function.  Null pointers point to nothing.
+//! Closures, calls through function pointers, and method calls are treated as unknown code.
+//!
+//! Results are queried by expression: `PointsTo::points_to` gives the objects an expression may
+//! point to, and `PointsTo::aliases` checks whether two pointer expressions may point to the same
+//! object.  Queries must use expressions from the crate that was analyzed.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::source_map::Span;
+use syntax::visit::{self, FnKind, Visitor};
+
+use crate::analysis::thread_sharing::{fn_ref, spawned_thread};
+use crate::ast_manip::fn_edit::visit_fns;
+use crate::ast_manip::Visit;
+use crate::transform::heap::{callee_name, is_null_ptr};
+use crate::RefactorCtxt;
+
+/// An abstract memory location.  Each one is both an object that pointers can point to and a
+/// holder of pointer values.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Loc {
+    /// A local, argument, or static, identified by its binding or definition.
+    Var(HirId),
+    /// An allocation site or address-taken temporary, identified by its expression.
+    Alloc(NodeId),
+    /// Memory outside the crate.
+    Unknown,
+    /// The return value of a function.  Not addressable.
+    Ret(DefId),
+    /// The value of an expression.  Not addressable.
+    Temp(NodeId),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Constraint {
+    /// `pts(dst) ∋ obj`
+    Addr(Loc, Loc),
+    /// `pts(dst) ⊇ pts(src)`
+    Copy(Loc, Loc),
+    /// `pts(dst) ⊇ pts(o)` for each `o` in `pts(src)`
+    Load(Loc, Loc),
+    /// `pts(o) ⊇ pts(src)` for each `o` in `pts(dst)`
+    Store(Loc, Loc),
+}
+
+/// A place expression, as the object it names or the pointer it dereferences.
+#[derive(Clone, Copy, Debug)]
+enum Place {
+    Obj(Loc),
+    Deref(Loc),
+}
+
+fn arg(args: &[Option<Loc>], i: usize) -> Option<Loc> {
+    args.get(i).cloned().unwrap_or(None)
+}
+
+struct ConstraintVisitor<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The argument bindings of each function in the crate.
+    params: HashMap<DefId, Vec<Vec<HirId>>>,
+    /// The functions enclosing the current node, innermost last.  Closures push `None`.
+    fns: Vec<Option<DefId>>,
+    /// The location holding the value of each expression visited so far.
+    exprs: HashMap<NodeId, Option<Loc>>,
+    constraints: Vec<Constraint>,
+}
+
+/// Collect the bindings in `p`.
+fn pat_bindings(cx: &RefactorCtxt, p: &Pat) -> Vec<HirId> {
+    let mut hids = Vec::new();
+    p.walk(&mut |p| {
+        if let PatKind::Ident(..) = p.kind {
+            hids.extend(cx.hir_map().opt_node_to_hir_id(p.id));
+        }
+        true
+    });
+    hids
+}
+
+impl<'a, 'tcx> ConstraintVisitor<'a, 'tcx> {
+    fn add(&mut self, c: Constraint) {
+        self.constraints.push(c);
+    }
+
+    fn copy_from(&mut self, dst: Loc, src: Option<Loc>) {
+        if let Some(src) = src {
+            self.add(Constraint::Copy(dst, src));
+        }
+    }
+
+    fn bind_pat(&mut self, p: &Pat, src: Option<Loc>) {
+        for hid in pat_bindings(self.cx, p) {
+            self.copy_from(Loc::Var(hid), src);
+        }
+    }
+
+    fn is_array(&self, e: &Expr) -> bool {
+        let ty = match_or!([self.cx.opt_node_type(e.id)] Some(x) => x; return false);
+        matches!([ty.kind] TyKind::Array(..))
+    }
+
+    fn is_ref(&self, e: &Expr) -> bool {
+        let ty = match_or!([self.cx.opt_node_type(e.id)] Some(x) => x; return false);
+        matches!([ty.kind] TyKind::Ref(..))
+    }
+
+    /// Get the place named by the expression `e`.
+    fn place_of(&mut self, e: &Expr) -> Place {
+        match e.kind {
+            ExprKind::Paren(ref inner) => self.place_of(inner),
+            ExprKind::Path(..) => match self.cx.try_resolve_expr_to_hid(e) {
+                Some(hid) => Place::Obj(Loc::Var(hid)),
+                None => Place::Obj(Loc::Unknown),
+            },
+            ExprKind::Unary(UnOp::Deref, ref ptr) => {
+                let ptr = self.node_of(ptr).unwrap_or(Loc::Unknown);
+                Place::Deref(ptr)
+            }
+            ExprKind::Field(ref base, _) | ExprKind::Index(ref base, _) => {
+                // Indexing a slice and accessing a field through a reference both go through a
+                // pointer.  Arrays and structs are part of the enclosing object.
+                let through_ptr = match e.kind {
+                    ExprKind::Index(..) => !self.is_array(base),
+                    _ => self.is_ref(base),
+                };
+                if through_ptr {
+                    let ptr = self.node_of(base).unwrap_or(Loc::Unknown);
+                    Place::Deref(ptr)
+                } else {
+                    self.place_of(base)
+                }
+            }
+            _ => {
+                // A temporary, such as `&f()`.
+                let obj = Loc::Alloc(e.id);
+                let value = self.node_of(e);
+                self.copy_from(obj, value);
+                Place::Obj(obj)
+            }
+        }
+    }
+
+    /// Get the location holding the value of reading the place `e`.
+    fn read_place(&mut self, e: &Expr) -> Option<Loc> {
+        match self.place_of(e) {
+            Place::Obj(obj) => Some(obj),
+            Place::Deref(ptr) => {
+                let t = Loc::Temp(e.id);
+                self.add(Constraint::Load(t, ptr));
+                Some(t)
+            }
+        }
+    }
+
+    fn write_place(&mut self, lhs: &Expr, src: Option<Loc>) {
+        let src = match_or!([src] Some(x) => x; return);
+        match self.place_of(lhs) {
+            Place::Obj(obj) => self.add(Constraint::Copy(obj, src)),
+            Place::Deref(ptr) => self.add(Constraint::Store(ptr, src)),
+        }
+    }
+
+    /// Record that `ptr` is passed to code outside the crate.
+    fn escape(&mut self, ptr: Option<Loc>) {
+        if let Some(ptr) = ptr {
+            self.add(Constraint::Copy(Loc::Unknown, ptr));
+            self.add(Constraint::Store(ptr, Loc::Unknown));
+        }
+    }
+
+    fn block_value(&mut self, b: &Block) -> Option<Loc> {
+        match b.stmts.last() {
+            Some(&Stmt { kind: StmtKind::Expr(ref e), .. }) => self.node_of(e),
+            _ => None,
+        }
+    }
+
+    /// Get the location holding the value of `e`, and record the constraints for evaluating it.
+    /// Returns `None` for expressions that can't produce a pointer.
+    fn node_of(&mut self, e: &Expr) -> Option<Loc> {
+        if let Some(&loc) = self.exprs.get(&e.id) {
+            return loc;
+        }
+        // Guard against revisiting `e` while computing its value.
+        self.exprs.insert(e.id, None);
+        let loc = self.compute_node(e);
+        self.exprs.insert(e.id, loc);
+        loc
+    }
+
+    fn compute_node(&mut self, e: &Expr) -> Option<Loc> {
+        if is_null_ptr(e) {
+            return None;
+        }
+        let t = Loc::Temp(e.id);
+        match e.kind {
+            ExprKind::Paren(ref inner) => self.node_of(inner),
+
+            ExprKind::Cast(ref inner, _) => {
+                let from_int = self.cx.opt_node_type(inner.id)
+                    .map_or(false, |ty| matches!([ty.kind] TyKind::Int(_), TyKind::Uint(_)));
+                if from_int {
+                    self.add(Constraint::Addr(t, Loc::Unknown));
+                    Some(t)
+                } else {
+                    self.node_of(inner)
+                }
+            }
+
+            ExprKind::Lit(ref l) => match l.kind {
+                LitKind::Str(..) | LitKind::ByteStr(..) => {
+                    self.add(Constraint::Addr(t, Loc::Alloc(e.id)));
+                    Some(t)
+                }
+                _ => None,
+            },
+
+            ExprKind::Path(..) | ExprKind::Field(..) | ExprKind::Index(..) |
+            ExprKind::Unary(UnOp::Deref, _) => self.read_place(e),
+
+            ExprKind::AddrOf(_, _, ref place) => match self.place_of(place) {
+                Place::Obj(obj) => {
+                    self.add(Constraint::Addr(t, obj));
+                    Some(t)
+                }
+                Place::Deref(ptr) => Some(ptr),
+            },
+
+            ExprKind::Assign(ref lhs, ref rhs) => {
+                let src = self.node_of(rhs);
+                self.write_place(lhs, src);
+                None
+            }
+
+            ExprKind::AssignOp(_, ref lhs, ref rhs) => {
+                self.node_of(rhs);
+                self.place_of(lhs);
+                None
+            }
+
+            ExprKind::Call(ref func, ref args) => {
+                let arg_locs = args.iter().map(|a| self.node_of(a)).collect::<Vec<_>>();
+                self.call(e, func, &arg_locs)
+            }
+
+            ExprKind::MethodCall(ref seg, ref args) => {
+                let arg_locs = args.iter().map(|a| self.node_of(a)).collect::<Vec<_>>();
+                match &*seg.ident.as_str() {
+                    "offset" | "add" | "sub" | "wrapping_offset" | "wrapping_add" |
+                    "wrapping_sub" | "cast" => arg_locs[0],
+                    // `a.as_ptr()` on an array points into `a` itself.
+                    "as_ptr" | "as_mut_ptr" if self.is_array(&args[0]) => {
+                        match self.place_of(&args[0]) {
+                            Place::Obj(obj) => self.add(Constraint::Addr(t, obj)),
+                            Place::Deref(ptr) => self.add(Constraint::Copy(t, ptr)),
+                        }
+                        Some(t)
+                    }
+                    "as_ptr" | "as_mut_ptr" => arg_locs[0],
+                    _ => {
+                        for &a in &arg_locs {
+                            self.escape(a);
+                        }
+                        self.add(Constraint::Addr(t, Loc::Unknown));
+                        Some(t)
+                    }
+                }
+            }
+
+            ExprKind::Block(ref b, _) => self.block_value(b),
+
+            ExprKind::If(ref cond, ref then, ref els) => {
+                let then_value = self.block_value(then);
+                self.copy_from(t, then_value);
+                if let Some(ref els) = *els {
+                    let els_value = self.node_of(els);
+                    self.copy_from(t, els_value);
+                }
+                Some(t)
+            }
+
+            ExprKind::Match(ref scrut, ref arms) => {
+                let scrut = self.node_of(scrut);
+                for arm in arms {
+                    self.bind_pat(&arm.pat, scrut);
+                    let body = self.node_of(&arm.body);
+                    self.copy_from(t, body);
+                }
+                Some(t)
+            }
+
+            ExprKind::Let(ref pat, ref scrut) => {
+                let scrut = self.node_of(scrut);
+                self.bind_pat(pat, scrut);
+                None
+            }
+
+            ExprKind::Struct(_, ref fields, ref base) => {
+                for f in fields {
+                    let value = self.node_of(&f.expr);
+                    self.copy_from(t, value);
+                }
+                if let Some(ref base) = *base {
+                    let value = self.node_of(base);
+                    self.copy_from(t, value);
+                }
+                Some(t)
+            }
+
+            ExprKind::Tup(ref elems) | ExprKind::Array(ref elems) => {
+                for elem in elems {
+                    let value = self.node_of(elem);
+                    self.copy_from(t, value);
+                }
+                Some(t)
+            }
+
+            ExprKind::Repeat(ref elem, _) => self.node_of(elem),
+
+            ExprKind::Ret(ref value) => {
+                if let Some(ref value) = *value {
+                    let value = self.node_of(value);
+                    if let Some(&Some(did)) = self.fns.last() {
+                        self.copy_from(Loc::Ret(did), value);
+                    }
+                }
+                None
+            }
+
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, e: &Expr, func: &Expr, args: &[Option<Loc>]) -> Option<Loc> {
+        let t = Loc::Temp(e.id);
+        if let Some(did) = self.cx.opt_callee(e) {
+            if let Some(params) = self.params.get(&did).cloned() {
+                for (bindings, &a) in params.iter().zip(args) {
+                    for &hid in bindings {
+                        self.copy_from(Loc::Var(hid), a);
+                    }
+                }
+                return Some(Loc::Ret(did));
+            }
+        }
+
+        let name = callee_name(e).map(|n| n.as_str());
+        match name.as_ref().map(|n| &**n) {
+            Some("malloc") | Some("calloc") | Some("strdup") | Some("strndup") => {
+                self.add(Constraint::Addr(t, Loc::Alloc(e.id)));
+                Some(t)
+            }
+            Some("realloc") => {
+                self.add(Constraint::Addr(t, Loc::Alloc(e.id)));
+                self.copy_from(t, arg(args, 0));
+                // The old contents move to the new allocation.
+                let old = Loc::Temp(func.id);
+                if let Some(ptr) = arg(args, 0) {
+                    self.add(Constraint::Load(old, ptr));
+                    self.add(Constraint::Store(t, old));
+                }
+                Some(t)
+            }
+            Some("free") => None,
+            Some("pthread_create") | Some("thrd_create") => {
+                // The new thread's start function receives the argument.
+                let (start, thread_arg) = match_or!([spawned_thread(e)] Some(x) => x; return None);
+                let start = match_or!([fn_ref(self.cx, start)] Some(x) => x; return None);
+                let value = thread_arg.and_then(|a| self.node_of(a));
+                let first = self.params.get(&start).and_then(|p| p.first()).cloned();
+                for hid in first.unwrap_or_default() {
+                    self.copy_from(Loc::Var(hid), value);
+                }
+                None
+            }
+            Some("memcpy") | Some("memmove") | Some("strcpy") | Some("strncpy") |
+            Some("strcat") | Some("strncat") => {
+                let (dest, src) = match (arg(args, 0), arg(args, 1)) {
+                    (Some(dest), Some(src)) => (dest, src),
+                    (dest, _) => return dest,
+                };
+                // Copy the pointers stored in `*src` into `*dest`.
+                let contents = Loc::Temp(func.id);
+                self.add(Constraint::Load(contents, src));
+                self.add(Constraint::Store(dest, contents));
+                Some(dest)
+            }
+            Some("memset") | Some("strchr") | Some("strrchr") | Some("strstr") |
+            Some("strpbrk") => arg(args, 0),
+            _ => {
+                for &a in args {
+                    self.escape(a);
+                }
+                self.add(Constraint::Addr(t, Loc::Unknown));
+                Some(t)
+            }
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for ConstraintVisitor<'a, 'tcx> {
+    fn visit_item(&mut self, i: &'ast Item) {
+        if let ItemKind::Static(_, _, ref init) = i.kind {
+            let value = self.node_of(init);
+            if let Some(hid) = self.cx.hir_map().opt_node_to_hir_id(i.id) {
+                self.copy_from(Loc::Var(hid), value);
+            }
+        }
+        visit::walk_item(self, i);
+    }
+
+    fn visit_fn(&mut self, kind: FnKind<'ast>, fd: &'ast FnDecl, span: Span, id: NodeId) {
+        let did = match kind {
+            FnKind::ItemFn(..) | FnKind::Method(..) => Some(self.cx.node_def_id(id)),
+            FnKind::Closure(_) => None,
+        };
+        self.fns.push(did);
+        visit::walk_fn(self, kind, fd, span);
+        let body = match kind {
+            FnKind::ItemFn(_, _, _, body) | FnKind::Method(_, _, _, body) => Some(body),
+            FnKind::Closure(_) => None,
+        };
+        if let (Some(did), Some(body)) = (did, body) {
+            let value = self.block_value(body);
+            self.copy_from(Loc::Ret(did), value);
+        }
+        self.fns.pop();
+    }
+
+    fn visit_local(&mut self, l: &'ast Local) {
+        let value = match l.init {
+            Some(ref init) => self.node_of(init),
+            None => None,
+        };
+        self.bind_pat(&l.pat, value);
+        visit::walk_local(self, l);
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        // Children are visited again by the walk, but their values are memoized.
+        self.node_of(e);
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// The result of the points-to analysis.
+#[derive(Debug)]
+pub struct PointsTo {
+    pts: HashMap<Loc, HashSet<Loc>>,
+    exprs: HashMap<NodeId, Option<Loc>>,
+    empty: HashSet<Loc>,
+}
+
+impl PointsTo {
+    /// Get the objects that the value of `e` may point to.  Returns `None` if `e` was not part of
+    /// the analyzed crate.  Values that are always null, and values that aren't pointers, point
+    /// to nothing.
+    pub fn points_to(&self, e: &Expr) -> Option<&HashSet<Loc>> {
+        let loc = self.exprs.get(&e.id)?;
+        Some(loc.and_then(|loc| self.pts.get(&loc)).unwrap_or(&self.empty))
+    }
+
+    /// Get the objects that the local, argument, or static `hid` may point to.
+    pub fn var_points_to(&self, hid: HirId) -> &HashSet<Loc> {
+        self.pts.get(&Loc::Var(hid)).unwrap_or(&self.empty)
+    }
+
+    /// Get the locations that may hold a pointer to `obj`.
+    pub fn holders_of(&self, obj: Loc) -> Vec<Loc> {
+        self.pts.iter()
+            .filter(|&(_, objs)| objs.contains(&obj))
+            .map(|(&loc, _)| loc)
+            .collect()
+    }
+
+    /// Check if the pointers `a` and `b` may point to the same object.  This is conservative: it
+    /// returns `true` if either expression was not analyzed or may point to unknown memory.
+    pub fn aliases(&self, a: &Expr, b: &Expr) -> bool {
+        let (pa, pb) = match (self.points_to(a), self.points_to(b)) {
+            (Some(pa), Some(pb)) => (pa, pb),
+            _ => return true,
+        };
+        if pa.contains(&Loc::Unknown) && !pb.is_empty() ||
+           pb.contains(&Loc::Unknown) && !pa.is_empty() {
+            return true;
+        }
+        pa.intersection(pb).next().is_some()
+    }
+}
+
+/// Solve the constraints, by applying them repeatedly until nothing changes.
+fn solve(constraints: &[Constraint]) -> HashMap<Loc, HashSet<Loc>> {
+    let mut pts: HashMap<Loc, HashSet<Loc>> = HashMap::new();
+    // Unknown memory may hold pointers to unknown memory.
+    pts.entry(Loc::Unknown).or_insert_with(HashSet::new).insert(Loc::Unknown);
+
+    fn add_all(pts: &mut HashMap<Loc, HashSet<Loc>>, dst: Loc, src: &HashSet<Loc>) -> bool {
+        let set = pts.entry(dst).or_insert_with(HashSet::new);
+        let old_len = set.len();
+        set.extend(src.iter().cloned());
+        set.len() != old_len
+    }
+
+    loop {
+        let mut changed = false;
+        for &c in constraints {
+            match c {
+                Constraint::Addr(dst, obj) => {
+                    changed |= pts.entry(dst).or_insert_with(HashSet::new).insert(obj);
+                }
+                Constraint::Copy(dst, src) => {
+                    let src_pts = match_or!([pts.get(&src)] Some(x) => x.clone(); continue);
+                    changed |= add_all(&mut pts, dst, &src_pts);
+                }
+                Constraint::Load(dst, src) => {
+                    let objs = match_or!([pts.get(&src)] Some(x) => x.clone(); continue);
+                    for o in objs {
+                        let o_pts = match_or!([pts.get(&o)] Some(x) => x.clone(); continue);
+                        changed |= add_all(&mut pts, dst, &o_pts);
+                    }
+                }
+                Constraint::Store(dst, src) => {
+                    let objs = match_or!([pts.get(&dst)] Some(x) => x.clone(); continue);
+                    let src_pts = match_or!([pts.get(&src)] Some(x) => x.clone(); continue);
+                    for o in objs {
+                        changed |= add_all(&mut pts, o, &src_pts);
+                    }
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    pts
+}
+
+/// Run the points-to analysis on `krate`.
+pub fn analyze(cx: &RefactorCtxt, krate: &Crate) -> PointsTo {
+    let mut params = HashMap::new();
+    visit_fns(krate, |fl| {
+        if fl.block.is_none() {
+            return;
+        }
+        let bindings = fl.decl.inputs.iter()
+            .map(|arg| pat_bindings(cx, &arg.pat))
+            .collect::<Vec<_>>();
+        params.insert(cx.node_def_id(fl.id), bindings);
+    });
+
+    let mut v = ConstraintVisitor {
+        cx,
+        params,
+        fns: Vec::new(),
+        exprs: HashMap::new(),
+        constraints: Vec::new(),
+    };
+    krate.visit(&mut v);
+
+    PointsTo {
+        pts: solve(&v.constraints),
+        exprs: v.exprs,
+        empty: HashSet::new(),
+    }
+}
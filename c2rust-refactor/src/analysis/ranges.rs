@@ -0,0 +1,549 @@
+//! Value-range analysis for integer locals.
+//!
+//! This computes, for each point in a function, an interval containing every value each integer
+//! local can hold there.  It runs on the function's MIR using the `dataflow` framework.  Constants
+//! and arithmetic are evaluated with interval arithmetic.  Comparisons that control a branch
+//! narrow the ranges of their operands along each edge, so that `i` is known to be less than `n`
+//! inside `while i < n { ... }`.  To make loops converge, a block's state is widened to the full
+//! range of each type once it has changed a few times.  Branch conditions inside the loop can then
+//! narrow it again.
+//!
+//! A local whose address is taken could be changed through a pointer, so its range is always the
+//! full range of its type.  Overflow is assumed to wrap: if the result of an operation may not
+//! fit in its type, its range is the full range of the type.
+//!
+//! Transforms query the results with `ValueRanges`, which maps AST expressions to MIR locals and
+//! computes the results for each function the first time they are needed.
+
+use std::cmp;
+use std::collections::HashMap;
+use rustc::hir::def_id::DefId;
+use rustc::mir::*;
+use rustc::ty::{ParamEnv, Ty, TyCtxt, TyKind};
+use rustc_index::bit_set::BitSet;
+use rustc_index::vec::IndexVec;
+use syntax::ast::{Expr, ExprKind, LitKind, UnOp};
+use syntax::source_map::Span;
+
+use crate::analysis::dataflow::{self, Analysis, Results};
+use crate::RefactorCtxt;
+
+/// The number of times the state on entry to a block may change before it is widened.
+const WIDEN_DELAY: u32 = 2;
+
+/// A closed interval of integer values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Interval {
+    pub lo: i128,
+    pub hi: i128,
+}
+
+impl Interval {
+    /// The range of values that includes every integer.
+    pub const FULL: Interval = Interval { lo: i128::min_value(), hi: i128::max_value() };
+
+    pub fn new(lo: i128, hi: i128) -> Interval {
+        Interval { lo, hi }
+    }
+
+    pub fn single(n: i128) -> Interval {
+        Interval { lo: n, hi: n }
+    }
+
+    pub fn is_non_negative(&self) -> bool {
+        self.lo >= 0
+    }
+
+    pub fn contains(&self, other: Interval) -> bool {
+        self.lo <= other.lo && other.hi <= self.hi
+    }
+
+    /// Intersect two intervals.  Returns `None` if they have no values in common.
+    pub fn meet(&self, other: Interval) -> Option<Interval> {
+        let r = Interval::new(cmp::max(self.lo, other.lo), cmp::min(self.hi, other.hi));
+        if r.lo <= r.hi { Some(r) } else { None }
+    }
+
+    fn hull(&self, other: Interval) -> Interval {
+        Interval::new(cmp::min(self.lo, other.lo), cmp::max(self.hi, other.hi))
+    }
+
+    fn corners<F: Fn(i128, i128) -> i128>(&self, other: Interval, f: F) -> Interval {
+        let xs = [f(self.lo, other.lo), f(self.lo, other.hi),
+                  f(self.hi, other.lo), f(self.hi, other.hi)];
+        Interval::new(*xs.iter().min().unwrap(), *xs.iter().max().unwrap())
+    }
+}
+
+/// Get the width in bits of the primitive integer type `ty`.
+fn int_width<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<usize> {
+    let ptr_bits = tcx.data_layout.pointer_size.bits() as usize;
+    match ty.kind {
+        TyKind::Int(ity) => Some(ity.bit_width().unwrap_or(ptr_bits)),
+        TyKind::Uint(uty) => Some(uty.bit_width().unwrap_or(ptr_bits)),
+        _ => None,
+    }
+}
+
+/// Get the range of values of the integer type `ty`, or `None` if it isn't an integer type.
+/// `bool` and `char` count as integer types.
+pub fn int_bounds<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Option<Interval> {
+    match ty.kind {
+        TyKind::Int(_) => {
+            let bits = int_width(tcx, ty)?;
+            if bits >= 128 {
+                return Some(Interval::FULL);
+            }
+            Some(Interval::new(-(1 << (bits - 1)), (1 << (bits - 1)) - 1))
+        }
+        TyKind::Uint(_) => {
+            let bits = int_width(tcx, ty)?;
+            if bits >= 128 {
+                return Some(Interval::new(0, i128::max_value()));
+            }
+            Some(Interval::new(0, (1 << bits) - 1))
+        }
+        TyKind::Bool => Some(Interval::new(0, 1)),
+        TyKind::Char => Some(Interval::new(0, 0x10ffff)),
+        _ => None,
+    }
+}
+
+/// The state at a program point: the range of each local, or `None` if the point can't be
+/// reached.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RangeState {
+    ranges: IndexVec<Local, Interval>,
+    /// The number of times this state has changed by joining in another state.
+    changes: u32,
+}
+
+impl RangeState {
+    pub fn range(&self, local: Local) -> Interval {
+        self.ranges[local]
+    }
+}
+
+struct RangeAnalysis<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    body: &'a Body<'tcx>,
+    /// Locals whose address is taken somewhere in the function.
+    escaped: BitSet<Local>,
+}
+
+impl<'a, 'tcx> RangeAnalysis<'a, 'tcx> {
+    fn bounds(&self, ty: Ty<'tcx>) -> Interval {
+        int_bounds(self.tcx, ty).unwrap_or(Interval::FULL)
+    }
+
+    fn local_bounds(&self, local: Local) -> Interval {
+        self.bounds(self.body.local_decls[local].ty)
+    }
+
+    /// Get the local that `place` reads, if it's a whole local that we track.  A place
+    /// projecting field 0 also counts, since that's where checked arithmetic leaves its result.
+    fn tracked_local(&self, place: &Place<'tcx>) -> Option<Local> {
+        let l = match_or!([place.base] PlaceBase::Local(l) => l; return None);
+        match *place.projection {
+            [] => {}
+            [ProjectionElem::Field(f, _)] if f.index() == 0 => {}
+            _ => return None,
+        }
+        if self.escaped.contains(l) { None } else { Some(l) }
+    }
+
+    fn eval_operand(&self, state: &RangeState, op: &Operand<'tcx>) -> Interval {
+        let ty = op.ty(self.body, self.tcx);
+        let bounds = self.bounds(ty);
+        let r = match *op {
+            Operand::Copy(ref place) | Operand::Move(ref place) =>
+                self.tracked_local(place).map(|l| state.ranges[l]),
+            Operand::Constant(ref c) => {
+                let bits = c.literal.try_eval_bits(self.tcx, self.param_env, ty);
+                match (bits, &ty.kind) {
+                    // Sign-extend from the width of the type.
+                    (Some(bits), &TyKind::Int(_)) => int_width(self.tcx, ty).map(|w| {
+                        Interval::single(((bits << (128 - w)) as i128) >> (128 - w))
+                    }),
+                    (Some(bits), _) if bits <= i128::max_value() as u128 =>
+                        Some(Interval::single(bits as i128)),
+                    _ => None,
+                }
+            }
+        };
+        r.and_then(|r| r.meet(bounds)).unwrap_or(bounds)
+    }
+
+    fn eval_binary(&self, op: BinOp, a: Interval, b: Interval) -> Interval {
+        match op {
+            BinOp::Add => Interval::new(a.lo.saturating_add(b.lo), a.hi.saturating_add(b.hi)),
+            BinOp::Sub => Interval::new(a.lo.saturating_sub(b.hi), a.hi.saturating_sub(b.lo)),
+            BinOp::Mul => a.corners(b, |x, y| x.saturating_mul(y)),
+            BinOp::Div if b.lo > 0 => a.corners(b, |x, y| x / y),
+            BinOp::Rem if b.lo > 0 => {
+                let m = b.hi - 1;
+                if a.lo >= 0 {
+                    Interval::new(0, cmp::min(a.hi, m))
+                } else {
+                    Interval::new(-m, m)
+                }
+            }
+            BinOp::BitAnd if a.lo >= 0 && b.lo >= 0 => Interval::new(0, cmp::min(a.hi, b.hi)),
+            BinOp::BitAnd if a.lo >= 0 => Interval::new(0, a.hi),
+            BinOp::BitAnd if b.lo >= 0 => Interval::new(0, b.hi),
+            BinOp::Shr if a.lo >= 0 => Interval::new(0, a.hi),
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge =>
+                Interval::new(0, 1),
+            _ => Interval::FULL,
+        }
+    }
+
+    fn eval_rvalue(&self, state: &RangeState, rv: &Rvalue<'tcx>) -> Interval {
+        let bounds = self.bounds(rv.ty(self.body, self.tcx));
+        let r = match *rv {
+            Rvalue::Use(ref op) | Rvalue::Cast(_, ref op, _) => self.eval_operand(state, op),
+            Rvalue::BinaryOp(op, ref a, ref b) => {
+                let a = self.eval_operand(state, a);
+                let b = self.eval_operand(state, b);
+                self.eval_binary(op, a, b)
+            }
+            Rvalue::CheckedBinaryOp(op, ref a, ref b) => {
+                // The result is only used if the operation didn't overflow.
+                let ty = a.ty(self.body, self.tcx);
+                let a = self.eval_operand(state, a);
+                let b = self.eval_operand(state, b);
+                return self.eval_binary(op, a, b).meet(self.bounds(ty))
+                    .unwrap_or_else(|| self.bounds(ty));
+            }
+            Rvalue::Len(_) => Interval::new(0, i64::max_value() as i128),
+            _ => bounds,
+        };
+        if bounds.contains(r) { r } else { bounds }
+    }
+
+    fn write(&self, state: &mut RangeState, place: &Place<'tcx>, value: Option<Interval>) {
+        if let PlaceBase::Local(l) = place.base {
+            state.ranges[l] = match value {
+                Some(r) if place.projection.is_empty() => r,
+                _ => self.local_bounds(l),
+            };
+        }
+    }
+
+    /// Find the statement in `bb` that last assigns `local` as a whole, if nothing it reads is
+    /// assigned again later in the block.
+    fn last_def(&self, bb: BasicBlock, local: Local) -> Option<&'a Rvalue<'tcx>> {
+        let stmts = &self.body.basic_blocks()[bb].statements;
+        let mut written = BitSet::new_empty(self.body.local_decls.len());
+        for stmt in stmts.iter().rev() {
+            let (place, rv) = match_or!([stmt.kind] StatementKind::Assign(box(ref p, ref rv)) =>
+                                        (p, rv); continue);
+            let base = match_or!([place.base] PlaceBase::Local(l) => l; continue);
+            if base == local && place.projection.is_empty() {
+                let reads_written = |op: &Operand| match *op {
+                    Operand::Copy(ref p) | Operand::Move(ref p) => match p.base {
+                        PlaceBase::Local(l) => written.contains(l),
+                        _ => false,
+                    },
+                    Operand::Constant(..) => false,
+                };
+                let ok = match *rv {
+                    Rvalue::Use(ref a) => !reads_written(a),
+                    Rvalue::BinaryOp(_, ref a, ref b) => !reads_written(a) && !reads_written(b),
+                    _ => false,
+                };
+                return if ok { Some(rv) } else { None };
+            }
+            written.insert(base);
+        }
+        None
+    }
+
+    /// Narrow the range of the local read by `op`, and of the local it was copied from in `bb`,
+    /// to `r`.  Returns `false` if the range becomes empty.
+    fn narrow(&self, state: &mut RangeState, bb: BasicBlock, op: &Operand<'tcx>,
+              r: Interval) -> bool {
+        let mut place = match *op {
+            Operand::Copy(ref p) | Operand::Move(ref p) => p,
+            Operand::Constant(..) => return true,
+        };
+        loop {
+            let l = match self.tracked_local(place) {
+                Some(l) if place.projection.is_empty() => l,
+                _ => return true,
+            };
+            state.ranges[l] = match_or!([state.ranges[l].meet(r)] Some(x) => x; return false);
+            place = match self.last_def(bb, l) {
+                Some(&Rvalue::Use(Operand::Copy(ref p))) |
+                Some(&Rvalue::Use(Operand::Move(ref p))) => p,
+                _ => return true,
+            };
+        }
+    }
+
+    /// Narrow the ranges of `a` and `b` knowing that `a OP b` is true.  Returns `false` if that
+    /// is impossible.
+    fn assume(&self, state: &mut RangeState, bb: BasicBlock, op: BinOp, a: &Operand<'tcx>,
+              b: &Operand<'tcx>) -> bool {
+        let ra = self.eval_operand(state, a);
+        let rb = self.eval_operand(state, b);
+        let (na, nb) = match op {
+            BinOp::Lt => (Interval::new(ra.lo, rb.hi.saturating_sub(1)),
+                          Interval::new(ra.lo.saturating_add(1), rb.hi)),
+            BinOp::Le => (Interval::new(ra.lo, rb.hi), Interval::new(ra.lo, rb.hi)),
+            BinOp::Gt => return self.assume(state, bb, BinOp::Lt, b, a),
+            BinOp::Ge => return self.assume(state, bb, BinOp::Le, b, a),
+            BinOp::Eq => (rb, ra),
+            BinOp::Ne => {
+                let skip = |r: Interval, n: Interval| {
+                    if n.lo != n.hi {
+                        r
+                    } else if r.lo == n.lo {
+                        Interval::new(r.lo.saturating_add(1), r.hi)
+                    } else if r.hi == n.lo {
+                        Interval::new(r.lo, r.hi.saturating_sub(1))
+                    } else {
+                        r
+                    }
+                };
+                (skip(ra, rb), skip(rb, ra))
+            }
+            _ => return true,
+        };
+        self.narrow(state, bb, a, na) && self.narrow(state, bb, b, nb)
+    }
+}
+
+fn negate(op: BinOp) -> Option<BinOp> {
+    Some(match op {
+        BinOp::Lt => BinOp::Ge,
+        BinOp::Le => BinOp::Gt,
+        BinOp::Gt => BinOp::Le,
+        BinOp::Ge => BinOp::Lt,
+        BinOp::Eq => BinOp::Ne,
+        BinOp::Ne => BinOp::Eq,
+        _ => return None,
+    })
+}
+
+impl<'a, 'tcx> Analysis<'tcx> for RangeAnalysis<'a, 'tcx> {
+    type Domain = Option<RangeState>;
+
+    fn entry_state(&self, body: &Body<'tcx>) -> Option<RangeState> {
+        Some(RangeState {
+            ranges: body.local_decls.indices().map(|l| self.local_bounds(l)).collect(),
+            changes: 0,
+        })
+    }
+
+    fn bottom(&self, _body: &Body<'tcx>) -> Option<RangeState> {
+        None
+    }
+
+    fn join(&self, state: &mut Option<RangeState>, other: &Option<RangeState>) -> bool {
+        let other = match_or!([*other] Some(ref x) => x; return false);
+        let state = match *state {
+            Some(ref mut x) => x,
+            None => {
+                *state = Some(RangeState { ranges: other.ranges.clone(), changes: 0 });
+                return true;
+            }
+        };
+        let widen = state.changes >= WIDEN_DELAY;
+        let mut changed = false;
+        for l in state.ranges.indices() {
+            let old = state.ranges[l];
+            let mut new = old.hull(other.ranges[l]);
+            if widen && new != old {
+                let bounds = self.local_bounds(l);
+                if new.lo < old.lo {
+                    new.lo = bounds.lo;
+                }
+                if new.hi > old.hi {
+                    new.hi = bounds.hi;
+                }
+            }
+            if new != old {
+                state.ranges[l] = new;
+                changed = true;
+            }
+        }
+        if changed {
+            state.changes += 1;
+        }
+        changed
+    }
+
+    fn apply_statement(&self, state: &mut Option<RangeState>, stmt: &Statement<'tcx>,
+                       _loc: Location) {
+        let state = match_or!([*state] Some(ref mut x) => x; return);
+        if let StatementKind::Assign(box(ref place, ref rv)) = stmt.kind {
+            let value = self.eval_rvalue(state, rv);
+            self.write(state, place, Some(value));
+        }
+    }
+
+    fn apply_terminator(&self, state: &mut Option<RangeState>, term: &Terminator<'tcx>,
+                        _loc: Location) {
+        let state = match_or!([*state] Some(ref mut x) => x; return);
+        match term.kind {
+            TerminatorKind::Call { destination: Some((ref place, _)), .. } |
+            TerminatorKind::DropAndReplace { location: ref place, .. } => {
+                self.write(state, place, None);
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_edge(&self, state: &mut Option<RangeState>, body: &Body<'tcx>, from: BasicBlock,
+                  to: BasicBlock) {
+        let (discr, values, targets) = match body.basic_blocks()[from].terminator().kind {
+            TerminatorKind::SwitchInt { ref discr, ref values, ref targets, .. } =>
+                (discr, values, targets),
+            _ => return,
+        };
+        if targets.iter().filter(|&&t| t == to).count() != 1 {
+            return;
+        }
+        let idx = targets.iter().position(|&t| t == to).unwrap();
+        let is_true = match values.get(idx) {
+            Some(&v) => v != 0,
+            None if values[..] == [0] => true,
+            None => return,
+        };
+
+        let place = match *discr {
+            Operand::Copy(ref p) | Operand::Move(ref p) => p,
+            Operand::Constant(..) => return,
+        };
+        let cond = match (&place.base, place.projection.is_empty()) {
+            (&PlaceBase::Local(l), true) => l,
+            _ => return,
+        };
+        let (op, a, b) = match self.last_def(from, cond) {
+            Some(&Rvalue::BinaryOp(op, ref a, ref b)) => (op, a, b),
+            _ => return,
+        };
+        let op = if is_true { op } else { match_or!([negate(op)] Some(x) => x; return) };
+
+        let ok = match *state {
+            Some(ref mut s) => self.assume(s, from, op, a, b),
+            None => return,
+        };
+        if !ok {
+            *state = None;
+        }
+    }
+}
+
+fn escaped_locals(body: &Body) -> BitSet<Local> {
+    let mut escaped = BitSet::new_empty(body.local_decls.len());
+    for data in body.basic_blocks() {
+        for stmt in &data.statements {
+            if let StatementKind::Assign(box(_, Rvalue::Ref(_, _, ref place))) = stmt.kind {
+                if let PlaceBase::Local(l) = place.base {
+                    escaped.insert(l);
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Run the value-range analysis on the function `def_id`.  Returns `None` if no MIR is available
+/// for it.
+fn analyze_fn<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, def_id: DefId)
+                    -> Option<Results<'tcx, 'tcx, RangeAnalysis<'tcx, 'tcx>>> {
+    let tcx = cx.ty_ctxt();
+    if !def_id.is_local() || !tcx.is_mir_available(def_id) {
+        return None;
+    }
+    let body = tcx.optimized_mir(def_id);
+    dataflow::analyze_fn(cx, def_id, RangeAnalysis {
+        tcx,
+        param_env: tcx.param_env(def_id),
+        body,
+        escaped: escaped_locals(body),
+    })
+}
+
+/// Value ranges of the integer expressions in a crate, computed on demand for each function.
+pub struct ValueRanges<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    fns: HashMap<DefId, Option<Results<'tcx, 'tcx, RangeAnalysis<'tcx, 'tcx>>>>,
+}
+
+impl<'a, 'tcx> ValueRanges<'a, 'tcx> {
+    pub fn new(cx: &'a RefactorCtxt<'a, 'tcx>) -> ValueRanges<'a, 'tcx> {
+        ValueRanges { cx, fns: HashMap::new() }
+    }
+
+    /// Get a range containing every value that the integer expression `e` can have when control
+    /// enters the code of `at`, the span of an expression that contains `e` and evaluates it
+    /// before anything else can change its value.  Literals, local variables, and casts between
+    /// integer types are evaluated exactly; the range of anything else is the range of its type.
+    /// Returns `None` if `e` is not an integer, or if the code of `at` is unreachable.
+    pub fn expr_range(&mut self, e: &Expr, at: Span) -> Option<Interval> {
+        let tcx = self.cx.ty_ctxt();
+        let bounds = int_bounds(tcx, self.cx.opt_node_type(e.id)?)?;
+        let r = match e.kind {
+            ExprKind::Paren(ref inner) => return self.expr_range(inner, at),
+            ExprKind::Lit(ref l) => match l.kind {
+                LitKind::Int(n, _) if n <= i128::max_value() as u128 =>
+                    Some(Interval::single(n as i128)),
+                _ => None,
+            },
+            ExprKind::Unary(UnOp::Neg, ref inner) => self.expr_range(inner, at)
+                .map(|r| Interval::new(r.hi.saturating_neg(), r.lo.saturating_neg())),
+            ExprKind::Cast(ref inner, _) => self.expr_range(inner, at),
+            ExprKind::Path(..) => return self.local_range(e, at).and_then(|r| r.meet(bounds)),
+            _ => None,
+        };
+        Some(r.filter(|&r| bounds.contains(r)).unwrap_or(bounds))
+    }
+
+    /// Check whether the integer expression `e` is never negative when control enters the code
+    /// of `at`.  See `expr_range`.
+    pub fn is_non_negative(&mut self, e: &Expr, at: Span) -> bool {
+        self.expr_range(e, at).map_or(false, |r| r.is_non_negative())
+    }
+
+    fn local_range(&mut self, e: &Expr, at: Span) -> Option<Interval> {
+        let cx = self.cx;
+        let hir_map = cx.hir_map();
+        let hid = cx.try_resolve_expr_to_hid(e)?;
+        let pat_span = hir_map.span(hid);
+        let def_id = hir_map.opt_local_def_id(hir_map.get_parent_item(hid))?;
+        let results = self.fns.entry(def_id).or_insert_with(|| analyze_fn(cx, def_id))
+            .as_ref()?;
+        let local = dataflow::local_for_pat(results.body(), pat_span)?;
+        let state = results.state_before_span(at)??;
+        Some(state.range(local))
+    }
+}
+
+/// Run the value-range analysis on every function in the crate, and log the range of each user
+/// variable of integer type on entry to each basic block (at level `info`).
+pub fn dump_ranges(cx: &RefactorCtxt) {
+    let tcx = cx.ty_ctxt();
+    let source_map = cx.session().source_map();
+    for def_id in tcx.body_owners() {
+        let results = match_or!([analyze_fn(cx, def_id)] Some(x) => x; continue);
+        let body = results.body();
+        info!("ranges for {:?}:", def_id);
+        for bb in body.basic_blocks().indices() {
+            let state = match_or!([*results.entry_set(bb)] Some(ref x) => x; continue);
+            for local in body.local_decls.indices() {
+                let pat_span = match_or!([dataflow::local_pat_span(body, local)] Some(x) => x;
+                                         continue);
+                if int_bounds(tcx, body.local_decls[local].ty).is_none() {
+                    continue;
+                }
+                let name = source_map.span_to_snippet(pat_span).unwrap_or_default();
+                let r = state.range(local);
+                info!("  {:?}: {} in {}..={}", bb, name, r.lo, r.hi);
+            }
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! String provenance analysis.  The goal is to find which byte buffers are known to hold valid
+//! UTF-8, so that transforms can choose between `&str` and a byte type for each one.
+//!
+//! The analysis tracks values of C string and buffer types (`*const c_char`, `*mut c_char`,
+//! `[c_char; N]`, and `Vec<u8>`) from their sources:
+//!
+//!  * String literals, and bytestring literals that are valid UTF-8, are `Utf8`.  Bytestring
+//!    literals that aren't valid UTF-8 are `Binary`.
+//!  * Buffers filled by `read`, `recv`, `recvfrom`, `pread`, or `fread` are `Binary`.
+//!  * Buffers filled by `fgets`, `gets`, `getline`, or `getdelim` hold text of unknown encoding,
+//!    as do the results of foreign functions like `getenv` and any other value the analysis
+//!    doesn't understand.  These are `Unknown`.
+//!
+//! Provenance flows through assignments, initializers, struct literals, calls to and returns
+//! from functions in the crate, pointer arithmetic, and the C string functions that copy from one
+//! buffer to another (`strcpy`, `memcpy`, `sprintf`, and so on).  Fresh allocations and null
+//! pointers contribute nothing, since their contents must be written before they are read.
+//!
+//! The analysis is flow- and context-insensitive: each local, argument, struct field, static, and
+//! function return value gets the combined provenance of every value stored into it anywhere in
+//! the crate.  Writes through pointers other than by the known C string functions are not
+//! tracked.
+
+use std::collections::HashMap;
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, TyKind};
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+use syntax::source_map::Span;
+use syntax::visit::{self, FnKind, Visitor};
+
+use crate::ast_manip::fn_edit::visit_fns;
+use crate::ast_manip::{visit_nodes, Visit};
+use crate::command::CommandState;
+use crate::transform::heap::{as_int_lit, callee_name, field_def_id, is_alloc_call};
+use crate::transform::heap::{is_null_ptr, strip_casts};
+use crate::RefactorCtxt;
+use c2rust_ast_builder::IntoSymbol;
+
+/// Where the contents of a buffer come from.  Combining two provenances gives the greater one.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Provenance {
+    /// Every value is known to be valid UTF-8.
+    Utf8,
+    /// Some value comes from a source that makes no promise about its encoding.
+    Unknown,
+    /// Some value is known to be binary data.
+    Binary,
+}
+
+pub const PROVENANCES: &[Provenance] = &[
+    Provenance::Utf8,
+    Provenance::Unknown,
+    Provenance::Binary,
+];
+
+impl Provenance {
+    /// The mark that `analyze_string_provenance` applies to buffers with this provenance.
+    pub fn label(self) -> Symbol {
+        match self {
+            Provenance::Utf8 => "utf8",
+            Provenance::Unknown => "unknown",
+            Provenance::Binary => "binary",
+        }.into_symbol()
+    }
+}
+
+/// A location that holds a buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Loc {
+    /// A local, function argument, struct field, or static, identified by its binding or
+    /// definition.
+    Var(HirId),
+    /// The return value of a function.
+    Ret(DefId),
+}
+
+/// A value flowing into a location.
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Loc(Loc),
+    Known(Provenance),
+}
+
+fn is_byte_ty(ty: ty::Ty) -> bool {
+    matches!([ty.kind] TyKind::Int(IntTy::I8), TyKind::Uint(UintTy::U8))
+}
+
+/// Check if `ty` is a C string or buffer type tracked by this analysis.
+fn is_buffer_ty(cx: &RefactorCtxt, ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::RawPtr(mt) => is_byte_ty(mt.ty),
+        TyKind::Array(elem, _) => is_byte_ty(elem),
+        TyKind::Adt(adt, substs) => {
+            let path = cx.ty_ctxt().def_path_str(adt.did);
+            (path == "std::vec::Vec" || path == "alloc::vec::Vec") &&
+                substs.types().next().map_or(false, is_byte_ty)
+        }
+        _ => false,
+    }
+}
+
+fn bytes_provenance(bytes: &[u8]) -> Provenance {
+    if std::str::from_utf8(bytes).is_ok() {
+        Provenance::Utf8
+    } else {
+        Provenance::Binary
+    }
+}
+
+struct FlowVisitor<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The argument bindings of each function in the crate.
+    params: HashMap<DefId, Vec<Option<HirId>>>,
+    /// The functions enclosing the current node, innermost last.  Closures push `None`.
+    fns: Vec<Option<DefId>>,
+    /// The values flowing into each location.
+    inflow: HashMap<Loc, Vec<Value>>,
+}
+
+impl<'a, 'tcx> FlowVisitor<'a, 'tcx> {
+    /// Get the location named by the path or field expression `e`.
+    fn loc_of(&self, e: &Expr) -> Option<Loc> {
+        let hir_map = self.cx.hir_map();
+        match strip_casts(e).kind {
+            ExprKind::Path(..) => Some(Loc::Var(self.cx.try_resolve_expr_to_hid(e)?)),
+            ExprKind::Field(ref obj, name) => {
+                let did = field_def_id(self.cx, obj.id, name)?;
+                Some(Loc::Var(hir_map.as_local_hir_id(did)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the buffer written through the pointer `e`, such as `buf` in `buf.as_mut_ptr()` or
+    /// `&mut buf[0]`.
+    fn dest_loc(&self, e: &Expr) -> Option<Loc> {
+        match strip_casts(e).kind {
+            ExprKind::AddrOf(_, _, ref inner) | ExprKind::Index(ref inner, _) => {
+                self.dest_loc(inner)
+            }
+            ExprKind::MethodCall(ref seg, ref args) if !args.is_empty() && matches!(
+                [&*seg.ident.as_str()] "as_ptr", "as_mut_ptr", "offset", "add", "wrapping_offset",
+                "wrapping_add") => self.dest_loc(&args[0]),
+            _ => self.loc_of(e),
+        }
+    }
+
+    /// Check if `e` is a `str` or `String`, or a reference to one.
+    fn is_str(&self, e: &Expr) -> bool {
+        let mut ty = match_or!([self.cx.opt_adjusted_node_type(e.id)] Some(x) => x; return false);
+        while let TyKind::Ref(_, inner, _) = ty.kind {
+            ty = inner;
+        }
+        match ty.kind {
+            TyKind::Str => true,
+            TyKind::Adt(adt, _) => {
+                let path = self.cx.ty_ctxt().def_path_str(adt.did);
+                path == "std::string::String" || path == "alloc::string::String"
+            }
+            _ => false,
+        }
+    }
+
+    fn tail_values(&self, b: &Block, out: &mut Vec<Value>) {
+        if let Some(&Stmt { kind: StmtKind::Expr(ref e), .. }) = b.stmts.last() {
+            self.values_of(e, out);
+        }
+    }
+
+    /// Collect the values that the expression `e` may evaluate to.
+    fn values_of(&self, e: &Expr, out: &mut Vec<Value>) {
+        let e = strip_casts(e);
+        if is_null_ptr(e) || is_alloc_call(e) {
+            return;
+        }
+        match e.kind {
+            ExprKind::Lit(ref l) => out.push(Value::Known(match l.kind {
+                LitKind::Str(..) => Provenance::Utf8,
+                LitKind::ByteStr(ref bs) => bytes_provenance(bs),
+                _ => Provenance::Unknown,
+            })),
+
+            ExprKind::Path(..) | ExprKind::Field(..) => match self.loc_of(e) {
+                Some(loc) => out.push(Value::Loc(loc)),
+                None => out.push(Value::Known(Provenance::Unknown)),
+            },
+
+            ExprKind::AddrOf(_, _, ref inner) => match inner.kind {
+                ExprKind::Index(ref base, _) => self.values_of(base, out),
+                _ => self.values_of(inner, out),
+            },
+
+            ExprKind::MethodCall(ref seg, ref args) => match &*seg.ident.as_str() {
+                "as_ptr" | "as_mut_ptr" | "offset" | "add" | "sub" | "wrapping_offset" |
+                "wrapping_add" | "wrapping_sub" | "clone" | "to_vec" | "to_owned" => {
+                    self.values_of(&args[0], out)
+                }
+                "as_bytes" | "into_bytes" if self.is_str(&args[0]) => {
+                    out.push(Value::Known(Provenance::Utf8))
+                }
+                _ => out.push(Value::Known(Provenance::Unknown)),
+            },
+
+            ExprKind::Call(_, ref args) => {
+                if let Some(did) = self.cx.opt_callee(e) {
+                    if self.params.contains_key(&did) {
+                        out.push(Value::Loc(Loc::Ret(did)));
+                        return;
+                    }
+                }
+                let name = match_or!([callee_name(e)] Some(x) => x;
+                                     return out.push(Value::Known(Provenance::Unknown)));
+                match &*name.as_str() {
+                    // These return a pointer into their first argument.
+                    "strcpy" | "strncpy" | "strcat" | "strncat" | "memcpy" | "memmove" |
+                    "memset" | "fgets" | "gets" | "strdup" | "strndup" | "strchr" |
+                    "strrchr" | "strstr" | "strpbrk" if !args.is_empty() => {
+                        self.values_of(&args[0], out)
+                    }
+                    _ => out.push(Value::Known(Provenance::Unknown)),
+                }
+            }
+
+            ExprKind::If(_, ref then, ref els) => {
+                self.tail_values(then, out);
+                if let Some(ref els) = *els {
+                    self.values_of(els, out);
+                }
+            }
+            ExprKind::Block(ref b, _) => self.tail_values(b, out),
+
+            _ => out.push(Value::Known(Provenance::Unknown)),
+        }
+    }
+
+    fn flow(&mut self, loc: Option<Loc>, e: &Expr) {
+        let loc = match_or!([loc] Some(x) => x; return);
+        let mut values = Vec::new();
+        self.values_of(e, &mut values);
+        self.inflow.entry(loc).or_insert_with(Vec::new).extend(values);
+    }
+
+    fn flow_known(&mut self, loc: Option<Loc>, p: Provenance) {
+        let loc = match_or!([loc] Some(x) => x; return);
+        self.inflow.entry(loc).or_insert_with(Vec::new).push(Value::Known(p));
+    }
+
+    /// Record the flows caused by a call to a C library function.
+    fn libc_call(&mut self, name: &str, args: &[P<Expr>]) {
+        let arg_count = args.len();
+        match name {
+            "strcpy" | "strncpy" | "strcat" | "strncat" | "memcpy" | "memmove" | "strlcpy" |
+            "strlcat" if arg_count >= 2 => {
+                let dest = self.dest_loc(&args[0]);
+                self.flow(dest, &args[1]);
+            }
+            "read" | "pread" | "recv" | "recvfrom" if arg_count >= 2 => {
+                let dest = self.dest_loc(&args[1]);
+                self.flow_known(dest, Provenance::Binary);
+            }
+            "fread" if arg_count >= 1 => {
+                let dest = self.dest_loc(&args[0]);
+                self.flow_known(dest, Provenance::Binary);
+            }
+            "fgets" | "gets" | "getline" | "getdelim" if arg_count >= 1 => {
+                let dest = self.dest_loc(&args[0]);
+                self.flow_known(dest, Provenance::Unknown);
+            }
+            "memset" if arg_count >= 2 => {
+                let dest = self.dest_loc(&args[0]);
+                let p = match as_int_lit(&args[1]) {
+                    Some(b) if b < 0x80 => Provenance::Utf8,
+                    _ => Provenance::Unknown,
+                };
+                self.flow_known(dest, p);
+            }
+            "sprintf" | "snprintf" => {
+                // The output is the format string with the `%s` arguments substituted in.  Other
+                // conversions only produce ASCII.
+                let fmt_idx = if name == "snprintf" { 2 } else { 1 };
+                if arg_count <= fmt_idx {
+                    return;
+                }
+                let dest = self.dest_loc(&args[0]);
+                self.flow(dest, &args[fmt_idx]);
+                for a in &args[fmt_idx + 1..] {
+                    let is_str_arg = self.cx.opt_node_type(a.id).map_or(false, |ty| match ty.kind {
+                        TyKind::RawPtr(mt) => is_byte_ty(mt.ty),
+                        _ => false,
+                    });
+                    if is_str_arg {
+                        self.flow(dest, a);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for FlowVisitor<'a, 'tcx> {
+    fn visit_item(&mut self, i: &'ast Item) {
+        if let ItemKind::Static(_, _, ref init) = i.kind {
+            let loc = self.cx.hir_map().opt_node_to_hir_id(i.id).map(Loc::Var);
+            self.flow(loc, init);
+        }
+        visit::walk_item(self, i);
+    }
+
+    fn visit_fn(&mut self, kind: FnKind<'ast>, fd: &'ast FnDecl, span: Span, id: NodeId) {
+        let did = match kind {
+            FnKind::ItemFn(_, _, _, body) | FnKind::Method(_, _, _, body) => {
+                let did = self.cx.node_def_id(id);
+                let mut values = Vec::new();
+                self.tail_values(body, &mut values);
+                self.inflow.entry(Loc::Ret(did)).or_insert_with(Vec::new).extend(values);
+                Some(did)
+            }
+            FnKind::Closure(_) => None,
+        };
+        self.fns.push(did);
+        visit::walk_fn(self, kind, fd, span);
+        self.fns.pop();
+    }
+
+    fn visit_local(&mut self, l: &'ast Local) {
+        if let Some(ref init) = l.init {
+            if let PatKind::Ident(..) = l.pat.kind {
+                let loc = self.cx.hir_map().opt_node_to_hir_id(l.pat.id).map(Loc::Var);
+                self.flow(loc, init);
+            }
+        }
+        visit::walk_local(self, l);
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) => {
+                let loc = self.loc_of(lhs);
+                self.flow(loc, rhs);
+            }
+
+            ExprKind::Call(_, ref args) => {
+                let params = self.cx.opt_callee(e).and_then(|did| self.params.get(&did)).cloned();
+                if let Some(params) = params {
+                    for (param, arg) in params.into_iter().zip(args.iter()) {
+                        self.flow(param.map(Loc::Var), arg);
+                    }
+                } else if let Some(name) = callee_name(e) {
+                    self.libc_call(&name.as_str(), args);
+                }
+            }
+
+            ExprKind::MethodCall(ref seg, ref args) => match &*seg.ident.as_str() {
+                "extend_from_slice" | "extend" | "push_str" if args.len() == 2 => {
+                    let dest = self.loc_of(&args[0]);
+                    self.flow(dest, &args[1]);
+                }
+                "push" if args.len() == 2 => {
+                    let dest = self.loc_of(&args[0]);
+                    let p = match as_int_lit(&args[1]) {
+                        Some(b) if b < 0x80 => Provenance::Utf8,
+                        _ => Provenance::Unknown,
+                    };
+                    self.flow_known(dest, p);
+                }
+                _ => {}
+            },
+
+            ExprKind::Struct(_, ref fields, _) => {
+                let adt = match self.cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::Adt(adt, _)) if !adt.is_enum() => Some(adt),
+                    _ => None,
+                };
+                if let Some(adt) = adt {
+                    for f in fields {
+                        let loc = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .and_then(|fd| self.cx.hir_map().as_local_hir_id(fd.did))
+                            .map(Loc::Var);
+                        self.flow(loc, &f.expr);
+                    }
+                }
+            }
+
+            ExprKind::Ret(Some(ref v)) => {
+                let loc = self.fns.last().cloned().and_then(|f| f).map(Loc::Ret);
+                self.flow(loc, v);
+            }
+
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Compute the provenance of every location in `krate` that has a value stored into it.
+pub fn analyze(cx: &RefactorCtxt, krate: &Crate) -> HashMap<Loc, Provenance> {
+    let mut params = HashMap::new();
+    visit_fns(krate, |fl| {
+        if fl.block.is_none() {
+            return;
+        }
+        let bindings = fl.decl.inputs.iter()
+            .map(|arg| match arg.pat.kind {
+                PatKind::Ident(..) => cx.hir_map().opt_node_to_hir_id(arg.pat.id),
+                _ => None,
+            })
+            .collect();
+        params.insert(cx.node_def_id(fl.id), bindings);
+    });
+
+    let mut v = FlowVisitor {
+        cx,
+        params,
+        fns: Vec::new(),
+        inflow: HashMap::new(),
+    };
+    krate.visit(&mut v);
+
+    // Propagate provenance along the flow edges until nothing changes.  Provenance only ever
+    // increases, so this terminates after at most a few passes per location.
+    let mut result: HashMap<Loc, Provenance> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (&loc, values) in &v.inflow {
+            let new = values.iter()
+                .filter_map(|&value| match value {
+                    Value::Known(p) => Some(p),
+                    Value::Loc(src) => result.get(&src).cloned(),
+                })
+                .max();
+            let new = match_or!([new] Some(x) => x; continue);
+            if result.get(&loc).map_or(true, |&old| old < new) {
+                result.insert(loc, new);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    result
+}
+
+/// Mark each buffer-typed local, argument, field, and static in `krate` with its provenance.
+/// Locals and arguments are marked on their binding pattern.  Buffers that nothing is stored into
+/// are marked `unknown`.
+pub fn mark_provenance(st: &CommandState, cx: &RefactorCtxt, krate: &Crate) {
+    let result = analyze(cx, krate);
+    let provenance_of = |id: NodeId| {
+        cx.hir_map().opt_node_to_hir_id(id)
+            .and_then(|hid| result.get(&Loc::Var(hid)).cloned())
+            .unwrap_or(Provenance::Unknown)
+    };
+
+    visit_nodes(krate, |p: &Pat| {
+        if let PatKind::Ident(..) = p.kind {
+            if cx.opt_node_type(p.id).map_or(false, |ty| is_buffer_ty(cx, ty)) {
+                st.add_mark(p.id, provenance_of(p.id).label());
+            }
+        }
+    });
+    visit_nodes(krate, |sf: &StructField| {
+        if is_buffer_ty(cx, cx.ty_ctxt().type_of(cx.node_def_id(sf.id))) {
+            st.add_mark(sf.id, provenance_of(sf.id).label());
+        }
+    });
+    visit_nodes(krate, |i: &Item| {
+        if let ItemKind::Static(..) = i.kind {
+            if is_buffer_ty(cx, cx.ty_ctxt().type_of(cx.node_def_id(i.id))) {
+                st.add_mark(i.id, provenance_of(i.id).label());
+            }
+        }
+    });
+}
+
+/// Get the provenance mark on the node `id`, as applied by `analyze_string_provenance`.
+pub fn node_provenance(st: &CommandState, id: NodeId) -> Option<Provenance> {
+    PROVENANCES.iter().cloned().find(|p| st.marked(id, p.label()))
+}
+
+/// Get the provenance mark on the local, argument, field, or static that the expression `e`
+/// refers to, ignoring casts.
+pub fn expr_provenance(st: &CommandState, cx: &RefactorCtxt, e: &Expr) -> Option<Provenance> {
+    let e = strip_casts(e);
+    let hid = match e.kind {
+        ExprKind::Path(..) => cx.try_resolve_expr_to_hid(e)?,
+        ExprKind::Field(ref obj, name) => {
+            cx.hir_map().as_local_hir_id(field_def_id(cx, obj.id, name)?)?
+        }
+        _ => return None,
+    };
+    node_provenance(st, cx.hir_map().hir_to_node_id(hid))
+}
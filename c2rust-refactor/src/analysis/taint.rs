@@ -0,0 +1,626 @@
+//! Taint analysis.  The goal is to find the places where data from outside the program, which an
+//! attacker may control, is used in a way that can corrupt memory if it isn't validated first.
+//!
+//! Tainted data comes from these sources:
+//!
+//!  * Buffers filled by `read`, `pread`, `recv`, `recvfrom`, `fread`, `fgets`, `gets`,
+//!    `getline`, or `getdelim`, and the variables written by `scanf` and `fscanf`.
+//!  * The results of `getenv`, `getchar`, `fgetc`, and `getc`, and the byte counts returned by
+//!    the reading functions above.
+//!  * The arguments of the C `main` function (`main_0` in transpiled code): `argc`, `argv`, and
+//!    `envp`.
+//!
+//! Taint flows through assignments, initializers, struct literals, arithmetic, casts, pointer
+//! arithmetic, indexing, dereferences, calls to and returns from functions in the crate, and the
+//! C library functions that copy or convert their input (`memcpy`, `strcpy`, `sprintf`, `atoi`,
+//! `strtol`, `strlen`, and so on).  Other foreign functions return untainted values.  Like the
+//! string provenance analysis, this one is flow- and context-insensitive, and it doesn't
+//! distinguish a pointer from the data it points to.  Only explicit data flow is tracked: a value
+//! computed in a branch that depends on tainted data is not itself tainted, and neither is a
+//! value checked against a bound before use.
+//!
+//! The analysis reports tainted values that reach these sinks:
+//!
+//!  * The offset of pointer arithmetic (`offset`, `add`, `sub`, and their `wrapping_` forms).
+//!  * The length of `memcpy`, `memmove`, `memset`, `strncpy`, `strncat`, and the `ptr::copy`
+//!    family.
+//!  * The format string of `printf` and related functions.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use json::{self, object, JsonValue};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::source_map::{SourceMap, Span};
+use syntax::symbol::Symbol;
+use syntax::visit::{self, FnKind, Visitor};
+
+use crate::ast_manip::fn_edit::visit_fns;
+use crate::ast_manip::{visit_nodes, Visit};
+use crate::command::CommandState;
+use crate::transform::heap::{callee_name, field_def_id, strip_casts};
+use crate::RefactorCtxt;
+use c2rust_ast_builder::IntoSymbol;
+
+/// A location that holds a value.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Loc {
+    /// A local, function argument, struct field, or static, identified by its binding or
+    /// definition.
+    Var(HirId),
+    /// The return value of a function.
+    Ret(DefId),
+}
+
+/// A value flowing into a location or sink.
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Loc(Loc),
+    /// Data from the source with this index.
+    Source(usize),
+}
+
+/// A place where tainted data enters the program.
+#[derive(Clone, Debug)]
+pub struct Source {
+    /// The function that produces the data, or `argc`, `argv`, or `envp`.
+    pub name: Symbol,
+    /// The call that reads the data, or the binding of the argument.
+    pub id: NodeId,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SinkKind {
+    PointerArith,
+    CopyLength,
+    FormatString,
+}
+
+impl SinkKind {
+    fn key(self) -> &'static str {
+        match self {
+            SinkKind::PointerArith => "pointer_arith",
+            SinkKind::CopyLength => "copy_length",
+            SinkKind::FormatString => "format_string",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            SinkKind::PointerArith => "pointer offset",
+            SinkKind::CopyLength => "copy length",
+            SinkKind::FormatString => "format string",
+        }
+    }
+}
+
+/// A tainted value used in a dangerous way.
+#[derive(Clone, Debug)]
+pub struct Sink {
+    pub kind: SinkKind,
+    /// The tainted expression: the offset, length, or format string.
+    pub expr: NodeId,
+    pub span: Span,
+    /// The function containing the sink.
+    pub func: Option<DefId>,
+    /// The indices of the sources whose data may reach the sink.
+    pub sources: BTreeSet<usize>,
+}
+
+/// The results of the taint analysis.
+pub struct Taint {
+    pub sources: Vec<Source>,
+    /// The sources whose data may reach each location.  Untainted locations are omitted.
+    pub tainted: HashMap<Loc, BTreeSet<usize>>,
+    pub sinks: Vec<Sink>,
+}
+
+/// Check if a call to the function `name` returns tainted data.  The reading functions return
+/// the number of bytes read, which is as much under the attacker's control as the bytes are.
+fn returns_input(name: &str) -> bool {
+    matches!([name] "getenv", "getchar", "fgetc", "getc", "read", "pread", "recv", "recvfrom",
+             "fread", "getline", "getdelim")
+}
+
+/// Get the indices of the arguments that a call to the function `name` fills with input.
+fn input_args(name: &str, arg_count: usize) -> Vec<usize> {
+    match name {
+        "read" | "pread" | "recv" | "recvfrom" if arg_count >= 2 => vec![1],
+        "fread" | "fgets" | "gets" | "getline" | "getdelim" if arg_count >= 1 => vec![0],
+        "scanf" => (1..arg_count).collect(),
+        "fscanf" => (2..arg_count).collect(),
+        _ => vec![],
+    }
+}
+
+/// Get the index of the format string argument of a call to the function `name`.
+fn format_arg(name: &str) -> Option<usize> {
+    match name {
+        "printf" | "vprintf" => Some(0),
+        "fprintf" | "dprintf" | "sprintf" | "vfprintf" | "vdprintf" | "vsprintf" |
+        "syslog" => Some(1),
+        "snprintf" | "vsnprintf" => Some(2),
+        _ => None,
+    }
+}
+
+/// Get the index of the length argument of a call to the function `name`.
+fn length_arg(name: &str) -> Option<usize> {
+    match name {
+        "memcpy" | "memmove" | "memset" | "strncpy" | "strncat" | "copy_nonoverlapping" |
+        "copy" | "write_bytes" => Some(2),
+        _ => None,
+    }
+}
+
+struct FlowVisitor<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The argument bindings of each function in the crate.
+    params: HashMap<DefId, Vec<Option<HirId>>>,
+    /// The source index of each call that reads input.
+    source_calls: HashMap<NodeId, usize>,
+    /// The functions enclosing the current node, innermost last.  Closures push `None`.
+    fns: Vec<Option<DefId>>,
+    /// The values flowing into each location.
+    inflow: HashMap<Loc, Vec<Value>>,
+    /// The possible sinks, along with the values that reach each one.
+    sinks: Vec<(Sink, Vec<Value>)>,
+}
+
+impl<'a, 'tcx> FlowVisitor<'a, 'tcx> {
+    /// Get the location named by the path or field expression `e`.
+    fn loc_of(&self, e: &Expr) -> Option<Loc> {
+        let hir_map = self.cx.hir_map();
+        let e = strip_casts(e);
+        match e.kind {
+            ExprKind::Path(..) => Some(Loc::Var(self.cx.try_resolve_expr_to_hid(e)?)),
+            ExprKind::Field(ref obj, name) => {
+                let did = field_def_id(self.cx, obj.id, name)?;
+                Some(Loc::Var(hir_map.as_local_hir_id(did)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the location written through the pointer `e`, such as `buf` in `buf.as_mut_ptr()` or
+    /// `&mut buf[0]`.
+    fn dest_loc(&self, e: &Expr) -> Option<Loc> {
+        match strip_casts(e).kind {
+            ExprKind::AddrOf(_, _, ref inner) | ExprKind::Index(ref inner, _) |
+            ExprKind::Unary(UnOp::Deref, ref inner) => self.dest_loc(inner),
+            ExprKind::MethodCall(ref seg, ref args) if !args.is_empty() && matches!(
+                [&*seg.ident.as_str()] "as_ptr", "as_mut_ptr", "offset", "add", "wrapping_offset",
+                "wrapping_add") => self.dest_loc(&args[0]),
+            _ => self.loc_of(e),
+        }
+    }
+
+    fn tail_values(&self, b: &Block, out: &mut Vec<Value>) {
+        if let Some(&Stmt { kind: StmtKind::Expr(ref e), .. }) = b.stmts.last() {
+            self.values_of(e, out);
+        }
+    }
+
+    /// Collect the values that the expression `e` is computed from.
+    fn values_of(&self, e: &Expr, out: &mut Vec<Value>) {
+        match e.kind {
+            ExprKind::Path(..) => out.extend(self.loc_of(e).map(Value::Loc)),
+            ExprKind::Field(ref obj, _) => match self.loc_of(e) {
+                Some(loc) => out.push(Value::Loc(loc)),
+                None => self.values_of(obj, out),
+            },
+
+            ExprKind::Paren(ref inner) | ExprKind::Cast(ref inner, _) |
+            ExprKind::Type(ref inner, _) | ExprKind::Unary(_, ref inner) |
+            ExprKind::AddrOf(_, _, ref inner) | ExprKind::Index(ref inner, _) => {
+                self.values_of(inner, out)
+            }
+            ExprKind::Binary(_, ref a, ref b) => {
+                self.values_of(a, out);
+                self.values_of(b, out);
+            }
+
+            ExprKind::MethodCall(_, ref args) => {
+                for a in args {
+                    self.values_of(a, out);
+                }
+            }
+
+            ExprKind::Call(_, ref args) => {
+                if let Some(&src) = self.source_calls.get(&e.id) {
+                    out.push(Value::Source(src));
+                    return;
+                }
+                if let Some(did) = self.cx.opt_callee(e) {
+                    if self.params.contains_key(&did) {
+                        out.push(Value::Loc(Loc::Ret(did)));
+                        return;
+                    }
+                }
+                let name = match_or!([callee_name(e)] Some(x) => x; return);
+                match &*name.as_str() {
+                    // These return a value computed from, or a pointer into, their first
+                    // argument.
+                    "atoi" | "atol" | "atoll" | "atof" | "strtol" | "strtoul" | "strtoll" |
+                    "strtoull" | "strtod" | "strtof" | "strlen" | "strnlen" | "ntohs" |
+                    "ntohl" | "htons" | "htonl" | "strcpy" | "strncpy" | "strcat" | "strncat" |
+                    "memcpy" | "memmove" | "strdup" | "strndup" | "strchr" | "strrchr" |
+                    "strstr" | "strpbrk" | "fgets" | "gets" | "transmute" | "Some"
+                        if !args.is_empty() => self.values_of(&args[0], out),
+                    _ => {}
+                }
+            }
+
+            ExprKind::If(_, ref then, ref els) => {
+                self.tail_values(then, out);
+                if let Some(ref els) = *els {
+                    self.values_of(els, out);
+                }
+            }
+            ExprKind::Match(_, ref arms) => {
+                for arm in arms {
+                    self.values_of(&arm.body, out);
+                }
+            }
+            ExprKind::Block(ref b, _) => self.tail_values(b, out),
+
+            _ => {}
+        }
+    }
+
+    fn flow(&mut self, loc: Option<Loc>, e: &Expr) {
+        let loc = match_or!([loc] Some(x) => x; return);
+        let mut values = Vec::new();
+        self.values_of(e, &mut values);
+        self.inflow.entry(loc).or_insert_with(Vec::new).extend(values);
+    }
+
+    fn add_sink(&mut self, kind: SinkKind, e: &Expr) {
+        let mut values = Vec::new();
+        self.values_of(e, &mut values);
+        if values.is_empty() {
+            return;
+        }
+        let sink = Sink {
+            kind,
+            expr: e.id,
+            span: e.span,
+            func: self.fns.last().cloned().and_then(|f| f),
+            sources: BTreeSet::new(),
+        };
+        self.sinks.push((sink, values));
+    }
+
+    /// Record the flows and sinks of a call to a C library function.
+    fn libc_call(&mut self, e: &Expr, name: &str, args: &[P<Expr>]) {
+        let arg_count = args.len();
+        if let Some(&src) = self.source_calls.get(&e.id) {
+            for i in input_args(name, arg_count) {
+                if let Some(dest) = self.dest_loc(&args[i]) {
+                    self.inflow.entry(dest).or_insert_with(Vec::new).push(Value::Source(src));
+                }
+            }
+        }
+
+        match name {
+            "strcpy" | "strncpy" | "strcat" | "strncat" | "memcpy" | "memmove" | "strlcpy" |
+            "strlcat" if arg_count >= 2 => {
+                let dest = self.dest_loc(&args[0]);
+                self.flow(dest, &args[1]);
+            }
+            "sprintf" | "snprintf" if arg_count >= 1 => {
+                let dest = self.dest_loc(&args[0]);
+                for a in &args[1..] {
+                    self.flow(dest, a);
+                }
+            }
+            "sscanf" if arg_count >= 2 => {
+                for a in &args[2..] {
+                    let dest = self.dest_loc(a);
+                    self.flow(dest, &args[0]);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(i) = length_arg(name) {
+            if i < arg_count {
+                self.add_sink(SinkKind::CopyLength, &args[i]);
+            }
+        }
+        if let Some(i) = format_arg(name) {
+            if i < arg_count {
+                self.add_sink(SinkKind::FormatString, &args[i]);
+            }
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for FlowVisitor<'a, 'tcx> {
+    fn visit_fn(&mut self, kind: FnKind<'ast>, fd: &'ast FnDecl, span: Span, id: NodeId) {
+        let did = match kind {
+            FnKind::ItemFn(_, _, _, body) | FnKind::Method(_, _, _, body) => {
+                let did = self.cx.node_def_id(id);
+                let mut values = Vec::new();
+                self.tail_values(body, &mut values);
+                self.inflow.entry(Loc::Ret(did)).or_insert_with(Vec::new).extend(values);
+                Some(did)
+            }
+            FnKind::Closure(_) => None,
+        };
+        self.fns.push(did);
+        visit::walk_fn(self, kind, fd, span);
+        self.fns.pop();
+    }
+
+    fn visit_local(&mut self, l: &'ast Local) {
+        if let Some(ref init) = l.init {
+            if let PatKind::Ident(..) = l.pat.kind {
+                let loc = self.cx.hir_map().opt_node_to_hir_id(l.pat.id).map(Loc::Var);
+                self.flow(loc, init);
+            }
+        }
+        visit::walk_local(self, l);
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Assign(ref lhs, ref rhs) | ExprKind::AssignOp(_, ref lhs, ref rhs) => {
+                let loc = self.dest_loc(lhs);
+                self.flow(loc, rhs);
+            }
+
+            ExprKind::Call(_, ref args) => {
+                let params = self.cx.opt_callee(e).and_then(|did| self.params.get(&did)).cloned();
+                if let Some(params) = params {
+                    for (param, arg) in params.into_iter().zip(args.iter()) {
+                        self.flow(param.map(Loc::Var), arg);
+                    }
+                } else if let Some(name) = callee_name(e) {
+                    self.libc_call(e, &name.as_str(), args);
+                }
+            }
+
+            ExprKind::MethodCall(ref seg, ref args) => {
+                let is_ptr = |e: &Expr| self.cx.opt_node_type(e.id)
+                    .map_or(false, |ty| matches!([ty.kind] TyKind::RawPtr(..)));
+                if args.len() == 2 && is_ptr(&args[0]) && matches!(
+                    [&*seg.ident.as_str()] "offset", "add", "sub", "wrapping_offset",
+                    "wrapping_add", "wrapping_sub") {
+                    self.add_sink(SinkKind::PointerArith, &args[1]);
+                }
+            }
+
+            ExprKind::Struct(_, ref fields, _) => {
+                let adt = match self.cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::Adt(adt, _)) if !adt.is_enum() => Some(adt),
+                    _ => None,
+                };
+                if let Some(adt) = adt {
+                    for f in fields {
+                        let loc = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .and_then(|fd| self.cx.hir_map().as_local_hir_id(fd.did))
+                            .map(Loc::Var);
+                        self.flow(loc, &f.expr);
+                    }
+                }
+            }
+
+            ExprKind::Ret(Some(ref v)) => {
+                let loc = self.fns.last().cloned().and_then(|f| f).map(Loc::Ret);
+                self.flow(loc, v);
+            }
+
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Check if the function `name`, with `arg_count` arguments, is the C `main` function.
+fn is_c_main(name: &str, arg_count: usize) -> bool {
+    (name == "main_0" || name == "main") && arg_count > 0
+}
+
+/// Find the sources of tainted data in `krate`, and compute where that data can flow.
+pub fn analyze(cx: &RefactorCtxt, krate: &Crate) -> Taint {
+    let mut sources = Vec::new();
+    let mut params = HashMap::new();
+    let mut inflow = HashMap::new();
+    visit_fns(krate, |fl| {
+        if fl.block.is_none() {
+            return;
+        }
+        let bindings = fl.decl.inputs.iter()
+            .map(|arg| match arg.pat.kind {
+                PatKind::Ident(..) => cx.hir_map().opt_node_to_hir_id(arg.pat.id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if is_c_main(&fl.ident.as_str(), bindings.len()) {
+            let names = ["argc", "argv", "envp"];
+            for (arg, (&name, hid)) in fl.decl.inputs.iter().zip(names.iter().zip(&bindings)) {
+                if let Some(hid) = *hid {
+                    inflow.insert(Loc::Var(hid), vec![Value::Source(sources.len())]);
+                    sources.push(Source {
+                        name: name.into_symbol(),
+                        id: arg.pat.id,
+                        span: arg.pat.span,
+                    });
+                }
+            }
+        }
+        params.insert(cx.node_def_id(fl.id), bindings);
+    });
+
+    let mut source_calls = HashMap::new();
+    visit_nodes(krate, |e: &Expr| {
+        let name = match_or!([callee_name(e)] Some(x) => x; return);
+        let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+        if cx.opt_callee(e).map_or(false, |did| params.contains_key(&did)) {
+            return;
+        }
+        let name_str = name.as_str();
+        if returns_input(&name_str) || !input_args(&name_str, args.len()).is_empty() {
+            source_calls.insert(e.id, sources.len());
+            sources.push(Source { name, id: e.id, span: e.span });
+        }
+    });
+
+    let mut v = FlowVisitor {
+        cx,
+        params,
+        source_calls,
+        fns: Vec::new(),
+        inflow,
+        sinks: Vec::new(),
+    };
+    krate.visit(&mut v);
+
+    // Propagate taint along the flow edges until nothing changes.  The set of sources reaching
+    // each location only ever grows, so this terminates.
+    let resolve = |tainted: &HashMap<Loc, BTreeSet<usize>>, values: &[Value]| {
+        let mut set = BTreeSet::new();
+        for &value in values {
+            match value {
+                Value::Source(src) => {
+                    set.insert(src);
+                }
+                Value::Loc(loc) => {
+                    if let Some(srcs) = tainted.get(&loc) {
+                        set.extend(srcs.iter().cloned());
+                    }
+                }
+            }
+        }
+        set
+    };
+    let mut tainted: HashMap<Loc, BTreeSet<usize>> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (&loc, values) in &v.inflow {
+            let new = resolve(&tainted, values);
+            if new.is_empty() {
+                continue;
+            }
+            if tainted.get(&loc).map_or(true, |old| old.len() < new.len()) {
+                tainted.insert(loc, new);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let sinks = v.sinks.into_iter()
+        .filter_map(|(mut sink, values)| {
+            sink.sources = resolve(&tainted, &values);
+            if sink.sources.is_empty() {
+                None
+            } else {
+                Some(sink)
+            }
+        })
+        .collect();
+
+    Taint { sources, tainted, sinks }
+}
+
+fn span_json(cx: &RefactorCtxt, span: Span) -> JsonValue {
+    let sm: &SourceMap = cx.session().source_map();
+    let loc = sm.lookup_char_pos(span.source_callsite().lo());
+    object! {
+        "file" => loc.file.name.to_string(),
+        "line" => loc.line,
+        "col" => loc.col.0 + 1
+    }
+}
+
+fn report_json(cx: &RefactorCtxt, taint: &Taint) -> JsonValue {
+    let sm: &SourceMap = cx.session().source_map();
+    let sources = taint.sources.iter().enumerate()
+        .map(|(i, src)| object! {
+            "id" => i,
+            "kind" => src.name.to_string(),
+            "location" => span_json(cx, src.span)
+        })
+        .collect::<Vec<_>>();
+    let sinks = taint.sinks.iter()
+        .map(|sink| object! {
+            "kind" => sink.kind.key(),
+            "function" => sink.func.map(|did| cx.ty_ctxt().def_path_str(did)),
+            "expr" => sm.span_to_snippet(sink.span).ok(),
+            "location" => span_json(cx, sink.span),
+            "sources" => sink.sources.iter().cloned().collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    object! {
+        "version" => 1,
+        "sources" => sources,
+        "sinks" => sinks
+    }
+}
+
+/// Run the taint analysis on `krate` and record the results.  Marks `taint_source` on each call
+/// that reads input and on the arguments of `main`, `tainted` on the binding or definition of
+/// each tainted local, argument, field, and static, and `taint_sink` on each tainted offset,
+/// length, or format string, warning about each sink.  If `report_path` is given, a JSON report
+/// of the sources and sinks is also written there.
+pub fn mark_taint(st: &CommandState, cx: &RefactorCtxt, krate: &Crate,
+                  report_path: Option<&str>) {
+    let taint = analyze(cx, krate);
+    let is_tainted = |id: NodeId| {
+        cx.hir_map().opt_node_to_hir_id(id)
+            .map_or(false, |hid| taint.tainted.contains_key(&Loc::Var(hid)))
+    };
+
+    for src in &taint.sources {
+        st.add_mark(src.id, "taint_source");
+    }
+    visit_nodes(krate, |p: &Pat| {
+        if let PatKind::Ident(..) = p.kind {
+            if is_tainted(p.id) {
+                st.add_mark(p.id, "tainted");
+            }
+        }
+    });
+    visit_nodes(krate, |sf: &StructField| {
+        if is_tainted(sf.id) {
+            st.add_mark(sf.id, "tainted");
+        }
+    });
+    visit_nodes(krate, |i: &Item| {
+        if let ItemKind::Static(..) = i.kind {
+            if is_tainted(i.id) {
+                st.add_mark(i.id, "tainted");
+            }
+        }
+    });
+
+    for sink in &taint.sinks {
+        st.add_mark(sink.expr, "taint_sink");
+        let names = sink.sources.iter()
+            .map(|&i| taint.sources[i].name.to_string())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        warn!("{}: {} may be controlled by input from {}",
+              cx.session().source_map().span_to_string(sink.span),
+              sink.kind.description(), names.join(", "));
+    }
+
+    if let Some(path) = report_path {
+        let report = json::stringify_pretty(report_json(cx, &taint), 2);
+        fs::write(path, report).unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+    }
+}
@@ -0,0 +1,433 @@
+//! Detection of data shared between threads.
+//!
+//! This finds the contexts each function may run in: the main thread, a thread started by
+//! `pthread_create` or `thrd_create` at some function, or a callback registered with foreign code
+//! (such as a signal handler, or a function passed to a library that may call it from its own
+//! threads).  A function runs in every context from which it can be reached through direct calls
+//! in the crate.  Functions that are neither thread start functions nor callbacks, and that
+//! nothing in the crate calls, are entry points of the main context.  Functions used as values
+//! anywhere else are treated as if they were called at that point.
+//!
+//! Data is shared if it may be accessed from more than one context at once:
+//!
+//!  * A `static mut` is shared if it is accessed in functions of two different contexts, or of a
+//!    thread context whose start function may be running in more than one thread at once, because
+//!    it's started from several places or inside a loop.
+//!  * The objects that the argument of `pthread_create` may point to, according to the
+//!    `points_to` analysis, are shared between the new thread and its creator.
+//!
+//! An access to shared data is unsynchronized unless it comes after a call to a locking function
+//! such as `pthread_mutex_lock`, and before the matching unlock, in the same function.  This
+//! check is lexical: locks taken in callers, or on only some paths, are not seen.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
+use syntax::ast::*;
+use syntax::ptr::P;
+use syntax::source_map::Span;
+use syntax::visit::{self, Visitor};
+
+use crate::analysis::points_to::{self, Loc};
+use crate::ast_manip::fn_edit::visit_fns;
+use crate::ast_manip::visit_nodes;
+use crate::command::CommandState;
+use crate::transform::heap::{is_call_to, strip_casts};
+use crate::RefactorCtxt;
+
+/// Foreign functions that start a thread, with the indices of their start function and argument.
+const THREAD_SPAWNERS: &[(&str, usize, usize)] = &[
+    ("pthread_create", 2, 3),
+    ("thrd_create", 1, 2),
+];
+
+const LOCK_FNS: &[&str] = &[
+    "pthread_mutex_lock",
+    "pthread_rwlock_rdlock",
+    "pthread_rwlock_wrlock",
+    "pthread_spin_lock",
+    "mtx_lock",
+];
+
+const UNLOCK_FNS: &[&str] = &[
+    "pthread_mutex_unlock",
+    "pthread_rwlock_unlock",
+    "pthread_spin_unlock",
+    "mtx_unlock",
+];
+
+/// Prefixes of the names of functions that operate on synchronization objects.  Statics passed
+/// to them are locks, not shared data.
+const SYNC_PREFIXES: &[&str] = &[
+    "pthread_mutex_",
+    "pthread_rwlock_",
+    "pthread_spin_",
+    "pthread_cond_",
+    "mtx_",
+    "cnd_",
+];
+
+/// A context that code may run in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Context {
+    /// The main thread, starting from `main` or any other function that isn't called in the crate.
+    Main,
+    /// A thread started at the given function.
+    Thread(DefId),
+    /// A callback registered with foreign code, which may call it at any time.
+    Callback(DefId),
+}
+
+/// If `e` is a call that starts a thread, return its start function and argument expressions.
+pub fn spawned_thread(e: &Expr) -> Option<(&Expr, Option<&Expr>)> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let &(_, start, arg) = THREAD_SPAWNERS.iter().find(|&&(name, _, _)| is_call_to(e, name))?;
+    Some((args.get(start)?, args.get(arg).map(|a| &**a)))
+}
+
+/// If `e` refers to a function, possibly wrapped in `Some`, casts, or `transmute`, get the
+/// function's `DefId`.
+pub fn fn_ref(cx: &RefactorCtxt, e: &Expr) -> Option<DefId> {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Call(_, ref args) if args.len() == 1 &&
+                                       (is_call_to(e, "Some") || is_call_to(e, "transmute")) =>
+            fn_ref(cx, &args[0]),
+        ExprKind::Path(..) => {
+            let did = cx.try_resolve_expr(e)?;
+            if cx.ty_ctxt().is_foreign_item(did) {
+                return None;
+            }
+            match cx.ty_ctxt().type_of(did).kind {
+                TcxTyKind::FnDef(..) => Some(did),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// An access to possibly shared data.
+struct Access {
+    id: NodeId,
+    span: Span,
+    locked: bool,
+}
+
+/// A call that starts a thread.
+struct Spawn {
+    start: DefId,
+    in_loop: bool,
+    arg: Option<P<Expr>>,
+}
+
+/// Everything the analysis needs to know about one function body.
+#[derive(Default)]
+struct FnInfo {
+    /// Functions in the crate that this one calls or uses as a value.
+    calls: HashSet<DefId>,
+    spawns: Vec<Spawn>,
+    callbacks: Vec<DefId>,
+    /// Accesses to `static mut`s.
+    statics: Vec<(DefId, Access)>,
+    /// Dereferences of pointers, with the pointer expression.
+    derefs: Vec<(P<Expr>, Access)>,
+    /// `static mut`s used as synchronization objects.
+    sync_statics: HashSet<DefId>,
+}
+
+struct FnScanner<'a, 'b, 'tcx: 'b> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    fns: &'a HashSet<DefId>,
+    statics: &'a HashSet<DefId>,
+    info: FnInfo,
+    /// The number of locks taken so far in the function.
+    locks: usize,
+    loop_depth: usize,
+}
+
+impl<'a, 'b, 'tcx> FnScanner<'a, 'b, 'tcx> {
+    fn access(&self, e: &Expr) -> Access {
+        Access { id: e.id, span: e.span, locked: self.locks > 0 }
+    }
+
+    fn visit_call(&mut self, e: &Expr, func: &Expr, args: &[P<Expr>]) {
+        if let Some((start, arg)) = spawned_thread(e) {
+            if let Some(start) = fn_ref(self.cx, start) {
+                self.info.spawns.push(Spawn {
+                    start,
+                    in_loop: self.loop_depth > 0,
+                    arg: arg.map(|a| P(a.clone())),
+                });
+            }
+            for a in args {
+                if fn_ref(self.cx, a).is_none() {
+                    self.visit_expr(a);
+                }
+            }
+            return;
+        }
+
+        let callee_name = match func.kind {
+            ExprKind::Path(None, ref path) => path.segments.last().map(|seg| seg.ident.as_str()),
+            _ => None,
+        };
+        if callee_name.map_or(false, |name| SYNC_PREFIXES.iter().any(|p| name.starts_with(p))) {
+            for a in args {
+                visit_nodes(&**a, |e: &Expr| {
+                    let did = match_or!([self.cx.try_resolve_expr(e)] Some(x) => x; return);
+                    if self.statics.contains(&did) {
+                        self.info.sync_statics.insert(did);
+                    }
+                });
+            }
+            if LOCK_FNS.iter().any(|name| is_call_to(e, name)) {
+                self.locks += 1;
+            } else if UNLOCK_FNS.iter().any(|name| is_call_to(e, name)) {
+                self.locks = self.locks.saturating_sub(1);
+            }
+            return;
+        }
+
+        let tcx = self.cx.ty_ctxt();
+        let is_foreign = self.cx.opt_callee(e).map_or(false, |did| tcx.is_foreign_item(did));
+        for a in args {
+            // Functions passed to foreign code are callbacks, not calls.
+            match fn_ref(self.cx, a) {
+                Some(f) if is_foreign => self.info.callbacks.push(f),
+                _ => self.visit_expr(a),
+            }
+        }
+        self.visit_expr(func);
+    }
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for FnScanner<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        match e.kind {
+            ExprKind::Call(ref func, ref args) => {
+                self.visit_call(e, func, args);
+                return;
+            }
+            ExprKind::Path(..) => {
+                if let Some(did) = self.cx.try_resolve_expr(e) {
+                    if self.statics.contains(&did) {
+                        let access = self.access(e);
+                        self.info.statics.push((did, access));
+                    } else if self.fns.contains(&did) {
+                        self.info.calls.insert(did);
+                    }
+                }
+            }
+            ExprKind::Unary(UnOp::Deref, ref ptr) => {
+                let access = self.access(e);
+                self.info.derefs.push((ptr.clone(), access));
+            }
+            ExprKind::While(..) | ExprKind::Loop(..) | ExprKind::ForLoop(..) => {
+                self.loop_depth += 1;
+                visit::walk_expr(self, e);
+                self.loop_depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// The result of the thread-sharing analysis.
+#[derive(Debug, Default)]
+pub struct ThreadSharing {
+    /// The contexts that each function in the crate may run in.
+    pub fn_contexts: HashMap<DefId, HashSet<Context>>,
+    /// Thread start functions that may be running in more than one thread at once.
+    pub multi_threads: HashSet<DefId>,
+    /// `static mut`s that may be accessed from more than one context at once.
+    pub shared_statics: HashSet<DefId>,
+    /// The `static mut`s that each function may access, itself or through the functions and
+    /// callbacks it calls.
+    pub fn_statics: HashMap<DefId, HashSet<DefId>>,
+    /// Objects that are passed to new threads.
+    pub shared_objects: HashSet<Loc>,
+    /// Accesses to shared statics and objects made without holding a lock, by expression.
+    pub unsync_accesses: Vec<(NodeId, Span)>,
+}
+
+impl ThreadSharing {
+    /// Check if code in `contexts` may run more than once at the same time.
+    fn is_concurrent(&self, contexts: &HashSet<Context>) -> bool {
+        contexts.len() > 1 || contexts.iter().any(|c| match *c {
+            Context::Thread(start) => self.multi_threads.contains(&start),
+            _ => false,
+        })
+    }
+}
+
+/// Run the thread-sharing analysis on `krate`.
+pub fn analyze(cx: &RefactorCtxt, krate: &Crate) -> ThreadSharing {
+    let mut fns = HashSet::new();
+    visit_fns(krate, |fl| {
+        if fl.block.is_some() {
+            fns.insert(cx.node_def_id(fl.id));
+        }
+    });
+    let mut statics = HashSet::new();
+    visit_nodes(krate, |i: &Item| {
+        if let ItemKind::Static(_, Mutability::Mutable, _) = i.kind {
+            statics.insert(cx.node_def_id(i.id));
+        }
+    });
+
+    // (1) Scan each function body.
+
+    let mut infos = HashMap::new();
+    visit_fns(krate, |fl| {
+        let block = match_or!([fl.block] Some(ref b) => b; return);
+        let mut v = FnScanner {
+            cx,
+            fns: &fns,
+            statics: &statics,
+            info: FnInfo::default(),
+            locks: 0,
+            loop_depth: 0,
+        };
+        v.visit_block(block);
+        infos.insert(cx.node_def_id(fl.id), v.info);
+    });
+
+    // (2) Find the entry point of each context.
+
+    let mut result = ThreadSharing::default();
+    let mut roots = Vec::new();
+    let mut spawn_counts = HashMap::new();
+    for info in infos.values() {
+        for spawn in &info.spawns {
+            let count = spawn_counts.entry(spawn.start).or_insert(0);
+            *count += if spawn.in_loop { 2 } else { 1 };
+            roots.push((Context::Thread(spawn.start), spawn.start));
+        }
+        for &f in &info.callbacks {
+            roots.push((Context::Callback(f), f));
+        }
+    }
+    result.multi_threads = spawn_counts.into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(start, _)| start)
+        .collect();
+
+    let called = infos.values().flat_map(|info| info.calls.iter().cloned()).collect::<HashSet<_>>();
+    let non_main = roots.iter().map(|&(_, f)| f).collect::<HashSet<_>>();
+    for &f in &fns {
+        if !called.contains(&f) && !non_main.contains(&f) {
+            roots.push((Context::Main, f));
+        }
+    }
+
+    // (3) Propagate contexts through the call graph.
+
+    for (ctx, root) in roots {
+        let mut stack = vec![root];
+        while let Some(f) = stack.pop() {
+            if !result.fn_contexts.entry(f).or_insert_with(HashSet::new).insert(ctx) {
+                continue;
+            }
+            if let Some(info) = infos.get(&f) {
+                stack.extend(info.calls.iter().cloned());
+            }
+        }
+    }
+
+    // (4) Find the statics each function may access.  Callbacks passed to foreign code count as
+    // calls, since functions like `qsort` call them before returning.
+
+    for &f in infos.keys() {
+        let mut accessed = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![f];
+        while let Some(g) = stack.pop() {
+            if !seen.insert(g) {
+                continue;
+            }
+            if let Some(info) = infos.get(&g) {
+                accessed.extend(info.statics.iter().map(|&(did, _)| did));
+                stack.extend(info.calls.iter().chain(&info.callbacks).cloned());
+            }
+        }
+        result.fn_statics.insert(f, accessed);
+    }
+
+    // (5) Find the shared statics and objects, and the unsynchronized accesses to them.
+
+    let sync_statics = infos.values()
+        .flat_map(|info| info.sync_statics.iter().cloned())
+        .collect::<HashSet<_>>();
+    let mut static_contexts: HashMap<DefId, HashSet<Context>> = HashMap::new();
+    for (f, info) in &infos {
+        let contexts = match_or!([result.fn_contexts.get(f)] Some(x) => x; continue);
+        for &(did, _) in &info.statics {
+            let entry = static_contexts.entry(did).or_insert_with(HashSet::new);
+            entry.extend(contexts.iter().cloned());
+        }
+    }
+    result.shared_statics = static_contexts.iter()
+        .filter(|&(did, contexts)| !sync_statics.contains(did) && result.is_concurrent(contexts))
+        .map(|(&did, _)| did)
+        .collect();
+
+    let pts = points_to::analyze(cx, krate);
+    for info in infos.values() {
+        for spawn in &info.spawns {
+            let arg = match_or!([spawn.arg] Some(ref x) => x; continue);
+            let objs = match_or!([pts.points_to(arg)] Some(x) => x; continue);
+            result.shared_objects.extend(objs.iter().cloned().filter(|&o| o != Loc::Unknown));
+        }
+    }
+
+    for info in infos.values() {
+        for &(did, ref access) in &info.statics {
+            if !access.locked && result.shared_statics.contains(&did) {
+                result.unsync_accesses.push((access.id, access.span));
+            }
+        }
+        for &(ref ptr, ref access) in &info.derefs {
+            let shared = pts.points_to(ptr)
+                .map_or(false, |objs| objs.iter().any(|o| result.shared_objects.contains(o)));
+            if !access.locked && shared {
+                result.unsync_accesses.push((access.id, access.span));
+            }
+        }
+    }
+
+    result
+}
+
+/// Run the thread-sharing analysis, and mark shared data and unsynchronized accesses to it.
+pub fn mark_sharing(st: &CommandState, cx: &RefactorCtxt, krate: &Crate) {
+    let result = analyze(cx, krate);
+
+    visit_nodes(krate, |i: &Item| {
+        if let ItemKind::Static(..) = i.kind {
+            if result.shared_statics.contains(&cx.node_def_id(i.id)) {
+                st.add_mark(i.id, "shared");
+            }
+        }
+    });
+    for &obj in &result.shared_objects {
+        match obj {
+            Loc::Var(hid) => st.add_mark(cx.hir_map().hir_to_node_id(hid), "shared"),
+            Loc::Alloc(id) => st.add_mark(id, "shared"),
+            _ => {}
+        }
+    }
+
+    let source_map = cx.session().source_map();
+    for &(id, span) in &result.unsync_accesses {
+        st.add_mark(id, "unsync");
+        warn!("{}: unsynchronized access to data shared between threads",
+              source_map.span_to_string(span));
+    }
+}
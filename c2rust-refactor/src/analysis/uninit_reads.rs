@@ -0,0 +1,135 @@
+//! Detection of dummy-initialized locals whose initial value is never read.
+//!
+//! The translator gives every local an initializer, even when the C variable had none, using a
+//! dummy value such as `0` or `mem::zeroed()`.  This analysis checks whether such a dummy value
+//! can ever be observed: whether some use of the local may happen before any write to the whole
+//! local other than its initializer.  It runs on the function's MIR using the `dataflow`
+//! framework, tracking which locals are definitely written on every path.
+//!
+//! Every use other than a write of the whole local counts as a read, including borrows, writes
+//! to single fields, and drops.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
+use rustc::mir::visit::{MutatingUseContext, PlaceContext, Visitor};
+use rustc::mir::*;
+use rustc_index::bit_set::BitSet;
+use syntax::source_map::Span;
+
+use crate::analysis::dataflow::{self, Analysis};
+use crate::RefactorCtxt;
+
+/// Tracks the locals that are definitely written, not counting their dummy initializers.
+struct DefinitelyWritten<'a> {
+    /// The span of the `let` statement that declares each dummy-initialized local.
+    inits: &'a HashMap<Local, Span>,
+}
+
+impl<'a> DefinitelyWritten<'a> {
+    fn write(&self, state: &mut BitSet<Local>, place: &Place, span: Span) {
+        if let PlaceBase::Local(l) = place.base {
+            let is_init = self.inits.get(&l).map_or(true, |init| init.contains(span));
+            if place.projection.is_empty() && !is_init {
+                state.insert(l);
+            }
+        }
+    }
+}
+
+impl<'a, 'tcx> Analysis<'tcx> for DefinitelyWritten<'a> {
+    type Domain = BitSet<Local>;
+
+    fn entry_state(&self, body: &Body<'tcx>) -> BitSet<Local> {
+        BitSet::new_empty(body.local_decls.len())
+    }
+
+    fn bottom(&self, body: &Body<'tcx>) -> BitSet<Local> {
+        BitSet::new_filled(body.local_decls.len())
+    }
+
+    fn join(&self, state: &mut BitSet<Local>, other: &BitSet<Local>) -> bool {
+        state.intersect(other)
+    }
+
+    fn apply_statement(&self, state: &mut BitSet<Local>, stmt: &Statement<'tcx>, _loc: Location) {
+        if let StatementKind::Assign(box(ref place, _)) = stmt.kind {
+            self.write(state, place, stmt.source_info.span);
+        }
+    }
+
+    fn apply_terminator(&self, state: &mut BitSet<Local>, term: &Terminator<'tcx>,
+                        _loc: Location) {
+        match term.kind {
+            TerminatorKind::Call { destination: Some((ref place, _)), .. } |
+            TerminatorKind::DropAndReplace { location: ref place, .. } => {
+                self.write(state, place, term.source_info.span);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collects the reads of dummy-initialized locals in a statement or terminator.
+struct ReadCollector<'a> {
+    inits: &'a HashMap<Local, Span>,
+    reads: Vec<Local>,
+}
+
+impl<'a, 'tcx> Visitor<'tcx> for ReadCollector<'a> {
+    fn visit_local(&mut self, &local: &Local, context: PlaceContext, _loc: Location) {
+        let is_read = match context {
+            PlaceContext::MutatingUse(MutatingUseContext::Store) |
+            PlaceContext::MutatingUse(MutatingUseContext::Call) |
+            PlaceContext::NonUse(_) => false,
+            _ => true,
+        };
+        if is_read && self.inits.contains_key(&local) {
+            self.reads.push(local);
+        }
+    }
+}
+
+/// Check which dummy-initialized locals of the function `def_id` are never read before being
+/// written.  `inits` maps the span of each local's binding pattern to the span of the `let`
+/// statement that declares it.  Returns the pattern spans of the locals whose initial value is
+/// never read, or `None` if no MIR is available for the function.
+///
+/// Locals that can't be found in the MIR, for example because they were optimized away, are
+/// never included in the result.
+pub fn unread_dummy_inits(cx: &RefactorCtxt, def_id: DefId, inits: &HashMap<Span, Span>)
+                          -> Option<HashSet<Span>> {
+    let tcx = cx.ty_ctxt();
+    if !def_id.is_local() || !tcx.is_mir_available(def_id) {
+        return None;
+    }
+    let body = tcx.optimized_mir(def_id);
+
+    let locals = inits.iter()
+        .filter_map(|(&pat_span, &let_span)| {
+            Some((dataflow::local_for_pat(body, pat_span)?, let_span))
+        })
+        .collect::<HashMap<_, _>>();
+    let results = dataflow::analyze_body(body, DefinitelyWritten { inits: &locals });
+
+    let mut read = HashSet::new();
+    results.visit_with_state(|loc, state| {
+        let data = &body.basic_blocks()[loc.block];
+        let mut v = ReadCollector { inits: &locals, reads: Vec::new() };
+        match data.statements.get(loc.statement_index) {
+            Some(stmt) => match stmt.kind {
+                // These only matter to the borrow checker.
+                StatementKind::FakeRead(..) |
+                StatementKind::AscribeUserType(..) |
+                StatementKind::Retag { .. } => {}
+                _ => v.visit_statement(stmt, loc),
+            },
+            None => v.visit_terminator(data.terminator(), loc),
+        }
+        read.extend(v.reads.into_iter().filter(|&l| !state.contains(l)));
+    });
+
+    Some(locals.keys()
+         .filter(|l| !read.contains(*l))
+         .filter_map(|&l| dataflow::local_pat_span(body, l))
+         .collect())
+}
@@ -0,0 +1,158 @@
+//! Find the operations in a piece of code that actually require an `unsafe` context.
+//!
+//! Transpiled code wraps nearly everything in `unsafe`, but many of those blocks contain only
+//! safe operations.  This analysis looks for the operations that Rust considers unsafe:
+//!
+//!  * Dereferencing a raw pointer
+//!  * Calling an `unsafe` function or method (including foreign functions)
+//!  * Accessing a field of a union
+//!  * Accessing a `static mut` or an extern static
+//!  * Inline assembly
+//!
+//! Operations inside nested `unsafe` blocks and nested items are not included, since they don't
+//! depend on the enclosing context.  Closures, on the other hand, inherit the enclosing unsafety,
+//! so their bodies are included.
+//!
+//! This is a syntactic approximation of rustc's own unsafety checker.  It runs on the expanded AST
+//! and relies on the typeck results in `RefactorCtxt`, so it must be run in phase 3.
+
+use std::fmt;
+use rustc::hir;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::source_map::Span;
+use syntax::visit::{self, Visitor};
+
+use crate::RefactorCtxt;
+
+
+/// The kinds of operations that require `unsafe`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum UnsafeOpKind {
+    RawDeref,
+    UnsafeCall,
+    UnionField,
+    MutableStatic,
+    ExternStatic,
+    InlineAsm,
+}
+
+impl fmt::Display for UnsafeOpKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            UnsafeOpKind::RawDeref => "dereference of raw pointer",
+            UnsafeOpKind::UnsafeCall => "call to unsafe function",
+            UnsafeOpKind::UnionField => "access to union field",
+            UnsafeOpKind::MutableStatic => "use of mutable static",
+            UnsafeOpKind::ExternStatic => "use of extern static",
+            UnsafeOpKind::InlineAsm => "use of inline assembly",
+        };
+        f.write_str(s)
+    }
+}
+
+/// An operation that requires `unsafe`.
+#[derive(Clone, Copy, Debug)]
+pub struct UnsafeOp {
+    pub kind: UnsafeOpKind,
+    pub span: Span,
+}
+
+struct UnsafeOpVisitor<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    ops: Vec<UnsafeOp>,
+}
+
+/// Classify `e`, if it is itself an operation that requires `unsafe`.  Operations in the
+/// subexpressions of `e` are not considered.
+pub fn expr_unsafe_op(cx: &RefactorCtxt, e: &Expr) -> Option<UnsafeOpKind> {
+    match e.kind {
+        ExprKind::Unary(UnOp::Deref, ref inner) => {
+            if let Some(ty) = cx.opt_node_type(inner.id) {
+                if let TyKind::RawPtr(_) = ty.kind {
+                    return Some(UnsafeOpKind::RawDeref);
+                }
+            }
+        }
+
+        ExprKind::Call(..) | ExprKind::MethodCall(..) => {
+            if let Some(sig) = cx.opt_callee_fn_sig(e) {
+                if sig.unsafety == hir::Unsafety::Unsafe {
+                    return Some(UnsafeOpKind::UnsafeCall);
+                }
+            }
+        }
+
+        ExprKind::Field(ref obj, _) => {
+            if let Some(ty) = cx.opt_adjusted_node_type(obj.id) {
+                if let TyKind::Adt(adt, _) = ty.kind {
+                    if adt.is_union() {
+                        return Some(UnsafeOpKind::UnionField);
+                    }
+                }
+            }
+        }
+
+        ExprKind::Path(..) => {
+            if let Some(did) = cx.try_resolve_expr(e) {
+                let tcx = cx.ty_ctxt();
+                match tcx.static_mutability(did) {
+                    Some(hir::Mutability::Mutable) => return Some(UnsafeOpKind::MutableStatic),
+                    Some(_) if tcx.is_foreign_item(did) => return Some(UnsafeOpKind::ExternStatic),
+                    _ => {}
+                }
+            }
+        }
+
+        ExprKind::InlineAsm(..) => return Some(UnsafeOpKind::InlineAsm),
+
+        _ => {}
+    }
+    None
+}
+
+impl<'a, 'tcx> UnsafeOpVisitor<'a, 'tcx> {
+    fn check_expr(&mut self, e: &Expr) {
+        if let Some(kind) = expr_unsafe_op(self.cx, e) {
+            self.ops.push(UnsafeOp { kind, span: e.span });
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for UnsafeOpVisitor<'a, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let ExprKind::Block(ref b, _) = e.kind {
+            if let BlockCheckMode::Unsafe(_) = b.rules {
+                // Operations in a nested `unsafe` block are covered by that block.
+                return;
+            }
+        }
+        self.check_expr(e);
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_item(&mut self, _i: &'ast Item) {
+        // Nested items don't inherit the enclosing unsafety.
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+/// Find the operations in `s` that require an `unsafe` context.
+pub fn stmt_unsafe_ops(cx: &RefactorCtxt, s: &Stmt) -> Vec<UnsafeOp> {
+    let mut v = UnsafeOpVisitor { cx, ops: Vec::new() };
+    v.visit_stmt(s);
+    v.ops
+}
+
+/// Find the operations in the statements of `b` that require an `unsafe` context.  `b` itself may
+/// be an `unsafe` block; only the blocks nested inside it are skipped.
+pub fn block_unsafe_ops(cx: &RefactorCtxt, b: &Block) -> Vec<UnsafeOp> {
+    let mut v = UnsafeOpVisitor { cx, ops: Vec::new() };
+    for s in &b.stmts {
+        v.visit_stmt(s);
+    }
+    v.ops
+}
@@ -0,0 +1,320 @@
+//! Report of the unsafe code remaining in a crate, for tracking the progress of a migration.
+//!
+//! The report counts unsafe blocks and functions, along with the unsafe operations that most
+//! often keep code from being made safe.  Counts are grouped by the C file each piece of code was
+//! translated from: code inside a module carrying a `#[c2rust::header_src]` attribute belongs to
+//! that header, and all other code belongs to the C file named like its Rust file (`foo.rs` is
+//! assumed to come from `foo.c`).
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use json::{self, object, JsonValue};
+use rustc::hir::def_id::DefId;
+use syntax::ast::*;
+use syntax::source_map::{SourceMap, Span};
+use syntax::visit::{self, Visitor};
+use syntax_pos::FileName;
+
+use crate::analysis::unsafety::{expr_unsafe_op, UnsafeOpKind};
+use crate::ast_manip::util::is_c2rust_attr;
+use crate::ast_manip::Visit;
+use crate::command::{DriverCommand, Registry};
+use crate::driver::Phase;
+use crate::RefactorCtxt;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum ReportKind {
+    UnsafeBlock,
+    UnsafeFn,
+    RawDeref,
+    StaticMut,
+    Transmute,
+    FfiCall,
+}
+
+const KINDS: &[ReportKind] = &[
+    ReportKind::UnsafeBlock,
+    ReportKind::UnsafeFn,
+    ReportKind::RawDeref,
+    ReportKind::StaticMut,
+    ReportKind::Transmute,
+    ReportKind::FfiCall,
+];
+
+impl ReportKind {
+    fn key(self) -> &'static str {
+        match self {
+            ReportKind::UnsafeBlock => "unsafe_blocks",
+            ReportKind::UnsafeFn => "unsafe_fns",
+            ReportKind::RawDeref => "raw_derefs",
+            ReportKind::StaticMut => "static_mut_accesses",
+            ReportKind::Transmute => "transmutes",
+            ReportKind::FfiCall => "ffi_calls",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ReportKind::UnsafeBlock => "unsafe block",
+            ReportKind::UnsafeFn => "unsafe fn",
+            ReportKind::RawDeref => "raw pointer dereference",
+            ReportKind::StaticMut => "static mut access",
+            ReportKind::Transmute => "transmute",
+            ReportKind::FfiCall => "FFI call",
+        }
+    }
+}
+
+/// A single reported item, located in the Rust source.
+struct Location {
+    kind: ReportKind,
+    file: String,
+    line: usize,
+    col: usize,
+}
+
+struct ReportVisitor<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The headers that the enclosing modules were translated from, innermost last.
+    headers: Vec<String>,
+    /// The reported items, grouped by C file.
+    groups: BTreeMap<String, Vec<Location>>,
+}
+
+/// Get the header path from a `#[c2rust::header_src = "path:line"]` attribute.
+fn header_src(attrs: &[Attribute]) -> Option<String> {
+    let attr = attrs.iter().find(|attr| is_c2rust_attr(attr, "header_src"))?;
+    let value = attr.value_str()?.as_str();
+    let path = value.rsplitn(2, ':').last()?;
+    Some(path.to_owned())
+}
+
+fn rust_file_to_c_file(name: &FileName) -> String {
+    match name {
+        FileName::Real(path) => path.with_extension("c").display().to_string(),
+        _ => name.to_string(),
+    }
+}
+
+impl<'a, 'tcx> ReportVisitor<'a, 'tcx> {
+    fn record(&mut self, kind: ReportKind, span: Span) {
+        let span = span.source_callsite();
+        if span.is_dummy() {
+            return;
+        }
+        let sm: &SourceMap = self.cx.session().source_map();
+        let loc = sm.lookup_char_pos(span.lo());
+        let group = match self.headers.last() {
+            Some(header) => header.clone(),
+            None => rust_file_to_c_file(&loc.file.name),
+        };
+        self.groups.entry(group).or_insert_with(Vec::new).push(Location {
+            kind,
+            file: loc.file.name.to_string(),
+            line: loc.line,
+            col: loc.col.0 + 1,
+        });
+    }
+
+    fn callee_def_id(&self, e: &Expr) -> Option<DefId> {
+        match e.kind {
+            ExprKind::Call(ref func, _) => self.cx.try_resolve_expr(func),
+            _ => None,
+        }
+    }
+
+    fn is_transmute(&self, did: DefId) -> bool {
+        let tcx = self.cx.ty_ctxt();
+        let krate = tcx.crate_name(did.krate);
+        tcx.item_name(did).as_str() == "transmute"
+            && (krate.as_str() == "core" || krate.as_str() == "std")
+    }
+
+    fn check_fn_sig(&mut self, sig: &FnSig, span: Span) {
+        if let Unsafety::Unsafe = sig.header.unsafety {
+            self.record(ReportKind::UnsafeFn, span);
+        }
+    }
+}
+
+impl<'a, 'tcx, 'ast> Visitor<'ast> for ReportVisitor<'a, 'tcx> {
+    fn visit_item(&mut self, i: &'ast Item) {
+        if let ItemKind::Fn(ref sig, ..) = i.kind {
+            self.check_fn_sig(sig, i.span);
+        }
+
+        let header = header_src(&i.attrs);
+        let pushed = header.is_some();
+        self.headers.extend(header);
+        visit::walk_item(self, i);
+        if pushed {
+            self.headers.pop();
+        }
+    }
+
+    fn visit_impl_item(&mut self, i: &'ast ImplItem) {
+        if let ImplItemKind::Method(ref sig, _) = i.kind {
+            self.check_fn_sig(sig, i.span);
+        }
+        visit::walk_impl_item(self, i);
+    }
+
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let ExprKind::Block(ref b, _) = e.kind {
+            if let BlockCheckMode::Unsafe(UnsafeSource::UserProvided) = b.rules {
+                self.record(ReportKind::UnsafeBlock, e.span);
+            }
+        }
+
+        match expr_unsafe_op(self.cx, e) {
+            Some(UnsafeOpKind::RawDeref) => self.record(ReportKind::RawDeref, e.span),
+            Some(UnsafeOpKind::MutableStatic) | Some(UnsafeOpKind::ExternStatic) => {
+                self.record(ReportKind::StaticMut, e.span)
+            }
+            Some(UnsafeOpKind::UnsafeCall) => match self.callee_def_id(e) {
+                Some(did) if self.is_transmute(did) => self.record(ReportKind::Transmute, e.span),
+                Some(did) if self.cx.ty_ctxt().is_foreign_item(did) => {
+                    self.record(ReportKind::FfiCall, e.span)
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        visit::walk_expr(self, e);
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+fn counts(locs: &[Location]) -> BTreeMap<ReportKind, usize> {
+    let mut counts = KINDS.iter().map(|&k| (k, 0)).collect::<BTreeMap<_, _>>();
+    for loc in locs {
+        *counts.get_mut(&loc.kind).unwrap() += 1;
+    }
+    counts
+}
+
+fn counts_json(counts: &BTreeMap<ReportKind, usize>) -> JsonValue {
+    let mut obj = JsonValue::new_object();
+    for (&kind, &n) in counts {
+        obj[kind.key()] = n.into();
+    }
+    obj
+}
+
+fn report_json(groups: &BTreeMap<String, Vec<Location>>) -> JsonValue {
+    let mut totals = KINDS.iter().map(|&k| (k, 0)).collect::<BTreeMap<_, _>>();
+    let files = groups.iter()
+        .map(|(file, locs)| {
+            for (kind, n) in counts(locs) {
+                *totals.get_mut(&kind).unwrap() += n;
+            }
+            let locations = locs.iter()
+                .map(|loc| object! {
+                    "kind" => loc.kind.key(),
+                    "file" => loc.file.clone(),
+                    "line" => loc.line,
+                    "col" => loc.col
+                })
+                .collect::<Vec<_>>();
+            object! {
+                "file" => file.clone(),
+                "counts" => counts_json(&counts(locs)),
+                "locations" => locations
+            }
+        })
+        .collect::<Vec<_>>();
+
+    object! {
+        "version" => 1,
+        "totals" => counts_json(&totals),
+        "files" => files
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn report_html(groups: &BTreeMap<String, Vec<Location>>) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Unsafety report</title>\n<style>\n");
+    html.push_str("table { border-collapse: collapse; }\n");
+    html.push_str("th, td { border: 1px solid #ccc; padding: 2px 8px; text-align: right; }\n");
+    html.push_str("th:first-child, td:first-child { text-align: left; }\n");
+    html.push_str("</style>\n</head>\n<body>\n<h1>Unsafety report</h1>\n");
+    html.push_str("<table>\n<tr><th>File</th>");
+    for kind in KINDS {
+        write!(html, "<th>{}</th>", kind.description()).unwrap();
+    }
+    html.push_str("</tr>\n");
+
+    let mut totals = KINDS.iter().map(|&k| (k, 0)).collect::<BTreeMap<_, _>>();
+    for (i, (file, locs)) in groups.iter().enumerate() {
+        write!(html, "<tr><td><a href=\"#file{}\">{}</a></td>", i, escape_html(file)).unwrap();
+        for (kind, n) in counts(locs) {
+            *totals.get_mut(&kind).unwrap() += n;
+            write!(html, "<td>{}</td>", n).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("<tr><th>Total</th>");
+    for n in totals.values() {
+        write!(html, "<th>{}</th>", n).unwrap();
+    }
+    html.push_str("</tr>\n</table>\n");
+
+    for (i, (file, locs)) in groups.iter().enumerate() {
+        writeln!(html, "<h2 id=\"file{}\">{}</h2>\n<ul>", i, escape_html(file)).unwrap();
+        for loc in locs {
+            writeln!(html, "<li>{}: {}:{}:{}</li>",
+                   loc.kind.description(), escape_html(&loc.file), loc.line, loc.col).unwrap();
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// # `unsafety_report` Command
+///
+/// Usage: `unsafety_report [FILE]`
+///
+/// Write a report of the unsafe code remaining in the crate to `FILE` (default:
+/// `unsafety_report.json`).  The report is written as HTML if `FILE` ends in `.html`, and as JSON
+/// otherwise.
+///
+/// The report counts unsafe blocks, unsafe functions, raw pointer dereferences, accesses to
+/// `static mut` and extern statics, calls to `transmute`, and calls to foreign functions, and
+/// lists the location of each one.  The results are grouped by the C file that the code was
+/// translated from: code in a module with a `#[c2rust::header_src]` attribute is attributed to
+/// that header, and other code to the C file with the same name as its Rust file.
+fn register_unsafety_report(reg: &mut Registry) {
+    reg.register("unsafety_report", |args| {
+        let path = args.get(0).cloned().unwrap_or_else(|| "unsafety_report.json".to_owned());
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            let mut v = ReportVisitor {
+                cx,
+                headers: Vec::new(),
+                groups: BTreeMap::new(),
+            };
+            st.krate().visit(&mut v);
+
+            let report = if path.ends_with(".html") {
+                report_html(&v.groups)
+            } else {
+                json::stringify_pretty(report_json(&v.groups), 2)
+            };
+            fs::write(&path, report)
+                .unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+        }))
+    });
+}
+
+pub fn register_commands(reg: &mut Registry) {
+    register_unsafety_report(reg);
+}
@@ -0,0 +1,57 @@
+//! Convenience re-exports for plugins.
+//!
+//! A plugin crate can `use c2rust_refactor::api::*;` to get the types needed to implement and
+//! register commands, along with the most commonly used AST helpers.  See PLUGINS.txt for details
+//! on building and loading plugins.
+
+pub use c2rust_ast_builder::{mk, Builder, IntoSymbol, Make};
+
+pub use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+pub use crate::ast_manip::{
+    visit_nodes, AstEquiv, FlatMapNodes, GetNodeId, GetSpan, MutVisit, MutVisitNodes, Visit,
+};
+pub use crate::command::{
+    Command, CommandState, DriverCommand, FuncCommand, RefactorState, Registry, TypeckLoopResult,
+};
+pub use crate::driver::{parse_expr, parse_items, parse_stmts, parse_ty, Phase};
+pub use crate::matcher::{
+    find_first, mut_visit_match, mut_visit_match_with, replace_expr, replace_stmts, Bindings,
+    MatchCtxt, Subst,
+};
+pub use crate::transform::{Transform, TransformCommand};
+pub use crate::RefactorCtxt;
+
+/// Version of the plugin interface.  This is bumped whenever a change to `c2rust-refactor` would
+/// break plugins built against an older version, so that mismatched plugins are rejected at load
+/// time instead of crashing.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+/// Wrap a `Transform` to produce a `Box<dyn Command>`, for use with `Registry::register`.
+pub fn transform_command<T: Transform + 'static>(t: T) -> Box<dyn Command> {
+    Box::new(TransformCommand(t))
+}
+
+/// Define the entry points that `c2rust-refactor` looks for when loading a plugin.  The argument
+/// is the path of a `fn(&mut Registry)` that registers the plugin's commands:
+///
+/// ```ignore
+///     fn register_commands(reg: &mut Registry) {
+///         reg.register("my_command", |args| transform_command(MyTransform::new(args)));
+///     }
+///
+///     declare_plugin!(register_commands);
+/// ```
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        pub extern "C" fn c2rust_refactor_plugin_api_version() -> u32 {
+            $crate::api::PLUGIN_API_VERSION
+        }
+
+        #[no_mangle]
+        pub fn register_commands(reg: &mut $crate::command::Registry) {
+            $register(reg)
+        }
+    };
+}
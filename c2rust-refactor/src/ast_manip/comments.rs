@@ -14,7 +14,7 @@ use crate::ast_manip::Visit;
 
 pub use syntax::util::comments::{Comment, CommentStyle};
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct CommentMap(HashMap<NodeId, Vec<Comment>>);
 
 impl CommentMap {
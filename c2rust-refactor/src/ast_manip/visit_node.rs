@@ -140,6 +140,22 @@ gen_visit_node_impl! {
     walk = visit::walk_stmt(self, s);
 }
 
+gen_visit_node_impl! {
+    node = Ty;
+    visitor = TyNodeVisitor;
+    visitor_post = TyNodeVisitorPost;
+    fn visit_ty(&mut self, t: &'ast Ty);
+    walk = visit::walk_ty(self, t);
+}
+
+gen_visit_node_impl! {
+    node = Pat;
+    visitor = PatNodeVisitor;
+    visitor_post = PatNodeVisitorPost;
+    fn visit_pat(&mut self, p: &'ast Pat);
+    walk = visit::walk_pat(self, p);
+}
+
 /// Visit nodes of the callback's argument type within `target`.  This function performs a preorder
 /// traversal.
 pub fn visit_nodes<N, T, F>(target: &T, callback: F)
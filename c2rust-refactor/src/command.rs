@@ -34,6 +34,7 @@ use crate::driver::{self, Phase};
 use crate::file_io::FileIO;
 use crate::node_map::NodeMap;
 use crate::rewrite;
+use crate::rewrite::edits::AppliedEdits;
 use crate::rewrite::files;
 use crate::span_fix;
 use crate::RefactorCtxt;
@@ -100,6 +101,24 @@ struct DiskState {
     comment_map: CommentMap,
 }
 
+/// A snapshot of the parts of `RefactorState` that commands can change, for use by `undo`.
+struct UndoState {
+    /// The command whose effects this snapshot undoes
+    command: String,
+    krate: Option<Crate>,
+    marks: HashSet<(NodeId, Symbol)>,
+    node_map: NodeMap,
+    parsed_nodes: ParsedNodes,
+    comment_map: Option<CommentMap>,
+    commands: Vec<String>,
+    /// `None` once the crate has been saved after the snapshot, since the files on disk may then
+    /// hold the undone command's changes
+    applied_edits: Option<AppliedEdits>,
+}
+
+/// Maximum number of snapshots kept for `undo`.  Each one holds a full copy of the crate.
+const UNDO_LIMIT: usize = 16;
+
 /// Stores the overall state of the refactoring process, which can be read and updated by
 /// `Command`s.
 pub struct RefactorState {
@@ -135,8 +154,20 @@ pub struct RefactorState {
     /// Commands run so far
     commands: Vec<String>,
 
+    /// Whether to take a snapshot before each command, so that it can be undone
+    checkpoints: bool,
+
+    /// Snapshots taken before each command, most recent last
+    undo_stack: Vec<UndoState>,
+
     /// Generation number for TyCtxt references
     tcx_gen: TyCtxtGeneration,
+
+    /// Format pretty-printed items with `rustfmt` when saving the crate
+    format: bool,
+
+    /// Edits written by the previous `save_crate`, so unchanged files aren't written again
+    applied_edits: AppliedEdits,
 }
 
 // #[cfg_attr(feature = "profile", flame)]
@@ -214,6 +245,10 @@ impl RefactorState {
 
             commands: vec![],
 
+            checkpoints: false,
+
+            undo_stack: vec![],
+
             disk_state: None,
 
             node_map: NodeMap::new(),
@@ -225,6 +260,26 @@ impl RefactorState {
             node_id_counter: NodeIdCounter::new(FRESH_NODE_ID_START),
 
             tcx_gen: Arc::new(AtomicUsize::new(1)),
+
+            format: false,
+
+            applied_edits: AppliedEdits::new(),
+        }
+    }
+
+    /// Set whether items that have to be pretty-printed when saving the crate are formatted with
+    /// `rustfmt`.
+    pub fn set_format(&mut self, format: bool) {
+        self.format = format;
+    }
+
+    /// Set whether a snapshot of the crate is taken before each command, so that `undo` can
+    /// discard it.  Each snapshot holds a full copy of the crate, so this is off unless the
+    /// commands may be undone.
+    pub fn set_checkpoints(&mut self, checkpoints: bool) {
+        self.checkpoints = checkpoints;
+        if !checkpoints {
+            self.undo_stack.clear();
         }
     }
 
@@ -251,6 +306,9 @@ impl RefactorState {
         self.node_map = NodeMap::new();
         self.parsed_nodes = ParsedNodes::default();
         self.node_id_counter = NodeIdCounter::new(FRESH_NODE_ID_START);
+        self.applied_edits.clear();
+        // Snapshots refer to the old `disk_state`, so they can't be restored after reloading.
+        self.undo_stack.clear();
     }
 
     /// Save the crate to disk, by writing out the new source text produced by rewriting.
@@ -278,12 +336,27 @@ impl RefactorState {
             )
             .unwrap();
 
-        let rw = rewrite::rewrite(self.session(), old, new, &disk_state.comment_map, node_id_map, |map| {
-            map_ast_into(&self.parsed_nodes, map);
-        });
+        let rw = rewrite::rewrite(
+            self.session(),
+            old,
+            new,
+            &disk_state.comment_map,
+            node_id_map,
+            self.format,
+            |map| map_ast_into(&self.parsed_nodes, map),
+        );
         // Note that `rewrite_files_with` does not read any files from disk - it uses the
         // `SourceMap` to get files' original source text.
-        files::rewrite_files_with(self.source_map(), &rw, &*self.file_io).unwrap();
+        files::rewrite_files_with(
+            self.compiler.source_map(),
+            &rw,
+            &mut self.applied_edits,
+            &*self.file_io,
+        )
+        .unwrap();
+        for undo in &mut self.undo_stack {
+            undo.applied_edits = None;
+        }
     }
 
     #[cfg_attr(feature = "profile", flame)]
@@ -518,6 +591,51 @@ impl RefactorState {
         result.unwrap()
     }
 
+    /// Save a snapshot of the current crate and marks, which `undo` can return to later.
+    fn checkpoint(&mut self, command: &str) {
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(UndoState {
+            command: command.to_owned(),
+            krate: self.krate.clone(),
+            marks: self.marks.clone(),
+            node_map: self.node_map.clone(),
+            parsed_nodes: self.parsed_nodes.clone(),
+            comment_map: self.disk_state.as_ref().map(|ds| ds.comment_map.clone()),
+            commands: self.commands.clone(),
+            applied_edits: Some(self.applied_edits.clone()),
+        });
+    }
+
+    /// Restore the state from before the most recent command, discarding its changes.  Returns
+    /// the undone command, or `None` if there is nothing to undo.
+    ///
+    /// `node_id_counter` is not rolled back, so nodes parsed after the undo never reuse the IDs
+    /// of discarded nodes.
+    pub fn undo(&mut self) -> Option<String> {
+        let undo = self.undo_stack.pop()?;
+        self.krate = undo.krate;
+        self.marks = undo.marks;
+        self.node_map = undo.node_map;
+        self.parsed_nodes = undo.parsed_nodes;
+        match (&mut self.disk_state, undo.comment_map) {
+            (Some(ds), Some(comment_map)) => ds.comment_map = comment_map,
+            // The crate was first parsed by the undone command.  Keep the parsed crate, since
+            // `krate` is `None` again and will be recreated from it.
+            (_, None) => {}
+            (None, Some(_)) => unreachable!("disk state was discarded without reloading"),
+        }
+        self.commands = undo.commands;
+        // If the crate was saved since the snapshot, the files may hold the undone command's
+        // changes, so the next save has to write every file again.
+        match undo.applied_edits {
+            Some(applied_edits) => self.applied_edits = applied_edits,
+            None => self.applied_edits.clear(),
+        }
+        Some(undo.command)
+    }
+
     pub fn clear_marks(&mut self) {
         self.marks.clear();
     }
@@ -530,12 +648,19 @@ impl RefactorState {
             .map(|s| s.as_ref().to_owned())
             .collect::<Vec<_>>();
         info!("running command: {} {:?}", cmd_name, args);
+        let mut cmd = self.cmd_reg.get_command(cmd_name, &args)?;
+        // `undo` restores a snapshot instead of making changes of its own, so it shouldn't take
+        // one.
+        if self.checkpoints && cmd_name != "undo" {
+            self.checkpoint(&iter::once(cmd_name).chain(args.iter().map(|s| s as &str))
+                .collect::<Vec<_>>()
+                .join(" "));
+        }
         self.commands.push(args.iter().fold(cmd_name.to_string(), |mut s, arg| {
             s.push_str(arg);
             s
         }));
 
-        let mut cmd = self.cmd_reg.get_command(cmd_name, &args)?;
         profile_start!(format!("Command {}", cmd_name));
         cmd.run(self);
         profile_end!(format!("Command {}", cmd_name));
@@ -888,6 +1013,32 @@ fn register_commit(reg: &mut Registry) {
     });
 }
 
+/// # `undo` Command
+///
+/// Usage: `undo`
+///
+/// Discard the changes made by the most recent command, restoring the crate and marks to their
+/// state before it ran.  Repeated `undo`s step further back, up to the last 16 commands.  The
+/// history is cleared whenever the crate is reloaded from disk, as by `commit`.
+///
+/// Commands can only be undone in interactive mode, or when the command list or pipeline
+/// includes `undo`, since taking the snapshots otherwise costs a copy of the crate per command.
+fn register_undo(reg: &mut Registry) {
+    reg.register("undo", |_args| {
+        Box::new(FuncCommand(|rs: &mut RefactorState| {
+            match rs.undo() {
+                Some(cmd) => info!("undid command: {}", cmd),
+                None if !rs.checkpoints => {
+                    warn!("can't undo: snapshots are only taken in interactive mode, or when \
+                           the commands include `undo`")
+                }
+                None => warn!("nothing to undo"),
+            }
+        }))
+    });
+}
+
 pub fn register_commands(reg: &mut Registry) {
     register_commit(reg);
+    register_undo(reg);
 }
@@ -0,0 +1,218 @@
+//! Programmatic interface to the refactoring engine.
+//!
+//! `Refactor` runs refactoring commands on a crate without going through the command line.
+//! Instead of writing the rewritten source to disk, it returns the changes as a list of
+//! `TextEdit`s:
+//!
+//! ```ignore
+//!     let edits = Refactor::new(RustcArgSource::Cargo(CargoTarget::Lib))
+//!         .run(|state| {
+//!             state.run("rename_items_regex", &["foo", "bar"])?;
+//!             state.transform_crate(Phase::Phase2, |st, _cx| {
+//!                 println!("{} top-level items", st.krate().module.items.len());
+//!             }).map_err(|_| "compilation failed".to_owned())
+//!         })?;
+//! ```
+//!
+//! The closure passed to `run` gets the full `RefactorState`, so it can run commands, inspect or
+//! modify the AST with `transform_crate`, and read or set marks.
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use syntax::ast::NodeId;
+use syntax::symbol::Symbol;
+
+use crate::command::{RefactorState, Registry};
+use crate::driver;
+use crate::file_io::FileIO;
+use crate::RustcArgSource;
+use c2rust_ast_builder::IntoSymbol;
+
+/// A change to a single source file: the bytes `lo .. hi` of the original file are replaced with
+/// `new_text`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TextEdit {
+    /// The file, as named by the compiler.  Newly created files have an empty original text.
+    pub file: PathBuf,
+    pub lo: usize,
+    pub hi: usize,
+    /// The line number (1-based) of `lo` in the original file.
+    pub line: usize,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+/// A `FileIO` that keeps rewritten files in memory instead of writing them out.
+struct MemoryFileIO {
+    files: Mutex<HashMap<PathBuf, String>>,
+}
+
+impl MemoryFileIO {
+    fn new() -> MemoryFileIO {
+        MemoryFileIO {
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Compute the edits that turn the files on disk into their rewritten versions.
+    fn edits(&self) -> io::Result<Vec<TextEdit>> {
+        let files = self.files.lock().unwrap();
+        let mut paths = files.keys().collect::<Vec<_>>();
+        paths.sort();
+
+        let mut edits = Vec::new();
+        for path in paths {
+            let old = if path.exists() {
+                fs::read_to_string(path)?
+            } else {
+                String::new()
+            };
+            diff_edits(path, &old, &files[path], &mut edits);
+        }
+        Ok(edits)
+    }
+}
+
+impl FileIO for MemoryFileIO {
+    fn file_exists(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path) || fs::metadata(path).is_ok()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        if let Some(s) = self.files.lock().unwrap().get(path) {
+            return Ok(s.clone());
+        }
+        fs::read_to_string(path)
+    }
+
+    fn write_file(&self, path: &Path, s: &str) -> io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_owned(), s.to_owned());
+        Ok(())
+    }
+}
+
+/// Compute line-granularity edits between `old` and `new`, appending them to `edits`.
+pub(crate) fn diff_edits(path: &Path, old: &str, new: &str, edits: &mut Vec<TextEdit>) {
+    // Byte offsets and line number of the current position in `old` and `new`.
+    let mut old_pos = 0;
+    let mut new_pos = 0;
+    let mut line = 1;
+    // Start of the pending edit in `old` and `new`, if there is one.
+    let mut start: Option<(usize, usize, usize)> = None;
+
+    let mut flush = |start: &mut Option<(usize, usize, usize)>, old_pos: usize, new_pos: usize| {
+        if let Some((lo, new_lo, line)) = start.take() {
+            let hi = old_pos.min(old.len());
+            let new_hi = new_pos.min(new.len());
+            edits.push(TextEdit {
+                file: path.to_owned(),
+                lo,
+                hi,
+                line,
+                old_text: old[lo..hi].to_owned(),
+                new_text: new[new_lo..new_hi].to_owned(),
+            });
+        }
+    };
+
+    for r in diff::lines(old, new) {
+        match r {
+            diff::Result::Both(l, r) => {
+                flush(&mut start, old_pos, new_pos);
+                old_pos += l.len() + 1;
+                new_pos += r.len() + 1;
+                line += 1;
+            }
+            diff::Result::Left(l) => {
+                start.get_or_insert((old_pos, new_pos, line));
+                old_pos += l.len() + 1;
+                line += 1;
+            }
+            diff::Result::Right(r) => {
+                start.get_or_insert((old_pos, new_pos, line));
+                new_pos += r.len() + 1;
+            }
+        }
+    }
+    flush(&mut start, old_pos, new_pos);
+}
+
+/// A refactoring session on a crate, configured with the builder methods.
+pub struct Refactor {
+    rustc_args: RustcArgSource,
+    marks: HashSet<(NodeId, Symbol)>,
+    extra_commands: Vec<Box<dyn Fn(&mut Registry)>>,
+}
+
+impl Refactor {
+    /// Refactor the crate built by the rustc invocations described by `rustc_args`.  Use
+    /// `RustcArgSource::Cargo` to refactor the cargo project in the current directory.
+    pub fn new(rustc_args: RustcArgSource) -> Refactor {
+        Refactor {
+            rustc_args,
+            marks: HashSet::new(),
+            extra_commands: Vec::new(),
+        }
+    }
+
+    /// Mark node `id` with `label` before running any commands.
+    pub fn mark<S: IntoSymbol>(mut self, id: NodeId, label: S) -> Refactor {
+        self.marks.insert((id, label.into_symbol()));
+        self
+    }
+
+    /// Register additional commands, alongside the built-in ones.
+    pub fn register_commands<F>(mut self, f: F) -> Refactor
+    where
+        F: Fn(&mut Registry) + 'static,
+    {
+        self.extra_commands.push(Box::new(f));
+        self
+    }
+
+    /// Run `f` on each crate, and return the resulting changes.  The source files are not
+    /// modified.  When refactoring several cargo targets, each one is refactored independently,
+    /// starting from the source on disk.
+    pub fn run<F>(&self, mut f: F) -> Result<Vec<TextEdit>, String>
+    where
+        F: FnMut(&mut RefactorState) -> Result<(), String>,
+    {
+        crate::set_compiler_env();
+
+        let target_args = crate::get_rustc_arg_strings(self.rustc_args.clone());
+        if target_args.is_empty() {
+            return Err("could not derive any rustc invocations for refactoring".to_owned());
+        }
+
+        let mut edits = Vec::new();
+        for rustc_args in target_args {
+            if let Some(ref cwd) = rustc_args.cwd {
+                env::set_current_dir(cwd)
+                    .map_err(|e| format!("failed to change directory to {:?}: {}", cwd, e))?;
+            }
+
+            let mut cmd_reg = crate::builtin_registry();
+            for register in &self.extra_commands {
+                register(&mut cmd_reg);
+            }
+
+            let config = driver::create_config(&rustc_args.args);
+            let file_io = Arc::new(MemoryFileIO::new());
+            let marks = self.marks.clone();
+            let f = &mut f;
+            rustc_driver::catch_fatal_errors(|| {
+                driver::run_refactoring(config, cmd_reg, file_io.clone(), marks, |mut state| {
+                    f(&mut state)?;
+                    state.save_crate();
+                    Ok(())
+                })
+            }).map_err(|_| "the compiler reported a fatal error".to_owned())??;
+
+            edits.extend(file_io.edits().map_err(|e| format!("failed to read source: {}", e))?);
+        }
+        Ok(edits)
+    }
+}
@@ -61,6 +61,9 @@ pub enum OutputMode {
     PrintDiff,
     Json,
     Marks,
+    /// Print a diff of each command's changes, and record them in `changes.json`, without
+    /// writing any source files.
+    DryRun,
 }
 
 impl OutputMode {
@@ -83,11 +86,17 @@ impl OutputMode {
     fn write_marks_json(self) -> bool {
         self == OutputMode::Marks
     }
+
+    fn write_changes_json(self) -> bool {
+        self == OutputMode::DryRun
+    }
 }
 
 struct RealState {
     rewrite_counter: usize,
     rewrites_json: Vec<JsonValue>,
+    changes_json: Vec<JsonValue>,
+    command: Option<String>,
     file_state: HashMap<PathBuf, String>,
 }
 
@@ -96,6 +105,8 @@ impl RealState {
         RealState {
             rewrite_counter: 0,
             rewrites_json: Vec::new(),
+            changes_json: Vec::new(),
+            command: None,
             file_state: HashMap::new(),
         }
     }
@@ -113,6 +124,11 @@ impl RealFileIO {
             state: Mutex::new(RealState::new()),
         }
     }
+
+    /// Set the command that subsequent changes are attributed to in `changes.json`.
+    pub fn set_command(&self, command: String) {
+        self.state.lock().unwrap().command = Some(command);
+    }
 }
 
 impl FileIO for RealFileIO {
@@ -130,6 +146,14 @@ impl FileIO for RealFileIO {
                 s,
             )?;
         }
+        if self
+            .output_modes
+            .iter()
+            .any(|&mode| mode.write_changes_json())
+        {
+            let s = json::stringify_pretty(JsonValue::Array(state.changes_json.clone()), 2);
+            fs::write(Path::new("changes.json"), s)?;
+        }
         state.rewrite_counter += 1;
         Ok(())
     }
@@ -160,6 +184,25 @@ impl FileIO for RealFileIO {
                     println!("+++ new/{}", path.display());
                     rewrite::files::print_diff(&old_s, s);
                 }
+                OutputMode::DryRun => {
+                    let old_s = self.read_file(path)?;
+                    if old_s == s {
+                        continue;
+                    }
+                    let diff = rewrite::files::format_diff(&old_s, s);
+                    println!();
+                    println!("--- a/{}", path.display());
+                    println!("+++ b/{}", path.display());
+                    print!("{}", diff);
+
+                    let mut state = self.state.lock().unwrap();
+                    let change = json::object! {
+                        "command" => state.command.clone(),
+                        "file" => path.display().to_string(),
+                        "diff" => diff,
+                    };
+                    state.changes_json.push(change);
+                }
                 OutputMode::Json => {}  // Handled in end_rewrite
                 OutputMode::Marks => {} // Handled in save_marks
             }
@@ -381,7 +381,7 @@ impl<'ast> Visitor<'ast> for CollectSpanVisitor {
     }
 }
 
-fn collect_spans<T: Visit>(target: &T, ids: HashSet<NodeId>) -> HashMap<NodeId, Span> {
+pub(crate) fn collect_spans<T: Visit>(target: &T, ids: HashSet<NodeId>) -> HashMap<NodeId, Span> {
     let mut v = CollectSpanVisitor {
         ids,
         spans: HashMap::new(),
@@ -39,6 +39,8 @@ pub mod command;
 pub mod file_io;
 pub mod interact;
 pub mod plugin;
+pub mod api;
+pub mod engine;
 
 pub mod mark_adjust;
 pub mod print_spans;
@@ -46,6 +48,9 @@ pub mod select;
 pub mod transform;
 
 mod context;
+mod lsp;
+mod pipeline;
+mod repl;
 mod scripting;
 
 use cargo::core::manifest::TargetKind;
@@ -53,6 +58,7 @@ use cargo::util::paths;
 use rustc_interface::interface;
 use std::collections::HashSet;
 use std::env;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::str::{self, FromStr};
 use std::sync::Arc;
@@ -137,6 +143,12 @@ pub struct Options {
 
     pub plugins: Vec<String>,
     pub plugin_dirs: Vec<String>,
+
+    /// Run the commands one at a time from an interactive prompt, previewing each one's changes.
+    pub interactive: bool,
+
+    /// Format rewritten items with `rustfmt`.
+    pub format: bool,
 }
 
 /// Try to find the rustup installation that provides the rustc at the given path.  The input path
@@ -338,12 +350,8 @@ fn rebuild() {
     ops::compile(&ws, &compile_opts).expect("Could not rebuild crate");
 }
 
-#[cfg_attr(feature = "profile", flame)]
-pub fn lib_main(opts: Options) -> interface::Result<()> {
-    env_logger::init();
-    rustc_driver::install_ice_hook();
-    info!("Begin refactoring");
-
+/// Set up the environment for the compiler invocations made while refactoring.
+fn set_compiler_env() {
     // Make sure we compile with the toolchain version that the refactoring tool
     // is built against.
     if let Some(toolchain_ver) = option_env!("RUSTUP_TOOLCHAIN") {
@@ -354,6 +362,29 @@ pub fn lib_main(opts: Options) -> interface::Result<()> {
     let mut rustflags = env::var_os("RUSTFLAGS").unwrap_or_default();
     rustflags.push(" -Awarnings");
     env::set_var("RUSTFLAGS", rustflags);
+}
+
+/// Build a `Registry` containing all the built-in commands.
+pub fn builtin_registry() -> command::Registry {
+    let mut cmd_reg = command::Registry::new();
+    transform::register_commands(&mut cmd_reg);
+    mark_adjust::register_commands(&mut cmd_reg);
+    pick_node::register_commands(&mut cmd_reg);
+    print_spans::register_commands(&mut cmd_reg);
+    select::register_commands(&mut cmd_reg);
+    analysis::register_commands(&mut cmd_reg);
+    reflect::register_commands(&mut cmd_reg);
+    command::register_commands(&mut cmd_reg);
+    cmd_reg
+}
+
+#[cfg_attr(feature = "profile", flame)]
+pub fn lib_main(opts: Options) -> interface::Result<()> {
+    env_logger::init();
+    rustc_driver::install_ice_hook();
+    info!("Begin refactoring");
+
+    set_compiler_env();
 
     rustc_driver::catch_fatal_errors(move || main_impl(opts)).and_then(|x| x)
 }
@@ -366,6 +397,11 @@ fn main_impl(opts: Options) -> interface::Result<()> {
             return Err(rustc_errors::ErrorReported);
         }
     }
+    if opts.commands.len() == 1 && opts.commands[0].name == "pipeline" {
+        if !pipeline::validate_command(&opts.commands[0]) {
+            return Err(rustc_errors::ErrorReported);
+        }
+    }
 
     let target_args = get_rustc_arg_strings(opts.rustc_args.clone());
     if target_args.is_empty() {
@@ -433,22 +469,23 @@ fn main_impl(opts: Options) -> interface::Result<()> {
             });
         }
 
-        let mut cmd_reg = command::Registry::new();
-        transform::register_commands(&mut cmd_reg);
-        mark_adjust::register_commands(&mut cmd_reg);
-        pick_node::register_commands(&mut cmd_reg);
-        print_spans::register_commands(&mut cmd_reg);
-        select::register_commands(&mut cmd_reg);
-        analysis::register_commands(&mut cmd_reg);
-        reflect::register_commands(&mut cmd_reg);
-        command::register_commands(&mut cmd_reg);
-
+        let mut cmd_reg = builtin_registry();
         plugin::load_plugins(&opts.plugin_dirs, &opts.plugins, &mut cmd_reg);
 
         let config = driver::create_config(&rustc_args.args);
 
-        if opts.commands.len() == 1 && opts.commands[0].name == "interact" {
+        if opts.interactive {
+            repl::run_repl(
+                config,
+                cmd_reg,
+                opts.rewrite_modes.clone(),
+                marks,
+                &opts.commands,
+            );
+        } else if opts.commands.len() == 1 && opts.commands[0].name == "interact" {
             interact::interact_command(&opts.commands[0].args, config, cmd_reg);
+        } else if opts.commands.len() == 1 && opts.commands[0].name == "lsp" {
+            lsp::run_server(config, cmd_reg);
         } else if opts.commands.len() == 1 && opts.commands[0].name == "script" {
             scripting::run_lua_file(
                 Path::new(&opts.commands[0].args[0]),
@@ -456,13 +493,38 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                 cmd_reg,
                 opts.rewrite_modes.clone(),
             ).expect("Error loading user script");
+        } else if opts.commands.len() == 1 && opts.commands[0].name == "pipeline" {
+            let result = pipeline::run_pipeline(
+                Path::new(&opts.commands[0].args[0]),
+                config,
+                cmd_reg,
+                opts.rewrite_modes.clone(),
+                marks,
+            );
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
         } else {
             let file_io = Arc::new(file_io::RealFileIO::new(opts.rewrite_modes.clone()));
-            driver::run_refactoring(config, cmd_reg, file_io, marks, |mut state| {
+            // In dry-run mode, we save after every command, so that each command's changes are
+            // reported separately.
+            let dry_run = opts.rewrite_modes.contains(&file_io::OutputMode::DryRun);
+            driver::run_refactoring(config, cmd_reg, file_io.clone(), marks, |mut state| {
+                state.set_format(opts.format);
+                state.set_checkpoints(opts.commands.iter().any(|cmd| cmd.name == "undo"));
                 for cmd in opts.commands.clone() {
-                    if &cmd.name == "interact" {
-                        panic!("`interact` must be the only command");
+                    if &cmd.name == "interact" || &cmd.name == "pipeline" || &cmd.name == "lsp" {
+                        panic!("`{}` must be the only command", cmd.name);
                     } else {
+                        if dry_run {
+                            let desc = iter::once(&cmd.name).chain(&cmd.args)
+                                .map(|s| s as &str)
+                                .collect::<Vec<_>>()
+                                .join(" ");
+                            println!("==== {} ====", desc);
+                            file_io.set_command(desc);
+                        }
                         match state.run(&cmd.name, &cmd.args) {
                             Ok(_) => {}
                             Err(e) => {
@@ -470,10 +532,15 @@ fn main_impl(opts: Options) -> interface::Result<()> {
                                 std::process::exit(1);
                             }
                         }
+                        if dry_run {
+                            state.save_crate();
+                        }
                     }
                 }
 
-                state.save_crate();
+                if !dry_run {
+                    state.save_crate();
+                }
             });
         }
 
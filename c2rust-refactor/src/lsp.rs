@@ -0,0 +1,446 @@
+//! Language Server Protocol frontend, for offering refactoring commands as editor code actions.
+//!
+//! `c2rust refactor lsp -- RUSTC_ARGS` runs a language server on stdin and stdout.  For each
+//! selection, the server offers code actions that run a refactoring command with the innermost
+//! item, statement, and expression containing the selection marked `target`.  The command's
+//! changes are sent to the editor as a `workspace/applyEdit` request, so nothing is written to
+//! disk by the server itself.
+//!
+//! The offered commands default to `DEFAULT_ACTIONS`.  The client can replace the list by
+//! passing `{"commands": ["CMD ARGS...", ...]}` as its `initializationOptions`.
+use json::{self, object, JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rustc_data_structures::sync::Lrc;
+use rustc_interface::interface;
+use syntax::source_map::{SourceFile, SourceMap, Span};
+use syntax_pos::hygiene::SyntaxContext;
+use syntax_pos::{BytePos, FileName};
+
+use crate::command::{self, RefactorState};
+use crate::driver::{self, Phase};
+use crate::engine::{diff_edits, TextEdit};
+use crate::file_io::FileIO;
+use crate::pick_node::{pick_node_containing, NodeKind};
+
+/// The name of the LSP command used to run refactoring commands.
+const REFACTOR_COMMAND: &str = "c2rust.refactor";
+
+/// Commands offered as code actions when the client doesn't provide its own list.
+const DEFAULT_ACTIONS: &[&str] = &["convert_format_args", "ptr_to_ref", "retcode_to_result"];
+
+/// Kinds of nodes marked when running a command on a selection.
+const SELECTION_KINDS: &[NodeKind] = &[NodeKind::ItemLike, NodeKind::Stmt, NodeKind::Expr];
+
+/// A `FileIO` that reads the editor's open documents in place of the files on disk, and records
+/// the files rewritten by a command instead of writing them out.
+struct LspFileIO {
+    /// Text of each document open in the editor, by canonical path.
+    documents: Mutex<HashMap<PathBuf, String>>,
+    /// Old and new text of each file written since the last `take_written`, by canonical path.
+    written: Mutex<HashMap<PathBuf, (String, String)>>,
+}
+
+impl LspFileIO {
+    fn new() -> LspFileIO {
+        LspFileIO {
+            documents: Mutex::new(HashMap::new()),
+            written: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn take_written(&self) -> HashMap<PathBuf, (String, String)> {
+        let mut written = self.written.lock().unwrap();
+        written.drain().collect()
+    }
+}
+
+/// Canonicalize `path`, if it exists.  New files are left as they are.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+impl FileIO for LspFileIO {
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        if let Some(s) = self.documents.lock().unwrap().get(&canonical(path)) {
+            return Ok(s.clone());
+        }
+        fs::read_to_string(path)
+    }
+
+    fn write_file(&self, path: &Path, s: &str) -> io::Result<()> {
+        let old = self.read_file(path).unwrap_or_default();
+        self.written.lock().unwrap().insert(canonical(path), (old, s.to_owned()));
+        Ok(())
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read one JSON-RPC message.  Returns `None` at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<JsonValue>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let header = "Content-Length:";
+        if line.starts_with(header) {
+            let n = line[header.len()..].trim().parse::<usize>()
+                .map_err(|e| invalid_data(format!("bad Content-Length: {}", e)))?;
+            len = Some(n);
+        }
+    }
+
+    let len = len.ok_or_else(|| invalid_data("missing Content-Length header".to_owned()))?;
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf)?;
+    let s = String::from_utf8(buf).map_err(|e| invalid_data(e.to_string()))?;
+    json::parse(&s).map(Some).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn write_message<W: Write>(out: &mut W, msg: &JsonValue) -> io::Result<()> {
+    let s = msg.dump();
+    write!(out, "Content-Length: {}\r\n\r\n{}", s.len(), s)?;
+    out.flush()
+}
+
+fn uri_to_path(uri: &str) -> Result<PathBuf, String> {
+    let scheme = "file://";
+    if !uri.starts_with(scheme) {
+        return Err(format!("unsupported URI `{}`", uri));
+    }
+
+    let mut bytes = Vec::new();
+    let mut iter = uri[scheme.len()..].bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hex = iter.next().into_iter().chain(iter.next()).collect::<Vec<_>>();
+        let decoded = String::from_utf8(hex).ok()
+            .and_then(|h| u8::from_str_radix(&h, 16).ok())
+            .ok_or_else(|| format!("bad escape in URI `{}`", uri))?;
+        bytes.push(decoded);
+    }
+    String::from_utf8(bytes)
+        .map(PathBuf::from)
+        .map_err(|_| format!("URI `{}` is not valid UTF-8", uri))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+fn lsp_position(line: usize, character: usize) -> JsonValue {
+    object! {
+        "line" => line,
+        "character" => character
+    }
+}
+
+/// Convert a `TextEdit` to an LSP `TextEdit`.  `diff_edits` produces edits that start at the
+/// beginning of a line, so only the end position needs any work.
+fn lsp_edit(edit: &TextEdit) -> JsonValue {
+    let start_line = edit.line - 1;
+    let end_line = start_line + edit.old_text.matches('\n').count();
+    let last_line = edit.old_text.rsplit('\n').next().unwrap_or("");
+    let end_character = last_line.encode_utf16().count();
+    object! {
+        "range" => object! {
+            "start" => lsp_position(start_line, 0),
+            "end" => lsp_position(end_line, end_character)
+        },
+        "newText" => edit.new_text.clone()
+    }
+}
+
+/// Find the source file for the canonical path `path`.
+fn find_source_file(sm: &SourceMap, path: &Path) -> Option<Lrc<SourceFile>> {
+    sm.files().iter()
+        .find(|sf| match sf.name {
+            FileName::Real(ref p) => canonical(p) == path,
+            _ => false,
+        })
+        .cloned()
+}
+
+/// Convert an LSP position (a line and a UTF-16 offset within the line) to a `BytePos`.
+fn byte_pos(sf: &SourceFile, pos: &JsonValue) -> Result<BytePos, String> {
+    let (line, character) = match (pos["line"].as_usize(), pos["character"].as_usize()) {
+        (Some(line), Some(character)) => (line, character),
+        _ => return Err(format!("bad position {}", pos.dump())),
+    };
+    if line >= sf.lines.len() {
+        return Ok(sf.end_pos);
+    }
+
+    let text = sf.get_line(line).ok_or_else(|| format!("line {} is unavailable", line + 1))?;
+    let mut units = 0;
+    let mut offset = text.len();
+    for (i, c) in text.char_indices() {
+        if units >= character {
+            offset = i;
+            break;
+        }
+        units += c.len_utf16();
+    }
+    let (lo, _) = sf.line_bounds(line);
+    Ok(lo + BytePos(offset as u32))
+}
+
+struct Server {
+    state: RefactorState,
+    io: Arc<LspFileIO>,
+    /// The commands to offer as code actions, each as a command name followed by its arguments.
+    actions: Vec<Vec<String>>,
+    next_request_id: usize,
+}
+
+impl Server {
+    fn send(&self, msg: &JsonValue) -> io::Result<()> {
+        let out = io::stdout();
+        let mut out = out.lock();
+        write_message(&mut out, msg)
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        while let Some(msg) = read_message(&mut input)? {
+            // Messages without a method are responses to our `workspace/applyEdit` requests.
+            let method = match msg["method"].as_str() {
+                Some(method) => method.to_owned(),
+                None => continue,
+            };
+            if method == "exit" {
+                break;
+            }
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.handle(&method, &msg["params"])
+            }));
+            let result = match result {
+                Ok(result) => result,
+                Err(e) => Err(match e.downcast_ref::<String>() {
+                    Some(s) => s.clone(),
+                    None => "an error occurred of unknown type".to_owned(),
+                }),
+            };
+
+            let id = &msg["id"];
+            if id.is_null() {
+                // Notifications get no response.
+                if let Err(e) = result {
+                    warn!("{}: {}", method, e);
+                }
+                continue;
+            }
+            let response = match result {
+                Ok(result) => object! {
+                    "jsonrpc" => "2.0",
+                    "id" => id.clone(),
+                    "result" => result
+                },
+                Err(e) => object! {
+                    "jsonrpc" => "2.0",
+                    "id" => id.clone(),
+                    "error" => object! {
+                        "code" => -32603,
+                        "message" => e
+                    }
+                },
+            };
+            self.send(&response)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, method: &str, params: &JsonValue) -> Result<JsonValue, String> {
+        match method {
+            "initialize" => {
+                let commands = &params["initializationOptions"]["commands"];
+                if commands.is_array() {
+                    self.actions = commands.members()
+                        .filter_map(|c| c.as_str())
+                        .map(|c| c.split_whitespace().map(|s| s.to_owned()).collect::<Vec<_>>())
+                        .filter(|words| !words.is_empty())
+                        .collect();
+                }
+                Ok(object! {
+                    "capabilities" => object! {
+                        // Full document sync
+                        "textDocumentSync" => 1,
+                        "codeActionProvider" => true,
+                        "executeCommandProvider" => object! {
+                            "commands" => vec![REFACTOR_COMMAND]
+                        }
+                    },
+                    "serverInfo" => object! {
+                        "name" => "c2rust-refactor"
+                    }
+                })
+            }
+            "initialized" | "shutdown" => Ok(JsonValue::Null),
+
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                let path = canonical(&uri_to_path(doc["uri"].as_str().unwrap_or(""))?);
+                let text = doc["text"].as_str().unwrap_or("").to_owned();
+                self.io.documents.lock().unwrap().insert(path, text);
+                Ok(JsonValue::Null)
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let path = canonical(&uri_to_path(uri)?);
+                // With full document sync, the last change holds the entire new text.
+                let changes = &params["contentChanges"];
+                if let Some(text) = changes[changes.len().saturating_sub(1)]["text"].as_str() {
+                    self.io.documents.lock().unwrap().insert(path, text.to_owned());
+                }
+                Ok(JsonValue::Null)
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let path = canonical(&uri_to_path(uri)?);
+                self.io.documents.lock().unwrap().remove(&path);
+                Ok(JsonValue::Null)
+            }
+
+            "textDocument/codeAction" => {
+                let uri = params["textDocument"]["uri"].clone();
+                let range = params["range"].clone();
+                let actions = self.actions.iter()
+                    .map(|words| {
+                        let title = format!("c2rust: {}", words.join(" "));
+                        object! {
+                            "title" => title.clone(),
+                            "command" => object! {
+                                "title" => title,
+                                "command" => REFACTOR_COMMAND,
+                                "arguments" => vec![object! {
+                                    "uri" => uri.clone(),
+                                    "range" => range.clone(),
+                                    "command" => words[0].clone(),
+                                    "args" => words[1..].to_vec()
+                                }]
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Ok(actions.into())
+            }
+
+            "workspace/executeCommand" => {
+                if params["command"].as_str() != Some(REFACTOR_COMMAND) {
+                    return Err(format!("unknown command {}", params["command"].dump()));
+                }
+                self.execute(&params["arguments"][0])?;
+                Ok(JsonValue::Null)
+            }
+
+            _ => Err(format!("unsupported method `{}`", method)),
+        }
+    }
+
+    /// Run a refactoring command on a selection, and send its changes to the client.
+    fn execute(&mut self, args: &JsonValue) -> Result<(), String> {
+        let path = canonical(&uri_to_path(args["uri"].as_str().unwrap_or(""))?);
+        let name = args["command"].as_str()
+            .ok_or_else(|| "missing refactoring command name".to_owned())?
+            .to_owned();
+        let cmd_args = args["args"].members()
+            .filter_map(|a| a.as_str())
+            .map(|a| a.to_owned())
+            .collect::<Vec<_>>();
+        let range = &args["range"];
+
+        // Reload to pick up any edits made in the editor since the last command.
+        self.state.load_crate();
+        self.state.clear_marks();
+        self.state.transform_crate(Phase::Phase2, |st, cx| -> Result<(), String> {
+            let sm = cx.session().source_map();
+            let sf = find_source_file(sm, &path)
+                .ok_or_else(|| format!("{} is not part of the crate", path.display()))?;
+            let lo = byte_pos(&sf, &range["start"])?;
+            let hi = byte_pos(&sf, &range["end"])?;
+            let selection = Span::new(lo, hi, SyntaxContext::root());
+            for &kind in SELECTION_KINDS {
+                if let Some(info) = pick_node_containing(&st.krate(), kind, selection) {
+                    st.add_mark(info.id, "target");
+                }
+            }
+            Ok(())
+        }).map_err(|_| "failed to run compiler".to_owned())??;
+
+        self.state.run(&name, &cmd_args)?;
+        self.io.take_written();
+        self.state.save_crate();
+
+        let mut written = self.io.take_written().into_iter().collect::<Vec<_>>();
+        written.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut changes = JsonValue::new_object();
+        for (path, (old, new)) in written {
+            let mut edits = Vec::new();
+            diff_edits(&path, &old, &new, &mut edits);
+            if !edits.is_empty() {
+                let uri = path_to_uri(&path);
+                changes[uri.as_str()] = edits.iter().map(lsp_edit).collect::<Vec<_>>().into();
+            }
+        }
+        if changes.is_empty() {
+            return Err(format!("`{}` made no changes", name));
+        }
+
+        let request = object! {
+            "jsonrpc" => "2.0",
+            "id" => self.next_request_id,
+            "method" => "workspace/applyEdit",
+            "params" => object! {
+                "label" => name,
+                "edit" => object! {
+                    "changes" => changes
+                }
+            }
+        };
+        self.next_request_id += 1;
+        self.send(&request).map_err(|e| e.to_string())
+    }
+}
+
+/// Run the language server until the client disconnects or sends `exit`.
+pub fn run_server(config: interface::Config, registry: command::Registry) {
+    let io = Arc::new(LspFileIO::new());
+    driver::run_refactoring(config, registry, io.clone(), HashSet::new(), |state| {
+        let mut server = Server {
+            state,
+            io,
+            actions: DEFAULT_ACTIONS.iter().map(|&c| vec![c.to_owned()]).collect(),
+            next_request_id: 0,
+        };
+        if let Err(e) = server.run() {
+            error!("language server failed: {}", e);
+        }
+    });
+}
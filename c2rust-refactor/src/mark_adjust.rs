@@ -1,7 +1,10 @@
 //! This module implements commands for manipulating the current set of marked nodes.
 use rustc::hir;
 use rustc::hir::def::{DefKind, Res};
+use json::{self, object};
 use rustc::ty::TyKind;
+use std::collections::HashMap;
+use std::fs;
 use std::str::FromStr;
 use syntax::ast;
 use syntax::ast::*;
@@ -12,6 +15,8 @@ use crate::ast_manip::{visit_nodes, Visit};
 use crate::command::CommandState;
 use crate::command::{DriverCommand, FuncCommand, RefactorState, Registry};
 use crate::driver::Phase;
+use crate::pick_node::NodeKind;
+use crate::select::filter::{iter_descendants, AnyNode};
 use crate::RefactorCtxt;
 use c2rust_ast_builder::IntoSymbol;
 
@@ -322,6 +327,169 @@ pub fn find_callers_command(st: &CommandState, cx: &RefactorCtxt, label: &str) {
     find_callers(&*st.krate(), st, cx, label);
 }
 
+/// Call `func` on every node in `krate` that the `select` language can refer to.
+fn for_each_node<F: FnMut(AnyNode)>(krate: &Crate, mut func: F) {
+    for item in &krate.module.items {
+        func(AnyNode::Item(item));
+        iter_descendants(AnyNode::Item(item), &mut func);
+    }
+}
+
+/// The location of a node in the source, which identifies it across sessions.
+struct NodeLoc {
+    file: String,
+    kind: &'static str,
+    /// Byte offsets within the file
+    lo: usize,
+    hi: usize,
+    line: usize,
+    text: String,
+}
+
+fn node_loc(cx: &RefactorCtxt, node: AnyNode) -> Option<NodeLoc> {
+    let span = node.span();
+    if span.is_dummy() || span.from_expansion() {
+        return None;
+    }
+    let sm = cx.session().source_map();
+    let lo = sm.lookup_byte_offset(span.lo());
+    let hi = sm.lookup_byte_offset(span.hi());
+    let src = lo.sf.src.as_ref()?;
+    Some(NodeLoc {
+        file: lo.sf.name.to_string(),
+        kind: node.kind().as_str(),
+        lo: lo.pos.0 as usize,
+        hi: hi.pos.0 as usize,
+        line: sm.lookup_char_pos(span.lo()).line,
+        text: src[lo.pos.0 as usize..hi.pos.0 as usize].to_owned(),
+    })
+}
+
+/// # `save_marks` Command
+///
+/// Usage: `save_marks FILE`
+///
+/// Marks: reads all
+///
+/// Write every mark to `FILE`, as JSON.  Each marked node is recorded by its file, kind, and
+/// source text, rather than its `NodeId`, so `load_marks` can find it again in a later session,
+/// even after unrelated edits to the source.  Marks on nodes with no source text of their own,
+/// such as nodes created by an earlier command in the same session, are skipped with a warning,
+/// so this is best run on a freshly loaded crate.
+pub fn save_marks(st: &CommandState, cx: &RefactorCtxt, path: &str) {
+    let mut labels: HashMap<NodeId, Vec<Symbol>> = HashMap::new();
+    for &(id, label) in st.marks().iter() {
+        labels.entry(id).or_insert_with(Vec::new).push(label);
+    }
+
+    let mut entries = Vec::new();
+    if let Some(crate_labels) = labels.remove(&CRATE_NODE_ID) {
+        entries.push((String::new(), 0, object! {
+            "kind" => "crate",
+            "labels" => crate_labels.iter().map(|l| l.to_string()).collect::<Vec<_>>(),
+        }));
+    }
+    let krate = st.krate();
+    for_each_node(&krate, |node| {
+        let node_labels = match labels.remove(&node.id()) {
+            Some(x) => x,
+            None => return,
+        };
+        let loc = match node_loc(cx, node) {
+            Some(x) => x,
+            None => {
+                warn!("can't save marks on {:?} node {:?}, which has no source text",
+                      node.kind(), node.id());
+                return;
+            }
+        };
+        let mut node_labels = node_labels.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        node_labels.sort();
+        entries.push((loc.file.clone(), loc.lo, object! {
+            "file" => loc.file,
+            "kind" => loc.kind,
+            "lo" => loc.lo,
+            "hi" => loc.hi,
+            "line" => loc.line,
+            "text" => loc.text,
+            "labels" => node_labels,
+        }));
+    });
+    for (id, _) in labels {
+        warn!("can't save marks on node {:?}, which was not found", id);
+    }
+
+    // Sort by position, so the file diffs well.
+    entries.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+    let js = object! {
+        "version" => 1,
+        "marks" => entries.into_iter().map(|(_, _, e)| e).collect::<Vec<_>>(),
+    };
+    fs::write(path, json::stringify_pretty(js, 2))
+        .unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+}
+
+/// # `load_marks` Command
+///
+/// Usage: `load_marks FILE`
+///
+/// Marks: sets the marks recorded in `FILE`
+///
+/// Restore the marks saved by `save_marks`.  Each saved node is matched to a node of the same
+/// kind, in the same file, with the same source text.  If several nodes match, the one closest to
+/// the saved position is used.  A warning is printed for each node that can no longer be found.
+pub fn load_marks(st: &CommandState, cx: &RefactorCtxt, path: &str) {
+    let src = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let js = json::parse(&src)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e));
+    if js["version"].as_u32() != Some(1) {
+        panic!("{}: unsupported version {}", path, js["version"]);
+    }
+
+    // Index the current nodes by file and kind.
+    let mut nodes: HashMap<(String, &'static str), Vec<(NodeLoc, NodeId)>> = HashMap::new();
+    let krate = st.krate();
+    for_each_node(&krate, |node| {
+        if let Some(loc) = node_loc(cx, node) {
+            let key = (loc.file.clone(), loc.kind);
+            nodes.entry(key).or_insert_with(Vec::new).push((loc, node.id()));
+        }
+    });
+
+    for entry in js["marks"].members() {
+        let labels = entry["labels"].members()
+            .filter_map(|l| l.as_str())
+            .map(|l| l.into_symbol())
+            .collect::<Vec<_>>();
+
+        let kind = entry["kind"].as_str().unwrap_or("");
+        let id = if kind == "crate" {
+            Some(CRATE_NODE_ID)
+        } else {
+            let file = entry["file"].as_str().unwrap_or("").to_owned();
+            let kind = NodeKind::from_str(kind).map_or("", |k| k.as_str());
+            let lo = entry["lo"].as_usize().unwrap_or(0);
+            let text = entry["text"].as_str().unwrap_or("");
+            nodes.get(&(file, kind)).and_then(|locs| {
+                locs.iter()
+                    .filter(|(loc, _)| loc.text == text)
+                    .min_by_key(|(loc, _)| (loc.lo as isize - lo as isize).abs())
+                    .map(|&(_, id)| id)
+            })
+        };
+
+        match id {
+            Some(id) => for label in labels {
+                st.add_mark(id, label);
+            },
+            None => warn!("can't find {} `{}` at {}:{}; its marks were not restored",
+                          kind, entry["text"].as_str().unwrap_or(""),
+                          entry["file"].as_str().unwrap_or(""), entry["line"]),
+        }
+    }
+}
+
 /// # `copy_marks` Command
 ///
 /// Usage: `copy_marks OLD_MARK NEW_MARK`
@@ -500,5 +668,19 @@ pub fn register_commands(reg: &mut Registry) {
         }))
     });
 
+    reg.register("save_marks", |args| {
+        let path = args[0].clone();
+        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+            save_marks(st, cx, &path);
+        }))
+    });
+
+    reg.register("load_marks", |args| {
+        let path = args[0].clone();
+        Box::new(DriverCommand::new(Phase::Phase2, move |st, cx| {
+            load_marks(st, cx, &path);
+        }))
+    });
+
     register_clear_marks(reg);
 }
@@ -271,10 +271,15 @@ impl FromStr for NodeKind {
 /// Select an AST node by its `BytePos` in the `SourceMap`.  Only nodes of the specified `kind` will
 /// be selected.
 pub fn pick_node(krate: &Crate, kind: NodeKind, pos: BytePos) -> Option<NodeInfo> {
+    pick_node_containing(krate, kind, Span::new(pos, pos, SyntaxContext::root()))
+}
+
+/// Select the innermost AST node of the specified `kind` whose span contains all of `target`.
+pub fn pick_node_containing(krate: &Crate, kind: NodeKind, target: Span) -> Option<NodeInfo> {
     let mut v = PickVisitor {
         node_info: None,
         kind,
-        target: Span::new(pos, pos, SyntaxContext::root()),
+        target,
     };
     krate.visit(&mut v);
 
@@ -0,0 +1,163 @@
+//! Refactoring pipelines, read from a configuration file.
+//!
+//! A pipeline lists the commands to run, in order, as an array of `step` tables:
+//!
+//! ```toml
+//! [[step]]
+//! select = "item(Foo);"
+//! command = "rename_struct"
+//! args = ["Bar"]
+//! checkpoint = true
+//!
+//! [[step]]
+//! command = "reorganize_definitions"
+//! ```
+//!
+//! Each step has the following keys:
+//!
+//!  * `command`: the name of the command to run.
+//!  * `args`: the command's arguments (default: none).
+//!  * `select`: a `select` expression to run before the command.  The matching nodes are marked
+//!    with the label `label` (default: `target`).
+//!  * `checkpoint`: if `true`, write out the crate after this step, using the selected rewrite
+//!    modes, and reload it before running the next step.  Reloading clears all marks.  If a later
+//!    step fails, the changes up to the last checkpoint have already been written.
+//!
+//! The pipeline is run with `c2rust refactor pipeline refactor.toml`.
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use rustc_interface::interface;
+use syntax::ast::NodeId;
+use syntax::symbol::Symbol;
+use toml::Value;
+
+use crate::command::{self, RefactorState};
+use crate::driver;
+use crate::file_io::{OutputMode, RealFileIO};
+use crate::Command;
+
+pub struct Step {
+    pub command: String,
+    pub args: Vec<String>,
+    pub select: Option<(String, String)>,
+    pub checkpoint: bool,
+}
+
+fn get_str(table: &toml::value::Table, key: &str, i: usize) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("step {}: `{}` must be a string", i + 1, key)),
+    }
+}
+
+fn parse_step(v: &Value, i: usize) -> Result<Step, String> {
+    let table = v.as_table()
+        .ok_or_else(|| format!("step {}: expected a table", i + 1))?;
+    for key in table.keys() {
+        if !["command", "args", "select", "label", "checkpoint"].contains(&(key as &str)) {
+            return Err(format!("step {}: unknown key `{}`", i + 1, key));
+        }
+    }
+
+    let command = get_str(table, "command", i)?
+        .ok_or_else(|| format!("step {}: missing `command`", i + 1))?;
+    let args = match table.get("args") {
+        None => Vec::new(),
+        Some(Value::Array(args)) => args.iter()
+            .map(|a| match a {
+                Value::String(s) => Ok(s.clone()),
+                // Allow `args = [3]` as shorthand for `args = ["3"]`.
+                Value::Integer(n) => Ok(n.to_string()),
+                _ => Err(format!("step {}: `args` must contain only strings", i + 1)),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err(format!("step {}: `args` must be an array", i + 1)),
+    };
+    let label = get_str(table, "label", i)?.unwrap_or_else(|| "target".to_owned());
+    let select = get_str(table, "select", i)?.map(|expr| (label, expr));
+    let checkpoint = match table.get("checkpoint") {
+        None => false,
+        Some(Value::Boolean(b)) => *b,
+        Some(_) => return Err(format!("step {}: `checkpoint` must be a boolean", i + 1)),
+    };
+
+    if ["interact", "script", "pipeline", "lsp"].contains(&(&command as &str)) {
+        return Err(format!("step {}: `{}` can't be used in a pipeline", i + 1, command));
+    }
+
+    Ok(Step { command, args, select, checkpoint })
+}
+
+/// Read and parse the pipeline in `path`.
+pub fn load_pipeline(path: &Path) -> Result<Vec<Step>, String> {
+    let src = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let v = src.parse::<Value>()
+        .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+    let steps = match v.get("step") {
+        Some(Value::Array(steps)) => steps,
+        Some(_) => return Err(format!("{}: `step` must be an array of tables", path.display())),
+        None => return Err(format!("{}: no steps found", path.display())),
+    };
+    steps.iter().enumerate().map(|(i, v)| parse_step(v, i)).collect()
+}
+
+/// Check that the pipeline named by the `pipeline` command can be loaded.  This runs before
+/// starting the compiler, so that mistakes in the pipeline file are reported quickly.
+pub fn validate_command(command: &Command) -> bool {
+    if command.args.len() != 1 {
+        error!("usage: pipeline FILE");
+        return false;
+    }
+    match load_pipeline(Path::new(&command.args[0])) {
+        Ok(_) => true,
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    }
+}
+
+fn run_step(state: &mut RefactorState, step: &Step) -> Result<(), String> {
+    if let Some((ref label, ref expr)) = step.select {
+        state.run("select", &[label, expr])?;
+    }
+    state.run(&step.command, &step.args)
+}
+
+pub fn run_pipeline(
+    path: &Path,
+    config: interface::Config,
+    registry: command::Registry,
+    rewrite_modes: Vec<OutputMode>,
+    marks: HashSet<(NodeId, Symbol)>,
+) -> Result<(), String> {
+    let steps = load_pipeline(path)?;
+    let io = Arc::new(RealFileIO::new(rewrite_modes));
+
+    driver::run_refactoring(config, registry, io, marks, |mut state| {
+        state.set_checkpoints(steps.iter().any(|step| step.command == "undo"));
+        let mut last_checkpoint = None;
+        for (i, step) in steps.iter().enumerate() {
+            info!("pipeline step {}: {} {:?}", i + 1, step.command, step.args);
+            if let Err(e) = run_step(&mut state, step) {
+                let saved = match last_checkpoint {
+                    Some(j) => format!("changes through step {} were saved", j + 1),
+                    None => "no changes were saved".to_owned(),
+                };
+                return Err(format!("step {} (`{}`) failed: {}; {}",
+                                   i + 1, step.command, e, saved));
+            }
+            if step.checkpoint && i + 1 < steps.len() {
+                state.save_crate();
+                state.load_crate();
+                last_checkpoint = Some(i);
+            }
+        }
+        state.save_crate();
+        Ok(())
+    })
+}
@@ -2,51 +2,77 @@
 //!
 //! See PLUGINS.txt for more details on plugins.
 use libc::{dlopen, dlsym, RTLD_LAZY};
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
 use std::ffi::CString;
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::api::PLUGIN_API_VERSION;
 use crate::command::Registry;
 
+/// Find the plugin named `name`.  A name containing a path separator or ending in the platform's
+/// library suffix is used as a path directly; otherwise, `libNAME.so` (or the platform's
+/// equivalent) is looked up in each directory of the search path.
+fn find_plugin(search_path: &[String], name: &str) -> Option<PathBuf> {
+    if name.contains('/') || name.ends_with(DLL_SUFFIX) {
+        let path = PathBuf::from(name);
+        return if path.exists() { Some(path) } else { None };
+    }
+
+    search_path.iter()
+        .map(|dir| Path::new(dir).join(format!("{}{}{}", DLL_PREFIX, name, DLL_SUFFIX)))
+        .find(|path| path.exists())
+}
+
 /// Find the named plugins in the search path, and pass `reg` to each of their `register_commands`
 /// entry points.
 pub fn load_plugins(search_path: &[String], plugins: &[String], reg: &mut Registry) {
     let sym_name = CString::new("register_commands").unwrap();
+    let version_sym_name = CString::new("c2rust_refactor_plugin_api_version").unwrap();
 
     for name in plugins {
         eprintln!("loading {}...", name);
-        let mut found = false;
-        for dir in search_path {
-            let path_str = format!("{}/lib{}.so", dir, name);
-            let path = Path::new(&path_str);
-            if path.exists() {
-                let c_path = CString::new(path_str.clone()).unwrap();
-                unsafe {
-                    let so = dlopen(c_path.as_ptr(), RTLD_LAZY);
-                    if so.is_null() {
-                        panic!("failed to open plugin `{}`", path_str);
-                    }
-                    let sym = dlsym(so, sym_name.as_ptr());
-                    if sym.is_null() {
-                        panic!(
-                            "failed to locate symbol `register_commands` in `{}`",
-                            path_str
-                        );
-                    }
-                    let f: fn(&mut Registry) = mem::transmute(sym);
-                    f(reg);
-                }
+        let path = match find_plugin(search_path, name) {
+            Some(x) => x,
+            None => panic!(
+                "plugin `{}` was not found in search path ({:?})",
+                name, search_path
+            ),
+        };
+        let path_str = path.display().to_string();
 
-                found = true;
-                break;
+        let c_path = CString::new(path_str.clone()).unwrap();
+        unsafe {
+            let so = dlopen(c_path.as_ptr(), RTLD_LAZY);
+            if so.is_null() {
+                panic!("failed to open plugin `{}`", path_str);
             }
-        }
 
-        if !found {
-            panic!(
-                "plugin `{}` was not found in search path ({:?})",
-                name, search_path
-            );
+            // Plugins built with `declare_plugin!` report the API version they were built
+            // against.  Older plugins don't, so we can only hope they're compatible.
+            let version_sym = dlsym(so, version_sym_name.as_ptr());
+            if version_sym.is_null() {
+                warn!("plugin `{}` does not declare its API version", path_str);
+            } else {
+                let version_fn: extern "C" fn() -> u32 = mem::transmute(version_sym);
+                let version = version_fn();
+                if version != PLUGIN_API_VERSION {
+                    panic!(
+                        "plugin `{}` was built for plugin API version {}, but this is version {}",
+                        path_str, version, PLUGIN_API_VERSION
+                    );
+                }
+            }
+
+            let sym = dlsym(so, sym_name.as_ptr());
+            if sym.is_null() {
+                panic!(
+                    "failed to locate symbol `register_commands` in `{}`",
+                    path_str
+                );
+            }
+            let f: fn(&mut Registry) = mem::transmute(sym);
+            f(reg);
         }
     }
 }
@@ -0,0 +1,401 @@
+//! Interactive command-line mode, for experimenting with refactoring commands.
+//!
+//! Commands are entered one at a time.  After each command, the changes it made to the source
+//! are shown as a diff, and the user decides whether to keep or discard them.  Accepted changes
+//! are only written out (using the normal rewrite modes) when the user asks for it with `write`.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rustc_interface::interface;
+use syntax::ast::{Crate, NodeId};
+use syntax::source_map::{SourceFile, SourceMap, Span};
+use syntax::symbol::Symbol;
+
+use crate::command::{self, RefactorState};
+use crate::driver::{self, Phase};
+use crate::file_io::{FileIO, OutputMode, RealFileIO};
+use crate::interact::collect_spans;
+use crate::rewrite::{files, TextRewrite};
+
+const HELP: &str = "\
+Commands:
+  CMD ARGS...   run a refactoring command, then preview its changes
+  marks         list the current marks
+  diff          show all accepted changes that haven't been written yet
+  undo          discard the most recently accepted command
+  write         write accepted changes using the selected rewrite modes
+  history       list the accepted commands
+  help          show this message
+  quit          exit (unwritten changes are discarded)
+
+Arguments are split on whitespace.  Use single or double quotes to pass an argument containing
+spaces, as in `select target 'crate; desc(fn);'`.";
+
+struct PreviewState {
+    /// When set, written files are recorded in `pending` instead of being passed to `real`.
+    previewing: bool,
+    /// The text of each file as of the last accepted command.
+    accepted: HashMap<PathBuf, String>,
+    /// The text of each file as rewritten during the current preview.
+    pending: HashMap<PathBuf, String>,
+}
+
+/// A `FileIO` that can capture the output of `save_crate` instead of writing it out, so that the
+/// REPL can show a diff before committing to the changes.
+struct PreviewFileIO {
+    real: RealFileIO,
+    state: Mutex<PreviewState>,
+}
+
+impl PreviewFileIO {
+    fn new(modes: Vec<OutputMode>) -> PreviewFileIO {
+        PreviewFileIO {
+            real: RealFileIO::new(modes),
+            state: Mutex::new(PreviewState {
+                previewing: false,
+                accepted: HashMap::new(),
+                pending: HashMap::new(),
+            }),
+        }
+    }
+
+    fn previewing(&self) -> bool {
+        self.state.lock().unwrap().previewing
+    }
+
+    fn set_previewing(&self, previewing: bool) {
+        self.state.lock().unwrap().previewing = previewing;
+    }
+
+    /// Get the last accepted text of `path`.
+    fn accepted_text(&self, path: &Path) -> io::Result<String> {
+        if let Some(s) = self.state.lock().unwrap().accepted.get(path) {
+            return Ok(s.clone());
+        }
+        self.real.read_file(path)
+    }
+
+    /// Print the difference between the accepted and pending text of each file.  Returns `true`
+    /// if any file changed.
+    fn print_pending_diff(&self) -> io::Result<bool> {
+        let pending = self.state.lock().unwrap().pending.clone();
+        print_diffs(pending, |path| self.accepted_text(path))
+    }
+
+    /// Print the difference between the original and accepted text of each file.  Returns `true`
+    /// if any file changed.
+    fn print_accepted_diff(&self) -> io::Result<bool> {
+        let accepted = self.state.lock().unwrap().accepted.clone();
+        print_diffs(accepted, |path| self.real.read_file(path))
+    }
+
+    fn accept_pending(&self) {
+        let mut state = self.state.lock().unwrap();
+        let pending = mem::replace(&mut state.pending, HashMap::new());
+        state.accepted.extend(pending);
+    }
+
+    fn discard_pending(&self) {
+        self.state.lock().unwrap().pending.clear();
+    }
+
+    /// Forget all accepted text, after it has been written out.
+    fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.accepted.clear();
+        state.pending.clear();
+    }
+}
+
+impl FileIO for PreviewFileIO {
+    fn end_rewrite(&self, sm: &SourceMap) -> io::Result<()> {
+        if self.previewing() {
+            return Ok(());
+        }
+        self.real.end_rewrite(sm)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        self.real.read_file(path)
+    }
+
+    fn write_file(&self, path: &Path, s: &str) -> io::Result<()> {
+        if self.previewing() {
+            let mut state = self.state.lock().unwrap();
+            state.pending.insert(path.to_owned(), s.to_owned());
+            return Ok(());
+        }
+        self.real.write_file(path, s)
+    }
+
+    fn save_rewrites(
+        &self,
+        sm: &SourceMap,
+        sf: &SourceFile,
+        rws: &[TextRewrite],
+        nodes: &[(Span, NodeId)],
+    ) -> io::Result<()> {
+        if self.previewing() {
+            return Ok(());
+        }
+        self.real.save_rewrites(sm, sf, rws, nodes)
+    }
+
+    fn save_marks(
+        &self,
+        krate: &Crate,
+        sm: &SourceMap,
+        node_id_map: &HashMap<NodeId, NodeId>,
+        marks: &HashSet<(NodeId, Symbol)>,
+    ) -> io::Result<()> {
+        if self.previewing() {
+            return Ok(());
+        }
+        self.real.save_marks(krate, sm, node_id_map, marks)
+    }
+}
+
+/// Print a diff for each file in `new` whose text differs from `old(path)`.
+fn print_diffs<F>(new: HashMap<PathBuf, String>, old: F) -> io::Result<bool>
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    let mut new = new.into_iter().collect::<Vec<_>>();
+    new.sort();
+
+    let mut changed = false;
+    for (path, new_s) in new {
+        let old_s = old(&path)?;
+        if old_s == new_s {
+            continue;
+        }
+        changed = true;
+        println!();
+        println!("--- old/{}", path.display());
+        println!("+++ new/{}", path.display());
+        files::print_diff(&old_s, &new_s);
+    }
+    Ok(changed)
+}
+
+/// Split a REPL input line into words, honoring single and double quotes.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut cur = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => cur.get_or_insert_with(String::new).push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                cur.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => words.extend(cur.take()),
+            None => cur.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_owned());
+    }
+    words.extend(cur);
+    Ok(words)
+}
+
+fn ask(prompt: &str) -> bool {
+    loop {
+        print!("{} [y/n] ", prompt);
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line).unwrap() == 0 {
+            return false;
+        }
+        match line.trim() {
+            "y" | "yes" => return true,
+            "n" | "no" => return false,
+            _ => {}
+        }
+    }
+}
+
+struct Repl {
+    state: RefactorState,
+    io: Arc<PreviewFileIO>,
+    /// Marks to restore when reloading the crate.
+    init_marks: HashSet<(NodeId, Symbol)>,
+    /// Commands accepted since the crate was last loaded.
+    history: Vec<Vec<String>>,
+}
+
+impl Repl {
+    /// Discard the effects of the most recent command.  This restores the state's snapshot if it
+    /// has one, and otherwise reloads the crate and replays the accepted commands.
+    fn discard_last(&mut self) {
+        if self.state.undo().is_some() {
+            return;
+        }
+        self.state.load_crate();
+        *self.state.marks_mut() = self.init_marks.clone();
+        for words in &self.history {
+            self.state.run(&words[0], &words[1..])
+                .expect("previously accepted command failed");
+        }
+    }
+
+    /// Rewrite the source into the preview buffers.
+    fn preview(&mut self) {
+        self.io.set_previewing(true);
+        self.state.save_crate();
+        self.io.set_previewing(false);
+    }
+
+    fn run_command(&mut self, words: Vec<String>) {
+        if let Err(e) = self.state.run(&words[0], &words[1..]) {
+            println!("error: {}", e);
+            return;
+        }
+
+        self.preview();
+        let changed = match self.io.print_pending_diff() {
+            Ok(changed) => changed,
+            Err(e) => {
+                println!("error: failed to read source: {}", e);
+                true
+            }
+        };
+        if !changed {
+            println!("(no changes to the source)");
+        }
+        if !changed || ask("accept these changes?") {
+            self.io.accept_pending();
+            self.history.push(words);
+        } else {
+            self.io.discard_pending();
+            self.discard_last();
+        }
+    }
+
+    fn print_marks(&mut self) {
+        let result = self.state.transform_crate(Phase::Phase2, |st, cx| {
+            let marks = st.marks();
+            let ids = marks.iter().map(|&(id, _)| id).collect();
+            let spans = collect_spans(&*st.krate(), ids);
+
+            let mut marks = marks.iter().copied().collect::<Vec<_>>();
+            marks.sort();
+            for (id, label) in marks {
+                let span = match spans.get(&id) {
+                    Some(&span) => span,
+                    None => {
+                        println!("{}:{} <unknown node>", id.as_usize(), label.as_str());
+                        continue;
+                    }
+                };
+                let sm = cx.session().source_map();
+                let snippet = sm.span_to_snippet(span).unwrap_or_default();
+                println!("{}:{} {}: {}", id.as_usize(), label.as_str(),
+                         sm.span_to_string(span), snippet.lines().next().unwrap_or(""));
+            }
+        });
+        if result.is_err() {
+            println!("error: failed to run compiler");
+        }
+    }
+
+    fn write(&mut self) {
+        self.state.save_crate();
+        // The written text may now be what the compiler reads back, so start over from it.
+        self.io.reset();
+        self.history.clear();
+        self.init_marks.clear();
+        self.state.load_crate();
+        println!("changes written; marks have been cleared");
+    }
+
+    fn run(&mut self) {
+        let stdin = io::stdin();
+        loop {
+            print!("refactor> ");
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap() == 0 {
+                println!();
+                break;
+            }
+
+            let words = match split_words(&line) {
+                Ok(words) => words,
+                Err(e) => {
+                    println!("error: {}", e);
+                    continue;
+                }
+            };
+            if words.is_empty() {
+                continue;
+            }
+
+            match &words[0] as &str {
+                "help" => println!("{}", HELP),
+                "quit" | "exit" => break,
+                "marks" => self.print_marks(),
+                "diff" => match self.io.print_accepted_diff() {
+                    Ok(true) => {}
+                    Ok(false) => println!("(no changes)"),
+                    Err(e) => println!("error: failed to read source: {}", e),
+                },
+                "undo" => match self.history.pop() {
+                    Some(words) => {
+                        println!("discarding `{}`", words.join(" "));
+                        self.io.reset();
+                        self.discard_last();
+                        self.preview();
+                        self.io.accept_pending();
+                    }
+                    None => println!("nothing to undo"),
+                },
+                "write" => self.write(),
+                "history" => for words in &self.history {
+                    println!("{}", words.join(" "));
+                },
+                "interact" | "script" | "lsp" => {
+                    println!("error: `{}` can't be run here", words[0])
+                }
+                _ => self.run_command(words),
+            }
+        }
+
+        if !self.history.is_empty() {
+            println!("discarding {} unwritten command(s)", self.history.len());
+        }
+    }
+}
+
+/// Run the interactive REPL.  The commands in `cmds` are run first, and previewed like the
+/// commands entered at the prompt.
+pub fn run_repl(
+    config: interface::Config,
+    registry: command::Registry,
+    rewrite_modes: Vec<OutputMode>,
+    marks: HashSet<(NodeId, Symbol)>,
+    cmds: &[crate::Command],
+) {
+    let io = Arc::new(PreviewFileIO::new(rewrite_modes));
+    driver::run_refactoring(config, registry, io.clone(), marks.clone(), |mut state| {
+        state.set_checkpoints(true);
+        let mut repl = Repl {
+            state,
+            io,
+            init_marks: marks,
+            history: Vec::new(),
+        };
+        for cmd in cmds {
+            let mut words = vec![cmd.name.clone()];
+            words.extend(cmd.args.iter().cloned());
+            repl.run_command(words);
+        }
+        println!("Type `help` for a list of commands.");
+        repl.run();
+    });
+}
@@ -0,0 +1,140 @@
+//! Flattening of `TextRewrite` trees into minimal per-file text edits.
+//!
+//! The rewriter describes its output as a tree of `TextRewrite`s, and a rewrite that had to fall
+//! back on the `print` strategy often covers a whole item even though only a few tokens of it
+//! actually changed.  Before writing anything out, we render each top-level rewrite to text and
+//! trim away the prefix and suffix it shares with the original source, so that the remaining
+//! `Edit`s cover only the text that really differs.
+//!
+//! `AppliedEdits` remembers the edits last written for each file.  Since every `save_crate`
+//! rewrites relative to the original source text, a file whose edits are unchanged since the
+//! previous save doesn't need to be rebuilt or written again.  This keeps command sequences that
+//! save after every step (dry runs, the REPL, and the editor integrations) from re-emitting
+//! every file they have touched so far.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Replacement of the original text in `lo .. hi` (byte offsets within a file) with `text`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Edit {
+    pub lo: usize,
+    pub hi: usize,
+    pub text: String,
+}
+
+/// A sorted, non-overlapping list of edits to a single file.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FileEdits {
+    edits: Vec<Edit>,
+}
+
+impl FileEdits {
+    pub fn new() -> FileEdits {
+        FileEdits { edits: Vec::new() }
+    }
+
+    /// Add an edit replacing `src[lo .. hi]` with `text`.  Edits must be pushed in order of
+    /// position.  The edit is shrunk to the part of `text` that differs from the original, and
+    /// dropped entirely if nothing differs.  An edit that touches the previous one is merged into
+    /// it.
+    pub fn push(&mut self, src: &str, lo: usize, hi: usize, text: &str) {
+        let old = &src[lo..hi];
+        let prefix = common_prefix_len(old, text);
+        let suffix = common_suffix_len(&old[prefix..], &text[prefix..]);
+        let (lo, hi) = (lo + prefix, hi - suffix);
+        let text = &text[prefix..text.len() - suffix];
+        if lo == hi && text.is_empty() {
+            return;
+        }
+
+        if let Some(prev) = self.edits.last_mut() {
+            assert!(
+                prev.hi <= lo,
+                "edits out of order: {:?} then {}..{}",
+                prev,
+                lo,
+                hi
+            );
+            if prev.hi == lo {
+                prev.hi = hi;
+                prev.text.push_str(text);
+                return;
+            }
+        }
+        self.edits.push(Edit {
+            lo,
+            hi,
+            text: text.to_owned(),
+        });
+    }
+
+    /// Apply the edits to `src`, which must be the text they were computed against.
+    pub fn apply(&self, src: &str) -> String {
+        let mut out = String::with_capacity(src.len());
+        let mut cur = 0;
+        for edit in &self.edits {
+            out.push_str(&src[cur..edit.lo]);
+            out.push_str(&edit.text);
+            cur = edit.hi;
+        }
+        out.push_str(&src[cur..]);
+        out
+    }
+}
+
+/// Length in bytes of the longest common prefix of `a` and `b` that ends on a char boundary.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    let mut len = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !a.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
+/// Length in bytes of the longest common suffix of `a` and `b` that starts on a char boundary.
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    let mut len = a
+        .bytes()
+        .rev()
+        .zip(b.bytes().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    while !a.is_char_boundary(a.len() - len) {
+        len -= 1;
+    }
+    len
+}
+
+/// The edits most recently written out for each file, relative to the file's original text.
+#[derive(Clone, Default)]
+pub struct AppliedEdits {
+    files: HashMap<PathBuf, FileEdits>,
+}
+
+impl AppliedEdits {
+    pub fn new() -> AppliedEdits {
+        AppliedEdits {
+            files: HashMap::new(),
+        }
+    }
+
+    /// Record `edits` as the current edits for `path`.  Returns `false` if they are the same as
+    /// the edits already written for that file, in which case there is nothing new to write.
+    pub fn update(&mut self, path: &Path, edits: FileEdits) -> bool {
+        let prev = self
+            .files
+            .entry(path.to_owned())
+            .or_insert_with(FileEdits::new);
+        if *prev == edits {
+            return false;
+        }
+        *prev = edits;
+        true
+    }
+
+    /// Forget all recorded edits.  This must be called whenever the original source text is
+    /// reloaded, since the recorded edits are relative to the old text.
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
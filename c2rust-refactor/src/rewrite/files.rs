@@ -1,17 +1,28 @@
 //! Code for applying `TextRewrite`s to the actual source files.
 use diff;
 use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
 use std::io;
+use std::slice;
 use syntax::source_map::{SourceFile, SourceMap};
 use syntax_pos::{BytePos, FileName};
 
 use crate::file_io::FileIO;
 use crate::rewrite::cleanup::cleanup_rewrites;
+use crate::rewrite::edits::{AppliedEdits, FileEdits};
 use crate::rewrite::{TextAdjust, TextRewrite};
 
 /// Apply a sequence of rewrites to the source code, handling the results by passing the new text
 /// to `callback` along with the `SourceFile` describing the original source file.
-pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) -> io::Result<()> {
+///
+/// Files whose minimized edits match those recorded in `applied` are left alone, so repeated
+/// saves only write the files that changed since the last one.
+pub fn rewrite_files_with(
+    cm: &SourceMap,
+    rw: &TextRewrite,
+    applied: &mut AppliedEdits,
+    io: &dyn FileIO,
+) -> io::Result<()> {
     let mut by_file = HashMap::new();
 
     for rw in &rw.rewrites {
@@ -45,12 +56,13 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) ->
 
         // TODO: do something with nodes
         io.save_rewrites(cm, &sf, &rewrites, &nodes)?;
-        let mut buf = String::new();
         let rewrites = cleanup_rewrites(cm, rewrites);
-        rewrite_range(cm, sf.start_pos, sf.end_pos, &rewrites, &mut |s| {
-            buf.push_str(s)
-        });
-        io.write_file(path, &buf)?;
+        let edits = file_edits(cm, &sf, &rewrites);
+        if !applied.update(path, edits.clone()) {
+            continue;
+        }
+        let src = source_text(&sf);
+        io.write_file(path, &edits.apply(src))?;
     }
 
     io.end_rewrite(cm)?;
@@ -58,6 +70,32 @@ pub fn rewrite_files_with(cm: &SourceMap, rw: &TextRewrite, io: &dyn FileIO) ->
     Ok(())
 }
 
+/// Flatten the (cleaned up) top-level rewrites for `sf` into minimal edits of its source text.
+fn file_edits(cm: &SourceMap, sf: &SourceFile, rewrites: &[TextRewrite]) -> FileEdits {
+    let src = source_text(sf);
+    let mut edits = FileEdits::new();
+    for rw in rewrites {
+        let mut text = String::new();
+        rewrite_range(
+            cm,
+            rw.old_span.lo(),
+            rw.old_span.hi(),
+            slice::from_ref(rw),
+            &mut |s| text.push_str(s),
+        );
+        let lo = (rw.old_span.lo() - sf.start_pos).0 as usize;
+        let hi = (rw.old_span.hi() - sf.start_pos).0 as usize;
+        edits.push(src, lo, hi, &text);
+    }
+    edits
+}
+
+fn source_text(sf: &SourceFile) -> &str {
+    sf.src
+        .as_ref()
+        .unwrap_or_else(|| panic!("source of file {} is not available", sf.name))
+}
+
 #[allow(dead_code)] // Helper function for debugging
 fn print_rewrite(rw: &TextRewrite, depth: usize) {
     for _ in 0..depth {
@@ -145,6 +183,13 @@ fn emit_chunk<F: FnMut(&str)>(cm: &SourceMap, lo: BytePos, hi: BytePos, mut call
 
 /// Print a unified diff between lines of `s1` and lines of `s2`.
 pub fn print_diff(s1: &str, s2: &str) {
+    print!("{}", format_diff(s1, s2));
+}
+
+/// Build a unified diff between lines of `s1` and lines of `s2`.  The result contains only the
+/// hunks, without the `---`/`+++` file header.
+pub fn format_diff(s1: &str, s2: &str) -> String {
+    let mut out = String::new();
     enum State {
         /// We're not in a hunk, just keeping `buf` populated with `CONTEXT` lines of history.
         History,
@@ -206,7 +251,7 @@ pub fn print_diff(s1: &str, s2: &str) {
                         // End of the hunk
                         let end = buf.len() - CONTEXT;
                         let suffix = buf.split_off(end);
-                        print_hunk(&buf, l_start, r_start);
+                        write_hunk(&mut out, &buf, l_start, r_start);
                         buf = suffix;
                         state = State::History;
                     } else {
@@ -251,15 +296,21 @@ pub fn print_diff(s1: &str, s2: &str) {
                 let end = buf.len() - (CONTEXT - unchanged_limit);
                 buf.truncate(end);
             }
-            print_hunk(&buf, l_start, r_start);
+            write_hunk(&mut out, &buf, l_start, r_start);
         }
         _ => {}
     }
+    out
 }
 
-/// Print a single diff hunk, starting at line `l_start` in the left file and `r_start` in the
-/// right file.
-fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize) {
+/// Write a single diff hunk to `out`, starting at line `l_start` in the left file and `r_start` in
+/// the right file.
+fn write_hunk(
+    out: &mut String,
+    buf: &VecDeque<diff::Result<&str>>,
+    l_start: usize,
+    r_start: usize,
+) {
     let l_size = buf
         .iter()
         .filter(|r| match r {
@@ -276,7 +327,7 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
         })
         .count();
 
-    println!("@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size);
+    writeln!(out, "@@ -{},{} +{},{} @@", l_start, l_size, r_start, r_size).unwrap();
 
     // Print all "left" lines immediately.  Keep all "right" lines and print them just before the
     // next unchanged line.  This way we get the usual output, with separate old and new blocks:
@@ -290,22 +341,25 @@ fn print_hunk(buf: &VecDeque<diff::Result<&str>>, l_start: usize, r_start: usize
     for r in buf {
         match r {
             diff::Result::Left(s) => {
-                println!("-{}", s);
+                writeln!(out, "-{}", s).unwrap();
             }
             diff::Result::Right(s) => {
                 right_buf.push(s);
             }
             diff::Result::Both(s1, s2) => {
                 if s1 != s2 {
-                    println!("-{}", s1);
+                    writeln!(out, "-{}", s1).unwrap();
                     right_buf.push(s2);
                 } else {
                     for s in right_buf.drain(..) {
-                        println!("+{}", s);
+                        writeln!(out, "+{}", s).unwrap();
                     }
-                    println!(" {}", s1);
+                    writeln!(out, " {}", s1).unwrap();
                 }
             }
         }
     }
+    for s in right_buf {
+        writeln!(out, "+{}", s).unwrap();
+    }
 }
@@ -67,10 +67,12 @@ use crate::ast_manip::{GetSpan, Visit, CommentMap};
 use crate::driver;
 
 mod cleanup;
+pub mod edits;
 pub mod files;
 pub mod json;
 
 mod base;
+mod rustfmt;
 mod strategy;
 
 pub use self::base::Rewrite;
@@ -181,6 +183,9 @@ pub struct RewriteCtxt<'s> {
     /// looking for recycled text to splice in, it checks `old_nodes` for a node whose ID is
     /// `node_id_map[new_node.id]`.
     node_id_map: HashMap<NodeId, NodeId>,
+
+    /// Format pretty-printed items with `rustfmt` before splicing them in.
+    format: bool,
 }
 
 impl<'s> RewriteCtxt<'s> {
@@ -189,6 +194,7 @@ impl<'s> RewriteCtxt<'s> {
         old_nodes: AstMap<'s>,
         comment_map: &'s CommentMap,
         node_id_map: HashMap<NodeId, NodeId>,
+        format: bool,
     ) -> RewriteCtxt<'s> {
         RewriteCtxt {
             sess,
@@ -199,6 +205,7 @@ impl<'s> RewriteCtxt<'s> {
             fresh_start: DUMMY_SP,
             expr_prec: ExprPrec::Normal(parser::PREC_RESET),
             node_id_map,
+            format,
         }
     }
 
@@ -214,6 +221,10 @@ impl<'s> RewriteCtxt<'s> {
         &self.comment_map
     }
 
+    pub fn format(&self) -> bool {
+        self.format
+    }
+
     pub fn fresh_start(&self) -> Span {
         self.fresh_start
     }
@@ -309,6 +320,7 @@ pub fn rewrite<'s, T>(
     new: &T,
     comment_map: &CommentMap,
     node_id_map: HashMap<NodeId, NodeId>,
+    format: bool,
     map_extra_ast: impl FnOnce(&mut AstMap<'s>),
 ) -> TextRewrite
 where
@@ -318,7 +330,7 @@ where
     map_extra_ast(&mut map);
 
     let mut rw = TextRewrite::new(DUMMY_SP, old.get_span());
-    let mut rcx = RewriteCtxt::new(sess, map, comment_map, node_id_map);
+    let mut rcx = RewriteCtxt::new(sess, map, comment_map, node_id_map, format);
     let ok = Rewrite::rewrite(old, new, rcx.enter(&mut rw));
     assert!(ok, "rewriting did not complete");
     rw
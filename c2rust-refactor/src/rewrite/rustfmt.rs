@@ -0,0 +1,30 @@
+//! Formatting of pretty-printed source text with `rustfmt`.
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use syntax_pos::edition::Edition;
+
+/// Format `src`, which must be a sequence of items, with `rustfmt`.  The result has no trailing
+/// newline, so it can be spliced in place of the printed text.
+pub fn rustfmt(src: &str, edition: Edition) -> io::Result<String> {
+    let mut child = Command::new("rustfmt")
+        .arg("--edition")
+        .arg(edition.to_string())
+        .arg("--emit")
+        .arg("stdout")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // `rustfmt` reads all of its input before writing anything, so this can't deadlock.
+    child.stdin.take().unwrap().write_all(src.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    let formatted = String::from_utf8(output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(formatted.trim_end().to_owned())
+}
@@ -36,6 +36,7 @@ use crate::ast_manip::util::extend_span_attrs;
 use crate::ast_manip::{AstDeref, GetSpan, MaybeGetNodeId};
 use crate::driver;
 use crate::rewrite::base::{binop_left_prec, binop_right_prec};
+use crate::rewrite::rustfmt::rustfmt;
 use crate::rewrite::base::{describe, extend_span_comments, extend_span_comments_strict, is_rewritable, rewind_span_over_whitespace};
 use crate::rewrite::{ExprPrec, Rewrite, RewriteCtxt, RewriteCtxtRef, TextAdjust, TextRewrite};
 use crate::util::Lone;
@@ -56,6 +57,12 @@ pub trait PrintParse {
     type Parsed: AstDeref<Target = Self>;
     /// Parse a string to a node of this type.  Panics if parsing fails.
     fn parse(sess: &Session, src: &str) -> Self::Parsed;
+
+    /// Format printed source text of this node type with `rustfmt`.  Returns `None` if `rustfmt`
+    /// can't format this kind of node on its own.
+    fn format(_sess: &Session, _src: &str) -> Option<String> {
+        None
+    }
 }
 
 impl PrintParse for Expr {
@@ -117,6 +124,16 @@ impl PrintParse for Item {
     fn parse(sess: &Session, src: &str) -> Self::Parsed {
         driver::parse_items(sess, src).lone()
     }
+
+    fn format(sess: &Session, src: &str) -> Option<String> {
+        match rustfmt(src, sess.edition()) {
+            Ok(formatted) => Some(formatted),
+            Err(e) => {
+                warn!("rustfmt failed, keeping the pretty-printed item: {}", e);
+                None
+            }
+        }
+    }
 }
 
 // TODO: ImplItem
@@ -659,7 +676,12 @@ fn rewrite_at_impl<T>(old_span: Span, new: &T, mut rcx: RewriteCtxtRef) -> bool
 where
     T: PrintParse + RecoverChildren + Splice + MaybeGetNodeId,
 {
-    let printed = add_comments(new.to_string(), new, &rcx);
+    let mut printed = add_comments(new.to_string(), new, &rcx);
+    if rcx.format() {
+        if let Some(formatted) = T::format(rcx.session(), &printed) {
+            printed = formatted;
+        }
+    }
     let reparsed = T::parse(rcx.session(), &printed);
     let reparsed = reparsed.ast_deref();
 
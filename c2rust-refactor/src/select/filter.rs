@@ -1,6 +1,8 @@
+use std::path::Path as FsPath;
 use std::str::FromStr;
 use syntax::ast::*;
 use syntax::attr;
+use syntax::print::pprust;
 use syntax::source_map::Span;
 use syntax::symbol::Symbol;
 use syntax::visit::{self, FnKind, Visitor};
@@ -103,6 +105,61 @@ impl<'ast> AnyNode<'ast> {
         }
     }
 
+    pub fn span(&self) -> Span {
+        match *self {
+            AnyNode::Item(x) => x.span,
+            AnyNode::TraitItem(x) => x.span,
+            AnyNode::ImplItem(x) => x.span,
+            AnyNode::ForeignItem(x) => x.span,
+            AnyNode::Stmt(x) => x.span,
+            AnyNode::Expr(x) => x.span,
+            AnyNode::Pat(x) => x.span,
+            AnyNode::Ty(x) => x.span,
+            AnyNode::Param(x) => x.span,
+            AnyNode::Field(x) => x.span,
+        }
+    }
+
+    /// Get the type of the node as written in the source, if it has one.
+    pub fn declared_ty(&self) -> Option<&'ast Ty> {
+        match *self {
+            AnyNode::Item(i) => match i.kind {
+                ItemKind::Static(ref ty, _, _) | ItemKind::Const(ref ty, _) => Some(ty),
+                _ => None,
+            },
+            AnyNode::ForeignItem(fi) => match fi.kind {
+                ForeignItemKind::Static(ref ty, _) => Some(ty),
+                _ => None,
+            },
+            AnyNode::Ty(ty) => Some(ty),
+            AnyNode::Param(a) => Some(&a.ty),
+            AnyNode::Field(f) => Some(&f.ty),
+            _ => None,
+        }
+    }
+
+    pub fn fn_decl(&self) -> Option<&'ast FnDecl> {
+        match *self {
+            AnyNode::Item(i) => match i.kind {
+                ItemKind::Fn(ref sig, _, _) => Some(&sig.decl),
+                _ => None,
+            },
+            AnyNode::TraitItem(i) => match i.kind {
+                TraitItemKind::Method(ref sig, _) => Some(&sig.decl),
+                _ => None,
+            },
+            AnyNode::ImplItem(i) => match i.kind {
+                ImplItemKind::Method(ref sig, _) => Some(&sig.decl),
+                _ => None,
+            },
+            AnyNode::ForeignItem(i) => match i.kind {
+                ForeignItemKind::Fn(ref decl, _) => Some(decl),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn attrs(&self) -> Option<&'ast [Attribute]> {
         match *self {
             AnyNode::Item(i) => Some(&i.attrs),
@@ -278,6 +335,47 @@ pub fn matches_filter(
             _ => false,
         },
         Filter::Marked(label) => st.marked(node.id(), label),
+        Filter::Typed(ref pattern) => {
+            if let Some(ty) = node.declared_ty() {
+                return ty_matches(st, cx, pattern, ty);
+            }
+            let id = match node {
+                AnyNode::Expr(_) | AnyNode::Pat(_) => node.id(),
+                _ => return false,
+            };
+            match cx.opt_node_type(id) {
+                Some(ty) => ty_matches(st, cx, pattern, &reflect::reflect_tcx_ty(cx.ty_ctxt(), ty)),
+                None => false,
+            }
+        }
+        Filter::Returns(ref pattern) => match node.fn_decl().map(|decl| &decl.output) {
+            Some(FunctionRetTy::Ty(ty)) => ty_matches(st, cx, pattern, ty),
+            Some(FunctionRetTy::Default(_)) => match pattern.kind {
+                TyKind::Tup(ref tys) => tys.is_empty(),
+                _ => false,
+            },
+            None => false,
+        },
+        Filter::Calls(ref path) => {
+            let mut result = match node {
+                AnyNode::Expr(e) => calls_path(e, path),
+                _ => false,
+            };
+            iter_descendants(node, |child| {
+                if let AnyNode::Expr(e) = child {
+                    result = result || calls_path(e, path);
+                }
+            });
+            result
+        }
+        Filter::InFile(ref name) => {
+            let span = node.span();
+            if span.is_dummy() {
+                return false;
+            }
+            let file_name = cx.session().source_map().span_to_filename(span).to_string();
+            file_name_matches(&file_name, name)
+        }
 
         Filter::AnyChild(ref filt) => {
             let mut result = false;
@@ -337,6 +435,60 @@ pub fn matches_filter(
     }
 }
 
+/// Check if type `ty` matches type pattern `pattern`.  Besides the usual `matcher` rules, we
+/// accept types that differ only in the module paths of their type names, so `*mut c_char`
+/// matches `*mut libc::c_char`.
+fn ty_matches(st: &CommandState, cx: &RefactorCtxt, pattern: &Ty, ty: &Ty) -> bool {
+    MatchCtxt::from_match(st, cx, pattern, ty).is_ok()
+        || strip_path_prefixes(&pprust::ty_to_string(pattern))
+            == strip_path_prefixes(&pprust::ty_to_string(ty))
+}
+
+/// Remove every `module::` prefix from the paths in a printed type.
+fn strip_path_prefixes(s: &str) -> String {
+    let parts = s.split("::").collect::<Vec<_>>();
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i + 1 < parts.len() {
+            out.push_str(part.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_'));
+        } else {
+            out.push_str(part);
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Check if `e` is a call to a function or method whose path ends with `path`.
+fn calls_path(e: &Expr, path: &Path) -> bool {
+    let segs = path.segments.iter().map(|seg| seg.ident.name).collect::<Vec<_>>();
+    match e.kind {
+        ExprKind::Call(ref func, _) => match func.kind {
+            ExprKind::Path(_, ref func_path) => {
+                let func_segs = func_path.segments.iter()
+                    .map(|seg| seg.ident.name)
+                    .collect::<Vec<_>>();
+                func_segs.ends_with(&segs)
+            }
+            _ => false,
+        },
+        ExprKind::MethodCall(ref seg, _) => segs == [seg.ident.name],
+        _ => false,
+    }
+}
+
+/// Check if the file `file_name` matches the name `name` given to `in_file`.
+fn file_name_matches(file_name: &str, name: &str) -> bool {
+    if file_name.ends_with(name) {
+        return true;
+    }
+    // `foo.c` and `foo.h` also match `foo.rs`.
+    let name = FsPath::new(name);
+    match name.extension().and_then(|ext| ext.to_str()) {
+        Some("c") | Some("h") => file_name.ends_with(&*name.with_extension("rs").to_string_lossy()),
+        _ => false,
+    }
+}
+
 struct ChildVisitor<F: FnMut(AnyNode)> {
     func: F,
 }
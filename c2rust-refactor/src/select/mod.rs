@@ -88,6 +88,20 @@ pub enum Filter {
     Matches(AnyPattern),
     /// `marked(l)`: The node is marked with label `l`.
     Marked(Symbol),
+    /// `typed(T)`: The node's type matches type pattern `T`.  This applies to expressions,
+    /// patterns, types, params, fields, and statics and consts.  Params, fields, statics, and
+    /// consts are checked against their declared type, so type aliases like `c_char` can be
+    /// matched; other nodes use their inferred type, where aliases are already expanded.
+    Typed(P<Ty>),
+    /// `returns(T)`: The node is a function whose declared return type matches type pattern `T`.
+    Returns(P<Ty>),
+    /// `calls(p)`: The node is or contains a call to a function or method whose path ends with
+    /// `p`, as in `calls(free)`.
+    Calls(Box<Path>),
+    /// `in_file(s)`: The node comes from a source file whose path ends with `s`.  Since
+    /// translated files keep the name of their C source, a file name ending in `.c` or `.h` also
+    /// matches the corresponding `.rs` file, as in `in_file("parser.c")`.
+    InFile(String),
 
     /// `any_child(f)`: At least one direct child of the node matches filter `f`.
     AnyChild(Box<Filter>),
@@ -3,7 +3,8 @@ use rustc::session::Session;
 use std::mem;
 use std::str::FromStr;
 use std::vec;
-use syntax::ast::Path;
+use syntax::ast::{Path, Ty};
+use syntax::ptr::P;
 use rustc_parse::parser::{Parser, PathStyle};
 use syntax::token::{DelimToken, Lit, LitKind, Token, TokenKind};
 use syntax::sess::ParseSess;
@@ -172,6 +173,21 @@ impl<'a> Stream<'a> {
         }
     }
 
+    /// Parse a parenthesized type, as in `match_ty(T)` or `typed(T)`.
+    fn ty_arg(&mut self) -> PResult<P<Ty>> {
+        let ts = self.parens_raw()?;
+
+        let mut p = Parser::new(self.sess, ts, None, false, false, None);
+        let mut x = p
+            .parse_ty()
+            .map_err(|e| format!("error parsing ty: {}", e.message()))?;
+        p.expect(&TokenKind::Eof)
+            .map_err(|e| format!("error parsing ty: {}", e.message()))?;
+
+        remove_paren(&mut x);
+        Ok(x)
+    }
+
     fn filter_single(&mut self) -> PResult<Filter> {
         if self.maybe_expect(&TokenKind::Not) {
             let filt = self.filter_single()?;
@@ -289,19 +305,7 @@ impl<'a> Stream<'a> {
                     Ok(Filter::Matches(AnyPattern::Pat(x)))
                 }
 
-                "match_ty" => {
-                    let ts = self.parens_raw()?;
-
-                    let mut p = Parser::new(self.sess, ts, None, false, false, None);
-                    let mut x = p
-                        .parse_ty()
-                        .map_err(|e| format!("error parsing ty: {}", e.message()))?;
-                    p.expect(&TokenKind::Eof)
-                        .map_err(|e| format!("error parsing ty: {}", e.message()))?;
-
-                    remove_paren(&mut x);
-                    Ok(Filter::Matches(AnyPattern::Ty(x)))
-                }
+                "match_ty" => Ok(Filter::Matches(AnyPattern::Ty(self.ty_arg()?))),
 
                 "match_stmt" => {
                     let ts = self.parens_raw()?;
@@ -329,6 +333,29 @@ impl<'a> Stream<'a> {
                     Ok(Filter::Marked(label))
                 }
 
+                "typed" => Ok(Filter::Typed(self.ty_arg()?)),
+
+                "returns" => Ok(Filter::Returns(self.ty_arg()?)),
+
+                "calls" => {
+                    let mut inner = self.parens()?;
+                    let path = inner.path()?;
+                    inner.last()?;
+                    Ok(Filter::Calls(Box::new(path)))
+                }
+
+                "in_file" => {
+                    let mut inner = self.parens()?;
+                    let lit = inner.lit()?;
+                    inner.last()?;
+
+                    match lit.kind {
+                        LitKind::Str | LitKind::StrRaw(_) =>
+                            Ok(Filter::InFile((&lit.symbol.as_str() as &str).to_owned())),
+                        l => fail!("expected string literal, but got {:?}", l),
+                    }
+                }
+
                 "any_child" => {
                     let mut inner = self.parens()?;
                     let filt = inner.filter()?;
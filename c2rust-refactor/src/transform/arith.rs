@@ -0,0 +1,256 @@
+//! Conversion of integer arithmetic to operations with explicit overflow behavior.
+
+use rustc::ty::{Ty, TyKind};
+use smallvec::{smallvec, SmallVec};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+
+use crate::ast_manip::MutVisit;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::matcher::{Bindings, Subst};
+use crate::transform::Transform;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+
+/// How to handle arithmetic that may overflow.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Wrap around, as C does for unsigned types: `a.wrapping_add(b)`.
+    Wrapping,
+    /// Panic on overflow, even in release builds: `a.checked_add(b).unwrap()`.
+    Checked,
+    /// Compute in a wider type and truncate the result: `(a as i64 + b as i64) as u32`.
+    Widen,
+}
+
+impl OverflowPolicy {
+    fn from_arg(s: &str) -> OverflowPolicy {
+        match s {
+            "wrapping" => OverflowPolicy::Wrapping,
+            "checked" => OverflowPolicy::Checked,
+            "widen" => OverflowPolicy::Widen,
+            _ => panic!("unknown overflow policy {:?} (expected wrapping, checked, or widen)", s),
+        }
+    }
+}
+
+/// # `convert_overflow_arith` Command
+///
+/// Usage: `convert_overflow_arith [POLICY] [all]`
+///
+/// Rewrite integer `+`, `-`, and `*` (including the compound assignment forms)
+/// so that overflow behaves as it did in C, instead of panicking in debug
+/// builds and wrapping in release builds.  By default only arithmetic on
+/// unsigned types is converted, since signed overflow is undefined behavior in
+/// C anyway.  With `all`, signed arithmetic and negation are converted too.
+///
+/// `POLICY` selects the replacement (default: `wrapping`):
+///
+///  * `wrapping`: `a + b` becomes `a.wrapping_add(b)`.
+///
+///  * `checked`: `a + b` becomes `a.checked_add(b).unwrap()`, which panics on
+///    overflow in every build.
+///
+///  * `widen`: `a + b` becomes `(a as i64 + b as i64) as u32`, computing the
+///    result in a signed type twice as wide (`i128` for 64-bit and
+///    pointer-sized types) and then truncating it.  128-bit arithmetic is left
+///    alone.
+///
+/// `a += b` becomes `a = a.wrapping_add(b)` and so on, but only when `a` can be
+/// evaluated twice without side effects.  Code in constants, statics, and array
+/// lengths is not changed.  Arithmetic that the translator already wrote as
+/// `wrapping_*` calls needs no changes.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut h: u32 = 5381;
+///     h = h * 33 + c;
+///     count -= 1;
+/// ```
+///
+/// After running `convert_overflow_arith`, if `h` and `count` are unsigned:
+///
+/// ```ignore
+///     let mut h: u32 = 5381;
+///     h = h.wrapping_mul(33).wrapping_add(c);
+///     count = count.wrapping_sub(1);
+/// ```
+pub struct ConvertOverflowArith {
+    pub policy: OverflowPolicy,
+    pub signed: bool,
+}
+
+/// Check if the place expression `e` can be evaluated twice without side effects.
+fn is_pure_place(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Path(..) | ExprKind::Lit(..) => true,
+        ExprKind::Paren(ref e) | ExprKind::Field(ref e, _) | ExprKind::Unary(UnOp::Deref, ref e) =>
+            is_pure_place(e),
+        ExprKind::Index(ref e, ref idx) => is_pure_place(e) && is_pure_place(idx),
+        _ => false,
+    }
+}
+
+fn is_int_lit(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Lit(ref l) => matches!([l.kind] LitKind::Int(..)),
+        ExprKind::Paren(ref e) => is_int_lit(e),
+        _ => false,
+    }
+}
+
+fn method_suffix(op: BinOpKind) -> Option<&'static str> {
+    match op {
+        BinOpKind::Add => Some("add"),
+        BinOpKind::Sub => Some("sub"),
+        BinOpKind::Mul => Some("mul"),
+        _ => None,
+    }
+}
+
+struct OverflowFolder<'a, 'tcx: 'a> {
+    st: &'a CommandState,
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    policy: OverflowPolicy,
+    signed: bool,
+}
+
+impl<'a, 'tcx> OverflowFolder<'a, 'tcx> {
+    /// If `e` has one of the integer types this command converts, get its type and the name of
+    /// the wider type to use for it with the `widen` policy.  The wider type is `""` for types
+    /// that have none.
+    fn int_kind(&self, e: &Expr) -> Option<(&'static str, Ty<'tcx>)> {
+        let ty = self.cx.opt_node_type(e.id)?;
+        let wide = match ty.kind {
+            TyKind::Uint(UintTy::U128) | TyKind::Int(IntTy::I128) => "",
+            TyKind::Uint(UintTy::U64) | TyKind::Uint(UintTy::Usize) |
+            TyKind::Int(IntTy::I64) | TyKind::Int(IntTy::Isize) => "i128",
+            TyKind::Uint(_) | TyKind::Int(_) => "i64",
+            _ => return None,
+        };
+        if !self.signed && !matches!([ty.kind] TyKind::Uint(_)) {
+            return None;
+        }
+        Some((wide, ty))
+    }
+
+    /// Give the literal `a` an explicit type suffix, so that methods can be called on it.
+    fn suffixed(&self, a: &P<Expr>, ty: Ty<'tcx>) -> P<Expr> {
+        let n = match a.kind {
+            ExprKind::Lit(Lit { kind: LitKind::Int(n, LitIntType::Unsuffixed), .. }) => n,
+            _ => return a.clone(),
+        };
+        match ty.kind {
+            TyKind::Int(ity) => mk().lit_expr(mk().int_lit(n, ity)),
+            TyKind::Uint(uty) => mk().lit_expr(mk().int_lit(n, uty)),
+            _ => a.clone(),
+        }
+    }
+
+    fn build(&self, src: &str, a: &P<Expr>, b: Option<&P<Expr>>) -> P<Expr> {
+        let mut bnd = Bindings::new();
+        bnd.add("__a", a.clone());
+        if let Some(b) = b {
+            bnd.add("__b", b.clone());
+        }
+        parse_expr(self.cx.session(), src).subst(self.st, self.cx, &bnd)
+    }
+
+    /// Build the replacement for `a OP b`, where `e` is the original expression of the result
+    /// type.
+    fn convert_binary(&self, e: &Expr, op: BinOpKind, a: &P<Expr>, b: &P<Expr>)
+                      -> Option<P<Expr>> {
+        let suffix = method_suffix(op)?;
+        let (wide, ty) = self.int_kind(e)?;
+        if is_int_lit(a) && is_int_lit(b) {
+            // Overflow in constant arithmetic is already a compile error.
+            return None;
+        }
+        let a = &self.suffixed(a, ty);
+        let src = match self.policy {
+            OverflowPolicy::Wrapping => format!("__a.wrapping_{}(__b)", suffix),
+            OverflowPolicy::Checked => format!("__a.checked_{}(__b).unwrap()", suffix),
+            OverflowPolicy::Widen if wide.is_empty() => return None,
+            OverflowPolicy::Widen => format!("(__a as {} {} __b as {}) as {}",
+                                             wide, op.to_string(), wide, ty.to_string()),
+        };
+        Some(self.build(&src, a, Some(b)))
+    }
+
+    fn convert_neg(&self, e: &Expr, a: &P<Expr>) -> Option<P<Expr>> {
+        if !self.signed || is_int_lit(a) {
+            return None;
+        }
+        let (wide, ty) = self.int_kind(e)?;
+        let src = match self.policy {
+            OverflowPolicy::Wrapping => "__a.wrapping_neg()".to_owned(),
+            OverflowPolicy::Checked => "__a.checked_neg().unwrap()".to_owned(),
+            OverflowPolicy::Widen if wide.is_empty() => return None,
+            OverflowPolicy::Widen => format!("(-(__a as {})) as {}", wide, ty.to_string()),
+        };
+        Some(self.build(&src, a, None))
+    }
+}
+
+impl<'a, 'tcx> MutVisitor for OverflowFolder<'a, 'tcx> {
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        // Method calls like `checked_add` can't be used in constant expressions.
+        match i.kind {
+            ItemKind::Const(..) | ItemKind::Static(..) => smallvec![i],
+            _ => mut_visit::noop_flat_map_item(i, self),
+        }
+    }
+
+    fn visit_anon_const(&mut self, _c: &mut AnonConst) {}
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        mut_visit::noop_visit_expr(e, self);
+
+        let new_e = match e.kind {
+            ExprKind::Binary(op, ref a, ref b) => self.convert_binary(e, op.node, a, b),
+            ExprKind::Unary(UnOp::Neg, ref a) => self.convert_neg(e, a),
+            ExprKind::AssignOp(op, ref lhs, ref rhs) if is_pure_place(lhs) => {
+                self.convert_binary(lhs, op.node, lhs, rhs)
+                    .map(|value| self.build("__a = __b", lhs, Some(&value)))
+            }
+            _ => None,
+        };
+        if let Some(new_e) = new_e {
+            *e = new_e;
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for ConvertOverflowArith {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut OverflowFolder {
+            st,
+            cx,
+            policy: self.policy,
+            signed: self.signed,
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("convert_overflow_arith", |args| {
+        let policy = args.get(0).map_or(OverflowPolicy::Wrapping, |s| OverflowPolicy::from_arg(s));
+        let signed = args.get(1).map_or(false, |s| s == "all");
+        mk(ConvertOverflowArith { policy, signed })
+    });
+}
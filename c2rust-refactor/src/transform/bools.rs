@@ -0,0 +1,412 @@
+//! Transforms for replacing C integer flags with `bool`.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, TyKind};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, fold_output_exprs, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::strip_casts;
+use crate::RefactorCtxt;
+
+
+/// A location selected for conversion to `bool`: a local variable or argument, a struct field, or
+/// the return value of a function.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum BoolVar {
+    Local(HirId),
+    Field(DefId),
+    Return(DefId),
+}
+
+/// If `e` is an integer literal (possibly with casts), return its value.
+fn int_lit_value(e: &Expr) -> Option<u128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(x, _) => Some(x),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn strip_parens(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Paren(ref e) => strip_parens(e),
+        _ => e,
+    }
+}
+
+fn is_arith_op(op: BinOpKind) -> bool {
+    !matches!([op] BinOpKind::Eq, BinOpKind::Ne, BinOpKind::And, BinOpKind::Or)
+}
+
+struct BoolVars<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    /// The original integer type of each selected location.
+    int_tys: HashMap<BoolVar, P<Ty>>,
+}
+
+impl<'a, 'tcx> BoolVars<'a, 'tcx> {
+    /// If `e` reads one of the selected locations (including by calling a selected function),
+    /// return it.
+    fn var_of(&self, e: &Expr) -> Option<BoolVar> {
+        let cx = self.cx;
+        let var = match e.kind {
+            ExprKind::Path(..) => BoolVar::Local(cx.try_resolve_expr_to_hid(e)?),
+            ExprKind::Field(ref obj, name) => {
+                let ty = cx.opt_adjusted_node_type(obj.id)?;
+                let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return None);
+                if adt.is_enum() {
+                    return None;
+                }
+                BoolVar::Field(adt.non_enum_variant().fields.iter().find(|f| f.ident == name)?.did)
+            }
+            ExprKind::Call(..) | ExprKind::MethodCall(..) => BoolVar::Return(cx.opt_callee(e)?),
+            _ => return None,
+        };
+        if self.int_tys.contains_key(&var) { Some(var) } else { None }
+    }
+
+    fn var_of_operand(&self, e: &Expr) -> Option<BoolVar> {
+        self.var_of(strip_parens(e))
+    }
+}
+
+
+/// # `int_to_bool` Command
+///
+/// Usage: `int_to_bool`
+///
+/// Marks: `target`
+///
+/// For each local variable, function argument, struct field, or function marked
+/// `target` with an integer type (for functions, an integer return type), change
+/// the type to `bool`.
+///
+/// Comparisons of a converted value against `0` or `1` are simplified: `x != 0`
+/// and `x == 1` become `x`, while `x == 0` and `x != 1` become `!x`.  Values
+/// stored into a converted location (by assignment, initialization, function
+/// argument, or `return`) are converted as well: the literals `0` and `1` become
+/// `false` and `true`, `cond as c_int` becomes `cond`, and any other integer `e`
+/// becomes `e != 0`.  Any other read of a converted value is cast back to the
+/// original integer type.
+///
+/// Values that are used arithmetically (including bitwise operators, ordering
+/// comparisons, compound assignment, or comparison with anything other than `0`,
+/// `1`, or another converted value), or whose address is taken, are left
+/// unchanged, since they may hold values other than 0 and 1.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut done: libc::c_int = 0;
+///     while done == 0 {
+///         done = (x > 10) as libc::c_int;
+///     }
+/// ```
+///
+/// After running `int_to_bool`, with `done` marked:
+///
+/// ```ignore
+///     let mut done: bool = false;
+///     while !done {
+///         done = x > 10;
+///     }
+/// ```
+pub struct IntToBool;
+
+impl Transform for IntToBool {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked locations that have integer types.
+
+        let mut vars = BoolVars { cx, int_tys: HashMap::new() };
+        {
+            let mut add = |var: BoolVar, ty: Option<ty::Ty>, what: &dyn Fn() -> String| {
+                match ty {
+                    Some(ty) if matches!([ty.kind] TyKind::Int(_), TyKind::Uint(_)) => {
+                        vars.int_tys.insert(var, reflect_tcx_ty(cx.ty_ctxt(), ty));
+                    }
+                    _ => warn!("{} does not have an integer type; skipping it", what()),
+                }
+            };
+
+            visit_nodes(krate, |l: &Local| {
+                if st.marked(l.id, "target") || st.marked(l.pat.id, "target") {
+                    add(BoolVar::Local(cx.hir_map().node_to_hir_id(l.pat.id)),
+                        cx.opt_node_type(l.pat.id),
+                        &|| format!("local `{}`", pprust::pat_to_string(&l.pat)));
+                }
+            });
+
+            visit_nodes(krate, |i: &Item| {
+                match i.kind {
+                    ItemKind::Fn(ref sig, _, _) => {
+                        for arg in &sig.decl.inputs {
+                            if st.marked(arg.id, "target") || st.marked(arg.pat.id, "target") {
+                                add(BoolVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id)),
+                                    cx.opt_node_type(arg.pat.id),
+                                    &|| format!("argument `{}`", pprust::pat_to_string(&arg.pat)));
+                            }
+                        }
+                        if st.marked(i.id, "target") {
+                            let did = cx.node_def_id(i.id);
+                            add(BoolVar::Return(did),
+                                Some(cx.ty_ctxt().fn_sig(did).skip_binder().output()),
+                                &|| format!("return value of `{}`", i.ident));
+                        }
+                    }
+                    ItemKind::Struct(VariantData::Struct(ref fields, _), _) => {
+                        for sf in fields {
+                            if st.marked(sf.id, "target") {
+                                let did = cx.node_def_id(sf.id);
+                                add(BoolVar::Field(did),
+                                    Some(cx.ty_ctxt().type_of(did)),
+                                    &|| format!("field `{}::{:?}`", i.ident, sf.ident));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            });
+        }
+
+        // (2) Leave alone any location that is used arithmetically.
+
+        let mut unsupported = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let mut bail = |operand: &Expr| {
+                if let Some(var) = vars.var_of_operand(operand) {
+                    unsupported.insert(var);
+                }
+            };
+            match e.kind {
+                ExprKind::Binary(op, ref a, ref b) if is_arith_op(op.node) => {
+                    bail(a);
+                    bail(b);
+                }
+                ExprKind::Binary(op, ref a, ref b)
+                        if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) => {
+                    // `==` and `!=` are fine as long as the other side is `0`, `1`, or another
+                    // converted value.
+                    let ok = |x: &Expr| {
+                        vars.var_of_operand(x).is_some() ||
+                        int_lit_value(x).map_or(false, |v| v <= 1)
+                    };
+                    if !ok(b) {
+                        bail(a);
+                    }
+                    if !ok(a) {
+                        bail(b);
+                    }
+                }
+                ExprKind::AssignOp(_, ref a, ref b) => {
+                    bail(a);
+                    bail(b);
+                }
+                ExprKind::Unary(UnOp::Neg, ref a) |
+                ExprKind::Unary(UnOp::Not, ref a) |
+                ExprKind::AddrOf(_, _, ref a) => bail(a),
+                ExprKind::MethodCall(_, ref args) => bail(&args[0]),
+                _ => {}
+            }
+        });
+        for var in &unsupported {
+            warn!("{:?} is used arithmetically; leaving it unchanged", var);
+            vars.int_tys.remove(var);
+        }
+        if vars.int_tys.is_empty() {
+            return;
+        }
+
+        // Convert an integer value to `bool`.
+        let false_expr = parse_expr(cx.session(), "false");
+        let true_expr = parse_expr(cx.session(), "true");
+        let ne_zero = parse_expr(cx.session(), "__e != 0");
+        let handled: RefCell<HashSet<NodeId>> = RefCell::new(HashSet::new());
+        let convert_value = |e: &P<Expr>| -> P<Expr> {
+            if let Some(v) = int_lit_value(e) {
+                return if v == 0 { false_expr.clone() } else { true_expr.clone() };
+            }
+            let inner = strip_parens(e);
+            if vars.var_of(inner).is_some() {
+                handled.borrow_mut().insert(inner.id);
+                return e.clone();
+            }
+            if let ExprKind::Cast(ref cond, _) = inner.kind {
+                let cond_ty = cx.opt_node_type(cond.id);
+                if cond_ty.map_or(false, |ty| matches!([ty.kind] TyKind::Bool)) {
+                    return cond.clone();
+                }
+            }
+            let mut bnd = Bindings::new();
+            bnd.add("__e", e.clone());
+            ne_zero.clone().subst(st, cx, &bnd)
+        };
+
+        // (3) Change types, and convert initializers and return values.
+
+        let bool_ty = parse_ty(cx.session(), "bool");
+        let mut mod_fn_args: HashMap<DefId, HashSet<usize>> = HashMap::new();
+
+        mut_visit_fns(krate, |fl| {
+            let did = cx.node_def_id(fl.id);
+            for (i, arg) in fl.decl.inputs.iter_mut().enumerate() {
+                let var = BoolVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id));
+                if vars.int_tys.contains_key(&var) {
+                    arg.ty = bool_ty.clone();
+                    mod_fn_args.entry(did).or_insert_with(HashSet::new).insert(i);
+                }
+            }
+
+            if vars.int_tys.contains_key(&BoolVar::Return(did)) {
+                fl.decl.output = FunctionRetTy::Ty(bool_ty.clone());
+                fl.block.as_mut().map(|b| fold_output_exprs(b, true, |e| {
+                    *e = convert_value(e);
+                }));
+            }
+        });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = BoolVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if !vars.int_tys.contains_key(&var) {
+                return;
+            }
+            if l.ty.is_some() {
+                l.ty = Some(bool_ty.clone());
+            }
+            if let Some(ref mut init) = l.init {
+                *init = convert_value(init);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if vars.int_tys.contains_key(&BoolVar::Field(cx.node_def_id(sf.id))) {
+                        sf.ty = bool_ty.clone();
+                    }
+                }
+            }
+        });
+
+        // (4) Simplify comparisons, and convert assigned values, struct literal fields, and
+        // arguments of modified functions.
+
+        let not = parse_expr(cx.session(), "!__e");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let Some(mod_args) = cx.opt_callee(&e).and_then(|did| mod_fn_args.get(&did)) {
+                let args: &mut [P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref mut args) => args,
+                    ExprKind::MethodCall(_, ref mut args) => args,
+                    _ => return,
+                };
+                for &idx in mod_args {
+                    if let Some(arg) = args.get_mut(idx) {
+                        *arg = convert_value(arg);
+                    }
+                }
+                return;
+            }
+
+            let new_e = match e.kind {
+                ExprKind::Binary(op, ref a, ref b)
+                        if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) => {
+                    let (x, lit) = match (vars.var_of_operand(a), vars.var_of_operand(b)) {
+                        (Some(_), None) => (a, int_lit_value(b)),
+                        (None, Some(_)) => (b, int_lit_value(a)),
+                        (Some(_), Some(_)) => {
+                            handled.borrow_mut().insert(strip_parens(a).id);
+                            handled.borrow_mut().insert(strip_parens(b).id);
+                            return;
+                        }
+                        (None, None) => return,
+                    };
+                    let lit = match_or!([lit] Some(x) => x; return);
+                    handled.borrow_mut().insert(strip_parens(x).id);
+                    // `x != 0` and `x == 1` are true exactly when `x` is.
+                    if (op.node == BinOpKind::Ne) == (lit == 0) {
+                        x.clone()
+                    } else {
+                        let mut bnd = Bindings::new();
+                        bnd.add("__e", x.clone());
+                        not.clone().subst(st, cx, &bnd)
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if vars.var_of(lhs).is_some() {
+                        handled.borrow_mut().insert(lhs.id);
+                        *rhs = convert_value(rhs);
+                    }
+                    return;
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if did.map_or(false, |did| vars.int_tys.contains_key(&BoolVar::Field(did))) {
+                            f.expr = convert_value(&f.expr);
+                        }
+                    }
+                    return;
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+
+        // (5) Any other read of a converted value gets cast back to its original type.
+
+        let cast = parse_expr(cx.session(), "__e as __t");
+        let handled = handled.into_inner();
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) {
+                return;
+            }
+            let var = match_or!([vars.var_of(e)] Some(x) => x; return);
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    let mut bnd = Bindings::new();
+                    bnd.add("__e", e.clone());
+                    bnd.add("__t", vars.int_tys[&var].clone());
+                    *e = cast.clone().subst(st, cx, &bnd);
+                }
+                _ => {
+                    warn!("can't convert lvalue use of {:?}: `{}`",
+                          var, pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("int_to_bool", |_args| mk(IntToBool));
+}
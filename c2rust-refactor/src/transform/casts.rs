@@ -1,12 +1,16 @@
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
 use rustc::ty::{self, ParamEnv, TyKind};
 use syntax::ast::*;
 use syntax::token;
 use syntax::ptr::P;
 use syntax_pos::Symbol;
 
+use crate::analysis::ranges::ValueRanges;
+use crate::ast_manip::{visit_nodes, MutVisitNodes};
 use crate::command::{CommandState, Registry};
-use crate::driver::Phase;
-use crate::matcher::{mut_visit_match_with, replace_expr, MatchCtxt};
+use crate::driver::{Phase, parse_expr};
+use crate::matcher::{mut_visit_match_with, replace_expr, Bindings, MatchCtxt, Subst};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::mk;
@@ -560,6 +564,216 @@ fn eval_const<'tcx>(e: P<Expr>, cx: &RefactorCtxt) -> Option<ConstantValue> {
     }
 }
 
+/// # `int_counters_to_usize` Command
+///
+/// Usage: `int_counters_to_usize`
+///
+/// Change the type of integer counters that are never negative to `usize`, so they can be used
+/// as indices without casts.  A local is converted if it's declared with an explicit integer type
+/// and a literal initializer, and every use of it is one of:
+///
+///  * An assignment of a literal, like `i = 0`.
+///  * An increment by a literal, like `i += 1` or `i = i + 1`.
+///  * A cast to an integer type, like `i as usize`.
+///  * A comparison with a literal, or with an integer expression that the value-range analysis
+///    (see the `ranges` module) proves is never negative at that point, like `i < n`.
+///
+/// Such a local never holds a negative value, so changing its type doesn't change its value.
+/// Casts of it to `usize` are removed, and the other operand of each comparison is cast to
+/// `usize`.
+///
+/// Example:
+///
+/// ```ignore
+///     if n <= 0 {
+///         return;
+///     }
+///     let mut i: i32 = 0;
+///     while i < n {
+///         buf[i as usize] = 0;
+///         i += 1;
+///     }
+/// ```
+///
+/// After running `int_counters_to_usize`:
+///
+/// ```ignore
+///     if n <= 0 {
+///         return;
+///     }
+///     let mut i: usize = 0;
+///     while i < n as usize {
+///         buf[i] = 0;
+///         i += 1;
+///     }
+/// ```
+pub struct IntCountersToUsize;
+
+/// A candidate counter, along with the changes needed to convert it.
+#[derive(Default)]
+struct Counter {
+    /// Number of uses of the local that we know how to convert.
+    ok_uses: usize,
+    /// Total number of uses of the local.
+    uses: usize,
+    /// Casts of the counter to `usize`, which become redundant.
+    casts: Vec<NodeId>,
+    /// Literals assigned to or compared with the counter, which must become `usize`.
+    lits: Vec<NodeId>,
+    /// Expressions compared with the counter, which must be cast to `usize`, along with whether
+    /// they are on the left side of the comparison.
+    operands: Vec<(NodeId, bool)>,
+}
+
+fn as_int_lit(e: &Expr) -> Option<&Lit> {
+    match e.kind {
+        ExprKind::Lit(ref l) if matches!([l.kind] LitKind::Int(..)) => Some(l),
+        _ => None,
+    }
+}
+
+fn is_comparison(op: BinOpKind) -> bool {
+    matches!([op] BinOpKind::Lt, BinOpKind::Le, BinOpKind::Gt, BinOpKind::Ge, BinOpKind::Eq,
+             BinOpKind::Ne)
+}
+
+impl Transform for IntCountersToUsize {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find locals with an explicit integer type and a literal initializer.
+
+        let mut counters: HashMap<HirId, Counter> = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if !matches!([l.pat.kind] PatKind::Ident(BindingMode::ByValue(_), _, None)) ||
+               l.ty.is_none() {
+                return;
+            }
+            let init = match l.init {
+                Some(ref e) if as_int_lit(e).is_some() => e,
+                _ => return,
+            };
+            match cx.opt_node_type(l.pat.id).map(|ty| &ty.kind) {
+                Some(&TyKind::Int(ity)) if ity != IntTy::I128 => {}
+                Some(&TyKind::Uint(uty)) if uty != UintTy::U128 && uty != UintTy::Usize => {}
+                _ => return,
+            }
+            let mut c = Counter::default();
+            c.lits.push(init.id);
+            counters.insert(cx.hir_map().node_to_hir_id(l.pat.id), c);
+        });
+        if counters.is_empty() {
+            return;
+        }
+
+        // (2) Check that every use of each counter keeps it non-negative, and collect the
+        // changes needed to convert it.
+
+        let counter_of = |e: &Expr| match e.kind {
+            ExprKind::Path(..) => cx.try_resolve_expr_to_hid(e),
+            _ => None,
+        };
+        let mut ranges = ValueRanges::new(cx);
+        visit_nodes(krate, |e: &Expr| {
+            let hid = match e.kind {
+                ExprKind::Path(..) => {
+                    if let Some(c) = counter_of(e).and_then(|hid| counters.get_mut(&hid)) {
+                        c.uses += 1;
+                    }
+                    return;
+                }
+                ExprKind::Cast(ref inner, _) => counter_of(inner),
+                ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) =>
+                    counter_of(lhs),
+                ExprKind::Binary(op, ref a, ref b) if is_comparison(op.node) =>
+                    counter_of(a).or_else(|| counter_of(b)),
+                _ => return,
+            };
+            let c = match_or!([hid.and_then(|hid| counters.get_mut(&hid))] Some(x) => x; return);
+            let is_counter = |e: &Expr| counter_of(e) == hid;
+
+            match e.kind {
+                ExprKind::Cast(..) => {
+                    match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                        Some(&TyKind::Uint(UintTy::Usize)) => c.casts.push(e.id),
+                        Some(&TyKind::Int(_)) | Some(&TyKind::Uint(_)) => {}
+                        _ => return,
+                    }
+                    c.ok_uses += 1;
+                }
+                ExprKind::Assign(_, ref rhs) => {
+                    if as_int_lit(rhs).is_some() {
+                        c.lits.push(rhs.id);
+                        c.ok_uses += 1;
+                    } else if let ExprKind::Binary(op, ref a, ref k) = rhs.kind {
+                        if op.node == BinOpKind::Add && is_counter(a) && as_int_lit(k).is_some() {
+                            c.lits.push(k.id);
+                            c.ok_uses += 2;
+                        }
+                    }
+                }
+                ExprKind::AssignOp(op, _, ref k) => {
+                    if op.node == BinOpKind::Add && as_int_lit(k).is_some() {
+                        c.lits.push(k.id);
+                        c.ok_uses += 1;
+                    }
+                }
+                ExprKind::Binary(_, ref a, ref b) => {
+                    let (other, other_is_lhs) = if is_counter(a) { (b, false) } else { (a, true) };
+                    if is_counter(other) {
+                        return;
+                    }
+                    if as_int_lit(other).is_some() {
+                        c.lits.push(other.id);
+                    } else if ranges.is_non_negative(other, e.span) {
+                        c.operands.push((other.id, other_is_lhs));
+                    } else {
+                        return;
+                    }
+                    c.ok_uses += 1;
+                }
+                _ => {}
+            }
+        });
+        counters.retain(|_, c| c.uses == c.ok_uses);
+
+        // (3) Change the types of the counters and fix up their uses.
+
+        let mut casts = HashSet::new();
+        let mut lits = HashSet::new();
+        let mut operands = HashMap::new();
+        for c in counters.values() {
+            casts.extend(c.casts.iter().cloned());
+            lits.extend(c.lits.iter().cloned());
+            operands.extend(c.operands.iter().cloned());
+        }
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let hid = match_or!([cx.hir_map().opt_node_to_hir_id(l.pat.id)] Some(x) => x; return);
+            if counters.contains_key(&hid) {
+                l.ty = Some(mk().ident_ty("usize"));
+            }
+        });
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if casts.contains(&e.id) {
+                let inner = expect!([e.kind] ExprKind::Cast(ref inner, _) => inner.clone());
+                *e = inner;
+            } else if lits.contains(&e.id) {
+                let n = expect!([e.kind] ExprKind::Lit(Lit { kind: LitKind::Int(n, _), .. }) => n);
+                *e = parse_expr(cx.session(), &n.to_string());
+            } else if let Some(&is_lhs) = operands.get(&e.id) {
+                // `n as usize < i` would parse as the start of a generic type.
+                let src = if is_lhs { "(__e as usize)" } else { "__e as usize" };
+                let mut bnd = Bindings::new();
+                bnd.add("__e", e.clone());
+                *e = parse_expr(cx.session(), src).subst(st, cx, &bnd);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 /// # `convert_cast_as_ptr` Command
 ///
 /// Usage: `convert_cast_as_ptr`
@@ -610,4 +824,5 @@ pub fn register_commands(reg: &mut Registry) {
 
     reg.register("remove_redundant_casts", |_| mk(RemoveRedundantCasts));
     reg.register("convert_cast_as_ptr", |_| mk(ConvertCastAsPtr));
+    reg.register("int_counters_to_usize", |_| mk(IntCountersToUsize));
 }
@@ -1,14 +1,24 @@
+use std::collections::{HashMap, HashSet};
 use rustc::hir::{self, HirId};
-use rustc::ty::{self, ParamEnv};
+use rustc::ty::{self, ParamEnv, TyKind};
 use rustc_typeck::expr_use_visitor::*;
-use syntax::ast::{Crate, Expr, ExprKind, Lit, LitKind, Stmt, StmtKind};
+use syntax::ast::{Arm, BinOpKind, BindingMode, Block, BlockCheckMode, Crate, Expr, ExprKind};
+use syntax::ast::{FunctionRetTy, Label, Lit, LitIntType, LitKind, Mutability, NodeId, Pat, PatKind};
+use syntax::ast::{RangeLimits, Stmt, StmtKind, UintTy, UnOp, DUMMY_NODE_ID};
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::symbol::Symbol;
 
+use crate::analysis::points_to::{self, Loc, PointsTo};
+use crate::ast_manip::{AstEquiv, MutVisitNodes, Visit, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
 use crate::command::{CommandState, Registry};
 use crate::context::HirMap;
-use crate::driver::Phase;
+use crate::driver::{Phase, parse_expr, parse_stmts};
 use crate::matcher::{MatchCtxt, Subst, replace_expr, mut_visit_match_with, find_first};
+use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
+use crate::transform::heap::strip_casts;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::mk;
 
@@ -314,10 +324,1122 @@ impl Transform for RemoveUnusedLabels {
 }
 
 
+/// # `switch_to_match` Command
+///
+/// Usage: `switch_to_match`
+///
+/// Flatten the `current_block` encoding the transpiler uses for `switch` fallthrough and for
+/// `case`s that share code.  This encoding consists of a `match` whose arms end by setting a
+/// dispatch variable, followed by a second `match` on that variable.  The code from each arm of
+/// the second `match` is moved into the arms of the first that select it, duplicating it when
+/// several arms select the same code.  Afterward, adjacent arms with identical bodies are merged
+/// into a single arm with an or-pattern.
+///
+/// A pair of `match`es is only rewritten when this preserves the program's behavior: every arm of
+/// the first `match` must either end by assigning a literal to the dispatch variable or end with
+/// `return`, `break`, or `continue`; the second `match` must dispatch on literals, with an
+/// optional `_` arm; and each of the variable's reads must be the scrutinee of such a pair, so
+/// its value never flows anywhere else.  The variable's declaration is removed once all its pairs
+/// are rewritten.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut current_block: u64;
+///     match c {
+///         1 => {
+///             a();
+///             current_block = 7;
+///         }
+///         2 => {
+///             current_block = 7;
+///         }
+///         _ => {
+///             current_block = 9;
+///         }
+///     }
+///     match current_block {
+///         7 => {
+///             b();
+///         }
+///         _ => {}
+///     }
+/// ```
+///
+/// After running `switch_to_match`:
+///
+/// ```ignore
+///     match c {
+///         1 => {
+///             a();
+///             b();
+///         }
+///         2 => {
+///             b();
+///         }
+///         _ => {}
+///     }
+/// ```
+pub struct SwitchToMatch;
+
+/// How an arm of the first `match` in a dispatch pair completes.
+enum ArmExit {
+    /// The arm sets the dispatch variable to a literal, given as source text.
+    Goto(String),
+    /// The arm never completes normally.
+    Diverges,
+}
+
+/// A `match` whose arms set a variable, followed by a `match` on that variable.
+struct Dispatch<'a> {
+    var: HirId,
+    /// The number of arms of the first `match` that set `var`.
+    gotos: usize,
+    /// The arms of the second `match`, keyed by the source text of their literal patterns.
+    targets: HashMap<String, &'a P<Expr>>,
+    /// The body of the second `match`'s `_` arm, if any.
+    default: Option<&'a P<Expr>>,
+}
+
+impl<'a> Dispatch<'a> {
+    fn target(&self, label: &str) -> Option<&'a P<Expr>> {
+        self.targets.get(label).cloned().or(self.default)
+    }
+}
+
+fn stmt_match(s: &Stmt) -> Option<(&P<Expr>, &Vec<Arm>)> {
+    match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+            ExprKind::Match(ref scrut, ref arms) => Some((scrut, arms)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn local_var(cx: &RefactorCtxt, e: &Expr) -> Option<HirId> {
+    match e.kind {
+        ExprKind::Paren(ref e) => local_var(cx, e),
+        ExprKind::Path(..) => cx.try_resolve_expr_to_hid(e),
+        _ => None,
+    }
+}
+
+/// Count the uses of the local `var` within `x`.
+fn count_uses<T: Visit>(cx: &RefactorCtxt, var: HirId, x: &T) -> usize {
+    let mut count = 0;
+    visit_nodes(x, |e: &Expr| {
+        if let ExprKind::Path(..) = e.kind {
+            if cx.try_resolve_expr_to_hid(e) == Some(var) {
+                count += 1;
+            }
+        }
+    });
+    count
+}
+
+/// Get the statements of an arm body, which may or may not be a block.
+fn body_stmts(e: &P<Expr>) -> Vec<Stmt> {
+    match e.kind {
+        ExprKind::Block(ref b, None) if b.rules == BlockCheckMode::Default => b.stmts.clone(),
+        _ => vec![Stmt {
+            id: DUMMY_NODE_ID,
+            span: e.span,
+            kind: StmtKind::Expr(e.clone()),
+        }],
+    }
+}
+
+fn arm_exit(cx: &RefactorCtxt, arm: &Arm, var: HirId) -> Option<ArmExit> {
+    let b = match_or!([arm.body.kind] ExprKind::Block(ref b, None) => b; return None);
+    if b.rules != BlockCheckMode::Default {
+        return None;
+    }
+    let last = match_or!([b.stmts.last().map(|s| &s.kind)]
+                         Some(&StmtKind::Semi(ref e)) => e,
+                         Some(&StmtKind::Expr(ref e)) => e;
+                         return None);
+    match last.kind {
+        ExprKind::Assign(ref lhs, ref rhs) if local_var(cx, lhs) == Some(var) => {
+            match rhs.kind {
+                ExprKind::Lit(..) => Some(ArmExit::Goto(pprust::expr_to_string(rhs))),
+                _ => None,
+            }
+        }
+        ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) => Some(ArmExit::Diverges),
+        _ => None,
+    }
+}
+
+/// Check whether moving `target` to the end of `arm` would change what any of its names refer to,
+/// because the arm declares a local with the same name.
+fn is_shadowed(arm: &Arm, target: &Expr) -> bool {
+    let mut names = HashSet::new();
+    if let ExprKind::Block(ref b, _) = arm.body.kind {
+        for s in &b.stmts {
+            if let StmtKind::Local(ref l) = s.kind {
+                visit_nodes(&*l.pat, |p: &Pat| {
+                    if let PatKind::Ident(_, ident, _) = p.kind {
+                        names.insert(ident.name);
+                    }
+                });
+            }
+        }
+    }
+    if names.is_empty() {
+        return false;
+    }
+
+    let mut shadowed = false;
+    visit_nodes(target, |e: &Expr| {
+        if let ExprKind::Path(None, ref path) = e.kind {
+            if path.segments.len() == 1 && names.contains(&path.segments[0].ident.name) {
+                shadowed = true;
+            }
+        }
+    });
+    shadowed
+}
+
+/// Check whether `s1` and `s2` form a dispatch pair, ignoring any other uses of the variable in
+/// `s1`.
+fn as_dispatch<'a>(cx: &RefactorCtxt, s1: &Stmt, s2: &'a Stmt) -> Option<Dispatch<'a>> {
+    let (scrut, arms2) = stmt_match(s2)?;
+    let var = local_var(cx, scrut)?;
+
+    let mut targets = HashMap::new();
+    let mut default = None;
+    for arm in arms2 {
+        if arm.guard.is_some() {
+            return None;
+        }
+        match arm.pat.kind {
+            PatKind::Lit(ref lit) => { targets.insert(pprust::expr_to_string(lit), &arm.body); }
+            PatKind::Wild => default = Some(&arm.body),
+            _ => return None,
+        }
+    }
+    let mut d = Dispatch { var, gotos: 0, targets, default };
+
+    let (_, arms1) = stmt_match(s1)?;
+    for arm in arms1 {
+        match arm_exit(cx, arm, var)? {
+            ArmExit::Goto(label) => {
+                let target = d.target(&label)?;
+                if is_shadowed(arm, target) {
+                    return None;
+                }
+                d.gotos += 1;
+            }
+            ArmExit::Diverges => {}
+        }
+    }
+    if d.gotos == 0 {
+        return None;
+    }
+    Some(d)
+}
+
+/// Merge adjacent arms that have no guards and equivalent bodies.
+fn merge_equal_arms(arms: Vec<Arm>) -> Vec<Arm> {
+    fn mergeable(p: &Pat) -> bool {
+        matches!([p.kind] PatKind::Lit(..), PatKind::Range(..), PatKind::Path(..), PatKind::Or(..))
+    }
+    fn alternatives(p: P<Pat>) -> Vec<P<Pat>> {
+        match p.kind {
+            PatKind::Or(ref pats) => pats.clone(),
+            _ => vec![p],
+        }
+    }
+
+    let mut out: Vec<Arm> = Vec::with_capacity(arms.len());
+    for arm in arms {
+        if let Some(prev) = out.last_mut() {
+            if prev.guard.is_none() && arm.guard.is_none() &&
+               mergeable(&prev.pat) && mergeable(&arm.pat) &&
+               prev.body.ast_equiv(&arm.body) {
+                let span = prev.pat.span;
+                let mut pats = alternatives(prev.pat.clone());
+                pats.extend(alternatives(arm.pat));
+                prev.pat = P(Pat { id: DUMMY_NODE_ID, kind: PatKind::Or(pats), span });
+                continue;
+            }
+        }
+        out.push(arm);
+    }
+    out
+}
+
+/// Rewrite the dispatch pairs on `convertible` variables among the statements of `b`.
+fn flatten_dispatches(cx: &RefactorCtxt, b: &mut Block, convertible: &HashSet<HirId>) {
+    let mut i = 0;
+    while i + 1 < b.stmts.len() {
+        let new_arms = {
+            let (s1, s2) = (&b.stmts[i], &b.stmts[i + 1]);
+            let d = match as_dispatch(cx, s1, s2) {
+                Some(d) if convertible.contains(&d.var) &&
+                           count_uses(cx, d.var, s1) == d.gotos => d,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+
+            let (_, arms1) = stmt_match(s1).unwrap();
+            let arms = arms1.iter().map(|arm| {
+                let label = match_or!([arm_exit(cx, arm, d.var)] Some(ArmExit::Goto(l)) => l;
+                                      return arm.clone());
+                let mut arm = arm.clone();
+                let target = d.target(&label).unwrap();
+                if let ExprKind::Block(ref mut body, _) = arm.body.kind {
+                    body.stmts.pop();
+                    body.stmts.extend(body_stmts(target));
+                }
+                arm
+            }).collect::<Vec<_>>();
+            merge_equal_arms(arms)
+        };
+
+        let s2 = b.stmts.remove(i + 1);
+        let s1 = b.stmts.remove(i);
+        // If the second `match` produced the value of the block, the merged one does now.
+        let semi = matches!([s1.kind] StmtKind::Semi(..)) &&
+                   !matches!([s2.kind] StmtKind::Expr(..));
+        let mut e = expect!([s1.kind] StmtKind::Expr(e) => e, StmtKind::Semi(e) => e);
+        if let ExprKind::Match(_, ref mut arms) = e.kind {
+            *arms = new_arms;
+        }
+        let kind = if semi { StmtKind::Semi(e) } else { StmtKind::Expr(e) };
+        b.stmts.insert(i, Stmt { kind, ..s1 });
+        i += 1;
+    }
+}
+
+impl Transform for SwitchToMatch {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        mut_visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref mut b) => b; return);
+
+            // (1) Find the dispatch pairs.  A pair is only valid if the variable isn't used in
+            // its first `match`, except by the arms that set it and by valid pairs nested inside.
+
+            struct Candidate {
+                /// The `Stmt` ID of the first `match`.
+                id: NodeId,
+                var: HirId,
+                uses: usize,
+                gotos: usize,
+                /// `Stmt` IDs of the first `match` and everything inside it.
+                stmts: HashSet<NodeId>,
+            }
+            let mut candidates = Vec::new();
+            visit_nodes(&**block, |b: &Block| {
+                for w in b.stmts.windows(2) {
+                    let d = match_or!([as_dispatch(cx, &w[0], &w[1])] Some(x) => x; continue);
+                    let mut stmts = HashSet::new();
+                    visit_nodes(&w[0], |s: &Stmt| { stmts.insert(s.id); });
+                    candidates.push(Candidate {
+                        id: w[0].id,
+                        var: d.var,
+                        uses: count_uses(cx, d.var, &w[0]),
+                        gotos: d.gotos,
+                        stmts,
+                    });
+                }
+            });
+
+            let mut valid = vec![false; candidates.len()];
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for (i, c) in candidates.iter().enumerate() {
+                    if valid[i] {
+                        continue;
+                    }
+                    let nested = candidates.iter().enumerate()
+                        .filter(|&(j, n)| valid[j] && n.var == c.var && n.id != c.id &&
+                                c.stmts.contains(&n.id))
+                        .map(|(_, n)| n.gotos + 1)
+                        .sum::<usize>();
+                    if c.uses == c.gotos + nested {
+                        valid[i] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            // A variable can be removed if all its uses are in valid pairs.
+            let mut paired = HashMap::new();
+            for (c, _) in candidates.iter().zip(&valid).filter(|&(_, &v)| v) {
+                *paired.entry(c.var).or_insert(0) += c.gotos + 1;
+            }
+            let convertible = paired.into_iter()
+                .filter(|&(var, n)| count_uses(cx, var, &**block) == n)
+                .map(|(var, _)| var)
+                .collect::<HashSet<_>>();
+            if convertible.is_empty() {
+                return;
+            }
+
+            // (2) Rewrite the pairs, innermost first, and remove the variables' declarations.
+
+            MutVisitNodes::visit(block, |b: &mut P<Block>| {
+                flatten_dispatches(cx, b, &convertible);
+                b.stmts.retain(|s| match s.kind {
+                    StmtKind::Local(ref l) => {
+                        let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+                        !convertible.contains(&hid)
+                    }
+                    _ => true,
+                });
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `loops_to_iterators` Command
+///
+/// Usage: `loops_to_iterators`
+///
+/// Rewrite loops that step through an array or pointer one element at a time into loops over
+/// iterators.  `while` loops on a counter are first turned into `for` loops over ranges, as in
+/// `reconstruct_for_range`.  Then:
+///
+///  * `for i in start .. end { ... }`, where the body only uses `i` to index a single array or
+///    slice `a`, becomes `for elem in a[start .. end].iter() { ... }`, with each `a[i]` replaced
+///    by `*elem`.  If `a[i]` is ever written or mutably borrowed, the loop uses `iter_mut()`
+///    instead.  If `i` is used for other purposes and `start` is zero, the loop becomes
+///    `for (i, elem) in a[.. end].iter().enumerate()`.
+///
+///  * `for i in start .. end { a[i] = b[i]; }` becomes
+///    `a[start .. end].copy_from_slice(&b[start .. end])`, and
+///    `for i in start .. end { a[i] = v; }`, where `v` is a value that does not depend on the
+///    loop, becomes `a[start .. end].fill(v)`.
+///
+///  * `while p < end { ...; p = p.offset(1); }`, where the body only uses `p` as `*p`, becomes
+///    a loop over `std::slice::from_raw_parts(p, ...)` (or `from_raw_parts_mut`, if `*p` is
+///    written), with each `*p` replaced by `*elem`.  This is only done when `p` is not used
+///    anywhere else in the function, since the loop no longer advances it, and when the
+///    points-to analysis shows that nothing else the body writes (or, for `from_raw_parts_mut`,
+///    accesses) may overlap the elements `p` walks over.
+///
+/// To avoid introducing aliasing, a loop is left alone if its body refers to the array some
+/// other way than through the loop index.  Loops over a range whose body may exit early with
+/// `break`, `return`, or `?` are also left alone, since slicing the array up front could panic
+/// where the original loop would not.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut sum = 0;
+///     for i in 0 .. n {
+///         sum += a[i];
+///     }
+///     for i in 0 .. n {
+///         b[i] = 0;
+///     }
+/// ```
+///
+/// After running `loops_to_iterators`:
+///
+/// ```ignore
+///     let mut sum = 0;
+///     for elem in a[..n].iter() {
+///         sum += *elem;
+///     }
+///     b[..n].fill(0);
+/// ```
+pub struct LoopsToIterators;
+
+/// Check if `e` is a path consisting only of the identifier `name`.
+fn is_name(e: &Expr, name: Symbol) -> bool {
+    match e.kind {
+        ExprKind::Path(None, ref path) =>
+            path.segments.len() == 1 && path.segments[0].ident.name == name,
+        _ => false,
+    }
+}
+
+/// Count the paths consisting only of the identifier `name` within `x`.
+fn count_names<T: Visit>(x: &T, name: Symbol) -> usize {
+    let mut count = 0;
+    visit_nodes(x, |e: &Expr| if is_name(e, name) { count += 1; });
+    count
+}
+
+/// Check if `x` contains a pattern that binds `name`.
+fn binds_name<T: Visit>(x: &T, name: Symbol) -> bool {
+    let mut found = false;
+    visit_nodes(x, |p: &Pat| match p.kind {
+        PatKind::Ident(_, ident, _) if ident.name == name => found = true,
+        _ => {}
+    });
+    found
+}
+
+/// Check if `x` contains anything that could exit a loop early.
+fn may_exit_early<T: Visit>(x: &T) -> bool {
+    let mut found = false;
+    visit_nodes(x, |e: &Expr| match e.kind {
+        ExprKind::Break(..) | ExprKind::Ret(..) | ExprKind::Try(..) => found = true,
+        _ => {}
+    });
+    found
+}
+
+/// Collect the IDs of the place expressions in `x` that may be written or mutably borrowed.  The
+/// receivers of method calls are included, since they may be autoref'd as `&mut`.
+pub fn mut_places<T: Visit>(x: &T) -> HashSet<NodeId> {
+    let mut ids = HashSet::new();
+    visit_nodes(x, |e: &Expr| {
+        let mut place = match e.kind {
+            ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) => lhs,
+            ExprKind::AddrOf(_, Mutability::Mutable, ref e) => e,
+            ExprKind::MethodCall(_, ref args) => &args[0],
+            _ => return,
+        };
+        loop {
+            ids.insert(place.id);
+            place = match place.kind {
+                ExprKind::Field(ref e, _) |
+                ExprKind::Index(ref e, _) |
+                ExprKind::Paren(ref e) => e,
+                _ => break,
+            };
+        }
+    });
+    ids
+}
+
+/// Pick a name for the loop's element variable that doesn't collide with anything in `body`.
+fn elem_name(body: &Block) -> String {
+    let mut names = HashSet::new();
+    visit_nodes(body, |e: &Expr| if let ExprKind::Path(None, ref path) = e.kind {
+        names.insert(path.segments[0].ident.name);
+    });
+    visit_nodes(body, |p: &Pat| if let PatKind::Ident(_, ident, _) = p.kind {
+        names.insert(ident.name);
+    });
+    (0..).map(|i| if i == 0 { "elem".to_owned() } else { format!("elem{}", i) })
+        .find(|n| !names.contains(&Symbol::intern(n)))
+        .unwrap()
+}
+
+fn label_str(label: Option<Label>) -> String {
+    label.map_or(String::new(), |l| format!("{}: ", l.ident))
+}
+
+/// Check if `e` has an array or slice type, possibly behind references.
+fn is_array_like(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let mut ty = match_or!([cx.opt_node_type(e.id)] Some(x) => x; return false);
+    while let TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    match ty.kind {
+        TyKind::Array(..) | TyKind::Slice(..) => true,
+        _ => false,
+    }
+}
+
+/// Render a range bound for use as a slice index.
+fn slice_bound(cx: &RefactorCtxt, e: &Expr) -> String {
+    let is_usize = match cx.opt_node_type(e.id) {
+        Some(ty) => ty.kind == TyKind::Uint(UintTy::Usize),
+        None => false,
+    };
+    match e.kind {
+        ExprKind::Lit(ref l) if l.kind == LitKind::Int(0, LitIntType::Unsuffixed) => String::new(),
+        ExprKind::Lit(Lit { kind: LitKind::Int(_, LitIntType::Unsuffixed), .. }) =>
+            pprust::expr_to_string(e),
+        _ if is_usize => pprust::expr_to_string(e),
+        ExprKind::Path(..) | ExprKind::Lit(..) => format!("{} as usize", pprust::expr_to_string(e)),
+        _ => format!("({}) as usize", pprust::expr_to_string(e)),
+    }
+}
+
+/// Try to rewrite `for i in start .. end { ... }` over the elements of an array.
+fn rewrite_index_loop(cx: &RefactorCtxt, e: &Expr) -> Option<P<Expr>> {
+    let (pat, iter, body, label) = match_or!([e.kind]
+        ExprKind::ForLoop(ref pat, ref iter, ref body, label) => (pat, iter, body, label);
+        return None);
+    let i = match_or!([pat.kind]
+        PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), ident, None) => ident.name;
+        return None);
+    let (start, end) = match_or!([strip_parens(iter).kind]
+        ExprKind::Range(Some(ref start), Some(ref end), RangeLimits::HalfOpen) => (start, end);
+        return None);
+    if binds_name(&**body, i) {
+        return None;
+    }
+
+    // Find each `a[i]`, along with the name of `a`.
+    let mut indexes = Vec::new();
+    visit_nodes(&**body, |e: &Expr| {
+        if let ExprKind::Index(ref base, ref idx) = e.kind {
+            if !is_name(strip_casts(idx), i) {
+                return;
+            }
+            if let ExprKind::Path(None, ref path) = base.kind {
+                if path.segments.len() == 1 && is_array_like(cx, base) {
+                    indexes.push((path.segments[0].ident.name, e.id));
+                    return;
+                }
+            }
+            // Indexing into something we can't handle.
+            indexes.push((i, e.id));
+        }
+    });
+    if indexes.is_empty() || indexes.iter().any(|&(a, _)| a == i) {
+        return None;
+    }
+    let a = indexes[0].0;
+    let start_str = slice_bound(cx, start);
+    let range = format!("{}..{}", start_str, slice_bound(cx, end));
+
+    // `a[i] = b[i];` and `a[i] = v;`
+    if let [ref stmt] = body.stmts[..] {
+        let assign = match stmt.kind {
+            StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if lhs.id == indexes[0].1 => Some(rhs),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(rhs) = assign {
+            if let [_, (b, id)] = indexes[..] {
+                if b != a && id == rhs.id && count_names(&**body, i) == 2 &&
+                   count_names(&**body, a) == 1 && count_names(&**body, b) == 1 {
+                    let src = format!("{}[{}].copy_from_slice(&{}[{}])", a, range, b, range);
+                    return Some(parse_expr(cx.session(), &src));
+                }
+            }
+            let mut invariant = indexes.len() == 1;
+            visit_nodes(&**rhs, |e: &Expr| match e.kind {
+                ExprKind::Call(..) | ExprKind::MethodCall(..) | ExprKind::Mac(..) |
+                ExprKind::Assign(..) | ExprKind::AssignOp(..) => invariant = false,
+                _ if is_name(e, i) || is_name(e, a) => invariant = false,
+                _ => {}
+            });
+            if invariant {
+                let src = format!("{}[{}].fill({})", a, range, pprust::expr_to_string(rhs));
+                return Some(parse_expr(cx.session(), &src));
+            }
+        }
+    }
+
+    // The general case.  The body must not refer to `a` except through `a[i]`.
+    if indexes.iter().any(|&(b, _)| b != a) || count_names(&**body, a) != indexes.len() {
+        return None;
+    }
+    if may_exit_early(&**body) {
+        return None;
+    }
+    let enumerate = count_names(&**body, i) > indexes.len();
+    if enumerate && !start_str.is_empty() {
+        return None;
+    }
+    // `enumerate` produces a `usize`, so other uses of `i` may need a cast.
+    let mut i_cast = None;
+    if enumerate {
+        let mut i_ty = None;
+        visit_nodes(&**body, |e: &Expr| if is_name(e, i) && i_ty.is_none() {
+            i_ty = cx.opt_node_type(e.id);
+        });
+        match i_ty.map(|ty| &ty.kind) {
+            Some(&TyKind::Uint(UintTy::Usize)) => {}
+            Some(&TyKind::Int(_)) | Some(&TyKind::Uint(_)) => i_cast = i_ty,
+            _ => return None,
+        }
+    }
+
+    let mutable = {
+        let places = mut_places(&**body);
+        indexes.iter().any(|&(_, id)| places.contains(&id))
+    };
+    let elem = elem_name(body);
+    let elem_expr = parse_expr(cx.session(), &format!("*{}", elem));
+    let index_ids = indexes.iter().map(|&(_, id)| id).collect::<HashSet<_>>();
+    let mut new_body = body.clone();
+    MutVisitNodes::visit(&mut new_body, |e: &mut P<Expr>| {
+        if index_ids.contains(&e.id) {
+            *e = elem_expr.clone();
+        } else if let Some(ty) = i_cast {
+            if is_name(e, i) {
+                *e = parse_expr(cx.session(), &format!("({} as {})", i, ty));
+            }
+        }
+    });
+
+    let iter_method = if mutable { "iter_mut" } else { "iter" };
+    let src = if enumerate {
+        format!("{}for ({}, {}) in {}[{}].{}().enumerate() {{}}",
+                label_str(label), i, elem, a, range, iter_method)
+    } else {
+        format!("{}for {} in {}[{}].{}() {{}}", label_str(label), elem, a, range, iter_method)
+    };
+    Some(with_loop_body(parse_expr(cx.session(), &src), new_body))
+}
+
+/// Try to rewrite `while p < end { ...; p = p.offset(1); }` over the elements between `p` and
+/// `end`.  `fn_body` is the original body of the enclosing function, and `pts` is the result of
+/// the points-to analysis on the original crate.
+fn rewrite_ptr_walk(cx: &RefactorCtxt, e: &Expr, fn_body: &Block, pts: &PointsTo)
+                    -> Option<P<Expr>> {
+    let (cond, body, label) = match_or!([e.kind]
+        ExprKind::While(ref cond, ref body, label) => (cond, body, label);
+        return None);
+    let (ptr, end) = match strip_parens(cond).kind {
+        ExprKind::Binary(op, ref lhs, ref rhs) if op.node == BinOpKind::Lt => (lhs, rhs),
+        _ => return None,
+    };
+    let p = match ptr.kind {
+        ExprKind::Path(None, ref path) if path.segments.len() == 1 => path.segments[0].ident.name,
+        _ => return None,
+    };
+    let p_hid = cx.try_resolve_expr_to_hid(ptr)?;
+    let mutbl = match_or!([cx.opt_node_type(ptr.id).map(|ty| &ty.kind)]
+                          Some(&TyKind::RawPtr(mt)) => mt.mutbl; return None);
+
+    // The last statement must be `p = p.offset(1)` or `p = p.add(1)`.
+    let (last, rest) = body.stmts.split_last()?;
+    let step = match last.kind {
+        StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => e,
+        _ => return None,
+    };
+    let (lhs, rhs) = match_or!([step.kind]
+        ExprKind::Assign(ref lhs, ref rhs) => (lhs, rhs); return None);
+    let (seg, args) = match_or!([rhs.kind]
+        ExprKind::MethodCall(ref seg, ref args) => (seg, args); return None);
+    if !is_name(lhs, p) || !is_name(&args[0], p) || args.len() != 2 ||
+       !(seg.ident.as_str() == "offset" || seg.ident.as_str() == "add") ||
+       !is_one_expr(strip_casts(&args[1])) {
+        return None;
+    }
+
+    // Every other use of `p` in the body must be `*p`, and `end` must not change in the loop.
+    let mut derefs = HashSet::new();
+    let mut uses = 0;
+    for s in rest {
+        if binds_name(s, p) {
+            return None;
+        }
+        uses += count_names(s, p);
+        visit_nodes(s, |e: &Expr| match e.kind {
+            ExprKind::Unary(UnOp::Deref, ref inner) if is_name(inner, p) => {
+                derefs.insert(e.id);
+            }
+            _ => {}
+        });
+    }
+    if uses != derefs.len() {
+        return None;
+    }
+    let mut end_names = Vec::new();
+    visit_nodes(&**end, |e: &Expr| if let ExprKind::Path(None, ref path) = e.kind {
+        end_names.push(path.segments[0].ident.name);
+    });
+    if end_names.iter().any(|&n| n == p || rest.iter().any(|s| count_names(s, n) > 0)) {
+        return None;
+    }
+    // The loop no longer advances `p`, so it must be dead afterward.
+    if count_uses(cx, p_hid, fn_body) != count_uses(cx, p_hid, e) {
+        return None;
+    }
+
+    let places = mut_places(&**body);
+    let writes = derefs.iter().any(|id| places.contains(id));
+    if writes && mutbl == Mutability::Immutable {
+        return None;
+    }
+
+    // The slice must not overlap anything the body accesses some other way: through another
+    // pointer that may alias `p`, or directly through a variable `p` may point into.  Reads are
+    // fine as long as neither side writes.
+    let p_objs = pts.points_to(ptr)?;
+    let mut overlaps = false;
+    for s in rest {
+        visit_nodes(s, |e: &Expr| {
+            let conflict = match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) if !derefs.contains(&e.id) => {
+                    pts.aliases(ptr, inner)
+                }
+                ExprKind::Path(..) => match cx.try_resolve_expr_to_hid(e) {
+                    Some(hid) => hid != p_hid && p_objs.contains(&Loc::Var(hid)),
+                    None => false,
+                },
+                _ => false,
+            };
+            if conflict && (writes || places.contains(&e.id)) {
+                overlaps = true;
+            }
+        });
+    }
+    if overlaps {
+        return None;
+    }
+
+    let elem = elem_name(body);
+    let elem_expr = parse_expr(cx.session(), &format!("*{}", elem));
+    let mut new_body = body.clone();
+    new_body.stmts.pop();
+    MutVisitNodes::visit(&mut new_body, |e: &mut P<Expr>| if derefs.contains(&e.id) {
+        *e = elem_expr.clone();
+    });
+
+    let src = format!(
+        "{}for {} in ::std::slice::{}({}, {}.offset_from({}).max(0) as usize) {{}}",
+        label_str(label), elem, if writes { "from_raw_parts_mut" } else { "from_raw_parts" },
+        p, pprust::expr_to_string(end), p);
+    Some(with_loop_body(parse_expr(cx.session(), &src), new_body))
+}
+
+pub fn strip_parens(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Paren(ref e) => strip_parens(e),
+        _ => e,
+    }
+}
+
+/// Replace the body of the parsed `for` loop `e`.
+fn with_loop_body(mut e: P<Expr>, body: P<Block>) -> P<Expr> {
+    if let ExprKind::ForLoop(_, _, ref mut b, _) = e.kind {
+        *b = body;
+    }
+    e
+}
+
+impl Transform for LoopsToIterators {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // `ReconstructForRange` leaves `while` loops it doesn't convert in place, so the analysis
+        // results still apply to them.
+        let pts = points_to::analyze(cx, krate);
+        ReconstructForRange.transform(krate, st, cx);
+
+        mut_visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref mut b) => b; return);
+            let orig = block.clone();
+            MutVisitNodes::visit(block, |b: &mut P<Block>| {
+                for s in &mut b.stmts {
+                    let new = match s.kind {
+                        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => {
+                            match rewrite_index_loop(cx, e)
+                                .or_else(|| rewrite_ptr_walk(cx, e, &orig, &pts)) {
+                                Some(x) => x,
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    // `copy_from_slice` and `fill` calls need a semicolon.
+                    s.kind = match new.kind {
+                        ExprKind::ForLoop(..) => StmtKind::Expr(new),
+                        _ => StmtKind::Semi(new),
+                    };
+                }
+            });
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `restructure_goto_cleanup` Command
+///
+/// Usage: `restructure_goto_cleanup`
+///
+/// Remove the labeled blocks that the transpiler emits for C's `goto cleanup` idiom, where
+/// several error paths jump forward to a single label that frees resources before returning.
+/// The translation of such a function looks like this:
+///
+/// ```ignore
+///     'fail: {
+///         a = malloc(...);
+///         if a.is_null() {
+///             break 'fail;
+///         }
+///         ...
+///         ret = 0;
+///     }
+///     free(a);
+///     return ret;
+/// ```
+///
+/// After running `restructure_goto_cleanup`, the label is gone, and each `break` performs the
+/// cleanup and returns directly, calling a closure generated from the cleanup code:
+///
+/// ```ignore
+///     let cleanup_fail = |a: *mut libc::c_void| {
+///         free(a);
+///     };
+///     {
+///         a = malloc(...);
+///         if a.is_null() {
+///             cleanup_fail(a);
+///             return ret;
+///         }
+///         ...
+///         ret = 0;
+///     }
+///     free(a);
+///     return ret;
+/// ```
+///
+/// The cleanup code is the code between the labeled block and the end of the function, or the
+/// final `return` or trailing expression.  Ladders of nested labeled blocks are handled as well:
+/// the code following an inner block ends by falling out of the outer block, so the inner
+/// block's `break`s become a call to its cleanup closure followed by a `break` out of the outer
+/// block, which is in turn rewritten when the outer block is processed.
+///
+/// The cleanup closure takes the local variables it uses as arguments, so it doesn't hold any
+/// borrows while the block runs.  A labeled block is left alone if its cleanup code assigns to
+/// local variables, uses local variables that aren't `Copy` scalars or raw pointers, or contains
+/// `return`, `break`, or `continue`, or if the block's body declares variables that would
+/// shadow the ones the cleanup code uses.
+pub struct RestructureGotoCleanup;
+
+/// Check if `ty` is a scalar or raw pointer, which can be passed by copy.
+fn is_scalar_ty(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Bool | TyKind::Char | TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) |
+        TyKind::RawPtr(_) | TyKind::FnPtr(_) => true,
+        _ => false,
+    }
+}
+
+/// If `s` is a labeled block, return its label.
+fn stmt_label(s: &Stmt) -> Option<Label> {
+    match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => match e.kind {
+            ExprKind::Block(_, label) => label,
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn stmt_block_mut(s: &mut Stmt) -> &mut P<Block> {
+    match s.kind {
+        StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => match e.kind {
+            ExprKind::Block(ref mut b, _) => b,
+            _ => panic!("expected a block statement"),
+        },
+        _ => panic!("expected a block statement"),
+    }
+}
+
+/// Build the code that runs when the labeled block at `b.stmts[k]` is exited early, given that
+/// the cleanup code `b.stmts[k + 1 .. end]` is followed by `exit`.  Returns the closure
+/// definition, if one is needed, and the source of the early exit.
+fn cleanup_exit(cx: &RefactorCtxt,
+                b: &Block,
+                k: usize,
+                end: usize,
+                exit: &str) -> Option<(Option<String>, String)> {
+    let label = stmt_label(&b.stmts[k])?;
+    let cleanup = &b.stmts[k + 1 .. end];
+    if cleanup.is_empty() {
+        return Some((None, exit.to_owned()));
+    }
+
+    let mut ok = true;
+    // Locals declared inside the cleanup code don't need to be passed in.
+    let mut inner_locals = HashSet::new();
+    let mut written = HashSet::new();
+    for s in cleanup {
+        visit_nodes(s, |p: &Pat| { inner_locals.insert(cx.hir_map().node_to_hir_id(p.id)); });
+        visit_nodes(s, |e: &Expr| match e.kind {
+            ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) => ok = false,
+            ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+            ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => {
+                written.insert(lhs.id);
+            }
+            _ => {}
+        });
+    }
+    if !ok {
+        return None;
+    }
+
+    let mut params = Vec::<(HirId, String, String)>::new();
+    for s in cleanup {
+        visit_nodes(s, |e: &Expr| {
+            let hid = match_or!([cx.try_resolve_expr_hir(e)]
+                                Some(hir::def::Res::Local(x)) => x; return);
+            if inner_locals.contains(&hid) || params.iter().any(|p| p.0 == hid) {
+                return;
+            }
+            let ty = match_or!([cx.opt_node_type(e.id)] Some(x) => x; { ok = false; return });
+            if written.contains(&e.id) || !is_scalar_ty(ty) {
+                ok = false;
+                return;
+            }
+            params.push((hid, pprust::expr_to_string(e),
+                         pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ty))));
+        });
+    }
+    if !ok {
+        return None;
+    }
+
+    let name = format!("cleanup_{}", label.ident.as_str().trim_start_matches('\''));
+    let body = cleanup.iter().map(|s| pprust::stmt_to_string(s)).collect::<Vec<_>>();
+    let closure = format!("let {} = |{}| {{ {} }};",
+                          name,
+                          params.iter()
+                              .map(|p| format!("{}: {}", p.1, p.2))
+                              .collect::<Vec<_>>().join(", "),
+                          body.join(" "));
+    let call = format!("{}({})",
+                       name,
+                       params.iter().map(|p| p.1.clone()).collect::<Vec<_>>().join(", "));
+    Some((Some(closure), format!("{{ {}; {}; }}", call, exit)))
+}
+
+/// Collect the names of the variables bound anywhere in `x`.
+fn bound_names<T: Visit>(x: &T) -> HashSet<Symbol> {
+    let mut names = HashSet::new();
+    visit_nodes(x, |p: &Pat| if let PatKind::Ident(_, ident, _) = p.kind {
+        names.insert(ident.name);
+    });
+    names
+}
+
+/// Check if `e` is `break 'label`.
+fn is_break_to(e: &Expr, label: Label) -> bool {
+    match e.kind {
+        ExprKind::Break(Some(l), None) => l.ident == label.ident,
+        _ => false,
+    }
+}
+
+/// Remove the cleanup labels among the statements of `b`, processing nested labeled blocks
+/// first.  The statements `b.stmts[.. end]` are followed by `exit`.
+fn restructure_cleanup_labels(cx: &RefactorCtxt, b: &mut Block, mut end: usize, exit: &str) {
+    let mut k = end;
+    while k > 0 {
+        k -= 1;
+        let label = match_or!([stmt_label(&b.stmts[k])] Some(x) => x; continue);
+
+        {
+            let body = stmt_block_mut(&mut b.stmts[k]);
+            let len = body.stmts.len();
+            restructure_cleanup_labels(cx, body, len, &format!("break {}", label.ident));
+        }
+
+        let (closure, early_exit) = match_or!([cleanup_exit(cx, b, k, end, exit)] Some(x) => x;
+                                              continue);
+        let early_exit = parse_expr(cx.session(), &early_exit);
+
+        // The early exits are moved into the block, so they must not refer to any variable that
+        // the block shadows.
+        let mut exit_names = HashSet::new();
+        visit_nodes(&*early_exit, |e: &Expr| if let ExprKind::Path(None, ref path) = e.kind {
+            exit_names.insert(path.segments[0].ident.name);
+        });
+        let body = stmt_block_mut(&mut b.stmts[k]);
+        if bound_names(&**body).iter().any(|n| exit_names.contains(n)) {
+            continue;
+        }
+        let mut has_exits = false;
+        visit_nodes(&**body, |e: &Expr| has_exits |= is_break_to(e, label));
+
+        // Replace `break 'label;` statements with the statements of the early exit, and any
+        // other `break 'label` with the whole early exit.
+        let exit_stmts = match early_exit.kind {
+            ExprKind::Block(ref eb, None) => eb.stmts.clone(),
+            _ => vec![Stmt {
+                id: DUMMY_NODE_ID,
+                kind: StmtKind::Semi(early_exit.clone()),
+                span: early_exit.span,
+            }],
+        };
+        MutVisitNodes::visit(body, |b: &mut P<Block>| {
+            let stmts = b.stmts.drain(..).collect::<Vec<_>>();
+            for s in stmts {
+                let is_exit = match s.kind {
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => is_break_to(e, label),
+                    _ => false,
+                };
+                if is_exit {
+                    b.stmts.extend(exit_stmts.iter().cloned());
+                } else {
+                    b.stmts.push(s);
+                }
+            }
+        });
+        MutVisitNodes::visit(body, |e: &mut P<Expr>| if is_break_to(e, label) {
+            *e = early_exit.clone();
+        });
+
+        match b.stmts[k].kind {
+            StmtKind::Expr(ref mut e) | StmtKind::Semi(ref mut e) => match e.kind {
+                ExprKind::Block(_, ref mut l) => *l = None,
+                _ => {}
+            },
+            _ => {}
+        }
+        if let (true, Some(src)) = (has_exits, closure) {
+            let stmts = parse_stmts(cx.session(), &src);
+            end += stmts.len();
+            b.stmts.splice(k .. k, stmts);
+        }
+    }
+}
+
+impl Transform for RestructureGotoCleanup {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        mut_visit_fns(krate, |fl| {
+            let returns_unit = match fl.decl.output {
+                FunctionRetTy::Default(..) => true,
+                _ => false,
+            };
+            let block = match_or!([fl.block] Some(ref mut b) => b; return);
+
+            // Find the code that ends the function, which the cleanup code is followed by.
+            let n = block.stmts.len();
+            let tail = block.stmts.last().and_then(|s| match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                    ExprKind::Ret(Some(ref r)) =>
+                        Some(format!("return {}", pprust::expr_to_string(r))),
+                    ExprKind::Ret(None) => Some("return".to_owned()),
+                    // Only simple trailing expressions are copied into the early exits.
+                    ExprKind::Path(..) | ExprKind::Lit(..) | ExprKind::Field(..) |
+                    ExprKind::Cast(..) | ExprKind::Unary(..)
+                            if matches!([s.kind] StmtKind::Expr(..)) =>
+                        Some(format!("return {}", pprust::expr_to_string(e))),
+                    _ => None,
+                },
+                _ => None,
+            });
+            match tail {
+                Some(exit) => restructure_cleanup_labels(cx, block, n - 1, &exit),
+                None if returns_unit => restructure_cleanup_labels(cx, block, n, "return"),
+                None => {}
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("reconstruct_while", |_args| mk(ReconstructWhile));
     reg.register("reconstruct_for_range", |_args| mk(ReconstructForRange));
     reg.register("remove_unused_labels", |_args| mk(RemoveUnusedLabels));
+    reg.register("switch_to_match", |_args| mk(SwitchToMatch));
+    reg.register("loops_to_iterators", |_args| mk(LoopsToIterators));
+    reg.register("restructure_goto_cleanup", |_args| mk(RestructureGotoCleanup));
 }
@@ -0,0 +1,336 @@
+//! Conversion of `dlopen` and `dlsym` to `libloading`.
+
+use std::collections::HashSet;
+use rustc::hir::HirId;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// If `e` is `dlopen(path, flags)`, get `path` and `flags`.
+fn as_dlopen(e: &Expr) -> Option<(&Expr, &Expr)> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "dlopen") || args.len() != 2 {
+        return None;
+    }
+    Some((&args[0], &args[1]))
+}
+
+/// If `e` is `dlsym(handle, name)`, get `handle` and `name`.
+fn as_dlsym(e: &Expr) -> Option<(&Expr, &Expr)> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "dlsym") || args.len() != 2 {
+        return None;
+    }
+    Some((strip_casts(&args[0]), &args[1]))
+}
+
+/// If `ty` is `Option<F>`, get `F`.
+fn option_arg(ty: &Ty) -> Option<&Ty> {
+    let path = match_or!([ty.kind] TyKind::Path(None, ref p) => p; return None);
+    let seg = path.segments.last()?;
+    if seg.ident.as_str() != "Option" {
+        return None;
+    }
+    let args = match_or!([seg.args.as_ref().map(|a| &**a)]
+                         Some(GenericArgs::AngleBracketed(ref a)) => a; return None);
+    match args.args.as_slice() {
+        [GenericArg::Type(ref ty)] => Some(ty),
+        _ => None,
+    }
+}
+
+/// How the result of a `dlsym` call is used.
+enum SymbolUse<'a> {
+    /// `transmute::<*mut c_void, Option<F>>(dlsym(..))`: a function of type `F`.
+    Function(&'a Ty),
+    /// `dlsym(..) as *mut T`, or `dlsym(..)` on its own: a data symbol of pointer type `T`.
+    Data(&'a Ty),
+}
+
+/// If `e` is a use of a `dlsym` call that we can convert, get the call and how it's used.
+fn as_symbol_use(e: &Expr) -> Option<(&Expr, SymbolUse)> {
+    match e.kind {
+        ExprKind::Call(ref func, ref args) if args.len() == 1 && is_call_to(e, "transmute") => {
+            let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+            let generic_args = path.segments.last()?.args.as_ref()?;
+            let generic_args = match_or!([**generic_args]
+                                         GenericArgs::AngleBracketed(ref a) => a; return None);
+            let ty = match generic_args.args.as_slice() {
+                [_, GenericArg::Type(ref ty)] => option_arg(ty)?,
+                _ => return None,
+            };
+            let call = strip_casts(&args[0]);
+            as_dlsym(call)?;
+            Some((call, SymbolUse::Function(ty)))
+        }
+        ExprKind::Cast(ref inner, ref ty) if matches!([ty.kind] TyKind::Ptr(..)) => {
+            let call = strip_casts(inner);
+            as_dlsym(call)?;
+            Some((call, SymbolUse::Data(ty)))
+        }
+        _ => None,
+    }
+}
+
+/// Build an expression for the name of a symbol, as a byte string.
+fn symbol_name_str(name: &Expr) -> String {
+    let name = strip_casts(name);
+    match name.kind {
+        ExprKind::Lit(ref l) if matches!([l.kind] LitKind::ByteStr(..)) =>
+            pprust::expr_to_string(name),
+        _ => format!("::std::ffi::CStr::from_ptr({}).to_bytes()", pprust::expr_to_string(name)),
+    }
+}
+
+
+/// # `dlopen_to_libloading` Command
+///
+/// Usage: `dlopen_to_libloading`
+///
+/// Marks: `target`
+///
+/// Convert each marked local that is initialized with `dlopen` into an
+/// `Option<libloading::Library>`, which is `None` if loading failed:
+///
+///  * `dlopen(path, flags)` becomes a call to
+///    `libloading::os::unix::Library::open` with the same flags, and
+///    `dlopen(NULL, flags)` becomes `Library::this()`.
+///
+///  * A function symbol, `transmute::<*mut c_void, Option<F>>(dlsym(h, name))`,
+///    becomes `h.as_ref().and_then(|library| library.get::<F>(name).ok()).map(|s| *s)`,
+///    with the function type `F` taken from the `transmute`.
+///
+///  * A data symbol, `dlsym(h, name) as *mut T`, becomes a lookup of a
+///    `*mut T` in the same way, with a null pointer if it isn't found.
+///
+///  * `h.is_null()` becomes `h.is_none()`, and `dlclose(h);` drops the library.
+///
+/// Locals with any other uses are left unchanged, and a warning is printed.
+/// `libloading` takes the error message that `dlerror` would return, so
+/// `dlerror()` returns null after a converted call fails; a warning is printed
+/// for functions that call it.  The converted crate needs a dependency on
+/// `libloading`.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut lib: *mut libc::c_void = dlopen(path, 0x2 as libc::c_int);
+///     if lib.is_null() {
+///         return -1;
+///     }
+///     let mut init: Option<unsafe extern "C" fn() -> libc::c_int> =
+///         ::std::mem::transmute::<*mut libc::c_void,
+///                                 Option<unsafe extern "C" fn() -> libc::c_int>>(
+///             dlsym(lib, b"plugin_init\0" as *const u8 as *const libc::c_char));
+///     let ret = init.expect("non-null function pointer")();
+///     dlclose(lib);
+/// ```
+///
+/// After running `dlopen_to_libloading`:
+///
+/// ```ignore
+///     let mut lib: Option<::libloading::Library> = ::libloading::os::unix::Library::open(
+///         Some(<::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(
+///             ::std::ffi::CStr::from_ptr(path).to_bytes())),
+///         0x2 as libc::c_int).map(::libloading::Library::from).ok();
+///     if lib.is_none() {
+///         return -1;
+///     }
+///     let mut init: Option<unsafe extern "C" fn() -> libc::c_int> = lib.as_ref()
+///         .and_then(|library| library.get::<unsafe extern "C" fn() -> libc::c_int>(
+///             b"plugin_init\0").ok())
+///         .map(|s| *s);
+///     let ret = init.expect("non-null function pointer")();
+///     lib = None;
+/// ```
+pub struct DlopenToLibloading;
+
+impl Transform for DlopenToLibloading {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let var_of = |e: &Expr| -> Option<HirId> {
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            cx.try_resolve_expr_to_hid(e)
+        };
+
+        // (1) Collect the marked locals that are initialized with `dlopen`.
+
+        let mut vars = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            if l.init.as_ref().and_then(|init| as_dlopen(init)).is_some() {
+                vars.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+            } else {
+                warn!("local `{}` is not initialized with `dlopen`; skipping it",
+                      pprust::pat_to_string(&l.pat));
+            }
+        });
+
+        // (2) Check that every use of each library is one we can convert.
+
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                stmt_exprs.insert(e.id);
+            }
+        });
+        let mut ok_uses = HashSet::new();
+        let mut uses = Vec::new();
+        // `dlsym` calls whose result is converted along with the expression using it.
+        let mut symbol_calls = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(var) = var_of(e).filter(|v| vars.contains(v)) {
+                uses.push((e.id, var));
+            }
+            if let Some((call, _)) = as_symbol_use(e) {
+                symbol_calls.insert(call.id);
+            }
+            if let Some((handle, _)) = as_dlsym(e) {
+                ok_uses.insert(handle.id);
+            }
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args)
+                    if args.len() == 1 && seg.ident.as_str() == "is_null" => {
+                    ok_uses.insert(args[0].id);
+                }
+                ExprKind::Call(_, ref args)
+                    if is_call_to(e, "dlclose") && args.len() == 1 &&
+                       stmt_exprs.contains(&e.id) => {
+                    ok_uses.insert(strip_casts(&args[0]).id);
+                }
+                _ => {}
+            }
+        });
+        for (id, var) in uses {
+            if !ok_uses.contains(&id) && vars.remove(&var) {
+                warn!("{:?} has uses that can't be converted; skipping it", var);
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+        let is_lib = |e: &Expr| var_of(e).map_or(false, |v| vars.contains(&v));
+
+        visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref b) => b; return);
+            let mut converted = false;
+            let mut calls_dlerror = false;
+            visit_nodes(&**block, |e: &Expr| {
+                converted |= is_lib(e);
+                calls_dlerror |= is_call_to(e, "dlerror");
+            });
+            if converted && calls_dlerror {
+                warn!("`{}` calls `dlerror`, which returns null after a converted call fails",
+                      fl.ident);
+            }
+        });
+
+        // (3) Change the types and initializers of the libraries.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if !vars.contains(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                return;
+            }
+            let src = {
+                let (path, flags) = as_dlopen(l.init.as_ref().unwrap()).unwrap();
+                if is_null_ptr(path) {
+                    "Some(::libloading::Library::from(::libloading::os::unix::Library::this()))"
+                        .to_owned()
+                } else {
+                    format!("::libloading::os::unix::Library::open(\
+                                 Some(<::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>\
+                                     ::from_bytes(::std::ffi::CStr::from_ptr({}).to_bytes())), \
+                                 {}).map(::libloading::Library::from).ok()",
+                            pprust::expr_to_string(path), pprust::expr_to_string(flags))
+                }
+            };
+            l.ty = Some(parse_ty(cx.session(), "Option<::libloading::Library>"));
+            l.init = Some(parse_expr(cx.session(), &src));
+        });
+
+        // (4) Rewrite the `dlclose` statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; continue);
+                    if !is_call_to(e, "dlclose") || args.len() != 1 ||
+                       !is_lib(strip_casts(&args[0])) {
+                        continue;
+                    }
+                    format!("{} = None;", pprust::expr_to_string(strip_casts(&args[0])))
+                };
+                *s = parse_stmts(cx.session(), &src).lone();
+            }
+        });
+
+        // (5) Rewrite the symbol lookups and null checks.
+
+        let lookup_str = |call: &Expr, ty: &Ty| -> Option<String> {
+            let (handle, name) = as_dlsym(call)?;
+            if !is_lib(handle) {
+                return None;
+            }
+            Some(format!("{}.as_ref().and_then(|library| library.get::<{}>({}).ok())",
+                         pprust::expr_to_string(handle), pprust::ty_to_string(ty),
+                         symbol_name_str(name)))
+        };
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = match e.kind {
+                ExprKind::MethodCall(ref seg, ref args)
+                    if args.len() == 1 && seg.ident.as_str() == "is_null" && is_lib(&args[0]) =>
+                    format!("{}.is_none()", pprust::expr_to_string(&args[0])),
+                _ => if let Some((call, symbol)) = as_symbol_use(e) {
+                    match symbol {
+                        SymbolUse::Function(ty) => {
+                            let lookup = match_or!([lookup_str(call, ty)] Some(x) => x; return);
+                            format!("{}.map(|s| *s)", lookup)
+                        }
+                        SymbolUse::Data(ty) => {
+                            let lookup = match_or!([lookup_str(call, ty)] Some(x) => x; return);
+                            let null = match ty.kind {
+                                TyKind::Ptr(MutTy { mutbl: Mutability::Mutable, .. }) =>
+                                    "::std::ptr::null_mut()",
+                                _ => "::std::ptr::null()",
+                            };
+                            format!("{}.map_or({}, |s| *s)", lookup, null)
+                        }
+                    }
+                } else if as_dlsym(e).is_some() && !symbol_calls.contains(&e.id) {
+                    let ty = parse_ty(cx.session(), "*mut ::std::ffi::c_void");
+                    let lookup = match_or!([lookup_str(e, &ty)] Some(x) => x; return);
+                    format!("{}.map_or(::std::ptr::null_mut(), |s| *s)", lookup)
+                } else {
+                    return;
+                },
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("dlopen_to_libloading", |_args| mk(DlopenToLibloading));
+}
@@ -0,0 +1,204 @@
+//! Conversion of C `errno` accesses to `std::io::Error` and the `c2rust-errno` shim.
+
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::ptr::P;
+
+use crate::ast_manip::MutVisit;
+use crate::command::{CommandState, Registry};
+use crate::driver::parse_expr;
+use crate::matcher::{Bindings, Subst};
+use crate::transform::Transform;
+use crate::transform::control_flow::strip_parens;
+use crate::RefactorCtxt;
+
+
+/// Functions that the C library's `errno` macro calls to get the location of `errno`: glibc and
+/// musl, macOS and the BSDs, and Android and Solaris.
+const LIBC_ERRNO_FNS: &[&str] = &["__errno_location", "__error", "__errno", "___errno"];
+
+/// `errno` values that `io::Error::kind` maps to an `ErrorKind` that no other value maps to, so
+/// that comparing kinds is the same as comparing the values.
+const ERROR_KINDS: &[(&str, &str)] = &[
+    ("EINTR", "Interrupted"),
+    ("ENOENT", "NotFound"),
+    ("EEXIST", "AlreadyExists"),
+    ("EINVAL", "InvalidInput"),
+    ("EPIPE", "BrokenPipe"),
+    ("EAGAIN", "WouldBlock"),
+    ("EWOULDBLOCK", "WouldBlock"),
+    ("ECONNREFUSED", "ConnectionRefused"),
+    ("ECONNRESET", "ConnectionReset"),
+    ("ECONNABORTED", "ConnectionAborted"),
+    ("ENOTCONN", "NotConnected"),
+    ("EADDRINUSE", "AddrInUse"),
+    ("EADDRNOTAVAIL", "AddrNotAvailable"),
+    ("ETIMEDOUT", "TimedOut"),
+];
+
+/// Where an `errno` access gets its value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ErrnoSource {
+    /// The C library's `errno`, through `*__errno_location()` or similar.
+    Libc,
+    /// The thread-local from `c2rust-errno`, through `*c2rust_errno::errno_location()`.
+    Shim,
+}
+
+/// If `e` is `*__errno_location()` or one of its equivalents, return where it gets `errno` from.
+fn as_errno(e: &Expr) -> Option<ErrnoSource> {
+    let inner = match_or!([strip_parens(e).kind] ExprKind::Unary(UnOp::Deref, ref x) => x;
+                          return None);
+    let (func, args) = match_or!([strip_parens(inner).kind] ExprKind::Call(ref f, ref a) => (f, a);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if !args.is_empty() {
+        return None;
+    }
+    let name = path.segments.last()?.ident.as_str();
+    if LIBC_ERRNO_FNS.contains(&&*name) {
+        return Some(ErrnoSource::Libc);
+    }
+    let is_shim = path.segments.len() >= 2 &&
+        path.segments[path.segments.len() - 2].ident.as_str() == "c2rust_errno";
+    if is_shim && &*name == "errno_location" {
+        return Some(ErrnoSource::Shim);
+    }
+    None
+}
+
+/// If `e` names one of the `errno` values in `ERROR_KINDS`, return the matching `ErrorKind`.
+fn as_error_kind(e: &Expr) -> Option<&'static str> {
+    let path = match_or!([strip_parens(e).kind] ExprKind::Path(None, ref p) => p; return None);
+    let name = path.segments.last()?.ident.as_str();
+    ERROR_KINDS.iter().find(|&&(code, _)| code == &*name).map(|&(_, kind)| kind)
+}
+
+
+/// # `errno_to_io_error` Command
+///
+/// Usage: `errno_to_io_error`
+///
+/// Rewrite uses of C's `errno`, which the transpiler emits as
+/// `*__errno_location()` (or `*__error()` and so on, depending on the C
+/// library), into uses of `std::io::Error`.  Comparisons against error codes
+/// that `io::ErrorKind` distinguishes, like `EINTR` or `ENOENT`, become
+/// comparisons of the error's kind, and other reads become reads of its raw
+/// OS error code:
+///
+///  * `*__errno_location() == EINTR` becomes
+///    `std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted`.
+///
+///  * Other reads of `*__errno_location()` become
+///    `std::io::Error::last_os_error().raw_os_error().unwrap_or(0)`.
+///
+/// `std` has no way to set the C library's `errno`, so writes to it, and
+/// other uses of it as a place, like `&mut errno`, are left alone.
+///
+/// Code translated with `--errno-shim` accesses the thread-local `errno` from
+/// the `c2rust-errno` crate instead, as `*c2rust_errno::errno_location()`.
+/// Those accesses are rewritten to the crate's functions, which include
+/// writes:
+///
+///  * `*c2rust_errno::errno_location() == EINTR` becomes
+///    `c2rust_errno::last_os_error().kind() == std::io::ErrorKind::Interrupted`.
+///
+///  * Other reads become `c2rust_errno::errno()`.
+///
+///  * `*c2rust_errno::errno_location() = x` becomes `c2rust_errno::set_errno(x)`.
+///
+/// Codes like `EACCES` and `EPERM`, which share an `ErrorKind`, are compared
+/// by value as before.
+pub struct ErrnoToIoError;
+
+struct ErrnoFolder<'a, 'b, 'tcx> {
+    st: &'a CommandState,
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+}
+
+impl<'a, 'b, 'tcx> ErrnoFolder<'a, 'b, 'tcx> {
+    fn build(&self, src: &str, a: Option<&P<Expr>>) -> P<Expr> {
+        let mut bnd = Bindings::new();
+        if let Some(a) = a {
+            bnd.add("__a", a.clone());
+        }
+        parse_expr(self.cx.session(), src).subst(self.st, self.cx, &bnd)
+    }
+
+    /// Rewrite `errno == CODE` or `errno != CODE` into a comparison of error kinds.
+    fn convert_compare(&self, op: BinOpKind, a: &Expr, b: &Expr) -> Option<P<Expr>> {
+        if op != BinOpKind::Eq && op != BinOpKind::Ne {
+            return None;
+        }
+        let (src, kind) = match (as_errno(a), as_errno(b)) {
+            (Some(src), None) => (src, as_error_kind(b)?),
+            (None, Some(src)) => (src, as_error_kind(a)?),
+            _ => return None,
+        };
+        let error = match src {
+            ErrnoSource::Libc => "std::io::Error::last_os_error()",
+            ErrnoSource::Shim => "c2rust_errno::last_os_error()",
+        };
+        Some(self.build(&format!("{}.kind() {} std::io::ErrorKind::{}",
+                                 error, op.to_string(), kind), None))
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for ErrnoFolder<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        match e.kind {
+            ExprKind::Assign(ref lhs, ref mut rhs) => if let Some(src) = as_errno(lhs) {
+                self.visit_expr(rhs);
+                if src == ErrnoSource::Shim {
+                    let new_e = self.build("c2rust_errno::set_errno(__a)", Some(rhs));
+                    *e = new_e;
+                }
+                return;
+            },
+            // Other uses of `errno` as a place can't be rewritten.
+            ExprKind::AssignOp(_, ref lhs, ref mut rhs) => if as_errno(lhs).is_some() {
+                self.visit_expr(rhs);
+                return;
+            },
+            ExprKind::AddrOf(_, _, ref inner) => if as_errno(inner).is_some() {
+                return;
+            },
+            _ => {}
+        }
+
+        let new_e = match e.kind {
+            ExprKind::Binary(op, ref a, ref b) => self.convert_compare(op.node, a, b),
+            _ => None,
+        };
+        if let Some(new_e) = new_e {
+            *e = new_e;
+            return;
+        }
+
+        match as_errno(e) {
+            Some(ErrnoSource::Libc) => {
+                *e = self.build("std::io::Error::last_os_error().raw_os_error().unwrap_or(0)",
+                                None);
+            }
+            Some(ErrnoSource::Shim) => *e = self.build("c2rust_errno::errno()", None),
+            None => mut_visit::noop_visit_expr(e, self),
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for ErrnoToIoError {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut ErrnoFolder { st, cx });
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("errno_to_io_error", |_args| mk(ErrnoToIoError));
+}
@@ -0,0 +1,406 @@
+//! Conversion of C `FILE` streams to `std::fs` and `std::io`.
+
+use std::collections::{HashMap, HashSet};
+use std::str;
+use rustc::hir::HirId;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::format::{build_format_macro, maybe_non_utf8};
+use crate::transform::heap::{is_call_to, strip_casts};
+use crate::transform::slices::cast_str;
+use crate::transform::strings::{c_str_lit, is_fmt_lit};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// The mode a converted stream was opened with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FileMode {
+    /// `"r"`, a `BufReader` over a `File::open`.
+    Read,
+    /// `"w"`, a `BufWriter` over a `File::create`.
+    Write,
+    /// `"a"`, a `BufWriter` over a file opened for appending.
+    Append,
+}
+
+impl FileMode {
+    fn ty_str(self) -> &'static str {
+        match self {
+            FileMode::Read => "Option<::std::io::BufReader<::std::fs::File>>",
+            FileMode::Write | FileMode::Append => "Option<::std::io::BufWriter<::std::fs::File>>",
+        }
+    }
+}
+
+/// A use of a stream that `file_to_std` can convert.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FileOp {
+    /// `f.is_null()`
+    IsNull,
+    /// `fread(p, size, n, f)`
+    Read,
+    /// `fgets(buf, n, f)`
+    Gets,
+    /// `fwrite(p, size, n, f)`
+    Write,
+    /// `fseek(f, off, whence)`
+    Seek,
+    /// `fflush(f)`
+    Flush,
+    /// `fclose(f)`, as a statement.
+    Close,
+    /// `fprintf(f, fmt, ...)`, as a statement.
+    Print,
+}
+
+impl FileOp {
+    fn allowed(self, mode: FileMode) -> bool {
+        match self {
+            FileOp::Read | FileOp::Gets => mode == FileMode::Read,
+            FileOp::Write | FileOp::Flush | FileOp::Print => mode != FileMode::Read,
+            FileOp::IsNull | FileOp::Seek | FileOp::Close => true,
+        }
+    }
+
+    /// Check if the return value of this operation can't be converted, so that it can only be
+    /// used as a statement.
+    fn needs_stmt(self) -> bool {
+        matches!([self] FileOp::Close, FileOp::Print)
+    }
+}
+
+/// If `e` is `fopen(path, mode)` with a supported mode, return the path and the mode.
+fn as_fopen(e: &Expr) -> Option<(&Expr, FileMode)> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "fopen") || args.len() != 2 {
+        return None;
+    }
+    // The `b` flag has no effect on POSIX systems.
+    let mode = match c_str_lit(&args[1])? {
+        b"r" | b"rb" => FileMode::Read,
+        b"w" | b"wb" => FileMode::Write,
+        b"a" | b"ab" => FileMode::Append,
+        _ => return None,
+    };
+    Some((&args[0], mode))
+}
+
+/// Get the `SeekFrom` variant for an `fseek` origin.
+fn seek_from(e: &Expr) -> Option<&'static str> {
+    let e = strip_casts(e);
+    let name = match e.kind {
+        ExprKind::Path(None, ref path) => path.segments.last()?.ident.as_str().to_string(),
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(0, _) => "SEEK_SET".to_owned(),
+            LitKind::Int(1, _) => "SEEK_CUR".to_owned(),
+            LitKind::Int(2, _) => "SEEK_END".to_owned(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match &name[..] {
+        "SEEK_SET" => Some("Start"),
+        "SEEK_CUR" => Some("Current"),
+        "SEEK_END" => Some("End"),
+        _ => None,
+    }
+}
+
+/// If `e` is a use of a stream that can be converted, return the operation and the stream.
+fn as_file_op(e: &Expr) -> Option<(FileOp, &Expr)> {
+    let args = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && seg.ident.as_str() == "is_null" =>
+            return Some((FileOp::IsNull, &args[0])),
+        ExprKind::Call(_, ref args) => args,
+        _ => return None,
+    };
+    let (op, idx) = if is_call_to(e, "fread") && args.len() == 4 {
+        (FileOp::Read, 3)
+    } else if is_call_to(e, "fgets") && args.len() == 3 {
+        (FileOp::Gets, 2)
+    } else if is_call_to(e, "fwrite") && args.len() == 4 {
+        (FileOp::Write, 3)
+    } else if is_call_to(e, "fseek") && args.len() == 3 && seek_from(&args[2]).is_some() {
+        (FileOp::Seek, 0)
+    } else if is_call_to(e, "fflush") && args.len() == 1 {
+        (FileOp::Flush, 0)
+    } else if is_call_to(e, "fclose") && args.len() == 1 {
+        (FileOp::Close, 0)
+    } else if is_call_to(e, "fprintf") && args.len() >= 2 && is_fmt_lit(&args[1]) {
+        (FileOp::Print, 0)
+    } else {
+        return None;
+    };
+    Some((op, &args[idx]))
+}
+
+/// Render the path argument of `fopen` as a `std::path::Path`-compatible expression.
+fn path_str(e: &Expr) -> String {
+    if let Some(s) = c_str_lit(e).and_then(|bs| str::from_utf8(bs).ok()) {
+        return format!("{:?}", s);
+    }
+    format!("<::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(\
+             ::std::ffi::CStr::from_ptr({}).to_bytes())",
+            pprust::expr_to_string(e))
+}
+
+fn open_str(path: &Expr, mode: FileMode) -> String {
+    let path = path_str(path);
+    match mode {
+        FileMode::Read =>
+            format!("::std::fs::File::open({}).ok().map(::std::io::BufReader::new)", path),
+        FileMode::Write =>
+            format!("::std::fs::File::create({}).ok().map(::std::io::BufWriter::new)", path),
+        FileMode::Append =>
+            format!("::std::fs::OpenOptions::new().append(true).create(true).open({})\
+                     .ok().map(::std::io::BufWriter::new)", path),
+    }
+}
+
+
+/// # `file_to_std` Command
+///
+/// Usage: `file_to_std`
+///
+/// Marks: `target`
+///
+/// Convert each marked local `FILE *` variable that is initialized with
+/// `fopen(path, "r")`, `"w"` or `"a"` (with or without `b`) into an
+/// `Option<BufReader<File>>` or `Option<BufWriter<File>>`, which is `None`
+/// when the file couldn't be opened.  Uses of the variable are rewritten to
+/// the matching `std::io` calls:
+///
+///  * `f.is_null()` becomes `f.is_none()`.
+///  * `fread` and `fwrite` become loops over `Read::read` and a call to
+///    `Write::write_all`, which return the number of whole items that were
+///    transferred, like in C.
+///  * `fgets` becomes `BufRead::read_until` on at most `n - 1` bytes,
+///    followed by a copy into the buffer.
+///  * `fseek` becomes `Seek::seek`, and `fflush` becomes `Write::flush`.
+///    Both return `0` on success and `-1` on failure.
+///  * A `fprintf` statement becomes `Write::write_fmt` with the arguments
+///    converted like in `convert_printfs`, and a `fclose` statement sets the
+///    variable to `None`, which flushes and closes the file.
+///
+/// Variables with any other uses are left unchanged, and a warning is
+/// printed.  `fopen`'s path becomes a `&str` when it's a string literal, and
+/// is converted through `OsStrExt` (so only on Unix) otherwise.  A negative
+/// offset for `SEEK_SET` is not an error, as it is in C, but seeks far past the
+/// end of the file.  A `fwrite` that fails after writing part of its
+/// data returns `0`.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut f: *mut FILE = fopen(b"out.txt\0" as *const u8 as *const libc::c_char,
+///                                  b"w\0" as *const u8 as *const libc::c_char);
+///     fprintf(f, b"%d\n\0" as *const u8 as *const libc::c_char, x);
+///     fclose(f);
+/// ```
+///
+/// After running `file_to_std`, with the `let` marked:
+///
+/// ```ignore
+///     let mut f: Option<::std::io::BufWriter<::std::fs::File>> =
+///         ::std::fs::File::create("out.txt").ok().map(::std::io::BufWriter::new);
+///     let _ = ::std::io::Write::write_fmt(f.as_mut().unwrap(), format_args!("{}\n", x));
+///     f = None;
+/// ```
+pub struct FileToStd;
+
+impl Transform for FileToStd {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Collect the marked locals that are opened with `fopen`.
+
+        let mut vars: HashMap<HirId, FileMode> = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            match l.init.as_ref().and_then(|init| as_fopen(init)) {
+                Some((_, mode)) => {
+                    vars.insert(cx.hir_map().node_to_hir_id(l.pat.id), mode);
+                }
+                None => warn!("local `{}` is not opened with `fopen` in a supported mode; \
+                               skipping it", pprust::pat_to_string(&l.pat)),
+            }
+        });
+
+        // (2) Check that every use of each stream is one we can convert.
+
+        let var_of = |e: &Expr| -> Option<HirId> {
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            cx.try_resolve_expr_to_hid(e)
+        };
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                stmt_exprs.insert(e.id);
+            }
+        });
+        let mut ok_uses = HashSet::new();
+        let mut uses = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(var) = var_of(e).filter(|v| vars.contains_key(v)) {
+                uses.push((e.id, var));
+            }
+            if let Some((op, f)) = as_file_op(e) {
+                let mode = match var_of(f).and_then(|v| vars.get(&v)) {
+                    Some(&mode) => mode,
+                    None => return,
+                };
+                if op.allowed(mode) && (!op.needs_stmt() || stmt_exprs.contains(&e.id)) {
+                    ok_uses.insert(f.id);
+                }
+            }
+        });
+        for (id, var) in uses {
+            if !ok_uses.contains(&id) && vars.remove(&var).is_some() {
+                warn!("{:?} has uses that can't be converted; skipping it", var);
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+        let is_var = |e: &Expr| var_of(e).map_or(false, |v| vars.contains_key(&v));
+
+        // (3) Change the types and initializers of the streams.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let mode = match vars.get(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                Some(&mode) => mode,
+                None => return,
+            };
+            if l.ty.is_some() {
+                l.ty = Some(parse_ty(cx.session(), mode.ty_str()));
+            }
+            let src = {
+                let (path, _) = as_fopen(l.init.as_ref().unwrap()).unwrap();
+                open_str(path, mode)
+            };
+            l.init = Some(parse_expr(cx.session(), &src));
+        });
+
+        // (4) Rewrite the uses that need to be statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                    let (op, f) = match as_file_op(e) {
+                        Some((op, f)) if op.needs_stmt() && is_var(f) => (op, f),
+                        _ => continue,
+                    };
+                    let f = pprust::expr_to_string(f);
+                    if op == FileOp::Close {
+                        format!("{} = None;", f)
+                    } else {
+                        let args = match_or!([e.kind] ExprKind::Call(_, ref a) => a; continue);
+                        let lossy = |e: &Expr| maybe_non_utf8(st, cx, e);
+                        let mac = build_format_macro("format_args", None, None, &args[1..], None,
+                                                     &lossy);
+                        format!("let _ = ::std::io::Write::write_fmt({}.as_mut().unwrap(), {});",
+                                f, pprust::expr_to_string(&mk().mac_expr(mac)))
+                    }
+                };
+                *s = parse_stmts(cx.session(), &src).lone();
+            }
+        });
+
+        // (5) Rewrite the other uses.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = {
+                let (op, f) = match as_file_op(e) {
+                    Some((op, f)) if !op.needs_stmt() && is_var(f) => (op, f),
+                    _ => return,
+                };
+                let f = pprust::expr_to_string(f);
+                let ret_ty = || cx.opt_node_type(e.id)
+                    .map(|ty| pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ty)));
+                let args: &[P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref args) => args,
+                    _ => &[],
+                };
+                match op {
+                    FileOp::IsNull => format!("{}.is_none()", f),
+                    FileOp::Read => format!(
+                        "{{ let (file, ptr, size, count) = \
+                              ({}.as_mut().unwrap(), {}, {}, {}); \
+                            let buf = ::std::slice::from_raw_parts_mut(ptr, size * count); \
+                            let mut len = 0; \
+                            while len < buf.len() {{ \
+                                match ::std::io::Read::read(&mut *file, &mut buf[len..]) {{ \
+                                    Ok(0) | Err(_) => break, \
+                                    Ok(n) => len += n, \
+                                }} \
+                            }} \
+                            if size == 0 {{ 0 }} else {{ (len / size) as {} }} }}",
+                        f, cast_str(&args[0], "*mut u8"), cast_str(&args[1], "usize"),
+                        cast_str(&args[2], "usize"), ret_ty().unwrap_or("_".to_owned())),
+                    FileOp::Write => format!(
+                        "{{ let (file, ptr, size, count) = \
+                              ({}.as_mut().unwrap(), {}, {}, {}); \
+                            let buf = ::std::slice::from_raw_parts(ptr, size * count); \
+                            if ::std::io::Write::write_all(file, buf).is_ok() {{ \
+                                count as {} \
+                            }} else {{ 0 }} }}",
+                        f, cast_str(&args[0], "*const u8"), cast_str(&args[1], "usize"),
+                        cast_str(&args[2], "usize"), ret_ty().unwrap_or("_".to_owned())),
+                    FileOp::Gets => format!(
+                        "{{ let (buf, file, limit) = \
+                              ({}, {}.as_mut().unwrap(), ({} - 1).max(0) as u64); \
+                            let mut line = Vec::new(); \
+                            match ::std::io::BufRead::read_until( \
+                                    &mut ::std::io::Read::take(file, limit), b'\\n', &mut line) {{ \
+                                Ok(0) | Err(_) => ::std::ptr::null_mut(), \
+                                Ok(_) => {{ \
+                                    line.push(0); \
+                                    ::std::ptr::copy_nonoverlapping( \
+                                        line.as_ptr() as *const _, buf, line.len()); \
+                                    buf \
+                                }} \
+                            }} }}",
+                        pprust::expr_to_string(&args[0]), f, cast_str(&args[1], "i64")),
+                    FileOp::Seek => {
+                        let (variant, off_ty) = match seek_from(&args[2]).unwrap() {
+                            "Start" => ("Start", "u64"),
+                            v => (v, "i64"),
+                        };
+                        format!("::std::io::Seek::seek({}.as_mut().unwrap(), \
+                                 ::std::io::SeekFrom::{}({})).map_or(-1, |_| 0)",
+                                f, variant, cast_str(&args[1], off_ty))
+                    }
+                    FileOp::Flush => format!(
+                        "::std::io::Write::flush({}.as_mut().unwrap()).map_or(-1, |()| 0)", f),
+                    FileOp::Close | FileOp::Print => unreachable!(),
+                }
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("file_to_std", |_args| mk(FileToStd));
+}
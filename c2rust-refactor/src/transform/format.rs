@@ -13,11 +13,14 @@ use syntax_pos::{sym, Span};
 use smallvec::smallvec;
 
 use c2rust_ast_builder::mk;
+use crate::analysis::string_provenance::{expr_provenance, Provenance};
 use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
 use crate::transform::Transform;
 use crate::RefactorCtxt;
 
+#[cfg(test)]
+mod tests;
 
 /// # `convert_format_args` Command
 ///
@@ -54,7 +57,7 @@ use crate::RefactorCtxt;
 pub struct ConvertFormatArgs;
 
 impl Transform for ConvertFormatArgs {
-    fn transform(&self, krate: &mut Crate, st: &CommandState, _cx: &RefactorCtxt) {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
         MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
             let fmt_idx = match e.kind {
                 ExprKind::Call(_, ref args) =>
@@ -83,7 +86,9 @@ impl Transform for ConvertFormatArgs {
                     old_fmt_str_expr = Some(P(e.clone()));
                 }
             });
-            let mac = build_format_macro("format_args", None, old_fmt_str_expr, &args[fmt_idx..], None);
+            let lossy = |e: &Expr| maybe_non_utf8(st, cx, e);
+            let mac = build_format_macro("format_args", None, old_fmt_str_expr, &args[fmt_idx..],
+                                         None, &lossy);
             let mut new_args = args[..fmt_idx].to_owned();
             new_args.push(mk().mac_expr(mac));
 
@@ -93,12 +98,21 @@ impl Transform for ConvertFormatArgs {
 }
 
 
-fn build_format_macro(
+/// Check if `e` refers to a string that `analyze_string_provenance` found may not be valid UTF-8.
+pub fn maybe_non_utf8(st: &CommandState, cx: &RefactorCtxt, e: &Expr) -> bool {
+    matches!([expr_provenance(st, cx, e)] Some(Provenance::Unknown), Some(Provenance::Binary))
+}
+
+/// Build a formatting macro invocation from a `printf`-style format string and arguments.  `%s`
+/// arguments for which `lossy` returns `true` are converted with `to_string_lossy`, and the
+/// others with `to_str().unwrap()`.
+pub fn build_format_macro(
     macro_name: &str,
     ln_macro_name: Option<&str>,
     old_fmt_str_expr: Option<P<Expr>>,
     fmt_args: &[P<Expr>],
     span: Option<Span>,
+    lossy: &dyn Fn(&Expr) -> bool,
 ) -> Mac {
     let old_fmt_str_expr = old_fmt_str_expr.unwrap_or_else(|| fmt_args[0].clone());
 
@@ -182,7 +196,7 @@ fn build_format_macro(
     macro_tts.push(expr_tt(new_fmt_str_expr));
     for (i, arg) in fmt_args[1..].iter().enumerate() {
         if let Some(cast) = casts.get(&i) {
-            let tt = expr_tt(cast.apply(arg.clone()));
+            let tt = expr_tt(cast.apply(arg.clone(), lossy(arg)));
             macro_tts.push(TokenTree::Token(Token {kind: TokenKind::Comma, span: DUMMY_SP}));
             macro_tts.push(tt);
         }
@@ -208,6 +222,10 @@ fn build_format_macro(
 /// using `extern "C"` and marked `#[no_mangle]`, to make sure the caller
 /// is actually calling the libc functions.
 ///
+/// `%s` arguments are converted with `CStr::from_ptr(s).to_str().unwrap()`,
+/// which panics on invalid UTF-8.  Arguments that `analyze_string_provenance`
+/// marked `binary` or `unknown` are converted with `to_string_lossy()` instead.
+///
 /// Example:
 ///
 /// ```ignore
@@ -222,7 +240,7 @@ fn build_format_macro(
 pub struct ConvertPrintfs;
 
 impl Transform for ConvertPrintfs {
-    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
         let mut printf_defs = HashSet::<DefId>::new();
         let mut fprintf_defs = HashSet::<DefId>::new();
         let mut stderr_defs = HashSet::<DefId>::new();
@@ -242,6 +260,7 @@ impl Transform for ConvertPrintfs {
                 }
             }
         });
+        let lossy = |e: &Expr| maybe_non_utf8(st, cx, e);
         FlatMapNodes::visit(krate, |s: Stmt| {
             match s.kind {
                 StmtKind::Semi(ref expr) => {
@@ -252,11 +271,13 @@ impl Transform for ConvertPrintfs {
                         match (cx.try_resolve_expr(f), cx.try_resolve_expr(&*args[0])) {
                             (Some(ref f_id), Some(ref arg0_id)) if fprintf_defs.contains(f_id) &&
                                 stderr_defs.contains(arg0_id) => {
-                                let mac = build_format_macro("eprint", Some("eprintln"), None, &args[1..], Some(expr.span));
+                                let mac = build_format_macro("eprint", Some("eprintln"), None, &args[1..],
+                                                             Some(expr.span), &lossy);
                                 return smallvec![mk().span(s.span).mac_stmt(mac)];
                             }
                             (Some(ref f_id), _) if printf_defs.contains(f_id) => {
-                                let mac = build_format_macro("print", Some("println"), None, &args[..], Some(expr.span));
+                                let mac = build_format_macro("print", Some("println"), None, &args[..],
+                                                             Some(expr.span), &lossy);
                                 return smallvec![mk().span(s.span).mac_stmt(mac)];
                             },
                             _ => {}
@@ -282,7 +303,7 @@ enum CastType {
 }
 
 impl CastType {
-    fn apply(&self, mut e: P<Expr>) -> P<Expr> {
+    fn apply(&self, mut e: P<Expr>, lossy: bool) -> P<Expr> {
         // Since these get passed to the new print! macros, they need to have spans,
         // and the spans need to match the original expressions
         // FIXME: should all the inner nodes have spans too???
@@ -305,8 +326,13 @@ impl CastType {
                     // TODO(kkysen) change `"std"` to `"core"` after `#![feature(core_c_str)]` is stabilized in `1.63.0`
                     mk().path_expr(vec!["std", "ffi", "CStr", "from_ptr"]),
                     vec![e]);
-                let s = mk().method_call_expr(cs, "to_str", Vec::new());
-                let call = mk().method_call_expr(s, "unwrap", Vec::new());
+                let call = if lossy {
+                    // CStr::from_ptr(e as *const libc::c_char).to_string_lossy()
+                    mk().method_call_expr(cs, "to_string_lossy", Vec::new())
+                } else {
+                    let s = mk().method_call_expr(cs, "to_str", Vec::new());
+                    mk().method_call_expr(s, "unwrap", Vec::new())
+                };
                 let b = mk().unsafe_().block(vec![mk().expr_stmt(call)]);
                 mk().span(span).block_expr(b)
             },
@@ -0,0 +1,300 @@
+use super::build_format_macro;
+use c2rust_ast_builder::mk;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use syntax::print::pprust;
+use syntax_pos::edition::Edition;
+
+/// Integer length modifiers, with the types that `snprintf` receives for a signed and an unsigned
+/// conversion after the default argument promotions.  `%tu` and `%tx` aren't supported by the
+/// conversion yet.
+const INT_LENGTHS: &[(&str, &str, Option<&str>)] = &[
+    ("", "libc::c_int", Some("libc::c_uint")),
+    ("hh", "libc::c_int", Some("libc::c_int")),
+    ("h", "libc::c_int", Some("libc::c_int")),
+    ("l", "libc::c_long", Some("libc::c_ulong")),
+    ("ll", "libc::c_longlong", Some("libc::c_ulonglong")),
+    ("j", "libc::intmax_t", Some("libc::uintmax_t")),
+    ("z", "libc::ssize_t", Some("libc::size_t")),
+    ("t", "libc::ptrdiff_t", None),
+];
+
+const TEXT_CHARS: &[u8] = b"abcXYZ019 -:,.{}";
+
+const STR_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789 ";
+
+/// An argument to a generated format call.
+struct Arg {
+    /// The Rust declaration of the value, of the form `let NAME...;`.
+    decl: String,
+    /// The argument passed to `snprintf`.
+    c_arg: String,
+}
+
+/// A `printf` format string and a matching list of arguments.
+struct Case {
+    fmt: String,
+    args: Vec<Arg>,
+}
+
+impl Case {
+    fn push_arg(&mut self, ty: &str, value: String, c_ty: &str) {
+        let name = format!("arg{}", self.args.len());
+        self.args.push(Arg {
+            decl: format!("let {}: {} = {};", name, ty, value),
+            c_arg: format!("{} as {}", name, c_ty),
+        });
+    }
+
+    fn push_amount(&mut self, rng: &mut StdRng, max: usize) {
+        if rng.gen_bool(0.1) {
+            self.fmt.push('*');
+            let n = rng.gen_range(0, max);
+            self.push_arg("i64", n.to_string(), "libc::c_int");
+        } else {
+            self.fmt.push_str(&rng.gen_range(1, max).to_string());
+        }
+    }
+
+    fn push_int(&mut self, rng: &mut StdRng, c_ty: &str, signed: bool) {
+        let value = match rng.gen_range(0, 4) {
+            0 => [0, 1, 42, 255, 256, 65535, 65536][rng.gen_range(0, 7)],
+            1 => rng.gen_range(0, 1000),
+            2 => rng.gen::<i32>() as i64,
+            _ => rng.gen::<i64>(),
+        };
+        let value = if signed && rng.gen_bool(0.5) { value.wrapping_neg() } else { value };
+        if signed {
+            self.push_arg("i64", value.to_string(), c_ty);
+        } else {
+            self.push_arg("u64", (value as u64).to_string(), c_ty);
+        }
+    }
+
+    fn push_conv(&mut self, rng: &mut StdRng) {
+        self.fmt.push('%');
+        let kind = b"duxXcsf"[rng.gen_range(0, 7)];
+        if rng.gen_bool(0.5) {
+            self.push_amount(rng, 16);
+        }
+        // C leaves the precision of `%c` undefined.
+        if kind != b'c' && rng.gen_bool(0.3) {
+            self.fmt.push('.');
+            self.push_amount(rng, 10);
+        }
+
+        match kind {
+            b'd' | b'u' | b'x' | b'X' => {
+                let signed = kind == b'd';
+                let (len, c_ty) = loop {
+                    let i = rng.gen_range(0, INT_LENGTHS.len());
+                    let (len, signed_ty, unsigned_ty) = INT_LENGTHS[i];
+                    match (signed, unsigned_ty) {
+                        (true, _) => break (len, signed_ty),
+                        (false, Some(ty)) => break (len, ty),
+                        (false, None) => {},
+                    }
+                };
+                self.fmt.push_str(len);
+                self.push_int(rng, c_ty, signed);
+            },
+            b'c' => {
+                let c = rng.gen_range(b' ', b'~' + 1);
+                self.push_arg("i64", c.to_string(), "libc::c_int");
+            },
+            b's' => {
+                let len = rng.gen_range(0, 8);
+                let s = (0..len)
+                    .map(|_| STR_CHARS[rng.gen_range(0, STR_CHARS.len())] as char)
+                    .collect::<String>();
+                self.push_arg("*const u8", format!("b\"{}\\0\".as_ptr()", s),
+                              "*const libc::c_char");
+            },
+            b'f' => {
+                let value = match rng.gen_range(0, 3) {
+                    0 => [0.0, -0.0, 0.5, 1.0, -2.5, 1e10, 123456.789][rng.gen_range(0, 7)],
+                    1 => rng.gen_range(-1_000_000, 1_000_000) as f64 / 1000.0,
+                    _ => rng.gen::<f64>() * 100.0,
+                };
+                self.push_arg("f64", format!("{:?}", value), "f64");
+            },
+            _ => unreachable!(),
+        }
+        self.fmt.push(kind as char);
+    }
+
+    fn generate(rng: &mut StdRng) -> Case {
+        let mut case = Case { fmt: String::new(), args: Vec::new() };
+        for _ in 0..rng.gen_range(1, 4) {
+            if rng.gen_bool(0.3) {
+                for _ in 0..rng.gen_range(1, 4) {
+                    case.fmt.push(TEXT_CHARS[rng.gen_range(0, TEXT_CHARS.len())] as char);
+                }
+                if rng.gen_bool(0.2) {
+                    case.fmt.push_str("%%");
+                }
+            } else {
+                case.push_conv(rng);
+            }
+        }
+        case
+    }
+
+    /// Convert the format string and arguments into a `format!` invocation, the same way
+    /// `convert_printfs` does.
+    fn convert(&self) -> String {
+        let mut fmt_args = vec![mk().lit_expr(&self.fmt)];
+        for i in 0..self.args.len() {
+            fmt_args.push(mk().path_expr(vec![format!("arg{}", i)]));
+        }
+        let mac = build_format_macro("format", None, None, &fmt_args, None, &|_| false);
+        pprust::expr_to_string(&mk().mac_expr(mac))
+    }
+
+    /// Write a block that formats this case both ways and prints any difference.
+    fn write_check(&self, id: usize, rust: &str, out: &mut String) {
+        out.push_str("    {\n");
+        for arg in &self.args {
+            out.push_str(&format!("        {}\n", arg.decl));
+        }
+        let c_args = self.args.iter().map(|a| format!(", {}", a.c_arg)).collect::<String>();
+        out.push_str(&format!(
+            "        let c = c_format({:?}, |buf, len, fmt| unsafe {{ \
+             snprintf(buf, len, fmt{}) }});\n",
+            self.fmt, c_args));
+        out.push_str(&format!("        let rust = {};\n", rust));
+        out.push_str(&format!("        check({}, c, rust);\n", id));
+        out.push_str("    }\n");
+    }
+}
+
+const PRELUDE: &str = r#"
+#![allow(non_camel_case_types, unused_parens, unused_unsafe, unused_variables)]
+
+mod libc {
+    pub use std::os::raw::*;
+    pub type size_t = usize;
+    pub type ssize_t = isize;
+    pub type ptrdiff_t = isize;
+    pub type intmax_t = i64;
+    pub type uintmax_t = u64;
+}
+
+extern "C" {
+    fn snprintf(buf: *mut libc::c_char, len: libc::size_t, fmt: *const libc::c_char, ...)
+                -> libc::c_int;
+}
+
+fn c_format<F>(fmt: &str, f: F) -> String
+where F: FnOnce(*mut libc::c_char, libc::size_t, *const libc::c_char) -> libc::c_int {
+    let fmt = std::ffi::CString::new(fmt).unwrap();
+    let mut buf = vec![0 as libc::c_char; 1024];
+    let n = f(buf.as_mut_ptr(), buf.len(), fmt.as_ptr());
+    assert!(n >= 0 && (n as usize) < buf.len());
+    unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+fn check(id: usize, c: String, rust: String) {
+    if c != rust {
+        println!("{}\t{:?}\t{:?}", id, c, rust);
+    }
+}
+"#;
+
+/// Write a program that checks each of `cases` to `dir/NAME.rs`, compile it, and return the
+/// path of the executable, or the compiler's error output.
+fn compile(dir: &Path, name: &str, cases: &[(usize, &Case, &str)]) -> Result<PathBuf, String> {
+    let mut src = PRELUDE.to_owned();
+    src.push_str("\nfn main() {\n");
+    for &(id, case, rust) in cases {
+        case.write_check(id, rust, &mut src);
+    }
+    src.push_str("}\n");
+
+    let src_path = dir.join(format!("{}.rs", name));
+    let exe_path = dir.join(name);
+    fs::write(&src_path, src).unwrap();
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let output = Command::new(rustc)
+        .arg("--edition=2018")
+        .arg("-o").arg(&exe_path)
+        .arg(&src_path)
+        .output()
+        .expect("failed to run rustc");
+    if output.status.success() {
+        Ok(exe_path)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+fn env_or<T: std::str::FromStr>(var: &str, default: T) -> T {
+    env::var(var).ok().and_then(|s| s.parse().ok()).unwrap_or(default)
+}
+
+/// Generate random format strings and arguments, and check that the converted `format!` calls
+/// print the same thing as `snprintf`.  This builds and runs a program with `rustc`, and the
+/// conversion is known to differ from C in some cases (such as the default precision of `%f`), so
+/// the test is ignored by default; run it with `cargo test format_matches_snprintf -- --ignored`.
+/// `C2RUST_FORMAT_SEED` and `C2RUST_FORMAT_CASES` set the random seed and the number of cases.
+#[test]
+#[ignore]
+fn format_matches_snprintf() {
+    let seed = env_or("C2RUST_FORMAT_SEED", 0);
+    let count = env_or("C2RUST_FORMAT_CASES", 200);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let cases = (0..count).map(|_| Case::generate(&mut rng)).collect::<Vec<_>>();
+    let converted = syntax::with_globals(Edition::Edition2018, || {
+        cases.iter().map(|c| c.convert()).collect::<Vec<_>>()
+    });
+
+    let dir = env::temp_dir().join(format!("c2rust-format-test-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut failures = Vec::new();
+    let all = cases.iter().zip(&converted).enumerate()
+        .map(|(id, (case, rust))| (id, case, rust.as_str()))
+        .collect::<Vec<_>>();
+    let exes = match compile(&dir, "all", &all) {
+        Ok(exe) => vec![exe],
+        Err(_) => {
+            // Find the cases that were converted into invalid Rust, and check the rest.
+            let mut valid = Vec::new();
+            for &(id, case, rust) in &all {
+                match compile(&dir, "single", &[(id, case, rust)]) {
+                    Ok(_) => valid.push((id, case, rust)),
+                    Err(err) => failures.push(format!(
+                        "case {}: {:?} was converted into invalid Rust: {}\n{}",
+                        id, case.fmt, rust, err)),
+                }
+            }
+            vec![compile(&dir, "valid", &valid).unwrap_or_else(|err| panic!("{}", err))]
+        },
+    };
+
+    for exe in exes {
+        let output = Command::new(&exe).output().unwrap();
+        assert!(output.status.success(), "{} failed: {}", exe.display(),
+                String::from_utf8_lossy(&output.stderr));
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let parts = line.splitn(3, '\t').collect::<Vec<_>>();
+            let id: usize = parts[0].parse().unwrap();
+            let case = &cases[id];
+            let args = case.args.iter().map(|a| a.decl.as_str()).collect::<Vec<_>>();
+            failures.push(format!(
+                "case {}: {:?} {}\n  converted: {}\n  C:    {}\n  Rust: {}",
+                id, case.fmt, args.join(" "), converted[id], parts[1], parts[2]));
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+
+    for failure in &failures {
+        println!("{}\n", failure);
+    }
+    assert!(failures.is_empty(), "{} of {} cases don't match C (seed {})",
+            failures.len(), count, seed);
+}
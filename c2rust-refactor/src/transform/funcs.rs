@@ -1,21 +1,32 @@
 use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
 use rustc::hir::def_id::DefId;
 use rustc::ty::TyKind;
 use syntax::ast;
 use syntax::ast::*;
 use syntax::attr;
 use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
-use syntax_pos::sym;
+use syntax::source_map::respan;
+use syntax::symbol::{kw, Symbol};
+use syntax_pos::{sym, BytePos};
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules, visit_nodes, MutVisit};
+use crate::analysis::points_to::{self, Loc, PointsTo};
+use crate::analysis::unsafety;
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisitNodes, fold_modules, visit_nodes, MutVisit};
+use crate::ast_manip::fn_edit::{mut_visit_fns, FnLike};
+use crate::ast_manip::output_exprs::fold_output_exprs;
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
-use crate::matcher::{BindingType, MatchCtxt, Subst, mut_visit_match_with};
+use crate::driver::{Phase, parse_expr, parse_items, parse_stmts, parse_ty};
+use crate::matcher::{BindingType, Bindings, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::{fold_resolved_paths, fold_resolved_paths_with_id};
+use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
+use crate::transform::control_flow::{mut_places, strip_parens};
+use crate::transform::heap::{is_null_ptr, strip_casts};
 use crate::util::Lone;
 use crate::RefactorCtxt;
 
@@ -263,6 +274,316 @@ impl Transform for ToMethod {
 }
 
 
+/// # `struct_methods` Command
+///
+/// Usage: `struct_methods STRUCT [PREFIX]`
+///
+/// Turn free functions whose first argument is a `STRUCT`, or a reference or raw pointer to one,
+/// into methods in a new `impl STRUCT` block placed after the struct definition.  The first
+/// argument becomes `self`, `&self`, or `&mut self`, and `PREFIX` (which defaults to the
+/// snake-case struct name followed by `_`) is removed from the start of each function's name.
+/// All calls are rewritten to use method-call syntax.
+///
+/// A raw pointer argument becomes `&self` or `&mut self` depending on the pointer's mutability.
+/// In the function body, `*p` becomes `*self`, and other uses of `p` become `self` cast back to a
+/// raw pointer.  At call sites, `&x` and `&mut x` arguments are passed as the receiver `x`, and
+/// other pointers are dereferenced.
+///
+/// Functions whose first argument is reassigned, and functions used other than by calling them
+/// directly, are left unchanged.  So is any function whose name would not be a valid identifier
+/// after removing the prefix.
+///
+/// Example:
+///
+/// ```ignore
+///     struct Stack {
+///         len: usize,
+///     }
+///
+///     unsafe fn stack_push(s: *mut Stack, x: i32) {
+///         (*s).len += 1;
+///     }
+///
+///     unsafe fn stack_len(s: *const Stack) -> usize {
+///         (*s).len
+///     }
+///
+///     unsafe fn f(x: i32, s: *mut Stack) {
+///         stack_push(s, x);
+///     }
+/// ```
+///
+/// After running `struct_methods Stack`:
+///
+/// ```ignore
+///     struct Stack {
+///         len: usize,
+///     }
+///     impl Stack {
+///         unsafe fn push(&mut self, x: i32) {
+///             (*self).len += 1;
+///         }
+///
+///         unsafe fn len(&self) -> usize {
+///             (*self).len
+///         }
+///     }
+///
+///     unsafe fn f(x: i32, s: *mut Stack) {
+///         (*s).push(x);
+///     }
+/// ```
+pub struct StructMethods {
+    pub struct_name: String,
+    pub prefix: Option<String>,
+}
+
+/// How the first argument of a function converted by `struct_methods` is passed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SelfArg {
+    Value(Mutability),
+    Ref(Mutability),
+    Ptr(Mutability),
+}
+
+/// Convert `SomeName` to `some_name`.
+fn snake_case(s: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+impl Transform for StructMethods {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the struct.
+
+        let mut struct_item = None;
+        visit_nodes(krate, |i: &Item| {
+            if matches!([i.kind] ItemKind::Struct(..)) &&
+               i.ident.as_str() == &self.struct_name as &str {
+                struct_item = Some((i.id, cx.node_def_id(i.id)));
+            }
+        });
+        let (struct_id, struct_did) = match_or!([struct_item] Some(x) => x; return);
+        let prefix = self.prefix.clone()
+            .unwrap_or_else(|| format!("{}_", snake_case(&self.struct_name)));
+
+        // (2) Find free functions that take the struct as their first argument.
+
+        let mut fns = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let sig = match_or!([i.kind] ItemKind::Fn(ref sig, ..) => sig; return);
+            let arg = match_or!([sig.decl.inputs.get(0)] Some(x) => x; return);
+            let mode = match_or!([arg.pat.kind] PatKind::Ident(mode, _, None) => mode; return);
+            let is_struct = |ty: rustc::ty::Ty| match ty.kind {
+                TyKind::Adt(adt, _) => adt.did == struct_did,
+                _ => false,
+            };
+            let self_arg = match cx.node_type(arg.pat.id).kind {
+                TyKind::RawPtr(mty) if is_struct(mty.ty) => SelfArg::Ptr(mty.mutbl),
+                TyKind::Ref(_, ty, mutbl) if is_struct(ty) => SelfArg::Ref(mutbl),
+                _ if is_struct(cx.node_type(arg.pat.id)) => match mode {
+                    BindingMode::ByValue(mutbl) => SelfArg::Value(mutbl),
+                    BindingMode::ByRef(_) => return,
+                },
+                _ => return,
+            };
+
+            let name = i.ident.as_str();
+            let new_name = if name.starts_with(&prefix as &str) {
+                Ident::from_str(&name[prefix.len()..])
+            } else {
+                i.ident
+            };
+            if new_name.as_str().is_empty() || new_name.is_reserved() ||
+               new_name.as_str().starts_with(|c: char| c.is_ascii_digit()) {
+                info!("can't convert `{}`: `{}` is not a valid method name", name, new_name);
+                return;
+            }
+            fns.insert(cx.node_def_id(i.id), (self_arg, new_name));
+        });
+
+        // Functions used as values, or that reassign their first argument, keep their signatures.
+        let mut callees = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Call(ref func, _) = e.kind {
+                callees.insert(func.id);
+            }
+        });
+        let mut skip = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if !matches!([e.kind] ExprKind::Path(..)) || callees.contains(&e.id) {
+                return;
+            }
+            if let Some(did) = cx.try_resolve_expr(e) {
+                if fns.contains_key(&did) {
+                    info!("can't convert `{:?}`: it is used as a value", did);
+                    skip.insert(did);
+                }
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref block) =>
+                                         (sig, block); return);
+            let did = cx.node_def_id(i.id);
+            if !fns.contains_key(&did) {
+                return;
+            }
+            let arg_hid = cx.hir_map().node_to_hir_id(sig.decl.inputs[0].pat.id);
+            visit_nodes(&**block, |e: &Expr| {
+                let lhs = match e.kind {
+                    ExprKind::Assign(ref lhs, _) |
+                    ExprKind::AssignOp(_, ref lhs, _) |
+                    ExprKind::AddrOf(_, Mutability::Mutable, ref lhs) => lhs,
+                    _ => return,
+                };
+                if cx.try_resolve_expr_to_hid(lhs) == Some(arg_hid) {
+                    info!("can't convert `{}`: its first argument is reassigned", i.ident);
+                    skip.insert(did);
+                }
+            });
+        });
+        fns.retain(|did, _| !skip.contains(did));
+
+        // (3) Remove the functions, and turn them into methods.
+
+        let self_expr = parse_expr(cx.session(), "self");
+        let mut methods = Vec::new();
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Fn(..)) {
+                return smallvec![i];
+            }
+            let &(self_arg, new_name) =
+                match_or!([fns.get(&cx.node_def_id(i.id))] Some(x) => x; return smallvec![i]);
+            let i = i.into_inner();
+            unpack!([i.kind] ItemKind::Fn(sig, generics, block));
+            let mut sig = sig;
+            let mut block = block;
+
+            let arg = sig.decl.inputs[0].clone();
+            let arg_hid = cx.hir_map().node_to_hir_id(arg.pat.id);
+            let self_kind = match self_arg {
+                SelfArg::Value(mutbl) => SelfKind::Value(mutbl),
+                SelfArg::Ref(mutbl) | SelfArg::Ptr(mutbl) => SelfKind::Region(None, mutbl),
+            };
+            let self_param = Param::from_self(
+                arg.attrs.clone(),
+                respan(arg.span, self_kind),
+                Ident::new(kw::SelfLower, arg.pat.span));
+            sig.decl = sig.decl.map(|mut fd| {
+                fd.inputs[0] = self_param;
+                fd
+            });
+
+            if let SelfArg::Ptr(_) = self_arg {
+                // `*p` becomes `*self`.  The new `self` exprs don't resolve to the old argument,
+                // so they're left alone by the next step.
+                MutVisitNodes::visit(&mut block, |e: &mut P<Expr>| {
+                    if let ExprKind::Unary(UnOp::Deref, ref mut inner) = e.kind {
+                        if cx.try_resolve_expr_to_hid(inner) == Some(arg_hid) {
+                            *inner = self_expr.clone();
+                        }
+                    }
+                });
+            }
+            let arg_repl = match self_arg {
+                SelfArg::Ptr(_) => parse_expr(
+                    cx.session(), &format!("(self as {})", pprust::ty_to_string(&arg.ty))),
+                _ => self_expr.clone(),
+            };
+            MutVisitNodes::visit(&mut block, |e: &mut P<Expr>| {
+                if cx.try_resolve_expr_to_hid(e) == Some(arg_hid) {
+                    *e = arg_repl.clone();
+                }
+            });
+
+            methods.push(ImplItem {
+                id: DUMMY_NODE_ID,
+                ident: new_name,
+                vis: i.vis,
+                defaultness: Defaultness::Final,
+                attrs: i.attrs,
+                generics,
+                kind: ImplItemKind::Method(sig, block),
+                span: i.span,
+                tokens: None,
+            });
+            smallvec![]
+        });
+
+        if methods.is_empty() {
+            return;
+        }
+
+        // (4) Add the new `impl` after the struct definition.
+
+        let impl_item: P<Item> =
+            st.parse_items(cx, &format!("impl {} {{}}", self.struct_name)).lone();
+        let impl_item = impl_item.map(|mut i| {
+            if let ItemKind::Impl(.., ref mut items) = i.kind {
+                items.extend(methods.drain(..));
+            }
+            i
+        });
+        let mut impl_item = Some(impl_item);
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if i.id == struct_id {
+                if let Some(impl_item) = impl_item.take() {
+                    return smallvec![i, impl_item];
+                }
+            }
+            smallvec![i]
+        });
+
+        // (5) Rewrite calls into method calls.
+
+        let deref = parse_expr(cx.session(), "*__e");
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return);
+            let did = match_or!([cx.try_resolve_expr(func)] Some(x) => x; return);
+            let &(self_arg, new_name) = match_or!([fns.get(&did)] Some(x) => x; return);
+
+            let mut args = args.clone();
+            let self_val = args.remove(0);
+            // `&x` and `&mut x` pass `x`, and inside a converted method, `self` passes itself.
+            let stripped = strip_casts(&self_val);
+            let borrowed = match stripped.kind {
+                ExprKind::AddrOf(_, _, ref inner) => Some(inner.clone()),
+                ExprKind::Path(None, ref path) if path.segments.len() == 1 &&
+                        path.segments[0].ident.name == kw::SelfLower =>
+                    Some(P(stripped.clone())),
+                _ => None,
+            };
+            let recv = match (self_arg, borrowed) {
+                (SelfArg::Value(_), _) => self_val,
+                (_, Some(inner)) => inner,
+                (SelfArg::Ref(_), None) => self_val,
+                (SelfArg::Ptr(_), None) => {
+                    let mut bnd = Bindings::new();
+                    bnd.add("__e", self_val);
+                    deref.clone().subst(st, cx, &bnd)
+                }
+            };
+            args.insert(0, recv);
+            e.kind = ExprKind::MethodCall(PathSegment::from_ident(new_name), args);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 /// # `fix_unused_unsafe` Command
 ///
 /// Usage: `fix_unused_unsafe`
@@ -293,6 +614,264 @@ impl Transform for FixUnusedUnsafe {
 }
 
 
+/// # `remove_redundant_unsafe` Command
+///
+/// Usage: `remove_redundant_unsafe`
+///
+/// Find `unsafe` blocks that contain no operations requiring `unsafe` (raw
+/// pointer dereferences, calls to unsafe or foreign functions, union field
+/// accesses, and uses of mutable or extern statics) and turn them into ordinary
+/// blocks.
+///
+/// Before that, dereferences `*p` of a pointer that, according to the points-to
+/// analysis (`analysis/points_to.rs`), can only point to the local variable `x`
+/// are replaced with `x` itself.  This is only done when `x`'s address never
+/// leaves the current call of its function (it is only held by other locals,
+/// not arguments, statics, or the heap), `&x` is only ever used to make a raw
+/// pointer, `x` is the only binding with that name in the function and is in
+/// scope at the dereference, and the dereference has the same type as `x`.
+/// Dereferences that are written through also require `x` to be `mut`.
+///
+/// `unsafe` blocks used as statements are also shrunk, by moving the leading and
+/// trailing statements that don't require `unsafe` out of the block.  `let`
+/// statements are never moved, since that would change the scope of the
+/// variables they declare.
+///
+/// Unlike `fix_unused_unsafe`, which relies on rustc's unused-unsafe lint, this
+/// command checks each statement separately, so it can also shrink blocks that
+/// are only partly unsafe.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe {
+///         let x = 1;
+///         let y = *p;
+///         total += y;
+///     }
+/// ```
+///
+/// After running `remove_redundant_unsafe`:
+///
+/// ```ignore
+///     let x = 1;
+///     unsafe {
+///         let y = *p;
+///         total += y;
+///     }
+/// ```
+///
+/// (The `let y` statement keeps `total += y` inside the block, since `y` would
+/// otherwise go out of scope.)
+pub struct RemoveRedundantUnsafe;
+
+/// Replace dereferences of pointers that can only point to a local of `fl` with the local itself.
+fn deref_to_local(cx: &RefactorCtxt, pts: &PointsTo, fl: &mut FnLike) {
+    let block = match_or!([fl.block] Some(ref mut b) => b; return);
+
+    // The by-value bindings of the function, with their names, mutability, and pattern IDs.
+    let mut bindings: HashMap<HirId, (Symbol, Mutability, NodeId)> = HashMap::new();
+    let mut name_counts: HashMap<Symbol, usize> = HashMap::new();
+    let mut params = HashSet::new();
+    {
+        let mut record = |p: &Pat, is_param: bool| {
+            let (mode, ident) = match_or!([p.kind] PatKind::Ident(mode, ident, _) => (mode, ident);
+                                          return);
+            *name_counts.entry(ident.name).or_insert(0) += 1;
+            let hid = match_or!([cx.hir_map().opt_node_to_hir_id(p.id)] Some(x) => x; return);
+            if let BindingMode::ByValue(mutbl) = mode {
+                bindings.insert(hid, (ident.name, mutbl, p.id));
+            }
+            if is_param {
+                params.insert(hid);
+            }
+        };
+        for arg in &fl.decl.inputs {
+            visit_nodes(&*arg.pat, |p: &Pat| record(p, true));
+        }
+        visit_nodes(&**block, |p: &Pat| record(p, false));
+    }
+
+    // The range of source where each binding is in scope: the rest of the enclosing block after
+    // a `let`, or the whole body for arguments.  Bindings in other patterns are not handled.
+    let mut scopes: HashMap<HirId, (BytePos, BytePos)> = HashMap::new();
+    for &hid in &params {
+        scopes.insert(hid, (block.span.lo(), block.span.hi()));
+    }
+    visit_nodes(&**block, |b: &Block| {
+        for s in &b.stmts {
+            let l = match_or!([s.kind] StmtKind::Local(ref l) => l; continue);
+            visit_nodes(&*l.pat, |p: &Pat| {
+                if let Some(hid) = cx.hir_map().opt_node_to_hir_id(p.id) {
+                    scopes.insert(hid, (s.span.hi(), b.span.hi()));
+                }
+            });
+        }
+    });
+
+    // Bindings that are borrowed other than to immediately make a raw pointer.  Replacing a
+    // dereference with a use of one of these could conflict with the borrow.
+    let mut cast_addrs = HashSet::new();
+    visit_nodes(&**block, |e: &Expr| if let ExprKind::Cast(ref inner, _) = e.kind {
+        cast_addrs.insert(strip_parens(inner).id);
+    });
+    let mut borrowed = HashSet::new();
+    visit_nodes(&**block, |e: &Expr| {
+        let mut place = match_or!([e.kind] ExprKind::AddrOf(_, _, ref x) => x; return);
+        if cast_addrs.contains(&e.id) {
+            return;
+        }
+        while let ExprKind::Field(ref x, _) | ExprKind::Index(ref x, _) |
+                  ExprKind::Paren(ref x) = place.kind {
+            place = x;
+        }
+        borrowed.extend(cx.try_resolve_expr_to_hid(place));
+    });
+
+    let mut closures = Vec::new();
+    visit_nodes(&**block, |e: &Expr| if let ExprKind::Closure(..) = e.kind {
+        closures.push(e.span);
+    });
+    let writes = mut_places(&**block);
+
+    // Check that pointers to `x` are only ever held by locals of this function (or by
+    // temporaries), so that they can't refer to `x` in some other call of it.
+    let mut local_only = HashMap::new();
+    let mut is_local_only = |x: HirId| *local_only.entry(x).or_insert_with(|| {
+        pts.holders_of(Loc::Var(x)).iter().all(|loc| match *loc {
+            Loc::Temp(_) => true,
+            Loc::Var(hid) => bindings.contains_key(&hid) && !params.contains(&hid),
+            _ => false,
+        })
+    });
+
+    MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+        let ptr = match_or!([e.kind] ExprKind::Unary(UnOp::Deref, ref ptr) => ptr; return);
+        let objs = match_or!([pts.points_to(ptr)] Some(x) => x; return);
+        if objs.len() != 1 {
+            return;
+        }
+        let x = match_or!([objs.iter().next()] Some(&Loc::Var(x)) => x; return);
+        let (name, mutbl, pat_id) = match_or!([bindings.get(&x)] Some(&b) => b; return);
+        let (lo, hi) = match_or!([scopes.get(&x)] Some(&s) => s; return);
+        let in_scope = lo <= e.span.lo() && e.span.hi() <= hi;
+        let in_closure = closures.iter().any(|sp| sp.contains(e.span));
+        let same_ty = match (cx.opt_node_type(e.id), cx.opt_node_type(pat_id)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        };
+        if name_counts[&name] != 1 || !in_scope || in_closure || !same_ty ||
+           borrowed.contains(&x) || !is_local_only(x) {
+            return;
+        }
+        if writes.contains(&e.id) && mutbl != Mutability::Mutable {
+            return;
+        }
+        *e = mk().ident_expr(name);
+    });
+}
+
+/// Check if `s` can be moved out of an `unsafe` block without changing the scope of any bindings.
+fn is_movable_stmt(s: &Stmt) -> bool {
+    match s.kind {
+        StmtKind::Expr(_) | StmtKind::Semi(_) => true,
+        _ => false,
+    }
+}
+
+/// Split the statements of the `unsafe` block `b`, whose value is unused, into the statements
+/// that can be moved out before it, the statements that must stay inside, and the statements that
+/// can be moved out after it.  Returns `None` if no statements can be moved.
+fn split_unsafe_stmts(cx: &RefactorCtxt, b: &Block) -> Option<(Vec<Stmt>, Vec<Stmt>, Vec<Stmt>)> {
+    let needs_unsafe = b.stmts.iter()
+        .map(|s| !unsafety::stmt_unsafe_ops(cx, s).is_empty())
+        .collect::<Vec<_>>();
+    let first = needs_unsafe.iter().position(|&x| x)?;
+    let last = needs_unsafe.iter().rposition(|&x| x)?;
+
+    let start = b.stmts[..first].iter().position(|s| !is_movable_stmt(s)).unwrap_or(first);
+    let mut end = b.stmts[last + 1..].iter().rposition(|s| !is_movable_stmt(s))
+        .map_or(last + 1, |i| last + 1 + i + 1);
+    // Statements after a `let` may refer to the variable it declares.
+    if !b.stmts[start..end].iter().all(is_movable_stmt) {
+        end = b.stmts.len();
+    }
+    if start == 0 && end == b.stmts.len() {
+        return None;
+    }
+
+    let mut prefix = b.stmts.clone();
+    let mut suffix = prefix.split_off(end);
+    let inner = prefix.split_off(start);
+
+    // The block's value is unused, so its trailing expression can become an ordinary statement.
+    if let Some(s) = suffix.last_mut() {
+        if let StmtKind::Expr(ref e) = s.kind {
+            s.kind = StmtKind::Semi(e.clone());
+        }
+    }
+
+    Some((prefix, inner, suffix))
+}
+
+impl Transform for RemoveRedundantUnsafe {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Replace dereferences of pointers to locals with the locals themselves.
+
+        let pts = points_to::analyze(cx, krate);
+        mut_visit_fns(krate, |fl| deref_to_local(cx, &pts, fl));
+
+        // (2) Remove `unsafe` from blocks that don't need it.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            if let BlockCheckMode::Unsafe(UnsafeSource::UserProvided) = b.rules {
+                if unsafety::block_unsafe_ops(cx, b).is_empty() {
+                    b.rules = BlockCheckMode::Default;
+                }
+            }
+        });
+
+        // (3) Shrink the remaining `unsafe` blocks that are used as statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let num_stmts = b.stmts.len();
+            let mut new_stmts = Vec::with_capacity(num_stmts);
+            for (i, mut s) in b.stmts.drain(..).enumerate() {
+                let split = match s.kind {
+                    // A trailing expression provides the value of the enclosing block, so it
+                    // can't be split up.
+                    StmtKind::Expr(_) if i + 1 == num_stmts => None,
+                    StmtKind::Semi(ref e) | StmtKind::Expr(ref e) => match e.kind {
+                        ExprKind::Block(ref inner, None)
+                            if matches!([inner.rules]
+                                        BlockCheckMode::Unsafe(UnsafeSource::UserProvided)) =>
+                            split_unsafe_stmts(cx, inner),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                let (prefix, inner, suffix) = match_or!([split] Some(x) => x;
+                                                         { new_stmts.push(s); continue });
+                new_stmts.extend(prefix);
+                if let StmtKind::Semi(ref mut e) | StmtKind::Expr(ref mut e) = s.kind {
+                    if let ExprKind::Block(ref mut blk, _) = e.kind {
+                        blk.stmts = inner;
+                    }
+                }
+                new_stmts.push(s);
+                new_stmts.extend(suffix);
+            }
+            b.stmts = new_stmts;
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 /// # `sink_unsafe` Command
 ///
 /// Usage: `sink_unsafe`
@@ -797,12 +1376,791 @@ impl Transform for Abstract {
 }
 
 
+/// Information about a function whose out-parameters were converted.
+struct OutparamFn {
+    /// Index of each removed out-parameter in the original argument list.
+    outs: Vec<usize>,
+    /// The function originally returned `()`.
+    unit_ret: bool,
+    /// The function returns a `Result`, and the out-parameter values are added to its `Ok` value.
+    result_ret: bool,
+}
+
+/// Check if `ty` names `Result`.
+pub fn is_result_ty(ty: &Ty) -> bool {
+    match ty.kind {
+        ast::TyKind::Path(None, ref path) =>
+            path.segments.last().map_or(false, |seg| seg.ident.as_str() == "Result"),
+        _ => false,
+    }
+}
+
+/// Check if `e` is a call to `Err`.
+fn is_err_call(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Call(ref f, _) => pprust::expr_to_string(f) == "Err",
+        _ => false,
+    }
+}
+
+/// Build the value returned by a converted function, from the original return value `ret` (if
+/// any) and the names of the out-parameters.
+fn tuple_src(ret: Option<&str>, outs: &[String]) -> String {
+    let parts = ret.into_iter().map(|s| s.to_owned()).chain(outs.iter().cloned())
+        .collect::<Vec<_>>();
+    if parts.len() == 1 {
+        parts.into_iter().next().unwrap()
+    } else {
+        format!("({})", parts.join(", "))
+    }
+}
+
+/// # `outparam_to_return` Command
+///
+/// Usage: `outparam_to_return`
+///
+/// Marks: `target`
+///
+/// For each function argument marked `target` with type `*mut T`, remove the
+/// argument and return its final value instead.  If the function already
+/// returns a value, the new return type is a tuple of the old return value
+/// followed by the out-parameters, in order.  If it returns a `Result`, the
+/// out-parameters are added to its `Ok` value instead.
+///
+/// Inside the function, the argument is replaced by a local of type `T`,
+/// initialized with `mem::zeroed()`.  `*out` becomes `out`, and any other use
+/// of the argument becomes `&mut out as *mut T`.
+///
+/// At call sites, the argument passed for the out-parameter must be `&mut x`
+/// (possibly cast to a raw pointer), in which case `x` receives the returned
+/// value, a null pointer, in which case the value is discarded, or some other
+/// pointer `p`, in which case the value is stored to `*p`.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn div(a: i32, b: i32, rem: *mut i32) -> i32 {
+///         *rem = a % b;
+///         a / b
+///     }
+///
+///     let mut r = 0;
+///     let q = div(7, 2, &mut r);
+/// ```
+///
+/// After running `outparam_to_return`, with `rem` marked:
+///
+/// ```ignore
+///     unsafe fn div(a: i32, b: i32) -> (i32, i32) {
+///         let mut rem: i32 = ::std::mem::zeroed();
+///         rem = a % b;
+///         (a / b, rem)
+///     }
+///
+///     let mut r = 0;
+///     let q = {
+///         let (ret_, out0_) = div(7, 2);
+///         r = out0_;
+///         ret_
+///     };
+/// ```
+pub struct OutparamToReturn;
+
+impl Transform for OutparamToReturn {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Change the signatures and bodies of functions with marked arguments.
+
+        let mut mod_fns: HashMap<DefId, OutparamFn> = HashMap::new();
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = cx.node_def_id(i.id);
+            let fn_name = i.ident;
+            let (sig, block) = match_or!([i.kind]
+                ItemKind::Fn(ref mut sig, _, ref mut b) => (sig, b); return);
+
+            // The out-parameters, with their names, bindings, and pointee types.
+            let mut outs = Vec::new();
+            for (idx, arg) in sig.decl.inputs.iter().enumerate() {
+                if !st.marked(arg.id, "target") && !st.marked(arg.pat.id, "target") {
+                    continue;
+                }
+                let ident = match_or!([arg.pat.kind] PatKind::Ident(_, ident, None) => ident; {
+                    warn!("argument `{}` of `{}` is not a simple binding; skipping it",
+                          pprust::pat_to_string(&arg.pat), fn_name);
+                    continue;
+                });
+                let pointee = match cx.opt_node_type(arg.pat.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::RawPtr(mt)) if mt.mutbl == Mutability::Mutable => mt.ty,
+                    _ => {
+                        warn!("argument `{}` of `{}` is not a `*mut` pointer; skipping it",
+                              ident, fn_name);
+                        continue;
+                    }
+                };
+                outs.push((idx, ident, cx.hir_map().node_to_hir_id(arg.pat.id),
+                           reflect_tcx_ty(cx.ty_ctxt(), pointee)));
+            }
+            if outs.is_empty() {
+                return;
+            }
+
+            let old_ret = match sig.decl.output {
+                FunctionRetTy::Default(_) => None,
+                FunctionRetTy::Ty(ref ty) => Some(ty.clone()),
+            };
+            let unit_ret = old_ret.as_ref().map_or(true, |ty| match ty.kind {
+                ast::TyKind::Tup(ref tys) => tys.is_empty(),
+                _ => false,
+            });
+            let result_ret = old_ret.as_ref().map_or(false, |ty| is_result_ty(ty));
+            let out_names = outs.iter().map(|o| o.1.to_string()).collect::<Vec<_>>();
+            let out_tys = outs.iter().map(|o| pprust::ty_to_string(&o.3)).collect::<Vec<_>>();
+
+            // New return type.
+            let new_ret = if result_ret {
+                let mut ty = old_ret.clone().unwrap();
+                if let ast::TyKind::Path(_, ref mut path) = ty.kind {
+                    let seg = path.segments.last_mut().unwrap();
+                    if let Some(ref mut args) = seg.args {
+                        if let GenericArgs::AngleBracketed(ref mut abpd) = **args {
+                            if let Some(GenericArg::Type(ref mut ok_ty)) = abpd.args.get_mut(0) {
+                                let ok_src = pprust::ty_to_string(ok_ty);
+                                let ok_src = Some(&ok_src as &str).filter(|&s| s != "()");
+                                *ok_ty = parse_ty(cx.session(), &tuple_src(ok_src, &out_tys));
+                            }
+                        }
+                    }
+                }
+                ty
+            } else {
+                let ret_src = old_ret.as_ref().filter(|_| !unit_ret)
+                    .map(|ty| pprust::ty_to_string(ty));
+                parse_ty(cx.session(), &tuple_src(ret_src.as_ref().map(|s| s as &str), &out_tys))
+            };
+            sig.decl.output = FunctionRetTy::Ty(new_ret);
+            let is_unsafe = sig.header.unsafety == Unsafety::Unsafe;
+
+            let removed = outs.iter().map(|o| o.0).collect::<HashSet<_>>();
+            let mut idx = 0;
+            sig.decl.inputs.retain(|_| {
+                idx += 1;
+                !removed.contains(&(idx - 1))
+            });
+
+            // Rewrite uses of the out-parameters.
+            let out_hids = outs.iter().map(|o| (o.2, o.3.clone())).collect::<HashMap<_, _>>();
+            let as_ptr = parse_expr(cx.session(), "&mut __p as *mut __t");
+            let mut handled = HashSet::new();
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                    let hid = cx.try_resolve_expr_to_hid(inner);
+                    if hid.map_or(false, |h| out_hids.contains_key(&h)) {
+                        handled.insert(inner.id);
+                        let inner = inner.clone();
+                        *e = inner;
+                        return;
+                    }
+                }
+                if handled.contains(&e.id) || !matches!([e.kind] ExprKind::Path(..)) {
+                    return;
+                }
+                let hid = match_or!([cx.try_resolve_expr_to_hid(e)] Some(x) => x; return);
+                let pointee = match_or!([out_hids.get(&hid)] Some(x) => x; return);
+                let mut bnd = Bindings::new();
+                bnd.add("__p", e.clone());
+                bnd.add("__t", pointee.clone());
+                *e = as_ptr.clone().subst(st, cx, &bnd);
+            });
+
+            // Return the out-parameters.
+            let outs_src = tuple_src(None, &out_names);
+            let wrap = |e: &P<Expr>| -> P<Expr> {
+                let mut bnd = Bindings::new();
+                let src = if result_ret {
+                    let ok_arg = match e.kind {
+                        ExprKind::Call(ref f, ref args) if args.len() == 1 &&
+                            pprust::expr_to_string(f) == "Ok" => Some(&args[0]),
+                        _ => None,
+                    };
+                    match ok_arg {
+                        Some(a) if pprust::expr_to_string(a) == "()" =>
+                            format!("Ok({})", outs_src),
+                        Some(a) => {
+                            bnd.add("__e", a.clone());
+                            format!("Ok({})", tuple_src(Some("__e"), &out_names))
+                        }
+                        None if is_err_call(e) => return e.clone(),
+                        None => {
+                            bnd.add("__e", e.clone());
+                            format!("__e.map(|ret_| {})", tuple_src(Some("ret_"), &out_names))
+                        }
+                    }
+                } else {
+                    bnd.add("__e", e.clone());
+                    if unit_ret {
+                        format!("{{ __e; {} }}", outs_src)
+                    } else {
+                        tuple_src(Some("__e"), &out_names)
+                    }
+                };
+                parse_expr(cx.session(), &src).subst(st, cx, &bnd)
+            };
+            fold_output_exprs(block, true, |e| *e = wrap(e));
+            if unit_ret && !result_ret {
+                MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                    if let ExprKind::Ret(ref mut ret @ None) = e.kind {
+                        *ret = Some(parse_expr(cx.session(), &outs_src));
+                    }
+                });
+                let has_trailing = block.stmts.last().map_or(false, |s| {
+                    matches!([s.kind] StmtKind::Expr(..))
+                });
+                if !has_trailing {
+                    block.stmts.push(Stmt {
+                        id: DUMMY_NODE_ID,
+                        kind: StmtKind::Expr(parse_expr(cx.session(), &outs_src)),
+                        span: block.span.shrink_to_hi(),
+                    });
+                }
+            }
+
+            // Declare the locals that replace the out-parameters.
+            let init =
+                if is_unsafe { "::std::mem::zeroed()" }
+                else { "unsafe { ::std::mem::zeroed() }" };
+            let mut decls = outs.iter().map(|o| {
+                let src = format!("let mut {}: {} = {};", o.1, pprust::ty_to_string(&o.3), init);
+                parse_stmts(cx.session(), &src).lone()
+            }).collect::<Vec<_>>();
+            decls.extend(block.stmts.drain(..));
+            block.stmts = decls;
+
+            mod_fns.insert(did, OutparamFn {
+                outs: outs.iter().map(|o| o.0).collect(),
+                unit_ret,
+                result_ret,
+            });
+        });
+
+        if mod_fns.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite call sites.
+
+        let mut handled_calls = HashSet::new();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            let info = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
+            // The rewritten expression contains the original call.
+            if !handled_calls.insert(e.id) {
+                return;
+            }
+            let args: &mut Vec<P<Expr>> = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                _ => {
+                    warn!("can't rewrite non-call use of modified function `{}`",
+                          pprust::expr_to_string(e));
+                    return;
+                }
+            };
+
+            // Find where each out-parameter value should be stored.  `None` means the caller
+            // passed a null pointer, so the value is discarded.
+            let mut bnd = Bindings::new();
+            let mut pats = Vec::new();
+            let mut stores = Vec::new();
+            for (j, &idx) in info.outs.iter().enumerate() {
+                let arg = &args[idx];
+                let dest = match strip_casts(arg).kind {
+                    _ if is_null_ptr(arg) => None,
+                    ExprKind::AddrOf(_, Mutability::Mutable, ref inner) => Some(inner.clone()),
+                    _ => Some(parse_expr(cx.session(), "*__ptr")
+                              .subst(st, cx, &{
+                                  let mut b = Bindings::new();
+                                  b.add("__ptr", arg.clone());
+                                  b
+                              })),
+                };
+                match dest {
+                    Some(dest) => {
+                        let name = format!("__x{}", j);
+                        bnd.add(&name as &str, dest);
+                        pats.push(format!("out{}_", j));
+                        stores.push(format!("{} = out{}_;", name, j));
+                    }
+                    None => pats.push("_".to_owned()),
+                }
+            }
+
+            let removed = info.outs.iter().cloned().collect::<HashSet<_>>();
+            let mut idx = 0;
+            args.retain(|_| {
+                idx += 1;
+                !removed.contains(&(idx - 1))
+            });
+            bnd.add("__call", e.clone());
+
+            let ret = if info.unit_ret { None } else { Some("ret_") };
+            let pat = tuple_src(ret, &pats);
+            let body = format!("{} {}", stores.join(" "), ret.unwrap_or(""));
+            let src = if info.result_ret {
+                format!("__call.map(|{}| {{ {} }})", pat, body)
+            } else if info.unit_ret && stores.len() == 1 && pats.len() == 1 {
+                "__x0 = __call".to_owned()
+            } else {
+                format!("{{ let {} = __call; {} }}", pat, body)
+            };
+            *e = parse_expr(cx.session(), &src).subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `callback_to_closure` Command
+///
+/// Usage: `callback_to_closure`
+///
+/// Marks: `target`
+///
+/// For each function marked `target` that takes a C-style callback, meaning a
+/// function pointer (possibly wrapped in `Option`) whose arguments include a
+/// `*mut c_void` context pointer, along with a separate `*mut c_void` argument
+/// that is only ever passed through as that context, replace the pair with a
+/// generic `F: FnMut(...)` parameter.
+///
+/// The function must only call the callback, or test it with `is_some` or
+/// `is_none`; functions that store the callback are left unchanged.  Each call
+/// site passes a closure that calls the original callback with the original
+/// context.  If a callback function is only used this way, and its context
+/// argument is only ever cast to a single pointer type `*mut T`, its context
+/// argument is changed to have type `*mut T` directly.  The closure calls the
+/// callback in an `unsafe` block if it is an unsafe function pointer; running
+/// `fix_unused_unsafe` afterward removes any that turn out to be redundant.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn for_each(xs: *const i32, n: usize,
+///                        cb: Option<unsafe extern "C" fn(i32, *mut c_void)>,
+///                        data: *mut c_void) {
+///         for i in 0..n {
+///             cb.expect("non-null function pointer")(*xs.add(i), data);
+///         }
+///     }
+///
+///     unsafe extern "C" fn add(x: i32, data: *mut c_void) {
+///         *(data as *mut i32) += x;
+///     }
+///
+///     for_each(xs, n, Some(add), &mut sum as *mut i32 as *mut c_void);
+/// ```
+///
+/// After running `callback_to_closure`, with `for_each` marked:
+///
+/// ```ignore
+///     unsafe fn for_each<F: FnMut(i32)>(xs: *const i32, n: usize, mut cb: F) {
+///         for i in 0..n {
+///             cb(*xs.add(i));
+///         }
+///     }
+///
+///     unsafe extern "C" fn add(x: i32, data: *mut i32) {
+///         *data += x;
+///     }
+///
+///     for_each(xs, n, |a0| unsafe { add(a0, &mut sum as *mut i32) });
+/// ```
+pub struct CallbackToClosure;
+
+/// A callback-taking function being converted by `callback_to_closure`.
+struct CallbackParam {
+    /// Index of the callback argument.
+    cb: usize,
+    /// Index of the context argument.
+    data: usize,
+    /// Index of the context argument in the callback's own argument list.
+    ctx: usize,
+    /// The callback's function pointer type.
+    fn_ty: P<BareFnTy>,
+}
+
+pub fn is_void_ptr_ty(ty: &Ty) -> bool {
+    match ty.kind {
+        ast::TyKind::Ptr(ref mt) => match mt.ty.kind {
+            ast::TyKind::Path(None, ref path) =>
+                path.segments.last().map_or(false, |seg| seg.ident.as_str() == "c_void"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Get the function pointer type of a callback argument, which may be wrapped in `Option`.
+fn callback_fn_ty(ty: &Ty) -> Option<&P<BareFnTy>> {
+    match ty.kind {
+        ast::TyKind::BareFn(ref f) => Some(f),
+        ast::TyKind::Path(None, ref path) => {
+            let seg = path.segments.last()?;
+            if seg.ident.as_str() != "Option" {
+                return None;
+            }
+            match **seg.args.as_ref()? {
+                GenericArgs::AngleBracketed(ref abpd) => match abpd.args.get(0) {
+                    Some(GenericArg::Type(ref ty)) => match ty.kind {
+                        ast::TyKind::BareFn(ref f) => Some(f),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn find_callback_param(decl: &FnDecl) -> Option<CallbackParam> {
+    for (cb, arg) in decl.inputs.iter().enumerate() {
+        let fn_ty = match_or!([callback_fn_ty(&arg.ty)] Some(x) => x; continue);
+        let ctx = match_or!([fn_ty.decl.inputs.iter().rposition(|a| is_void_ptr_ty(&a.ty))]
+                            Some(x) => x; continue);
+        let data = match_or!([decl.inputs.iter().enumerate()
+                              .position(|(j, a)| j != cb && is_void_ptr_ty(&a.ty))]
+                             Some(x) => x; continue);
+        return Some(CallbackParam { cb, data, ctx, fn_ty: fn_ty.clone() });
+    }
+    None
+}
+
+/// If `e` calls the callback `cb_hid` (directly or through `expect`/`unwrap`), return the
+/// call's arguments.
+fn callback_call_args<'a>(cx: &RefactorCtxt,
+                          e: &'a Expr,
+                          cb_hid: HirId) -> Option<&'a [P<Expr>]> {
+    let (f, args) = match_or!([e.kind] ExprKind::Call(ref f, ref args) => (f, args);
+                              return None);
+    let f = match f.kind {
+        ExprKind::MethodCall(ref seg, ref margs) if margs.len() <= 2 &&
+            (seg.ident.as_str() == "expect" || seg.ident.as_str() == "unwrap") => &margs[0],
+        _ => f,
+    };
+    if cx.try_resolve_expr_to_hid(f) == Some(cb_hid) {
+        Some(args)
+    } else {
+        None
+    }
+}
+
+/// Check that the callback and context arguments of `sig`/`block` are only used in ways
+/// `callback_to_closure` can rewrite.
+fn callback_uses_ok(cx: &RefactorCtxt, decl: &FnDecl, block: &Block, p: &CallbackParam) -> bool {
+    let cb_hid = cx.hir_map().node_to_hir_id(decl.inputs[p.cb].pat.id);
+    let data_hid = cx.hir_map().node_to_hir_id(decl.inputs[p.data].pat.id);
+    let arity = p.fn_ty.decl.inputs.len();
+    let (mut cb_uses, mut data_uses, mut ok_cb_uses, mut ok_data_uses) = (0, 0, 0, 0);
+    visit_nodes(block, |e: &Expr| {
+        match cx.try_resolve_expr_to_hid(e) {
+            Some(hid) if hid == cb_hid => cb_uses += 1,
+            Some(hid) if hid == data_hid => data_uses += 1,
+            _ => {}
+        }
+        if let Some(args) = callback_call_args(cx, e, cb_hid) {
+            ok_cb_uses += 1;
+            if args.len() == arity &&
+               cx.try_resolve_expr_to_hid(strip_casts(&args[p.ctx])) == Some(data_hid) {
+                ok_data_uses += 1;
+            }
+        }
+        if let ExprKind::MethodCall(ref seg, ref args) = e.kind {
+            if (seg.ident.as_str() == "is_some" || seg.ident.as_str() == "is_none") &&
+               cx.try_resolve_expr_to_hid(&args[0]) == Some(cb_hid) {
+                ok_cb_uses += 1;
+            }
+        }
+    });
+    cb_uses == ok_cb_uses && data_uses == ok_data_uses
+}
+
+/// Check if `e` is a side-effect-free expression that can be evaluated once per callback call.
+fn is_simple_ctx_expr(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Path(..) | ExprKind::Lit(..) => true,
+        ExprKind::Cast(ref e, _) | ExprKind::AddrOf(_, _, ref e) | ExprKind::Paren(ref e) |
+        ExprKind::Field(ref e, _) => is_simple_ctx_expr(e),
+        _ => false,
+    }
+}
+
+impl Transform for CallbackToClosure {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked functions whose callbacks can be converted.
+
+        let mut cb_fns: HashMap<DefId, CallbackParam> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref b) => (sig, b);
+                                         return);
+            let p = match_or!([find_callback_param(&sig.decl)] Some(x) => x; {
+                warn!("`{}` has no callback with a context argument; skipping it", i.ident);
+                return;
+            });
+            if !callback_uses_ok(cx, &sig.decl, block, &p) {
+                warn!("`{}` uses its callback in ways that can't be converted; skipping it",
+                      i.ident);
+                return;
+            }
+            cb_fns.insert(cx.node_def_id(i.id), p);
+        });
+
+        // (2) Check that every use of the functions is a call with a non-null callback, and
+        // count how the callback functions passed to them are used.
+
+        let mut fn_refs: HashMap<DefId, usize> = HashMap::new();
+        let mut call_refs: HashMap<DefId, usize> = HashMap::new();
+        let mut bad_fns = HashSet::new();
+        // Number of times each local function is passed as a converted callback.
+        let mut cb_refs: HashMap<DefId, usize> = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(did) = cx.try_resolve_expr(e) {
+                *fn_refs.entry(did).or_insert(0) += 1;
+            }
+            let callee = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+            let p = match_or!([cb_fns.get(&callee)] Some(x) => x; return);
+            let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+            *call_refs.entry(callee).or_insert(0) += 1;
+            let cb_arg = match args[p.cb].kind {
+                ExprKind::Call(ref f, ref a) if a.len() == 1 &&
+                    pprust::expr_to_string(f) == "Some" => strip_casts(&a[0]),
+                _ => &args[p.cb],
+            };
+            if is_null_ptr(cb_arg) || pprust::expr_to_string(cb_arg) == "None" {
+                bad_fns.insert(callee);
+            } else if let Some(did) = cx.try_resolve_expr(cb_arg) {
+                *cb_refs.entry(did).or_insert(0) += 1;
+            }
+        });
+        for (did, &n) in &call_refs {
+            if fn_refs.get(did).cloned().unwrap_or(0) != n {
+                bad_fns.insert(*did);
+            }
+        }
+        for did in bad_fns {
+            warn!("`{}` is passed a null callback or used without being called; skipping it",
+                  cx.ty_ctxt().def_path_str(did));
+            cb_fns.remove(&did);
+        }
+        if cb_fns.is_empty() {
+            return;
+        }
+
+        // (3) Find the callback functions whose context argument can be retyped: every use of
+        // the function is as a converted callback, and the context is only cast to one type.
+
+        let ctx_idx = cb_fns.values().map(|p| p.ctx).collect::<HashSet<_>>();
+        let mut ctx_tys: HashMap<DefId, P<Ty>> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(i.id)] Some(x) => x;
+                                return);
+            let n = match_or!([cb_refs.get(&did)] Some(&x) => x; return);
+            if fn_refs.get(&did).cloned().unwrap_or(0) != n || ctx_idx.len() != 1 {
+                return;
+            }
+            let ctx = *ctx_idx.iter().next().unwrap();
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref b) => (sig, b);
+                                         return);
+            let arg = match_or!([sig.decl.inputs.get(ctx)] Some(x) => x; return);
+            if !is_void_ptr_ty(&arg.ty) {
+                return;
+            }
+            let hid = cx.hir_map().node_to_hir_id(arg.pat.id);
+            let (mut uses, mut casts) = (0, Vec::new());
+            visit_nodes(&**block, |e: &Expr| {
+                if cx.try_resolve_expr_to_hid(e) == Some(hid) {
+                    uses += 1;
+                }
+                if let ExprKind::Cast(ref inner, ref ty) = e.kind {
+                    if cx.try_resolve_expr_to_hid(inner) == Some(hid) {
+                        casts.push(ty.clone());
+                    }
+                }
+            });
+            let ty = match_or!([casts.get(0)] Some(x) => x; return);
+            let is_ptr = matches!([ty.kind] ast::TyKind::Ptr(..));
+            if is_ptr && casts.len() == uses && casts.iter().all(|t| t.ast_equiv(ty)) {
+                ctx_tys.insert(did, ty.clone());
+            }
+        });
+
+        // (4) Rewrite the callback functions.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(i.id)] Some(x) => x;
+                                return);
+            let ctx_ty = match_or!([ctx_tys.get(&did)] Some(x) => x; return);
+            let ctx = *ctx_idx.iter().next().unwrap();
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref mut sig, _, ref mut b) =>
+                                         (sig, b); return);
+            let hid = cx.hir_map().node_to_hir_id(sig.decl.inputs[ctx].pat.id);
+            sig.decl.inputs[ctx].ty = ctx_ty.clone();
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                let inner = match e.kind {
+                    ExprKind::Cast(ref inner, _)
+                        if cx.try_resolve_expr_to_hid(inner) == Some(hid) => inner.clone(),
+                    _ => return,
+                };
+                *e = inner;
+            });
+        });
+
+        // (5) Rewrite calls to the converted functions.  This runs before the functions
+        // themselves are changed, so the calls can still be resolved.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+            let p = match_or!([cb_fns.get(&callee)] Some(x) => x; return);
+            let args = match_or!([e.kind] ExprKind::Call(_, ref mut args) => args; return);
+
+            let mut bnd = Bindings::new();
+            let cb_fn = match args[p.cb].kind {
+                ExprKind::Call(ref f, ref a) if a.len() == 1 &&
+                    pprust::expr_to_string(f) == "Some" => a[0].clone(),
+                _ => parse_expr(cx.session(), "__cb.expect(\"non-null function pointer\")")
+                    .subst(st, cx, &{
+                        let mut b = Bindings::new();
+                        b.add("__cb", args[p.cb].clone());
+                        b
+                    }),
+            };
+            let retyped = cx.try_resolve_expr(strip_casts(&cb_fn))
+                .and_then(|did| ctx_tys.get(&did));
+            bnd.add("__f", cb_fn);
+
+            let mut data = args[p.data].clone();
+            if let Some(ctx_ty) = retyped {
+                data = match data.kind {
+                    ExprKind::Cast(ref inner, _) => match inner.kind {
+                        ExprKind::Cast(_, ref ty) if ty.ast_equiv(ctx_ty) => Some(inner.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                }.unwrap_or_else(|| mk().cast_expr(data.clone(), ctx_ty.clone()));
+            }
+
+            let params = (0 .. p.fn_ty.decl.inputs.len() - 1)
+                .map(|j| format!("a{}", j))
+                .collect::<Vec<_>>();
+            let mut call_args = params.clone();
+            call_args.insert(p.ctx, "__data".to_owned());
+            let call = format!("__f({})", call_args.join(", "));
+            let body = if p.fn_ty.unsafety == Unsafety::Unsafe {
+                format!("unsafe {{ {} }}", call)
+            } else {
+                call
+            };
+            let closure_src = if is_simple_ctx_expr(&data) {
+                bnd.add("__data", data);
+                format!("|{}| {}", params.join(", "), body)
+            } else {
+                // Evaluate the context once, outside the closure.
+                bnd.add("__ctx", data);
+                format!("{{ let ctx_ = __ctx; move |{}| {} }}", params.join(", "),
+                        body.replace("__data", "ctx_"))
+            };
+            args[p.cb] = parse_expr(cx.session(), &closure_src).subst(st, cx, &bnd);
+            args.remove(p.data);
+        });
+
+        // (6) Rewrite the converted functions.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(i.id)] Some(x) => x;
+                                return);
+            let p = match_or!([cb_fns.get(&did)] Some(x) => x; return);
+            let (sig, generics, block) = match_or!([i.kind]
+                ItemKind::Fn(ref mut sig, ref mut generics, ref mut b) => (sig, generics, b);
+                return);
+
+            let cb_hid = cx.hir_map().node_to_hir_id(sig.decl.inputs[p.cb].pat.id);
+            let cb_name = match_or!([sig.decl.inputs[p.cb].pat.kind]
+                                    PatKind::Ident(_, ident, _) => ident; return);
+
+            // Rewrite calls of the callback, dropping the context argument.
+            MutVisitNodes::visit(block, |e: &mut P<Expr>| {
+                if callback_call_args(cx, e, cb_hid).is_some() {
+                    if let ExprKind::Call(ref mut f, ref mut args) = e.kind {
+                        *f = mk().ident_expr(cb_name);
+                        args.remove(p.ctx);
+                    }
+                    return;
+                }
+                let is_some = match e.kind {
+                    ExprKind::MethodCall(ref seg, ref args)
+                        if cx.try_resolve_expr_to_hid(&args[0]) == Some(cb_hid) => {
+                        match &*seg.ident.as_str() {
+                            "is_some" => true,
+                            "is_none" => false,
+                            _ => return,
+                        }
+                    }
+                    _ => return,
+                };
+                *e = mk().lit_expr(mk().bool_lit(is_some));
+            });
+
+            // Build the new generic parameter and its bound.
+            let mut name = "F".to_owned();
+            let mut k = 0;
+            while generics.params.iter().any(|gp| gp.ident.as_str() == name) {
+                k += 1;
+                name = format!("F{}", k);
+            }
+            let arg_tys = p.fn_ty.decl.inputs.iter().enumerate()
+                .filter(|&(j, _)| j != p.ctx)
+                .map(|(_, a)| pprust::ty_to_string(&a.ty))
+                .collect::<Vec<_>>();
+            let ret = match p.fn_ty.decl.output {
+                FunctionRetTy::Ty(ref ty) => format!(" -> {}", pprust::ty_to_string(ty)),
+                FunctionRetTy::Default(_) => String::new(),
+            };
+            let src = format!("fn f<{}: FnMut({}){}>() {{}}", name, arg_tys.join(", "), ret);
+            let param = expect!([parse_items(cx.session(), &src).lone().kind]
+                                ItemKind::Fn(_, ref g, _) => g.params[0].clone());
+            generics.params.push(param);
+
+            let cb_arg = &mut sig.decl.inputs[p.cb];
+            cb_arg.ty = parse_ty(cx.session(), &name);
+            if let PatKind::Ident(BindingMode::ByValue(ref mut mutbl), _, _) = cb_arg.pat.kind {
+                *mutbl = Mutability::Mutable;
+            }
+            sig.decl.inputs.remove(p.data);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("func_to_method", |_args| mk(ToMethod));
+    reg.register("struct_methods", |args| mk(StructMethods {
+        struct_name: args[0].clone(),
+        prefix: args.get(1).cloned(),
+    }));
     reg.register("fix_unused_unsafe", |_args| mk(FixUnusedUnsafe));
+    reg.register("remove_redundant_unsafe", |_args| mk(RemoveRedundantUnsafe));
     reg.register("sink_unsafe", |_args| mk(SinkUnsafe));
+    reg.register("outparam_to_return", |_args| mk(OutparamToReturn));
+    reg.register("callback_to_closure", |_args| mk(CallbackToClosure));
     reg.register("wrap_extern", |_args| mk(WrapExtern));
     reg.register("wrap_api", |_args| mk(WrapApi));
     reg.register("abstract", |args| mk(Abstract {
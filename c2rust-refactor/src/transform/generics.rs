@@ -1,14 +1,20 @@
-use std::collections::HashSet;
-use syntax::ast::*;
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, TyKind};
+use syntax::ast::{self, *};
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
 use smallvec::smallvec;
 
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes};
+use crate::ast_manip::{AstEquiv, FlatMapNodes, MutVisitNodes, visit_nodes};
 use crate::command::{CommandState, Registry};
-use crate::driver::{parse_ty};
+use crate::driver::{Phase, parse_ty};
 use crate::path_edit::fold_resolved_paths_with_id;
+use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
+use crate::transform::funcs::is_void_ptr_ty;
+use crate::transform::control_flow::strip_parens;
 use crate::RefactorCtxt;
 use c2rust_ast_builder::{mk, IntoSymbol};
 
@@ -172,6 +178,278 @@ impl Transform for GeneralizeItems {
 }
 
 
+/// # `generalize_void_container` Command
+///
+/// Usage: `generalize_void_container [VAR] [TY]`
+///
+/// Marks: `target`
+///
+/// Turn a type-erased container into a generic one.  Each struct marked
+/// `target` that has `*mut c_void` fields gets a new type parameter called
+/// `VAR` (default: `T`), and those fields become `*mut VAR`.  Every function
+/// whose signature mentions one of these structs also gains the type
+/// parameter, and all `c_void` pointer types inside it, including in its
+/// arguments, return type, locals, and casts, become pointers to `VAR`.
+/// Pointers that such a function passes to other functions expecting
+/// `*mut c_void` are cast back.
+///
+/// Outside these items, the containers are used with the concrete element
+/// type `TY`.  Arguments passed as `*mut c_void` to the container functions
+/// are cast to `*mut TY` instead, and returned element pointers that aren't
+/// immediately cast to another type are cast back to `*mut c_void`.  If `TY` is
+/// not provided, it defaults to the element type that callers cast to and from,
+/// if there is exactly one.
+///
+/// Example:
+///
+/// ```ignore
+///     struct list {       // list: target
+///         data: *mut c_void,
+///         next: *mut list,
+///     }
+///
+///     unsafe fn list_push(l: *mut list, data: *mut c_void) -> *mut list { ... }
+///
+///     let l: *mut list = list_push(l, &mut x as *mut i32 as *mut c_void);
+/// ```
+///
+/// After running `generalize_void_container`:
+///
+/// ```ignore
+///     struct list<T> {
+///         data: *mut T,
+///         next: *mut list<T>,
+///     }
+///
+///     unsafe fn list_push<T>(l: *mut list<T>, data: *mut T) -> *mut list<T> { ... }
+///
+///     let l: *mut list<i32> = list_push(l, &mut x as *mut i32 as *mut i32);
+/// ```
+pub struct GeneralizeVoidContainer {
+    ty_var_name: Symbol,
+    elem_ty: Option<String>,
+}
+
+/// A function that gains a type parameter from `generalize_void_container`.
+struct ContainerFn {
+    /// Indices of the arguments of type `*mut c_void`.
+    void_args: Vec<usize>,
+    /// The function returns `*mut c_void`.
+    void_ret: bool,
+}
+
+fn is_c_void_ptr(cx: &RefactorCtxt, ty: ty::Ty) -> bool {
+    let did = match ty.kind {
+        TyKind::RawPtr(mt) => match mt.ty.kind {
+            TyKind::Adt(def, _) => def.did,
+            TyKind::Foreign(did) => did,
+            _ => return false,
+        },
+        _ => return false,
+    };
+    cx.ty_ctxt().item_name(did).as_str() == "c_void"
+}
+
+impl Transform for GeneralizeVoidContainer {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let var = self.ty_var_name;
+
+        // (1) Find the marked containers and the functions that operate on them.
+
+        let mut containers = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let vd = match_or!([i.kind] ItemKind::Struct(ref vd, _) => vd; return);
+            if vd.fields().iter().any(|f| is_void_ptr_ty(&f.ty)) {
+                containers.insert(cx.node_def_id(i.id));
+            } else {
+                warn!("struct `{}` has no `*mut c_void` fields; skipping it", i.ident);
+            }
+        });
+        if containers.is_empty() {
+            return;
+        }
+
+        let mut fns: HashMap<DefId, ContainerFn> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let sig = match_or!([i.kind] ItemKind::Fn(ref sig, _, _) => sig; return);
+            let mut sig_tys = sig.decl.inputs.iter().map(|arg| &arg.ty).collect::<Vec<_>>();
+            if let FunctionRetTy::Ty(ref ty) = sig.decl.output {
+                sig_tys.push(ty);
+            }
+            let mut uses_container = false;
+            for ty in sig_tys {
+                visit_nodes(&**ty, |t: &Ty| {
+                    let did = cx.try_resolve_ty(t);
+                    uses_container |= did.map_or(false, |did| containers.contains(&did));
+                });
+            }
+            if !uses_container {
+                return;
+            }
+            fns.insert(cx.node_def_id(i.id), ContainerFn {
+                void_args: sig.decl.inputs.iter().enumerate()
+                    .filter(|(_, arg)| is_void_ptr_ty(&arg.ty))
+                    .map(|(j, _)| j)
+                    .collect(),
+                void_ret: match sig.decl.output {
+                    FunctionRetTy::Ty(ref ty) => is_void_ptr_ty(ty),
+                    FunctionRetTy::Default(_) => false,
+                },
+            });
+        });
+
+        let generalized = |did: DefId| containers.contains(&did) || fns.contains_key(&did);
+        let in_generalized = |id: NodeId| {
+            let hir_id = cx.hir_map().node_to_hir_id(id);
+            let parent_id = cx.hir_map().get_parent_item(hir_id);
+            generalized(cx.hir_map().local_def_id(parent_id))
+        };
+
+        // (2) Find the element type used outside the generalized items, and the calls whose
+        // results are immediately cast.
+
+        let mut cast_calls = HashSet::new();
+        let mut elem_tys: Vec<P<Ty>> = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if in_generalized(e.id) {
+                return;
+            }
+            let mut add_elem_ty = |ty: &Ty| if let ast::TyKind::Ptr(ref mt) = ty.kind {
+                if !is_void_ptr_ty(ty) && !elem_tys.iter().any(|t| t.ast_equiv(&mt.ty)) {
+                    elem_tys.push(mt.ty.clone());
+                }
+            };
+            match e.kind {
+                ExprKind::Cast(ref inner, ref ty) => {
+                    let callee = cx.opt_callee(strip_parens(inner));
+                    if let Some(f) = callee.and_then(|did| fns.get(&did)) {
+                        cast_calls.insert(strip_parens(inner).id);
+                        if f.void_ret {
+                            add_elem_ty(ty);
+                        }
+                    }
+                }
+                ExprKind::Call(_, ref args) => {
+                    let f = match_or!([cx.opt_callee(e).and_then(|did| fns.get(&did))]
+                                      Some(x) => x; return);
+                    for &j in &f.void_args {
+                        if let ExprKind::Cast(ref inner, _) = args[j].kind {
+                            if let Some(ty) = cx.opt_node_type(inner.id) {
+                                if !is_c_void_ptr(cx, ty) {
+                                    add_elem_ty(&reflect_tcx_ty(cx.ty_ctxt(), ty));
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+        let elem_ty = match self.elem_ty {
+            Some(ref s) => parse_ty(cx.session(), s),
+            None if elem_tys.len() == 1 => elem_tys.pop().unwrap(),
+            None => panic!("can't infer the container element type; pass TY explicitly"),
+        };
+        let elem_ptr_src = format!("*mut {}", pprust::ty_to_string(&elem_ty));
+
+        // (3) Rewrite uses of the container functions outside the generalized items.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let f = match_or!([cx.opt_callee(e).and_then(|did| fns.get(&did))] Some(x) => x;
+                              return);
+            if in_generalized(e.id) {
+                return;
+            }
+            let id = e.id;
+            if let ExprKind::Call(_, ref mut args) = e.kind {
+                for &j in &f.void_args {
+                    match args[j].kind {
+                        ExprKind::Cast(_, ref mut ty) if is_void_ptr_ty(ty) => {
+                            *ty = parse_ty(cx.session(), &elem_ptr_src);
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    let ty = parse_ty(cx.session(), &elem_ptr_src);
+                    args[j] = mk().cast_expr(args[j].clone(), ty);
+                }
+            }
+            if f.void_ret && !cast_calls.contains(&id) {
+                *e = mk().cast_expr(e.clone(), parse_ty(cx.session(), "*mut ::std::ffi::c_void"));
+            }
+        });
+
+        // (4) Add the type parameter to the generalized items, and replace their `c_void`
+        // pointers with pointers to it.
+
+        let mut void_passes = Vec::new();
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(i.id)] Some(x) => x;
+                                return);
+            if !generalized(did) {
+                return;
+            }
+            // Find pointers this item passes to non-generalized functions as `*mut c_void`.
+            visit_nodes(&**i, |e: &Expr| {
+                let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+                if cx.opt_callee(e).map_or(false, |did| generalized(did)) {
+                    return;
+                }
+                for arg in args {
+                    if cx.opt_node_type(arg.id).map_or(false, |ty| is_c_void_ptr(cx, ty)) {
+                        void_passes.push(arg.id);
+                    }
+                }
+            });
+
+            MutVisitNodes::visit(i, |t: &mut P<Ty>| {
+                if is_void_ptr_ty(t) {
+                    if let ast::TyKind::Ptr(ref mut mt) = t.kind {
+                        mt.ty = mk().ident_ty(var);
+                    }
+                }
+            });
+            match i.kind {
+                ItemKind::Fn(_, ref mut gen, _) | ItemKind::Struct(_, ref mut gen) =>
+                    gen.params.push(mk().ty_param(var)),
+                _ => {}
+            }
+        });
+        let void_passes = void_passes.into_iter().collect::<HashSet<_>>();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if void_passes.contains(&e.id) {
+                *e = mk().cast_expr(e.clone(), parse_ty(cx.session(), "*mut ::std::ffi::c_void"));
+            }
+        });
+
+        // (5) Pass the type argument to every use of a container type.
+
+        fold_resolved_paths_with_id(krate, cx, |path_id, qself, mut path, def| {
+            match def[0].opt_def_id() {
+                Some(def_id) if containers.contains(&def_id) => (),
+                _ => return (qself, path),
+            };
+
+            let arg = if in_generalized(path_id) {
+                mk().ident_ty(var)
+            } else {
+                elem_ty.clone()
+            };
+            let seg = path.segments.last_mut().unwrap();
+            let abpd = mk().angle_bracketed_args(vec![arg]);
+            seg.args = Some(P(GenericArgs::AngleBracketed(abpd)));
+            (qself, path)
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
@@ -179,4 +457,9 @@ pub fn register_commands(reg: &mut Registry) {
         ty_var_name: args.get(0).map_or("T", |x| x).into_symbol(),
         replacement_ty: args.get(1).cloned(),
     }));
+
+    reg.register("generalize_void_container", |args| mk(GeneralizeVoidContainer {
+        ty_var_name: args.get(0).map_or("T", |x| x).into_symbol(),
+        elem_ty: args.get(1).cloned(),
+    }));
 }
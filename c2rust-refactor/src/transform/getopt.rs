@@ -0,0 +1,412 @@
+//! Conversion of `getopt` and `getopt_long` loops to `clap`.
+
+use std::collections::HashMap;
+use std::str;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{visit_nodes, AstEquiv, MutVisitNodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::command::{CommandState, Registry};
+use crate::driver::parse_stmts;
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::transform::slices::cast_str;
+use crate::transform::strings::c_str_lit;
+use crate::RefactorCtxt;
+
+
+/// An option accepted by a `getopt` loop.
+#[derive(Clone, Debug)]
+struct GetoptOpt {
+    /// The value that `getopt` returns for this option.
+    val: i128,
+    short: Option<char>,
+    long: Option<String>,
+    takes_value: bool,
+}
+
+/// Get the value of an integer or character literal, possibly negated and with casts.
+fn int_lit(e: &Expr) -> Option<i128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(i, _) => Some(i as i128),
+            LitKind::Char(c) => Some(c as i128),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref e) => int_lit(e).map(|i| -i),
+        _ => None,
+    }
+}
+
+/// Parse a `getopt` option string.  Returns `None` for the features that `clap` has no
+/// equivalent for: optional arguments (`::`), and the `+` and `-` modes for non-option arguments.
+fn parse_optstring(s: &[u8]) -> Option<Vec<GetoptOpt>> {
+    if s.first().map_or(false, |&c| c == b'+' || c == b'-') {
+        return None;
+    }
+    // A leading `:` only changes how errors are reported.
+    let s = s.strip_prefix(b":").unwrap_or(s);
+    let mut opts = Vec::new();
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i];
+        if c == b':' || !c.is_ascii_graphic() {
+            return None;
+        }
+        let colons = s[i + 1..].iter().take_while(|&&c| c == b':').count();
+        if colons > 1 {
+            return None;
+        }
+        opts.push(GetoptOpt {
+            val: c as i128,
+            short: Some(c as char),
+            long: None,
+            takes_value: colons == 1,
+        });
+        i += 1 + colons;
+    }
+    Some(opts)
+}
+
+/// Parse the initializer of a `struct option` array for `getopt_long`, returning the name,
+/// whether it takes an argument, and the value of each entry.  Entries with a non-null `flag`
+/// or an optional argument aren't supported.
+fn parse_longopts(e: &Expr) -> Option<Vec<(String, bool, i128)>> {
+    let elems = match_or!([e.kind] ExprKind::Array(ref elems) => elems; return None);
+    let mut longopts = Vec::new();
+    for elem in elems {
+        let fields = match_or!([elem.kind] ExprKind::Struct(_, ref fields, None) => fields;
+                               return None);
+        let field = |name: &str| fields.iter()
+            .find(|f| f.ident.as_str() == name)
+            .map(|f| &*f.expr);
+        let name = field("name")?;
+        if is_null_ptr(name) {
+            // The terminating entry.
+            break;
+        }
+        let name = str::from_utf8(c_str_lit(name)?).ok()?.to_owned();
+        if !is_null_ptr(field("flag")?) {
+            return None;
+        }
+        let takes_value = match int_lit(field("has_arg")?)? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        longopts.push((name, takes_value, int_lit(field("val")?)?));
+    }
+    Some(longopts)
+}
+
+/// Get the name of the array passed as the `longopts` argument of `getopt_long`.
+fn longopts_name(e: &Expr) -> Option<String> {
+    let e = strip_casts(e);
+    let base = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && matches!([&*seg.ident.as_str()] "as_ptr", "as_mut_ptr") =>
+            strip_casts(&args[0]),
+        ExprKind::AddrOf(_, _, ref inner) => match inner.kind {
+            ExprKind::Index(ref base, ref idx) if int_lit(idx) == Some(0) => &**base,
+            _ => &**inner,
+        },
+        _ => return None,
+    };
+    let path = match_or!([base.kind] ExprKind::Path(None, ref p) => p; return None);
+    Some(path.segments.last()?.ident.to_string())
+}
+
+/// The parts of a transpiled `while ((c = getopt(...)) != -1) switch (c) { ... }` loop:
+///
+/// ```ignore
+///     loop {
+///         c = getopt(argc, argv, optstring);
+///         if !(c != -1) { break; }
+///         match c { ... }
+///     }
+/// ```
+struct GetoptLoop<'a> {
+    /// The variable holding the option, `c` above.
+    opt: &'a Expr,
+    /// The arguments of `getopt` or `getopt_long`.
+    args: &'a [P<Expr>],
+    /// The `match` on the option.
+    body: &'a Expr,
+}
+
+fn expr_of_stmt(s: &Stmt) -> Option<&Expr> {
+    match s.kind {
+        StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => Some(e),
+        _ => None,
+    }
+}
+
+/// Check if `e` is `!(opt != -1)` or `opt == -1`.
+fn is_getopt_done(e: &Expr, opt: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Unary(UnOp::Not, ref inner) => is_getopt_not_done(inner, opt),
+        ExprKind::Binary(op, ref lhs, ref rhs) =>
+            op.node == BinOpKind::Eq && lhs.ast_equiv(opt) && int_lit(rhs) == Some(-1),
+        ExprKind::Paren(ref inner) => is_getopt_done(inner, opt),
+        _ => false,
+    }
+}
+
+/// Check if `e` is `opt != -1`.
+fn is_getopt_not_done(e: &Expr, opt: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Binary(op, ref lhs, ref rhs) =>
+            op.node == BinOpKind::Ne && lhs.ast_equiv(opt) && int_lit(rhs) == Some(-1),
+        ExprKind::Paren(ref inner) => is_getopt_not_done(inner, opt),
+        _ => false,
+    }
+}
+
+fn as_getopt_loop(s: &Stmt) -> Option<GetoptLoop> {
+    let body = match_or!([expr_of_stmt(s)?.kind] ExprKind::Loop(ref body, None) => body;
+                         return None);
+    if body.stmts.len() != 3 {
+        return None;
+    }
+
+    let (opt, call) = match_or!([expr_of_stmt(&body.stmts[0])?.kind]
+                                ExprKind::Assign(ref lhs, ref rhs) => (lhs, rhs); return None);
+    let args = match_or!([call.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !(is_call_to(call, "getopt") && args.len() == 3) &&
+       !(is_call_to(call, "getopt_long") && args.len() == 5) {
+        return None;
+    }
+
+    let (cond, then) = match_or!([expr_of_stmt(&body.stmts[1])?.kind]
+                                 ExprKind::If(ref cond, ref then, None) => (cond, then);
+                                 return None);
+    let is_break = then.stmts.len() == 1 &&
+        matches!([expr_of_stmt(&then.stmts[0]).map(|e| &e.kind)]
+                 Some(ExprKind::Break(None, None)));
+    if !is_break || !is_getopt_done(cond, opt) {
+        return None;
+    }
+
+    let body = expr_of_stmt(&body.stmts[2])?;
+    match body.kind {
+        ExprKind::Match(ref scrutinee, _) if scrutinee.ast_equiv(opt) => {}
+        _ => return None,
+    }
+    Some(GetoptLoop { opt, args, body })
+}
+
+/// Build the statements that replace a `getopt` loop.
+fn build_clap_stmts(l: &GetoptLoop, opts: &[GetoptOpt]) -> String {
+    let argc = cast_str(&l.args[0], "isize");
+    let argv = strip_casts(&l.args[1]);
+    let argv = match argv.kind {
+        ExprKind::Path(..) | ExprKind::Field(..) | ExprKind::MethodCall(..) =>
+            pprust::expr_to_string(argv),
+        _ => format!("({})", pprust::expr_to_string(argv)),
+    };
+
+    let mut src = String::new();
+    src.push_str("let matches = ::clap::Command::new(env!(\"CARGO_PKG_NAME\"))\
+                  .disable_help_flag(true)");
+    for (i, opt) in opts.iter().enumerate() {
+        src.push_str(&format!(".arg(::clap::Arg::new(\"opt{}\")", i));
+        if let Some(c) = opt.short {
+            src.push_str(&format!(".short({:?})", c));
+        }
+        if let Some(ref name) = opt.long {
+            src.push_str(&format!(".long({:?})", name));
+        }
+        if opt.takes_value {
+            src.push_str(".takes_value(true)");
+        }
+        src.push_str(".multiple_occurrences(true))");
+    }
+    // `getopt` ignores non-option arguments, which are left for the program.
+    src.push_str(".arg(::clap::Arg::new(\"args\").multiple_values(true).hide(true))");
+    src.push_str(&format!(
+        ".get_matches_from((0..{}).map(|i| \
+             <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(\
+                 ::std::ffi::CStr::from_ptr(*{}.offset(i)).to_bytes()).to_os_string()));",
+        argc, argv));
+
+    // Collect the options in the order they were given, along with their arguments.
+    src.push_str("let mut opts: Vec<(usize, _, Option<::std::ffi::CString>)> = Vec::new();");
+    for (i, opt) in opts.iter().enumerate() {
+        if opt.takes_value {
+            src.push_str(&format!(
+                "if let (Some(indices), Some(values)) = \
+                     (matches.indices_of(\"opt{0}\"), matches.values_of_os(\"opt{0}\")) {{ \
+                     opts.extend(indices.zip(values).map(|(i, v)| (i, {1}, Some(\
+                         ::std::ffi::CString::new(\
+                             <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::as_bytes(v))\
+                         .unwrap())))); \
+                 }}",
+                i, opt.val));
+        } else {
+            src.push_str(&format!(
+                "if let Some(indices) = matches.indices_of(\"opt{}\") {{ \
+                     opts.extend(indices.map(|i| (i, {}, None))); \
+                 }}",
+                i, opt.val));
+        }
+    }
+    src.push_str("opts.sort_by_key(|&(i, _, _)| i);");
+
+    let (arg, set_optarg) = if opts.iter().any(|o| o.takes_value) {
+        ("ref arg",
+         "optarg = arg.as_ref().map_or(::std::ptr::null_mut(), |a| a.as_ptr() as *mut _);")
+    } else {
+        ("_", "")
+    };
+    src.push_str(&format!(
+        "for &(_, opt, {}) in &opts {{ {} = opt; {} {} }}",
+        arg, pprust::expr_to_string(l.opt), set_optarg, pprust::expr_to_string(l.body)));
+    src
+}
+
+
+/// # `getopt_to_clap` Command
+///
+/// Usage: `getopt_to_clap`
+///
+/// Replace the usual `getopt` option parsing loop,
+/// `while ((c = getopt(argc, argv, "ab:")) != -1) switch (c) { ... }`, or the
+/// same loop with `getopt_long`, with argument parsing by `clap`.  The options
+/// from the option string and the `longopts` array become `clap::Arg`s, and the
+/// `switch` (now a `match`) runs once for each option that `clap` found, in the
+/// order they were given on the command line, with `optarg` pointing to the
+/// option's argument:
+///
+/// ```ignore
+///     loop {
+///         c = getopt(argc, argv, b"vo:\0" as *const u8 as *const libc::c_char);
+///         if !(c != -(1 as libc::c_int)) {
+///             break;
+///         }
+///         match c {
+///             118 => verbose = 1,
+///             111 => output = optarg,
+///             _ => return 2,
+///         }
+///     }
+/// ```
+///
+/// becomes
+///
+/// ```ignore
+///     let matches = ::clap::Command::new(env!("CARGO_PKG_NAME"))
+///         .disable_help_flag(true)
+///         .arg(::clap::Arg::new("opt0").short('v').multiple_occurrences(true))
+///         .arg(::clap::Arg::new("opt1").short('o').takes_value(true).multiple_occurrences(true))
+///         ...
+///         .get_matches_from(...);
+///     let mut opts: Vec<(usize, _, Option<::std::ffi::CString>)> = Vec::new();
+///     ...
+///     for &(_, opt, ref arg) in &opts {
+///         c = opt;
+///         optarg = arg.as_ref().map_or(::std::ptr::null_mut(), |a| a.as_ptr() as *mut _);
+///         match c { ... }
+///     }
+/// ```
+///
+/// `clap` reports unknown options and missing arguments itself, and exits with
+/// status 2, so the `'?'` case of the `switch` is no longer reached.  Loops in
+/// functions that use `optind`, `optopt` or `opterr`, and option strings or
+/// `longopts` entries that use optional arguments, the `+` or `-` modes, or a
+/// non-null `flag`, are left unchanged, and a warning is printed.  The
+/// converted crate needs a dependency on `clap` 3.
+pub struct GetoptToClap;
+
+impl Transform for GetoptToClap {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // Find the initializers of all the arrays that could be `longopts`.
+        let mut longopts = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Static(_, _, ref init) = i.kind {
+                longopts.insert(i.ident.to_string(), parse_longopts(init));
+            }
+        });
+        visit_nodes(krate, |l: &Local| {
+            if let (PatKind::Ident(_, ident, None), Some(init)) = (&l.pat.kind, &l.init) {
+                longopts.insert(ident.to_string(), parse_longopts(init));
+            }
+        });
+
+        mut_visit_fns(krate, |fl| {
+            let block = match fl.block {
+                Some(ref mut block) => block,
+                None => return,
+            };
+            let ident = fl.ident;
+            let mut uses_globals = false;
+            visit_nodes(&**block, |e: &Expr| {
+                if let ExprKind::Path(None, ref path) = e.kind {
+                    let name = path.segments.last().unwrap().ident.as_str();
+                    if matches!([&*name] "optind", "optopt", "opterr") {
+                        uses_globals = true;
+                    }
+                }
+            });
+
+            MutVisitNodes::visit(block, |b: &mut P<Block>| {
+                let mut new_stmts = Vec::with_capacity(b.stmts.len());
+                for s in b.stmts.drain(..) {
+                    let src = as_getopt_loop(&s).and_then(|l| {
+                        if uses_globals {
+                            warn!("`{}` uses `optind`, `optopt` or `opterr`; \
+                                   skipping its `getopt` loop", ident);
+                            return None;
+                        }
+                        let optstring = match c_str_lit(&l.args[2]).and_then(parse_optstring) {
+                            Some(opts) => opts,
+                            None => {
+                                warn!("unsupported `getopt` option string in `{}`", ident);
+                                return None;
+                            }
+                        };
+                        let mut opts = optstring;
+                        if l.args.len() == 5 {
+                            let longs = longopts_name(&l.args[3])
+                                .and_then(|name| longopts.get(&name).cloned())
+                                .flatten();
+                            let longs = match longs {
+                                Some(longs) if is_null_ptr(&l.args[4]) => longs,
+                                _ => {
+                                    warn!("unsupported `getopt_long` options in `{}`", ident);
+                                    return None;
+                                }
+                            };
+                            for (name, takes_value, val) in longs {
+                                match opts.iter_mut().find(|o| o.val == val && o.long.is_none()) {
+                                    Some(ref o) if o.takes_value != takes_value => return None,
+                                    Some(o) => o.long = Some(name),
+                                    None => opts.push(GetoptOpt {
+                                        val,
+                                        short: None,
+                                        long: Some(name),
+                                        takes_value,
+                                    }),
+                                }
+                            }
+                        }
+                        Some(build_clap_stmts(&l, &opts))
+                    });
+                    match src {
+                        Some(src) => new_stmts.extend(parse_stmts(cx.session(), &src)),
+                        None => new_stmts.push(s),
+                    }
+                }
+                b.stmts = new_stmts;
+            });
+        });
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("getopt_to_clap", |_args| mk(GetoptToClap));
+}
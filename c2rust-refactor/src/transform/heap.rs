@@ -0,0 +1,1121 @@
+//! Transforms for replacing C heap management (`malloc`, `calloc`, `free`) with owned Rust types.
+
+use std::collections::{HashMap, HashSet};
+use smallvec::smallvec;
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv, TyKind};
+use syntax::ast;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::Symbol;
+
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::slices::as_size_of;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+
+/// A pointer-typed storage location selected for conversion: either a local variable (identified
+/// by its binding) or a struct field.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum HeapVar {
+    Local(HirId),
+    Field(DefId),
+}
+
+/// Strip any casts and parentheses surrounding `e`.  Transpiled code casts pointers to and from
+/// `*mut c_void` around every allocator call.
+pub fn strip_casts(e: &Expr) -> &Expr {
+    match e.kind {
+        ExprKind::Cast(ref e, _) | ExprKind::Paren(ref e) => strip_casts(e),
+        _ => e,
+    }
+}
+
+/// Get the name of the function called by `e`, if it's a call to a path.  Only the last segment
+/// is used, since libc functions may be declared in any module of the transpiled crate.
+pub fn callee_name(e: &Expr) -> Option<Symbol> {
+    let func = match_or!([e.kind] ExprKind::Call(ref func, _) => func; return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref path) => path; return None);
+    path.segments.last().map(|seg| seg.ident.name)
+}
+
+/// Check if `e` is a call to a function named `name`.
+pub fn is_call_to(e: &Expr, name: &str) -> bool {
+    callee_name(e).map_or(false, |n| n.as_str() == name)
+}
+
+pub fn is_alloc_call(e: &Expr) -> bool {
+    let e = strip_casts(e);
+    is_call_to(e, "malloc") || is_call_to(e, "calloc")
+}
+
+/// Check if `e` is a null pointer constant, such as `0 as *mut T` or `ptr::null_mut()`.
+pub fn is_null_ptr(e: &Expr) -> bool {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref l) => matches!([l.kind] LitKind::Int(0, _)),
+        _ => is_call_to(e, "null_mut") || is_call_to(e, "null"),
+    }
+}
+
+/// If `e` is a call to `free`, return its (uncast) argument.
+pub fn freed_expr(e: &Expr) -> Option<&Expr> {
+    if !is_call_to(e, "free") {
+        return None;
+    }
+    let args = expect!([e.kind] ExprKind::Call(_, ref args) => args);
+    args.get(0).map(|a| strip_casts(a))
+}
+
+/// If `e` is a `size_of::<T>()` call, return `T`.
+fn size_of_ty(e: &Expr) -> Option<&P<ast::Ty>> {
+    let e = strip_casts(e);
+    if !is_call_to(e, "size_of") {
+        return None;
+    }
+    let func = expect!([e.kind] ExprKind::Call(ref func, _) => func);
+    let path = expect!([func.kind] ExprKind::Path(_, ref path) => path);
+    let args = path.segments.last()?.args.as_ref()?;
+    match **args {
+        GenericArgs::AngleBracketed(ref abpd) => match abpd.args.get(0) {
+            Some(GenericArg::Type(ref ty)) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Given the byte size passed to an allocator function, compute the number of elements being
+/// allocated.  This recognizes `n * size_of::<T>()` and `size_of::<T>() * n` (including the
+/// `wrapping_mul` form that the transpiler produces for unsigned multiplication), as well as a bare
+/// `size_of::<T>()`, which allocates a single element.
+fn elem_count(size: &Expr) -> Option<P<Expr>> {
+    let size = strip_casts(size);
+    if size_of_ty(size).is_some() {
+        return Some(mk().lit_expr(mk().int_lit(1, "")));
+    }
+    let (a, b) = match size.kind {
+        ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::Mul => (a, b),
+        ExprKind::MethodCall(ref seg, ref args)
+            if seg.ident.as_str() == "wrapping_mul" && args.len() == 2 => (&args[0], &args[1]),
+        _ => return None,
+    };
+    if size_of_ty(b).is_some() {
+        Some(P(strip_casts(a).clone()))
+    } else if size_of_ty(a).is_some() {
+        Some(P(strip_casts(b).clone()))
+    } else {
+        None
+    }
+}
+
+/// If `e` is a call to `malloc`, `calloc`, or `realloc`, return the number of elements it
+/// allocates.
+fn alloc_count(e: &Expr) -> Option<P<Expr>> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if is_call_to(e, "malloc") && args.len() == 1 {
+        elem_count(&args[0])
+    } else if is_call_to(e, "calloc") && args.len() == 2 {
+        Some(P(strip_casts(&args[0]).clone()))
+    } else if is_call_to(e, "realloc") && args.len() == 2 {
+        elem_count(&args[1])
+    } else {
+        None
+    }
+}
+
+/// Check if `e` is a `malloc` or `calloc` call allocating exactly one object.  Anything else is an
+/// array, which doesn't fit in a `Box<T>`.
+fn is_single_alloc(e: &Expr) -> bool {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return false);
+    let is_one = |n: Option<P<Expr>>| n.map_or(false, |n| as_int_lit(&n) == Some(1));
+    if is_call_to(e, "malloc") {
+        is_one(alloc_count(e))
+    } else if is_call_to(e, "calloc") {
+        is_one(alloc_count(e)) && is_one(elem_count(&args[1]))
+    } else {
+        false
+    }
+}
+
+fn pointee_ty(ty: &ast::Ty) -> Option<P<ast::Ty>> {
+    match ty.kind {
+        ast::TyKind::Ptr(ref mty) => Some(mty.ty.clone()),
+        _ => None,
+    }
+}
+
+/// Get the `DefId` of the field `name` of the struct that the expression `obj` evaluates to.
+pub fn field_def_id(cx: &RefactorCtxt, obj: NodeId, name: Ident) -> Option<DefId> {
+    match cx.opt_adjusted_node_type(obj)?.kind {
+        TyKind::Adt(adt, _) if !adt.is_enum() => {
+            adt.non_enum_variant().fields.iter()
+                .find(|f| f.ident == name)
+                .map(|f| f.did)
+        }
+        _ => None,
+    }
+}
+
+/// Where a value stored into one of the selected locations comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Source {
+    Null,
+    /// An allocation of a single object.
+    Alloc,
+    /// Another selected location, whose `Box` gets moved.
+    Var(HeapVar),
+    /// Anything else, such as an array allocation or a pointer that may have other aliases.
+    Other,
+}
+
+/// The set of heap locations selected for conversion, along with the original pointee type of
+/// each one.
+struct HeapVars<'a, 'tcx: 'a> {
+    cx: &'a RefactorCtxt<'a, 'tcx>,
+    pointees: HashMap<HeapVar, P<ast::Ty>>,
+}
+
+impl<'a, 'tcx> HeapVars<'a, 'tcx> {
+    /// Collect the locals and struct fields marked with `label`.
+    fn collect(st: &CommandState, cx: &'a RefactorCtxt<'a, 'tcx>, krate: &Crate, label: &str)
+               -> HeapVars<'a, 'tcx> {
+        let mut pointees = HashMap::new();
+
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, label) && !st.marked(l.pat.id, label) {
+                return;
+            }
+            let pointee = l.ty.as_ref().and_then(|ty| pointee_ty(ty)).or_else(|| {
+                match cx.node_type(l.pat.id).kind {
+                    TyKind::RawPtr(mt) => Some(reflect_tcx_ty(cx.ty_ctxt(), mt.ty)),
+                    _ => None,
+                }
+            });
+            match pointee {
+                Some(ty) => {
+                    let hir_id = cx.hir_map().node_to_hir_id(l.pat.id);
+                    pointees.insert(HeapVar::Local(hir_id), ty);
+                }
+                None => warn!("local `{}` is not a raw pointer; skipping it",
+                              pprust::pat_to_string(&l.pat)),
+            }
+        });
+
+        visit_nodes(krate, |i: &Item| {
+            let fields = match i.kind {
+                ItemKind::Struct(VariantData::Struct(ref fields, _), _) => fields,
+                _ => return,
+            };
+            for sf in fields {
+                if !st.marked(sf.id, label) {
+                    continue;
+                }
+                match pointee_ty(&sf.ty) {
+                    Some(ty) => { pointees.insert(HeapVar::Field(cx.node_def_id(sf.id)), ty); }
+                    None => warn!("field `{}::{:?}` is not a raw pointer; skipping it",
+                                  i.ident, sf.ident),
+                }
+            }
+        });
+
+        HeapVars { cx, pointees }
+    }
+
+    /// If `e` refers directly to one of the selected locals or fields, return it.
+    fn var_of(&self, e: &Expr) -> Option<HeapVar> {
+        let var = match e.kind {
+            ExprKind::Path(..) => HeapVar::Local(self.cx.try_resolve_expr_to_hid(e)?),
+            ExprKind::Field(ref obj, name) => HeapVar::Field(field_def_id(self.cx, obj.id, name)?),
+            _ => return None,
+        };
+        if self.pointees.contains_key(&var) {
+            Some(var)
+        } else {
+            None
+        }
+    }
+
+    /// Find out where the value `e`, stored into one of the selected locations, comes from.
+    fn source_of(&self, e: &Expr) -> Source {
+        if is_null_ptr(e) {
+            Source::Null
+        } else if is_single_alloc(e) {
+            Source::Alloc
+        } else if let Some(var) = self.var_of(strip_casts(e)) {
+            Source::Var(var)
+        } else {
+            Source::Other
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pointees.is_empty()
+    }
+}
+
+
+/// # `malloc_to_box` Command
+///
+/// Usage: `malloc_to_box`
+///
+/// Marks: `target`
+///
+/// For each local variable or struct field marked `target` that holds a raw
+/// pointer to a single heap-allocated object, change its type from `*mut T` to
+/// `Box<T>`, replacing `malloc(sizeof(T))`/`calloc(1, sizeof(T))` allocations with
+/// `Box::new` and removing the matching `free` calls.
+///
+/// Every value stored in the pointer must be null, such an allocation, or another
+/// converted pointer, whose `Box` is moved into it.  Pointers that are assigned
+/// anything else, like an allocation of several objects or a pointer that may have
+/// other aliases, are left unchanged, since a `Box` would free them twice or hold
+/// an array.
+///
+/// If the pointer is ever null (it is compared with `is_null()`, assigned or
+/// initialized with a null pointer or a nullable converted pointer, or is a field
+/// that gets freed separately from its containing struct), its type becomes
+/// `Option<Box<T>>` instead, and
+/// dereferences are rewritten to go through `as_ref()`/`as_mut()`.  Any remaining
+/// uses that need the raw pointer are converted back with `&mut *p as *mut T`.
+///
+/// Pointers that are used with pointer arithmetic (or any method other than
+/// `is_null`) are left unchanged, since they most likely refer to arrays.  Use
+/// `heap_array_to_vec` for those.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut p: *mut Point = malloc(::std::mem::size_of::<Point>() as libc::c_ulong)
+///         as *mut Point;
+///     (*p).x = 1;
+///     free(p as *mut libc::c_void);
+/// ```
+///
+/// After running `malloc_to_box`, with `p` marked:
+///
+/// ```ignore
+///     let mut p: Box<Point> = Box::new(::std::mem::zeroed::<Point>());
+///     (*p).x = 1;
+///     ::std::mem::drop(p);
+/// ```
+pub struct MallocToBox;
+
+impl Transform for MallocToBox {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut vars = HeapVars::collect(st, cx, krate, "target");
+        if vars.is_empty() {
+            return;
+        }
+
+        // (1) Inspect the uses of each selected location, to find out which ones may be null and
+        // which ones are used in ways we can't handle.
+
+        let mut nullable = HashSet::new();
+        let mut unsupported = HashSet::new();
+        let mut values: Vec<(HeapVar, P<Expr>)> = Vec::new();
+
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args) => {
+                    if let Some(var) = vars.var_of(strip_casts(&args[0])) {
+                        if seg.ident.as_str() == "is_null" {
+                            nullable.insert(var);
+                        } else {
+                            unsupported.insert(var);
+                        }
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    if let Some(var) = vars.var_of(lhs) {
+                        values.push((var, rhs.clone()));
+                    }
+                }
+
+                ExprKind::Struct(_, ref fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(e.id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if let Some(var) = did.map(HeapVar::Field) {
+                            if vars.pointees.contains_key(&var) {
+                                values.push((var, f.expr.clone()));
+                            }
+                        }
+                    }
+                }
+
+                _ => {
+                    // A field freed on its own (rather than along with its parent struct) is left
+                    // dangling, so it needs an `Option` to represent the freed state.
+                    if let Some(var @ HeapVar::Field(_)) = freed_expr(e).and_then(|a| vars.var_of(a)) {
+                        nullable.insert(var);
+                    }
+                }
+            }
+        });
+
+        visit_nodes(krate, |l: &Local| {
+            let var = HeapVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if let Some(ref init) = l.init {
+                if vars.pointees.contains_key(&var) {
+                    values.push((var, init.clone()));
+                }
+            }
+        });
+
+        for var in &unsupported {
+            warn!("{:?} is used with pointer arithmetic; leaving it unchanged", var);
+            vars.pointees.remove(var);
+        }
+
+        // Check the values stored into each location.  Leaving one location unchanged can rule
+        // out the locations it's copied to or from, so repeat until nothing changes.
+        loop {
+            let mut changed = false;
+            for &(var, ref value) in &values {
+                if !vars.pointees.contains_key(&var) {
+                    // The raw pointer copied here could be freed through this location.
+                    if let Some(src) = vars.var_of(strip_casts(value)) {
+                        warn!("{:?} is copied to {:?}, which is left unchanged; \
+                               leaving it unchanged too", src, var);
+                        vars.pointees.remove(&src);
+                        changed = true;
+                    }
+                    continue;
+                }
+                match vars.source_of(value) {
+                    Source::Null => changed |= nullable.insert(var),
+                    Source::Alloc => {}
+                    Source::Var(src) => {
+                        if nullable.contains(&src) {
+                            changed |= nullable.insert(var);
+                        }
+                    }
+                    Source::Other => {
+                        warn!("{:?} is assigned `{}`, which isn't a single allocation or another \
+                               converted pointer; leaving it unchanged",
+                              var, pprust::expr_to_string(value));
+                        vars.pointees.remove(&var);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+
+        // Converted pointers copied into other converted pointers are moved as they are.
+        let moved: HashSet<NodeId> = values.iter()
+            .filter(|&&(var, ref value)| {
+                vars.pointees.contains_key(&var) &&
+                    matches!([vars.source_of(value)] Source::Var(_))
+            })
+            .map(|(_, value)| strip_casts(value).id)
+            .collect();
+
+        let convert = |var: HeapVar, tmpl: &P<Expr>, opt_tmpl: &P<Expr>, e: &Expr| -> P<Expr> {
+            let mut bnd = Bindings::new();
+            bnd.add("__e", P(e.clone()));
+            bnd.add("__t", vars.pointees[&var].clone());
+            if nullable.contains(&var) {
+                opt_tmpl.clone().subst(st, cx, &bnd)
+            } else {
+                tmpl.clone().subst(st, cx, &bnd)
+            }
+        };
+
+        let alloc = parse_expr(cx.session(), "Box::new(::std::mem::zeroed::<__t>())");
+        let opt_alloc = parse_expr(cx.session(), "Some(Box::new(::std::mem::zeroed::<__t>()))");
+        let none = parse_expr(cx.session(), "None");
+
+        // Convert a value of the original pointer type, as assigned to `var`.
+        let convert_value = |var: HeapVar, e: &Expr| -> P<Expr> {
+            match vars.source_of(e) {
+                Source::Alloc => convert(var, &alloc, &opt_alloc, e),
+                Source::Null => none.clone(),
+                Source::Var(src) => {
+                    let src_e = P(strip_casts(e).clone());
+                    if nullable.contains(&var) && !nullable.contains(&src) {
+                        mk().call_expr(mk().path_expr(vec!["Some"]), vec![src_e])
+                    } else {
+                        src_e
+                    }
+                }
+                Source::Other => panic!("unchecked value assigned to {:?}: `{}`",
+                                        var, pprust::expr_to_string(e)),
+            }
+        };
+
+        // (2) Change the types of the selected locals and fields, and convert their initializers.
+
+        let box_ty = parse_ty(cx.session(), "Box<__t>");
+        let opt_box_ty = parse_ty(cx.session(), "Option<Box<__t>>");
+        let new_ty = |var: HeapVar| -> P<ast::Ty> {
+            let mut bnd = Bindings::new();
+            bnd.add("__t", vars.pointees[&var].clone());
+            if nullable.contains(&var) {
+                opt_box_ty.clone().subst(st, cx, &bnd)
+            } else {
+                box_ty.clone().subst(st, cx, &bnd)
+            }
+        };
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = HeapVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if !vars.pointees.contains_key(&var) {
+                return;
+            }
+            if l.ty.is_some() {
+                l.ty = Some(new_ty(var));
+            }
+            if let Some(ref mut init) = l.init {
+                *init = convert_value(var, init);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if !st.marked(sf.id, "target") {
+                        continue;
+                    }
+                    let var = HeapVar::Field(cx.node_def_id(sf.id));
+                    if vars.pointees.contains_key(&var) {
+                        sf.ty = new_ty(var);
+                    }
+                }
+            }
+        });
+
+        // (3) Rewrite the operations that have direct `Box` equivalents: allocation, `free`, null
+        // checks, and dereferences.  Uses handled here are recorded in `handled` so the next step
+        // leaves them alone.
+
+        let free_tmpl = parse_expr(cx.session(), "::std::mem::drop(__e)");
+        let opt_free_tmpl = parse_expr(cx.session(), "__e = None");
+        let is_null_tmpl = parse_expr(cx.session(), "false");
+        let opt_is_null_tmpl = parse_expr(cx.session(), "__e.is_none()");
+
+        let mut handled: HashSet<NodeId> = moved;
+        let mut opt_derefs: HashSet<NodeId> = HashSet::new();
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some((var, arg)) = freed_expr(e).and_then(|a| vars.var_of(a).map(|v| (v, a))) {
+                handled.insert(arg.id);
+                let new_e = convert(var, &free_tmpl, &opt_free_tmpl, arg);
+                *e = new_e;
+                return;
+            }
+
+            let id = e.id;
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "is_null" => {
+                    let recv = strip_casts(&args[0]);
+                    if let Some(var) = vars.var_of(recv) {
+                        handled.insert(recv.id);
+                        let new_e = convert(var, &is_null_tmpl, &opt_is_null_tmpl, recv);
+                        *e = new_e;
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if handled.contains(&lhs.id) {
+                        return;
+                    }
+                    if let Some(var) = vars.var_of(lhs) {
+                        handled.insert(lhs.id);
+                        *rhs = convert_value(var, rhs);
+                    }
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if let Some(var) = did.map(HeapVar::Field) {
+                            if vars.pointees.contains_key(&var) {
+                                f.expr = convert_value(var, &f.expr);
+                            }
+                        }
+                    }
+                }
+
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    if let Some(var) = vars.var_of(inner) {
+                        // `*b` works the same for `Box<T>` as for `*mut T`.
+                        handled.insert(inner.id);
+                        if nullable.contains(&var) {
+                            opt_derefs.insert(id);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+        // (4) Rewrite the remaining uses, which depend on the context of the use: dereferences of
+        // `Option<Box<T>>` need `as_mut()` in mutable contexts, and any other use of the pointer
+        // value gets converted back to a raw pointer.
+
+        let deref_ref = parse_expr(cx.session(), "**__e.as_ref().unwrap()");
+        let deref_mut = parse_expr(cx.session(), "**__e.as_mut().unwrap()");
+        let to_raw = parse_expr(cx.session(), "&mut *__e as *mut __t");
+        let opt_to_raw = parse_expr(
+            cx.session(),
+            "__e.as_mut().map_or(::std::ptr::null_mut(), |b| &mut **b as *mut __t)");
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if opt_derefs.contains(&e.id) {
+                let inner = expect!([e.kind] ExprKind::Unary(UnOp::Deref, ref inner) => inner);
+                let tmpl = match ectx {
+                    lr_expr::Context::LvalueMut => &deref_mut,
+                    _ => &deref_ref,
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("__e", inner.clone());
+                *e = tmpl.clone().subst(st, cx, &bnd);
+                return;
+            }
+
+            if handled.contains(&e.id) {
+                return;
+            }
+            let var = match_or!([vars.var_of(e)] Some(x) => x; return);
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    *e = convert(var, &to_raw, &opt_to_raw, e);
+                }
+                _ => {
+                    warn!("can't convert lvalue use of {:?}: `{}`",
+                          var, pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `heap_array_to_vec` Command
+///
+/// Usage: `heap_array_to_vec`
+///
+/// Marks: `target`
+///
+/// For each local variable or struct field marked `target` that holds a raw
+/// pointer to a dynamically sized heap buffer, change its type from `*mut T` to
+/// `Vec<T>`.  Allocations of the form `malloc(n * size_of::<T>())` and
+/// `calloc(n, size_of::<T>())` become zero-filled vectors built with
+/// `Vec::with_capacity`, `realloc` calls become `resize`, and `free` calls are
+/// removed, since the vector releases its buffer when it is dropped or
+/// reassigned.
+///
+/// Element accesses through `*p.offset(i)`, `*p.add(i)` and `*p` become `p[i]`
+/// and `p[0]`, and null checks become `is_empty()` checks.  Any remaining use of
+/// the pointer value is converted back with `p.as_mut_ptr()`.
+///
+/// A pointer that is assigned anything other than an allocation or a null
+/// pointer is left unchanged, since its buffer may not be owned.
+pub struct HeapArrayToVec;
+
+impl Transform for HeapArrayToVec {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut vars = HeapVars::collect(st, cx, krate, "target");
+        if vars.is_empty() {
+            return;
+        }
+
+        // (1) Make sure every value stored into a selected location is a fresh allocation (or
+        // null), and that every method call on it is one we know how to translate.
+
+        let is_owned_value = |e: &Expr| is_null_ptr(e) || alloc_count(e).is_some();
+        let mut unsupported = HashSet::new();
+
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args) => {
+                    if let Some(var) = vars.var_of(strip_casts(&args[0])) {
+                        if !matches!([&*seg.ident.as_str()] "is_null", "offset", "add") {
+                            unsupported.insert(var);
+                        }
+                    }
+                }
+
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    if let Some(var) = vars.var_of(lhs) {
+                        if !is_owned_value(rhs) {
+                            unsupported.insert(var);
+                        }
+                    }
+                }
+
+                ExprKind::Struct(_, ref fields, _) => {
+                    for f in fields {
+                        let did = match_or!([field_def_id(cx, e.id, f.ident)] Some(x) => x; continue);
+                        let var = HeapVar::Field(did);
+                        if vars.pointees.contains_key(&var) && !is_owned_value(&f.expr) {
+                            unsupported.insert(var);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+        visit_nodes(krate, |l: &Local| {
+            let var = HeapVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if vars.pointees.contains_key(&var) &&
+               !l.init.as_ref().map_or(true, |init| is_owned_value(init)) {
+                unsupported.insert(var);
+            }
+        });
+
+        for var in &unsupported {
+            warn!("{:?} may hold a pointer it doesn't own; leaving it unchanged", var);
+            vars.pointees.remove(var);
+        }
+        if vars.is_empty() {
+            return;
+        }
+
+        let vec_alloc = parse_expr(
+            cx.session(),
+            "{ let n = __n as usize; \
+               let mut v = Vec::with_capacity(n); \
+               v.resize(n, ::std::mem::zeroed::<__t>()); \
+               v }");
+        let vec_new = parse_expr(cx.session(), "Vec::new()");
+        let subst_count = |tmpl: &P<Expr>, var: HeapVar, e: Option<&Expr>, n: P<Expr>| {
+            let mut bnd = Bindings::new();
+            if let Some(e) = e {
+                bnd.add("__e", P(e.clone()));
+            }
+            bnd.add("__n", n);
+            bnd.add("__t", vars.pointees[&var].clone());
+            tmpl.clone().subst(st, cx, &bnd)
+        };
+        let convert_value = |var: HeapVar, e: &Expr| -> P<Expr> {
+            match alloc_count(e) {
+                Some(n) => subst_count(&vec_alloc, var, None, n),
+                None => vec_new.clone(),
+            }
+        };
+
+        // (2) Change the types of the selected locals and fields, and convert their initializers.
+
+        let vec_ty = parse_ty(cx.session(), "Vec<__t>");
+        let new_ty = |var: HeapVar| -> P<ast::Ty> {
+            let mut bnd = Bindings::new();
+            bnd.add("__t", vars.pointees[&var].clone());
+            vec_ty.clone().subst(st, cx, &bnd)
+        };
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = HeapVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if !vars.pointees.contains_key(&var) {
+                return;
+            }
+            if l.ty.is_some() {
+                l.ty = Some(new_ty(var));
+            }
+            if let Some(ref mut init) = l.init {
+                *init = convert_value(var, init);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if !st.marked(sf.id, "target") {
+                        continue;
+                    }
+                    let var = HeapVar::Field(cx.node_def_id(sf.id));
+                    if vars.pointees.contains_key(&var) {
+                        sf.ty = new_ty(var);
+                    }
+                }
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                for f in fields {
+                    let did = match_or!([field_def_id(cx, id, f.ident)] Some(x) => x; continue);
+                    let var = HeapVar::Field(did);
+                    if vars.pointees.contains_key(&var) {
+                        f.expr = convert_value(var, &f.expr);
+                    }
+                }
+            }
+        });
+
+        // (3) Remove `free` calls.  The buffer is released when the `Vec` is dropped.
+
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let freed_var = match s.kind {
+                StmtKind::Semi(ref e) | StmtKind::Expr(ref e) =>
+                    freed_expr(e).and_then(|a| vars.var_of(a)),
+                _ => None,
+            };
+            if freed_var.is_some() {
+                smallvec![]
+            } else {
+                smallvec![s]
+            }
+        });
+
+        // (4) Rewrite allocations, reallocations, null checks, and element accesses.
+
+        let resize = parse_expr(cx.session(), "__e.resize(__n as usize, ::std::mem::zeroed::<__t>())");
+        let index = parse_expr(cx.session(), "__e[__n as usize]");
+        let is_empty = parse_expr(cx.session(), "__e.is_empty()");
+        let zero = mk().lit_expr(mk().int_lit(0, ""));
+
+        let mut handled: HashSet<NodeId> = HashSet::new();
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    let var = match_or!([vars.var_of(lhs)] Some(x) => x; return);
+                    if handled.contains(&lhs.id) {
+                        return;
+                    }
+                    handled.insert(lhs.id);
+                    let rhs = strip_casts(rhs);
+                    if is_call_to(rhs, "realloc") {
+                        let args = expect!([rhs.kind] ExprKind::Call(_, ref args) => args);
+                        handled.insert(strip_casts(&args[0]).id);
+                        let n = alloc_count(rhs).unwrap();
+                        subst_count(&resize, var, Some(lhs), n)
+                    } else {
+                        let mut bnd = Bindings::new();
+                        bnd.add("__e", lhs.clone());
+                        bnd.add("__v", convert_value(var, rhs));
+                        parse_expr(cx.session(), "__e = __v").subst(st, cx, &bnd)
+                    }
+                }
+
+                ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "is_null" => {
+                    let recv = strip_casts(&args[0]);
+                    let var = match_or!([vars.var_of(recv)] Some(x) => x; return);
+                    handled.insert(recv.id);
+                    subst_count(&is_empty, var, Some(recv), zero.clone())
+                }
+
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    if let Some(var) = vars.var_of(inner) {
+                        handled.insert(inner.id);
+                        subst_count(&index, var, Some(inner), zero.clone())
+                    } else if let ExprKind::MethodCall(ref seg, ref args) = inner.kind {
+                        if !matches!([&*seg.ident.as_str()] "offset", "add") {
+                            return;
+                        }
+                        let recv = strip_casts(&args[0]);
+                        let var = match_or!([vars.var_of(recv)] Some(x) => x; return);
+                        handled.insert(recv.id);
+                        subst_count(&index, var, Some(recv), P(strip_casts(&args[1]).clone()))
+                    } else {
+                        return;
+                    }
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+
+        // (5) Any other use of the pointer value gets the vector's buffer pointer instead.
+
+        let as_ptr = parse_expr(cx.session(), "__e.as_mut_ptr()");
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) {
+                return;
+            }
+            let var = match_or!([vars.var_of(e)] Some(x) => x; return);
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    *e = subst_count(&as_ptr, var, Some(e), zero.clone());
+                }
+                _ => {
+                    warn!("can't convert lvalue use of {:?}: `{}`",
+                          var, pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `simplify_sizeof` Command
+///
+/// Usage: `simplify_sizeof`
+///
+/// Clean up the `size_of::<T>()` expressions that the transpiler emits for C `sizeof`:
+///
+///  * `malloc(n * size_of::<T>()) as *mut T` becomes `malloc_array::<T>(n)`, a typed wrapper
+///    around `malloc` that is added to the crate root.  The memory it returns can still be
+///    released with `free`.
+///  * Multiplying by the size of a one-byte type is removed, and multiplying an integer literal
+///    by the size of a fixed-size type (one whose size is the same on every target) is folded
+///    into a single literal.
+///  * Dividing by `size_of::<T>()` is removed when the dividend is the size of an array of `T`
+///    (giving the array length), a multiple of `size_of::<T>()`, or the size of an array or
+///    slice of `T` computed with `size_of_val` (giving its `len()`).
+///
+/// Casts around the simplified expressions are kept, so their types don't change.
+///
+/// Example:
+///
+/// ```ignore
+///     let p = malloc((n as libc::c_ulong)
+///         .wrapping_mul(::std::mem::size_of::<Point>() as libc::c_ulong)) as *mut Point;
+///     let count = (::std::mem::size_of::<[i32; 8]>() as libc::c_ulong)
+///         .wrapping_div(::std::mem::size_of::<i32>() as libc::c_ulong);
+///     memset(buf as *mut libc::c_void, 0,
+///            (16 as libc::c_ulong).wrapping_mul(::std::mem::size_of::<u32>() as libc::c_ulong));
+/// ```
+///
+/// After running `simplify_sizeof`:
+///
+/// ```ignore
+///     let p = malloc_array::<Point>(n as usize);
+///     let count = 8 as libc::c_ulong;
+///     memset(buf as *mut libc::c_void, 0, 64 as libc::c_ulong);
+/// ```
+pub struct SimplifySizeof;
+
+/// Name of the typed allocation function added by `simplify_sizeof`.
+const MALLOC_ARRAY: &str = "malloc_array";
+
+/// Get the size of `ty` in bytes, if it's the same on every target.
+fn fixed_size_of<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, ty: ty::Ty<'tcx>) -> Option<u128> {
+    let bits = match ty.kind {
+        TyKind::Bool => 8,
+        TyKind::Char => 32,
+        TyKind::Int(ity) => ity.bit_width()?,
+        TyKind::Uint(uty) => uty.bit_width()?,
+        TyKind::Float(fty) => fty.bit_width(),
+        TyKind::Array(elem, len) => {
+            let len = len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty())?;
+            return Some(fixed_size_of(cx, elem)? * len as u128);
+        }
+        _ => return None,
+    };
+    Some(bits as u128 / 8)
+}
+
+/// If `e` is `a * b` or `a.wrapping_mul(b)` (or the `div` equivalents, if `div` is set), return
+/// `a` and `b`.
+fn as_mul_or_div(e: &Expr, div: bool) -> Option<(&P<Expr>, &P<Expr>)> {
+    let (op, method) = if div {
+        (BinOpKind::Div, "wrapping_div")
+    } else {
+        (BinOpKind::Mul, "wrapping_mul")
+    };
+    match e.kind {
+        ExprKind::Binary(bin_op, ref a, ref b) if bin_op.node == op => Some((a, b)),
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 2 && seg.ident.as_str() == method => Some((&args[0], &args[1])),
+        _ => None,
+    }
+}
+
+/// If `e` is an integer literal, possibly under casts, return its value.
+pub fn as_int_lit(e: &Expr) -> Option<u128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match_or!([l.kind] LitKind::Int(n, _) => Some(n); None),
+        _ => None,
+    }
+}
+
+/// Replace the part of `e` beneath any casts and parentheses with `new`.
+fn replace_inner(e: &mut P<Expr>, new: P<Expr>) {
+    match e.kind {
+        ExprKind::Cast(ref mut inner, _) | ExprKind::Paren(ref mut inner) =>
+            replace_inner(inner, new),
+        _ => *e = new,
+    }
+}
+
+/// Try to simplify a product involving a `size_of` call.
+fn simplify_size_mul(cx: &RefactorCtxt, e: &Expr) -> Option<P<Expr>> {
+    let (a, b) = as_mul_or_div(e, false)?;
+    let (size, other) = match (as_size_of(cx, a), as_size_of(cx, b)) {
+        (_, Some(ty)) => (fixed_size_of(cx, ty), a),
+        (Some(ty), None) => (fixed_size_of(cx, ty), b),
+        (None, None) => return None,
+    };
+    let size = size?;
+    if size == 1 {
+        return Some(other.clone());
+    }
+    let n = as_int_lit(other)?;
+    let mut new_e = other.clone();
+    replace_inner(&mut new_e, parse_expr(cx.session(), &(n * size).to_string()));
+    Some(new_e)
+}
+
+/// Try to simplify a quotient whose divisor is a `size_of` call.
+fn simplify_size_div(cx: &RefactorCtxt, e: &Expr) -> Option<P<Expr>> {
+    let (a, b) = as_mul_or_div(e, true)?;
+    let elem_ty = as_size_of(cx, b)?;
+    if fixed_size_of(cx, elem_ty) == Some(1) {
+        return Some(a.clone());
+    }
+
+    let inner = strip_casts(a);
+    let new_inner = if let Some(ty) = as_size_of(cx, inner) {
+        // `size_of::<[T; N]>() / size_of::<T>()`
+        match ty.kind {
+            TyKind::Array(elem, len) if elem == elem_ty => {
+                let len = len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty())?;
+                parse_expr(cx.session(), &len.to_string())
+            }
+            _ => return None,
+        }
+    } else if let Some((x, y)) = as_mul_or_div(inner, false) {
+        // `n * size_of::<T>() / size_of::<T>()`
+        if as_size_of(cx, y) == Some(elem_ty) {
+            x.clone()
+        } else if as_size_of(cx, x) == Some(elem_ty) {
+            y.clone()
+        } else {
+            return None;
+        }
+    } else if is_call_to(inner, "size_of_val") {
+        // `size_of_val(&a) / size_of::<T>()`, for an array or slice `a`
+        let args = expect!([inner.kind] ExprKind::Call(_, ref args) => args);
+        let arg = match args.get(0).map(|a| &strip_casts(a).kind) {
+            Some(&ExprKind::AddrOf(_, _, ref x)) => x,
+            _ => return None,
+        };
+        match cx.opt_node_type(arg.id).map(|ty| &ty.kind) {
+            Some(&TyKind::Slice(elem)) |
+            Some(&TyKind::Array(elem, _)) if elem == elem_ty => {}
+            _ => return None,
+        }
+        mk().method_call_expr(arg.clone(), "len", Vec::new())
+    } else {
+        let n = as_int_lit(inner)?;
+        let size = fixed_size_of(cx, elem_ty)?;
+        if size == 0 || n % size != 0 {
+            return None;
+        }
+        parse_expr(cx.session(), &(n / size).to_string())
+    };
+    let mut new_e = a.clone();
+    replace_inner(&mut new_e, new_inner);
+    Some(new_e)
+}
+
+impl Transform for SimplifySizeof {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Replace `malloc` calls that allocate arrays of a single type.
+
+        let name_taken = krate.module.items.iter().any(|i| i.ident.as_str() == MALLOC_ARRAY);
+        let mut malloc_def = None;
+        if !name_taken {
+            MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+                let call = match_or!([e.kind] ExprKind::Cast(ref inner, _) => strip_casts(inner);
+                                     return);
+                let args = match call.kind {
+                    ExprKind::Call(_, ref args) if args.len() == 1 && is_call_to(call, "malloc") =>
+                        args,
+                    _ => return,
+                };
+                let pointee = match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::RawPtr(mt)) => mt.ty,
+                    _ => return,
+                };
+                let size = strip_casts(&args[0]);
+                let sized = as_size_of(cx, size).or_else(|| {
+                    let (a, b) = as_mul_or_div(size, false)?;
+                    as_size_of(cx, a).or_else(|| as_size_of(cx, b))
+                });
+                if sized != Some(pointee) {
+                    return;
+                }
+                let count = match_or!([elem_count(size)] Some(x) => x; return);
+                let callee = match_or!([cx.opt_callee(call)] Some(x) => x; return);
+                malloc_def.get_or_insert(callee);
+
+                let count = match count.kind {
+                    ExprKind::Lit(_) => count,
+                    _ => match cx.opt_node_type(count.id).map(|ty| &ty.kind) {
+                        Some(&TyKind::Uint(UintTy::Usize)) => count,
+                        _ => mk().cast_expr(count, mk().ident_ty("usize")),
+                    },
+                };
+                let func = mk().path_segment_with_args(
+                    MALLOC_ARRAY,
+                    mk().angle_bracketed_args(vec![reflect_tcx_ty(cx.ty_ctxt(), pointee)]));
+                *e = mk().call_expr(mk().path_expr(vec![func]), vec![count]);
+            });
+        }
+
+        if let Some(did) = malloc_def {
+            let src = format!(
+                "/// Allocate space for `n` values of type `T` with `malloc`.\n\
+                 unsafe fn {}<T>(n: usize) -> *mut T {{\n    \
+                     {}(n.wrapping_mul(::std::mem::size_of::<T>()) as _) as *mut T\n\
+                 }}",
+                MALLOC_ARRAY, pprust::path_to_string(&cx.def_path(did)));
+            krate.module.items.extend(st.parse_items(cx, &src));
+        }
+
+        // (2) Fold and remove sizes in arithmetic.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = simplify_size_mul(cx, e).or_else(|| simplify_size_div(cx, e));
+            if let Some(new_e) = new_e {
+                *e = new_e;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("malloc_to_box", |_args| mk(MallocToBox));
+    reg.register("heap_array_to_vec", |_args| mk(HeapArrayToVec));
+    reg.register("simplify_sizeof", |_args| mk(SimplifySizeof));
+}
@@ -1,17 +1,26 @@
 use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use regex::Regex;
+use std::mem;
+use regex::{Captures, Regex};
 use rustc::hir::HirId;
+use rustc::hir::def::Namespace;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind;
 use rustc_parse::parser::FollowedByType;
 use syntax::ast::*;
+use syntax::attr;
 use syntax::source_map::DUMMY_SP;
 use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
 use syntax::symbol::Symbol;
+use syntax::token::{self, Token, TokenKind};
+use syntax::tokenstream::{TokenStream, TokenTree};
+use syntax_pos::sym;
 use smallvec::{smallvec, SmallVec};
 
 use c2rust_ast_builder::{mk, Make, IntoSymbol};
-use crate::ast_manip::{FlatMapNodes, MutVisit, AstEquiv};
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, AstEquiv, Visit, visit_nodes};
+use crate::ast_manip::util::is_export_attr;
 use crate::command::{CommandState, Registry};
 use crate::driver::{self, Phase};
 use crate::path_edit::fold_resolved_paths;
@@ -172,6 +181,292 @@ impl Transform for RenameUnnamed {
 }
 
 
+/// # `normalize_names` Command
+///
+/// Usage: `normalize_names [PREFIX] [MAP_FILE]`
+///
+/// Rename module-level items to follow Rust naming conventions: functions get
+/// `snake_case` names, types and traits get `CamelCase` names, and constants and
+/// statics get `SCREAMING_SNAKE_CASE` names.  If `PREFIX` is provided and not empty,
+/// it is first stripped from the start of every name, so that with `PREFIX` set
+/// to `mylib_`, `mylib_parseHeader` becomes `parse_header`.  All paths referring to
+/// renamed items are updated, along with whole-word mentions in doc comments and
+/// names in `#[cross_check]` attributes.
+///
+/// Exported functions and statics keep their link names: `#[no_mangle]` is
+/// replaced with `#[export_name = "..."]` giving the old name, and renamed extern
+/// declarations get a `#[link_name]` attribute.  Items whose new name is a keyword
+/// or would collide with another item in the same module are left alone, as are
+/// `main` and names starting with an underscore.
+///
+/// If `MAP_FILE` is provided, a JSON array describing each rename is written to
+/// it, with one entry per item:
+///
+/// ```ignore
+///     { "old": "foo::mylib_parseHeader", "new": "foo::parse_header" }
+/// ```
+pub struct NormalizeNames {
+    prefix: Option<String>,
+    map_file: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NameCase {
+    Snake,
+    Camel,
+    Screaming,
+}
+
+/// Split an identifier into lowercase words, at underscores and case changes.
+fn ident_words(s: &str) -> Vec<String> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut cur = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !cur.is_empty() {
+                words.push(mem::replace(&mut cur, String::new()));
+            }
+            continue;
+        }
+        // Break before an uppercase letter that follows a lowercase letter or
+        // digit, or that starts a word after an acronym, as in `HTTPServer`.
+        let boundary = c.is_uppercase() && !cur.is_empty() &&
+            (!chars[i - 1].is_uppercase() ||
+             chars.get(i + 1).map_or(false, |n| n.is_lowercase()));
+        if boundary {
+            words.push(mem::replace(&mut cur, String::new()));
+        }
+        cur.extend(c.to_lowercase());
+    }
+    if !cur.is_empty() {
+        words.push(cur);
+    }
+    words
+}
+
+fn convert_case(s: &str, case: NameCase) -> String {
+    let words = ident_words(s);
+    match case {
+        NameCase::Snake => words.join("_"),
+        NameCase::Screaming => words.join("_").to_uppercase(),
+        NameCase::Camel => words.iter().map(|w| {
+            let mut cs = w.chars();
+            match cs.next() {
+                Some(c) => c.to_uppercase().chain(cs).collect(),
+                None => String::new(),
+            }
+        }).collect(),
+    }
+}
+
+/// Strip `prefix` from the start of `name`, ignoring case, along with any underscores that
+/// follow it.
+fn strip_name_prefix<'a>(name: &'a str, prefix: &str) -> &'a str {
+    if prefix.is_empty() || name.len() <= prefix.len() ||
+       !name.is_char_boundary(prefix.len()) ||
+       !name[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        return name;
+    }
+    let rest = name[prefix.len()..].trim_start_matches('_');
+    if rest.is_empty() || rest.starts_with(|c: char| c.is_ascii_digit()) {
+        name
+    } else {
+        rest
+    }
+}
+
+fn attr_str(name: Symbol, value: Symbol) -> Attribute {
+    attr::mk_attr_outer(attr::mk_name_value_item_str(Ident::new(name, DUMMY_SP), value, DUMMY_SP))
+}
+
+/// Replace renamed identifiers and string literals naming renamed items in a token stream.
+fn rename_tokens(ts: TokenStream, renames: &HashMap<Symbol, Symbol>) -> TokenStream {
+    ts.trees().map(|tt| match tt {
+        TokenTree::Token(Token { kind: TokenKind::Ident(name, is_raw), span }) => {
+            let name = renames.get(&name).cloned().unwrap_or(name);
+            TokenTree::Token(Token { kind: TokenKind::Ident(name, is_raw), span })
+        }
+        TokenTree::Token(Token { kind: TokenKind::Literal(mut lit), span }) => {
+            if lit.kind == token::LitKind::Str {
+                lit.symbol = renames.get(&lit.symbol).cloned().unwrap_or(lit.symbol);
+            }
+            TokenTree::Token(Token { kind: TokenKind::Literal(lit), span })
+        }
+        TokenTree::Delimited(span, delim, tts) =>
+            TokenTree::Delimited(span, delim, rename_tokens(tts, renames)),
+        tt => tt,
+    }).collect()
+}
+
+impl Transform for NormalizeNames {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        let prefix = self.prefix.as_ref().map_or("", |s| s as &str);
+
+        // (1) Collect the names in each module and namespace, and the new name each item should
+        // get.
+
+        struct Candidate {
+            hir_id: HirId,
+            module: HirId,
+            ns: Namespace,
+            old: Symbol,
+            new: Symbol,
+        }
+        let mut names: HashSet<(HirId, Namespace, Symbol)> = HashSet::new();
+        let mut candidates = vec![];
+        let mut add_candidate = |id: NodeId, ident: Ident, ns: Namespace, case: Option<NameCase>| {
+            let hir_id = cx.hir_map().node_to_hir_id(id);
+            let module = cx.hir_map().get_module_parent_node(hir_id);
+            names.insert((module, ns, ident.name));
+
+            let case = match_or!([case] Some(x) => x; return);
+            let old = ident.as_str();
+            if old.starts_with('_') || &*old == "main" {
+                return;
+            }
+            let new = convert_case(strip_name_prefix(&old, prefix), case);
+            if new.is_empty() || new == &*old || Ident::from_str(&new).is_reserved() {
+                return;
+            }
+            let new = Symbol::intern(&new);
+            candidates.push(Candidate { hir_id, module, ns, old: ident.name, new });
+        };
+        visit_nodes(krate, |i: &Item| {
+            let (ns, case) = match i.kind {
+                ItemKind::Fn(..) => (Namespace::ValueNS, Some(NameCase::Snake)),
+                ItemKind::Static(..) | ItemKind::Const(..) =>
+                    (Namespace::ValueNS, Some(NameCase::Screaming)),
+                ItemKind::Struct(..) | ItemKind::Enum(..) | ItemKind::Union(..) |
+                ItemKind::TyAlias(..) | ItemKind::Trait(..) =>
+                    (Namespace::TypeNS, Some(NameCase::Camel)),
+                ItemKind::Mod(..) | ItemKind::ExternCrate(..) => (Namespace::TypeNS, None),
+                _ => return,
+            };
+            add_candidate(i.id, i.ident, ns, case);
+        });
+        visit_nodes(krate, |i: &ForeignItem| {
+            let (ns, case) = match i.kind {
+                ForeignItemKind::Fn(..) => (Namespace::ValueNS, NameCase::Snake),
+                ForeignItemKind::Static(..) => (Namespace::ValueNS, NameCase::Screaming),
+                ForeignItemKind::Ty => (Namespace::TypeNS, NameCase::Camel),
+                ForeignItemKind::Macro(..) => return,
+            };
+            add_candidate(i.id, i.ident, ns, Some(case));
+        });
+
+        // (2) Pick the renames that don't collide with existing names.
+
+        let mut new_idents = HashMap::new();
+        let mut renames = HashMap::new();
+        for c in candidates {
+            if !names.insert((c.module, c.ns, c.new)) {
+                warn!("not renaming `{}`: `{}` is already defined", c.old, c.new);
+                continue;
+            }
+            new_idents.insert(c.hir_id, Ident::new(c.new, DUMMY_SP));
+            renames.insert(c.old, c.new);
+        }
+
+        if let Some(ref path) = self.map_file {
+            let mut entries = new_idents.iter().map(|(&hir_id, new)| {
+                let def_id = cx.hir_map().local_def_id(hir_id);
+                let old_path = cx.ty_ctxt().def_path_str(def_id);
+                let new_path = match old_path.rfind("::") {
+                    Some(pos) => format!("{}::{}", &old_path[..pos], new),
+                    None => new.to_string(),
+                };
+                (old_path, new_path)
+            }).collect::<Vec<_>>();
+            entries.sort();
+            let json = json::JsonValue::Array(entries.into_iter().map(|(old, new)| {
+                json::object! { "old" => old, "new" => new }
+            }).collect());
+            if let Err(e) = ::std::fs::write(path, json::stringify_pretty(json, 2)) {
+                warn!("failed to write rename map to {}: {}", path, e);
+            }
+        }
+
+        // (3) Rename the items, preserving the symbol names of exported and extern items.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let new_ident = match new_idents.get(&cx.hir_map().node_to_hir_id(i.id)) {
+                Some(&x) => x,
+                None => return smallvec![i],
+            };
+            smallvec![i.map(|mut i| {
+                if attr::contains_name(&i.attrs, sym::no_mangle) {
+                    i.attrs.retain(|attr| !attr.check_name(sym::no_mangle));
+                    i.attrs.push(attr_str(sym::export_name, i.ident.name));
+                }
+                i.ident = new_ident;
+                i
+            })]
+        });
+        FlatMapNodes::visit(krate, |mut i: ForeignItem| {
+            if let Some(&new_ident) = new_idents.get(&cx.hir_map().node_to_hir_id(i.id)) {
+                let has_link_name = attr::contains_name(&i.attrs, sym::link_name);
+                if !matches!([i.kind] ForeignItemKind::Ty) && !has_link_name {
+                    i.attrs.push(attr_str(sym::link_name, i.ident.name));
+                }
+                i.ident = new_ident;
+            }
+            smallvec![i]
+        });
+
+        // (4) Rewrite paths referring to renamed items
+
+        fold_resolved_paths(krate, cx, |qself, mut path, def| {
+            if let Some(hir_id) = cx.res_to_hir_id(&def[0]) {
+                if let Some(new_ident) = new_idents.get(&hir_id) {
+                    path.segments.last_mut().unwrap().ident = *new_ident;
+                }
+            }
+            (qself, path)
+        });
+
+        // (5) Update doc comments and cross-check attributes
+
+        struct AttrRenamer {
+            renames: HashMap<Symbol, Symbol>,
+            word_re: Regex,
+        }
+
+        impl MutVisitor for AttrRenamer {
+            fn visit_attribute(&mut self, attr: &mut Attribute) {
+                match attr.kind {
+                    AttrKind::DocComment(ref mut text) => {
+                        let renames = &self.renames;
+                        let new_text = self.word_re.replace_all(&text.as_str(), |caps: &Captures| {
+                            let word = Symbol::intern(&caps[0]);
+                            renames.get(&word).map_or(word, |&s| s).to_string()
+                        }).into_owned();
+                        *text = Symbol::intern(&new_text);
+                    }
+                    AttrKind::Normal(ref mut item) if item.path == "cross_check".into_symbol() => {
+                        if let MacArgs::Delimited(_, _, ref mut tokens) = item.args {
+                            *tokens = rename_tokens(tokens.clone(), &self.renames);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if !renames.is_empty() {
+            krate.visit(&mut AttrRenamer {
+                renames,
+                word_re: Regex::new(r"\b[A-Za-z_][A-Za-z0-9_]*\b").unwrap(),
+            });
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 /// # `replace_items` Command
 ///
 /// Usage: `replace_items`
@@ -611,6 +906,224 @@ impl Transform for DeleteItems {
 }
 
 
+/// # `remove_dead_items` Command
+///
+/// Usage: `remove_dead_items [ROOTS]`
+///
+/// Marks: reads `target` if `ROOTS` includes `marked`
+///
+/// Delete module-level functions, statics, constants, types, traits, and extern declarations
+/// that can't be reached from any root.  `ROOTS` is a comma-separated list of the kinds of items
+/// to treat as roots, chosen from `exported` (items with `#[no_mangle]` or `#[export_name]`),
+/// `pub` (public items and the targets of public `use`s), and `marked` (items marked
+/// `target`).  It defaults to `exported`.  Functions named `main` and items with `#[used]` or
+/// `#[link_section]` are always roots.
+///
+/// An item is reachable if a reachable item refers to it by path, method call, or the type of
+/// an expression.  Impls are kept as long as their self type is, and `use`s of deleted items
+/// are deleted along with them.
+pub struct RemoveDeadItems {
+    roots: Vec<String>,
+}
+
+/// Visit each item in `items` and its submodules, but not items nested inside other items.
+fn visit_mod_items<'a>(items: &'a [P<Item>], f: &mut dyn FnMut(&'a Item)) {
+    for i in items {
+        f(i);
+        if let ItemKind::Mod(ref m) = i.kind {
+            visit_mod_items(&m.items, f);
+        }
+    }
+}
+
+/// Collect the defs referenced anywhere inside `x`.
+fn referenced_defs<T: Visit>(cx: &RefactorCtxt, x: &T) -> HashSet<DefId> {
+    let mut refs = HashSet::new();
+    visit_nodes(x, |e: &Expr| {
+        refs.extend(cx.try_resolve_expr(e));
+        if let ExprKind::MethodCall(..) = e.kind {
+            refs.extend(cx.opt_callee(e));
+        }
+        if let Some(ty) = cx.opt_node_type(e.id) {
+            if let TyKind::Adt(def, _) = ty.kind {
+                refs.insert(def.did);
+            }
+        }
+    });
+    visit_nodes(x, |t: &Ty| refs.extend(cx.try_resolve_ty(t)));
+    visit_nodes(x, |p: &Pat| refs.extend(cx.try_resolve_pat_hir(p).and_then(|r| r.opt_def_id())));
+    refs
+}
+
+impl Transform for RemoveDeadItems {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let tcx = cx.ty_ctxt();
+        let (mut root_exported, mut root_pub, mut root_marked) = (false, false, false);
+        for kind in &self.roots {
+            match kind as &str {
+                "exported" => root_exported = true,
+                "pub" => root_pub = true,
+                "marked" => root_marked = true,
+                _ => panic!("unknown root kind `{}`", kind),
+            }
+        }
+        let target = "target".into_symbol();
+
+        // (1) Collect the removable items, the roots among them, and the owner of each impl,
+        // which is the item its self type is defined by.
+
+        let mut defs = HashSet::new();
+        let mut roots = vec![];
+        let mut impls = vec![];
+        visit_mod_items(&krate.module.items, &mut |i| {
+            match i.kind {
+                ItemKind::Fn(..) | ItemKind::Static(..) | ItemKind::Const(..) |
+                ItemKind::Struct(..) | ItemKind::Enum(..) | ItemKind::Union(..) |
+                ItemKind::TyAlias(..) | ItemKind::Trait(..) => {
+                    let did = cx.node_def_id(i.id);
+                    defs.insert(did);
+                    let is_root =
+                        (i.ident.name == sym::main && matches!([i.kind] ItemKind::Fn(..))) ||
+                        attr::contains_name(&i.attrs, sym::used) ||
+                        attr::contains_name(&i.attrs, sym::link_section) ||
+                        (root_exported && i.attrs.iter().any(is_export_attr)) ||
+                        (root_pub && i.vis.node.is_pub()) ||
+                        (root_marked && st.marked(i.id, target));
+                    if is_root {
+                        roots.push(did);
+                    }
+                }
+                ItemKind::ForeignMod(ref fm) => {
+                    for fi in &fm.items {
+                        let did = cx.node_def_id(fi.id);
+                        defs.insert(did);
+                        if root_marked && st.marked(fi.id, target) {
+                            roots.push(did);
+                        }
+                    }
+                }
+                ItemKind::Use(..) if root_pub && i.vis.node.is_pub() => {
+                    roots.extend(cx.try_resolve_use_id(i.id).and_then(|p| p.res.opt_def_id()));
+                }
+                ItemKind::Impl(..) => impls.push(i),
+                _ => {}
+            }
+        });
+
+        let mut impl_owners = HashMap::new();
+        for i in &impls {
+            let did = cx.node_def_id(i.id);
+            let owner = match tcx.type_of(did).kind {
+                TyKind::Adt(def, _) if defs.contains(&def.did) => Some(def.did),
+                _ => None,
+            };
+            impl_owners.insert(did, owner);
+        }
+
+        // Map a def to the removable item containing it, if any.
+        let owner_of = |mut did: DefId| -> Option<DefId> {
+            loop {
+                if defs.contains(&did) {
+                    return Some(did);
+                }
+                if let Some(&owner) = impl_owners.get(&did) {
+                    return owner;
+                }
+                did = tcx.parent(did)?;
+            }
+        };
+
+        // (2) Build the reference graph.  Impls belong to their self type, or are roots if it
+        // isn't removable.
+
+        let mut edges: HashMap<DefId, HashSet<DefId>> = HashMap::new();
+        visit_mod_items(&krate.module.items, &mut |i| {
+            let (from, mut refs) = match i.kind {
+                ItemKind::Impl(..) => {
+                    let did = cx.node_def_id(i.id);
+                    let mut refs = referenced_defs(cx, i);
+                    refs.extend(tcx.impl_trait_ref(did).map(|tr| tr.def_id));
+                    (impl_owners[&did], refs)
+                }
+                ItemKind::ForeignMod(ref fm) => {
+                    for fi in &fm.items {
+                        let refs = referenced_defs(cx, fi);
+                        edges.entry(cx.node_def_id(fi.id)).or_default().extend(refs);
+                    }
+                    return;
+                }
+                _ => {
+                    let did = cx.node_def_id(i.id);
+                    if !defs.contains(&did) {
+                        return;
+                    }
+                    (Some(did), referenced_defs(cx, i))
+                }
+            };
+            if let Some(from) = from {
+                edges.entry(from).or_default().extend(refs);
+            } else {
+                roots.extend(refs.drain());
+            }
+        });
+
+        // (3) Find the reachable items.
+
+        let mut live = HashSet::new();
+        let mut queue = roots.into_iter().filter_map(|did| owner_of(did)).collect::<Vec<_>>();
+        while let Some(did) = queue.pop() {
+            if !live.insert(did) {
+                continue;
+            }
+            if let Some(refs) = edges.get(&did) {
+                queue.extend(refs.iter().filter_map(|&did| owner_of(did)));
+            }
+        }
+        let is_dead = |did: DefId| owner_of(did).map_or(false, |owner| !live.contains(&owner));
+
+        // (4) Delete the unreachable items, along with their impls and `use`s.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            let did = match cx.hir_map().opt_local_def_id_from_node_id(i.id) {
+                Some(x) => x,
+                None => return smallvec![i],
+            };
+            let dead = match i.kind {
+                ItemKind::Impl(..) => impl_owners.get(&did).map_or(false, |owner| {
+                    owner.map_or(false, |owner| !live.contains(&owner))
+                }),
+                ItemKind::Use(..) => cx.try_resolve_use_id(i.id)
+                    .and_then(|p| p.res.opt_def_id())
+                    .map_or(false, |did| is_dead(did)),
+                _ => defs.contains(&did) && !live.contains(&did),
+            };
+            if dead {
+                smallvec![]
+            } else {
+                smallvec![i]
+            }
+        });
+        MutVisitNodes::visit(krate, |m: &mut Mod| {
+            m.items.retain(|i| match i.kind {
+                ItemKind::ForeignMod(ref fm) => fm.items.is_empty() || fm.items.iter().any(|fi| {
+                    live.contains(&cx.node_def_id(fi.id))
+                }),
+                _ => true,
+            });
+            for i in &mut m.items {
+                if let ItemKind::ForeignMod(ref mut fm) = i.kind {
+                    fm.items.retain(|fi| live.contains(&cx.node_def_id(fi.id)));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
@@ -622,6 +1135,11 @@ pub fn register_commands(reg: &mut Registry) {
 
     reg.register("rename_unnamed", |_args| mk(RenameUnnamed));
 
+    reg.register("normalize_names", |args| mk(NormalizeNames {
+        prefix: args.get(0).cloned(),
+        map_file: args.get(1).cloned(),
+    }));
+
     reg.register("replace_items", |_args| mk(ReplaceItems));
 
     reg.register("set_visibility", |args| mk(SetVisibility {
@@ -644,5 +1162,12 @@ pub fn register_commands(reg: &mut Registry) {
     }));
 
     reg.register("delete_items", |_args| mk(DeleteItems));
+
+    reg.register("remove_dead_items", |args| mk(RemoveDeadItems {
+        roots: args.get(0).map_or_else(
+            || vec!["exported".to_owned()],
+            |s| s.split(',').map(|s| s.trim().to_owned()).collect(),
+        ),
+    }));
 }
 
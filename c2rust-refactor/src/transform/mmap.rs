@@ -0,0 +1,402 @@
+//! Conversion of `mmap` mappings to `memmap2` maps and `Vec`s.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::ty::TyKind as TcxTyKind;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, strip_casts};
+use crate::transform::slices::{as_offset_deref, cast_str, usize_str};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+const PROT_READ: i128 = 0x1;
+const PROT_WRITE: i128 = 0x2;
+const MAP_SHARED: i128 = 0x1;
+const MAP_PRIVATE: i128 = 0x2;
+/// `MAP_ANONYMOUS` on Linux, and `MAP_ANON` on macOS and the BSDs.
+const MAP_ANONYMOUS: &[i128] = &[0x20, 0x1000];
+
+/// What replaces a mapping.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MapKind {
+    /// A read-only file mapping, which becomes an `Option<memmap2::Mmap>`.
+    ReadOnly,
+    /// A writable `MAP_SHARED` file mapping, which becomes an `Option<memmap2::MmapMut>`.
+    Shared,
+    /// A writable `MAP_PRIVATE` file mapping, which becomes a copy-on-write
+    /// `Option<memmap2::MmapMut>`.
+    CopyOnWrite,
+    /// A private anonymous mapping, which becomes a zeroed `Vec<u8>`.
+    Anonymous,
+}
+
+impl MapKind {
+    fn ty_str(self) -> &'static str {
+        match self {
+            MapKind::ReadOnly => "Option<::memmap2::Mmap>",
+            MapKind::Shared | MapKind::CopyOnWrite => "Option<::memmap2::MmapMut>",
+            MapKind::Anonymous => "Vec<u8>",
+        }
+    }
+
+    /// Build an expression for the bytes of the mapping `var`, which can be indexed.
+    fn bytes_str(self, var: &str) -> String {
+        match self {
+            MapKind::ReadOnly => format!("{}.as_ref().unwrap()", var),
+            MapKind::Shared | MapKind::CopyOnWrite => format!("{}.as_mut().unwrap()", var),
+            MapKind::Anonymous => var.to_owned(),
+        }
+    }
+}
+
+/// A mapping to convert.
+#[derive(Clone, Copy, Debug)]
+struct MapVar {
+    kind: MapKind,
+    /// Whether the pointer is a `*mut c_char`, whose elements are `i8` rather than `u8`.
+    signed: bool,
+}
+
+/// Evaluate a constant integer expression made of literals and `|`, like the transpiled form of
+/// `PROT_READ | PROT_WRITE`.
+fn const_int(e: &Expr) -> Option<i128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(i, _) => Some(i as i128),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref e) => const_int(e).map(|i| -i),
+        ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::BitOr =>
+            Some(const_int(a)? | const_int(b)?),
+        _ => None,
+    }
+}
+
+/// If `e` is a call to `mmap` that we can replace, return the kind of mapping, along with its
+/// length, file descriptor, and offset.
+fn as_mmap(e: &Expr) -> Option<(MapKind, &Expr, &Expr, &Expr)> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "mmap") || args.len() != 6 || const_int(&args[0]) != Some(0) {
+        return None;
+    }
+    let prot = const_int(&args[2])?;
+    let flags = const_int(&args[3])?;
+    let anonymous = MAP_ANONYMOUS.iter().find(|&&f| flags & f != 0);
+    let kind = match (anonymous, flags & !anonymous.cloned().unwrap_or(0)) {
+        (Some(_), MAP_PRIVATE) if const_int(&args[4]) == Some(-1) => MapKind::Anonymous,
+        (None, MAP_SHARED) if prot == PROT_READ | PROT_WRITE => MapKind::Shared,
+        (None, MAP_PRIVATE) if prot == PROT_READ | PROT_WRITE => MapKind::CopyOnWrite,
+        (None, MAP_SHARED) | (None, MAP_PRIVATE) if prot == PROT_READ => MapKind::ReadOnly,
+        _ => return None,
+    };
+    Some((kind, &args[1], &args[4], &args[5]))
+}
+
+/// If `e` is `p == MAP_FAILED` or `p != MAP_FAILED`, get `p` and whether the comparison is `==`.
+fn as_failed_check(e: &Expr) -> Option<(&Expr, bool)> {
+    let (op, a, b) = match_or!([e.kind] ExprKind::Binary(op, ref a, ref b) => (op.node, a, b);
+                               return None);
+    let eq = match op {
+        BinOpKind::Eq => true,
+        BinOpKind::Ne => false,
+        _ => return None,
+    };
+    if const_int(b) != Some(-1) {
+        return None;
+    }
+    Some((strip_casts(a), eq))
+}
+
+/// If `e` is an access to an element of a pointer, `*p` or `*p.offset(i)`, get `p` and `i`.
+fn as_elem_access(e: &Expr) -> Option<(&Expr, Option<&Expr>)> {
+    if let Some((p, i)) = as_offset_deref(e) {
+        return Some((p, Some(i)));
+    }
+    match e.kind {
+        ExprKind::Unary(UnOp::Deref, ref p) => Some((p, None)),
+        _ => None,
+    }
+}
+
+
+/// # `mmap_to_memmap` Command
+///
+/// Usage: `mmap_to_memmap`
+///
+/// Marks: `target`
+///
+/// Convert each marked local of type `*mut u8` or `*mut c_char` that is
+/// initialized with a call to `mmap` into a safe owner of the mapping:
+///
+///  * A file mapping becomes an `Option<memmap2::Mmap>` if it is read-only,
+///    or an `Option<memmap2::MmapMut>` if it is writable, mapped with
+///    `MmapOptions::map`, `map_mut`, or `map_copy` for `MAP_PRIVATE`, and
+///    `None` if mapping fails.
+///
+///  * A private anonymous mapping becomes a zeroed `Vec<u8>` of the same
+///    length.
+///
+/// Element accesses `*p.offset(i)` and `*p` become indexing into the mapping,
+/// which is bounds-checked, and `*mut c_char` elements are cast to and from
+/// `u8`.  Comparisons with `MAP_FAILED` check whether the mapping is `None`,
+/// and `munmap(p, len);` drops the mapping.  Mappings with a fixed address,
+/// other flags, or other uses of the pointer are left unchanged, and a warning
+/// is printed.  The converted crate needs a dependency on `memmap2`.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut data: *mut u8 = mmap(0 as *mut libc::c_void, len, 0x1 as libc::c_int,
+///                                  0x2 as libc::c_int, fd, 0 as libc::c_int as off_t)
+///         as *mut u8;
+///     if data as *mut libc::c_void == -(1 as libc::c_int) as *mut libc::c_void {
+///         return -1;
+///     }
+///     let first: u8 = *data.offset(0 as libc::c_int as isize);
+///     munmap(data as *mut libc::c_void, len);
+/// ```
+///
+/// After running `mmap_to_memmap`:
+///
+/// ```ignore
+///     let mut data: Option<::memmap2::Mmap> = ::memmap2::MmapOptions::new()
+///         .len(len as usize)
+///         .map(fd)
+///         .ok();
+///     if data.is_none() {
+///         return -1;
+///     }
+///     let first: u8 = data.as_ref().unwrap()[0 as usize];
+///     data = None;
+/// ```
+pub struct MmapToMemmap;
+
+impl Transform for MmapToMemmap {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let var_of = |e: &Expr| -> Option<HirId> {
+            let e = strip_casts(e);
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            cx.try_resolve_expr_to_hid(e)
+        };
+
+        // (1) Collect the marked locals that are initialized with `mmap`.
+
+        let mut vars: HashMap<HirId, MapVar> = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            let kind = l.init.as_ref().and_then(|init| as_mmap(init)).map(|(kind, ..)| kind);
+            let signed = cx.opt_node_type(l.pat.id).and_then(|ty| match ty.kind {
+                TcxTyKind::RawPtr(tm) => match tm.ty.kind {
+                    TcxTyKind::Uint(UintTy::U8) => Some(false),
+                    TcxTyKind::Int(IntTy::I8) => Some(true),
+                    _ => None,
+                },
+                _ => None,
+            });
+            match (kind, signed) {
+                (Some(kind), Some(signed)) => {
+                    let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+                    vars.insert(hid, MapVar { kind, signed });
+                }
+                _ => warn!("local `{}` is not a byte pointer initialized with a supported \
+                            `mmap` call; skipping it", pprust::pat_to_string(&l.pat)),
+            }
+        });
+
+        // (2) Check that every use of each mapping is one we can convert.
+
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                stmt_exprs.insert(e.id);
+            }
+        });
+        let mut ok_uses = HashSet::new();
+        let mut uses = Vec::new();
+        let mut assign_lhs = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let ExprKind::Path(..) = e.kind {
+                if let Some(var) = var_of(e).filter(|v| vars.contains_key(v)) {
+                    uses.push((e.id, var));
+                }
+            }
+            match e.kind {
+                ExprKind::Assign(ref lhs, _) => { assign_lhs.insert(lhs.id); }
+                // `Vec<u8>` and the maps always hold `u8`s.
+                ExprKind::AssignOp(_, ref lhs, _) => if let Some((p, _)) = as_elem_access(lhs) {
+                    if var_of(p).and_then(|v| vars.get(&v)).map_or(false, |v| !v.signed) {
+                        assign_lhs.insert(lhs.id);
+                    }
+                },
+                _ => {}
+            }
+
+            if let Some((p, i)) = as_elem_access(e) {
+                let mut nested = false;
+                if let Some(i) = i {
+                    visit_nodes(i, |e: &Expr| {
+                        nested |= var_of(e).map_or(false, |v| vars.contains_key(&v));
+                    });
+                }
+                if !nested {
+                    ok_uses.insert(strip_casts(p).id);
+                }
+            } else if let Some((p, _)) = as_failed_check(e) {
+                ok_uses.insert(p.id);
+            } else if is_call_to(e, "munmap") && stmt_exprs.contains(&e.id) {
+                let args = expect!([e.kind] ExprKind::Call(_, ref args) => args);
+                if let Some(p) = args.get(0) {
+                    ok_uses.insert(strip_casts(p).id);
+                }
+            }
+        });
+        // Element accesses whose address is taken, or that are updated in place with a signed
+        // element type, can't be converted.
+        visit_nodes(krate, |e: &Expr| {
+            let inner = match e.kind {
+                ExprKind::AddrOf(_, _, ref inner) => inner,
+                ExprKind::AssignOp(_, ref lhs, _) if !assign_lhs.contains(&lhs.id) => lhs,
+                _ => return,
+            };
+            if let Some((p, _)) = as_elem_access(inner) {
+                ok_uses.remove(&strip_casts(p).id);
+            }
+        });
+        for (id, var) in uses {
+            if !ok_uses.contains(&id) && vars.remove(&var).is_some() {
+                warn!("{:?} has uses that can't be converted; skipping it", var);
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+        let map_var = |e: &Expr| var_of(e).and_then(|v| vars.get(&v)).cloned();
+
+        // (3) Change the types and initializers of the mappings.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = match vars.get(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                Some(&var) => var,
+                None => return,
+            };
+            let src = {
+                let (_, len, fd, offset) = as_mmap(l.init.as_ref().unwrap()).unwrap();
+                let len = cast_str(len, "usize");
+                let method = match var.kind {
+                    MapKind::ReadOnly => "map",
+                    MapKind::Shared => "map_mut",
+                    MapKind::CopyOnWrite => "map_copy",
+                    MapKind::Anonymous => "",
+                };
+                let offset = match const_int(offset) {
+                    Some(0) => String::new(),
+                    _ => format!(".offset({})", cast_str(offset, "u64")),
+                };
+                match var.kind {
+                    MapKind::Anonymous => format!("vec![0u8; {}]", len),
+                    _ => format!("::memmap2::MmapOptions::new(){}.len({}).{}({}).ok()",
+                                 offset, len, method, pprust::expr_to_string(fd)),
+                }
+            };
+            l.ty = Some(parse_ty(cx.session(), var.kind.ty_str()));
+            l.init = Some(parse_expr(cx.session(), &src));
+        });
+
+        // (4) Rewrite the `munmap` statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; continue);
+                    if !is_call_to(e, "munmap") || args.is_empty() {
+                        continue;
+                    }
+                    let var = match_or!([map_var(&args[0])] Some(x) => x; continue);
+                    let empty = match var.kind {
+                        MapKind::Anonymous => "Vec::new()",
+                        _ => "None",
+                    };
+                    format!("{} = {};", pprust::expr_to_string(strip_casts(&args[0])), empty)
+                };
+                *s = parse_stmts(cx.session(), &src).lone();
+            }
+        });
+
+        // (5) Rewrite element accesses and `MAP_FAILED` checks.  Children are rewritten first,
+        // so assignments to elements are handled when visiting the assignment, before the
+        // element access on its left side is treated as a read.
+
+        let elem_str = |p: &Expr, i: Option<&Expr>| -> Option<(MapVar, String)> {
+            let var = map_var(p)?;
+            let idx = i.map_or_else(|| "0".to_owned(), |i| usize_str(cx, i));
+            let bytes = var.kind.bytes_str(&pprust::expr_to_string(strip_casts(p)));
+            Some((var, format!("{}[{}]", bytes, idx)))
+        };
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if assign_lhs.contains(&lhs.id) => {
+                    let (p, i) = match_or!([as_elem_access(lhs)] Some(x) => x; return);
+                    let (var, elem) = match_or!([elem_str(p, i)] Some(x) => x; return);
+                    let rhs = if var.signed {
+                        cast_str(rhs, "u8")
+                    } else {
+                        pprust::expr_to_string(rhs)
+                    };
+                    format!("{} = {}", elem, rhs)
+                }
+                ExprKind::AssignOp(op, ref lhs, ref rhs) if assign_lhs.contains(&lhs.id) => {
+                    let (p, i) = match_or!([as_elem_access(lhs)] Some(x) => x; return);
+                    let (_, elem) = match_or!([elem_str(p, i)] Some(x) => x; return);
+                    format!("{} {}= {}", elem, op.node.to_string(), pprust::expr_to_string(rhs))
+                }
+                _ => if let Some((p, eq)) = as_failed_check(e) {
+                    let var = match_or!([map_var(p)] Some(x) => x; return);
+                    let p = pprust::expr_to_string(p);
+                    match (var.kind, eq) {
+                        (MapKind::Anonymous, eq) => (!eq).to_string(),
+                        (_, true) => format!("{}.is_none()", p),
+                        (_, false) => format!("{}.is_some()", p),
+                    }
+                } else if let Some((p, i)) = as_elem_access(e) {
+                    if assign_lhs.contains(&e.id) {
+                        return;
+                    }
+                    let (var, elem) = match_or!([elem_str(p, i)] Some(x) => x; return);
+                    if var.signed {
+                        format!("({} as i8)", elem)
+                    } else {
+                        elem
+                    }
+                } else {
+                    return;
+                },
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("mmap_to_memmap", |_args| mk(MmapToMemmap));
+}
@@ -49,25 +49,43 @@ macro_rules! transform_modules {
 }
 
 transform_modules! {
+    arith,
+    bools,
     canonicalize_refs,
     casts,
     char_literals,
     control_flow,
+    dynload,
+    errno,
     externs,
+    files,
     format,
     funcs,
     generics,
+    getopt,
+    heap,
     ionize,
     items,
     lifetime_analysis,
     linkage,
     literals,
+    mmap,
+    nullable,
     reorganize_definitions,
     ownership,
+    ptr_to_ref,
+    random,
+    results,
     retype,
     rewrite,
+    signals,
+    slices,
+    sockets,
     statics,
+    strings,
     structs,
+    traits,
     test,
+    time,
     vars,
 }
@@ -0,0 +1,492 @@
+//! Conversion of nullable raw pointers to `Option`s of references.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, Visit, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns, FnKind};
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::RefactorCtxt;
+
+
+/// A nullable pointer variable selected for conversion.
+struct NullableVar {
+    ident: Ident,
+    mutbl: Mutability,
+    /// The original pointee type.
+    pointee: P<Ty>,
+}
+
+/// If `e` checks whether a pointer is null, return the pointer, along with `true` if the check
+/// succeeds when the pointer is null and `false` if it succeeds when the pointer is non-null.
+fn as_null_check(e: &Expr) -> Option<(bool, &Expr)> {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && seg.ident.as_str() == "is_null" =>
+            Some((true, strip_casts(&args[0]))),
+        ExprKind::Unary(UnOp::Not, ref inner) =>
+            as_null_check(inner).map(|(is_null, p)| (!is_null, p)),
+        ExprKind::Binary(op, ref a, ref b) if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) => {
+            let is_null = op.node == BinOpKind::Eq;
+            if is_null_ptr(b) {
+                Some((is_null, strip_casts(a)))
+            } else if is_null_ptr(a) {
+                Some((is_null, strip_casts(b)))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Check if `target` contains an assignment to a variable accepted by `is_var`.
+fn assigns_to<T: Visit, F: Fn(&Expr) -> bool>(target: &T, is_var: F) -> bool {
+    let mut found = false;
+    visit_nodes(target, |e: &Expr| {
+        if let ExprKind::Assign(ref lhs, _) = e.kind {
+            found |= is_var(lhs);
+        }
+    });
+    found
+}
+
+/// Collect the IDs of the path expressions in `target` that refer to the variable `hid`.
+fn collect_uses<T: Visit>(cx: &RefactorCtxt, target: &T, hid: HirId, dest: &mut HashSet<NodeId>) {
+    visit_nodes(target, |e: &Expr| {
+        if matches!([e.kind] ExprKind::Path(..)) && cx.try_resolve_expr_to_hid(e) == Some(hid) {
+            dest.insert(e.id);
+        }
+    });
+}
+
+/// Check if control never reaches the end of `b`.
+fn diverges(b: &Block) -> bool {
+    let last = match_or!([b.stmts.last()] Some(x) => x; return false);
+    let e = match_or!([last.kind] StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e;
+                      return false);
+    match e.kind {
+        ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) => true,
+        _ => is_call_to(e, "abort") || is_call_to(e, "exit"),
+    }
+}
+
+
+/// # `wrap_nullable_in_option` Command
+///
+/// Usage: `wrap_nullable_in_option`
+///
+/// Find raw pointer locals and function arguments that are compared against
+/// null or assigned a null pointer, and change their type from `*mut T` (or
+/// `*const T`) to `Option<&mut T>` (or `Option<&T>`).
+///
+/// Null checks on converted pointers become `p.is_none()` and `p.is_some()`.
+/// When the condition of an `if` checks that the pointer is non-null, and the
+/// body doesn't reassign it, the check becomes `if let Some(p) = ...`, and the
+/// pointer is used as a reference inside the body.  After an early exit on
+/// `p.is_null()` (an `if` whose body ends in `return`, `break`, `continue`, or
+/// a call to `abort` or `exit`), and in the `else` branch of such a check,
+/// `*p` becomes `*p.unwrap()` until the pointer is reassigned.  Uses of the
+/// pointer that aren't known to be non-null are converted back to raw
+/// pointers, mapping `None` to null.
+///
+/// Null pointers assigned to converted variables (or passed as converted
+/// arguments) become `None`, `&x` becomes `Some(&x)`, converted `Option<&T>`
+/// variables are copied as-is, and other raw pointers `q` become `q.as_mut()`
+/// (or `q.as_ref()`).
+///
+/// Pointers that are offset, compared to other pointers, updated in place,
+/// have their address taken, or are used as method receivers are left
+/// unchanged.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn len(mut n: *const Node) -> i32 {
+///         let mut count = 0;
+///         while !n.is_null() {
+///             count += 1;
+///             n = (*n).next;
+///         }
+///         count
+///     }
+///
+///     unsafe fn first(n: *const Node) -> i32 {
+///         if n.is_null() {
+///             return -1;
+///         }
+///         (*n).value
+///     }
+/// ```
+///
+/// After running `wrap_nullable_in_option`:
+///
+/// ```ignore
+///     unsafe fn len(mut n: Option<&Node>) -> i32 {
+///         let mut count = 0;
+///         while n.is_some() {
+///             count += 1;
+///             n = (*n.map_or(::std::ptr::null(), |p| p as *const Node)).next.as_ref();
+///         }
+///         count
+///     }
+///
+///     unsafe fn first(n: Option<&Node>) -> i32 {
+///         if n.is_none() {
+///             return -1;
+///         }
+///         (*n.unwrap()).value
+///     }
+/// ```
+pub struct WrapNullableInOption;
+
+impl Transform for WrapNullableInOption {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the raw pointer locals and arguments.
+
+        let mut vars: HashMap<HirId, NullableVar> = HashMap::new();
+        let add_var = |vars: &mut HashMap<HirId, NullableVar>, pat: &Pat| {
+            let ident = match_or!([pat.kind] PatKind::Ident(_, ident, None) => ident; return);
+            let mt = match_or!([cx.opt_node_type(pat.id).map(|ty| &ty.kind)]
+                               Some(&TcxTyKind::RawPtr(mt)) => mt; return);
+            vars.insert(cx.hir_map().node_to_hir_id(pat.id), NullableVar {
+                ident,
+                mutbl: mt.mutbl,
+                pointee: reflect_tcx_ty(cx.ty_ctxt(), mt.ty),
+            });
+        };
+        visit_fns(krate, |fl| {
+            if fl.kind == FnKind::Foreign {
+                return;
+            }
+            for arg in &fl.decl.inputs {
+                add_var(&mut vars, &arg.pat);
+            }
+        });
+        visit_nodes(krate, |l: &Local| add_var(&mut vars, &l.pat));
+
+        let var_of = |vars: &HashMap<HirId, NullableVar>, e: &Expr| -> Option<HirId> {
+            let e = strip_casts(e);
+            if !matches!([e.kind] ExprKind::Path(..)) {
+                return None;
+            }
+            let hid = cx.try_resolve_expr_to_hid(e)?;
+            if vars.contains_key(&hid) { Some(hid) } else { None }
+        };
+
+        // (2) Keep only the pointers that are checked against or assigned null, and that can be
+        // represented as options.
+
+        let mut nullable = HashSet::new();
+        let mut unsupported = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some((_, p)) = as_null_check(e) {
+                nullable.extend(var_of(&vars, p));
+                return;
+            }
+            let mut bail = |operand: &Expr| unsupported.extend(var_of(&vars, operand));
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if is_null_ptr(rhs) =>
+                    nullable.extend(var_of(&vars, lhs)),
+                ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() != "is_null" =>
+                    bail(&args[0]),
+                ExprKind::AddrOf(_, _, ref a) => bail(a),
+                ExprKind::AssignOp(_, ref lhs, _) => bail(lhs),
+                ExprKind::Binary(_, ref a, ref b) => {
+                    bail(a);
+                    bail(b);
+                }
+                _ => {}
+            }
+        });
+        visit_nodes(krate, |l: &Local| {
+            if l.init.as_ref().map_or(false, |init| is_null_ptr(init)) {
+                nullable.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+            }
+        });
+        vars.retain(|hid, _| nullable.contains(hid) && !unsupported.contains(hid));
+        if vars.is_empty() {
+            return;
+        }
+
+        // Converted arguments of each function, by index.
+        let mut conv_args: HashMap<DefId, HashMap<usize, HirId>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter().enumerate() {
+                let hid = cx.hir_map().node_to_hir_id(arg.pat.id);
+                if vars.contains_key(&hid) {
+                    conv_args.entry(cx.node_def_id(fl.id)).or_insert_with(HashMap::new)
+                        .insert(i, hid);
+                }
+            }
+        });
+
+        // (3) Find the uses that are known to be non-null.  `guard_ifs` are the `if`s that will
+        // become `if let`, and `guarded` are the uses of the pointer inside their bodies, which
+        // will refer to the new binding.
+
+        let is_var = |hid: HirId| {
+            let vars = &vars;
+            move |e: &Expr| var_of(vars, e) == Some(hid)
+        };
+
+        let mut guard_ifs = HashSet::new();
+        let mut guarded = HashSet::new();
+        let mut nonnull = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let (cond, then, els) = match_or!([e.kind]
+                ExprKind::If(ref c, ref t, ref el) => (c, t, el); return);
+            let (is_null, p) = match_or!([as_null_check(cond)] Some(x) => x; return);
+            let hid = match_or!([var_of(&vars, p)] Some(x) => x; return);
+            if guarded.contains(&p.id) {
+                // Already a reference, inside the body of an enclosing `if let`.
+                return;
+            }
+            if !is_null {
+                if !assigns_to(&**then, is_var(hid)) {
+                    guard_ifs.insert(e.id);
+                    collect_uses(cx, &**then, hid, &mut guarded);
+                }
+            } else if let Some(ref els) = *els {
+                if !assigns_to(&**els, is_var(hid)) {
+                    collect_uses(cx, &**els, hid, &mut nonnull);
+                }
+            }
+        });
+        visit_nodes(krate, |b: &Block| {
+            for (i, s) in b.stmts.iter().enumerate() {
+                let e = match_or!([s.kind] StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e;
+                                  continue);
+                let (cond, then) = match_or!([e.kind] ExprKind::If(ref c, ref t, None) => (c, t);
+                                             continue);
+                let (is_null, p) = match_or!([as_null_check(cond)] Some(x) => x; continue);
+                let hid = match_or!([var_of(&vars, p)] Some(x) => x; continue);
+                if !is_null || !diverges(then) {
+                    continue;
+                }
+                for s in &b.stmts[i + 1..] {
+                    if assigns_to(s, is_var(hid)) {
+                        break;
+                    }
+                    collect_uses(cx, s, hid, &mut nonnull);
+                }
+            }
+        });
+
+        // (4) Rewrite null checks, stores, arguments, and dereferences.
+
+        let pick = |mutbl: Mutability, m: &str, i: &str| match mutbl {
+            Mutability::Mutable => parse_expr(cx.session(), m),
+            Mutability::Immutable => parse_expr(cx.session(), i),
+        };
+        let mut handled: HashSet<NodeId> = HashSet::new();
+
+        // Convert a raw pointer expression `e` to an option for a variable of mutability `mutbl`.
+        let to_option = |handled: &mut HashSet<NodeId>, e: &P<Expr>, mutbl: Mutability|
+                         -> P<Expr> {
+            if is_null_ptr(e) {
+                return parse_expr(cx.session(), "None");
+            }
+            let inner = strip_casts(e);
+            let mut bnd = Bindings::new();
+            let is_ref = matches!([inner.kind] ExprKind::AddrOf(..));
+            let tmpl = if is_ref || guarded.contains(&inner.id) {
+                bnd.add("__e", P(inner.clone()));
+                parse_expr(cx.session(), "Some(__e)")
+            } else if mutbl == Mutability::Immutable && var_of(&vars, inner).map_or(false, |hid| {
+                vars[&hid].mutbl == Mutability::Immutable
+            }) {
+                // `Option<&T>` is `Copy`.  Copying an `Option<&mut T>` would need a reborrow that
+                // outlives the source variable, so those go through a raw pointer instead.
+                handled.insert(inner.id);
+                return P(inner.clone());
+            } else {
+                bnd.add("__e", e.clone());
+                pick(mutbl, "__e.as_mut()", "__e.as_ref()")
+            };
+            tmpl.subst(st, cx, &bnd)
+        };
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if guard_ifs.contains(&e.id) {
+                let (p_id, hid) = {
+                    let cond = expect!([e.kind] ExprKind::If(ref c, ..) => c);
+                    let (_, p) = as_null_check(cond).unwrap();
+                    (p.id, var_of(&vars, p).unwrap())
+                };
+                handled.insert(p_id);
+                let var = &vars[&hid];
+                let src = match var.mutbl {
+                    Mutability::Mutable => format!("if let Some({0}) = {0}.as_deref_mut() {{}}",
+                                                   var.ident),
+                    Mutability::Immutable => format!("if let Some({0}) = {0} {{}}", var.ident),
+                };
+                let new_cond = expect!([parse_expr(cx.session(), &src).kind]
+                                       ExprKind::If(ref c, ..) => c.clone());
+                expect!([e.kind] ExprKind::If(ref mut c, ..) => *c = new_cond);
+                return;
+            }
+
+            if let Some((is_null, p)) = as_null_check(e) {
+                if var_of(&vars, p).is_none() || guarded.contains(&p.id) {
+                    return;
+                }
+                handled.insert(p.id);
+                let mut bnd = Bindings::new();
+                bnd.add("__p", P(p.clone()));
+                let tmpl = if is_null { "__p.is_none()" } else { "__p.is_some()" };
+                *e = parse_expr(cx.session(), tmpl).subst(st, cx, &bnd);
+                return;
+            }
+
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    let hid = match_or!([var_of(&vars, lhs)] Some(x) => x; return);
+                    if guarded.contains(&strip_casts(lhs).id) {
+                        return;
+                    }
+                    handled.insert(strip_casts(lhs).id);
+                    *rhs = to_option(&mut handled, rhs, vars[&hid].mutbl);
+                }
+
+                ExprKind::Call(..) => {
+                    let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+                    let args_hids = match_or!([conv_args.get(&callee)] Some(x) => x; return);
+                    let args = expect!([e.kind] ExprKind::Call(_, ref mut args) => args);
+                    for (&i, hid) in args_hids {
+                        if let Some(arg) = args.get_mut(i) {
+                            *arg = to_option(&mut handled, arg, vars[hid].mutbl);
+                        }
+                    }
+                }
+
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    let p = strip_casts(inner);
+                    let hid = match_or!([var_of(&vars, p)] Some(x) => x; return);
+                    if !nonnull.contains(&p.id) {
+                        return;
+                    }
+                    handled.insert(p.id);
+                    let mut bnd = Bindings::new();
+                    bnd.add("__p", P(p.clone()));
+                    let tmpl = pick(vars[&hid].mutbl, "*__p.as_deref_mut().unwrap()",
+                                    "*__p.unwrap()");
+                    *e = tmpl.subst(st, cx, &bnd);
+                }
+
+                ExprKind::Cast(ref mut inner, _) => {
+                    // Inside an `if let`, the pointer is a reference, which can only be cast to
+                    // a raw pointer of the same type.
+                    if !guarded.contains(&inner.id) {
+                        return;
+                    }
+                    let hid = match_or!([var_of(&vars, inner)] Some(x) => x; return);
+                    let var = &vars[&hid];
+                    let mut bnd = Bindings::new();
+                    bnd.add("__p", inner.clone());
+                    bnd.add("__t", var.pointee.clone());
+                    let tmpl = pick(var.mutbl, "__p as *mut __t", "__p as *const __t");
+                    *inner = tmpl.subst(st, cx, &bnd);
+                    handled.insert(inner.id);
+                }
+
+                _ => {}
+            }
+        });
+
+        // (5) Change the types of the variables and their initializers.
+
+        let opt_ty_mut = parse_ty(cx.session(), "Option<&mut __t>");
+        let opt_ty = parse_ty(cx.session(), "Option<&__t>");
+        let opt_ty_for = |var: &NullableVar| {
+            let mut bnd = Bindings::new();
+            bnd.add("__t", var.pointee.clone());
+            match var.mutbl {
+                Mutability::Mutable => opt_ty_mut.clone().subst(st, cx, &bnd),
+                Mutability::Immutable => opt_ty.clone().subst(st, cx, &bnd),
+            }
+        };
+        // `Option<&mut T>` can only be reborrowed through a mutable binding.
+        let make_mut = |pat: &mut P<Pat>, var: &NullableVar| {
+            if var.mutbl == Mutability::Mutable {
+                if let PatKind::Ident(ref mut mode, _, _) = pat.kind {
+                    *mode = BindingMode::ByValue(Mutability::Mutable);
+                }
+            }
+        };
+
+        mut_visit_fns(krate, |fl| {
+            for arg in &mut fl.decl.inputs {
+                let hid = cx.hir_map().node_to_hir_id(arg.pat.id);
+                let var = match_or!([vars.get(&hid)] Some(x) => x; continue);
+                arg.ty = opt_ty_for(var);
+                make_mut(&mut arg.pat, var);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+            let var = match_or!([vars.get(&hid)] Some(x) => x; return);
+            if l.ty.is_some() {
+                l.ty = Some(opt_ty_for(var));
+            }
+            make_mut(&mut l.pat, var);
+            if let Some(ref mut init) = l.init {
+                *init = to_option(&mut handled, init, var.mutbl);
+            }
+        });
+
+        // (6) Convert the remaining uses back to raw pointers.
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) || guarded.contains(&e.id) ||
+               !matches!([e.kind] ExprKind::Path(..)) {
+                return;
+            }
+            let hid = match_or!([var_of(&vars, e)] Some(x) => x; return);
+            let var = &vars[&hid];
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    let mut bnd = Bindings::new();
+                    bnd.add("__p", e.clone());
+                    bnd.add("__t", var.pointee.clone());
+                    let tmpl = if nonnull.contains(&e.id) {
+                        pick(var.mutbl, "__p.as_deref_mut().unwrap() as *mut __t",
+                             "__p.unwrap() as *const __t")
+                    } else {
+                        pick(var.mutbl,
+                             "__p.as_deref_mut().map_or(::std::ptr::null_mut(), |p| p as *mut __t)",
+                             "__p.map_or(::std::ptr::null(), |p| p as *const __t)")
+                    };
+                    *e = tmpl.subst(st, cx, &bnd);
+                }
+                _ => {
+                    warn!("can't convert lvalue use of `{}`", pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("wrap_nullable_in_option", |_args| mk(WrapNullableInOption));
+}
@@ -14,10 +14,11 @@ use syntax::tokenstream::{TokenTree, TokenStream, DelimSpan};
 use smallvec::{smallvec, SmallVec};
 
 use crate::ast_manip::{MutVisitNodes, MutVisit};
-use crate::ast_manip::fn_edit::flat_map_fns;
+use crate::ast_manip::fn_edit::{flat_map_fns, mut_visit_fns};
 use crate::analysis::labeled_ty::LabeledTyCtxt;
 use crate::analysis::ownership::{self, ConcretePerm, Var, PTy};
 use crate::analysis::ownership::constraint::{ConstraintSet, Perm};
+use crate::analysis::pointer_roles::{self, PointerRole, PointerRoles, PointerSlot};
 use crate::command::{CommandState, Registry, DriverCommand};
 use crate::context::HirMap;
 use crate::driver::{Phase};
@@ -47,6 +48,14 @@ pub fn register_commands(reg: &mut Registry) {
             do_mark_pointers(st, cx);
         }))
     });
+
+    reg.register("analyze_ownership", |args| {
+        let path = args.get(0).cloned();
+
+        Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
+            do_analyze_ownership(st, cx, path.as_ref().map(|s| s.as_str()));
+        }))
+    });
 }
 
 /// # `ownership_annotate` Command
@@ -506,6 +515,25 @@ fn callee_new_name(cx: &RefactorCtxt,
 /// of the ownership analysis.
 /// See `analysis/ownership/README.md` for details on ownership inference.
 fn do_mark_pointers(st: &CommandState, cx: &RefactorCtxt) {
+    let s_ref = "ref".into_symbol();
+    let s_mut = "mut".into_symbol();
+    let s_move = "move".into_symbol();
+
+    for (id, p) in pointer_perms(st, cx) {
+        let label = match p {
+            ConcretePerm::Read => s_ref,
+            ConcretePerm::Write => s_mut,
+            ConcretePerm::Move => s_move,
+        };
+
+        st.add_mark(id, label);
+    }
+}
+
+/// Run ownership analysis and return the inferred permission of each pointer type annotation in
+/// the crate (in function signatures, locals, statics, and struct fields), keyed by the `NodeId`
+/// of the annotation.
+pub fn pointer_perms(st: &CommandState, cx: &RefactorCtxt) -> HashMap<NodeId, ConcretePerm> {
     let arena = SyncDroplessArena::default();
     let ana = ownership::analyze(&st, &cx, &arena);
 
@@ -581,22 +609,125 @@ fn do_mark_pointers(st: &CommandState, cx: &RefactorCtxt) {
         hir_map: cx.hir_map(),
     };
 
-    let s_ref = "ref".into_symbol();
-    let s_mut = "mut".into_symbol();
-    let s_move = "move".into_symbol();
-
+    let mut perms = HashMap::new();
     type_map::map_types(&cx.hir_map(), source, &st.krate(), |_source, ast_ty, lty| {
-        let p = match lty.label {
-            Some(x) => x,
-            None => return,
-        };
+        if let Some(p) = lty.label {
+            perms.insert(ast_ty.id, p);
+        }
+    });
+    perms
+}
 
-        let label = match p {
-            ConcretePerm::Read => s_ref,
-            ConcretePerm::Write => s_mut,
-            ConcretePerm::Move => s_move,
-        };
 
-        st.add_mark(ast_ty.id, label);
+/// # `analyze_ownership` Command
+///
+/// Usage: `analyze_ownership [FILE]`
+///
+/// Classify each pointer-typed argument and return value of the crate's
+/// functions, and each pointer-typed struct field, as owning, borrowing, or aliasing, based on the flow of values from
+/// `malloc` and into `free`, and on where pointers get stored.
+/// See `analysis/pointer_roles.rs` for details.
+///
+/// The results are recorded as attributes: each function gets
+/// `#[ownership_roles(ARG = ROLE, ...)]` listing its pointer arguments, plus
+/// `#[ownership_return(ROLE)]` if it returns a pointer, and each pointer field
+/// gets `#[ownership_role(ROLE)]`, where `ROLE` is one of `OWNED`, `BORROWED`,
+/// or `ALIASED`.  If `FILE` is given, the source is left unchanged and the
+/// results are instead written to `FILE` as JSON, with one entry per pointer:
+///
+/// ```ignore
+///     { "kind": "arg", "path": "foo::bar", "index": 0, "role": "owned" }
+/// ```
+///
+/// `kind` is one of `arg`, `return`, or `field`, and `index` is present only
+/// for arguments.
+fn do_analyze_ownership(st: &CommandState, cx: &RefactorCtxt, path: Option<&str>) {
+    let roles = pointer_roles::analyze(cx, &st.krate());
+
+    if let Some(path) = path {
+        let json = encode_pointer_roles(cx, &roles);
+        if let Err(e) = ::std::fs::write(path, json::stringify_pretty(json, 2)) {
+            warn!("failed to write ownership results to {}: {}", path, e);
+        }
+        return;
+    }
+
+    st.map_krate(|krate| {
+        mut_visit_fns(krate, |fl| {
+            let did = match_or!([cx.hir_map().opt_local_def_id_from_node_id(fl.id)]
+                                Some(x) => x; return);
+            fl.attrs.retain(|a| {
+                !a.check_name("ownership_roles".into_symbol()) &&
+                !a.check_name("ownership_return".into_symbol())
+            });
+
+            let mut args = Vec::new();
+            for (i, arg) in fl.decl.inputs.iter().enumerate() {
+                let role = match_or!([roles.get(PointerSlot::Arg(did, i))] Some(x) => x; continue);
+                let ident = match_or!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident;
+                                      continue);
+                if !args.is_empty() {
+                    args.push(token(TokenKind::Comma));
+                }
+                args.push(ident_token(&ident.as_str()));
+                args.push(token(TokenKind::Eq));
+                args.push(role_token(role));
+            }
+            if !args.is_empty() {
+                fl.attrs.push(make_attr("ownership_roles", delimited(args)));
+            }
+
+            if let Some(role) = roles.get(PointerSlot::Return(did)) {
+                fl.attrs.push(make_attr("ownership_return", delimited(vec![role_token(role)])));
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let vd = match_or!([i.kind] ItemKind::Struct(ref mut vd, _) |
+                                        ItemKind::Union(ref mut vd, _) => vd; return);
+            for f in vd.fields_mut() {
+                f.attrs.retain(|a| !a.check_name("ownership_role".into_symbol()));
+                let did = cx.node_def_id(f.id);
+                if let Some(role) = roles.get(PointerSlot::Field(did)) {
+                    f.attrs.push(make_attr("ownership_role", delimited(vec![role_token(role)])));
+                }
+            }
+        });
     });
 }
+
+fn role_token(r: PointerRole) -> TokenTree {
+    let name = match r {
+        PointerRole::Owned => "OWNED",
+        PointerRole::Borrowed => "BORROWED",
+        PointerRole::Aliased => "ALIASED",
+    };
+    ident_token(name)
+}
+
+fn encode_pointer_roles(cx: &RefactorCtxt, roles: &PointerRoles) -> json::JsonValue {
+    let tcx = cx.ty_ctxt();
+    let mut entries = roles.iter()
+        .map(|(slot, role)| {
+            let (kind, did, index) = match slot {
+                PointerSlot::Arg(did, i) => ("arg", did, Some(i)),
+                PointerSlot::Return(did) => ("return", did, None),
+                PointerSlot::Field(did) => ("field", did, None),
+            };
+            (tcx.def_path(did).to_string_no_crate(), kind, index, role)
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    json::JsonValue::Array(entries.into_iter().map(|(path, kind, index, role)| {
+        let mut obj = json::object! {
+            "kind" => kind,
+            "path" => path,
+            "role" => role.to_string(),
+        };
+        if let Some(i) = index {
+            obj["index"] = i.into();
+        }
+        obj
+    }).collect())
+}
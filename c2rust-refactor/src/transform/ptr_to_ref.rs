@@ -0,0 +1,592 @@
+//! Conversion of raw pointer arguments and struct fields to references, with inferred lifetimes.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::source_map::DUMMY_SP;
+
+use crate::analysis::ownership::ConcretePerm;
+use crate::analysis::points_to;
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::matcher::{Bindings, Subst};
+use crate::transform::Transform;
+use crate::transform::heap::{is_null_ptr, strip_casts};
+use crate::transform::ownership::pointer_perms;
+use crate::RefactorCtxt;
+
+
+/// The lifetime parameter added to structs (and their impls) that hold references.
+const STRUCT_LIFETIME: &str = "'a";
+
+/// A pointer-typed location selected for conversion: either a function argument (identified by
+/// its binding) or a struct field.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum RefVar {
+    Local(HirId),
+    Field(DefId),
+}
+
+fn mk_lifetime(name: &str) -> Lifetime {
+    Lifetime {
+        id: DUMMY_NODE_ID,
+        ident: Ident::from_str(name),
+    }
+}
+
+fn ref_ty(lifetime: Option<&str>, mutbl: Mutability, ty: P<Ty>) -> P<Ty> {
+    P(Ty {
+        id: DUMMY_NODE_ID,
+        kind: TyKind::Rptr(lifetime.map(mk_lifetime), MutTy { ty, mutbl }),
+        span: DUMMY_SP,
+    })
+}
+
+/// Add the lifetime parameter `name` to `generics`, unless it's already present.
+fn add_lifetime_param(generics: &mut Generics, name: &str) {
+    let ident = Ident::from_str(name);
+    let exists = generics.params.iter().any(|p| {
+        matches!([p.kind] GenericParamKind::Lifetime) && p.ident == ident
+    });
+    if exists {
+        return;
+    }
+    let param = GenericParam {
+        id: DUMMY_NODE_ID,
+        ident,
+        attrs: Default::default(),
+        bounds: Vec::new(),
+        kind: GenericParamKind::Lifetime,
+        is_placeholder: false,
+    };
+    // Lifetime parameters must come before type parameters.
+    generics.params.insert(0, param);
+}
+
+/// Add the lifetime argument `name` to the last segment of `path`, unless it already has one.
+fn add_lifetime_arg(path: &mut Path, name: &str) {
+    let seg = match_or!([path.segments.last_mut()] Some(x) => x; return);
+    let arg = GenericArg::Lifetime(mk_lifetime(name));
+    match seg.args {
+        Some(ref mut args) => {
+            if let GenericArgs::AngleBracketed(ref mut abpd) = **args {
+                if !abpd.args.iter().any(|a| matches!([a] GenericArg::Lifetime(_))) {
+                    abpd.args.insert(0, arg);
+                }
+            }
+        }
+        None => {
+            seg.args = Some(P(GenericArgs::AngleBracketed(AngleBracketedArgs {
+                span: DUMMY_SP,
+                args: vec![arg],
+                constraints: Vec::new(),
+            })));
+        }
+    }
+}
+
+/// Check if `ty` mentions any of the structs in `structs`.
+fn mentions_struct(cx: &RefactorCtxt, ty: &Ty, structs: &HashSet<DefId>) -> bool {
+    let mut found = false;
+    visit_nodes(ty, |t: &Ty| {
+        if let TyKind::Path(..) = t.kind {
+            if cx.try_resolve_ty(t).map_or(false, |did| structs.contains(&did)) {
+                found = true;
+            }
+        }
+    });
+    found
+}
+
+/// Add the lifetime argument `name` to every mention of one of `structs` in `ty`.
+fn add_struct_lifetimes(cx: &RefactorCtxt, ty: &mut P<Ty>, structs: &HashSet<DefId>, name: &str) {
+    MutVisitNodes::visit(ty, |t: &mut P<Ty>| {
+        let is_target = cx.try_resolve_ty(t).map_or(false, |did| structs.contains(&did));
+        if let TyKind::Path(_, ref mut path) = t.kind {
+            if is_target {
+                add_lifetime_arg(path, name);
+            }
+        }
+    });
+}
+
+/// Count the lifetime positions in a function's argument types, for the purposes of lifetime
+/// elision.
+fn count_input_lifetimes(cx: &RefactorCtxt, decl: &FnDecl, structs: &HashSet<DefId>) -> usize {
+    let mut count = 0;
+    for arg in &decl.inputs {
+        visit_nodes(&*arg.ty, |t: &Ty| {
+            match t.kind {
+                TyKind::Rptr(..) => count += 1,
+                TyKind::Path(..) => {
+                    if cx.try_resolve_ty(t).map_or(false, |did| structs.contains(&did)) {
+                        count += 1;
+                    }
+                }
+                _ => {}
+            }
+        });
+    }
+    count
+}
+
+/// Add explicit lifetimes to the output type of a function, if it mentions one of `structs` and
+/// elision can't determine the lifetime to use.
+fn fix_fn_output(cx: &RefactorCtxt, decl: &mut FnDecl, generics: &mut Generics,
+                 structs: &HashSet<DefId>) {
+    let out_ty = match_or!([decl.output] FunctionRetTy::Ty(ref ty) => ty; return);
+    if !mentions_struct(cx, out_ty, structs) {
+        return;
+    }
+    let has_self_ref = decl.inputs.get(0).map_or(false, |arg| {
+        arg.is_self() && matches!([arg.ty.kind] TyKind::Rptr(..))
+    });
+    if has_self_ref || count_input_lifetimes(cx, decl, structs) == 1 {
+        return;
+    }
+    let out_ty = match_or!([decl.output] FunctionRetTy::Ty(ref mut ty) => ty; return);
+    add_struct_lifetimes(cx, out_ty, structs, STRUCT_LIFETIME);
+    add_lifetime_param(generics, STRUCT_LIFETIME);
+}
+
+
+/// # `ptr_to_ref` Command
+///
+/// Usage: `ptr_to_ref`
+///
+/// Marks: `target`
+///
+/// For each function argument or struct field marked `target` with a raw
+/// pointer type, change its type to a reference.  Ownership analysis (see
+/// `analysis/ownership/README.md`) decides between `&T` and `&mut T`: pointers
+/// that are only read become `&T`, and pointers that are written through become
+/// `&mut T`.  Pointers that the analysis infers to be owning are left unchanged;
+/// use `malloc_to_box` for those.
+///
+/// At call sites and in stores to converted fields, `&mut x as *mut T` becomes
+/// `&mut x`, and other pointers `p` become `&*p` or `&mut *p`.  Null checks on
+/// converted values become `false`, and casts of converted values to other
+/// pointer types go through the original pointer type first.  Other uses need
+/// no changes, since references coerce to raw pointers.
+///
+/// Pointers that are offset, compared, have their address taken, or are
+/// assigned a null pointer are left unchanged, since they can't be represented
+/// as references.  So are arguments that, according to the points-to analysis
+/// (`analysis/points_to.rs`), may alias another pointer argument of the same
+/// call in a way the borrow rules forbid: an argument converted to `&mut T`
+/// may not alias any other pointer argument, and one converted to `&T` may not
+/// alias a `*mut` argument.
+///
+/// Function arguments rely on lifetime elision.  Structs with converted fields
+/// get a new lifetime parameter `'a`, which is also added to any struct that
+/// (transitively) contains one of them, to their `impl` blocks, and to
+/// functions whose return type mentions one of them when elision can't supply
+/// the lifetime.  Statics and constants use `'static`.  Type aliases, enums, and
+/// unions that mention the modified structs are not updated.
+///
+/// Example:
+///
+/// ```ignore
+///     struct Cursor {
+///         buf: *mut Buffer,
+///     }
+///
+///     unsafe fn advance(c: *mut Cursor) {
+///         (*(*c).buf).pos += 1;
+///     }
+/// ```
+///
+/// After running `ptr_to_ref`, with `buf` and `c` marked:
+///
+/// ```ignore
+///     struct Cursor<'a> {
+///         buf: &'a mut Buffer,
+///     }
+///
+///     unsafe fn advance(c: &mut Cursor) {
+///         (*(*c).buf).pos += 1;
+///     }
+/// ```
+pub struct PtrToRef;
+
+impl Transform for PtrToRef {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let perms = pointer_perms(st, cx);
+
+        // Decide how to convert the pointer type `ty`.  Returns the mutability of the reference
+        // and the pointee type.
+        let conversion = |ty: &Ty, what: &dyn Fn() -> String| -> Option<(Mutability, P<Ty>)> {
+            let mt = match ty.kind {
+                TyKind::Ptr(ref mt) => mt,
+                _ => {
+                    warn!("{} is not a raw pointer; skipping it", what());
+                    return None;
+                }
+            };
+            let mutbl = match perms.get(&ty.id) {
+                Some(ConcretePerm::Move) => {
+                    warn!("{} appears to be an owning pointer; skipping it", what());
+                    return None;
+                }
+                Some(ConcretePerm::Read) => Mutability::Immutable,
+                Some(ConcretePerm::Write) | None => mt.mutbl,
+            };
+            Some((mutbl, mt.ty.clone()))
+        };
+
+        // (1) Collect the marked arguments and fields.
+
+        let mut vars: HashMap<RefVar, (Mutability, P<Ty>)> = HashMap::new();
+
+        visit_fns(krate, |fl| {
+            for arg in &fl.decl.inputs {
+                if !st.marked(arg.id, "target") && !st.marked(arg.pat.id, "target") {
+                    continue;
+                }
+                let what = || format!("argument `{}` of `{}`",
+                                      pprust::pat_to_string(&arg.pat), fl.ident);
+                if let Some(conv) = conversion(&arg.ty, &what) {
+                    vars.insert(RefVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id)), conv);
+                }
+            }
+        });
+
+        visit_nodes(krate, |i: &Item| {
+            let fields = match_or!([i.kind] ItemKind::Struct(VariantData::Struct(ref fields, _), _)
+                                   => fields; return);
+            for sf in fields {
+                if !st.marked(sf.id, "target") {
+                    continue;
+                }
+                let what = || format!("field `{}::{:?}`", i.ident, sf.ident);
+                if let Some(conv) = conversion(&sf.ty, &what) {
+                    vars.insert(RefVar::Field(cx.node_def_id(sf.id)), conv);
+                }
+            }
+        });
+
+        let var_of = |vars: &HashMap<RefVar, _>, e: &Expr| -> Option<RefVar> {
+            let var = match e.kind {
+                ExprKind::Path(..) => RefVar::Local(cx.try_resolve_expr_to_hid(e)?),
+                ExprKind::Field(ref obj, name) => {
+                    let ty = cx.opt_adjusted_node_type(obj.id)?;
+                    let adt = match_or!([ty.kind] TcxTyKind::Adt(adt, _) => adt; return None);
+                    if adt.is_enum() {
+                        return None;
+                    }
+                    RefVar::Field(adt.non_enum_variant().fields.iter()
+                                  .find(|f| f.ident == name)?.did)
+                }
+                _ => return None,
+            };
+            if vars.contains_key(&var) { Some(var) } else { None }
+        };
+
+        // (2) Leave alone any pointer that can't be represented as a reference.
+
+        let mut unsupported = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let mut bail = |operand: &Expr| {
+                if let Some(var) = var_of(&vars, strip_casts(operand)) {
+                    unsupported.insert(var);
+                }
+            };
+            match e.kind {
+                ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() != "is_null" =>
+                    bail(&args[0]),
+                ExprKind::AddrOf(_, _, ref a) => bail(a),
+                ExprKind::Binary(_, ref a, ref b) => {
+                    bail(a);
+                    bail(b);
+                }
+                ExprKind::Assign(ref lhs, ref rhs) if is_null_ptr(rhs) => bail(lhs),
+                ExprKind::Struct(_, ref fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(e.id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TcxTyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        if !is_null_ptr(&f.expr) {
+                            continue;
+                        }
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if let Some(did) = did {
+                            unsupported.insert(RefVar::Field(did));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+        for var in &unsupported {
+            if vars.remove(var).is_some() {
+                warn!("{:?} can't be represented as a reference; leaving it unchanged", var);
+            }
+        }
+
+        // An argument converted to `&mut` must not alias any other pointer passed to the same
+        // call, and one converted to `&` must not alias a mutable pointer.
+
+        let pts = points_to::analyze(cx, krate);
+        let mut param_vars: HashMap<DefId, Vec<(usize, RefVar)>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter().enumerate() {
+                let var = RefVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id));
+                if vars.contains_key(&var) {
+                    param_vars.entry(cx.node_def_id(fl.id)).or_insert_with(Vec::new).push((i, var));
+                }
+            }
+        });
+
+        let ptr_mutbl = |e: &Expr| -> Option<Mutability> {
+            let ty = cx.opt_node_type(e.id)?;
+            match ty.kind {
+                TcxTyKind::RawPtr(mt) => Some(mt.mutbl),
+                TcxTyKind::Ref(_, _, mutbl) => Some(mutbl),
+                _ => None,
+            }
+        };
+        let mut aliased = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let params = match_or!([cx.opt_callee(e).and_then(|did| param_vars.get(&did))]
+                                   Some(x) => x; return);
+            let args: &[P<Expr>] = match e.kind {
+                ExprKind::Call(_, ref args) => args,
+                ExprKind::MethodCall(_, ref args) => args,
+                _ => return,
+            };
+            for &(idx, var) in params {
+                let arg = match_or!([args.get(idx)] Some(x) => x; continue);
+                let mutbl = vars[&var].0;
+                let conflict = args.iter().enumerate().any(|(j, other)| {
+                    let writable = match ptr_mutbl(other) {
+                        Some(Mutability::Mutable) => true,
+                        Some(Mutability::Immutable) => mutbl == Mutability::Mutable,
+                        None => false,
+                    };
+                    j != idx && writable && pts.aliases(arg, other)
+                });
+                if conflict {
+                    aliased.insert(var);
+                }
+            }
+        });
+        for var in &aliased {
+            if vars.remove(var).is_some() {
+                warn!("{:?} may alias another argument; leaving it unchanged", var);
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+
+        // (3) Convert values passed to converted arguments and stored into converted fields, and
+        // rewrite null checks and casts of converted values.
+
+        let mut mod_fns: HashMap<DefId, Vec<(usize, Mutability)>> = HashMap::new();
+        visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter().enumerate() {
+                let var = RefVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id));
+                if let Some(&(mutbl, _)) = vars.get(&var) {
+                    mod_fns.entry(cx.node_def_id(fl.id)).or_insert_with(Vec::new).push((i, mutbl));
+                }
+            }
+        });
+
+        let reborrow = parse_expr(cx.session(), "&*__e");
+        let reborrow_mut = parse_expr(cx.session(), "&mut *__e");
+        let to_ref = |e: &P<Expr>, mutbl: Mutability| -> P<Expr> {
+            let mut inner = e;
+            while let ExprKind::Paren(ref x) = inner.kind {
+                inner = x;
+            }
+            if var_of(&vars, inner).is_some() {
+                return inner.clone();
+            }
+            if let ExprKind::Cast(ref x, _) = inner.kind {
+                if let ExprKind::AddrOf(_, m, _) = x.kind {
+                    if m == mutbl || mutbl == Mutability::Immutable {
+                        return x.clone();
+                    }
+                }
+            }
+            let mut bnd = Bindings::new();
+            bnd.add("__e", e.clone());
+            match mutbl {
+                Mutability::Mutable => reborrow_mut.clone().subst(st, cx, &bnd),
+                Mutability::Immutable => reborrow.clone().subst(st, cx, &bnd),
+            }
+        };
+
+        let false_expr = parse_expr(cx.session(), "false");
+        let double_cast = parse_expr(cx.session(), "__e as __t as __u");
+        let mut handled_casts = HashSet::new();
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let Some(mod_args) = cx.opt_callee(&e).and_then(|did| mod_fns.get(&did)) {
+                let args: &mut [P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref mut args) => args,
+                    ExprKind::MethodCall(_, ref mut args) => args,
+                    _ => return,
+                };
+                for &(idx, mutbl) in mod_args {
+                    if let Some(arg) = args.get_mut(idx) {
+                        *arg = to_ref(arg, mutbl);
+                    }
+                }
+                return;
+            }
+
+            let new_e = match e.kind {
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if let Some(var) = var_of(&vars, lhs) {
+                        *rhs = to_ref(rhs, vars[&var].0);
+                    }
+                    return;
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TcxTyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if let Some(&(mutbl, _)) = did.and_then(|did| vars.get(&RefVar::Field(did))) {
+                            f.expr = to_ref(&f.expr, mutbl);
+                        }
+                    }
+                    return;
+                }
+
+                ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "is_null" => {
+                    if var_of(&vars, strip_casts(&args[0])).is_none() {
+                        return;
+                    }
+                    false_expr.clone()
+                }
+
+                ExprKind::Cast(ref x, ref ty) => {
+                    let var = match_or!([var_of(&vars, x)] Some(v) => v; return);
+                    if !handled_casts.insert(x.id) {
+                        return;
+                    }
+                    let (mutbl, ref pointee) = vars[&var];
+                    let ptr_ty = P(Ty {
+                        id: DUMMY_NODE_ID,
+                        kind: TyKind::Ptr(MutTy { ty: pointee.clone(), mutbl }),
+                        span: DUMMY_SP,
+                    });
+                    let mut bnd = Bindings::new();
+                    bnd.add("__e", x.clone());
+                    bnd.add("__t", ptr_ty);
+                    bnd.add("__u", ty.clone());
+                    double_cast.clone().subst(st, cx, &bnd)
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+
+        // (4) Change the types of the converted arguments and fields.
+
+        mut_visit_fns(krate, |fl| {
+            for arg in &mut fl.decl.inputs {
+                let var = RefVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id));
+                if let Some(&(mutbl, ref pointee)) = vars.get(&var) {
+                    arg.ty = ref_ty(None, mutbl, pointee.clone());
+                }
+            }
+        });
+
+        // Structs that need a lifetime parameter, because they hold a reference (directly or
+        // through another such struct).
+        let mut lt_structs: HashSet<DefId> = HashSet::new();
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = cx.node_def_id(i.id);
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    let var = RefVar::Field(cx.node_def_id(sf.id));
+                    if let Some(&(mutbl, ref pointee)) = vars.get(&var) {
+                        sf.ty = ref_ty(Some(STRUCT_LIFETIME), mutbl, pointee.clone());
+                        lt_structs.insert(did);
+                    }
+                }
+            }
+        });
+
+        // (5) Propagate lifetime parameters to structs containing the modified structs.
+
+        loop {
+            let mut new_structs = Vec::new();
+            visit_nodes(krate, |i: &Item| {
+                let fields = match_or!([i.kind] ItemKind::Struct(ref vd, _) => vd.fields(); return);
+                let did = cx.node_def_id(i.id);
+                if !lt_structs.contains(&did) &&
+                   fields.iter().any(|sf| mentions_struct(cx, &sf.ty, &lt_structs)) {
+                    new_structs.push(did);
+                }
+            });
+            if new_structs.is_empty() {
+                break;
+            }
+            lt_structs.extend(new_structs);
+        }
+
+        // (6) Add lifetime parameters and arguments where elision doesn't apply.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let did = cx.node_def_id(i.id);
+            match i.kind {
+                ItemKind::Struct(ref mut vd, ref mut generics) if lt_structs.contains(&did) => {
+                    add_lifetime_param(generics, STRUCT_LIFETIME);
+                    for sf in vd.fields_mut() {
+                        add_struct_lifetimes(cx, &mut sf.ty, &lt_structs, STRUCT_LIFETIME);
+                    }
+                }
+
+                ItemKind::Impl(_, _, _, ref mut generics, _, ref mut self_ty, ref mut items) => {
+                    if mentions_struct(cx, self_ty, &lt_structs) {
+                        add_struct_lifetimes(cx, self_ty, &lt_structs, STRUCT_LIFETIME);
+                        add_lifetime_param(generics, STRUCT_LIFETIME);
+                    }
+                    for item in items {
+                        if let ImplItemKind::Method(ref mut sig, _) = item.kind {
+                            fix_fn_output(cx, &mut sig.decl, &mut item.generics, &lt_structs);
+                        }
+                    }
+                }
+
+                ItemKind::Static(ref mut ty, _, _) | ItemKind::Const(ref mut ty, _) => {
+                    add_struct_lifetimes(cx, ty, &lt_structs, "'static");
+                }
+
+                ItemKind::Fn(ref mut sig, ref mut generics, _) => {
+                    fix_fn_output(cx, &mut sig.decl, generics, &lt_structs);
+                }
+
+                _ => {}
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("ptr_to_ref", |_args| mk(PtrToRef));
+}
@@ -0,0 +1,160 @@
+//! Conversion of the C library's random number generator to the `rand` crate.
+
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::MutVisitNodes;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::is_call_to;
+use crate::transform::slices::cast_str;
+use crate::RefactorCtxt;
+
+
+/// The generator that replaces `rand`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RngKind {
+    /// `rand::rngs::StdRng`, seeded from the `srand` seed.
+    Std,
+    /// The linear congruential generator from the C standard's sample implementation of `rand`.
+    Lcg,
+}
+
+impl RngKind {
+    fn from_arg(s: &str) -> RngKind {
+        match s {
+            "std" => RngKind::Std,
+            "lcg" => RngKind::Lcg,
+            _ => panic!("unknown generator {:?} (expected std or lcg)", s),
+        }
+    }
+}
+
+/// The C library's random number functions, and whether each one sets the seed.
+const RAND_FNS: &[(&str, bool)] = &[
+    ("rand", false),
+    ("random", false),
+    ("srand", true),
+    ("srandom", true),
+];
+
+/// The name of the static holding the generator's state.
+const RNG_STATIC: &str = "C2RUST_RNG";
+
+
+/// # `rand_to_rng` Command
+///
+/// Usage: `rand_to_rng [GENERATOR]`
+///
+/// Replace calls to the C library's `rand`, `random`, `srand` and `srandom`
+/// with a generator whose state lives in a new static, `C2RUST_RNG`, at the
+/// top of the crate.  `GENERATOR` selects the generator (default: `std`):
+///
+///  * `std`: `C2RUST_RNG` is an `Option<rand::rngs::StdRng>`.  `srand(seed)`
+///    becomes `C2RUST_RNG = Some(StdRng::seed_from_u64(seed as u64))`, and
+///    `rand()` draws a value between 0 and glibc's `RAND_MAX` from it,
+///    seeding it with 1 first if the program never called `srand`, as C does.
+///    The same seed gives the same sequence on every platform, but not the
+///    sequence the C library gave.
+///
+///  * `lcg`: `C2RUST_RNG` is the `u32` state of the generator from the C
+///    standard's sample implementation of `rand`, for programs whose output
+///    must match a C library that uses it.  Its values range from 0 to 32767,
+///    so code that divides by `RAND_MAX` (a literal in transpiled code) needs
+///    to be updated by hand.
+///
+/// The new static is marked `new`.  Functions that used the global seed can
+/// take the generator as an argument instead by marking them `user`, marking
+/// the static `target`, and running `static_to_local_ref`.  The crate needs a
+/// dependency on `rand` 0.8 for the `std` generator.
+///
+/// Example:
+///
+/// ```ignore
+///     srand(seed as libc::c_uint);
+///     let roll: libc::c_int = rand() % 6 as libc::c_int + 1 as libc::c_int;
+/// ```
+///
+/// After running `rand_to_rng`:
+///
+/// ```ignore
+///     static mut C2RUST_RNG: Option<::rand::rngs::StdRng> = None;
+///
+///     C2RUST_RNG = Some(::rand::SeedableRng::seed_from_u64(seed as libc::c_uint as u64));
+///     let roll: libc::c_int = ::rand::Rng::gen_range::<libc::c_int, _>(
+///         C2RUST_RNG.get_or_insert_with(|| ::rand::SeedableRng::seed_from_u64(1)),
+///         0..=2147483647) % 6 as libc::c_int + 1 as libc::c_int;
+/// ```
+pub struct RandToRng {
+    pub kind: RngKind,
+}
+
+impl Transform for RandToRng {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut changed = false;
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = {
+                let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+                let seeds = match RAND_FNS.iter().find(|&&(name, _)| is_call_to(e, name)) {
+                    Some(&(_, seeds)) => seeds,
+                    None => return,
+                };
+                if args.len() != seeds as usize {
+                    return;
+                }
+                let ty = cx.opt_node_type(e.id)
+                    .map(|ty| pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ty)))
+                    .unwrap_or_else(|| "_".to_owned());
+                match (self.kind, seeds) {
+                    (RngKind::Std, true) => format!(
+                        "{} = Some(::rand::SeedableRng::seed_from_u64({}))",
+                        RNG_STATIC, cast_str(&args[0], "u64")),
+                    (RngKind::Std, false) => format!(
+                        "::rand::Rng::gen_range::<{}, _>(\
+                             {}.get_or_insert_with(|| ::rand::SeedableRng::seed_from_u64(1)), \
+                             0..=2147483647)",
+                        ty, RNG_STATIC),
+                    (RngKind::Lcg, true) => format!(
+                        "{} = {}", RNG_STATIC, cast_str(&args[0], "u32")),
+                    (RngKind::Lcg, false) => format!(
+                        "{{ {0} = {0}.wrapping_mul(1103515245).wrapping_add(12345); \
+                            ({0} / 65536 % 32768) as {1} }}",
+                        RNG_STATIC, ty),
+                }
+            };
+            *e = parse_expr(cx.session(), &src);
+            changed = true;
+        });
+        if !changed {
+            return;
+        }
+
+        let src = match self.kind {
+            RngKind::Std => format!("static mut {}: Option<::rand::rngs::StdRng> = None;",
+                                    RNG_STATIC),
+            RngKind::Lcg => format!("static mut {}: u32 = 1;", RNG_STATIC),
+        };
+        let items = st.parse_items(cx, &src);
+        for i in &items {
+            st.add_mark(i.id, "new");
+        }
+        krate.module.items.splice(0..0, items);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("rand_to_rng", |args| {
+        let kind = args.get(0).map_or(RngKind::Std, |s| RngKind::from_arg(s));
+        mk(RandToRng { kind })
+    });
+}
@@ -2,6 +2,7 @@ use derive_more::From;
 use indexmap::IndexMap;
 use smallvec::SmallVec;
 use std::collections::{HashMap, HashSet, hash_map::Entry};
+use std::iter;
 use std::mem;
 
 use crate::transform::Transform;
@@ -968,7 +969,7 @@ impl HeaderInfo {
     // TODO: In macOS mojave the system headers aren't in `/usr/include` anymore,
     // so this needs to be updated.
     fn is_std(&self) -> bool {
-        self.path.contains("/usr/include")
+        is_std_header(&self.path)
     }
 }
 
@@ -1688,8 +1689,301 @@ impl Transform for ReorganizeDefinitions {
     }
 }
 
+/// Check if `path` is a system header.
+fn is_std_header(path: &str) -> bool {
+    path.contains("/usr/include")
+}
+
+/// # `reorganize_modules` Command
+///
+/// Usage: `reorganize_modules`
+///
+/// This refactoring operates on code transpiled with the
+/// `--reorganize-definitions` flag, which records the header each header
+/// module came from in a `#[c2rust::header_src]` attribute.
+///
+/// This pass moves the items in header modules into a module tree that mirrors
+/// the layout of the original C headers, relative to the deepest directory
+/// containing all of the project's headers: a declaration from
+/// `include/net/sock.h` ends up in `crate::include::net::sock_h`. System
+/// headers go in a `stdlib` module instead. Identical copies of a declaration
+/// from several translation units are merged into one.
+///
+/// Each header module keeps a `pub use` re-export of the items moved out of
+/// it, and all paths to the moved items are updated to their new location.
+/// An item is left in place if its destination module already contains a
+/// different item with the same name.
+pub struct ReorganizeModules;
+
+/// The items moved by `reorganize_modules`.
+#[derive(Default)]
+struct ModuleMoves {
+    /// Destination module of each moved item
+    dests: HashMap<NodeId, Vec<Ident>>,
+
+    /// Duplicate items that are deleted in favor of an identical moved item
+    dropped: HashSet<NodeId>,
+
+    /// New absolute path of each moved or deleted item
+    new_paths: HashMap<DefId, Path>,
+
+    /// Re-exports to add to each header module
+    reexports: HashMap<NodeId, Vec<Path>>,
+}
+
+/// Convert a directory or file name into a module identifier.
+fn module_ident(name: &str) -> Ident {
+    let mut s = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    let ident = Ident::from_str(&s);
+    if ident.is_reserved() {
+        Ident::from_str(&format!("{}_", s))
+    } else {
+        ident
+    }
+}
+
+/// Compute the destination module path of each header.
+fn header_module_paths(headers: &HashSet<String>) -> HashMap<String, Vec<Ident>> {
+    fn components(path: &str) -> Vec<&str> {
+        path.split('/').filter(|p| !p.is_empty()).collect()
+    }
+
+    // Find the directory containing all of the non-system headers
+    let mut prefix: Option<Vec<&str>> = None;
+    for header in headers.iter().filter(|h| !is_std_header(h)) {
+        let mut dirs = components(header);
+        dirs.pop();
+        prefix = Some(match prefix {
+            None => dirs,
+            Some(prefix) => prefix
+                .into_iter()
+                .zip(dirs)
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    let prefix_len = prefix.map_or(0, |p| p.len());
+
+    headers
+        .iter()
+        .map(|header| {
+            let parts = components(header);
+            let path = if is_std_header(header) {
+                vec![Ident::from_str("stdlib"), module_ident(parts[parts.len() - 1])]
+            } else {
+                parts[prefix_len..].iter().map(|p| module_ident(p)).collect()
+            };
+            (header.clone(), path)
+        })
+        .collect()
+}
+
+/// Collect all header modules in `items` and their submodules, along with
+/// their paths and headers.
+fn collect_header_mods<'a>(
+    items: &'a [P<Item>],
+    path: &mut Vec<Ident>,
+    out: &mut Vec<(Vec<Ident>, String, &'a Item)>,
+) {
+    for item in items {
+        if let ItemKind::Mod(m) = &item.kind {
+            path.push(item.ident);
+            match parse_source_header(&item.attrs) {
+                Some((ref header, _)) if header.split('/').any(|p| !p.is_empty()) => {
+                    out.push((path.clone(), header.clone(), &**item))
+                }
+                Some(_) => {}
+                None => collect_header_mods(&m.items, path, out),
+            }
+            path.pop();
+        }
+    }
+}
+
+fn find_module<'a>(module: &'a Mod, path: &[Ident]) -> Option<&'a Mod> {
+    match path.split_first() {
+        None => Some(module),
+        Some((ident, rest)) => module.items.iter().find_map(|i| match &i.kind {
+            ItemKind::Mod(m) if i.ident == *ident => find_module(m, rest),
+            _ => None,
+        }),
+    }
+}
+
+/// Remove the moved and dropped items from `module` and its submodules,
+/// adding re-exports to the header modules they were removed from.
+fn take_moved_items(
+    module: &mut Mod,
+    moves: &ModuleMoves,
+    moved: &mut Vec<(Vec<Ident>, P<Item>)>,
+) {
+    let items = mem::replace(&mut module.items, vec![]);
+    for mut item in items {
+        if let Some(dest) = moves.dests.get(&item.id) {
+            moved.push((dest.clone(), item));
+            continue;
+        }
+        if moves.dropped.contains(&item.id) {
+            continue;
+        }
+        let id = item.id;
+        if let ItemKind::Mod(m) = &mut item.kind {
+            take_moved_items(m, moves, moved);
+            if let Some(paths) = moves.reexports.get(&id) {
+                // Imports used by the moved items are no longer needed
+                if m.items.iter().all(|i| matches!([i.kind] ItemKind::Use(..))) {
+                    m.items.retain(|i| i.vis.node.is_pub());
+                }
+                for path in paths {
+                    m.items.push(mk().pub_().use_simple_item(path.clone(), None as Option<Ident>));
+                }
+            }
+        }
+        module.items.push(item);
+    }
+}
+
+/// Insert `item` into the module at `path` under `module`, creating any missing
+/// modules.
+fn insert_item(module: &mut Mod, path: &[Ident], item: P<Item>, inline: bool, st: &CommandState) {
+    let (ident, rest) = match path.split_first() {
+        Some(x) => x,
+        None => {
+            module.items.push(item);
+            return;
+        }
+    };
+    let pos = module
+        .items
+        .iter()
+        .position(|i| i.ident == *ident && matches!([i.kind] ItemKind::Mod(..)));
+    let pos = pos.unwrap_or_else(|| {
+        let mut new_mod = mk().mod_(vec![]);
+        new_mod.inline = inline;
+        module.items.push(mk().pub_().id(st.next_node_id()).mod_item(*ident, new_mod));
+        module.items.len() - 1
+    });
+    let m = expect!([module.items[pos].kind] ItemKind::Mod(ref mut m) => m);
+    insert_item(m, rest, item, inline, st)
+}
+
+impl ReorganizeModules {
+    /// Decide where each header item goes.
+    fn plan(&self, krate: &Crate, cx: &RefactorCtxt) -> ModuleMoves {
+        let mut header_mods = vec![];
+        collect_header_mods(&krate.module.items, &mut vec![], &mut header_mods);
+        let headers = header_mods.iter().map(|(_, h, _)| h.clone()).collect();
+        let header_paths = header_module_paths(&headers);
+
+        // Header modules that are already at their destination come first, so
+        // their items stay where they are.
+        header_mods.sort_by_key(|(path, header, _)| *path != header_paths[header]);
+
+        let mut moves = ModuleMoves::default();
+        let mut kept: HashMap<(Vec<Ident>, Namespace, Ident), &Item> = HashMap::new();
+        for (mod_path, header, header_mod) in &header_mods {
+            let dest = &header_paths[header];
+            let in_place = dest == mod_path;
+            let existing = if in_place { None } else { find_module(&krate.module, dest) };
+            let m = expect!([header_mod.kind] ItemKind::Mod(ref m) => m);
+            for item in &m.items {
+                match item.kind {
+                    ItemKind::Use(..) | ItemKind::ForeignMod(..) | ItemKind::Impl(..) |
+                    ItemKind::Mac(..) | ItemKind::MacroDef(..) => continue,
+                    _ => {}
+                }
+                let ns = match_or!([cx.item_namespace(item)] Some(x) => x; continue);
+                match kept.entry((dest.clone(), ns, item.ident)) {
+                    Entry::Occupied(e) => {
+                        if !e.get().ast_equiv(item) {
+                            continue;
+                        }
+                        moves.dropped.insert(item.id);
+                    }
+                    Entry::Vacant(e) => {
+                        let conflict = existing.map_or(false, |existing| {
+                            existing.items.iter().any(|i| {
+                                i.ident == item.ident && cx.item_namespace(i) == Some(ns)
+                            })
+                        });
+                        if conflict {
+                            continue;
+                        }
+                        e.insert(item);
+                        if in_place {
+                            continue;
+                        }
+                        moves.dests.insert(item.id, dest.clone());
+                    }
+                }
+
+                let new_path = Path {
+                    span: DUMMY_SP,
+                    segments: iter::once(Ident::new(kw::Crate, DUMMY_SP))
+                        .chain(dest.iter().cloned())
+                        .chain(iter::once(item.ident))
+                        .map(PathSegment::from_ident)
+                        .collect(),
+                };
+                moves.new_paths.insert(cx.node_def_id(item.id), new_path.clone());
+                if !in_place {
+                    moves.reexports.entry(header_mod.id).or_default().push(new_path);
+                }
+            }
+        }
+        moves
+    }
+}
+
+impl Transform for ReorganizeModules {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let moves = self.plan(krate, cx);
+
+        // Relative paths inside the moved items need to be made absolute.
+        let mut moved_ids = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            if moves.dests.contains_key(&i.id) {
+                visit_nodes(i, |e: &Expr| { moved_ids.insert(e.id); });
+                visit_nodes(i, |t: &Ty| { moved_ids.insert(t.id); });
+                visit_nodes(i, |p: &Pat| { moved_ids.insert(p.id); });
+                visit_nodes(i, |i: &Item| { moved_ids.insert(i.id); });
+            }
+        });
+        fold_resolved_paths_with_id(krate, cx, |id, qself, path, defs| {
+            if let Some(def_id) = defs[0].opt_def_id() {
+                if let Some(new_path) = moves.new_paths.get(&def_id) {
+                    return (qself, new_path.clone());
+                } else if moved_ids.contains(&id) && is_relative_path(&path) {
+                    return cx.def_qpath(def_id);
+                }
+            }
+            (qself, path)
+        });
+
+        let mut moved = vec![];
+        take_moved_items(&mut krate.module, &moves, &mut moved);
+        let inline = cx.is_executable();
+        for (dest, mut item) in moved {
+            item.vis.node = VisibilityKind::Public;
+            insert_item(&mut krate.module, &dest, item, inline, st);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
-    reg.register("reorganize_definitions", |_args| mk(ReorganizeDefinitions))
+    reg.register("reorganize_definitions", |_args| mk(ReorganizeDefinitions));
+    reg.register("reorganize_modules", |_args| mk(ReorganizeModules));
 }
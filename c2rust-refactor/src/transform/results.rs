@@ -0,0 +1,808 @@
+//! Conversion of C-style integer error codes to `Result`s.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
+use smallvec::{smallvec, SmallVec};
+use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, visit_nodes};
+use crate::ast_manip::output_exprs::fold_output_exprs;
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::transform::Transform;
+use crate::transform::funcs::is_result_ty;
+use crate::transform::heap::strip_casts;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+
+/// A function selected for conversion.
+struct RetcodeFn {
+    /// Name of the generated error enum.
+    enum_name: String,
+    /// The error variants, along with the original error code of each one.
+    variants: Vec<(String, P<Expr>)>,
+    /// Variant for each error code, keyed by the source text of the (uncast) code.
+    variant_of: HashMap<String, String>,
+    /// The function always returns 0 on success, so the `Ok` type is `()`.
+    unit_ok: bool,
+    /// The original return type.
+    ret_ty: P<Ty>,
+}
+
+/// Convert `SOME_NAME` or `some_name` to `SomeName`.
+pub fn camel_case(s: &str) -> String {
+    s.split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut cs = w.chars();
+            let first = cs.next().unwrap().to_ascii_uppercase();
+            let rest = cs.as_str().to_ascii_lowercase();
+            format!("{}{}", first, rest)
+        })
+        .collect()
+}
+
+/// Classification of a value returned by a converted function.
+enum RetValue {
+    /// An error code, with the name of its variant.
+    Error(String),
+    /// A success value, and whether it's a literal `0`.
+    Success(bool),
+}
+
+/// Classify a returned value `e`.  With no sentinel, negative constants are errors and
+/// non-negative integer literals are success values; other values can't be classified.  With a
+/// sentinel, only values written the same as the sentinel are errors.
+fn classify(e: &Expr, sentinel: Option<&str>) -> Option<RetValue> {
+    let e = strip_casts(e);
+    let src = pprust::expr_to_string(e);
+    let is_zero = match e.kind {
+        ExprKind::Lit(ref l) => matches!([l.kind] LitKind::Int(0, _)),
+        _ => false,
+    };
+
+    if let Some(sentinel) = sentinel {
+        if src == sentinel {
+            let name = match e.kind {
+                ExprKind::Path(None, ref path) => camel_case(&path.segments.last()?.ident.as_str()),
+                _ => "Error".to_owned(),
+            };
+            return Some(RetValue::Error(name));
+        }
+        return Some(RetValue::Success(is_zero));
+    }
+
+    match e.kind {
+        ExprKind::Lit(ref l) if matches!([l.kind] LitKind::Int(..)) =>
+            Some(RetValue::Success(is_zero)),
+        ExprKind::Unary(UnOp::Neg, ref inner) => {
+            let inner = strip_casts(inner);
+            match inner.kind {
+                ExprKind::Lit(ref l) => match l.kind {
+                    LitKind::Int(n, _) => Some(RetValue::Error(format!("Error{}", n))),
+                    _ => None,
+                },
+                ExprKind::Path(None, ref path) =>
+                    Some(RetValue::Error(camel_case(&path.segments.last()?.ident.as_str()))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The kinds of check that a caller can perform on an error code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Check {
+    IsErr,
+    IsOk,
+}
+
+/// If `e` compares the result of a call to a converted function against a constant in a way that
+/// checks whether the call failed, return the call and the kind of check.
+fn as_check<'a>(cx: &RefactorCtxt, fns: &HashMap<DefId, RetcodeFn>, sentinel: Option<&str>,
+                e: &'a Expr) -> Option<(&'a P<Expr>, Check)> {
+    let (op, a, b) = match_or!([e.kind] ExprKind::Binary(op, ref a, ref b) => (op.node, a, b);
+                               return None);
+    let is_call = |e: &Expr| cx.opt_callee(e).map_or(false, |did| fns.contains_key(&did));
+    // Normalize to `call OP other`.
+    let (call, op, other) = if is_call(a) {
+        (a, op, b)
+    } else if is_call(b) {
+        let op = match op {
+            BinOpKind::Lt => BinOpKind::Gt,
+            BinOpKind::Le => BinOpKind::Ge,
+            BinOpKind::Gt => BinOpKind::Lt,
+            BinOpKind::Ge => BinOpKind::Le,
+            op => op,
+        };
+        (b, op, a)
+    } else {
+        return None;
+    };
+    let info = &fns[&cx.opt_callee(call)?];
+    let other = pprust::expr_to_string(strip_casts(other));
+
+    let check = match (sentinel, op, &other as &str) {
+        (Some(s), BinOpKind::Eq, o) if o == s => Check::IsErr,
+        (Some(s), BinOpKind::Ne, o) if o == s => Check::IsOk,
+        (None, BinOpKind::Lt, "0") => Check::IsErr,
+        (None, BinOpKind::Ge, "0") => Check::IsOk,
+        (None, BinOpKind::Ne, "0") if info.unit_ok => Check::IsErr,
+        (None, BinOpKind::Eq, "0") if info.unit_ok => Check::IsOk,
+        _ => return None,
+    };
+    Some((call, check))
+}
+
+/// If `b` consists of a single `return Err(x)`, return `x`.
+fn as_err_return(b: &Block) -> Option<&P<Expr>> {
+    if b.stmts.len() != 1 {
+        return None;
+    }
+    let e = match_or!([b.stmts[0].kind] StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e;
+                      return None);
+    let ret = match_or!([e.kind] ExprKind::Ret(Some(ref r)) => r; return None);
+    as_err_value(ret)
+}
+
+/// If `e` is `Err(x)`, return `x`.
+fn as_err_value(e: &Expr) -> Option<&P<Expr>> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if args.len() != 1 || path.segments.len() != 1 || path.segments[0].ident.as_str() != "Err" {
+        return None;
+    }
+    Some(&args[0])
+}
+
+
+/// # `retcode_to_result` Command
+///
+/// Usage: `retcode_to_result [SENTINEL]`
+///
+/// Marks: `target`
+///
+/// For each function marked `target` that returns an integer error code,
+/// change its return type to `Result<T, E>`, where `E` is a new enum with one
+/// variant for each error code the function returns.  By default, negative
+/// constants like `-1` or `-EINVAL` are errors and non-negative integer
+/// literals are success values.  If `SENTINEL` is given, only returned values
+/// written exactly as `SENTINEL` (for example, `-1` or `ERR_FAIL`) are errors,
+/// and any other value is a success value.  `T` is `()` if the only success
+/// value is `0`, and the original return type otherwise.  Functions that return
+/// values that can't be classified are left unchanged.
+///
+/// The error enum is named after the function (`FooError` for `foo`), and has a
+/// `code` method that returns the original error code.
+///
+/// At call sites, checks like `foo() < 0` and `foo() != 0` (or `foo() ==
+/// SENTINEL`) become `foo().is_err()`, and the opposite checks become
+/// `foo().is_ok()`.  In converted functions, `if foo() < 0 { return ERR; }`
+/// becomes `foo().map_err(|_| ERR)?;`.  Other uses of the result are converted
+/// back to an integer with a `match`.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn parse(s: *const i8) -> c_int {
+///         if s.is_null() {
+///             return -EINVAL;
+///         }
+///         0
+///     }
+///
+///     unsafe fn load(s: *const i8) -> c_int {
+///         if parse(s) < 0 {
+///             return -1;
+///         }
+///         0
+///     }
+/// ```
+///
+/// After running `retcode_to_result`, with both functions marked:
+///
+/// ```ignore
+///     #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+///     enum ParseError {
+///         Einval,
+///     }
+///     impl ParseError {
+///         fn code(self) -> c_int {
+///             match self {
+///                 ParseError::Einval => -EINVAL,
+///             }
+///         }
+///     }
+///     unsafe fn parse(s: *const i8) -> Result<(), ParseError> {
+///         if s.is_null() {
+///             return Err(ParseError::Einval);
+///         }
+///         Ok(())
+///     }
+///
+///     // (similarly for `LoadError`)
+///     unsafe fn load(s: *const i8) -> Result<(), LoadError> {
+///         parse(s).map_err(|_| LoadError::Error1)?;
+///         Ok(())
+///     }
+/// ```
+pub struct RetcodeToResult {
+    pub sentinel: Option<String>,
+}
+
+impl Transform for RetcodeToResult {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let sentinel = self.sentinel.as_ref().map(|s| s as &str);
+
+        // (1) Find the marked functions, and classify the values they return.
+
+        let mut fns: HashMap<DefId, RetcodeFn> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            let (sig, block) = match_or!([i.kind] ItemKind::Fn(ref sig, _, ref b) => (sig, b);
+                                         return);
+            let ret_ty = match_or!([sig.decl.output] FunctionRetTy::Ty(ref ty) => ty.clone();
+                                   return);
+            let did = cx.node_def_id(i.id);
+            let out = cx.ty_ctxt().fn_sig(did).skip_binder().output();
+            if !matches!([out.kind] TcxTyKind::Int(_), TcxTyKind::Uint(_)) {
+                warn!("`{}` doesn't return an integer; skipping it", i.ident);
+                return;
+            }
+
+            let mut variants: Vec<(String, P<Expr>)> = Vec::new();
+            let mut variant_of = HashMap::new();
+            let mut unit_ok = true;
+            let mut ok = true;
+            let mut block = block.clone();
+            fold_output_exprs(&mut block, true, |e| {
+                match classify(e, sentinel) {
+                    Some(RetValue::Error(name)) => {
+                        let src = pprust::expr_to_string(strip_casts(e));
+                        if variant_of.contains_key(&src) {
+                            return;
+                        }
+                        // Different codes may map to the same name, for example `-1` and
+                        // `-1i32`.
+                        let mut name = name;
+                        while variants.iter().any(|&(ref v, _)| *v == name) {
+                            name.push('_');
+                        }
+                        variant_of.insert(src, name.clone());
+                        variants.push((name, e.clone()));
+                    }
+                    Some(RetValue::Success(is_zero)) => unit_ok &= is_zero,
+                    None => {
+                        warn!("can't classify return value `{}` of `{}`",
+                              pprust::expr_to_string(e), i.ident);
+                        ok = false;
+                    }
+                }
+            });
+            if !ok {
+                return;
+            }
+            if variants.is_empty() {
+                warn!("`{}` never returns an error; skipping it", i.ident);
+                return;
+            }
+
+            fns.insert(did, RetcodeFn {
+                enum_name: format!("{}Error", camel_case(&i.ident.as_str())),
+                variants,
+                variant_of,
+                unit_ok,
+                ret_ty,
+            });
+        });
+        if fns.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite the functions, and add the error enums.
+
+        FlatMapNodes::visit(krate, |i: P<Item>| {
+            if !matches!([i.kind] ItemKind::Fn(..)) {
+                return smallvec![i];
+            }
+            let info = match_or!([fns.get(&cx.node_def_id(i.id))] Some(x) => x;
+                                 return smallvec![i]);
+
+            let vis = pprust::vis_to_string(&i.vis);
+            let ret_ty = pprust::ty_to_string(&info.ret_ty);
+            let arms = info.variants.iter()
+                .map(|&(ref v, ref code)| format!("{}::{} => {},",
+                                                  info.enum_name, v, pprust::expr_to_string(code)))
+                .collect::<Vec<_>>();
+            let src = format!(
+                "#[derive(Clone, Copy, PartialEq, Eq, Debug)]\n\
+                 {vis}enum {name} {{ {variants} }}\n\
+                 impl {name} {{\n\
+                     {vis}fn code(self) -> {ret_ty} {{ match self {{ {arms} }} }}\n\
+                 }}",
+                vis = vis,
+                name = info.enum_name,
+                variants = info.variants.iter()
+                    .map(|&(ref v, _)| format!("{},", v))
+                    .collect::<String>(),
+                ret_ty = ret_ty,
+                arms = arms.join(" "),
+            );
+            let mut items = st.parse_items(cx, &src);
+
+            let ok_ty = if info.unit_ok { "()".to_owned() } else { ret_ty };
+            let err = parse_expr(cx.session(), "Err(__e)");
+            let ok = parse_expr(cx.session(), "Ok(__e)");
+            let new_ty = parse_ty(cx.session(),
+                                  &format!("Result<{}, {}>", ok_ty, info.enum_name));
+            let i = i.map(|mut i| {
+                expect!([i.kind] ItemKind::Fn(ref mut sig, _, ref mut block) => {
+                    sig.decl.output = FunctionRetTy::Ty(new_ty);
+                    fold_output_exprs(block, true, |e| {
+                        let mut bnd = Bindings::new();
+                        let tmpl = match classify(e, sentinel) {
+                            Some(RetValue::Error(_)) => {
+                                let src = pprust::expr_to_string(strip_casts(e));
+                                let path = format!("{}::{}", info.enum_name, info.variant_of[&src]);
+                                bnd.add("__e", parse_expr(cx.session(), &path));
+                                &err
+                            }
+                            _ => {
+                                let v = if info.unit_ok {
+                                    parse_expr(cx.session(), "()")
+                                } else {
+                                    e.clone()
+                                };
+                                bnd.add("__e", v);
+                                &ok
+                            }
+                        };
+                        *e = tmpl.clone().subst(st, cx, &bnd);
+                    });
+                });
+                i
+            });
+            items.push(i);
+            items.into_iter().collect()
+        });
+
+        // (3) Propagate errors from converted callees through `?` in converted callers.
+
+        let mut handled: HashSet<NodeId> = HashSet::new();
+        let map_err = parse_expr(cx.session(), "__c.map_err(|_| __e)?");
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let new_e = {
+                let e = match_or!([s.kind] StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e;
+                                  return smallvec![s]);
+                let (cond, then) = match_or!([e.kind] ExprKind::If(ref c, ref t, None) => (c, t);
+                                             return smallvec![s]);
+                let (call, check) = match_or!([as_check(cx, &fns, sentinel, cond)] Some(x) => x;
+                                              return smallvec![s]);
+                let err = match_or!([as_err_return(then)] Some(x) => x; return smallvec![s]);
+                if check != Check::IsErr {
+                    return smallvec![s];
+                }
+                handled.insert(call.id);
+                let mut bnd = Bindings::new();
+                bnd.add("__c", call.clone());
+                bnd.add("__e", err.clone());
+                map_err.clone().subst(st, cx, &bnd)
+            };
+            smallvec![Stmt {
+                kind: StmtKind::Semi(new_e),
+                .. s
+            }]
+        });
+
+        // (4) Rewrite checks on the results of converted functions, and convert other uses back
+        // to error codes.
+
+        // Calls whose results are discarded don't need any changes.
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                handled.insert(e.id);
+            }
+        });
+
+        let is_err = parse_expr(cx.session(), "__c.is_err()");
+        let is_ok = parse_expr(cx.session(), "__c.is_ok()");
+        let to_code = parse_expr(cx.session(), "match __c { Ok(v) => v, Err(e) => e.code() }");
+        let unit_to_code = parse_expr(cx.session(),
+                                      "match __c { Ok(()) => 0, Err(e) => e.code() }");
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some((call, check)) = as_check(cx, &fns, sentinel, e) {
+                if handled.contains(&call.id) {
+                    return;
+                }
+                handled.insert(call.id);
+                let mut bnd = Bindings::new();
+                bnd.add("__c", call.clone());
+                let tmpl = match check {
+                    Check::IsErr => &is_err,
+                    Check::IsOk => &is_ok,
+                };
+                *e = tmpl.clone().subst(st, cx, &bnd);
+                return;
+            }
+
+            if handled.contains(&e.id) || !matches!([e.kind] ExprKind::Call(..)) {
+                return;
+            }
+            let info = match_or!([cx.opt_callee(&e).and_then(|did| fns.get(&did))] Some(x) => x;
+                                 return);
+            handled.insert(e.id);
+            let mut bnd = Bindings::new();
+            bnd.add("__c", e.clone());
+            let tmpl = if info.unit_ok { &unit_to_code } else { &to_code };
+            *e = tmpl.clone().subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `err_returns_to_try` Command
+///
+/// Usage: `err_returns_to_try`
+///
+/// In functions that return a `Result`, replace explicit checks that return
+/// an error early with the `?` operator, and flatten the nesting that such
+/// checks produce.  This is meant to run after `retcode_to_result`, whose
+/// output still contains the conditionals that the translated C code used to
+/// check error codes.  The following forms are rewritten:
+///
+///  * `match x { Ok(v) => v, Err(e) => return Err(e) }` becomes `x?`, and
+///    `match x { Ok(v) => v, Err(e) => return Err(E) }` becomes
+///    `x.map_err(|e| E)?`.
+///
+///  * `if let Err(e) = x { return Err(E); }` becomes `x.map_err(|e| E)?;`,
+///    or `x?;` if `E` is just `e`.
+///
+///  * `if x.is_err() { return Err(E); }` becomes `x.map_err(|_| E)?;`.
+///
+///  * `if x.is_ok() { BODY } else { return Err(E); }`, and the same check
+///    written with `is_err` or `if let Ok(v) = x`, becomes
+///    `x.map_err(|_| E)?;` (or `let v = ...?;`) followed by the statements of
+///    `BODY`, one level out.
+///
+/// A check is left alone if `E` contains `return`, `break`, `continue`, or
+/// `?`, which would behave differently inside the `map_err` closure.  A body
+/// is only moved out of its `if` when that can't change which variables are
+/// in scope: either the `if` is the last statement of its block, or neither
+/// the body nor the `if let` declares any variables.  Code inside closures is not changed.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn init(s: *const i8) -> Result<(), InitError> {
+///         if parse(s).is_ok() {
+///             if let Err(e) = load(s) {
+///                 return Err(e);
+///             }
+///             Ok(())
+///         } else {
+///             return Err(InitError::Error1);
+///         }
+///     }
+/// ```
+///
+/// After running `err_returns_to_try`:
+///
+/// ```ignore
+///     unsafe fn init(s: *const i8) -> Result<(), InitError> {
+///         parse(s).map_err(|_| InitError::Error1)?;
+///         load(s)?;
+///         Ok(())
+///     }
+/// ```
+pub struct ErrReturnsToTry;
+
+/// Check if `e` exits early from the enclosing function or loop.
+fn has_early_exit(e: &Expr) -> bool {
+    let mut found = false;
+    visit_nodes(e, |e: &Expr| match e.kind {
+        ExprKind::Ret(..) | ExprKind::Break(..) | ExprKind::Continue(..) |
+        ExprKind::Try(..) | ExprKind::Mac(..) => found = true,
+        _ => {}
+    });
+    found
+}
+
+/// If `p` is `VARIANT(inner)`, return `inner`.
+fn variant_pat<'a>(p: &'a Pat, variant: &str) -> Option<&'a P<Pat>> {
+    let (path, pats) = match_or!([p.kind] PatKind::TupleStruct(ref path, ref pats) => (path, pats);
+                                 return None);
+    if path.segments.len() != 1 || path.segments[0].ident.as_str() != variant ||
+       pats.len() != 1 {
+        return None;
+    }
+    Some(&pats[0])
+}
+
+/// Check if `p` is a pattern that always matches: a plain binding or `_`.
+fn is_simple_pat(p: &Pat) -> bool {
+    match p.kind {
+        PatKind::Ident(_, _, None) | PatKind::Wild => true,
+        _ => false,
+    }
+}
+
+/// If `e` is the block `{ BODY }`, return `BODY`.
+fn as_plain_block(e: &Expr) -> Option<&P<Block>> {
+    match e.kind {
+        ExprKind::Block(ref b, None) if b.rules == BlockCheckMode::Default => Some(b),
+        _ => None,
+    }
+}
+
+/// If `e` is `x.METHOD()`, return `x`.
+fn method_receiver<'a>(e: &'a Expr, method: &str) -> Option<&'a P<Expr>> {
+    let (seg, args) = match_or!([e.kind] ExprKind::MethodCall(ref seg, ref args) => (seg, args);
+                                return None);
+    if seg.ident.as_str() != method || seg.args.is_some() || args.len() != 1 {
+        return None;
+    }
+    Some(&args[0])
+}
+
+/// A check that returns an error early, found in the statement `if COND { THEN } else { ELSE }`.
+struct EarlyErr<'a> {
+    /// The checked `Result`.
+    result: &'a P<Expr>,
+    /// The pattern for the error value passed to the `map_err` closure.
+    err_pat: Option<&'a P<Pat>>,
+    /// The error that's returned.
+    err: &'a P<Expr>,
+    /// The pattern for the success value, if the `if let` binds it.
+    ok_pat: Option<&'a P<Pat>>,
+    /// The code that runs when there's no error.
+    body: Option<&'a P<Block>>,
+}
+
+struct TryFolder<'a, 'b, 'tcx> {
+    st: &'a CommandState,
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    /// We're in a function that returns a `Result`.
+    in_result_fn: bool,
+}
+
+impl<'a, 'b, 'tcx> TryFolder<'a, 'b, 'tcx> {
+    fn is_result(&self, e: &Expr) -> bool {
+        let ty = match_or!([self.cx.opt_node_type(e.id)] Some(x) => x; return false);
+        match ty.kind {
+            TcxTyKind::Adt(adt, _) => {
+                let path = self.cx.ty_ctxt().def_path_str(adt.did);
+                path == "std::result::Result" || path == "core::result::Result"
+            }
+            _ => false,
+        }
+    }
+
+    /// Build `result.map_err(|err_pat| err)?`, or `result?` if the closure would be the identity.
+    fn build_try(&self, result: &P<Expr>, err_pat: Option<&P<Pat>>, err: &P<Expr>) -> P<Expr> {
+        let pat = err_pat.map_or_else(|| "_".to_owned(), |p| pprust::pat_to_string(p));
+        let src = match err_pat.map(|p| &p.kind) {
+            Some(&PatKind::Ident(BindingMode::ByValue(_), ident, None))
+                if pprust::expr_to_string(err) == ident.to_string() => "__r?".to_owned(),
+            _ => format!("__r.map_err(|{}| __e)?", pat),
+        };
+        let mut bnd = Bindings::new();
+        bnd.add("__r", result.clone());
+        bnd.add("__e", err.clone());
+        parse_expr(self.cx.session(), &src).subst(self.st, self.cx, &bnd)
+    }
+
+    /// Rewrite `match x { Ok(v) => v, Err(e) => return Err(E) }`.
+    fn convert_match(&self, e: &Expr) -> Option<P<Expr>> {
+        let (result, arms) = match_or!([e.kind] ExprKind::Match(ref r, ref a) => (r, a);
+                                       return None);
+        if arms.len() != 2 || arms.iter().any(|a| a.guard.is_some()) || !self.is_result(result) {
+            return None;
+        }
+        let ok_pat = variant_pat(&arms[0].pat, "Ok")?;
+        let err_pat = variant_pat(&arms[1].pat, "Err")?;
+        let ok_name = match_or!([ok_pat.kind] PatKind::Ident(BindingMode::ByValue(_), i, None) => i;
+                                return None);
+        if !is_simple_pat(err_pat) || pprust::expr_to_string(&arms[0].body) != ok_name.to_string() {
+            return None;
+        }
+        let ret = match_or!([arms[1].body.kind] ExprKind::Ret(Some(ref r)) => r; return None);
+        let err = as_err_value(ret)?;
+        if has_early_exit(err) {
+            return None;
+        }
+        Some(self.build_try(result, Some(err_pat), err))
+    }
+
+    /// Match an `if` statement that checks for an error and returns early.
+    fn as_early_err<'e>(&self, e: &'e Expr) -> Option<EarlyErr<'e>> {
+        let (cond, then, els) = match_or!([e.kind] ExprKind::If(ref c, ref t, ref e) => (c, t, e);
+                                          return None);
+        let els = match *els {
+            Some(ref e) => Some(as_plain_block(e)?),
+            None => None,
+        };
+
+        let found = if let ExprKind::Let(ref pat, ref result) = cond.kind {
+            if let Some(err_pat) = variant_pat(pat, "Err") {
+                // `if let Err(e) = x { return Err(E); }`
+                if els.is_some() || !is_simple_pat(err_pat) {
+                    return None;
+                }
+                EarlyErr { result, err_pat: Some(err_pat), err: as_err_return(then)?,
+                           ok_pat: None, body: None }
+            } else {
+                // `if let Ok(v) = x { BODY } else { return Err(E); }`
+                let ok_pat = variant_pat(pat, "Ok")?;
+                if !is_simple_pat(ok_pat) {
+                    return None;
+                }
+                EarlyErr { result, err_pat: None, err: as_err_return(els?)?,
+                           ok_pat: Some(ok_pat), body: Some(then) }
+            }
+        } else if let Some(result) = method_receiver(cond, "is_err") {
+            // `if x.is_err() { return Err(E); } [else { BODY }]`
+            EarlyErr { result, err_pat: None, err: as_err_return(then)?,
+                       ok_pat: None, body: els }
+        } else if let Some(result) = method_receiver(cond, "is_ok") {
+            // `if x.is_ok() { BODY } else { return Err(E); }`
+            EarlyErr { result, err_pat: None, err: as_err_return(els?)?,
+                       ok_pat: None, body: Some(then) }
+        } else {
+            return None;
+        };
+
+        if !self.is_result(found.result) || has_early_exit(found.err) {
+            return None;
+        }
+        Some(found)
+    }
+
+    /// Rewrite the early error checks among the statements of `b`.
+    fn convert_block(&self, b: &mut Block) {
+        let old_stmts = mem::replace(&mut b.stmts, Vec::new());
+        let len = old_stmts.len();
+        for (idx, s) in old_stmts.into_iter().enumerate() {
+            let is_last = idx + 1 == len;
+            match self.convert_stmt(&s, is_last) {
+                Some(stmts) => b.stmts.extend(stmts),
+                None => b.stmts.push(s),
+            }
+        }
+    }
+
+    fn convert_stmt(&self, s: &Stmt, is_last: bool) -> Option<Vec<Stmt>> {
+        let e = match_or!([s.kind] StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => e;
+                          return None);
+        let found = self.as_early_err(e)?;
+
+        let body = found.body.map_or(&[] as &[Stmt], |b| &b.stmts[..]);
+        // Moving the body out of the `if` keeps its variables in scope for the rest of the
+        // enclosing block, so it's only safe if there aren't any later statements.
+        let tail = is_last && matches!([s.kind] StmtKind::Expr(..));
+        if !is_last && (found.ok_pat.is_some() ||
+                        body.iter().any(|s| matches!([s.kind] StmtKind::Local(..),
+                                                              StmtKind::Mac(..)))) {
+            return None;
+        }
+        if body.iter().any(|s| matches!([s.kind] StmtKind::Item(..))) {
+            return None;
+        }
+
+        let check = self.build_try(found.result, found.err_pat, found.err);
+        let mut stmts = Vec::with_capacity(body.len() + 1);
+        match found.ok_pat {
+            Some(pat) => {
+                let local = mk().local(pat.clone(), None as Option<P<Ty>>, Some(check));
+                stmts.push(mk().local_stmt(P(local)));
+            }
+            None => stmts.push(Stmt { kind: StmtKind::Semi(check), .. s.clone() }),
+        }
+        for (idx, s) in body.iter().enumerate() {
+            let mut s = s.clone();
+            // A trailing expression only stays one if the `if` was the trailing expression too.
+            if idx + 1 == body.len() && !tail {
+                s.kind = match s.kind {
+                    StmtKind::Expr(e) => StmtKind::Semi(e),
+                    k => k,
+                };
+            }
+            stmts.push(s);
+        }
+        Some(stmts)
+    }
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for TryFolder<'a, 'b, 'tcx> {
+    fn flat_map_item(&mut self, i: P<Item>) -> SmallVec<[P<Item>; 1]> {
+        let old = self.in_result_fn;
+        self.in_result_fn = match i.kind {
+            ItemKind::Fn(ref sig, ..) => match sig.decl.output {
+                FunctionRetTy::Ty(ref ty) => is_result_ty(ty),
+                FunctionRetTy::Default(..) => false,
+            },
+            _ => false,
+        };
+        let r = mut_visit::noop_flat_map_item(i, self);
+        self.in_result_fn = old;
+        r
+    }
+
+    fn flat_map_impl_item(&mut self, i: ImplItem) -> SmallVec<[ImplItem; 1]> {
+        let old = self.in_result_fn;
+        self.in_result_fn = match i.kind {
+            ImplItemKind::Method(ref sig, _) => match sig.decl.output {
+                FunctionRetTy::Ty(ref ty) => is_result_ty(ty),
+                FunctionRetTy::Default(..) => false,
+            },
+            _ => false,
+        };
+        let r = mut_visit::noop_flat_map_impl_item(i, self);
+        self.in_result_fn = old;
+        r
+    }
+
+    fn visit_expr(&mut self, e: &mut P<Expr>) {
+        // `?` in a closure or async block returns from that instead of from the function.
+        if matches!([e.kind] ExprKind::Closure(..), ExprKind::Async(..), ExprKind::TryBlock(..)) {
+            let old = mem::replace(&mut self.in_result_fn, false);
+            mut_visit::noop_visit_expr(e, self);
+            self.in_result_fn = old;
+            return;
+        }
+
+        mut_visit::noop_visit_expr(e, self);
+        if !self.in_result_fn {
+            return;
+        }
+        if let Some(new_e) = self.convert_match(e) {
+            *e = new_e;
+        }
+    }
+
+    fn visit_block(&mut self, b: &mut P<Block>) {
+        // Convert inner blocks first, so that the bodies of nested checks are already flat when
+        // they're moved out.
+        mut_visit::noop_visit_block(b, self);
+        if self.in_result_fn {
+            self.convert_block(b);
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for ErrReturnsToTry {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        krate.visit(&mut TryFolder { st, cx, in_result_fn: false });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("retcode_to_result", |args| mk(RetcodeToResult {
+        sentinel: args.get(0).cloned(),
+    }));
+    reg.register("err_returns_to_try", |_args| mk(ErrReturnsToTry));
+}
@@ -1,10 +1,13 @@
-use syntax::ast::Crate;
+use std::fs;
+use syntax::ast::{Crate, Expr, Ty};
+use syntax::ptr::P;
 use syntax::symbol::Symbol;
+use toml::Value;
 
-use crate::command::{CommandState, Registry};
+use crate::command::{Command, CommandState, RefactorState, Registry, TypeckLoopResult};
 use crate::contains_mark::contains_mark;
 use crate::driver::Phase;
-use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with};
+use crate::matcher::{BindingType, MatchCtxt, Pattern, Subst, mut_visit_match_with};
 use crate::transform::Transform;
 use c2rust_ast_builder::IntoSymbol;
 use crate::RefactorCtxt;
@@ -151,6 +154,257 @@ impl Transform for RewriteStmts {
 }
 
 
+/// # `rewrite_templates` Command
+///
+/// Usage: `rewrite_templates FILE`
+///
+/// Marks: may read marks depending on the patterns in `FILE`
+///
+/// Apply the rewrite templates in `FILE` repeatedly, until none of them matches anywhere in the
+/// crate.  `FILE` is a TOML file containing an array of `template` tables:
+///
+/// ```toml
+/// [[template]]
+/// name = "free_to_drop"
+/// kind = "stmts"
+/// pattern = "$f($p as *mut ::libc::c_void); $p = ::std::ptr::null_mut();"
+/// replacement = "drop(::std::boxed::Box::from_raw($p)); $p = ::std::ptr::null_mut();"
+/// where = ["$f = def!(::libc::free)", "$p: *mut __t"]
+/// ```
+///
+/// Each template has the following keys:
+///
+///  * `kind`: the kind of AST the pattern matches: `expr` (the default), `ty`, or `stmts`.
+///    `stmts` patterns match consecutive statements within a block.
+///  * `pattern` and `replacement`: the pattern and its replacement, as in `rewrite_expr`,
+///    `rewrite_ty`, and `rewrite_stmts`.  Metavariables can be restricted to a kind of node
+///    using the `$x:Expr` syntax described in the `matcher` module.
+///  * `where`: guards that must hold for a match to be rewritten.  Each guard has one of the
+///    following forms:
+///     * `$x = PAT`: the node captured as `$x` must match `PAT`.  `PAT` can use any of the
+///       matcher's special forms, such as `def!(path)` to check what `$x` resolves to or
+///       `marked!(__y, label)` to check its marks.  Metavariables bound by `PAT` can be used in
+///       `replacement`.
+///     * `$x != PAT`: the node captured as `$x` must not match `PAT`.
+///     * `$x: TY`: the resolved type of `$x` must match the type pattern `TY`.  This is
+///       shorthand for `$x = typed!($x, TY)`.
+///  * `name`: a name for the template, used in log messages.
+///
+/// The templates are applied in order, and then the crate is typechecked again, so that
+/// guards can inspect the types and definitions of rewritten code.  This repeats until a pass
+/// makes no changes, or for at most 64 passes.
+pub struct RewriteTemplates {
+    pub path: String,
+}
+
+/// Maximum number of passes `rewrite_templates` makes while looking for a fixpoint.
+const MAX_TEMPLATE_PASSES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TemplateKind {
+    Expr,
+    Ty,
+    Stmts,
+}
+
+/// A `where` guard: the node captured as `var` must match `pat`, or must not match it if
+/// `negate` is set.
+struct Guard {
+    var: Symbol,
+    negate: bool,
+    pat: String,
+}
+
+struct Template {
+    name: String,
+    kind: TemplateKind,
+    pattern: String,
+    replacement: String,
+    guards: Vec<Guard>,
+}
+
+fn parse_guard(src: &str) -> Result<Guard, String> {
+    let src = src.trim();
+    if !src.starts_with('$') {
+        return Err(format!("guard `{}` must start with a metavariable", src));
+    }
+    let end = src[1..].find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(src.len(), |i| i + 1);
+    let var = &src[..end];
+    let rest = src[end..].trim_start();
+    let (negate, pat) = if rest.starts_with("!=") {
+        (true, rest[2..].trim().to_owned())
+    } else if rest.starts_with('=') {
+        (false, rest[1..].trim().to_owned())
+    } else if rest.starts_with(':') {
+        (false, format!("typed!({}, {})", var, rest[1..].trim()))
+    } else {
+        return Err(format!("guard `{}`: expected `=`, `!=`, or `:` after `{}`", src, var));
+    };
+    if var.len() == 1 || pat.is_empty() {
+        return Err(format!("guard `{}` is incomplete", src));
+    }
+    Ok(Guard { var: var.into_symbol(), negate, pat })
+}
+
+fn parse_template(v: &Value, i: usize) -> Result<Template, String> {
+    let table = v.as_table()
+        .ok_or_else(|| format!("template {}: expected a table", i + 1))?;
+    for key in table.keys() {
+        if !["name", "kind", "pattern", "replacement", "where"].contains(&(key as &str)) {
+            return Err(format!("template {}: unknown key `{}`", i + 1, key));
+        }
+    }
+
+    let get_str = |key: &str| match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("template {}: `{}` must be a string", i + 1, key)),
+    };
+    let name = get_str("name")?.unwrap_or_else(|| format!("template {}", i + 1));
+    let kind = match get_str("kind")?.as_ref().map(|s| s as &str) {
+        None | Some("expr") => TemplateKind::Expr,
+        Some("ty") => TemplateKind::Ty,
+        Some("stmts") => TemplateKind::Stmts,
+        Some(k) => return Err(format!("{}: unknown kind `{}`", name, k)),
+    };
+    let pattern = get_str("pattern")?
+        .ok_or_else(|| format!("{}: missing `pattern`", name))?;
+    let replacement = get_str("replacement")?
+        .ok_or_else(|| format!("{}: missing `replacement`", name))?;
+    let guards = match table.get("where") {
+        None => Vec::new(),
+        Some(Value::Array(guards)) => guards.iter()
+            .map(|g| match g {
+                Value::String(s) => parse_guard(s).map_err(|e| format!("{}: {}", name, e)),
+                _ => Err(format!("{}: `where` must contain only strings", name)),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err(format!("{}: `where` must be an array", name)),
+    };
+
+    Ok(Template { name, kind, pattern, replacement, guards })
+}
+
+fn load_templates(path: &str) -> Result<Vec<Template>, String> {
+    let src = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let v = src.parse::<Value>()
+        .map_err(|e| format!("failed to parse {}: {}", path, e))?;
+    match v.get("template") {
+        Some(Value::Array(ts)) => {
+            ts.iter().enumerate().map(|(i, v)| parse_template(v, i)).collect()
+        }
+        Some(_) => Err(format!("{}: `template` must be an array of tables", path)),
+        None => Err(format!("{}: no templates found", path)),
+    }
+}
+
+/// Check the `where` guards of a template against a match.  Returns the match context extended
+/// with any bindings made by the guards, or `None` if a guard fails.
+fn check_guards<'a, 'tcx>(
+    mut mcx: MatchCtxt<'a, 'tcx>,
+    guards: &[Guard],
+) -> Option<MatchCtxt<'a, 'tcx>> {
+    for g in guards {
+        let result = match mcx.bindings.get_type(g.var) {
+            Some(BindingType::Expr) => {
+                let target = mcx.bindings.get::<_, P<Expr>>(g.var).unwrap().clone();
+                let pat = mcx.parse_expr(&g.pat);
+                mcx.clone_match(&pat, &target)
+            }
+            Some(BindingType::Ty) => {
+                let target = mcx.bindings.get::<_, P<Ty>>(g.var).unwrap().clone();
+                let pat = mcx.parse_ty(&g.pat);
+                mcx.clone_match(&pat, &target)
+            }
+            Some(ty) => panic!("guard on {}: can't check a metavariable of type {:?}", g.var, ty),
+            None => panic!("guard on {}: the pattern doesn't bind {}", g.var, g.var),
+        };
+        match (result, g.negate) {
+            (Ok(m), false) => mcx = m,
+            (Err(_), true) => {}
+            _ => return None,
+        }
+    }
+    Some(mcx)
+}
+
+impl Template {
+    /// Rewrite every match of this template in `krate`.  Returns the number of rewrites.
+    fn apply(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) -> usize {
+        let mut mcx = MatchCtxt::new(st, cx);
+        match self.kind {
+            TemplateKind::Expr => {
+                let pat = mcx.parse_expr(&self.pattern);
+                let repl = mcx.parse_expr(&self.replacement);
+                self.rewrite_matches(mcx, pat, repl, krate, st, cx)
+            }
+            TemplateKind::Ty => {
+                let pat = mcx.parse_ty(&self.pattern);
+                let repl = mcx.parse_ty(&self.replacement);
+                self.rewrite_matches(mcx, pat, repl, krate, st, cx)
+            }
+            TemplateKind::Stmts => {
+                let pat = mcx.parse_stmts(&self.pattern);
+                let repl = mcx.parse_stmts(&self.replacement);
+                self.rewrite_matches(mcx, pat, repl, krate, st, cx)
+            }
+        }
+    }
+
+    fn rewrite_matches<'a, 'tcx, T>(
+        &self,
+        mcx: MatchCtxt<'a, 'tcx>,
+        pat: T,
+        repl: T,
+        krate: &mut Crate,
+        st: &CommandState,
+        cx: &RefactorCtxt,
+    ) -> usize
+    where
+        T: Pattern<T> + Subst + Clone,
+    {
+        let mut count = 0;
+        mut_visit_match_with(mcx, pat, krate, |ast, mcx| {
+            if let Some(mcx) = check_guards(mcx, &self.guards) {
+                *ast = repl.clone().subst(st, cx, &mcx.bindings);
+                count += 1;
+            }
+        });
+        count
+    }
+}
+
+impl Command for RewriteTemplates {
+    fn run(&mut self, state: &mut RefactorState) {
+        let templates = load_templates(&self.path).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut pass = 0;
+        state.run_typeck_loop(|krate, st, cx| {
+            pass += 1;
+            let mut total = 0;
+            for t in &templates {
+                let n = t.apply(krate, st, cx);
+                if n > 0 {
+                    info!("rewrite_templates pass {}: {} rewrote {} node(s)", pass, t.name, n);
+                }
+                total += n;
+            }
+
+            if total == 0 {
+                TypeckLoopResult::Finished
+            } else if pass == MAX_TEMPLATE_PASSES {
+                warn!("rewrite_templates: still rewriting after {} passes; stopping", pass);
+                TypeckLoopResult::Finished
+            } else {
+                TypeckLoopResult::Iterate
+            }
+        }).expect("Failed to apply rewrite templates");
+    }
+}
+
+
 pub struct DebugMatchExpr {
     pub pat: String,
 }
@@ -190,6 +444,9 @@ pub fn register_commands(reg: &mut Registry) {
         pat: args[0].clone(),
         repl: args[1].clone(),
     }));
+    reg.register("rewrite_templates", |args| Box::new(RewriteTemplates {
+        path: args[0].clone(),
+    }));
 
     reg.register("debug_match_expr", |args| mk(DebugMatchExpr {
         pat: args[0].clone(),
@@ -0,0 +1,261 @@
+//! Conversion of `signal` and `sigaction` handlers to `signal-hook`.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts};
+use crate::matcher::{Bindings, Subst};
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// If `e` is `Some(f as unsafe extern "C" fn(c_int))`, the transpiled form of a handler function,
+/// get `f`.
+fn as_handler(e: &Expr) -> Option<&Expr> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "Some") || args.len() != 1 {
+        return None;
+    }
+    let f = strip_casts(&args[0]);
+    match_or!([f.kind] ExprKind::Path(None, _) => Some(f); None)
+}
+
+/// If `e` sets the handler field of a local `struct sigaction`, as in
+/// `act.__sigaction_handler.sa_handler = Some(f)`, get the local's name and `f`.
+fn as_sa_handler_assign(e: &Expr) -> Option<(Ident, &Expr)> {
+    let (lhs, rhs) = match_or!([e.kind] ExprKind::Assign(ref l, ref r) => (l, r); return None);
+    let (mut base, field) = match_or!([lhs.kind] ExprKind::Field(ref b, f) => (b, f);
+                                      return None);
+    if !matches!([&*field.as_str()] "sa_handler", "__sa_handler") {
+        return None;
+    }
+    // glibc and macOS wrap the handler in a union field.
+    if let ExprKind::Field(ref b, _) = base.kind {
+        base = b;
+    }
+    let path = match_or!([base.kind] ExprKind::Path(None, ref p) => p; return None);
+    if path.segments.len() != 1 {
+        return None;
+    }
+    Some((path.segments[0].ident, as_handler(rhs)?))
+}
+
+/// Check if the signal number `e` can be evaluated twice.
+fn is_simple_signal(e: &Expr) -> bool {
+    matches!([strip_casts(e).kind] ExprKind::Lit(..), ExprKind::Path(None, _))
+}
+
+/// If `e` is `signal(SIG, Some(f))`, or `sigaction(SIG, &act, NULL)` for a local `act` whose
+/// handler is in `sa_handlers`, get `SIG` and `f`.
+fn as_registration<'a>(e: &'a Expr, sa_handlers: &HashMap<Ident, &'a Expr>)
+                       -> Option<(&'a Expr, &'a Expr)> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let handler = if is_call_to(e, "signal") && args.len() == 2 {
+        as_handler(&args[1])?
+    } else if is_call_to(e, "sigaction") && args.len() == 3 && is_null_ptr(&args[2]) {
+        let act = match_or!([strip_casts(&args[1]).kind]
+                            ExprKind::AddrOf(_, _, ref act) => act; return None);
+        let path = match_or!([act.kind] ExprKind::Path(None, ref p) => p; return None);
+        if path.segments.len() != 1 {
+            return None;
+        }
+        *sa_handlers.get(&path.segments[0].ident)?
+    } else {
+        return None;
+    };
+    if !is_simple_signal(&args[0]) {
+        return None;
+    }
+    Some((&args[0], handler))
+}
+
+/// If `e` is `read_volatile(&x)` or `write_volatile(&mut x, v)`, get `x` and `v`.
+fn as_volatile_access(e: &Expr) -> Option<(&P<Expr>, Option<&P<Expr>>)> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let value = if is_call_to(e, "read_volatile") && args.len() == 1 {
+        None
+    } else if is_call_to(e, "write_volatile") && args.len() == 2 {
+        Some(&args[1])
+    } else {
+        return None;
+    };
+    let place = match_or!([strip_casts(&args[0]).kind]
+                          ExprKind::AddrOf(_, _, ref place) => place; return None);
+    Some((place, value))
+}
+
+
+/// # `signal_to_signal_hook` Command
+///
+/// Usage: `signal_to_signal_hook`
+///
+/// Register signal handlers with `signal-hook` instead of `signal` or
+/// `sigaction`.  A statement `signal(SIG, Some(handler))`, or
+/// `sigaction(SIG, &act, NULL)` where `act.sa_handler` was set to
+/// `Some(handler)` earlier in the same block, becomes
+/// `let _ = ::signal_hook::low_level::register(SIG, || unsafe { handler(SIG) });`.
+/// The handler function itself is unchanged, and `signal-hook` still calls it
+/// from inside the signal handler, so it must only do async-signal-safe work,
+/// as in C.  `sa_mask` and `sa_flags` are ignored: `signal-hook` always
+/// restarts interrupted system calls, and handlers that take a `siginfo_t`
+/// aren't converted.
+///
+/// The `static mut`s that converted handlers assign to, usually
+/// `volatile sig_atomic_t` flags, are marked `target` and `atomic`, and their
+/// volatile reads and writes become plain ones.  Running `fix_static_mut`
+/// afterward turns them into atomics, whose `SeqCst` operations replace the
+/// guarantees of `volatile`.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut got_signal: sig_atomic_t = 0;
+///
+///     unsafe extern "C" fn on_signal(sig: libc::c_int) {
+///         ::std::ptr::write_volatile(&mut got_signal as *mut sig_atomic_t, 1);
+///     }
+///
+///     signal(2 as libc::c_int, Some(on_signal as unsafe extern "C" fn(libc::c_int) -> ()));
+///     while ::std::ptr::read_volatile::<sig_atomic_t>(&got_signal as *const sig_atomic_t) == 0
+///     {
+///         pause();
+///     }
+/// ```
+///
+/// After running `signal_to_signal_hook` and `fix_static_mut`:
+///
+/// ```ignore
+///     static got_signal: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+///
+///     unsafe extern "C" fn on_signal(sig: libc::c_int) {
+///         got_signal.store(1, ::std::sync::atomic::Ordering::SeqCst);
+///     }
+///
+///     let _ = ::signal_hook::low_level::register(2 as libc::c_int,
+///                                                || unsafe { on_signal(2 as libc::c_int) });
+///     while got_signal.load(::std::sync::atomic::Ordering::SeqCst) == 0 {
+///         pause();
+///     }
+/// ```
+pub struct SignalToSignalHook;
+
+impl Transform for SignalToSignalHook {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Rewrite the registrations, and collect the handlers.
+
+        let mut handlers = HashSet::new();
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut new_stmts = Vec::with_capacity(b.stmts.len());
+            {
+                let mut sa_handlers = HashMap::new();
+                for s in &b.stmts {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e;
+                                      { new_stmts.push(s.clone()); continue });
+                    if let Some((act, handler)) = as_sa_handler_assign(e) {
+                        sa_handlers.insert(act, handler);
+                    }
+                    let (sig, handler) = match as_registration(e, &sa_handlers) {
+                        Some(x) => x,
+                        None => {
+                            new_stmts.push(s.clone());
+                            continue;
+                        }
+                    };
+                    if let Some(did) = cx.try_resolve_expr(handler) {
+                        handlers.insert(did);
+                    }
+                    let sig = pprust::expr_to_string(sig);
+                    let src = format!(
+                        "let _ = ::signal_hook::low_level::register(\
+                             {0}, || unsafe {{ {1}({0}) }});",
+                        sig, pprust::expr_to_string(handler));
+                    new_stmts.push(parse_stmts(cx.session(), &src).lone());
+                }
+            }
+            b.stmts = new_stmts;
+        });
+        if handlers.is_empty() {
+            return;
+        }
+
+        // (2) Find the statics that the handlers write.
+
+        let mut statics = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Static(_, Mutability::Mutable, _) = i.kind {
+                statics.insert(cx.node_def_id(i.id), i.id);
+            }
+        });
+        let static_of = |e: &Expr| -> Option<DefId> {
+            if !matches!([e.kind] ExprKind::Path(..)) {
+                return None;
+            }
+            cx.try_resolve_expr(e).filter(|did| statics.contains_key(did))
+        };
+
+        let mut flags = HashSet::new();
+        visit_nodes(krate, |i: &Item| {
+            let block = match_or!([i.kind] ItemKind::Fn(_, _, ref block) => block; return);
+            if !handlers.contains(&cx.node_def_id(i.id)) {
+                return;
+            }
+            visit_nodes(&**block, |e: &Expr| {
+                let place = match e.kind {
+                    ExprKind::Assign(ref lhs, _) => lhs,
+                    _ => match as_volatile_access(e) {
+                        Some((place, Some(_))) => place,
+                        _ => return,
+                    },
+                };
+                if let Some(did) = static_of(place) {
+                    flags.insert(did);
+                }
+            });
+        });
+
+        // (3) Replace volatile accesses to the flags with plain ones, and mark the flags.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = {
+                let (place, value) = match_or!([as_volatile_access(e)] Some(x) => x; return);
+                if !static_of(place).map_or(false, |did| flags.contains(&did)) {
+                    return;
+                }
+                let mut bnd = Bindings::new();
+                bnd.add("__x", place.clone());
+                match value {
+                    Some(value) => {
+                        bnd.add("__e", value.clone());
+                        parse_expr(cx.session(), "__x = __e").subst(st, cx, &bnd)
+                    }
+                    None => place.clone(),
+                }
+            };
+            *e = new_e;
+        });
+
+        for did in flags {
+            let id = statics[&did];
+            st.add_mark(id, "target");
+            st.add_mark(id, "atomic");
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("signal_to_signal_hook", |_args| mk(SignalToSignalHook));
+}
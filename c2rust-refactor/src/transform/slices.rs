@@ -0,0 +1,1374 @@
+//! Transforms for replacing raw pointers into arrays with slices.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, ParamEnv, TyKind};
+use syntax::ast::*;
+use syntax::attr;
+use syntax::print::pprust;
+use syntax::ptr::P;
+use syntax::symbol::sym;
+use syntax_pos::{BytePos, Span, DUMMY_SP};
+
+use crate::analysis::ranges::ValueRanges;
+use crate::ast_manip::{visit_nodes, visit_nodes_post, AstEquiv, Comment, CommentStyle};
+use crate::ast_manip::MutVisitNodes;
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_items, parse_stmts, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::{as_int_lit, is_call_to, strip_casts};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+use c2rust_ast_builder::mk;
+
+
+/// A pointer argument being converted to a slice, along with its length argument.
+struct SliceArg {
+    ident: Ident,
+    mutbl: Mutability,
+    /// Original type of the length argument.
+    len_ty: P<Ty>,
+}
+
+/// Information about a function whose signature was changed.
+struct ModFn {
+    /// Index of each converted pointer argument, along with the index of its length argument, in
+    /// the original argument list, and the mutability of the pointer.
+    pairs: Vec<(usize, usize, Mutability)>,
+}
+
+/// If `e` is `*p.offset(i)` or `*p.add(i)`, return `p` and `i`.
+pub fn as_offset_deref(e: &Expr) -> Option<(&Expr, &Expr)> {
+    let inner = match_or!([e.kind] ExprKind::Unary(UnOp::Deref, ref inner) => inner; return None);
+    match inner.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 2 &&
+               matches!([&*seg.ident.as_str()] "offset", "add", "wrapping_offset") =>
+            Some((&args[0], strip_casts(&args[1]))),
+        _ => None,
+    }
+}
+
+
+/// # `ptr_len_to_slice` Command
+///
+/// Usage: `ptr_len_to_slice`
+///
+/// Marks: `target`, `len`
+///
+/// For each function argument marked `target` with a raw pointer type `*mut T`
+/// (or `*const T`), paired with an argument of the same function marked `len`
+/// giving the number of elements it points to, replace the pair with a single
+/// argument of type `&mut [T]` (or `&[T]`).  If a function has several marked
+/// pointers and lengths, they are paired up in order.
+///
+/// Inside the function, `*p.offset(i)`, `*p.add(i)` and `*p` become `p[i]` and
+/// `p[0]`, uses of the length argument become `p.len()`, and any other use of the
+/// pointer is converted back with `p.as_mut_ptr()` (or `p.as_ptr()`).
+///
+/// At each call site, the slice is built from the caller's pointer and length
+/// arguments with `slice::from_raw_parts_mut` (or `from_raw_parts`).  When the
+/// caller's pointer comes from `x.as_mut_ptr()` (or `x.as_ptr()`), the slice is
+/// built by indexing `x` instead.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn sum(p: *const i32, n: usize) -> i32 {
+///         let mut total = 0;
+///         for i in 0..n {
+///             total += *p.offset(i as isize);
+///         }
+///         total
+///     }
+///
+///     sum(arr.as_ptr(), 10);
+/// ```
+///
+/// After running `ptr_len_to_slice`, with `p` marked `target` and `n` marked `len`:
+///
+/// ```ignore
+///     unsafe fn sum(p: &[i32]) -> i32 {
+///         let mut total = 0;
+///         for i in 0..p.len() as usize {
+///             total += p[i as usize];
+///         }
+///         total
+///     }
+///
+///     sum(&arr[..10 as usize]);
+/// ```
+pub struct PtrLenToSlice;
+
+impl Transform for PtrLenToSlice {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Change function signatures.
+
+        let slice_ty_mut = parse_ty(cx.session(), "&mut [__t]");
+        let slice_ty = parse_ty(cx.session(), "&[__t]");
+
+        // Converted pointer arguments, by HirId.
+        let mut slice_args: HashMap<HirId, SliceArg> = HashMap::new();
+        // Removed length arguments, mapped to the HirId of the pointer argument they go with.
+        let mut len_args: HashMap<HirId, HirId> = HashMap::new();
+        let mut mod_fns: HashMap<DefId, ModFn> = HashMap::new();
+
+        mut_visit_fns(krate, |fl| {
+            let ptrs = fl.decl.inputs.iter().enumerate()
+                .filter(|&(_, arg)| st.marked(arg.id, "target"))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let lens = fl.decl.inputs.iter().enumerate()
+                .filter(|&(_, arg)| st.marked(arg.id, "len"))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if ptrs.is_empty() && lens.is_empty() {
+                return;
+            }
+            if ptrs.len() != lens.len() {
+                warn!("`{}` has {} marked pointers but {} marked lengths; skipping it",
+                      fl.ident, ptrs.len(), lens.len());
+                return;
+            }
+
+            let mut pairs = Vec::new();
+            for (&p, &n) in ptrs.iter().zip(lens.iter()) {
+                let arg = &fl.decl.inputs[p];
+                let mt = match cx.opt_node_type(arg.pat.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::RawPtr(mt)) => mt,
+                    _ => {
+                        warn!("argument `{}` of `{}` is not a raw pointer; skipping it",
+                              pprust::pat_to_string(&arg.pat), fl.ident);
+                        continue;
+                    }
+                };
+                let ident = match arg.pat.kind {
+                    PatKind::Ident(_, ident, None) => ident,
+                    _ => {
+                        warn!("argument `{}` of `{}` is not a simple binding; skipping it",
+                              pprust::pat_to_string(&arg.pat), fl.ident);
+                        continue;
+                    }
+                };
+                let ptr_hid = cx.hir_map().node_to_hir_id(arg.pat.id);
+                let len_arg = &fl.decl.inputs[n];
+                slice_args.insert(ptr_hid, SliceArg {
+                    ident,
+                    mutbl: mt.mutbl,
+                    len_ty: len_arg.ty.clone(),
+                });
+                len_args.insert(cx.hir_map().node_to_hir_id(len_arg.pat.id), ptr_hid);
+
+                let mut bnd = Bindings::new();
+                bnd.add("__t", reflect_tcx_ty(cx.ty_ctxt(), mt.ty));
+                let tmpl = match mt.mutbl {
+                    Mutability::Mutable => &slice_ty_mut,
+                    Mutability::Immutable => &slice_ty,
+                };
+                fl.decl.inputs[p].ty = tmpl.clone().subst(st, cx, &bnd);
+                pairs.push((p, n, mt.mutbl));
+            }
+
+            if pairs.is_empty() {
+                return;
+            }
+            let removed = pairs.iter().map(|&(_, n, _)| n).collect::<HashSet<_>>();
+            let mut i = 0;
+            fl.decl.inputs.retain(|_| {
+                i += 1;
+                !removed.contains(&(i - 1))
+            });
+            mod_fns.insert(cx.node_def_id(fl.id), ModFn { pairs });
+        });
+
+        if mod_fns.is_empty() {
+            return;
+        }
+
+        // (2) Rewrite call sites.
+
+        let from_raw_parts_mut = parse_expr(
+            cx.session(), "::std::slice::from_raw_parts_mut(__p, __n as usize)");
+        let from_raw_parts = parse_expr(
+            cx.session(), "::std::slice::from_raw_parts(__p, __n as usize)");
+        let subslice_mut = parse_expr(cx.session(), "&mut __p[..__n as usize]");
+        let subslice = parse_expr(cx.session(), "&__p[..__n as usize]");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let callee = match_or!([cx.opt_callee(&e)] Some(x) => x; return);
+            let mod_fn = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
+            let args: &mut Vec<P<Expr>> = match e.kind {
+                ExprKind::Call(_, ref mut args) => args,
+                _ => {
+                    warn!("can't rewrite non-call use of modified function `{}`",
+                          pprust::expr_to_string(e));
+                    return;
+                }
+            };
+
+            for &(p, n, mutbl) in &mod_fn.pairs {
+                let ptr = strip_casts(&args[p]);
+                let mut bnd = Bindings::new();
+                bnd.add("__n", args[n].clone());
+                let tmpl = match ptr.kind {
+                    ExprKind::MethodCall(ref seg, ref margs)
+                        if margs.len() == 1 &&
+                           matches!([&*seg.ident.as_str()] "as_ptr", "as_mut_ptr") => {
+                        bnd.add("__p", margs[0].clone());
+                        match mutbl {
+                            Mutability::Mutable => &subslice_mut,
+                            Mutability::Immutable => &subslice,
+                        }
+                    }
+                    _ => {
+                        bnd.add("__p", args[p].clone());
+                        match mutbl {
+                            Mutability::Mutable => &from_raw_parts_mut,
+                            Mutability::Immutable => &from_raw_parts,
+                        }
+                    }
+                };
+                args[p] = tmpl.clone().subst(st, cx, &bnd);
+            }
+
+            let removed = mod_fn.pairs.iter().map(|&(_, n, _)| n).collect::<HashSet<_>>();
+            let mut i = 0;
+            args.retain(|_| {
+                i += 1;
+                !removed.contains(&(i - 1))
+            });
+        });
+
+        // (3) Rewrite element accesses and other uses of the arguments.
+
+        rewrite_slice_arg_uses(krate, st, cx, &slice_args, &len_args);
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// Rewrite the uses of pointer arguments that were converted to slices (or arrays) by the
+/// enclosing functions' signature changes, along with the uses of their removed length arguments.
+fn rewrite_slice_arg_uses(krate: &mut Crate,
+                          st: &CommandState,
+                          cx: &RefactorCtxt,
+                          slice_args: &HashMap<HirId, SliceArg>,
+                          len_args: &HashMap<HirId, HirId>) {
+    // Rewrite element accesses.
+
+    let index = parse_expr(cx.session(), "__p[__i as usize]");
+    let zero = parse_expr(cx.session(), "0");
+    let mut handled: HashSet<NodeId> = HashSet::new();
+
+    MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+        let (ptr, idx) = match as_offset_deref(e) {
+            Some((ptr, idx)) => (ptr, idx),
+            None => match e.kind {
+                ExprKind::Unary(UnOp::Deref, ref inner) => (&**inner, &*zero),
+                _ => return,
+            },
+        };
+        let ptr = strip_casts(ptr);
+        let hid = match_or!([cx.try_resolve_expr_to_hid(ptr)] Some(x) => x; return);
+        if !slice_args.contains_key(&hid) {
+            return;
+        }
+        handled.insert(ptr.id);
+        let mut bnd = Bindings::new();
+        bnd.add("__p", P(ptr.clone()));
+        bnd.add("__i", P(idx.clone()));
+        let new_e = index.clone().subst(st, cx, &bnd);
+        *e = new_e;
+    });
+
+    // Rewrite other uses of the pointers and uses of the lengths.
+
+    let as_mut_ptr = parse_expr(cx.session(), "__p.as_mut_ptr()");
+    let as_ptr = parse_expr(cx.session(), "__p.as_ptr()");
+    let len = parse_expr(cx.session(), "__p.len() as __t");
+
+    fold_exprs_with_context(krate, |e, ectx| {
+        if handled.contains(&e.id) || !matches!([e.kind] ExprKind::Path(..)) {
+            return;
+        }
+        let hid = match_or!([cx.try_resolve_expr_to_hid(e)] Some(x) => x; return);
+        let mut bnd = Bindings::new();
+        let tmpl = if let Some(arg) = slice_args.get(&hid) {
+            bnd.add("__p", e.clone());
+            match arg.mutbl {
+                Mutability::Mutable => &as_mut_ptr,
+                Mutability::Immutable => &as_ptr,
+            }
+        } else if let Some(ptr_hid) = len_args.get(&hid) {
+            let arg = &slice_args[ptr_hid];
+            bnd.add("__p", parse_expr(cx.session(), &arg.ident.as_str()));
+            bnd.add("__t", arg.len_ty.clone());
+            &len
+        } else {
+            return;
+        };
+
+        match ectx {
+            lr_expr::Context::Rvalue => {
+                *e = tmpl.clone().subst(st, cx, &bnd);
+            }
+            _ => {
+                warn!("can't convert lvalue use of `{}`", pprust::expr_to_string(e));
+            }
+        }
+    });
+}
+
+
+/// # `mem_to_slice_ops` Command
+///
+/// Usage: `mem_to_slice_ops`
+///
+/// Replace calls to `memcpy`, `memmove` and `memset` whose pointer arguments come from arrays or
+/// slices with the equivalent slice operations:
+///
+///  * `memcpy(dst, src, n * size_of::<T>())` becomes
+///    `dst[..n].copy_from_slice(&src[..n])`.
+///  * `memmove` becomes `copy_within` when both pointers come from the same array, and
+///    `copy_from_slice` otherwise.
+///  * `memset(p, 0, n * size_of::<T>())` becomes `p[..n].fill(0)`.  For element types other than
+///    numbers, `bool`s, and raw pointers, the zero value is `Default::default()`.  A nonzero
+///    fill byte is only supported for byte arrays.
+///
+/// A pointer argument is recognized as coming from an array or slice `a` when it is
+/// `a.as_ptr()`, `a.as_mut_ptr()`, `&a`, `&mut a`, `&a[i]`, `&mut a[i]`, or
+/// `a.as_mut_ptr().offset(i)` (possibly with casts), as produced by the transpiler and by
+/// `ptr_len_to_slice`.  The byte count must be a multiple of the element size written with
+/// `size_of`, or the size of the whole array.  Calls are only rewritten when their return value is
+/// unused.
+///
+/// Example:
+///
+/// ```ignore
+///     memcpy(dst.as_mut_ptr() as *mut libc::c_void,
+///            src.as_ptr() as *const libc::c_void,
+///            (n as libc::c_ulong)
+///                .wrapping_mul(::std::mem::size_of::<i32>() as libc::c_ulong));
+///     memset(&mut buf as *mut [u8; 16] as *mut libc::c_void, 0,
+///            ::std::mem::size_of::<[u8; 16]>() as libc::c_ulong);
+/// ```
+///
+/// After running `mem_to_slice_ops`:
+///
+/// ```ignore
+///     dst[..n as usize].copy_from_slice(&src[..n as usize]);
+///     buf[..].fill(0);
+/// ```
+pub struct MemToSliceOps;
+
+/// A pointer into an array or slice.
+struct SlicePtr<'a, 'tcx> {
+    /// The array or slice.
+    base: &'a Expr,
+    /// The index of the element pointed to, if it's not the first.
+    offset: Option<&'a Expr>,
+    /// The type of the array or slice, without references.
+    ty: ty::Ty<'tcx>,
+    elem_ty: ty::Ty<'tcx>,
+}
+
+/// Get the type of an array or slice expression, along with its element type.
+fn array_like_ty<'tcx>(cx: &RefactorCtxt<'_, 'tcx>,
+                       e: &Expr) -> Option<(ty::Ty<'tcx>, ty::Ty<'tcx>)> {
+    let mut ty = cx.opt_node_type(e.id)?;
+    while let TyKind::Ref(_, inner, _) = ty.kind {
+        ty = inner;
+    }
+    match ty.kind {
+        TyKind::Array(elem, _) | TyKind::Slice(elem) => Some((ty, elem)),
+        _ => None,
+    }
+}
+
+fn as_slice_ptr<'a, 'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &'a Expr) -> Option<SlicePtr<'a, 'tcx>> {
+    let e = strip_casts(e);
+    let (base, offset) = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && matches!([&*seg.ident.as_str()] "as_ptr", "as_mut_ptr") =>
+            (&*args[0], None),
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 2 && matches!([&*seg.ident.as_str()] "offset", "add") => {
+            let inner = as_slice_ptr(cx, &args[0])?;
+            if inner.offset.is_some() {
+                return None;
+            }
+            (inner.base, Some(strip_casts(&args[1])))
+        }
+        // A reference to an array or slice.
+        ExprKind::Path(..) => (e, None),
+        ExprKind::AddrOf(_, _, ref inner) => match inner.kind {
+            ExprKind::Index(ref base, ref idx)
+                if !matches!([idx.kind] ExprKind::Range(..)) => (&**base, Some(&**idx)),
+            _ => (&**inner, None),
+        },
+        _ => return None,
+    };
+    let (ty, elem_ty) = array_like_ty(cx, base)?;
+    Some(SlicePtr { base, offset, ty, elem_ty })
+}
+
+/// Render `e` cast to the type `ty`, adding parentheses if needed.
+pub fn cast_str(e: &Expr, ty: &str) -> String {
+    let src = pprust::expr_to_string(e);
+    match e.kind {
+        ExprKind::Lit(_) | ExprKind::Path(..) | ExprKind::MethodCall(..) | ExprKind::Cast(..) |
+        ExprKind::Paren(..) => format!("{} as {}", src, ty),
+        _ => format!("({}) as {}", src, ty),
+    }
+}
+
+/// Render `e` as a `usize`, adding a cast if needed.
+pub fn usize_str(cx: &RefactorCtxt, e: &Expr) -> String {
+    let e = strip_casts(e);
+    match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+        Some(&TyKind::Uint(UintTy::Usize)) => pprust::expr_to_string(e),
+        _ => cast_str(e, "usize"),
+    }
+}
+
+/// If `e` is `size_of::<T>()`, possibly with casts, return `T`.
+pub fn as_size_of<'tcx>(cx: &RefactorCtxt<'_, 'tcx>, e: &Expr) -> Option<ty::Ty<'tcx>> {
+    let e = strip_casts(e);
+    let func = match e.kind {
+        ExprKind::Call(ref func, ref args) if args.is_empty() => func,
+        _ => return None,
+    };
+    let path = match_or!([func.kind] ExprKind::Path(None, ref path) => path; return None);
+    if path.segments.last()?.ident.as_str() != "size_of" {
+        return None;
+    }
+    Some(cx.opt_callee_info(e)?.substs?.type_at(0))
+}
+
+/// The number of elements covered by a byte count.
+enum ElemCount<'a> {
+    /// The count is given by an expression.
+    Expr(&'a Expr),
+    /// The count is a single element.
+    One,
+    /// The byte count is the size of the whole array.
+    All,
+}
+
+fn elem_count<'a, 'tcx>(cx: &RefactorCtxt<'_, 'tcx>,
+                        e: &'a Expr,
+                        ptr: &SlicePtr<'_, 'tcx>) -> Option<ElemCount<'a>> {
+    if let Some(ty) = as_size_of(cx, e) {
+        if ty == ptr.elem_ty {
+            return Some(ElemCount::One);
+        }
+        if ty == ptr.ty && ptr.offset.is_none() {
+            return Some(ElemCount::All);
+        }
+        return None;
+    }
+    let e = strip_casts(e);
+    let (a, b) = match e.kind {
+        ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::Mul => (a, b),
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 2 && seg.ident.as_str() == "wrapping_mul" => (&args[0], &args[1]),
+        _ => match ptr.elem_ty.kind {
+            // For byte arrays, the byte count is the element count.
+            TyKind::Int(IntTy::I8) | TyKind::Uint(UintTy::U8) => return Some(ElemCount::Expr(e)),
+            _ => return None,
+        },
+    };
+    if as_size_of(cx, b) == Some(ptr.elem_ty) {
+        Some(ElemCount::Expr(strip_casts(a)))
+    } else if as_size_of(cx, a) == Some(ptr.elem_ty) {
+        Some(ElemCount::Expr(strip_casts(b)))
+    } else {
+        None
+    }
+}
+
+/// Render the elements of the slice starting at `ptr` as a range, returning the start and end.
+fn range_strs(cx: &RefactorCtxt, ptr: &SlicePtr, count: &ElemCount) -> (String, String) {
+    let start = ptr.offset.map_or(String::new(), |off| usize_str(cx, off));
+    let len = match *count {
+        ElemCount::Expr(e) => usize_str(cx, e),
+        ElemCount::One => "1".to_owned(),
+        ElemCount::All => return (String::new(), String::new()),
+    };
+    let end = if start.is_empty() { len } else { format!("{} + {}", start, len) };
+    (start, end)
+}
+
+fn slice_str(cx: &RefactorCtxt, ptr: &SlicePtr, count: &ElemCount) -> String {
+    let (start, end) = range_strs(cx, ptr, count);
+    format!("{}[{}..{}]", pprust::expr_to_string(ptr.base), start, end)
+}
+
+/// Get the zero value of an element type, as source text.
+fn zero_str(ty: ty::Ty) -> &'static str {
+    match ty.kind {
+        TyKind::Int(_) | TyKind::Uint(_) => "0",
+        TyKind::Float(_) => "0.0",
+        TyKind::Bool => "false",
+        TyKind::RawPtr(mt) => match mt.mutbl {
+            Mutability::Mutable => "::std::ptr::null_mut()",
+            Mutability::Immutable => "::std::ptr::null()",
+        },
+        _ => "Default::default()",
+    }
+}
+
+/// Try to rewrite a `memcpy`, `memmove` or `memset` call as a slice operation.
+fn rewrite_mem_call(cx: &RefactorCtxt, e: &Expr) -> Option<String> {
+    let args = match e.kind {
+        ExprKind::Call(_, ref args) if args.len() == 3 => args,
+        _ => return None,
+    };
+    if is_call_to(e, "memcpy") || is_call_to(e, "memmove") {
+        let dst = as_slice_ptr(cx, &args[0])?;
+        let src = as_slice_ptr(cx, &args[1])?;
+        if dst.elem_ty != src.elem_ty {
+            return None;
+        }
+        let count = elem_count(cx, &args[2], &dst)?;
+        if let ElemCount::All = count {
+            if src.ty != dst.ty || src.offset.is_some() {
+                return None;
+            }
+        }
+
+        if dst.base.ast_equiv(src.base) {
+            let (start, end) = range_strs(cx, &src, &count);
+            let dst_start = dst.offset.map_or("0".to_owned(), |off| usize_str(cx, off));
+            return Some(format!("{}.copy_within({}..{}, {})",
+                                pprust::expr_to_string(dst.base), start, end, dst_start));
+        }
+        Some(format!("{}.copy_from_slice(&{})",
+                     slice_str(cx, &dst, &count), slice_str(cx, &src, &count)))
+    } else if is_call_to(e, "memset") {
+        let dst = as_slice_ptr(cx, &args[0])?;
+        let count = elem_count(cx, &args[2], &dst)?;
+        let val = strip_casts(&args[1]);
+        let is_zero = match val.kind {
+            ExprKind::Lit(ref l) => matches!([l.kind] LitKind::Int(0, _)),
+            _ => false,
+        };
+        let val = if is_zero {
+            zero_str(dst.elem_ty).to_owned()
+        } else {
+            match dst.elem_ty.kind {
+                TyKind::Uint(UintTy::U8) => cast_str(val, "u8"),
+                TyKind::Int(IntTy::I8) => cast_str(val, "i8"),
+                _ => return None,
+            }
+        };
+        Some(format!("{}.fill({})", slice_str(cx, &dst, &count), val))
+    } else {
+        None
+    }
+}
+
+impl Transform for MemToSliceOps {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = match_or!([s.kind] StmtKind::Semi(ref e) => rewrite_mem_call(cx, e);
+                                    continue);
+                if let Some(src) = src {
+                    s.kind = StmtKind::Semi(parse_expr(cx.session(), &src));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `qsort_to_sort_by` Command
+///
+/// Usage: `qsort_to_sort_by`
+///
+/// Replace calls to `qsort` and `bsearch` on arrays and slices with the equivalent slice methods,
+/// dropping the element size and void pointer bookkeeping:
+///
+///  * `qsort(a, n, size_of::<T>(), Some(cmp))` becomes `a[..n].sort_unstable_by(...)`.
+///  * `bsearch(key, a, n, size_of::<T>(), Some(cmp))` becomes a `binary_search_by` over
+///    `a[..n]`, mapped to a pointer to the element that was found, or a null pointer.
+///
+/// The comparator becomes a closure over references to the elements that calls the original
+/// comparator function and compares its result to zero, so the comparator itself is left
+/// unchanged.  Pointer arguments are recognized like in `mem_to_slice_ops`, and the element size
+/// must be `size_of` the element type.  For `bsearch`, the pointer must be to the start of the
+/// array, and the key must be free of side effects, since it's passed to every comparison.
+///
+/// Example:
+///
+/// ```ignore
+///     qsort(xs.as_mut_ptr() as *mut libc::c_void, n as size_t,
+///           ::std::mem::size_of::<i32>() as libc::c_ulong,
+///           Some(cmp_int as unsafe extern "C" fn(*const libc::c_void,
+///                                                *const libc::c_void) -> libc::c_int));
+/// ```
+///
+/// After running `qsort_to_sort_by`:
+///
+/// ```ignore
+///     xs[..n as usize].sort_unstable_by(|a, b| {
+///         cmp_int(a as *const i32 as *const _, b as *const i32 as *const _).cmp(&0)
+///     });
+/// ```
+pub struct QsortToSortBy;
+
+/// Get the comparator function passed as `Some(f)` or `Some(f as fn(..) -> _)`.
+fn as_comparator(e: &Expr) -> Option<&Expr> {
+    let (func, args) = match_or!([e.kind] ExprKind::Call(ref f, ref a) => (f, a); return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref p) => p; return None);
+    if args.len() != 1 || path.segments.last()?.ident.as_str() != "Some" {
+        return None;
+    }
+    let f = strip_casts(&args[0]);
+    match f.kind {
+        ExprKind::Path(..) => Some(f),
+        _ => None,
+    }
+}
+
+/// Check that evaluating `e` more than once has no side effects.
+fn is_pure_expr(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Path(..) | ExprKind::Lit(_) => true,
+        ExprKind::Paren(ref e) |
+        ExprKind::Cast(ref e, _) |
+        ExprKind::AddrOf(_, _, ref e) |
+        ExprKind::Field(ref e, _) => is_pure_expr(e),
+        _ => false,
+    }
+}
+
+/// Try to rewrite a `qsort` or `bsearch` call as a slice operation.
+fn rewrite_sort_call(cx: &RefactorCtxt, e: &Expr) -> Option<String> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if is_call_to(e, "qsort") && args.len() == 4 {
+        let ptr = as_slice_ptr(cx, &args[0])?;
+        if as_size_of(cx, &args[2]) != Some(ptr.elem_ty) {
+            return None;
+        }
+        let cmp = pprust::expr_to_string(as_comparator(&args[3])?);
+        let elem_ty = pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ptr.elem_ty));
+        Some(format!("{}.sort_unstable_by(|a, b| \
+                      {}(a as *const {} as *const _, b as *const {} as *const _).cmp(&0))",
+                     slice_str(cx, &ptr, &ElemCount::Expr(&args[1])), cmp, elem_ty, elem_ty))
+    } else if is_call_to(e, "bsearch") && args.len() == 5 {
+        if !is_pure_expr(&args[0]) {
+            return None;
+        }
+        let ptr = as_slice_ptr(cx, &args[1])?;
+        if ptr.offset.is_some() || as_size_of(cx, &args[3]) != Some(ptr.elem_ty) {
+            return None;
+        }
+        let cmp = pprust::expr_to_string(as_comparator(&args[4])?);
+        let elem_ty = pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ptr.elem_ty));
+        // `bsearch` passes the key first, while `binary_search_by` wants the ordering of the
+        // element relative to the key.
+        Some(format!("{}.binary_search_by(|elem| \
+                      {}({}, elem as *const {} as *const _).cmp(&0).reverse())\
+                      .map_or(::std::ptr::null_mut(), \
+                              |i| &{}[i] as *const {} as *mut ::std::ffi::c_void)",
+                     slice_str(cx, &ptr, &ElemCount::Expr(&args[2])),
+                     cmp, pprust::expr_to_string(&args[0]), elem_ty,
+                     pprust::expr_to_string(ptr.base), elem_ty))
+    } else {
+        None
+    }
+}
+
+impl Transform for QsortToSortBy {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if let Some(src) = rewrite_sort_call(cx, e) {
+                *e = parse_expr(cx.session(), &src);
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `ptr_arith_to_index` Command
+///
+/// Usage: `ptr_arith_to_index`
+///
+/// Replace pointer arithmetic on pointers into arrays and slices with indexing:
+///
+///  * `*a.as_ptr().offset(i)` and `*a.as_mut_ptr().add(i)` become `a[i as usize]`, and
+///    `*a.as_ptr()` becomes `a[0]`.
+///  * A local pointer `p` initialized with `a.as_ptr()` or `a.as_mut_ptr()` and only used to
+///    walk through `a` is replaced with an index `p_idx` into `a`.  `*p` becomes `a[p_idx]`,
+///    `*p.offset(k)` becomes `a[p_idx + k]`, and `p = p.add(k)` becomes `p_idx += k`.  Every
+///    step `k` must be unsigned or a literal, so that the index never goes backwards.
+///
+/// Indexing is checked, unless the index can be shown to be in bounds.  An index is in bounds if
+/// it's a literal smaller than the length of an array, or the variable of a `for` loop over
+/// `0..n` where `n` is at most the length of the array, or over `0..a.len()`, or if the
+/// value-range analysis (see the `ranges` module) proves that it is between zero and the length
+/// of an array, for example because it's a counter of a `while i < n` loop.  In those cases, the
+/// access becomes `*a.get_unchecked(i)` (or `get_unchecked_mut`) instead, and a `SAFETY` comment
+/// explaining why the index is in bounds is added to the enclosing statement.
+///
+/// Example:
+///
+/// ```ignore
+///     for i in 0..8 {
+///         total += *arr.as_ptr().offset(i as isize);
+///     }
+///     let mut p = src.as_ptr();
+///     while *p != 0 {
+///         p = p.offset(1);
+///     }
+/// ```
+///
+/// After running `ptr_arith_to_index`, if `arr` has type `[i32; 8]`:
+///
+/// ```ignore
+///     for i in 0..8 {
+///         // SAFETY: `i` is at most 7, and `arr` has 8 elements
+///         total += *arr.get_unchecked(i as usize);
+///     }
+///     let mut p_idx: usize = 0;
+///     while src[p_idx] != 0 {
+///         p_idx += 1;
+///     }
+/// ```
+pub struct PtrArithToIndex;
+
+/// An exclusive upper bound on the values of a `for` loop variable.
+enum IndexBound {
+    /// The variable is less than a constant.
+    Const(u128),
+    /// The variable is less than the length of an array or slice.
+    Len(P<Expr>),
+}
+
+/// A local pointer that walks through an array or slice.
+struct WalkPtr {
+    base: P<Expr>,
+    idx_name: String,
+    /// Number of uses of the pointer that can be converted to use the index.
+    ok_uses: usize,
+    /// Total number of uses of the pointer.
+    uses: usize,
+}
+
+/// Collect the bounds of all `for` loop variables iterating over simple ranges.
+fn loop_bounds(krate: &Crate, cx: &RefactorCtxt) -> HashMap<HirId, IndexBound> {
+    let mut bounds = HashMap::new();
+    visit_nodes(krate, |e: &Expr| {
+        let (pat, iter) = match_or!([e.kind] ExprKind::ForLoop(ref pat, ref iter, _, _) =>
+                                    (pat, iter); return);
+        match pat.kind {
+            PatKind::Ident(BindingMode::ByValue(Mutability::Immutable), _, None) => {}
+            _ => return,
+        }
+        let (lo, hi, limits) = match iter.kind {
+            ExprKind::Range(Some(ref lo), Some(ref hi), limits) => (lo, hi, limits),
+            _ => return,
+        };
+        if as_int_lit(lo).is_none() {
+            return;
+        }
+        let bound = match (as_int_lit(hi), limits) {
+            (Some(n), RangeLimits::HalfOpen) => IndexBound::Const(n),
+            (Some(n), RangeLimits::Closed) => IndexBound::Const(n + 1),
+            (None, RangeLimits::HalfOpen) => match hi.kind {
+                ExprKind::MethodCall(ref seg, ref args)
+                    if args.len() == 1 && seg.ident.as_str() == "len" =>
+                    IndexBound::Len(args[0].clone()),
+                _ => return,
+            },
+            (None, RangeLimits::Closed) => return,
+        };
+        bounds.insert(cx.hir_map().node_to_hir_id(pat.id), bound);
+    });
+    bounds
+}
+
+/// Render an index or step `e` as a `usize`.  Unsuffixed literals are left alone.
+fn index_str(cx: &RefactorCtxt, e: &Expr) -> String {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref l) if matches!([l.kind] LitKind::Int(_, LitIntType::Unsuffixed)) =>
+            pprust::expr_to_string(e),
+        _ => usize_str(cx, e),
+    }
+}
+
+/// Check whether `k` can be used as a forward step for a walking pointer.
+fn is_forward_step(cx: &RefactorCtxt, k: &Expr) -> bool {
+    let k = strip_casts(k);
+    as_int_lit(k).is_some() ||
+        matches!([cx.opt_node_type(k.id).map(|ty| &ty.kind)] Some(&TyKind::Uint(_)))
+}
+
+/// If `e` is `p.offset(k)` or `p.add(k)`, return `p` and `k`.
+fn as_ptr_step(e: &Expr) -> Option<(&Expr, &Expr)> {
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 2 && matches!([&*seg.ident.as_str()] "offset", "add") =>
+            Some((&args[0], &args[1])),
+        _ => None,
+    }
+}
+
+/// Explain why indexing the array or slice `base` of type `ty` with `idx` is in bounds, if it is.
+/// `at` is the span of the access.
+fn in_bounds_reason<'tcx>(cx: &RefactorCtxt<'_, 'tcx>,
+                          bounds: &HashMap<HirId, IndexBound>,
+                          ranges: &mut ValueRanges<'_, 'tcx>,
+                          base: &Expr,
+                          ty: ty::Ty<'tcx>,
+                          idx: &Expr,
+                          at: Span) -> Option<String> {
+    let base_str = pprust::expr_to_string(base);
+    let array_len = match ty.kind {
+        TyKind::Array(_, len) => len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty())
+            .map(|n| n as u128),
+        _ => None,
+    };
+    if let Some(n) = as_int_lit(idx) {
+        let len = array_len?;
+        if n >= len {
+            return None;
+        }
+        return Some(format!("{} is less than {}, the length of `{}`", n, len, base_str));
+    }
+
+    let idx_str = pprust::expr_to_string(idx);
+    match cx.try_resolve_expr_to_hid(idx).and_then(|hid| bounds.get(&hid)) {
+        None | Some(&IndexBound::Const(0)) => {}
+        Some(&IndexBound::Const(n)) => match array_len {
+            Some(len) if n <= len => {
+                return Some(format!("`{}` is at most {}, and `{}` has {} elements",
+                                    idx_str, n - 1, base_str, len));
+            }
+            _ => {}
+        },
+        Some(&IndexBound::Len(ref e)) => if (**e).ast_equiv(base) {
+            return Some(format!("`{}` is less than `{}.len()`", idx_str, base_str));
+        },
+    }
+
+    // Otherwise, the index must have a known range that fits in the array.
+    let len = array_len?;
+    let r = ranges.expr_range(idx, at)?;
+    if r.lo < 0 || r.hi as u128 >= len {
+        return None;
+    }
+    Some(format!("`{}` is between {} and {}, and `{}` has {} elements",
+                 pprust::expr_to_string(strip_casts(idx)), r.lo, r.hi, base_str, len))
+}
+
+impl Transform for PtrArithToIndex {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find local pointers that walk through arrays or slices.
+
+        let mut walks: HashMap<HirId, WalkPtr> = HashMap::new();
+        visit_nodes(krate, |l: &Local| {
+            let ident = match_or!([l.pat.kind] PatKind::Ident(_, ident, None) => ident; return);
+            let init = match_or!([l.init] Some(ref x) => x; return);
+            let ptr = match_or!([as_slice_ptr(cx, init)] Some(x) => x; return);
+            if ptr.offset.is_some() || !matches!([ptr.base.kind] ExprKind::Path(..)) {
+                return;
+            }
+            match cx.opt_node_type(l.pat.id).map(|ty| &ty.kind) {
+                Some(&TyKind::RawPtr(mt)) if mt.ty == ptr.elem_ty => {}
+                _ => return,
+            }
+            walks.insert(cx.hir_map().node_to_hir_id(l.pat.id), WalkPtr {
+                base: P(ptr.base.clone()),
+                idx_name: format!("{}_idx", ident.name),
+                ok_uses: 0,
+                uses: 0,
+            });
+        });
+
+        // Count the uses of each pointer that we know how to convert.  A pointer with any other
+        // use is left alone.
+        let resolve_walk = |e: &Expr| match e.kind {
+            ExprKind::Path(..) => cx.try_resolve_expr_to_hid(e),
+            _ => None,
+        };
+        visit_nodes(krate, |e: &Expr| {
+            let (ptr, uses, ok_uses) = match e.kind {
+                ExprKind::Path(..) => (e, 1, 0),
+                ExprKind::Unary(UnOp::Deref, ref inner) => match as_offset_deref(e) {
+                    Some((ptr, k)) if is_forward_step(cx, k) => (ptr, 0, 1),
+                    Some(_) => return,
+                    None => (&**inner, 0, 1),
+                },
+                ExprKind::Assign(ref lhs, ref rhs) => match as_ptr_step(rhs) {
+                    Some((ptr, k)) if ptr.ast_equiv(lhs) && is_forward_step(cx, k) =>
+                        (&**lhs, 0, 2),
+                    _ => return,
+                },
+                _ => return,
+            };
+            if let Some(w) = resolve_walk(ptr).and_then(|hid| walks.get_mut(&hid)) {
+                w.uses += uses;
+                w.ok_uses += ok_uses;
+            }
+        });
+        walks.retain(|_, w| w.uses == w.ok_uses);
+
+        // (2) Replace the walking pointers with indices.
+
+        if !walks.is_empty() {
+            MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+                for s in &mut b.stmts {
+                    let hid = match s.kind {
+                        StmtKind::Local(ref l) => cx.hir_map().node_to_hir_id(l.pat.id),
+                        _ => continue,
+                    };
+                    if let Some(w) = walks.get(&hid) {
+                        let src = format!("let mut {}: usize = 0;", w.idx_name);
+                        *s = parse_stmts(cx.session(), &src).lone();
+                    }
+                }
+            });
+
+            MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+                let src = if let ExprKind::Assign(ref lhs, ref rhs) = e.kind {
+                    let w = match_or!([resolve_walk(lhs).and_then(|hid| walks.get(&hid))]
+                                      Some(x) => x; return);
+                    let (_, k) = as_ptr_step(rhs).unwrap();
+                    format!("{} += {}", w.idx_name, index_str(cx, k))
+                } else if let Some((ptr, k)) = as_offset_deref(e) {
+                    let w = match_or!([resolve_walk(ptr).and_then(|hid| walks.get(&hid))]
+                                      Some(x) => x; return);
+                    format!("{}[{} + {}]", pprust::expr_to_string(&w.base), w.idx_name,
+                            index_str(cx, k))
+                } else if let ExprKind::Unary(UnOp::Deref, ref inner) = e.kind {
+                    let w = match_or!([resolve_walk(inner).and_then(|hid| walks.get(&hid))]
+                                      Some(x) => x; return);
+                    format!("{}[{}]", pprust::expr_to_string(&w.base), w.idx_name)
+                } else {
+                    return;
+                };
+                *e = parse_expr(cx.session(), &src);
+            });
+        }
+
+        // (3) Replace offsets from `as_ptr()` and `as_mut_ptr()` with indexing.
+
+        let bounds = loop_bounds(krate, cx);
+        let mut ranges = ValueRanges::new(cx);
+        let zero = parse_expr(cx.session(), "0");
+        // Explanations of why unchecked accesses are in bounds, by the ID of the access.
+        let mut reasons: HashMap<NodeId, String> = HashMap::new();
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            let (ptr_e, idx) = match as_offset_deref(e) {
+                Some(x) => x,
+                None => match e.kind {
+                    ExprKind::Unary(UnOp::Deref, ref inner) => (&**inner, &*zero),
+                    _ => return,
+                },
+            };
+            let ptr = match_or!([as_slice_ptr(cx, ptr_e)] Some(x) => x; return);
+            if ptr.offset.is_some() || matches!([strip_casts(ptr_e).kind] ExprKind::Path(..)) {
+                return;
+            }
+            // Casts that change the element type can't be turned into indexing.
+            match cx.opt_node_type(ptr_e.id).map(|ty| &ty.kind) {
+                Some(&TyKind::RawPtr(mt)) if mt.ty == ptr.elem_ty => {}
+                _ => return,
+            }
+
+            let base_str = pprust::expr_to_string(ptr.base);
+            let idx_str = index_str(cx, idx);
+            let reason = in_bounds_reason(cx, &bounds, &mut ranges, ptr.base, ptr.ty, idx, e.span);
+            let src = match (&reason, ectx) {
+                (None, _) => format!("{}[{}]", base_str, idx_str),
+                (Some(_), lr_expr::Context::LvalueMut) =>
+                    format!("*{}.get_unchecked_mut({})", base_str, idx_str),
+                (Some(_), _) => format!("*{}.get_unchecked({})", base_str, idx_str),
+            };
+            let new_e = st.parse_expr(cx, &src);
+            if let Some(reason) = reason {
+                reasons.insert(new_e.id, reason);
+            }
+            *e = new_e;
+        });
+
+        // (4) Add the safety comments to the innermost statements containing unchecked accesses.
+
+        visit_nodes_post(krate, |b: &Block| {
+            for s in &b.stmts {
+                let mut lines = Vec::new();
+                visit_nodes(s, |e: &Expr| {
+                    if let Some(reason) = reasons.remove(&e.id) {
+                        let line = format!("// SAFETY: {}", reason);
+                        if !lines.contains(&line) {
+                            lines.push(line);
+                        }
+                    }
+                });
+                if !lines.is_empty() {
+                    st.add_comment(s.id, Comment {
+                        style: CommentStyle::Isolated,
+                        lines,
+                        pos: BytePos(0),
+                    });
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `array_param_to_const_generic` Command
+///
+/// Usage: `array_param_to_const_generic`
+///
+/// Marks: `target`, `len`
+///
+/// For each function argument marked `target` that points into a fixed-size array, change its
+/// type to a reference to an array whose length is a new `const` generic parameter of the
+/// function:
+///
+///  * An argument of type `*mut [T; K]` (or `*const [T; K]`) becomes `&mut [T; N]` (or
+///    `&[T; N]`).  It must only be used by dereferencing it, as in `(*p)[i]`.
+///  * An argument of type `*mut T` (or `*const T`) becomes `&mut [T; N]` (or `&[T; N]`), if
+///    every call passes a pointer to the start of an array, such as `a.as_mut_ptr()`.  It may be
+///    paired with an argument marked `len` giving the length of the array, which is removed.  Each
+///    call must pass the length of its array for it.  Inside the function, element accesses and
+///    other uses of the pointer and length are rewritten as in `ptr_len_to_slice`.
+///
+/// Call sites pass references to the arrays instead, and the array length is inferred from them.
+/// A function whose marked arguments can't all be converted, or which is used other than by
+/// calling it, is left unchanged.  The crate gets `#![feature(const_generics)]` if needed.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn scale(v: *mut f32, n: i32, k: f32) {
+///         for i in 0..n {
+///             *v.offset(i as isize) *= k;
+///         }
+///     }
+///
+///     let mut v: [f32; 3] = [1., 2., 3.];
+///     scale(v.as_mut_ptr(), 3, 2.);
+/// ```
+///
+/// After running `array_param_to_const_generic`, with `v` marked `target` and `n` marked `len`:
+///
+/// ```ignore
+///     unsafe fn scale<const N: usize>(v: &mut [f32; N], k: f32) {
+///         for i in 0..v.len() as i32 {
+///             v[i as usize] *= k;
+///         }
+///     }
+///
+///     let mut v: [f32; 3] = [1., 2., 3.];
+///     scale(&mut v, 2.);
+/// ```
+pub struct ArrayParamToConstGeneric;
+
+/// A pointer argument being converted to a reference to an array of generic length.
+struct ArrayParam<'tcx> {
+    index: usize,
+    hir_id: HirId,
+    mutbl: Mutability,
+    elem_ty: ty::Ty<'tcx>,
+    /// The argument points to a whole array, rather than to its first element.
+    whole: bool,
+    /// Index of the argument giving the length of the array.
+    len: Option<usize>,
+}
+
+fn mut_prefix(mutbl: Mutability) -> &'static str {
+    match mutbl {
+        Mutability::Mutable => "mut ",
+        Mutability::Immutable => "",
+    }
+}
+
+impl Transform for ArrayParamToConstGeneric {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked arguments.
+
+        let mut fns: HashMap<DefId, Vec<ArrayParam>> = HashMap::new();
+        visit_nodes(krate, |i: &Item| {
+            let sig = match_or!([i.kind] ItemKind::Fn(ref sig, _, _) => sig; return);
+            let mut params = Vec::new();
+            let mut lens = Vec::new();
+            for (j, arg) in sig.decl.inputs.iter().enumerate() {
+                if st.marked(arg.id, "len") {
+                    lens.push(j);
+                }
+                if !st.marked(arg.id, "target") {
+                    continue;
+                }
+                let mt = match cx.opt_node_type(arg.pat.id).map(|ty| &ty.kind) {
+                    Some(&TyKind::RawPtr(mt)) => mt,
+                    _ => {
+                        warn!("argument `{}` of `{}` is not a raw pointer; skipping it",
+                              pprust::pat_to_string(&arg.pat), i.ident);
+                        continue;
+                    }
+                };
+                if !matches!([arg.pat.kind] PatKind::Ident(_, _, None)) {
+                    warn!("argument `{}` of `{}` is not a simple binding; skipping it",
+                          pprust::pat_to_string(&arg.pat), i.ident);
+                    continue;
+                }
+                let (elem_ty, whole) = match mt.ty.kind {
+                    TyKind::Array(elem, _) => (elem, true),
+                    _ => (mt.ty, false),
+                };
+                params.push(ArrayParam {
+                    index: j,
+                    hir_id: cx.hir_map().node_to_hir_id(arg.pat.id),
+                    mutbl: mt.mutbl,
+                    elem_ty,
+                    whole,
+                    len: None,
+                });
+            }
+
+            let mut elem_params = params.iter_mut().filter(|p| !p.whole);
+            for &len in &lens {
+                match elem_params.next() {
+                    Some(p) => p.len = Some(len),
+                    None => {
+                        warn!("`{}` has more marked lengths than element pointers; skipping it",
+                              i.ident);
+                        return;
+                    }
+                }
+            }
+            if !params.is_empty() {
+                fns.insert(cx.node_def_id(i.id), params);
+            }
+        });
+
+        if fns.is_empty() {
+            return;
+        }
+
+        // (2) Check the uses of the functions and of their whole-array arguments, and work out
+        // the new arguments for each call.
+
+        let whole_args = fns.iter()
+            .flat_map(|(&did, ps)| ps.iter().filter(|p| p.whole).map(move |p| (p.hir_id, did)))
+            .collect::<HashMap<_, _>>();
+        let mut bad: HashSet<DefId> = HashSet::new();
+        let mut callee_ids = HashSet::new();
+        let mut fn_refs = Vec::new();
+        let mut derefs = HashSet::new();
+        let mut whole_uses = Vec::new();
+        // New call arguments, by call expression ID.
+        let mut new_args: HashMap<NodeId, Vec<(usize, String)>> = HashMap::new();
+
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Call(ref func, ref args) => {
+                    let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+                    let params = match_or!([fns.get(&did)] Some(x) => x; return);
+                    callee_ids.insert(func.id);
+                    let mut call_args = Vec::new();
+                    for p in params {
+                        let arg = &args[p.index];
+                        let src = if p.whole {
+                            let addr_of = match arg.kind {
+                                ExprKind::Cast(ref inner, _) => match strip_casts(inner).kind {
+                                    ExprKind::AddrOf(_, _, ref x) => Some(x),
+                                    _ => None,
+                                },
+                                _ => None,
+                            };
+                            match addr_of {
+                                Some(x) => format!("&{}{}", mut_prefix(p.mutbl),
+                                                   pprust::expr_to_string(x)),
+                                None => format!("&{}*{}", mut_prefix(p.mutbl),
+                                                pprust::expr_to_string(arg)),
+                            }
+                        } else {
+                            let ptr = as_slice_ptr(cx, arg)
+                                .filter(|ptr| ptr.offset.is_none())
+                                .and_then(|ptr| match ptr.ty.kind {
+                                    TyKind::Array(elem, len) if elem == p.elem_ty =>
+                                        Some((ptr, len)),
+                                    _ => None,
+                                });
+                            let (ptr, len) = match_or!([ptr] Some(x) => x;
+                                                       { bad.insert(did); return });
+                            if let Some(j) = p.len {
+                                let len = len.try_eval_usize(cx.ty_ctxt(), ParamEnv::empty());
+                                let arg_len = as_int_lit(&args[j]);
+                                if len.is_none() || len.map(|n| n as u128) != arg_len {
+                                    bad.insert(did);
+                                    return;
+                                }
+                            }
+                            let base = pprust::expr_to_string(ptr.base);
+                            match cx.opt_node_type(ptr.base.id).map(|ty| &ty.kind) {
+                                Some(&TyKind::Ref(..)) =>
+                                    format!("&{}*{}", mut_prefix(p.mutbl), base),
+                                _ => format!("&{}{}", mut_prefix(p.mutbl), base),
+                            }
+                        };
+                        call_args.push((p.index, src));
+                    }
+                    new_args.insert(e.id, call_args);
+                }
+                ExprKind::Unary(UnOp::Deref, ref inner) => {
+                    derefs.insert(inner.id);
+                }
+                ExprKind::Path(..) => {
+                    if let Some(did) = cx.try_resolve_expr(e).filter(|did| fns.contains_key(did)) {
+                        fn_refs.push((e.id, did));
+                    } else if let Some(hid) = cx.try_resolve_expr_to_hid(e) {
+                        if let Some(&did) = whole_args.get(&hid) {
+                            whole_uses.push((e.id, did));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        for (id, did) in fn_refs {
+            if !callee_ids.contains(&id) {
+                bad.insert(did);
+            }
+        }
+        for (id, did) in whole_uses {
+            if !derefs.contains(&id) {
+                bad.insert(did);
+            }
+        }
+        for did in &bad {
+            warn!("can't convert all uses of `{}`; skipping it", cx.ty_ctxt().item_name(*did));
+            fns.remove(did);
+        }
+        if fns.is_empty() {
+            return;
+        }
+
+        // (3) Change the function signatures.
+
+        let mut slice_args: HashMap<HirId, SliceArg> = HashMap::new();
+        let mut len_args: HashMap<HirId, HirId> = HashMap::new();
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let params = match_or!([fns.get(&cx.node_def_id(i.id))] Some(x) => x; return);
+            let (sig, generics) = match_or!([i.kind] ItemKind::Fn(ref mut sig, ref mut g, _) =>
+                                            (sig, g); return);
+            let mut k = 0;
+            for p in params {
+                let mut name = "N".to_owned();
+                while generics.params.iter().any(|gp| gp.ident.as_str() == name) {
+                    k += 1;
+                    name = format!("N{}", k);
+                }
+                let src = format!("fn f<const {}: usize>() {{}}", name);
+                let param = expect!([parse_items(cx.session(), &src).lone().kind]
+                                    ItemKind::Fn(_, ref g, _) => g.params[0].clone());
+                generics.params.push(param);
+
+                let elem_ty = pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), p.elem_ty));
+                let arg = &mut sig.decl.inputs[p.index];
+                arg.ty = parse_ty(cx.session(), &format!("&{}[{}; {}]", mut_prefix(p.mutbl),
+                                                         elem_ty, name));
+                if p.whole {
+                    continue;
+                }
+                let ident = expect!([arg.pat.kind] PatKind::Ident(_, ident, _) => ident);
+                let len_ty = match p.len {
+                    Some(j) => {
+                        let len_arg = &sig.decl.inputs[j];
+                        len_args.insert(cx.hir_map().node_to_hir_id(len_arg.pat.id), p.hir_id);
+                        len_arg.ty.clone()
+                    }
+                    None => mk().ident_ty("usize"),
+                };
+                slice_args.insert(p.hir_id, SliceArg { ident, mutbl: p.mutbl, len_ty });
+            }
+
+            let removed = params.iter().filter_map(|p| p.len).collect::<HashSet<_>>();
+            let mut j = 0;
+            sig.decl.inputs.retain(|_| {
+                j += 1;
+                !removed.contains(&(j - 1))
+            });
+        });
+
+        // (4) Rewrite call sites.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let call_args = match_or!([new_args.get(&e.id)] Some(x) => x; return);
+            let did = match_or!([cx.opt_callee(e)] Some(x) => x; return);
+            let params = match_or!([fns.get(&did)] Some(x) => x; return);
+            let args = expect!([e.kind] ExprKind::Call(_, ref mut args) => args);
+            for &(j, ref src) in call_args {
+                args[j] = parse_expr(cx.session(), src);
+            }
+            let removed = params.iter().filter_map(|p| p.len).collect::<HashSet<_>>();
+            let mut j = 0;
+            args.retain(|_| {
+                j += 1;
+                !removed.contains(&(j - 1))
+            });
+        });
+
+        // (5) Rewrite uses of the element pointers and lengths inside the functions.
+
+        rewrite_slice_arg_uses(krate, st, cx, &slice_args, &len_args);
+
+        let has_feature = krate.attrs.iter().any(|attr| {
+            attr.check_name(sym::feature) &&
+                attr.meta_item_list().map_or(false, |items| {
+                    items.iter().any(|item| item.check_name(sym::const_generics))
+                })
+        });
+        if !has_feature {
+            krate.attrs.push(attr::mk_attr_inner(attr::mk_list_item(
+                Ident::new(sym::feature, DUMMY_SP),
+                vec![attr::mk_nested_word_item(Ident::new(sym::const_generics, DUMMY_SP))])));
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("ptr_len_to_slice", |_args| mk(PtrLenToSlice));
+    reg.register("mem_to_slice_ops", |_args| mk(MemToSliceOps));
+    reg.register("qsort_to_sort_by", |_args| mk(QsortToSortBy));
+    reg.register("ptr_arith_to_index", |_args| mk(PtrArithToIndex));
+    reg.register("array_param_to_const_generic", |_args| mk(ArrayParamToConstGeneric));
+}
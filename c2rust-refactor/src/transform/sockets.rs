@@ -0,0 +1,499 @@
+//! Conversion of BSD socket descriptors to `std::net` sockets.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use rustc::ty::TyKind;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::transform::slices::cast_str;
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// The `std::net` type that replaces a socket descriptor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SockKind {
+    /// A `SOCK_STREAM` socket that is passed to `listen`.
+    Listener,
+    /// Any other `SOCK_STREAM` socket, or one returned by `accept`.
+    Stream,
+    /// A `SOCK_DGRAM` socket.
+    Udp,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct SockVar {
+    kind: SockKind,
+    v6: bool,
+    /// The listener this socket was `accept`ed from, if any.
+    accepted_from: Option<HirId>,
+}
+
+impl SockVar {
+    fn ty_str(&self) -> &'static str {
+        match self.kind {
+            SockKind::Listener => "Option<::std::net::TcpListener>",
+            SockKind::Stream => "Option<::std::net::TcpStream>",
+            SockKind::Udp => "Option<::std::net::UdpSocket>",
+        }
+    }
+
+    fn allows(&self, op: SockOp) -> bool {
+        match op {
+            SockOp::Close | SockOp::Check(_) => true,
+            SockOp::Bind => matches!([self.kind] SockKind::Listener, SockKind::Udp),
+            SockOp::Listen | SockOp::Accept => self.kind == SockKind::Listener,
+            SockOp::Connect => self.kind == SockKind::Udp ||
+                (self.kind == SockKind::Stream && self.accepted_from.is_none()),
+            SockOp::Recv | SockOp::Send => self.kind != SockKind::Listener,
+        }
+    }
+}
+
+/// A use of a socket that `socket_to_std_net` can convert.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SockOp {
+    /// `bind(fd, &addr, len)`
+    Bind,
+    /// `listen(fd, backlog)`
+    Listen,
+    /// `accept(fd, NULL, NULL)`, as the initializer of another socket.
+    Accept,
+    /// `connect(fd, &addr, len)`
+    Connect,
+    /// `recv(fd, buf, len, 0)`
+    Recv,
+    /// `send(fd, buf, len, 0)`
+    Send,
+    /// `close(fd)`, as a statement.
+    Close,
+    /// `fd < 0` or `fd == -1` (`true`), or `fd >= 0` or `fd != -1` (`false`).
+    Check(bool),
+}
+
+/// Get the value of an integer literal, possibly negated and with casts.
+fn int_lit(e: &Expr) -> Option<i128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(i, _) => Some(i as i128),
+            _ => None,
+        },
+        ExprKind::Unary(UnOp::Neg, ref e) => int_lit(e).map(|i| -i),
+        ExprKind::Paren(ref e) => int_lit(e),
+        _ => None,
+    }
+}
+
+/// Get the name of a constant, or the value of an integer literal.
+fn const_name(e: &Expr) -> Option<String> {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Path(None, ref path) => Some(path.segments.last()?.ident.to_string()),
+        _ => int_lit(e).map(|i| i.to_string()),
+    }
+}
+
+/// If `e` is `socket(domain, type, 0)` for a supported domain and type, get the kind of socket
+/// and whether it's IPv6.
+fn as_socket(e: &Expr) -> Option<(SockKind, bool)> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "socket") || args.len() != 3 {
+        return None;
+    }
+    let v6 = match &const_name(&args[0])?[..] {
+        "AF_INET" | "PF_INET" | "2" => false,
+        "AF_INET6" | "PF_INET6" | "10" => true,
+        _ => return None,
+    };
+    let kind = match &const_name(&args[1])?[..] {
+        "SOCK_STREAM" | "1" => SockKind::Stream,
+        "SOCK_DGRAM" | "2" => SockKind::Udp,
+        "SOCK_RAW" | "3" => {
+            warn!("raw sockets have no `std::net` equivalent");
+            return None;
+        }
+        _ => return None,
+    };
+    Some((kind, v6))
+}
+
+/// If `e` is `accept(fd, NULL, NULL)`, return `fd`.
+fn as_accept(e: &Expr) -> Option<&Expr> {
+    let e = strip_casts(e);
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    if !is_call_to(e, "accept") || args.len() != 3 ||
+       !is_null_ptr(&args[1]) || !is_null_ptr(&args[2]) {
+        return None;
+    }
+    Some(&args[0])
+}
+
+/// If `e` is a use of a socket that can be converted, return the operation and the socket.
+fn as_sock_op(e: &Expr) -> Option<(SockOp, &Expr)> {
+    if let ExprKind::Binary(op, ref lhs, ref rhs) = e.kind {
+        let failed = match (op.node, int_lit(rhs)?) {
+            (BinOpKind::Lt, 0) | (BinOpKind::Eq, -1) => true,
+            (BinOpKind::Ge, 0) | (BinOpKind::Ne, -1) => false,
+            _ => return None,
+        };
+        return Some((SockOp::Check(failed), lhs));
+    }
+    if let Some(fd) = as_accept(e) {
+        return Some((SockOp::Accept, fd));
+    }
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let op = if is_call_to(e, "bind") && args.len() == 3 {
+        SockOp::Bind
+    } else if is_call_to(e, "listen") && args.len() == 2 {
+        SockOp::Listen
+    } else if is_call_to(e, "connect") && args.len() == 3 {
+        SockOp::Connect
+    } else if is_call_to(e, "recv") && args.len() == 4 && int_lit(&args[3]) == Some(0) {
+        SockOp::Recv
+    } else if is_call_to(e, "send") && args.len() == 4 && int_lit(&args[3]) == Some(0) {
+        SockOp::Send
+    } else if is_call_to(e, "close") && args.len() == 1 {
+        SockOp::Close
+    } else {
+        return None;
+    };
+    Some((op, &args[0]))
+}
+
+/// If `e` is `&addr` (possibly with casts) for a `sockaddr_in` or `sockaddr_in6` place `addr`,
+/// render it as a `std::net::SocketAddr`, and return whether it is IPv6.
+fn sock_addr_str(cx: &RefactorCtxt, e: &Expr) -> Option<(String, bool)> {
+    let place = match_or!([strip_casts(e).kind] ExprKind::AddrOf(_, _, ref p) => p; return None);
+    let adt = match_or!([cx.opt_node_type(place.id)?.kind] TyKind::Adt(adt, _) => adt;
+                        return None);
+    let place = pprust::expr_to_string(place);
+    // The port, address and flow label are stored in network byte order.
+    match &*cx.ty_ctxt().item_name(adt.did).as_str() {
+        "sockaddr_in" => Some((format!(
+            "::std::net::SocketAddr::from(::std::net::SocketAddrV4::new(\
+                 ::std::net::Ipv4Addr::from(u32::from_be({0}.sin_addr.s_addr)), \
+                 u16::from_be({0}.sin_port)))",
+            place), false)),
+        "sockaddr_in6" => Some((format!(
+            "::std::net::SocketAddr::from(::std::net::SocketAddrV6::new(\
+                 ::std::net::Ipv6Addr::from(*(&{0}.sin6_addr as *const _ as *const [u8; 16])), \
+                 u16::from_be({0}.sin6_port), u32::from_be({0}.sin6_flowinfo), \
+                 {0}.sin6_scope_id))",
+            place), true)),
+        _ => None,
+    }
+}
+
+
+/// # `socket_to_std_net` Command
+///
+/// Usage: `socket_to_std_net`
+///
+/// Marks: `target`
+///
+/// Convert each marked local socket descriptor that is initialized with
+/// `socket(AF_INET, ...)` or `socket(AF_INET6, ...)`, or with
+/// `accept(fd, NULL, NULL)` on a converted listening socket, into an `Option`
+/// of a `std::net` socket:
+///
+///  * A `SOCK_STREAM` socket that is passed to `listen` becomes an
+///    `Option<TcpListener>`, and `bind` creates the listener with
+///    `TcpListener::bind`.  `listen` has no effect, since `std` uses its own
+///    backlog.
+///  * Other `SOCK_STREAM` sockets become an `Option<TcpStream>`, created by
+///    `connect` with `TcpStream::connect`.  Sockets initialized with `accept`
+///    become an `Option<TcpStream>` holding the accepted connection.
+///  * `SOCK_DGRAM` sockets become an `Option<UdpSocket>`, created by `bind`,
+///    or by `connect` if they weren't bound yet.
+///
+/// `recv` and `send` with no flags become `Read::read` and `Write::write` (or
+/// `UdpSocket::recv` and `send`), `close` statements set the variable to
+/// `None`, and the usual error checks on the descriptor, like `fd < 0`, check
+/// whether it is `None`.  Since the `std` socket isn't created until it's bound
+/// or connected, errors from `socket` are reported by `bind` and `connect`
+/// instead, and checks on the result of `socket` become `false`.
+///
+/// The address passed to `bind` and `connect` must be `&addr` for a
+/// `sockaddr_in` or `sockaddr_in6` variable or field `addr`; it's converted to
+/// a `SocketAddr` at the call.  Sockets with other uses, and raw sockets, are
+/// left unchanged, and a warning is printed.  `TcpListener::bind` sets
+/// `SO_REUSEADDR` on Unix, which C programs usually do with `setsockopt`.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut fd: libc::c_int = socket(2 as libc::c_int, SOCK_STREAM as libc::c_int, 0);
+///     if bind(fd, &mut addr as *mut sockaddr_in as *mut sockaddr,
+///             ::std::mem::size_of::<sockaddr_in>() as socklen_t) < 0 {
+///         return -1;
+///     }
+///     listen(fd, 5);
+///     let mut client: libc::c_int = accept(fd, 0 as *mut sockaddr, 0 as *mut socklen_t);
+/// ```
+///
+/// After running `socket_to_std_net`, with both `let`s marked:
+///
+/// ```ignore
+///     let mut fd: Option<::std::net::TcpListener> = None;
+///     if {
+///         fd = ::std::net::TcpListener::bind(::std::net::SocketAddr::from(...)).ok();
+///         if fd.is_some() { 0 } else { -1 }
+///     } < 0 {
+///         return -1;
+///     }
+///     if fd.is_some() { 0 } else { -1 };
+///     let mut client: Option<::std::net::TcpStream> =
+///         fd.as_ref().and_then(|l| l.accept().ok()).map(|(s, _)| s);
+/// ```
+pub struct SocketToStdNet;
+
+impl Transform for SocketToStdNet {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let var_of = |e: &Expr| -> Option<HirId> {
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            cx.try_resolve_expr_to_hid(e)
+        };
+
+        // (1) Collect the marked locals that are initialized with `socket` or `accept`.
+
+        let mut vars: HashMap<HirId, SockVar> = HashMap::new();
+        let mut accepts = Vec::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            let hid = cx.hir_map().node_to_hir_id(l.pat.id);
+            let init = match l.init {
+                Some(ref init) => init,
+                None => return,
+            };
+            if let Some((kind, v6)) = as_socket(init) {
+                vars.insert(hid, SockVar { kind, v6, accepted_from: None });
+            } else if let Some(listener) = as_accept(init).and_then(|fd| var_of(fd)) {
+                accepts.push((hid, listener, strip_casts(init).id));
+            } else {
+                warn!("local `{}` is not initialized with a supported `socket` or `accept` \
+                       call; skipping it", pprust::pat_to_string(&l.pat));
+            }
+        });
+
+        // Stream sockets that are passed to `listen` are listeners.
+        visit_nodes(krate, |e: &Expr| {
+            if let Some((SockOp::Listen, fd)) = as_sock_op(e) {
+                if let Some(var) = var_of(fd).and_then(|v| vars.get_mut(&v)) {
+                    if var.kind == SockKind::Stream {
+                        var.kind = SockKind::Listener;
+                    }
+                }
+            }
+        });
+        for &(hid, listener, _) in &accepts {
+            if let Some(&SockVar { v6, .. }) = vars.get(&listener) {
+                vars.insert(hid, SockVar {
+                    kind: SockKind::Stream,
+                    v6,
+                    accepted_from: Some(listener),
+                });
+            }
+        }
+        let accept_inits = accepts.iter().map(|&(_, _, id)| id).collect::<HashSet<_>>();
+
+        // (2) Check that every use of each socket is one we can convert.  Removing a listener
+        // makes the `accept`s on it unconvertible, and the other way around, so repeat until
+        // nothing changes.
+
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                stmt_exprs.insert(e.id);
+            }
+        });
+        loop {
+            let mut ok_uses = HashSet::new();
+            let mut uses = Vec::new();
+            visit_nodes(krate, |e: &Expr| {
+                if let Some(var) = var_of(e).filter(|v| vars.contains_key(v)) {
+                    uses.push((e.id, var));
+                }
+                let (op, fd) = match_or!([as_sock_op(e)] Some(x) => x; return);
+                let var = match var_of(fd).and_then(|v| vars.get(&v)) {
+                    Some(var) => var,
+                    None => return,
+                };
+                let args: &[P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref args) => args,
+                    _ => &[],
+                };
+                let ok = var.allows(op) && match op {
+                    SockOp::Bind | SockOp::Connect => sock_addr_str(cx, &args[1])
+                        .map_or(false, |(_, v6)| v6 == var.v6),
+                    SockOp::Accept => accept_inits.contains(&e.id),
+                    SockOp::Close => stmt_exprs.contains(&e.id),
+                    _ => true,
+                };
+                if ok {
+                    ok_uses.insert(fd.id);
+                }
+            });
+
+            let mut bad = HashSet::new();
+            for (id, var) in uses {
+                if !ok_uses.contains(&id) {
+                    bad.insert(var);
+                }
+            }
+            for (&hid, var) in &vars {
+                if var.accepted_from.map_or(false, |l| !vars.contains_key(&l)) {
+                    bad.insert(hid);
+                }
+            }
+            if bad.is_empty() {
+                break;
+            }
+            for var in bad {
+                warn!("{:?} has uses that can't be converted; skipping it", var);
+                vars.remove(&var);
+            }
+        }
+        if vars.is_empty() {
+            return;
+        }
+        let sock_var = |e: &Expr| var_of(e).and_then(|v| vars.get(&v));
+
+        // (3) Change the types and initializers of the sockets.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = match vars.get(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                Some(var) => var,
+                None => return,
+            };
+            if l.ty.is_some() {
+                l.ty = Some(parse_ty(cx.session(), var.ty_str()));
+            }
+            let src = match var.accepted_from {
+                Some(_) => {
+                    let listener = as_accept(l.init.as_ref().unwrap()).unwrap();
+                    format!("{}.as_ref().and_then(|l| l.accept().ok()).map(|(s, _)| s)",
+                            pprust::expr_to_string(listener))
+                }
+                None => "None".to_owned(),
+            };
+            l.init = Some(parse_expr(cx.session(), &src));
+        });
+
+        // (4) Rewrite the `close` statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                    match as_sock_op(e) {
+                        Some((SockOp::Close, fd)) if sock_var(fd).is_some() =>
+                            format!("{} = None;", pprust::expr_to_string(fd)),
+                        _ => continue,
+                    }
+                };
+                *s = parse_stmts(cx.session(), &src).lone();
+            }
+        });
+
+        // (5) Rewrite the other uses.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = {
+                let (op, fd) = match_or!([as_sock_op(e)] Some(x) => x; return);
+                let var = match sock_var(fd) {
+                    Some(var) => var,
+                    None => return,
+                };
+                let fd = pprust::expr_to_string(fd);
+                let args: &[P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref args) => args,
+                    _ => &[],
+                };
+                let ret_ty = || cx.opt_node_type(e.id)
+                    .map(|ty| pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ty)))
+                    .unwrap_or_else(|| "_".to_owned());
+                let created = format!("if {}.is_some() {{ 0 }} else {{ -1 }}", fd);
+                match op {
+                    SockOp::Check(failed) => match (var.accepted_from, failed) {
+                        (Some(_), true) => format!("{}.is_none()", fd),
+                        (Some(_), false) => format!("{}.is_some()", fd),
+                        (None, failed) => (!failed).to_string(),
+                    },
+                    SockOp::Bind => {
+                        let ty = match var.kind {
+                            SockKind::Udp => "UdpSocket",
+                            _ => "TcpListener",
+                        };
+                        let (addr, _) = sock_addr_str(cx, &args[1]).unwrap();
+                        format!("{{ {} = ::std::net::{}::bind({}).ok(); {} }}",
+                                fd, ty, addr, created)
+                    }
+                    SockOp::Listen => created,
+                    SockOp::Connect => {
+                        let (addr, _) = sock_addr_str(cx, &args[1]).unwrap();
+                        if var.kind == SockKind::Udp {
+                            let unspecified = if var.v6 { "[::]:0" } else { "0.0.0.0:0" };
+                            format!("{{ if {0}.is_none() {{ \
+                                         {0} = ::std::net::UdpSocket::bind({1:?}).ok(); \
+                                     }} \
+                                     {0}.as_ref().map_or(-1, |s| \
+                                         s.connect({2}).map_or(-1, |()| 0)) }}",
+                                    fd, unspecified, addr)
+                        } else {
+                            format!("{{ {} = ::std::net::TcpStream::connect({}).ok(); {} }}",
+                                    fd, addr, created)
+                        }
+                    }
+                    SockOp::Recv | SockOp::Send => {
+                        let (slice, ptr_ty) = if op == SockOp::Recv {
+                            ("from_raw_parts_mut", "*mut u8")
+                        } else {
+                            ("from_raw_parts", "*const u8")
+                        };
+                        let call = match (var.kind, op) {
+                            (SockKind::Udp, SockOp::Recv) => "s.recv(buf)",
+                            (SockKind::Udp, _) => "s.send(buf)",
+                            (_, SockOp::Recv) => "::std::io::Read::read(s, buf)",
+                            _ => "::std::io::Write::write(s, buf)",
+                        };
+                        let sock = match var.kind {
+                            SockKind::Udp => "as_ref",
+                            _ => "as_mut",
+                        };
+                        format!("{{ let (sock, buf) = ({}.{}(), ::std::slice::{}({}, {})); \
+                                 sock.map_or(-1, |s| {}.map_or(-1, |n| n as {})) }}",
+                                fd, sock, slice, cast_str(&args[1], ptr_ty),
+                                cast_str(&args[2], "usize"), call, ret_ty())
+                    }
+                    // The `accept` calls were replaced along with the initializers.
+                    SockOp::Accept | SockOp::Close => return,
+                }
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("socket_to_std_net", |_args| mk(SocketToStdNet));
+}
@@ -1,15 +1,23 @@
 use std::collections::{HashMap, HashSet};
 use std::mem;
 use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
 use syntax::ast::*;
+use syntax::mut_visit::{self, MutVisitor};
+use syntax::print::pprust;
 use syntax::ptr::P;
+use syntax::source_map::Span;
 use syntax::symbol::Symbol;
-use smallvec::smallvec;
+use syntax::visit::{self, Visitor};
+use syntax::ThinVec;
+use smallvec::{smallvec, SmallVec};
 
-use crate::ast_manip::{FlatMapNodes, MutVisitNodes, fold_modules};
-use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::analysis::thread_sharing;
+use crate::ast_manip::{FlatMapNodes, MutVisit, MutVisitNodes, fold_modules, visit_nodes};
+use crate::ast_manip::fn_edit::{mut_visit_fns, visit_fns};
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
 use crate::command::{CommandState, Registry};
-use crate::driver::{parse_expr};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
 use crate::matcher::{Bindings, BindingType, MatchCtxt, Subst, mut_visit_match_with};
 use crate::path_edit::fold_resolved_paths;
 use crate::transform::Transform;
@@ -495,6 +503,556 @@ impl Transform for StaticToLocal {
     }
 }
 
+/// # `fix_static_mut` Command
+///
+/// Usage: `fix_static_mut`
+///
+/// Marks: `target`, `atomic`, `mutex`, `rwlock`, `once_lock`
+///
+/// Convert each `static mut` marked `target` into an immutable static of a type that is safe to
+/// share between threads, and rewrite every access to it, including those inside `unsafe`
+/// blocks.  The replacement is chosen separately for each static:
+///
+///  * Integers and `bool`s that are only modified by assignment or compound assignment become
+///    the corresponding `std::sync::atomic` type.  Reads become `load`s, and writes become
+///    `store`s or `fetch_*` operations, all using `SeqCst` ordering.  This isn't done for statics
+///    that may be accessed by more than one thread at once (see `analyze_thread_sharing`) and
+///    that are updated in ways that have no single atomic operation, like `X = X * 2` or
+///    `X *= 2`, since the separate `load` and `store` could lose concurrent updates.
+///  * Statics that are only modified by plain assignments, all within a single function, are
+///    treated as init-once data and become `OnceLock`s.  The assignment becomes a call to `set`,
+///    which panics if the static is initialized twice, and reads become calls to `get`, which
+///    panic if it is not initialized yet.  The original initializer is discarded.
+///  * All other statics become `Mutex`es.  Each access locks the mutex for the rest of the
+///    statement.  A statement that accesses the same static more than once locks it only once.
+///    A static is left unchanged if a statement holding its lock calls a function that may
+///    access it again, since locking it twice would deadlock.
+///
+/// Marking a static `atomic`, `mutex`, `rwlock`, or `once_lock` requests a particular
+/// replacement.  A static marked `rwlock` becomes an `RwLock`, with reads taking a read lock and
+/// all other accesses taking a write lock.  If the requested replacement can't be used, the
+/// static becomes a `Mutex` instead.
+///
+/// Statics converted to `Mutex`, `RwLock`, or `OnceLock` must have a type that can be shared
+/// between threads, which rules out raw pointers and structs containing them.
+///
+/// Example:
+///
+/// ```ignore
+///     static mut COUNT: i32 = 0;
+///     static mut CONFIG: Config = Config { verbose: 0 };
+///     static mut STATE: State = State { len: 0, total: 0 };
+///
+///     unsafe fn init(verbose: i32) {
+///         CONFIG = Config { verbose };
+///     }
+///
+///     unsafe fn push(x: i32) {
+///         COUNT += 1;
+///         STATE.len += 1;
+///         STATE.total = STATE.total + x;
+///         if CONFIG.verbose != 0 {
+///             println!("{}", COUNT);
+///         }
+///     }
+/// ```
+///
+/// After running `fix_static_mut`, with all three statics marked `target`:
+///
+/// ```ignore
+///     static COUNT: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+///     static CONFIG: ::std::sync::OnceLock<Config> = ::std::sync::OnceLock::new();
+///     static STATE: ::std::sync::Mutex<State> =
+///         ::std::sync::Mutex::new(State { len: 0, total: 0 });
+///
+///     unsafe fn init(verbose: i32) {
+///         CONFIG.set(Config { verbose })
+///             .unwrap_or_else(|_| panic!("`CONFIG` initialized twice"));
+///     }
+///
+///     unsafe fn push(x: i32) {
+///         COUNT.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+///         (*STATE.lock().unwrap()).len += 1;
+///         {
+///             let mut state_guard = STATE.lock().unwrap();
+///             (*state_guard).total = (*state_guard).total + x;
+///         }
+///         if (*CONFIG.get().expect("`CONFIG` used before initialization")).verbose != 0 {
+///             println!("{}", COUNT.load(::std::sync::atomic::Ordering::SeqCst));
+///         }
+///     }
+/// ```
+pub struct FixStaticMut;
+
+/// The safe replacement for a `static mut`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Strategy {
+    Atomic,
+    Mutex,
+    RwLock,
+    OnceLock,
+}
+
+struct StaticMut {
+    ident: Ident,
+    strategy: Strategy,
+    /// The name of the `std::sync::atomic` type to use, if the static's type has one.
+    atomic: Option<String>,
+}
+
+/// The ways a `static mut` is modified throughout the crate.
+#[derive(Default)]
+struct StaticWrites {
+    /// Uses in mutable lvalue contexts, including the ones counted below.
+    mut_uses: usize,
+    /// Plain assignments to the whole static.
+    assigns: usize,
+    /// Compound assignments to the whole static.
+    assign_ops: usize,
+    /// The functions containing plain assignments.
+    assign_fns: HashSet<NodeId>,
+    /// Assignments that read the static itself, and compound assignments with no `fetch_*`
+    /// equivalent.
+    read_modify_writes: usize,
+}
+
+const SEQ_CST: &str = "::std::sync::atomic::Ordering::SeqCst";
+
+/// Get the name of the atomic type that can replace a static of type `ty`, if there is one.
+fn atomic_type_name(ty: rustc::ty::Ty) -> Option<String> {
+    match ty.kind {
+        TcxTyKind::Bool => Some("AtomicBool".to_owned()),
+        TcxTyKind::Int(IntTy::I128) | TcxTyKind::Uint(UintTy::U128) => None,
+        TcxTyKind::Int(_) | TcxTyKind::Uint(_) => {
+            let name = ty.to_string();
+            Some(format!("Atomic{}{}", name[..1].to_uppercase(), &name[1..]))
+        }
+        _ => None,
+    }
+}
+
+/// If `e` is a path referring to one of `statics`, get the static's `DefId`.
+fn static_of<V>(cx: &RefactorCtxt, statics: &HashMap<DefId, V>, e: &Expr) -> Option<DefId> {
+    match e.kind {
+        ExprKind::Path(..) => cx.try_resolve_expr(e).filter(|did| statics.contains_key(did)),
+        _ => None,
+    }
+}
+
+fn is_locked(info: &StaticMut) -> bool {
+    info.strategy == Strategy::Mutex || info.strategy == Strategy::RwLock
+}
+
+/// Counts the uses of `Mutex` and `RwLock` statics in a statement.  Statements in nested blocks
+/// take their own locks, so uses inside nested blocks aren't counted, except under a `match`,
+/// `if let`, `while let`, or `for`, where the scrutinee's temporaries (including any lock guards)
+/// live until the end of the whole expression.
+struct LockUses<'a, 'b, 'tcx: 'b> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    statics: &'a HashMap<DefId, StaticMut>,
+    /// The number of uses of each static, along with the text of its first use.
+    uses: HashMap<DefId, (usize, String)>,
+    /// The functions called or used as values while the statement's locks are held.
+    fns: HashSet<DefId>,
+    in_scrutinee_scope: bool,
+}
+
+impl<'a, 'b, 'tcx> LockUses<'a, 'b, 'tcx> {
+    fn new(cx: &'a RefactorCtxt<'b, 'tcx>, statics: &'a HashMap<DefId, StaticMut>) -> Self {
+        LockUses {
+            cx,
+            statics,
+            uses: HashMap::new(),
+            fns: HashSet::new(),
+            in_scrutinee_scope: false,
+        }
+    }
+
+    /// Count the uses in the statement `s`.  Returns `false` for statements that can't use any
+    /// statics.
+    fn visit_lock_stmt(&mut self, s: &Stmt) -> bool {
+        match s.kind {
+            StmtKind::Local(ref l) => {
+                if let Some(ref init) = l.init {
+                    self.visit_expr(init);
+                }
+            }
+            StmtKind::Expr(ref e) | StmtKind::Semi(ref e) => self.visit_expr(e),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<'a, 'b, 'tcx, 'ast> Visitor<'ast> for LockUses<'a, 'b, 'tcx> {
+    fn visit_expr(&mut self, e: &'ast Expr) {
+        if let Some(did) = static_of(self.cx, self.statics, e) {
+            if is_locked(&self.statics[&did]) {
+                self.uses.entry(did)
+                    .or_insert_with(|| (0, pprust::expr_to_string(e)))
+                    .0 += 1;
+            }
+        }
+        let callee = match e.kind {
+            ExprKind::Path(..) => self.cx.try_resolve_expr(e),
+            ExprKind::MethodCall(..) => self.cx.opt_callee(e),
+            _ => None,
+        };
+        self.fns.extend(callee);
+
+        let scrutinee_scope = match e.kind {
+            ExprKind::Match(..) | ExprKind::ForLoop(..) => true,
+            ExprKind::If(ref cond, ..) | ExprKind::While(ref cond, ..) =>
+                matches!([cond.kind] ExprKind::Let(..)),
+            _ => false,
+        };
+        let old = self.in_scrutinee_scope;
+        self.in_scrutinee_scope |= scrutinee_scope;
+        visit::walk_expr(self, e);
+        self.in_scrutinee_scope = old;
+    }
+
+    fn visit_block(&mut self, b: &'ast Block) {
+        if self.in_scrutinee_scope {
+            visit::walk_block(self, b);
+        }
+    }
+
+    fn visit_mac(&mut self, mac: &'ast Mac) {
+        visit::walk_mac(self, mac)
+    }
+}
+
+fn block_expr(mut stmts: Vec<Stmt>, last: Stmt, span: Span) -> P<Expr> {
+    stmts.push(last);
+    let block = P(Block {
+        stmts,
+        id: DUMMY_NODE_ID,
+        rules: BlockCheckMode::Default,
+        span,
+    });
+    P(Expr {
+        id: DUMMY_NODE_ID,
+        kind: ExprKind::Block(block, None),
+        span,
+        attrs: ThinVec::new(),
+    })
+}
+
+/// If `s` uses some `Mutex` or `RwLock` statics more than once, lock each of them once, in a new
+/// block around the statement, and rewrite all their uses in the statement to go through the lock
+/// guards.
+fn lock_stmt(cx: &RefactorCtxt, statics: &HashMap<DefId, StaticMut>, mut s: Stmt) -> Stmt {
+    let mut v = LockUses::new(cx, statics);
+    if !v.visit_lock_stmt(&s) {
+        return s;
+    }
+
+    let mut locked = v.uses.into_iter()
+        .filter(|&(_, (count, _))| count > 1)
+        .map(|(did, (_, path))| (did, path))
+        .collect::<Vec<_>>();
+    if locked.is_empty() {
+        return s;
+    }
+    locked.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut guards = Vec::new();
+    for (did, path) in locked {
+        let info = &statics[&did];
+        let guard = format!("{}_guard", info.ident.as_str().to_lowercase());
+        let method = if info.strategy == Strategy::RwLock { "write" } else { "lock" };
+        guards.extend(parse_stmts(
+            cx.session(), &format!("let mut {} = {}.{}().unwrap();", guard, path, method)));
+
+        let guard_expr = parse_expr(cx.session(), &format!("(*{})", guard));
+        MutVisitNodes::visit(&mut s, |e: &mut P<Expr>| {
+            if static_of(cx, statics, e) == Some(did) {
+                *e = guard_expr.clone();
+            }
+        });
+    }
+
+    let span = s.span;
+    let kind = match s.kind {
+        StmtKind::Local(mut l) => {
+            let init = l.init.take().unwrap();
+            let init_stmt = Stmt { id: DUMMY_NODE_ID, span, kind: StmtKind::Expr(init) };
+            l.init = Some(block_expr(guards, init_stmt, span));
+            StmtKind::Local(l)
+        }
+        StmtKind::Expr(e) => {
+            let e_stmt = Stmt { id: DUMMY_NODE_ID, span, kind: StmtKind::Expr(e) };
+            StmtKind::Expr(block_expr(guards, e_stmt, span))
+        }
+        StmtKind::Semi(e) => {
+            let e_stmt = Stmt { id: DUMMY_NODE_ID, span, kind: StmtKind::Semi(e) };
+            StmtKind::Expr(block_expr(guards, e_stmt, span))
+        }
+        _ => unreachable!(),
+    };
+    Stmt { kind, ..s }
+}
+
+/// Find the `Mutex` and `RwLock` statics that some statement holds the lock of while calling a
+/// function that may access them again.  `fn_statics` maps each function to the statics it may
+/// access.
+fn relocked_statics(cx: &RefactorCtxt,
+                    krate: &Crate,
+                    statics: &HashMap<DefId, StaticMut>,
+                    fn_statics: &HashMap<DefId, HashSet<DefId>>) -> HashSet<DefId> {
+    let mut relocked = HashSet::new();
+    visit_nodes(krate, |s: &Stmt| {
+        let mut v = LockUses::new(cx, statics);
+        if !v.visit_lock_stmt(s) {
+            return;
+        }
+        for &did in v.uses.keys() {
+            if v.fns.iter().any(|f| fn_statics.get(f).map_or(false, |ss| ss.contains(&did))) {
+                relocked.insert(did);
+            }
+        }
+    });
+    relocked
+}
+
+struct LockStmts<'a, 'b, 'tcx: 'b> {
+    cx: &'a RefactorCtxt<'b, 'tcx>,
+    statics: &'a HashMap<DefId, StaticMut>,
+}
+
+impl<'a, 'b, 'tcx> MutVisitor for LockStmts<'a, 'b, 'tcx> {
+    fn flat_map_stmt(&mut self, s: Stmt) -> SmallVec<[Stmt; 1]> {
+        // Outer statements are handled first, so a statement that takes a lock for its whole
+        // duration has no remaining uses in its nested statements.
+        let s = lock_stmt(self.cx, self.statics, s);
+        mut_visit::noop_flat_map_stmt(s, self)
+    }
+
+    fn visit_mac(&mut self, mac: &mut Mac) {
+        mut_visit::noop_visit_mac(mac, self)
+    }
+}
+
+impl Transform for FixStaticMut {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the marked `static mut`s.
+
+        let mut writes = HashMap::new();
+        let mut marked = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if !st.marked(i.id, "target") {
+                return;
+            }
+            if let ItemKind::Static(_, Mutability::Mutable, _) = i.kind {
+                let did = cx.node_def_id(i.id);
+                writes.insert(did, StaticWrites::default());
+                marked.push((did, i.id, i.ident));
+            }
+        });
+
+        // (2) Find out how each one is modified.
+
+        visit_nodes(krate, |e: &Expr| {
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    if let Some(did) = static_of(cx, &writes, lhs) {
+                        let mut reads_self = false;
+                        visit_nodes(&**rhs, |e: &Expr| {
+                            reads_self |= static_of(cx, &writes, e) == Some(did);
+                        });
+                        let w = writes.get_mut(&did).unwrap();
+                        w.assigns += 1;
+                        if reads_self {
+                            w.read_modify_writes += 1;
+                        }
+                    }
+                }
+                ExprKind::AssignOp(op, ref lhs, _) => {
+                    if let Some(did) = static_of(cx, &writes, lhs) {
+                        let w = writes.get_mut(&did).unwrap();
+                        w.assign_ops += 1;
+                        if !matches!([op.node] BinOpKind::Add, BinOpKind::Sub, BinOpKind::BitAnd,
+                                     BinOpKind::BitOr, BinOpKind::BitXor) {
+                            w.read_modify_writes += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref b) => b; return);
+            visit_nodes(&**block, |e: &Expr| {
+                let lhs = match_or!([e.kind] ExprKind::Assign(ref lhs, _) => lhs; return);
+                if let Some(did) = static_of(cx, &writes, lhs) {
+                    writes.get_mut(&did).unwrap().assign_fns.insert(fl.id);
+                }
+            });
+        });
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if ectx != lr_expr::Context::LvalueMut {
+                return;
+            }
+            if let Some(did) = static_of(cx, &writes, e) {
+                writes.get_mut(&did).unwrap().mut_uses += 1;
+            }
+        });
+
+        // (3) Pick a replacement for each static.  Updates that aren't a single atomic operation
+        // are only safe if no other thread can access the static at the same time.
+
+        let sharing = thread_sharing::analyze(cx, krate);
+        let mut statics = HashMap::new();
+        for (did, id, ident) in marked {
+            let w = &writes[&did];
+            let atomic = atomic_type_name(cx.ty_ctxt().type_of(did));
+            let racy = w.read_modify_writes > 0 && sharing.shared_statics.contains(&did);
+            if racy {
+                info!("`{}` is shared between threads and has non-atomic updates", ident);
+            }
+            let atomic_ok = atomic.is_some() && w.mut_uses == w.assigns + w.assign_ops && !racy;
+            let once_ok = w.assigns > 0 && w.mut_uses == w.assigns && w.assign_fns.len() == 1;
+
+            let requested = [
+                ("atomic", Strategy::Atomic),
+                ("mutex", Strategy::Mutex),
+                ("rwlock", Strategy::RwLock),
+                ("once_lock", Strategy::OnceLock),
+            ].iter().find(|&&(mark, _)| st.marked(id, mark)).map(|&(_, s)| s);
+
+            let strategy = match requested {
+                Some(Strategy::Atomic) if atomic_ok => Strategy::Atomic,
+                Some(Strategy::OnceLock) if once_ok => Strategy::OnceLock,
+                Some(s @ Strategy::Mutex) | Some(s @ Strategy::RwLock) => s,
+                Some(s) => {
+                    warn!("can't convert `{}` to {:?}; using Mutex instead", ident, s);
+                    Strategy::Mutex
+                }
+                None if atomic_ok => Strategy::Atomic,
+                None if once_ok => Strategy::OnceLock,
+                None => Strategy::Mutex,
+            };
+            info!("converting `{}` to {:?}", ident, strategy);
+            statics.insert(did, StaticMut { ident, strategy, atomic });
+        }
+
+        // A thread can't take a `Mutex` it already holds, so locked statics that are accessed
+        // again by a function called while their lock is held are left alone.
+        for did in relocked_statics(cx, krate, &statics, &sharing.fn_statics) {
+            warn!("`{}` is accessed by a function called while it's locked; leaving it unchanged",
+                  statics[&did].ident);
+            statics.remove(&did);
+        }
+
+        // (4) Rewrite the static items.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let (ty, init) = match_or!([i.kind] ItemKind::Static(ref ty, _, ref init) =>
+                                       (ty.clone(), init.clone()); return);
+            let info = match_or!([statics.get(&cx.node_def_id(i.id))] Some(x) => x; return);
+
+            let (new_ty, new_init) = match info.strategy {
+                Strategy::Atomic => {
+                    let path = format!("::std::sync::atomic::{}", info.atomic.as_ref().unwrap());
+                    (path.clone(), format!("{}::new(__e)", path))
+                }
+                Strategy::Mutex => ("::std::sync::Mutex<__t>".to_owned(),
+                                    "::std::sync::Mutex::new(__e)".to_owned()),
+                Strategy::RwLock => ("::std::sync::RwLock<__t>".to_owned(),
+                                     "::std::sync::RwLock::new(__e)".to_owned()),
+                Strategy::OnceLock => ("::std::sync::OnceLock<__t>".to_owned(),
+                                       "::std::sync::OnceLock::new()".to_owned()),
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("__t", ty);
+            bnd.add("__e", init);
+            let new_ty = parse_ty(cx.session(), &new_ty).subst(st, cx, &bnd);
+            let new_init = parse_expr(cx.session(), &new_init).subst(st, cx, &bnd);
+            i.kind = ItemKind::Static(new_ty, Mutability::Immutable, new_init);
+        });
+
+        // (5) Lock `Mutex` and `RwLock` statics once in statements that use them more than once.
+
+        krate.visit(&mut LockStmts { cx, statics: &statics });
+
+        // (6) Rewrite writes to atomics and `OnceLock`s.
+
+        let mut handled = HashSet::new();
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let new_e = match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) => {
+                    let did = match_or!([static_of(cx, &statics, lhs)] Some(x) => x; return);
+                    let info = &statics[&did];
+                    let src = match info.strategy {
+                        Strategy::Atomic => format!("__x.store(__e, {})", SEQ_CST),
+                        Strategy::OnceLock => format!(
+                            "__x.set(__e).unwrap_or_else(|_| panic!(\"`{}` initialized twice\"))",
+                            info.ident),
+                        _ => return,
+                    };
+                    handled.insert(lhs.id);
+                    let mut bnd = Bindings::new();
+                    bnd.add("__x", lhs.clone());
+                    bnd.add("__e", rhs.clone());
+                    parse_expr(cx.session(), &src).subst(st, cx, &bnd)
+                }
+
+                ExprKind::AssignOp(op, ref lhs, ref rhs) => {
+                    let did = match_or!([static_of(cx, &statics, lhs)] Some(x) => x; return);
+                    if statics[&did].strategy != Strategy::Atomic {
+                        return;
+                    }
+                    let src = match op.node {
+                        BinOpKind::Add => format!("__x.fetch_add(__e, {})", SEQ_CST),
+                        BinOpKind::Sub => format!("__x.fetch_sub(__e, {})", SEQ_CST),
+                        BinOpKind::BitAnd => format!("__x.fetch_and(__e, {})", SEQ_CST),
+                        BinOpKind::BitOr => format!("__x.fetch_or(__e, {})", SEQ_CST),
+                        BinOpKind::BitXor => format!("__x.fetch_xor(__e, {})", SEQ_CST),
+                        _ => format!("__x.store(__x.load({0}) {1} __e, {0})",
+                                     SEQ_CST, op.node.to_string()),
+                    };
+                    handled.insert(lhs.id);
+                    let mut bnd = Bindings::new();
+                    bnd.add("__x", lhs.clone());
+                    bnd.add("__e", rhs.clone());
+                    parse_expr(cx.session(), &src).subst(st, cx, &bnd)
+                }
+
+                _ => return,
+            };
+            *e = new_e;
+        });
+
+        // (7) Rewrite all remaining accesses.
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) {
+                return;
+            }
+            let did = match_or!([static_of(cx, &statics, e)] Some(x) => x; return);
+            let info = &statics[&did];
+            let src = match (info.strategy, ectx) {
+                (Strategy::Atomic, _) => format!("__x.load({})", SEQ_CST),
+                (Strategy::Mutex, _) => "(*__x.lock().unwrap())".to_owned(),
+                (Strategy::RwLock, lr_expr::Context::LvalueMut) =>
+                    "(*__x.write().unwrap())".to_owned(),
+                (Strategy::RwLock, _) => "(*__x.read().unwrap())".to_owned(),
+                (Strategy::OnceLock, _) => format!(
+                    "(*__x.get().expect(\"`{}` used before initialization\"))", info.ident),
+            };
+            let mut bnd = Bindings::new();
+            bnd.add("__x", e.clone());
+            *e = parse_expr(cx.session(), &src).subst(st, cx, &bnd);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
 
 
 
@@ -507,4 +1065,5 @@ pub fn register_commands(reg: &mut Registry) {
     }));
     reg.register("static_to_local_ref", |_args| mk(Localize));
     reg.register("static_to_local", |_args| mk(StaticToLocal));
+    reg.register("fix_static_mut", |_args| mk(FixStaticMut));
 }
@@ -0,0 +1,1382 @@
+//! Transforms for replacing C string handling with Rust string types.
+
+use std::ascii;
+use std::collections::{HashMap, HashSet};
+use std::str;
+use rustc::hir::HirId;
+use rustc::hir::def_id::DefId;
+use rustc::ty::{self, TyKind};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use smallvec::smallvec;
+
+use c2rust_ast_builder::mk;
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::ast_manip::fn_edit::mut_visit_fns;
+use crate::analysis::string_provenance::{node_provenance, Provenance};
+use crate::ast_manip::lr_expr::{self, fold_exprs_with_context};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::matcher::{Bindings, Subst};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::format::{build_format_macro, maybe_non_utf8};
+use crate::transform::heap::{field_def_id, freed_expr, is_alloc_call, is_call_to};
+use crate::transform::heap::{is_null_ptr, strip_casts};
+use crate::RefactorCtxt;
+
+
+/// The Rust type that a C string is converted to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StrKind {
+    /// `&str`.  Conversion from C panics if the string is not valid UTF-8.
+    Str,
+    /// `&CStr`.  Conversion is lossless, but string operations are less convenient.
+    CStr,
+}
+
+impl StrKind {
+    /// Parse a `KIND` argument.  `auto` gives `None`, meaning the kind is chosen separately for
+    /// each string.
+    fn from_arg(s: &str) -> Option<StrKind> {
+        match s {
+            "str" => Some(StrKind::Str),
+            "cstr" => Some(StrKind::CStr),
+            "auto" => None,
+            _ => panic!("unknown string kind `{}` (expected `str`, `cstr`, or `auto`)", s),
+        }
+    }
+}
+
+/// Check if `ty` is `*const c_char` (either signedness).
+fn is_const_char_ptr(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::RawPtr(mt) if mt.mutbl == Mutability::Immutable =>
+            matches!([mt.ty.kind] TyKind::Int(IntTy::I8), TyKind::Uint(UintTy::U8)),
+        _ => false,
+    }
+}
+
+/// A string-typed location selected for conversion: either a function argument (identified by its
+/// binding) or a struct field.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum StrVar {
+    Local(HirId),
+    Field(DefId),
+}
+
+/// Templates for converting between C strings and the new string type.
+struct StrTemplates {
+    /// `*const c_char` to the new type.  Uses `__e`.
+    from_ptr: P<Expr>,
+    /// The new type back to a pointer, for passing to code that still expects `*const c_char`.
+    to_ptr: P<Expr>,
+    /// The new type to `&[u8]`, excluding the terminator.
+    to_bytes: P<Expr>,
+    /// `*const c_char` to `&[u8]`, excluding the terminator.
+    ptr_to_bytes: P<Expr>,
+}
+
+impl StrTemplates {
+    fn new(cx: &RefactorCtxt, kind: StrKind) -> StrTemplates {
+        let (from_ptr, to_ptr, to_bytes) = match kind {
+            StrKind::Str => (
+                "::std::ffi::CStr::from_ptr(__e).to_str().unwrap()",
+                "::std::ffi::CString::new(__e).unwrap().as_ptr()",
+                "__e.as_bytes()",
+            ),
+            StrKind::CStr => (
+                "::std::ffi::CStr::from_ptr(__e)",
+                "__e.as_ptr()",
+                "__e.to_bytes()",
+            ),
+        };
+        StrTemplates {
+            from_ptr: parse_expr(cx.session(), from_ptr),
+            to_ptr: parse_expr(cx.session(), to_ptr),
+            to_bytes: parse_expr(cx.session(), to_bytes),
+            ptr_to_bytes: parse_expr(cx.session(), "::std::ffi::CStr::from_ptr(__e).to_bytes()"),
+        }
+    }
+}
+
+fn subst_e(st: &CommandState, cx: &RefactorCtxt, tmpl: &P<Expr>, e: P<Expr>) -> P<Expr> {
+    let mut bnd = Bindings::new();
+    bnd.add("__e", e);
+    tmpl.clone().subst(st, cx, &bnd)
+}
+
+/// If `e` is a NUL-terminated bytestring literal (possibly cast to `*const c_char`), build a literal
+/// of the new string type with the same contents.
+fn convert_literal(cx: &RefactorCtxt, e: &Expr, kind: StrKind) -> Option<P<Expr>> {
+    let lit = match_or!([strip_casts(e).kind] ExprKind::Lit(ref l) => l; return None);
+    let bytes = match_or!([lit.kind] LitKind::ByteStr(ref bs) => bs; return None);
+    let (&last, contents) = bytes.split_last()?;
+    if last != 0 || contents.contains(&0) {
+        return None;
+    }
+    let src = match kind {
+        // The `Debug` form of a `str` is a valid string literal.
+        StrKind::Str => format!("{:?}", str::from_utf8(contents).ok()?),
+        StrKind::CStr => {
+            let escaped = bytes.iter()
+                .flat_map(|&b| ascii::escape_default(b))
+                .map(|b| b as char)
+                .collect::<String>();
+            format!("::std::ffi::CStr::from_bytes_with_nul_unchecked(b\"{}\")", escaped)
+        }
+    };
+    Some(parse_expr(cx.session(), &src))
+}
+
+
+/// # `cstr_to_str` Command
+///
+/// Usage: `cstr_to_str [KIND]`
+///
+/// Marks: `target`
+///
+/// For each function argument or struct field marked `target` with type
+/// `*const c_char`, change its type to `&str` (if `KIND` is `str`, the default)
+/// or `&CStr` (if `KIND` is `cstr`), and update its uses and the callers of the
+/// function to match.  If `KIND` is `auto`, arguments and fields that
+/// `analyze_string_provenance` marked `utf8` become `&str`, and all others become
+/// `&CStr`.  Struct fields get the type `&'static str` or
+/// `&'static CStr`, since the refactoring tool can't determine how long the
+/// pointed-to string actually lives.
+///
+/// Within the crate, uses of the converted value are rewritten as follows:
+///
+///  * `strlen(s)` becomes `s.len()` (for `str`) or `s.to_bytes().len()` (for
+///    `cstr`).
+///  * `strcmp(a, b)` compares the byte contents of the two strings directly,
+///    converting any operand that is still a raw pointer with `CStr::from_ptr`.
+///  * `strcpy(dest, s)` copies the bytes of `s` into `dest` and adds a
+///    terminator.
+///  * Any other use, such as passing the value to a foreign function, converts it
+///    back to a pointer.  For `str`, this inserts a temporary `CString`, which
+///    lives until the end of the enclosing statement.
+///
+/// At call sites of modified functions, and in assignments to modified fields,
+/// NUL-terminated bytestring literals are replaced with string literals, and
+/// other pointers are converted with `CStr::from_ptr`.  Conversion to `&str`
+/// panics if the string is not valid UTF-8.
+///
+/// Example:
+///
+/// ```ignore
+///     unsafe fn greet(name: *const libc::c_char) -> libc::c_ulong {
+///         strlen(name)
+///     }
+///
+///     greet(b"world\0" as *const u8 as *const libc::c_char);
+/// ```
+///
+/// After running `cstr_to_str`, with `name` marked:
+///
+/// ```ignore
+///     unsafe fn greet(name: &str) -> libc::c_ulong {
+///         name.len() as libc::c_ulong
+///     }
+///
+///     greet("world");
+/// ```
+pub struct CStrToStr {
+    /// The kind to convert to, or `None` to choose based on `analyze_string_provenance` marks.
+    pub kind: Option<StrKind>,
+}
+
+impl CStrToStr {
+    /// Get the kind to convert the argument or field `id` to.
+    fn kind_of(&self, st: &CommandState, id: NodeId) -> StrKind {
+        match self.kind {
+            Some(kind) => kind,
+            None if node_provenance(st, id) == Some(Provenance::Utf8) => StrKind::Str,
+            None => StrKind::CStr,
+        }
+    }
+}
+
+impl Transform for CStrToStr {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let str_tmpls = StrTemplates::new(cx, StrKind::Str);
+        let cstr_tmpls = StrTemplates::new(cx, StrKind::CStr);
+        let tmpls = |kind| match kind {
+            StrKind::Str => &str_tmpls,
+            StrKind::CStr => &cstr_tmpls,
+        };
+        let new_ty = |kind, field| parse_ty(cx.session(), match (kind, field) {
+            (StrKind::Str, false) => "&str",
+            (StrKind::Str, true) => "&'static str",
+            (StrKind::CStr, false) => "&::std::ffi::CStr",
+            (StrKind::CStr, true) => "&'static ::std::ffi::CStr",
+        });
+
+        // (1) Change the types of marked arguments and fields.
+
+        let mut vars: HashMap<StrVar, StrKind> = HashMap::new();
+        // Modified functions, by DefId.  For each one, we track the argument indices that were
+        // modified, and the kind each one was converted to.
+        let mut mod_fns: HashMap<DefId, HashMap<usize, StrKind>> = HashMap::new();
+
+        mut_visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter_mut().enumerate() {
+                if !st.marked(arg.id, "target") {
+                    continue;
+                }
+                if !cx.opt_node_type(arg.pat.id).map_or(false, is_const_char_ptr) {
+                    warn!("argument `{}` of `{}` is not a `*const c_char`; skipping it",
+                          pprust::pat_to_string(&arg.pat), fl.ident);
+                    continue;
+                }
+                let kind = self.kind_of(st, arg.pat.id);
+                arg.ty = new_ty(kind, false);
+                vars.insert(StrVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id)), kind);
+                mod_fns.entry(cx.node_def_id(fl.id)).or_insert_with(HashMap::new).insert(i, kind);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let ident = i.ident;
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if !st.marked(sf.id, "target") {
+                        continue;
+                    }
+                    let did = cx.node_def_id(sf.id);
+                    if !is_const_char_ptr(cx.ty_ctxt().type_of(did)) {
+                        warn!("field `{}::{:?}` is not a `*const c_char`; skipping it",
+                              ident, sf.ident);
+                        continue;
+                    }
+                    let kind = self.kind_of(st, sf.id);
+                    sf.ty = new_ty(kind, true);
+                    vars.insert(StrVar::Field(did), kind);
+                }
+            }
+        });
+
+        if vars.is_empty() {
+            return;
+        }
+
+        let var_of = |e: &Expr| -> Option<(StrVar, StrKind)> {
+            let var = match e.kind {
+                ExprKind::Path(..) => StrVar::Local(cx.try_resolve_expr_to_hid(e)?),
+                ExprKind::Field(ref obj, name) => {
+                    let ty = cx.opt_adjusted_node_type(obj.id)?;
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return None);
+                    if adt.is_enum() {
+                        return None;
+                    }
+                    let did = adt.non_enum_variant().fields.iter()
+                        .find(|f| f.ident == name)?.did;
+                    StrVar::Field(did)
+                }
+                _ => return None,
+            };
+            vars.get(&var).map(|&kind| (var, kind))
+        };
+
+        // Convert a value of the old type into the new type of the given kind.  Converted values
+        // pass through unchanged.
+        let convert_value = |e: &P<Expr>, kind: StrKind| -> P<Expr> {
+            if var_of(strip_casts(e)).is_some() {
+                return P(strip_casts(e).clone());
+            }
+            convert_literal(cx, e, kind)
+                .unwrap_or_else(|| subst_e(st, cx, &tmpls(kind).from_ptr, e.clone()))
+        };
+
+        // IDs of uses of converted values that have already been rewritten.
+        let mut handled: HashSet<NodeId> = HashSet::new();
+
+        // (2) Rewrite call sites of modified functions, assignments to modified fields, and struct
+        // literals.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let Some(callee) = cx.opt_callee(&e) {
+                let mod_args = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
+                let args: &mut [P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref mut args) => args,
+                    ExprKind::MethodCall(_, ref mut args) => args,
+                    _ => return,
+                };
+                for (&idx, &kind) in mod_args {
+                    if let Some(arg) = args.get_mut(idx) {
+                        handled.insert(strip_casts(arg).id);
+                        *arg = convert_value(arg, kind);
+                    }
+                }
+                return;
+            }
+
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if let Some((_, kind)) = var_of(lhs) {
+                        handled.insert(lhs.id);
+                        handled.insert(strip_casts(rhs).id);
+                        *rhs = convert_value(rhs, kind);
+                    }
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let kind = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .and_then(|fd| vars.get(&StrVar::Field(fd.did)));
+                        if let Some(&kind) = kind {
+                            handled.insert(strip_casts(&f.expr).id);
+                            f.expr = convert_value(&f.expr, kind);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+        // (3) Rewrite `strlen`, `strcmp`, and `strcpy` calls on converted values.
+
+        let strlen = |kind| parse_expr(cx.session(), match kind {
+            StrKind::Str => "__e.len() as __t",
+            StrKind::CStr => "__e.to_bytes().len() as __t",
+        });
+        let strcmp = parse_expr(cx.session(), "__a.cmp(__b) as __t");
+        let strcpy = parse_expr(
+            cx.session(),
+            "{ let dest = __d; \
+               let src = __s; \
+               ::std::ptr::copy_nonoverlapping(src.as_ptr() as *const _, dest, src.len()); \
+               *dest.add(src.len()) = 0; \
+               dest }");
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+            let mut converted = |arg: &P<Expr>| -> Option<(P<Expr>, StrKind)> {
+                let arg = strip_casts(arg);
+                let (_, kind) = var_of(arg)?;
+                handled.insert(arg.id);
+                Some((P(arg.clone()), kind))
+            };
+            let ret_ty = || reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(id));
+
+            let new_e = if is_call_to(e, "strlen") && args.len() == 1 {
+                let (s, kind) = match_or!([converted(&args[0])] Some(x) => x; return);
+                let mut bnd = Bindings::new();
+                bnd.add("__e", s);
+                bnd.add("__t", ret_ty());
+                strlen(kind).subst(st, cx, &bnd)
+            } else if is_call_to(e, "strcmp") && args.len() == 2 {
+                let bytes_of = |arg: &P<Expr>, conv: Option<(P<Expr>, StrKind)>| match conv {
+                    Some((s, kind)) => subst_e(st, cx, &tmpls(kind).to_bytes, s),
+                    None => subst_e(st, cx, &str_tmpls.ptr_to_bytes, arg.clone()),
+                };
+                let (a, b) = match (converted(&args[0]), converted(&args[1])) {
+                    (None, None) => return,
+                    (a, b) => (bytes_of(&args[0], a), bytes_of(&args[1], b)),
+                };
+                let mut bnd = Bindings::new();
+                bnd.add("__a", a);
+                bnd.add("__b", b);
+                bnd.add("__t", ret_ty());
+                strcmp.clone().subst(st, cx, &bnd)
+            } else if is_call_to(e, "strcpy") && args.len() == 2 {
+                let (s, kind) = match_or!([converted(&args[1])] Some(x) => x; return);
+                let mut bnd = Bindings::new();
+                bnd.add("__d", args[0].clone());
+                bnd.add("__s", subst_e(st, cx, &tmpls(kind).to_bytes, s));
+                strcpy.clone().subst(st, cx, &bnd)
+            } else {
+                return;
+            };
+            *e = new_e;
+        });
+
+        // (4) Any other use of a converted value gets converted back to a pointer.
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) {
+                return;
+            }
+            let (var, kind) = match_or!([var_of(e)] Some(x) => x; return);
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    *e = subst_e(st, cx, &tmpls(kind).to_ptr, e.clone());
+                }
+                _ => {
+                    warn!("can't convert lvalue use of {:?}: `{}`",
+                          var, pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `libc_str_to_rust` Command
+///
+/// Usage: `libc_str_to_rust`
+///
+/// Marks: `target`
+///
+/// Replace calls to C string functions on values that are Rust strings with the equivalent Rust
+/// string operations.  Each local, function argument, or struct field marked `target` with type
+/// `*const c_char` or `*mut c_char` is first changed to an owned `String`; values of type `&str`
+/// or `String`, such as those produced by `cstr_to_str`, are handled without being marked.
+///
+/// Where all the string operands are Rust strings, uses are rewritten as follows:
+///
+///  * `strcmp(a, b) == 0` becomes `a == b`, and likewise for the other comparisons against zero.
+///    Comparisons produced by `cstr_to_str`, like `a.as_bytes().cmp(b.as_bytes()) as c_int == 0`,
+///    are simplified the same way.
+///  * `strlen(s)` becomes `s.len()`.
+///  * `strcpy(d, s)` becomes `d.clone_from(&s)` or `d.replace_range(.., s)`, and `strcat(d, s)`
+///    becomes `d.push_str(s)`, when `d` is a converted `String` and the result of the call is
+///    unused.  `strncpy` and `strncat` are handled the same way, copying at most `n` bytes of
+///    `s`.
+///  * `free(s)` becomes `s = String::new()`.
+///
+/// Initializers and assignments of converted locals and fields, and the arguments of functions
+/// with converted parameters, become `String`s: allocations and null pointers become
+/// `String::new()`, NUL-terminated bytestring literals become string literals, and other pointers
+/// are converted with `CStr::from_ptr`, which panics if the string is not valid UTF-8.  Any other
+/// use of a converted value gets a temporary `CString`, so writes through a `*mut c_char` obtained
+/// this way are lost; a warning is printed for each such use.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut name: *mut libc::c_char =
+///         malloc(16 as libc::c_ulong) as *mut libc::c_char;
+///     strcpy(name, b"abc\0" as *const u8 as *const libc::c_char);
+///     strcat(name, suffix);
+///     if strcmp(name, b"abcdef\0" as *const u8 as *const libc::c_char) == 0 {
+///         n = strlen(name);
+///     }
+///     free(name as *mut libc::c_void);
+/// ```
+///
+/// After running `libc_str_to_rust`, with `name` marked and `suffix` of type `&str`:
+///
+/// ```ignore
+///     let mut name: String = String::new();
+///     name.replace_range(.., "abc");
+///     name.push_str(suffix);
+///     if name == "abcdef" {
+///         n = name.len() as libc::c_ulong;
+///     }
+///     name = String::new();
+/// ```
+pub struct LibcStrToRust;
+
+/// Check if `ty` is `*const c_char` or `*mut c_char` (either signedness).
+fn is_char_ptr(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::RawPtr(mt) =>
+            matches!([mt.ty.kind] TyKind::Int(IntTy::I8), TyKind::Uint(UintTy::U8)),
+        _ => false,
+    }
+}
+
+/// If `ty` is `&str`, return `Some(false)`; if it is `String` or `&String`, return `Some(true)`.
+fn rust_str_ty(cx: &RefactorCtxt, ty: ty::Ty) -> Option<bool> {
+    let ty = match ty.kind {
+        TyKind::Ref(_, inner, _) => {
+            if inner.kind == TyKind::Str {
+                return Some(false);
+            }
+            inner
+        }
+        _ => ty,
+    };
+    match ty.kind {
+        TyKind::Adt(adt, _) => {
+            let path = cx.ty_ctxt().def_path_str(adt.did);
+            if path == "std::string::String" || path == "alloc::string::String" {
+                Some(true)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// If `e` is `CString::new(x).unwrap().as_ptr()`, as produced by `cstr_to_str`, return `x`.
+fn cstring_temp(e: &Expr) -> Option<&Expr> {
+    let unwrap = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "as_ptr" => &args[0],
+        _ => return None,
+    };
+    let new = match unwrap.kind {
+        ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "unwrap" => &args[0],
+        _ => return None,
+    };
+    let (func, args) = match_or!([new.kind] ExprKind::Call(ref func, ref args) => (func, args);
+                                 return None);
+    let path = match_or!([func.kind] ExprKind::Path(None, ref path) => path; return None);
+    let n = path.segments.len();
+    if n < 2 || args.len() != 1 ||
+       path.segments[n - 2].ident.as_str() != "CString" ||
+       path.segments[n - 1].ident.as_str() != "new" {
+        return None;
+    }
+    Some(&args[0])
+}
+
+/// If `e` is `a.as_bytes().cmp(b.as_bytes())`, as produced by `cstr_to_str`, return `a` and `b`.
+fn bytes_cmp(e: &Expr) -> Option<(&Expr, &Expr)> {
+    let as_bytes = |e: &Expr| match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "as_bytes" =>
+            Some(&*args[0]),
+        _ => None,
+    };
+    match e.kind {
+        ExprKind::MethodCall(ref seg, ref args) if seg.ident.as_str() == "cmp" && args.len() == 2 =>
+            Some((as_bytes(&args[0])?, as_bytes(&args[1])?)),
+        _ => None,
+    }
+}
+
+/// A string operand that is already a Rust string.
+struct RustStr {
+    expr: P<Expr>,
+    /// `expr` is a `String` or `&String`, rather than a `&str`.
+    owned: bool,
+}
+
+impl Transform for LibcStrToRust {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let string_ty = parse_ty(cx.session(), "String");
+
+        // (1) Change the types of marked arguments and fields, and collect marked locals.  For
+        // each converted location, we track the mutability of its original pointer type.
+
+        let mut vars: HashMap<StrVar, Mutability> = HashMap::new();
+        let mut mod_fns: HashMap<DefId, HashSet<usize>> = HashMap::new();
+
+        mut_visit_fns(krate, |fl| {
+            for (i, arg) in fl.decl.inputs.iter_mut().enumerate() {
+                if !st.marked(arg.id, "target") && !st.marked(arg.pat.id, "target") {
+                    continue;
+                }
+                let mutbl = match cx.opt_node_type(arg.pat.id) {
+                    Some(ty) if is_char_ptr(ty) =>
+                        expect!([ty.kind] TyKind::RawPtr(mt) => mt.mutbl),
+                    _ => {
+                        warn!("argument `{}` of `{}` is not a `c_char` pointer; skipping it",
+                              pprust::pat_to_string(&arg.pat), fl.ident);
+                        continue;
+                    }
+                };
+                arg.ty = string_ty.clone();
+                vars.insert(StrVar::Local(cx.hir_map().node_to_hir_id(arg.pat.id)), mutbl);
+                mod_fns.entry(cx.node_def_id(fl.id)).or_insert_with(HashSet::new).insert(i);
+            }
+        });
+
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            match cx.opt_node_type(l.pat.id) {
+                Some(ty) if is_char_ptr(ty) => {
+                    let mutbl = expect!([ty.kind] TyKind::RawPtr(mt) => mt.mutbl);
+                    vars.insert(StrVar::Local(cx.hir_map().node_to_hir_id(l.pat.id)), mutbl);
+                }
+                _ => warn!("local `{}` is not a `c_char` pointer; skipping it",
+                           pprust::pat_to_string(&l.pat)),
+            }
+        });
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            let ident = i.ident;
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if !st.marked(sf.id, "target") {
+                        continue;
+                    }
+                    let did = cx.node_def_id(sf.id);
+                    let ty = cx.ty_ctxt().type_of(did);
+                    if !is_char_ptr(ty) {
+                        warn!("field `{}::{:?}` is not a `c_char` pointer; skipping it",
+                              ident, sf.ident);
+                        continue;
+                    }
+                    sf.ty = string_ty.clone();
+                    vars.insert(StrVar::Field(did),
+                                expect!([ty.kind] TyKind::RawPtr(mt) => mt.mutbl));
+                }
+            }
+        });
+
+        let var_of = |e: &Expr| -> Option<StrVar> {
+            let var = match e.kind {
+                ExprKind::Path(..) => StrVar::Local(cx.try_resolve_expr_to_hid(e)?),
+                ExprKind::Field(ref obj, name) => StrVar::Field(field_def_id(cx, obj.id, name)?),
+                _ => return None,
+            };
+            if vars.contains_key(&var) { Some(var) } else { None }
+        };
+
+        // If `e` is already a Rust string, get it as one.
+        let rust_str = |e: &Expr| -> Option<RustStr> {
+            let e = strip_casts(e);
+            if var_of(e).is_some() {
+                return Some(RustStr { expr: P(e.clone()), owned: true });
+            }
+            if let Some(lit) = convert_literal(cx, e, StrKind::Str) {
+                return Some(RustStr { expr: lit, owned: false });
+            }
+            let e = cstring_temp(e).unwrap_or(e);
+            let owned = rust_str_ty(cx, cx.opt_node_type(e.id)?)?;
+            Some(RustStr { expr: P(e.clone()), owned })
+        };
+
+        let new_string = parse_expr(cx.session(), "String::new()");
+        let to_owned = parse_expr(cx.session(), "__e.to_owned()");
+        let from_ptr = parse_expr(
+            cx.session(), "::std::ffi::CStr::from_ptr(__e).to_str().unwrap().to_owned()");
+        let clone = parse_expr(cx.session(), "__e.clone()");
+
+        // Convert a value of the old pointer type into a `String`.
+        let convert_value = |e: &P<Expr>| -> P<Expr> {
+            if is_alloc_call(e) || is_null_ptr(e) {
+                return new_string.clone();
+            }
+            match rust_str(e) {
+                Some(ref s) if s.owned => subst_e(st, cx, &clone, s.expr.clone()),
+                Some(s) => subst_e(st, cx, &to_owned, s.expr),
+                None => subst_e(st, cx, &from_ptr, e.clone()),
+            }
+        };
+
+        // IDs of uses of converted values that have already been rewritten.
+        let mut handled: HashSet<NodeId> = HashSet::new();
+
+        // (2) Convert initializers of marked locals, arguments at call sites of modified
+        // functions, and assignments to converted locals and fields.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let var = StrVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if !vars.contains_key(&var) {
+                return;
+            }
+            if l.ty.is_some() {
+                l.ty = Some(string_ty.clone());
+            }
+            if let Some(ref mut init) = l.init {
+                *init = convert_value(init);
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            if let Some(callee) = cx.opt_callee(&e) {
+                let mod_args = match_or!([mod_fns.get(&callee)] Some(x) => x; return);
+                let args: &mut [P<Expr>] = match e.kind {
+                    ExprKind::Call(_, ref mut args) => args,
+                    ExprKind::MethodCall(_, ref mut args) => args,
+                    _ => return,
+                };
+                for &idx in mod_args {
+                    if let Some(arg) = args.get_mut(idx) {
+                        handled.insert(strip_casts(arg).id);
+                        *arg = convert_value(arg);
+                    }
+                }
+                return;
+            }
+
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref mut rhs) => {
+                    if var_of(lhs).is_some() {
+                        handled.insert(lhs.id);
+                        handled.insert(strip_casts(rhs).id);
+                        *rhs = convert_value(rhs);
+                    }
+                }
+
+                ExprKind::Struct(_, ref mut fields, _) => {
+                    let ty = match_or!([cx.opt_node_type(id)] Some(x) => x; return);
+                    let adt = match_or!([ty.kind] TyKind::Adt(adt, _) => adt; return);
+                    for f in fields {
+                        let did = adt.non_enum_variant().fields.iter()
+                            .find(|fd| fd.ident == f.ident)
+                            .map(|fd| fd.did);
+                        if did.map_or(false, |did| vars.contains_key(&StrVar::Field(did))) {
+                            handled.insert(strip_casts(&f.expr).id);
+                            f.expr = convert_value(&f.expr);
+                        }
+                    }
+                }
+
+                _ => {}
+            }
+        });
+
+        // (3) Rewrite string operations whose operands are all Rust strings.
+
+        let as_str = parse_expr(cx.session(), "__e.as_str()");
+        let strlen = parse_expr(cx.session(), "__e.len() as __t");
+        let clone_from = parse_expr(cx.session(), "__d.clone_from(&__s)");
+        let replace = parse_expr(cx.session(), "__d.replace_range(.., __s)");
+        let push_str = parse_expr(cx.session(), "__d.push_str(__s)");
+        let prefix = parse_expr(
+            cx.session(),
+            "{ let src: &str = __s; &src[..::std::cmp::min(__n as usize, src.len())] }");
+        let free = parse_expr(cx.session(), "__e = String::new()");
+
+        // `strcpy` and friends return their destination, so we only rewrite calls whose result
+        // is discarded.
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| if let StmtKind::Semi(ref e) = s.kind {
+            stmt_exprs.insert(e.id);
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            let mut mark_handled = |e: &Expr| {
+                if var_of(e).is_some() {
+                    handled.insert(e.id);
+                }
+            };
+            let str_of = |s: &RustStr| if s.owned {
+                subst_e(st, cx, &as_str, s.expr.clone())
+            } else {
+                s.expr.clone()
+            };
+
+            // `strcmp(a, b) == 0` and similar comparisons.
+            if let ExprKind::Binary(op, ref mut lhs, ref mut rhs) = e.kind {
+                if !matches!([op.node] BinOpKind::Eq, BinOpKind::Ne, BinOpKind::Lt,
+                             BinOpKind::Le, BinOpKind::Gt, BinOpKind::Ge) ||
+                   !matches!([strip_casts(rhs).kind] ExprKind::Lit(Lit {
+                       kind: LitKind::Int(0, _), ..
+                   })) {
+                    return;
+                }
+                let cmp = strip_casts(lhs);
+                let operands = if is_call_to(cmp, "strcmp") {
+                    let args = expect!([cmp.kind] ExprKind::Call(_, ref args) => args);
+                    if args.len() != 2 {
+                        return;
+                    }
+                    (&*args[0], &*args[1])
+                } else {
+                    match_or!([bytes_cmp(cmp)] Some(x) => x; return)
+                };
+                let a = match_or!([rust_str(operands.0)] Some(x) => x; return);
+                let b = match_or!([rust_str(operands.1)] Some(x) => x; return);
+                mark_handled(&a.expr);
+                mark_handled(&b.expr);
+                // `String` and `&str` can be compared for equality, but not ordered.
+                let (a, b) = if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) {
+                    (a.expr, b.expr)
+                } else {
+                    (str_of(&a), str_of(&b))
+                };
+                *lhs = a;
+                *rhs = b;
+                return;
+            }
+
+            if let Some(arg) = freed_expr(e) {
+                if var_of(arg).is_some() && stmt_exprs.contains(&id) {
+                    mark_handled(arg);
+                    let arg = P(arg.clone());
+                    *e = subst_e(st, cx, &free, arg);
+                }
+                return;
+            }
+
+            let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args.clone(); return);
+            let new_e = if is_call_to(e, "strlen") && args.len() == 1 {
+                let s = match_or!([rust_str(&args[0])] Some(x) => x; return);
+                mark_handled(&s.expr);
+                let mut bnd = Bindings::new();
+                bnd.add("__e", s.expr);
+                bnd.add("__t", reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(id)));
+                strlen.clone().subst(st, cx, &bnd)
+            } else if ["strcpy", "strcat", "strncpy", "strncat"].iter()
+                    .any(|name| is_call_to(e, name)) {
+                let limited = is_call_to(e, "strncpy") || is_call_to(e, "strncat");
+                let nargs = if limited { 3 } else { 2 };
+                if args.len() != nargs || !stmt_exprs.contains(&id) {
+                    return;
+                }
+                let dest = strip_casts(&args[0]);
+                if var_of(dest).is_none() {
+                    return;
+                }
+                let s = match_or!([rust_str(&args[1])] Some(x) => x; return);
+                mark_handled(dest);
+                mark_handled(&s.expr);
+
+                let mut bnd = Bindings::new();
+                bnd.add("__d", P(dest.clone()));
+                let append = is_call_to(e, "strcat") || is_call_to(e, "strncat");
+                let tmpl = if limited {
+                    let mut prefix_bnd = Bindings::new();
+                    prefix_bnd.add("__s", str_of(&s));
+                    prefix_bnd.add("__n", args[2].clone());
+                    bnd.add("__s", prefix.clone().subst(st, cx, &prefix_bnd));
+                    if append { &push_str } else { &replace }
+                } else if append {
+                    bnd.add("__s", str_of(&s));
+                    &push_str
+                } else if s.owned {
+                    bnd.add("__s", s.expr);
+                    &clone_from
+                } else {
+                    bnd.add("__s", s.expr);
+                    &replace
+                };
+                tmpl.clone().subst(st, cx, &bnd)
+            } else {
+                return;
+            };
+            *e = new_e;
+        });
+
+        // (4) Any other use of a converted value gets a temporary C string.
+
+        let to_ptr = parse_expr(
+            cx.session(), "::std::ffi::CString::new(__e.as_str()).unwrap().as_ptr()");
+        let to_mut_ptr = parse_expr(
+            cx.session(),
+            "::std::ffi::CString::new(__e.as_str()).unwrap().as_ptr() as *mut _");
+
+        fold_exprs_with_context(krate, |e, ectx| {
+            if handled.contains(&e.id) {
+                return;
+            }
+            let var = match_or!([var_of(e)] Some(x) => x; return);
+            match ectx {
+                lr_expr::Context::Rvalue => {
+                    let tmpl = match vars[&var] {
+                        Mutability::Mutable => {
+                            warn!("{:?} is used as a mutable pointer; writes through it will be \
+                                   lost: `{}`", var, pprust::expr_to_string(e));
+                            &to_mut_ptr
+                        }
+                        Mutability::Immutable => &to_ptr,
+                    };
+                    *e = subst_e(st, cx, tmpl, e.clone());
+                }
+                _ => {
+                    warn!("can't convert lvalue use of {:?}: `{}`",
+                          var, pprust::expr_to_string(e));
+                }
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+/// # `char_array_to_string` Command
+///
+/// Usage: `char_array_to_string [KIND]`
+///
+/// Marks: `target`
+///
+/// Convert each local or struct field marked `target` with type `[c_char; N]`, which holds a
+/// NUL-terminated string, to an owned buffer.  `KIND` is either `string` (the default), to
+/// convert to `String`, or `bytes`, to convert to `Vec<u8>` for data that may not be UTF-8.
+///
+/// The buffer must only be used through pointers to its start, such as `buf.as_mut_ptr()` or
+/// `&mut buf as *mut [c_char; N] as *mut c_char`, or by checking or clearing its first byte, as in
+/// `buf[0] == 0` and `buf[0] = 0`.  Its initializers must be zero-filled arrays or bytestring
+/// literals.  Buffers with any other use are left unchanged.  The string operations on the buffer
+/// are rewritten as follows:
+///
+///  * `strcpy(buf, s)` becomes `buf.clear(); buf.push_str(s)` (or `extend_from_slice`), and
+///    `strcat(buf, s)` becomes `buf.push_str(s)`.  `strncpy` and `strncat` copy at most `n`
+///    bytes.  The result of the call must be unused.
+///  * `sprintf(buf, fmt, ...)` and `snprintf(buf, n, fmt, ...)` become
+///    `buf = format!(fmt, ...)`, when `fmt` is a string literal.  The size limit is dropped,
+///    since the buffer grows as needed.
+///  * `strlen(buf)` becomes `buf.len()`, and `strcmp(buf, s) == 0` becomes `buf == s`.
+///  * `buf[0] == 0` becomes `buf.is_empty()`, and `buf[0] = 0` becomes `buf.clear()`.
+///
+/// The other string operand `s` may be another converted buffer, a bytestring literal, or a
+/// `c_char` pointer, which is read with `CStr::from_ptr`.  Any other use of a pointer to the
+/// buffer, such as passing it to a foreign function, gets a temporary `CString`.  Writes through
+/// a `*mut c_char` obtained this way are lost, so a warning is printed for each one.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut name: [libc::c_char; 32] = [0; 32];
+///     strcpy(name.as_mut_ptr(), b"abc\0" as *const u8 as *const libc::c_char);
+///     strcat(name.as_mut_ptr(), suffix);
+///     n = strlen(name.as_mut_ptr());
+///     puts(name.as_mut_ptr());
+/// ```
+///
+/// After running `char_array_to_string`, with `name` marked:
+///
+/// ```ignore
+///     let mut name: String = String::new();
+///     name.clear();
+///     name.push_str("abc");
+///     name.push_str(::std::ffi::CStr::from_ptr(suffix).to_str().unwrap());
+///     n = name.len() as libc::c_ulong;
+///     puts(::std::ffi::CString::new(name.as_str()).unwrap().as_ptr() as *mut _);
+/// ```
+pub struct CharArrayToString {
+    bytes: bool,
+}
+
+/// Check if `ty` is an array of `c_char` (either signedness).
+fn is_char_array(ty: ty::Ty) -> bool {
+    match ty.kind {
+        TyKind::Array(elem, _) =>
+            matches!([elem.kind] TyKind::Int(IntTy::I8), TyKind::Uint(UintTy::U8)),
+        _ => false,
+    }
+}
+
+/// If `e` is a pointer to the start of an array, such as `a.as_ptr()`, `&mut a[0]`, or
+/// `&mut a as *mut [c_char; N] as *mut c_char`, return the array `a`.
+fn array_start_ptr(e: &Expr) -> Option<&Expr> {
+    match strip_casts(e).kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && matches!([&*seg.ident.as_str()] "as_ptr", "as_mut_ptr") =>
+            Some(&args[0]),
+        ExprKind::AddrOf(_, _, ref inner) => match inner.kind {
+            ExprKind::Index(ref base, ref idx) => {
+                if matches!([idx.kind] ExprKind::Lit(Lit { kind: LitKind::Int(0, _), .. })) {
+                    Some(base)
+                } else {
+                    None
+                }
+            }
+            _ => Some(inner),
+        },
+        _ => None,
+    }
+}
+
+/// If `e` is `a[0]`, possibly with casts, return `a`.
+fn first_elem(e: &Expr) -> Option<&Expr> {
+    match strip_casts(e).kind {
+        ExprKind::Index(ref base, ref idx) if matches!([strip_casts(idx).kind] ExprKind::Lit(
+            Lit { kind: LitKind::Int(0, _), .. })) => Some(base),
+        _ => None,
+    }
+}
+
+fn is_zero_lit(e: &Expr) -> bool {
+    matches!([strip_casts(e).kind] ExprKind::Lit(Lit { kind: LitKind::Int(0, _), .. }))
+}
+
+/// Get the new value for an initializer of a converted buffer, as source text.
+fn buf_init_str(e: &Expr, bytes: bool) -> Option<String> {
+    let empty = if bytes { "Vec::new()" } else { "String::new()" };
+    if let ExprKind::Repeat(ref elem, _) = e.kind {
+        return if is_zero_lit(elem) { Some(empty.to_owned()) } else { None };
+    }
+    // A string literal initializer, like `*::std::mem::transmute::<&[u8; 8], &mut [c_char; 8]>
+    // (b"abc\0\0\0\0\0")`.
+    let mut contents = None;
+    visit_nodes(e, |e: &Expr| if let ExprKind::Lit(ref lit) = e.kind {
+        if let LitKind::ByteStr(ref bs) = lit.kind {
+            let end = bs.iter().position(|&b| b == 0).unwrap_or(bs.len());
+            contents = Some(bs[..end].to_owned());
+        }
+    });
+    let contents = contents?;
+    if contents.is_empty() {
+        Some(empty.to_owned())
+    } else if bytes {
+        let escaped = contents.iter()
+            .flat_map(|&b| ascii::escape_default(b))
+            .map(|b| b as char)
+            .collect::<String>();
+        Some(format!("b\"{}\".to_vec()", escaped))
+    } else {
+        Some(format!("String::from({:?})", str::from_utf8(&contents).ok()?))
+    }
+}
+
+/// If `e` is a NUL-terminated bytestring literal, possibly with casts, return its contents.
+pub fn c_str_lit(e: &Expr) -> Option<&[u8]> {
+    let lit = match_or!([strip_casts(e).kind] ExprKind::Lit(ref l) => l; return None);
+    let bytes = match_or!([lit.kind] LitKind::ByteStr(ref bs) => bs; return None);
+    let (&last, contents) = bytes.split_last()?;
+    if last != 0 || contents.contains(&0) {
+        return None;
+    }
+    Some(contents)
+}
+
+/// Check if `e` is a `printf`-style format string literal.
+pub fn is_fmt_lit(e: &Expr) -> bool {
+    let e = strip_casts(e);
+    let e = match e.kind {
+        ExprKind::MethodCall(ref seg, ref args)
+            if args.len() == 1 && matches!([&*seg.ident.as_str()] "as_ptr", "as_mut_ptr") =>
+            strip_casts(&args[0]),
+        _ => e,
+    };
+    match e.kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Str(..) => true,
+            LitKind::ByteStr(ref bs) => str::from_utf8(bs).is_ok(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+impl Transform for CharArrayToString {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let bytes = self.bytes;
+        let buf_ty = parse_ty(cx.session(), if bytes { "Vec<u8>" } else { "String" });
+
+        // (1) Collect marked locals and fields.
+
+        let mut vars: HashSet<StrVar> = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            match cx.opt_node_type(l.pat.id) {
+                Some(ty) if is_char_array(ty) => {
+                    vars.insert(StrVar::Local(cx.hir_map().node_to_hir_id(l.pat.id)));
+                }
+                _ => warn!("local `{}` is not a `c_char` array; skipping it",
+                           pprust::pat_to_string(&l.pat)),
+            }
+        });
+        visit_nodes(krate, |i: &Item| {
+            if let ItemKind::Struct(VariantData::Struct(ref fields, _), _) = i.kind {
+                for sf in fields {
+                    if !st.marked(sf.id, "target") {
+                        continue;
+                    }
+                    let did = cx.node_def_id(sf.id);
+                    if is_char_array(cx.ty_ctxt().type_of(did)) {
+                        vars.insert(StrVar::Field(did));
+                    } else {
+                        warn!("field `{}::{:?}` is not a `c_char` array; skipping it",
+                              i.ident, sf.ident);
+                    }
+                }
+            }
+        });
+
+        // (2) Check that every use of each buffer is one we can convert.  We also record the
+        // outermost expression of each pointer to a buffer, to convert any that remain at the
+        // end.
+
+        let var_of_expr = |e: &Expr| -> Option<StrVar> {
+            match e.kind {
+                ExprKind::Path(..) => Some(StrVar::Local(cx.try_resolve_expr_to_hid(e)?)),
+                ExprKind::Field(ref obj, name) =>
+                    Some(StrVar::Field(field_def_id(cx, obj.id, name)?)),
+                _ => None,
+            }
+        };
+        let field_var = |e: &Expr, f: &Field| -> Option<StrVar> {
+            let adt = match_or!([cx.opt_node_type(e.id)?.kind] TyKind::Adt(adt, _) => adt;
+                                return None);
+            let fd = adt.non_enum_variant().fields.iter().find(|fd| fd.ident == f.ident)?;
+            Some(StrVar::Field(fd.did))
+        };
+
+        let mut ok_uses = HashSet::new();
+        let mut bad = HashSet::new();
+        let mut ptrs = HashSet::new();
+        let mut inner_ptrs = HashSet::new();
+        let mut uses = Vec::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(var) = var_of_expr(e).filter(|v| vars.contains(v)) {
+                uses.push((e.id, var));
+            }
+            if let Some(base) = array_start_ptr(e) {
+                if var_of_expr(base).map_or(false, |v| vars.contains(&v)) {
+                    ok_uses.insert(base.id);
+                    if !inner_ptrs.contains(&e.id) {
+                        ptrs.insert(e.id);
+                    }
+                    let mut inner = e;
+                    loop {
+                        inner = match inner.kind {
+                            ExprKind::Cast(ref x, _) | ExprKind::Paren(ref x) => x,
+                            _ => break,
+                        };
+                        inner_ptrs.insert(inner.id);
+                    }
+                }
+            }
+            match e.kind {
+                ExprKind::Assign(ref lhs, ref rhs) if is_zero_lit(rhs) => {
+                    if let Some(base) = first_elem(lhs) {
+                        ok_uses.insert(base.id);
+                    }
+                }
+                ExprKind::Binary(op, ref lhs, ref rhs)
+                        if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) &&
+                           is_zero_lit(rhs) => {
+                    if let Some(base) = first_elem(lhs) {
+                        ok_uses.insert(base.id);
+                    }
+                }
+                ExprKind::Struct(_, ref fields, _) => {
+                    for f in fields {
+                        if let Some(var) = field_var(e, f).filter(|v| vars.contains(v)) {
+                            if buf_init_str(&f.expr, bytes).is_none() {
+                                bad.insert(var);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        });
+        visit_nodes(krate, |l: &Local| {
+            let var = StrVar::Local(cx.hir_map().node_to_hir_id(l.pat.id));
+            if vars.contains(&var) &&
+               l.init.as_ref().map_or(false, |init| buf_init_str(init, bytes).is_none()) {
+                bad.insert(var);
+            }
+        });
+        for (id, var) in uses {
+            if !ok_uses.contains(&id) {
+                bad.insert(var);
+            }
+        }
+        for var in &bad {
+            warn!("{:?} has uses that can't be converted; skipping it", var);
+            vars.remove(var);
+        }
+        if vars.is_empty() {
+            return;
+        }
+
+        let var_of = |e: &Expr| var_of_expr(e).filter(|v| vars.contains(v));
+        let buf_of = |e: &Expr| array_start_ptr(e).filter(|base| var_of(base).is_some());
+
+        // (3) Change the types and initializers of the buffers.
+
+        MutVisitNodes::visit(krate, |i: &mut P<Item>| {
+            if let ItemKind::Struct(VariantData::Struct(ref mut fields, _), _) = i.kind {
+                for sf in fields {
+                    if vars.contains(&StrVar::Field(cx.node_def_id(sf.id))) {
+                        sf.ty = buf_ty.clone();
+                    }
+                }
+            }
+        });
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if !vars.contains(&StrVar::Local(cx.hir_map().node_to_hir_id(l.pat.id))) {
+                return;
+            }
+            if l.ty.is_some() {
+                l.ty = Some(buf_ty.clone());
+            }
+            if let Some(ref mut init) = l.init {
+                *init = parse_expr(cx.session(), &buf_init_str(init, bytes).unwrap());
+            }
+        });
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let expr: &Expr = e;
+            let new_fields = match expr.kind {
+                ExprKind::Struct(_, ref fields, _) => fields.iter()
+                    .map(|f| match field_var(expr, f).filter(|v| vars.contains(v)) {
+                        Some(_) => parse_expr(cx.session(),
+                                              &buf_init_str(&f.expr, bytes).unwrap()),
+                        None => f.expr.clone(),
+                    })
+                    .collect::<Vec<_>>(),
+                _ => return,
+            };
+            if let ExprKind::Struct(_, ref mut fields, _) = e.kind {
+                for (f, new_expr) in fields.iter_mut().zip(new_fields) {
+                    f.expr = new_expr;
+                }
+            }
+        });
+
+        // (4) Rewrite string operations on the buffers.
+
+        // Get the other operand of a string operation as a `&str` (or `&[u8]`).
+        let src_str = |e: &Expr| -> P<Expr> {
+            let src = if let Some(base) = buf_of(e) {
+                let base = pprust::expr_to_string(base);
+                if bytes { format!("&{}[..]", base) } else { format!("{}.as_str()", base) }
+            } else if let Some(contents) = c_str_lit(e).filter(|c| bytes ||
+                                                              str::from_utf8(c).is_ok()) {
+                if bytes {
+                    let escaped = contents.iter()
+                        .flat_map(|&b| ascii::escape_default(b))
+                        .map(|b| b as char)
+                        .collect::<String>();
+                    format!("b\"{}\"", escaped)
+                } else {
+                    format!("{:?}", str::from_utf8(contents).unwrap())
+                }
+            } else {
+                let conv = if bytes { "to_bytes()" } else { "to_str().unwrap()" };
+                format!("::std::ffi::CStr::from_ptr({}).{}", pprust::expr_to_string(e), conv)
+            };
+            parse_expr(cx.session(), &src)
+        };
+        let push = if bytes { "extend_from_slice" } else { "push_str" };
+        let prefix = parse_expr(cx.session(), &format!(
+            "{{ let src: {} = __s; &src[..::std::cmp::min(__n as usize, src.len())] }}",
+            if bytes { "&[u8]" } else { "&str" }));
+
+        FlatMapNodes::visit(krate, |s: Stmt| {
+            let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; return smallvec![s]);
+            let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args;
+                                 return smallvec![s]);
+            let dest = match_or!([args.get(0).and_then(|a| buf_of(a))] Some(x) => x;
+                                 return smallvec![s]);
+            let dest_str = pprust::expr_to_string(dest);
+
+            let srcs = if ["strcpy", "strcat", "strncpy", "strncat"].iter()
+                    .any(|name| is_call_to(e, name)) {
+                let limited = is_call_to(e, "strncpy") || is_call_to(e, "strncat");
+                if args.len() != if limited { 3 } else { 2 } {
+                    return smallvec![s];
+                }
+                let mut src = src_str(&args[1]);
+                if limited {
+                    let mut bnd = Bindings::new();
+                    bnd.add("__s", src);
+                    bnd.add("__n", args[2].clone());
+                    src = prefix.clone().subst(st, cx, &bnd);
+                }
+                let mut stmts = Vec::new();
+                if is_call_to(e, "strcpy") || is_call_to(e, "strncpy") {
+                    stmts.push(format!("{}.clear();", dest_str));
+                }
+                stmts.push(format!("{}.{}({});", dest_str, push, pprust::expr_to_string(&src)));
+                stmts
+            } else if is_call_to(e, "sprintf") || is_call_to(e, "snprintf") {
+                let fmt_idx = if is_call_to(e, "snprintf") { 2 } else { 1 };
+                if args.len() <= fmt_idx || !is_fmt_lit(&args[fmt_idx]) {
+                    return smallvec![s];
+                }
+                let mac = build_format_macro("format", None, None, &args[fmt_idx..], None,
+                                             &|e| maybe_non_utf8(st, cx, e));
+                let conv = if bytes { ".into_bytes()" } else { "" };
+                vec![format!("{} = {}{};", dest_str,
+                             pprust::expr_to_string(&mk().mac_expr(mac)), conv)]
+            } else {
+                return smallvec![s];
+            };
+            let src = srcs.join(" ");
+            parse_stmts(cx.session(), &src).into_iter().collect()
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = match e.kind {
+                ExprKind::Call(_, ref args) if is_call_to(e, "strlen") && args.len() == 1 => {
+                    let buf = match_or!([buf_of(&args[0])] Some(x) => x; return);
+                    let ty = reflect_tcx_ty(cx.ty_ctxt(), cx.node_type(e.id));
+                    format!("{}.len() as {}", pprust::expr_to_string(buf),
+                            pprust::ty_to_string(&ty))
+                }
+                ExprKind::Binary(op, ref lhs, ref rhs)
+                        if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) &&
+                           is_zero_lit(rhs) => {
+                    let not = if op.node == BinOpKind::Ne { "!" } else { "" };
+                    let lhs = strip_casts(lhs);
+                    if let Some(base) = first_elem(lhs).filter(|base| var_of(base).is_some()) {
+                        // `buf[0] == 0`
+                        format!("{}{}.is_empty()", not, pprust::expr_to_string(base))
+                    } else if is_call_to(lhs, "strcmp") {
+                        // `strcmp(buf, s) == 0`
+                        let args = expect!([lhs.kind] ExprKind::Call(_, ref args) => args);
+                        if args.len() != 2 {
+                            return;
+                        }
+                        let (buf, other) = match (buf_of(&args[0]), buf_of(&args[1])) {
+                            (Some(buf), _) => (buf, &args[1]),
+                            (None, Some(buf)) => (buf, &args[0]),
+                            (None, None) => return,
+                        };
+                        let op = if not.is_empty() { "==" } else { "!=" };
+                        format!("{} {} {}", pprust::expr_to_string(buf), op,
+                                pprust::expr_to_string(&src_str(other)))
+                    } else {
+                        return;
+                    }
+                }
+                ExprKind::Assign(ref lhs, ref rhs) if is_zero_lit(rhs) => {
+                    // `buf[0] = 0`
+                    let base = match_or!([first_elem(lhs).filter(|base| var_of(base).is_some())]
+                                         Some(x) => x; return);
+                    format!("{}.clear()", pprust::expr_to_string(base))
+                }
+                _ => return,
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+
+        // (5) Any other pointer to a buffer gets a temporary C string.
+
+        let to_ptr = parse_expr(cx.session(), &format!(
+            "::std::ffi::CString::new({}).unwrap().as_ptr()",
+            if bytes { "__e.clone()" } else { "__e.as_str()" }));
+        let to_mut_ptr = parse_expr(cx.session(), &format!(
+            "::std::ffi::CString::new({}).unwrap().as_ptr() as *mut _",
+            if bytes { "__e.clone()" } else { "__e.as_str()" }));
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            if !ptrs.contains(&e.id) {
+                return;
+            }
+            let base = match_or!([buf_of(e)] Some(x) => P(x.clone()); return);
+            let tmpl = match cx.opt_node_type(e.id).map(|ty| &ty.kind) {
+                Some(&TyKind::RawPtr(mt)) if mt.mutbl == Mutability::Mutable => {
+                    warn!("{} is used as a mutable pointer; writes through it will be lost: `{}`",
+                          pprust::expr_to_string(&base), pprust::expr_to_string(e));
+                    &to_mut_ptr
+                }
+                _ => &to_ptr,
+            };
+            *e = subst_e(st, cx, tmpl, base);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("cstr_to_str", |args| mk(CStrToStr {
+        kind: args.get(0).map_or(Some(StrKind::Str), |s| StrKind::from_arg(s)),
+    }));
+    reg.register("libc_str_to_rust", |_args| mk(LibcStrToRust));
+    reg.register("char_array_to_string", |args| mk(CharArrayToString {
+        bytes: match args.get(0).map(|s| s.as_str()) {
+            None | Some("string") => false,
+            Some("bytes") => true,
+            Some(s) => panic!("unknown buffer kind `{}` (expected `string` or `bytes`)", s),
+        },
+    }));
+}
@@ -1,15 +1,21 @@
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
 use rustc::ty;
 use syntax::ast::*;
+use syntax::print::pprust;
 use syntax::ptr::P;
 
 use smallvec::smallvec;
 
-use crate::ast_manip::{fold_blocks, FlatMapNodes, AstEquiv};
+use crate::ast_manip::{fold_blocks, visit_nodes, FlatMapNodes, MutVisitNodes, AstEquiv};
 use crate::command::{CommandState, Registry};
-use crate::driver::{Phase, parse_expr};
+use crate::driver::{Phase, parse_expr, parse_items, parse_ty};
 use crate::matcher::{mut_visit_match, Subst};
 use crate::path_edit::fold_resolved_paths;
+use crate::reflect::reflect_def_path;
 use crate::transform::Transform;
+use crate::transform::heap::{field_def_id, strip_casts};
+use crate::transform::results::camel_case;
 use c2rust_ast_builder::{mk, IntoSymbol};
 use crate::RefactorCtxt;
 
@@ -209,10 +215,373 @@ fn is_struct(i: &Item) -> bool {
 }
 
 
+/// # `bitflags` Command
+///
+/// Usage: `bitflags`
+///
+/// Marks: `target`
+///
+/// Convert integer struct fields that hold sets of flags into `bitflags!` types.  A field is
+/// converted when it is only used in these ways, with flag expressions built from power-of-two
+/// constants (such as `const FLAG_X: u32 = 1 << 3`), combined with `|`:
+///
+///  * `x.f & FLAGS != 0` and `x.f & FLAGS == 0`, which become `x.f.intersects(FLAGS)` (or
+///    `x.f.contains(FLAG)`, for a single flag) and its negation.  `x.f & FLAGS == FLAGS` becomes
+///    `x.f.contains(FLAGS)`.
+///  * `x.f |= FLAGS`, which becomes `x.f.insert(FLAGS)`.
+///  * `x.f &= !FLAGS`, which becomes `x.f.remove(FLAGS)`.
+///  * `x.f = FLAGS` and `S { f: FLAGS, .. }`, where `FLAGS` may also be `0`.
+///
+/// At least two different constants must be used with the field.  If any fields are marked
+/// `target`, only the marked fields are considered.
+///
+/// For each converted field `f` of struct `S`, a `bitflags!` type named `S` followed by the
+/// camel-cased name of `f` is defined right after `S`, containing each of the constants used with
+/// the field.  The original constants are kept, since they may have other uses.  The crate must
+/// depend on the `bitflags` crate.
+///
+/// Example:
+///
+/// ```ignore
+///     const FLAG_A: u32 = 1 << 0;
+///     const FLAG_B: u32 = 1 << 1;
+///     struct Node {
+///         flags: u32,
+///     }
+///
+///     n.flags |= FLAG_A;
+///     if n.flags & FLAG_B != 0 {
+///         n.flags &= !(FLAG_A | FLAG_B);
+///     }
+/// ```
+///
+/// After running `bitflags`:
+///
+/// ```ignore
+///     const FLAG_A: u32 = 1 << 0;
+///     const FLAG_B: u32 = 1 << 1;
+///     struct Node {
+///         flags: NodeFlags,
+///     }
+///     bitflags::bitflags! {
+///         struct NodeFlags: u32 {
+///             const FLAG_A = 1 << 0;
+///             const FLAG_B = 1 << 1;
+///         }
+///     }
+///
+///     n.flags.insert(NodeFlags::FLAG_A);
+///     if n.flags.contains(NodeFlags::FLAG_B) {
+///         n.flags.remove(NodeFlags::FLAG_A | NodeFlags::FLAG_B);
+///     }
+/// ```
+pub struct Bitflags;
+
+/// A flag constant: a `const` item whose value is a power of two.
+struct FlagConst {
+    ident: Ident,
+    bit: u32,
+}
+
+/// Evaluate an integer constant of the form `N` or `A << B`, ignoring casts.
+fn eval_shift(e: &Expr) -> Option<u128> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(v, _) => Some(v),
+            _ => None,
+        },
+        ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::Shl => {
+            let (a, b) = (eval_shift(a)?, eval_shift(b)?);
+            if b >= 128 { None } else { Some(a << b) }
+        }
+        _ => None,
+    }
+}
+
+/// A use of a candidate field together with a flag expression.
+enum FlagOp<'a> {
+    /// `f & flags != 0` (or `== 0`, if `negated`).
+    Test { flags: &'a Expr, negated: bool },
+    /// `f & flags == flags`.
+    Contains(&'a Expr),
+    Insert(&'a Expr),
+    Remove(&'a Expr),
+    Assign(&'a Expr),
+}
+
+/// Recognize a supported operation on a struct field.  Returns the field expression along with
+/// the operation.
+fn as_flag_op(e: &Expr) -> Option<(&Expr, FlagOp)> {
+    match e.kind {
+        ExprKind::Binary(op, ref lhs, ref rhs)
+                if matches!([op.node] BinOpKind::Eq, BinOpKind::Ne) => {
+            let (a, b) = match strip_casts(lhs).kind {
+                ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::BitAnd => (a, b),
+                _ => return None,
+            };
+            let (field, flags) = match (&strip_casts(a).kind, &strip_casts(b).kind) {
+                (&ExprKind::Field(..), _) => (strip_casts(a), &**b),
+                (_, &ExprKind::Field(..)) => (strip_casts(b), &**a),
+                _ => return None,
+            };
+            if strip_casts(rhs).ast_equiv(strip_casts(flags)) && op.node == BinOpKind::Eq {
+                return Some((field, FlagOp::Contains(flags)));
+            }
+            match strip_casts(rhs).kind {
+                ExprKind::Lit(ref l) if matches!([l.kind] LitKind::Int(0, _)) => {}
+                _ => return None,
+            }
+            Some((field, FlagOp::Test { flags, negated: op.node == BinOpKind::Eq }))
+        }
+        ExprKind::AssignOp(op, ref lhs, ref rhs) if op.node == BinOpKind::BitOr =>
+            Some((&**lhs, FlagOp::Insert(rhs))),
+        ExprKind::AssignOp(op, ref lhs, ref rhs) if op.node == BinOpKind::BitAnd => {
+            match strip_casts(rhs).kind {
+                ExprKind::Unary(UnOp::Not, ref flags) => Some((&**lhs, FlagOp::Remove(flags))),
+                _ => None,
+            }
+        }
+        ExprKind::Assign(ref lhs, ref rhs) => Some((&**lhs, FlagOp::Assign(rhs))),
+        _ => None,
+    }
+}
+
+/// Translate a flag expression into an expression on the flags type `ty`, collecting the
+/// constants it uses.  Returns the new expression's source and the number of flags it contains.
+fn flag_expr_str(cx: &RefactorCtxt,
+                 e: &Expr,
+                 consts: &HashMap<DefId, FlagConst>,
+                 ty: &str,
+                 used: &mut HashSet<DefId>) -> Option<(String, usize)> {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref l) if matches!([l.kind] LitKind::Int(0, _)) =>
+            Some((format!("{}::empty()", ty), 0)),
+        ExprKind::Binary(op, ref a, ref b) if op.node == BinOpKind::BitOr => {
+            let (a, n) = flag_expr_str(cx, a, consts, ty, used)?;
+            let (b, m) = flag_expr_str(cx, b, consts, ty, used)?;
+            Some((format!("{} | {}", a, b), n + m))
+        }
+        ExprKind::Path(..) => {
+            let did = cx.try_resolve_expr(e)?;
+            let c = consts.get(&did)?;
+            used.insert(did);
+            Some((format!("{}::{}", ty, c.ident), 1))
+        }
+        _ => None,
+    }
+}
+
+impl Transform for Bitflags {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the flag constants and the candidate fields.
+
+        let mut consts = HashMap::new();
+        let mut fields = HashMap::new();
+        let mut any_marked = false;
+        visit_nodes(krate, |i: &Item| match i.kind {
+            ItemKind::Const(_, ref init) => {
+                if let Some(v) = eval_shift(init) {
+                    if v.is_power_of_two() {
+                        let bit = v.trailing_zeros();
+                        consts.insert(cx.node_def_id(i.id), FlagConst { ident: i.ident, bit });
+                    }
+                }
+            }
+            ItemKind::Struct(VariantData::Struct(ref sfs, _), _) => {
+                for sf in sfs {
+                    let did = cx.node_def_id(sf.id);
+                    if !matches!([cx.ty_ctxt().type_of(did).kind]
+                                 ty::TyKind::Int(_), ty::TyKind::Uint(_)) {
+                        continue;
+                    }
+                    let ident = match_or!([sf.ident] Some(x) => x; continue);
+                    let marked = st.marked(sf.id, "target");
+                    any_marked |= marked;
+                    fields.insert(did, (i.ident, ident, marked));
+                }
+            }
+            _ => {}
+        });
+        if any_marked {
+            fields.retain(|_, &mut (_, _, marked)| marked);
+        }
+
+        // (2) Check that every use of each field is a supported flag operation.
+
+        let field_of = |e: &Expr| -> Option<DefId> {
+            match e.kind {
+                ExprKind::Field(ref obj, ident) => {
+                    let did = field_def_id(cx, obj.id, ident)?;
+                    if fields.contains_key(&did) { Some(did) } else { None }
+                }
+                _ => None,
+            }
+        };
+
+        let mut used: HashMap<DefId, HashSet<DefId>> = HashMap::new();
+        let mut unsupported = HashSet::new();
+        let mut handled = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let mut check = |field: DefId, flags: &Expr| {
+                let used = used.entry(field).or_insert_with(HashSet::new);
+                if flag_expr_str(cx, flags, &consts, "", used).is_none() {
+                    unsupported.insert(field);
+                }
+            };
+
+            if let ExprKind::Struct(_, ref sfs, _) = e.kind {
+                let adt = match_or!([cx.opt_node_type(e.id).map(|ty| &ty.kind)]
+                                    Some(&ty::TyKind::Adt(adt, _)) => adt; return);
+                for f in sfs {
+                    let did = adt.non_enum_variant().fields.iter()
+                        .find(|fd| fd.ident == f.ident)
+                        .map(|fd| fd.did);
+                    if let Some(did) = did.filter(|did| fields.contains_key(did)) {
+                        check(did, &f.expr);
+                    }
+                }
+                return;
+            }
+
+            let (field, op) = match_or!([as_flag_op(e)] Some(x) => x; return);
+            let did = match_or!([field_of(field)] Some(x) => x; return);
+            handled.insert(field.id);
+            match op {
+                FlagOp::Test { flags, .. } |
+                FlagOp::Contains(flags) |
+                FlagOp::Insert(flags) |
+                FlagOp::Remove(flags) |
+                FlagOp::Assign(flags) => check(did, flags),
+            }
+        });
+        visit_nodes(krate, |e: &Expr| {
+            if let Some(did) = field_of(e) {
+                if !handled.contains(&e.id) {
+                    unsupported.insert(did);
+                }
+            }
+        });
+
+        // Pick the name of each converted field's flags type.
+        let mut flag_tys: HashMap<DefId, String> = HashMap::new();
+        for (&did, &(struct_ident, field_ident, marked)) in &fields {
+            let n = used.get(&did).map_or(0, |u| u.len());
+            if unsupported.contains(&did) || n < 2 {
+                if marked {
+                    warn!("field `{}::{}` is not used as a set of flags; skipping it",
+                          struct_ident, field_ident);
+                }
+                continue;
+            }
+            flag_tys.insert(did, format!("{}{}", struct_ident, camel_case(&field_ident.as_str())));
+        }
+        if flag_tys.is_empty() {
+            return;
+        }
+
+        // The flags types are defined next to their structs, so uses in other modules need the
+        // full path.
+        let ty_path = |did: DefId| -> String {
+            let name = &flag_tys[&did];
+            let (_, mut path) = reflect_def_path(cx.ty_ctxt(), cx.ty_ctxt().parent(did).unwrap());
+            if path.segments.len() <= 2 {
+                return name.clone();
+            }
+            path.segments.last_mut().unwrap().ident = Ident::from_str(name);
+            pprust::path_to_string(&path)
+        };
+
+        // (3) Rewrite the flag operations.
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let id = e.id;
+            let convert = |did: DefId, flags: &Expr| -> (String, usize) {
+                expect!([flag_expr_str(cx, flags, &consts, &ty_path(did), &mut HashSet::new())]
+                        Some(x) => x)
+            };
+
+            if let ExprKind::Struct(_, ref mut sfs, _) = e.kind {
+                let adt = match_or!([cx.opt_node_type(id).map(|ty| &ty.kind)]
+                                    Some(&ty::TyKind::Adt(adt, _)) => adt; return);
+                for f in sfs {
+                    let did = adt.non_enum_variant().fields.iter()
+                        .find(|fd| fd.ident == f.ident)
+                        .map(|fd| fd.did);
+                    if let Some(did) = did.filter(|did| flag_tys.contains_key(did)) {
+                        let (src, _) = convert(did, &f.expr);
+                        f.expr = parse_expr(cx.session(), &src);
+                    }
+                }
+                return;
+            }
+
+            let src = {
+                let (field, op) = match_or!([as_flag_op(e)] Some(x) => x; return);
+                let did = match_or!([field_of(field)] Some(x) => x; return);
+                if !flag_tys.contains_key(&did) {
+                    return;
+                }
+                let field = pprust::expr_to_string(field);
+                match op {
+                    FlagOp::Test { flags, negated } => {
+                        let (flags, n) = convert(did, flags);
+                        let method = if n == 1 { "contains" } else { "intersects" };
+                        format!("{}{}.{}({})",
+                                if negated { "!" } else { "" }, field, method, flags)
+                    }
+                    FlagOp::Contains(flags) =>
+                        format!("{}.contains({})", field, convert(did, flags).0),
+                    FlagOp::Insert(flags) =>
+                        format!("{}.insert({})", field, convert(did, flags).0),
+                    FlagOp::Remove(flags) =>
+                        format!("{}.remove({})", field, convert(did, flags).0),
+                    FlagOp::Assign(flags) =>
+                        format!("{} = {}", field, convert(did, flags).0),
+                }
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+
+        // (4) Define the flags types and change the types of the fields.
+
+        FlatMapNodes::visit(krate, |mut i: P<Item>| {
+            let vis = pprust::vis_to_string(&i.vis);
+            let mut defs = Vec::new();
+            if let ItemKind::Struct(VariantData::Struct(ref mut sfs, _), _) = i.kind {
+                for sf in sfs {
+                    let did = cx.node_def_id(sf.id);
+                    let name = match_or!([flag_tys.get(&did)] Some(x) => x; continue);
+                    let mut flags = used[&did].iter().map(|c| &consts[c]).collect::<Vec<_>>();
+                    flags.sort_by_key(|c| c.bit);
+                    let flags = flags.iter()
+                        .map(|c| format!("        const {} = 1 << {};\n", c.ident, c.bit))
+                        .collect::<String>();
+                    defs.push(format!(
+                        "bitflags::bitflags! {{\n    {}struct {}: {} {{\n{}    }}\n}}",
+                        vis, name, pprust::ty_to_string(&sf.ty), flags));
+                    sf.ty = parse_ty(cx.session(), name);
+                }
+            }
+            let mut items = smallvec![i];
+            for src in defs {
+                items.extend(parse_items(cx.session(), &src));
+            }
+            items
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
 pub fn register_commands(reg: &mut Registry) {
     use super::mk;
 
     reg.register("struct_assign_to_update", |_args| mk(AssignToUpdate));
     reg.register("struct_merge_updates", |_args| mk(MergeUpdates));
     reg.register("rename_struct", |args| mk(Rename(args[0].clone())));
+    reg.register("bitflags", |_args| mk(Bitflags));
 }
@@ -0,0 +1,386 @@
+//! Conversion of C clock and timestamp code to `std::time`.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::HirId;
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_stmts, parse_ty};
+use crate::reflect::reflect_tcx_ty;
+use crate::transform::Transform;
+use crate::transform::heap::{is_call_to, is_null_ptr, strip_casts};
+use crate::util::Lone;
+use crate::RefactorCtxt;
+
+
+/// The `std::time` type that replaces a `struct timespec` or `struct timeval`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Clock {
+    /// `CLOCK_MONOTONIC`, which becomes `Instant`.
+    Monotonic,
+    /// `CLOCK_REALTIME` or `gettimeofday`, which become `SystemTime`.
+    Realtime,
+}
+
+impl Clock {
+    fn ty_str(self) -> &'static str {
+        match self {
+            Clock::Monotonic => "::std::time::Instant",
+            Clock::Realtime => "::std::time::SystemTime",
+        }
+    }
+}
+
+/// Get the clock named by the `clk_id` argument of `clock_gettime`.  The transpiler expands the
+/// `CLOCK_*` macros to their values, which are the same on Linux and the BSDs.
+fn as_clock(e: &Expr) -> Option<Clock> {
+    match strip_casts(e).kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(0, _) => Some(Clock::Realtime),
+            LitKind::Int(1, _) => Some(Clock::Monotonic),
+            _ => None,
+        },
+        ExprKind::Path(None, ref path) => match &*path.segments.last()?.ident.as_str() {
+            "CLOCK_REALTIME" => Some(Clock::Realtime),
+            "CLOCK_MONOTONIC" => Some(Clock::Monotonic),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `e` is `clock_gettime(CLOCK, &mut t)` or `gettimeofday(&mut t, NULL)`, get the clock and
+/// `t`.
+fn as_get_time(e: &Expr) -> Option<(Clock, &Expr)> {
+    let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return None);
+    let (clock, place) = if is_call_to(e, "clock_gettime") && args.len() == 2 {
+        (as_clock(&args[0])?, &args[1])
+    } else if is_call_to(e, "gettimeofday") && args.len() == 2 && is_null_ptr(&args[1]) {
+        (Clock::Realtime, &args[0])
+    } else {
+        return None;
+    };
+    let place = match_or!([strip_casts(place).kind]
+                          ExprKind::AddrOf(_, Mutability::Mutable, ref p) => p; return None);
+    Some((clock, place))
+}
+
+/// If `e` is a read of `tv_sec`, `tv_nsec` or `tv_usec`, get the struct and the field name.
+fn as_time_field(e: &Expr) -> Option<(&Expr, &'static str)> {
+    let (base, ident) = match_or!([e.kind] ExprKind::Field(ref b, i) => (b, i); return None);
+    let field = match &*ident.as_str() {
+        "tv_sec" => "tv_sec",
+        "tv_nsec" => "tv_nsec",
+        "tv_usec" => "tv_usec",
+        _ => return None,
+    };
+    Some((base, field))
+}
+
+/// If `e` is `b.FIELD - a.FIELD`, get `b`, `a` and `FIELD`.
+fn as_time_diff(e: &Expr) -> Option<(&Expr, &Expr, &'static str)> {
+    let (lhs, rhs) = match e.kind {
+        ExprKind::Binary(op, ref l, ref r) if op.node == BinOpKind::Sub => (l, r),
+        _ => return None,
+    };
+    let (b, field) = as_time_field(strip_casts(lhs))?;
+    let (a, field2) = as_time_field(strip_casts(rhs))?;
+    if field != field2 {
+        return None;
+    }
+    Some((b, a, field))
+}
+
+/// The `Duration` method that gets the value of `field`.
+fn duration_method(field: &str) -> &'static str {
+    match field {
+        "tv_sec" => "as_secs",
+        "tv_nsec" => "subsec_nanos",
+        "tv_usec" => "subsec_micros",
+        _ => unreachable!(),
+    }
+}
+
+/// Check if `init` is an initializer that the new value of a time local can replace: a zeroed
+/// struct literal or `mem::zeroed()`.
+fn is_replaceable_init(init: &Expr) -> bool {
+    match init.kind {
+        ExprKind::Struct(_, ref fields, None) => fields.iter().all(|f| {
+            matches!([strip_casts(&f.expr).kind] ExprKind::Lit(..))
+        }),
+        _ => is_call_to(init, "zeroed") || is_call_to(init, "uninitialized"),
+    }
+}
+
+
+/// # `time_to_std` Command
+///
+/// Usage: `time_to_std`
+///
+/// Marks: `target`
+///
+/// Convert each marked local of type `struct timespec` or `struct timeval`
+/// that is set with `clock_gettime` or `gettimeofday` to an `Instant` (for
+/// `CLOCK_MONOTONIC`) or a `SystemTime` (for `CLOCK_REALTIME` and
+/// `gettimeofday`):
+///
+///  * `clock_gettime(CLOCK_MONOTONIC, &mut t);` becomes
+///    `t = Instant::now();`, and similarly for the other clocks.
+///
+///  * Differences of the fields of two converted locals, like
+///    `end.tv_sec - start.tv_sec` or `end.tv_nsec - start.tv_nsec`, become
+///    `end.duration_since(start).as_secs()` or
+///    `end.duration_since(start).subsec_nanos()`, cast to the original type.
+///    The `Duration` splits the difference into seconds and nanoseconds
+///    differently than the subtraction of each field does, but any sum of
+///    the two parts with matching scales, like
+///    `sec + nsec as f64 / 1e9`, has the same value.
+///
+///  * Fields of a `SystemTime` can also be read on their own: `t.tv_sec`
+///    becomes `t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()`.
+///
+/// A local with any other use, such as passing it to another function or
+/// writing its fields, is left unchanged, and a warning is printed.  A
+/// `SystemTime` that goes backwards makes its difference zero instead of
+/// negative.
+///
+/// Independent of the marks, `time(NULL)` becomes the number of seconds
+/// since the epoch from `SystemTime::now()`, and `difftime(a, b)` becomes
+/// `(a - b) as f64`.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut start: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+///     clock_gettime(1 as libc::c_int, &mut start);
+///     work();
+///     let mut end: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+///     clock_gettime(1 as libc::c_int, &mut end);
+///     return (end.tv_sec - start.tv_sec) as f64
+///         + (end.tv_nsec - start.tv_nsec) as f64 / 1e9;
+/// ```
+///
+/// After running `time_to_std`:
+///
+/// ```ignore
+///     let mut start: ::std::time::Instant = ::std::time::Instant::now();
+///     start = ::std::time::Instant::now();
+///     work();
+///     let mut end: ::std::time::Instant = ::std::time::Instant::now();
+///     end = ::std::time::Instant::now();
+///     return (end.duration_since(start).as_secs() as i64) as f64
+///         + (end.duration_since(start).subsec_nanos() as i64) as f64 / 1e9;
+/// ```
+pub struct TimeToStd;
+
+impl Transform for TimeToStd {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let var_of = |e: &Expr| -> Option<HirId> {
+            if !matches!([e.kind] ExprKind::Path(None, _)) {
+                return None;
+            }
+            cx.try_resolve_expr_to_hid(e)
+        };
+
+        // (1) Collect the marked `timespec` and `timeval` locals.
+
+        let mut candidates = HashSet::new();
+        visit_nodes(krate, |l: &Local| {
+            if !st.marked(l.id, "target") && !st.marked(l.pat.id, "target") {
+                return;
+            }
+            let is_time_ty = l.ty.as_ref().map_or(false, |ty| match ty.kind {
+                TyKind::Path(None, ref path) => path.segments.last().map_or(false, |seg| {
+                    matches!([&*seg.ident.as_str()] "timespec", "timeval")
+                }),
+                _ => false,
+            });
+            if !is_time_ty || !l.init.as_ref().map_or(true, |init| is_replaceable_init(init)) {
+                warn!("local `{}` is not a zero-initialized `timespec` or `timeval`; skipping it",
+                      pprust::pat_to_string(&l.pat));
+                return;
+            }
+            candidates.insert(cx.hir_map().node_to_hir_id(l.pat.id));
+        });
+
+        // Each local gets its clock from the calls that set it.
+        let mut vars: HashMap<HirId, Clock> = HashMap::new();
+        let mut bad = HashSet::new();
+        visit_nodes(krate, |e: &Expr| {
+            let (clock, place) = match_or!([as_get_time(e)] Some(x) => x; return);
+            let var = match var_of(place).filter(|v| candidates.contains(v)) {
+                Some(var) => var,
+                None => return,
+            };
+            if *vars.entry(var).or_insert(clock) != clock {
+                bad.insert(var);
+            }
+        });
+
+        // (2) Check that every use of each local is one we can convert.  A difference needs
+        // both sides to be converted, so repeat until nothing changes.
+
+        let mut stmt_exprs = HashSet::new();
+        visit_nodes(krate, |s: &Stmt| {
+            if let StmtKind::Semi(ref e) = s.kind {
+                stmt_exprs.insert(e.id);
+            }
+        });
+        let mut places = HashSet::new();
+        visit_nodes(krate, |e: &Expr| match e.kind {
+            ExprKind::Assign(ref lhs, _) | ExprKind::AssignOp(_, ref lhs, _) |
+            ExprKind::AddrOf(_, _, ref lhs) => { places.insert(lhs.id); }
+            _ => {}
+        });
+        loop {
+            for var in bad.drain() {
+                warn!("{:?} has uses that can't be converted; skipping it", var);
+                vars.remove(&var);
+            }
+
+            let mut ok_uses = HashSet::new();
+            let mut uses = Vec::new();
+            visit_nodes(krate, |e: &Expr| {
+                if let Some(var) = var_of(e).filter(|v| vars.contains_key(v)) {
+                    uses.push((e.id, var));
+                }
+                if let Some((clock, place)) = as_get_time(e) {
+                    let ok = stmt_exprs.contains(&e.id) &&
+                        var_of(place).and_then(|v| vars.get(&v)) == Some(&clock);
+                    if ok {
+                        ok_uses.insert(place.id);
+                    }
+                } else if let Some((b, a, _)) = as_time_diff(e) {
+                    let clock_b = var_of(b).and_then(|v| vars.get(&v));
+                    let clock_a = var_of(a).and_then(|v| vars.get(&v));
+                    if clock_b.is_some() && clock_b == clock_a {
+                        ok_uses.insert(b.id);
+                        ok_uses.insert(a.id);
+                    }
+                } else if let Some((base, _)) = as_time_field(e) {
+                    let clock = var_of(base).and_then(|v| vars.get(&v));
+                    if clock == Some(&Clock::Realtime) && !places.contains(&e.id) {
+                        ok_uses.insert(base.id);
+                    }
+                }
+            });
+
+            for (id, var) in uses {
+                if !ok_uses.contains(&id) {
+                    bad.insert(var);
+                }
+            }
+            if bad.is_empty() {
+                break;
+            }
+        }
+        let time_var = |e: &Expr| var_of(e).and_then(|v| vars.get(&v)).cloned();
+
+        // (3) Change the types and initializers of the locals.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            let clock = match vars.get(&cx.hir_map().node_to_hir_id(l.pat.id)) {
+                Some(&clock) => clock,
+                None => return,
+            };
+            l.ty = Some(parse_ty(cx.session(), clock.ty_str()));
+            if l.init.is_some() {
+                let src = match clock {
+                    Clock::Monotonic => "::std::time::Instant::now()",
+                    Clock::Realtime => "::std::time::UNIX_EPOCH",
+                };
+                l.init = Some(parse_expr(cx.session(), src));
+            }
+        });
+
+        // (4) Rewrite the `clock_gettime` and `gettimeofday` statements.
+
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            for s in &mut b.stmts {
+                let src = {
+                    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; continue);
+                    match as_get_time(e) {
+                        Some((clock, place)) if time_var(place).is_some() =>
+                            format!("{} = {}::now();", pprust::expr_to_string(place),
+                                    clock.ty_str()),
+                        _ => continue,
+                    }
+                };
+                *s = parse_stmts(cx.session(), &src).lone();
+            }
+        });
+
+        // (5) Rewrite the field reads, and `time` and `difftime`.  The children of an expression
+        // are rewritten before it, so find the field reads, including the ones inside
+        // differences, first.
+
+        let ty_str = |e: &Expr| cx.opt_node_type(e.id)
+            .map(|ty| pprust::ty_to_string(&reflect_tcx_ty(cx.ty_ctxt(), ty)))
+            .unwrap_or_else(|| "i64".to_owned());
+        let mut field_reads = HashMap::new();
+        visit_nodes(krate, |e: &Expr| {
+            if let Some((b, a, field)) = as_time_diff(e) {
+                let clock = match time_var(b) {
+                    Some(clock) => clock,
+                    None => return,
+                };
+                let unwrap = match clock {
+                    Clock::Monotonic => "",
+                    Clock::Realtime => ".unwrap_or_default()",
+                };
+                field_reads.insert(e.id, format!(
+                    "({}.duration_since({}){}.{}() as {})",
+                    pprust::expr_to_string(b), pprust::expr_to_string(a), unwrap,
+                    duration_method(field), ty_str(e)));
+            } else if let Some((base, field)) = as_time_field(e) {
+                if time_var(base) != Some(Clock::Realtime) {
+                    return;
+                }
+                field_reads.insert(e.id, format!(
+                    "({}.duration_since(::std::time::UNIX_EPOCH)\
+                      .unwrap_or_default().{}() as {})",
+                    pprust::expr_to_string(base), duration_method(field), ty_str(e)));
+            }
+        });
+
+        MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+            let src = if let Some(src) = field_reads.get(&e.id) {
+                src.clone()
+            } else if is_call_to(e, "time") {
+                let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+                if args.len() != 1 || !is_null_ptr(&args[0]) {
+                    return;
+                }
+                format!("::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH)\
+                         .map_or(-1, |d| d.as_secs() as {})", ty_str(e))
+            } else if is_call_to(e, "difftime") {
+                let args = match_or!([e.kind] ExprKind::Call(_, ref args) => args; return);
+                if args.len() != 2 {
+                    return;
+                }
+                let operand = |e: &Expr| match e.kind {
+                    ExprKind::Binary(..) => format!("({})", pprust::expr_to_string(e)),
+                    _ => pprust::expr_to_string(e),
+                };
+                format!("({} - {}) as f64", operand(&args[0]), operand(&args[1]))
+            } else {
+                return;
+            };
+            *e = parse_expr(cx.session(), &src);
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("time_to_std", |_args| mk(TimeToStd));
+}
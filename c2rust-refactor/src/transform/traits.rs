@@ -0,0 +1,405 @@
+//! Conversion of C-style vtable structs into traits.
+
+use std::collections::{HashMap, HashSet};
+use rustc::hir::def_id::DefId;
+use rustc::ty::TyKind as TcxTyKind;
+use smallvec::{smallvec, SmallVec};
+use syntax::ast::*;
+use syntax::print::pprust;
+use syntax::ptr::P;
+
+use crate::ast_manip::{FlatMapNodes, MutVisitNodes, visit_nodes};
+use crate::command::{CommandState, Registry};
+use crate::driver::{Phase, parse_expr, parse_items, parse_ty};
+use crate::transform::Transform;
+use crate::transform::heap::{field_def_id, strip_casts};
+use crate::transform::results::camel_case;
+use crate::RefactorCtxt;
+
+
+/// # `fn_ptr_struct_to_trait` Command
+///
+/// Usage: `fn_ptr_struct_to_trait`
+///
+/// Marks: `target`
+///
+/// Convert each struct marked `target`, whose fields are all function pointers (or `Option`s of
+/// function pointers), into a trait with the same name.  Each field becomes a trait method taking
+/// `&self` followed by the function pointer's arguments.
+///
+/// Every instance of the struct must be the initializer of a `static` or `const`.  Each one is
+/// replaced with a new unit struct, named after the static, that implements the trait by calling
+/// the functions the instance was initialized with.  Fields initialized to `None` get methods
+/// that panic.  Indirect calls through a field, such as `(*ops).f.unwrap()(x)`, become dynamic
+/// method calls (`(*ops).f(x)`), and pointers and references to the struct become pointers and
+/// references to `dyn Trait`.
+///
+/// The struct is left unchanged if it is used in any other way: if a field is read other than
+/// to call it, if an instance is built anywhere other than a static initializer, or if the struct
+/// type appears other than behind a pointer or reference.
+///
+/// Example:
+///
+/// ```ignore
+///     struct Ops {
+///         open: Option<unsafe fn(i32) -> i32>,
+///         close: Option<unsafe fn()>,
+///     }
+///
+///     static FILE_OPS: Ops = Ops {
+///         open: Some(file_open),
+///         close: None,
+///     };
+///
+///     unsafe fn run(ops: *const Ops) -> i32 {
+///         (*ops).open.unwrap()(1)
+///     }
+/// ```
+///
+/// After running `fn_ptr_struct_to_trait`, with `Ops` marked:
+///
+/// ```ignore
+///     trait Ops {
+///         unsafe fn open(&self, arg0: i32) -> i32;
+///         unsafe fn close(&self);
+///     }
+///
+///     struct FileOps;
+///     impl Ops for FileOps {
+///         unsafe fn open(&self, arg0: i32) -> i32 {
+///             file_open(arg0)
+///         }
+///         unsafe fn close(&self) {
+///             panic!("`close` is not implemented by `FILE_OPS`")
+///         }
+///     }
+///     static FILE_OPS: FileOps = FileOps;
+///
+///     unsafe fn run(ops: *const dyn Ops) -> i32 {
+///         (*ops).open(1)
+///     }
+/// ```
+pub struct FnPtrStructToTrait;
+
+/// A function pointer field of a struct being converted.
+struct FnPtrField {
+    ident: Ident,
+    def_id: DefId,
+    unsafety: Unsafety,
+    inputs: Vec<P<Ty>>,
+    output: FunctionRetTy,
+}
+
+impl FnPtrField {
+    /// Build the method signature corresponding to this field, without the trailing `;`.
+    fn method_sig(&self) -> String {
+        let unsafety = match self.unsafety {
+            Unsafety::Unsafe => "unsafe ",
+            Unsafety::Normal => "",
+        };
+        let mut args = vec!["&self".to_owned()];
+        args.extend(self.inputs.iter().enumerate()
+                    .map(|(i, ty)| format!("arg{}: {}", i, pprust::ty_to_string(ty))));
+        let ret = match self.output {
+            FunctionRetTy::Ty(ref ty) => format!(" -> {}", pprust::ty_to_string(ty)),
+            FunctionRetTy::Default(_) => String::new(),
+        };
+        format!("{}fn {}({}){}", unsafety, self.ident, args.join(", "), ret)
+    }
+}
+
+/// Get the function pointer type of a field, looking through `Option`.
+fn fn_ptr_type(ty: &Ty) -> Option<&BareFnTy> {
+    match ty.kind {
+        TyKind::BareFn(ref bf) => Some(bf),
+        TyKind::Path(None, ref path) => {
+            let seg = path.segments.last()?;
+            if seg.ident.as_str() != "Option" {
+                return None;
+            }
+            let args = match_or!([seg.args.as_ref().map(|a| &**a)]
+                                 Some(GenericArgs::AngleBracketed(ref abpd)) => &abpd.args;
+                                 return None);
+            match args.get(0) {
+                Some(GenericArg::Type(ref ty)) if args.len() == 1 => fn_ptr_type(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// If `e` is the callee of an indirect call through a struct field, such as `s.f`, `(s.f)`,
+/// `s.f.unwrap()`, or `s.f.expect("...")`, get the field access expression.
+fn field_callee(e: &Expr) -> Option<&Expr> {
+    match e.kind {
+        ExprKind::Paren(ref e) => field_callee(e),
+        ExprKind::Field(..) => Some(e),
+        ExprKind::MethodCall(ref seg, ref args)
+            if seg.ident.as_str() == "unwrap" || seg.ident.as_str() == "expect" =>
+            field_callee(&args[0]),
+        _ => None,
+    }
+}
+
+/// If `e` initializes a function pointer field of an instance, get the function it points to, or
+/// `None` if the field is null.
+fn field_fn(e: &Expr) -> Option<Option<&Expr>> {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Path(None, ref path) if path.segments.len() == 1 &&
+                                          path.segments[0].ident.as_str() == "None" =>
+            Some(None),
+        ExprKind::Path(..) => Some(Some(e)),
+        ExprKind::Call(ref func, ref args) if args.len() == 1 &&
+                                              pprust::expr_to_string(func) == "Some" =>
+            match strip_casts(&args[0]).kind {
+                ExprKind::Path(..) => Some(Some(strip_casts(&args[0]))),
+                _ => None,
+            },
+        _ => None,
+    }
+}
+
+/// An instance of a struct being converted, used as the initializer of a static.
+struct Instance {
+    static_ident: Ident,
+    type_name: String,
+    /// The function that each field was initialized to, or `None` for null fields.
+    fns: HashMap<Ident, Option<String>>,
+}
+
+/// Convert the struct `struct_id` into a trait, if all of its uses can be converted.
+fn convert_struct(krate: &mut Crate, cx: &RefactorCtxt, struct_id: NodeId) {
+    let struct_did = cx.node_def_id(struct_id);
+
+    // (1) Collect the fields.
+
+    let mut struct_ident = None;
+    let mut fields = Vec::new();
+    let mut ok = true;
+    visit_nodes(krate, |i: &Item| {
+        if i.id != struct_id {
+            return;
+        }
+        let (vd, generics) = match_or!([i.kind] ItemKind::Struct(ref vd, ref g) => (vd, g);
+                                       return);
+        if !generics.params.is_empty() {
+            ok = false;
+            return;
+        }
+        struct_ident = Some(i.ident);
+        for f in vd.fields() {
+            let bf = match_or!([fn_ptr_type(&f.ty)] Some(x) => x; { ok = false; return });
+            fields.push(FnPtrField {
+                ident: match_or!([f.ident] Some(x) => x; { ok = false; return }),
+                def_id: cx.node_def_id(f.id),
+                unsafety: bf.unsafety,
+                inputs: bf.decl.inputs.iter().map(|arg| arg.ty.clone()).collect(),
+                output: bf.decl.output.clone(),
+            });
+        }
+    });
+    let struct_ident = match_or!([struct_ident] Some(x) => x; return);
+    if !ok {
+        warn!("can't convert `{}`: not a non-generic struct of function pointers", struct_ident);
+        return;
+    }
+    let field_dids = fields.iter().map(|f| f.def_id).collect::<HashSet<_>>();
+    let trait_name = struct_ident.to_string();
+
+    // (2) Find the instances, which must all be static initializers.
+
+    let mut instances = HashMap::new();
+    let mut instance_exprs = HashSet::new();
+    let mut instance_tys = HashSet::new();
+    visit_nodes(krate, |i: &Item| {
+        let (ty, init) = match_or!([i.kind]
+                                   ItemKind::Static(ref ty, _, ref init) => (ty, init),
+                                   ItemKind::Const(ref ty, ref init) => (ty, init);
+                                   return);
+        let init_fields = match_or!([init.kind] ExprKind::Struct(_, ref fs, None) => fs; return);
+        if cx.try_resolve_ty(ty) != Some(struct_did) {
+            return;
+        }
+
+        let mut fns = HashMap::new();
+        for f in init_fields {
+            let func = match_or!([field_fn(&f.expr)] Some(x) => x; return);
+            fns.insert(f.ident, func.map(|e| pprust::expr_to_string(e)));
+        }
+
+        let mut type_name = camel_case(&i.ident.as_str());
+        if type_name == trait_name {
+            type_name.push_str("Impl");
+        }
+        instances.insert(i.id, Instance { static_ident: i.ident, type_name, fns });
+        instance_exprs.insert(init.id);
+        instance_tys.insert(ty.id);
+    });
+
+    // (3) Check that the struct is used only in ways we can convert.
+
+    let mut callee_fields = HashSet::new();
+    visit_nodes(krate, |e: &Expr| {
+        if let ExprKind::Call(ref callee, _) = e.kind {
+            if let Some(f) = field_callee(callee) {
+                callee_fields.insert(f.id);
+            }
+        }
+    });
+
+    let mut pointee_tys = HashSet::new();
+    visit_nodes(krate, |ty: &Ty| {
+        match ty.kind {
+            TyKind::Ptr(ref mty) | TyKind::Rptr(_, ref mty) => {
+                if cx.try_resolve_ty(&mty.ty) == Some(struct_did) {
+                    pointee_tys.insert(mty.ty.id);
+                }
+            }
+            _ => {}
+        }
+    });
+
+    visit_nodes(krate, |e: &Expr| {
+        match e.kind {
+            ExprKind::Field(ref obj, ident) => {
+                let did = match_or!([field_def_id(cx, obj.id, ident)] Some(x) => x; return);
+                if field_dids.contains(&did) && !callee_fields.contains(&e.id) {
+                    warn!("can't convert `{}`: field is used as a value: `{}`",
+                          trait_name, pprust::expr_to_string(e));
+                    ok = false;
+                }
+            }
+            ExprKind::Struct(..) => {
+                let is_instance = cx.opt_node_type(e.id).map_or(false, |ty| match ty.kind {
+                    TcxTyKind::Adt(adt, _) => adt.did == struct_did,
+                    _ => false,
+                });
+                if is_instance && !instance_exprs.contains(&e.id) {
+                    warn!("can't convert `{}`: instance is not a static initializer: `{}`",
+                          trait_name, pprust::expr_to_string(e));
+                    ok = false;
+                }
+            }
+            _ => {}
+        }
+    });
+    visit_nodes(krate, |ty: &Ty| {
+        if !matches!([ty.kind] TyKind::Path(..)) ||
+           pointee_tys.contains(&ty.id) || instance_tys.contains(&ty.id) {
+            return;
+        }
+        if cx.try_resolve_ty(ty) == Some(struct_did) {
+            warn!("can't convert `{}`: type is used by value: `{}`",
+                  trait_name, pprust::ty_to_string(ty));
+            ok = false;
+        }
+    });
+    if !ok {
+        return;
+    }
+
+    // (4) Replace the struct with a trait, and each instance with an implementation.
+
+    FlatMapNodes::visit(krate, |i: P<Item>| {
+        if i.id == struct_id {
+            let methods = fields.iter()
+                .map(|f| format!("    {};\n", f.method_sig()))
+                .collect::<String>();
+            let src = format!("{}trait {} {{\n{}}}",
+                              pprust::vis_to_string(&i.vis), trait_name, methods);
+            return parse_items(cx.session(), &src).into_iter().collect();
+        }
+
+        let inst = match_or!([instances.get(&i.id)] Some(x) => x; return smallvec![i]);
+        let methods = fields.iter().map(|f| {
+            let body = match inst.fns.get(&f.ident) {
+                Some(&Some(ref func)) => {
+                    let args = (0 .. f.inputs.len())
+                        .map(|i| format!("arg{}", i))
+                        .collect::<Vec<_>>();
+                    format!("{}({})", func, args.join(", "))
+                }
+                _ => format!("panic!(\"`{}` is not implemented by `{}`\")",
+                             f.ident, inst.static_ident),
+            };
+            format!("    {} {{\n        {}\n    }}\n", f.method_sig(), body)
+        }).collect::<String>();
+        let src = format!("{vis}struct {ty};\nimpl {tr} for {ty} {{\n{methods}}}",
+                          vis = pprust::vis_to_string(&i.vis),
+                          ty = inst.type_name,
+                          tr = trait_name,
+                          methods = methods);
+
+        let new_ty = parse_ty(cx.session(), &inst.type_name);
+        let new_init = parse_expr(cx.session(), &inst.type_name);
+        let i = i.map(|mut i| {
+            i.kind = match i.kind {
+                ItemKind::Static(_, mutbl, _) => ItemKind::Static(new_ty, mutbl, new_init),
+                ItemKind::Const(..) => ItemKind::Const(new_ty, new_init),
+                _ => unreachable!(),
+            };
+            i
+        });
+
+        let mut items: SmallVec<[P<Item>; 1]> =
+            parse_items(cx.session(), &src).into_iter().collect();
+        items.push(i);
+        items
+    });
+
+    // (5) Turn indirect calls through fields into method calls.
+
+    MutVisitNodes::visit(krate, |e: &mut P<Expr>| {
+        let new_kind = {
+            let (callee, args) = match_or!([e.kind] ExprKind::Call(ref c, ref a) => (c, a);
+                                           return);
+            let field = match_or!([field_callee(callee)] Some(x) => x; return);
+            let (obj, ident) = expect!([field.kind] ExprKind::Field(ref o, i) => (o, i));
+            let did = match_or!([field_def_id(cx, obj.id, ident)] Some(x) => x; return);
+            if !field_dids.contains(&did) {
+                return;
+            }
+            let mut new_args = vec![obj.clone()];
+            new_args.extend(args.iter().cloned());
+            ExprKind::MethodCall(PathSegment::from_ident(ident), new_args)
+        };
+        e.kind = new_kind;
+    });
+
+    // (6) Pointers to the struct become pointers to trait objects.
+
+    let dyn_ty = parse_ty(cx.session(), &format!("dyn {}", trait_name));
+    MutVisitNodes::visit(krate, |ty: &mut P<Ty>| {
+        if pointee_tys.contains(&ty.id) {
+            *ty = dyn_ty.clone();
+        }
+    });
+}
+
+impl Transform for FnPtrStructToTrait {
+    fn transform(&self, krate: &mut Crate, st: &CommandState, cx: &RefactorCtxt) {
+        let mut structs = Vec::new();
+        visit_nodes(krate, |i: &Item| {
+            if st.marked(i.id, "target") && matches!([i.kind] ItemKind::Struct(..)) {
+                structs.push(i.id);
+            }
+        });
+
+        for id in structs {
+            convert_struct(krate, cx, id);
+        }
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
+
+pub fn register_commands(reg: &mut Registry) {
+    use super::mk;
+
+    reg.register("fn_ptr_struct_to_trait", |_args| mk(FnPtrStructToTrait));
+}
@@ -7,15 +7,20 @@ use rustc::ty::{TyKind, ParamEnv};
 use syntax::ast::*;
 use syntax::mut_visit::{self, MutVisitor};
 use syntax::ptr::P;
+use syntax::print::pprust;
 use syntax::visit::{self, Visitor};
 
 use c2rust_ast_builder::mk;
+use crate::analysis::uninit_reads;
 use crate::ast_manip::{MutVisit, MutVisitNodes, fold_blocks, visit_nodes};
+use crate::ast_manip::fn_edit::visit_fns;
 use crate::command::{CommandState, DriverCommand, Registry};
-use crate::driver::{Phase};
+use crate::driver::{Phase, parse_expr, parse_stmts};
 use crate::matcher::{MatchCtxt, Subst, mut_visit_match_with, replace_stmts};
 use crate::reflect::reflect_tcx_ty;
 use crate::transform::Transform;
+use crate::transform::heap::{is_null_ptr, strip_casts};
+use crate::util::Lone;
 use crate::RefactorCtxt;
 
 
@@ -249,9 +254,10 @@ fn expr_has_side_effects(cx: &RefactorCtxt, e: &P<Expr>) -> bool {
 }
 
 
-fn is_uninit_call(cx: &RefactorCtxt, e: &Expr) -> bool {
+/// Check if `e` is a call to the function `std::mem::<name>`.
+fn is_mem_call(cx: &RefactorCtxt, e: &Expr, name: &str) -> bool {
     let func = match_or!([e.kind] ExprKind::Call(ref func, _) => func; return false);
-    let def_id = cx.resolve_expr(func);
+    let def_id = match_or!([cx.try_resolve_expr(func)] Some(x) => x; return false);
     if def_id.krate == LOCAL_CRATE {
         return false;
     }
@@ -261,7 +267,32 @@ fn is_uninit_call(cx: &RefactorCtxt, e: &Expr) -> bool {
     (crate_name.as_str() == "std" || crate_name.as_str() == "core") &&
     path.data.len() == 2 &&
     path.data[0].data.get_opt_name().map_or(false, |sym| sym.as_str() == "mem") &&
-    path.data[1].data.get_opt_name().map_or(false, |sym| sym.as_str() == "uninitialized")
+    path.data[1].data.get_opt_name().map_or(false, |sym| sym.as_str() == name)
+}
+
+fn is_uninit_call(cx: &RefactorCtxt, e: &Expr) -> bool {
+    is_mem_call(cx, e, "uninitialized")
+}
+
+/// Check if `e` is a `mem::zeroed()` or `mem::uninitialized()` call.
+fn is_zeroed_call(cx: &RefactorCtxt, e: &Expr) -> bool {
+    is_mem_call(cx, e, "zeroed") || is_uninit_call(cx, e)
+}
+
+/// Check if `e` is one of the placeholder values the translator uses to initialize locals that
+/// had no initializer in C.
+fn is_dummy_init(cx: &RefactorCtxt, e: &Expr) -> bool {
+    let e = strip_casts(e);
+    match e.kind {
+        ExprKind::Lit(ref l) => match l.kind {
+            LitKind::Int(0, _) => true,
+            LitKind::Float(sym, _) | LitKind::FloatUnsuffixed(sym) =>
+                sym.as_str().parse::<f64>() == Ok(0.0),
+            _ => false,
+        },
+        ExprKind::Repeat(ref elem, _) => is_dummy_init(cx, elem),
+        _ => is_zeroed_call(cx, e) || is_null_ptr(e),
+    }
 }
 
 
@@ -510,6 +541,175 @@ impl Transform for RemoveRedundantLetTypes {
     }
 }
 
+/// # `fix_dummy_inits` Command
+///
+/// Usage: `fix_dummy_inits`
+///
+/// Clean up the dummy initializers the translator gives to local variables that
+/// had none in C, such as `let mut x: i32 = 0;` or
+/// `let mut s: S = ::std::mem::zeroed();`.  An initializer counts as a dummy if
+/// it is `mem::zeroed()`, `mem::uninitialized()`, a zero number, a null pointer,
+/// or an array of one of these.
+///
+/// If the uninitialized-reads analysis (`analysis/uninit_reads.rs`) shows that
+/// the dummy value is never read, because every use of the variable comes after
+/// some other assignment to the whole variable, the initializer is removed,
+/// leaving `let mut x: i32;`.
+///
+/// Otherwise, a struct variable initialized with `mem::zeroed()` or
+/// `mem::uninitialized()` whose fields are all assigned by the statements right
+/// after its `let` is converted to use `MaybeUninit`, and only assumed to be
+/// initialized once the last field is assigned.  This is only done when the
+/// assigned values don't mention the variable and the struct has nothing that
+/// needs to be dropped.
+///
+/// Variables without a type annotation are left alone.
+///
+/// Example:
+///
+/// ```ignore
+///     let mut p: Point = ::std::mem::zeroed();
+///     p.x = 1;
+///     p.y = 2;
+/// ```
+///
+/// After running `fix_dummy_inits`:
+///
+/// ```ignore
+///     let mut p = ::std::mem::MaybeUninit::<Point>::uninit();
+///     (*p.as_mut_ptr()).x = 1;
+///     (*p.as_mut_ptr()).y = 2;
+///     let mut p = p.assume_init();
+/// ```
+pub struct FixDummyInits;
+
+/// If `s` is `x.f = e;`, where `e` doesn't mention `x`, return the field name `f`.
+fn field_init_of(s: &Stmt, x: Ident) -> Option<Ident> {
+    let e = match_or!([s.kind] StmtKind::Semi(ref e) => e; return None);
+    let (lhs, rhs) = match_or!([e.kind] ExprKind::Assign(ref lhs, ref rhs) => (lhs, rhs);
+                               return None);
+    let (base, f) = match_or!([lhs.kind] ExprKind::Field(ref base, f) => (base, f); return None);
+    let is_x = |e: &Expr| match e.kind {
+        ExprKind::Path(None, ref path) => path.segments.len() == 1 && path.segments[0].ident == x,
+        _ => false,
+    };
+    if !is_x(base) {
+        return None;
+    }
+    let mut mentions_x = false;
+    visit_nodes(&**rhs, |e: &Expr| if is_x(e) { mentions_x = true; });
+    if mentions_x { None } else { Some(f) }
+}
+
+impl Transform for FixDummyInits {
+    fn transform(&self, krate: &mut Crate, _st: &CommandState, cx: &RefactorCtxt) {
+        // (1) Find the dummy-initialized locals of each function whose initial values are never
+        // read.
+
+        let mut unread = HashSet::new();
+        visit_fns(krate, |fl| {
+            let block = match_or!([fl.block] Some(ref b) => b; return);
+            let mut inits = HashMap::new();
+            let mut pat_ids = HashMap::new();
+            visit_nodes(&**block, |l: &Local| {
+                let init = match_or!([l.init] Some(ref e) => e; return);
+                let simple = matches!([l.pat.kind]
+                                      PatKind::Ident(BindingMode::ByValue(_), _, None));
+                if simple && l.ty.is_some() && is_dummy_init(cx, init) {
+                    inits.insert(l.pat.span, l.span);
+                    pat_ids.insert(l.pat.span, l.pat.id);
+                }
+            });
+            if inits.is_empty() {
+                return;
+            }
+            let def_id = cx.node_def_id(fl.id);
+            let spans = match_or!([uninit_reads::unread_dummy_inits(cx, def_id, &inits)]
+                                  Some(x) => x; return);
+            unread.extend(spans.iter().filter_map(|sp| pat_ids.get(sp).cloned()));
+        });
+
+        // (2) Remove their initializers.
+
+        MutVisitNodes::visit(krate, |l: &mut P<Local>| {
+            if unread.contains(&l.pat.id) {
+                l.init = None;
+            }
+        });
+
+        // (3) Convert structs that are initialized one field at a time to `MaybeUninit`.
+
+        let tcx = cx.ty_ctxt();
+        MutVisitNodes::visit(krate, |b: &mut P<Block>| {
+            let mut i = 0;
+            while i < b.stmts.len() {
+                let (x, ty, num_inits) = {
+                    let l = match_or!([b.stmts[i].kind] StmtKind::Local(ref l) => l;
+                                      { i += 1; continue });
+                    let x = match_or!([l.pat.kind]
+                        PatKind::Ident(BindingMode::ByValue(Mutability::Mutable), x, None) => x;
+                        { i += 1; continue });
+                    let init = match_or!([l.init] Some(ref e) => e; { i += 1; continue });
+                    let ty = match_or!([l.ty] Some(ref t) => t; { i += 1; continue });
+                    let rty = match_or!([cx.opt_node_type(l.pat.id)] Some(x) => x;
+                                        { i += 1; continue });
+                    let fields = match rty.kind {
+                        TyKind::Adt(adt, _) if adt.is_struct() =>
+                            adt.non_enum_variant().fields.iter()
+                                .map(|f| f.ident.name)
+                                .collect::<HashSet<_>>(),
+                        _ => { i += 1; continue }
+                    };
+                    if !is_zeroed_call(cx, init) || rty.needs_drop(tcx, ParamEnv::empty()) {
+                        i += 1;
+                        continue;
+                    }
+
+                    // Find the statements that assign every field.
+                    let mut assigned = HashSet::new();
+                    let mut n = 0;
+                    for s in &b.stmts[i + 1..] {
+                        if assigned.len() == fields.len() {
+                            break;
+                        }
+                        let f = match_or!([field_init_of(s, x)] Some(f) => f; break);
+                        assigned.insert(f.name);
+                        n += 1;
+                    }
+                    if assigned != fields {
+                        i += 1;
+                        continue;
+                    }
+                    (x, ty.clone(), n)
+                };
+
+                b.stmts[i] = parse_stmts(cx.session(), &format!(
+                    "let mut {} = ::std::mem::MaybeUninit::<{}>::uninit();",
+                    x, pprust::ty_to_string(&ty))).lone();
+                let ptr = parse_expr(cx.session(), &format!("(*{}.as_mut_ptr())", x));
+                for s in &mut b.stmts[i + 1..i + 1 + num_inits] {
+                    if let StmtKind::Semi(ref mut e) = s.kind {
+                        if let ExprKind::Assign(ref mut lhs, _) = e.kind {
+                            if let ExprKind::Field(ref mut base, _) = lhs.kind {
+                                *base = ptr.clone();
+                            }
+                        }
+                    }
+                }
+                let init = parse_stmts(cx.session(),
+                                       &format!("let mut {} = {}.assume_init();", x, x));
+                let end = i + 1 + num_inits;
+                b.stmts.insert(end, init.lone());
+                i = end + 1;
+            }
+        });
+    }
+
+    fn min_phase(&self) -> Phase {
+        Phase::Phase3
+    }
+}
+
 /// # `expand_local_ptr_tys` Command
 ///
 /// Usage: `expand_local_ptr_tys`
@@ -553,6 +753,7 @@ pub fn register_commands(reg: &mut Registry) {
     reg.register("fold_let_assign", |_args| mk(FoldLetAssign));
     reg.register("uninit_to_default", |_args| mk(UninitToDefault));
     reg.register("remove_redundant_let_types", |_args| mk(RemoveRedundantLetTypes));
+    reg.register("fix_dummy_inits", |_args| mk(FixDummyInits));
     reg.register("expand_local_ptr_tys", |_args| {
         Box::new(DriverCommand::new(Phase::Phase3, move |st, cx| {
             expand_local_ptr_tys(st, cx);
@@ -0,0 +1,50 @@
+extern "C" {
+    fn malloc(size: u64) -> *mut ::std::ffi::c_void;
+    fn free(ptr: *mut ::std::ffi::c_void);
+}
+
+struct List {
+    #[ownership_role(OWNED)]
+    head: *mut Node,
+    #[ownership_role(ALIASED)]
+    name: *const i8,
+}
+
+struct Node {
+    value: i32,
+    #[ownership_role(OWNED)]
+    next: *mut Node,
+}
+
+#[ownership_return(OWNED)]
+unsafe fn new_node(value: i32) -> *mut Node {
+    let n = malloc(::std::mem::size_of::<Node>() as u64) as *mut Node;
+    (*n).value = value;
+    (*n).next = 0 as *mut Node;
+    n
+}
+
+#[ownership_roles(list = BORROWED)]
+unsafe fn push(list: *mut List, value: i32) {
+    let n = new_node(value);
+    (*n).next = (*list).head;
+    (*list).head = n;
+}
+
+#[ownership_roles(list = BORROWED, name = ALIASED)]
+unsafe fn set_name(list: *mut List, name: *const i8) {
+    (*list).name = name;
+}
+
+#[ownership_roles(n = OWNED)]
+unsafe fn release(n: *mut Node) {
+    free(n as *mut ::std::ffi::c_void);
+}
+
+#[ownership_roles(n = BORROWED)]
+unsafe fn sum(n: *const Node) -> i32 {
+    if n.is_null() {
+        return 0;
+    }
+    (*n).value + sum((*n).next)
+}
@@ -0,0 +1,42 @@
+extern "C" {
+    fn malloc(size: u64) -> *mut ::std::ffi::c_void;
+    fn free(ptr: *mut ::std::ffi::c_void);
+}
+
+struct List {
+    head: *mut Node,
+    name: *const i8,
+}
+
+struct Node {
+    value: i32,
+    next: *mut Node,
+}
+
+unsafe fn new_node(value: i32) -> *mut Node {
+    let n = malloc(::std::mem::size_of::<Node>() as u64) as *mut Node;
+    (*n).value = value;
+    (*n).next = 0 as *mut Node;
+    n
+}
+
+unsafe fn push(list: *mut List, value: i32) {
+    let n = new_node(value);
+    (*n).next = (*list).head;
+    (*list).head = n;
+}
+
+unsafe fn set_name(list: *mut List, name: *const i8) {
+    (*list).name = name;
+}
+
+unsafe fn release(n: *mut Node) {
+    free(n as *mut ::std::ffi::c_void);
+}
+
+unsafe fn sum(n: *const Node) -> i32 {
+    if n.is_null() {
+        return 0;
+    }
+    (*n).value + sum((*n).next)
+}
@@ -0,0 +1,32 @@
+#![feature(const_generics)]
+unsafe fn scale<const N: usize>(v: &mut [f32; N], k: f32) {
+    let mut i = 0;
+    while i < v.len() as i32 {
+        v[i as usize] *= k;
+        i += 1;
+    }
+}
+
+unsafe fn trace<const N: usize>(m: &[[f32; 3]; N]) -> f32 {
+    (*m)[0][0] + (*m)[1][1] + (*m)[2][2]
+}
+
+unsafe fn first<const N: usize>(v: &[i32; N]) -> i32 {
+    v[0 as usize]
+}
+
+unsafe fn last(v: *const i32, n: i32) -> i32 {
+    *v.offset((n - 1) as isize)
+}
+
+fn main() {
+    let mut v: [f32; 3] = [1., 2., 3.];
+    let m: [[f32; 3]; 3] = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    let w = [1, 2, 3, 4];
+    unsafe {
+        scale(&mut v, 2.);
+        trace(&m);
+        first(&w);
+        last(w.as_ptr(), 2);
+    }
+}
@@ -0,0 +1,31 @@
+unsafe fn scale(v: *mut f32, n: i32, k: f32) {
+    let mut i = 0;
+    while i < n {
+        *v.offset(i as isize) *= k;
+        i += 1;
+    }
+}
+
+unsafe fn trace(m: *const [[f32; 3]; 3]) -> f32 {
+    (*m)[0][0] + (*m)[1][1] + (*m)[2][2]
+}
+
+unsafe fn first(v: *const i32) -> i32 {
+    *v
+}
+
+unsafe fn last(v: *const i32, n: i32) -> i32 {
+    *v.offset((n - 1) as isize)
+}
+
+fn main() {
+    let mut v: [f32; 3] = [1., 2., 3.];
+    let m: [[f32; 3]; 3] = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    let w = [1, 2, 3, 4];
+    unsafe {
+        scale(v.as_mut_ptr(), 3, 2.);
+        trace(&m as *const [[f32; 3]; 3]);
+        first(w.as_ptr());
+        last(w.as_ptr(), 2);
+    }
+}
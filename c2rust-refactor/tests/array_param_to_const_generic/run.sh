@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(arg && any_child(match_pat(v) || match_pat(m)));' \; \
+    select len 'crate; desc(arg && any_child(match_pat(n)));' \; \
+    array_param_to_const_generic \
+    -- old.rs $rustflags
@@ -0,0 +1,42 @@
+const FLAG_READ: u32 = 1 << 0;
+const FLAG_WRITE: u32 = 1 << 1;
+const FLAG_EXEC: u32 = 1 << 2;
+const LIMIT: u32 = 100;
+
+#[derive(Copy, Clone)]
+struct File {
+    mode: FileMode,
+    size: u32,
+}
+bitflags::bitflags! {
+    struct FileMode: u32 {
+        const FLAG_READ = 1 << 0;
+        const FLAG_WRITE = 1 << 1;
+        const FLAG_EXEC = 1 << 2;
+    }
+}
+
+fn open(size: u32) -> File {
+    File {
+        mode: FileMode::FLAG_READ | FileMode::FLAG_WRITE,
+        size,
+    }
+}
+
+fn update(f: &mut File) {
+    if f.mode.contains(FileMode::FLAG_WRITE) {
+        f.mode.insert(FileMode::FLAG_EXEC);
+    }
+    if !f.mode.intersects(FileMode::FLAG_READ | FileMode::FLAG_EXEC) {
+        f.mode = FileMode::empty();
+    }
+    if f.size > LIMIT {
+        f.mode.remove(FileMode::FLAG_WRITE);
+    }
+}
+
+fn main() {
+    let mut f = open(10);
+    update(&mut f);
+    println!("{}", f.size);
+}
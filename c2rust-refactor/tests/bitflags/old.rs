@@ -0,0 +1,35 @@
+const FLAG_READ: u32 = 1 << 0;
+const FLAG_WRITE: u32 = 1 << 1;
+const FLAG_EXEC: u32 = 1 << 2;
+const LIMIT: u32 = 100;
+
+#[derive(Copy, Clone)]
+struct File {
+    mode: u32,
+    size: u32,
+}
+
+fn open(size: u32) -> File {
+    File {
+        mode: FLAG_READ | FLAG_WRITE,
+        size,
+    }
+}
+
+fn update(f: &mut File) {
+    if f.mode & FLAG_WRITE != 0 {
+        f.mode |= FLAG_EXEC;
+    }
+    if f.mode & (FLAG_READ | FLAG_EXEC) == 0 {
+        f.mode = 0;
+    }
+    if f.size > LIMIT {
+        f.mode &= !FLAG_WRITE;
+    }
+}
+
+fn main() {
+    let mut f = open(10);
+    update(&mut f);
+    println!("{}", f.size);
+}
@@ -0,0 +1,22 @@
+use std::ffi::c_void;
+
+unsafe fn for_each<F: FnMut(i32)>(xs: *const i32, n: usize, mut cb: F) {
+    if false {
+        return;
+    }
+    for i in 0..n {
+        cb(*xs.add(i));
+    }
+}
+
+unsafe extern "C" fn add(x: i32, data: *mut i32) {
+    *(data) += x;
+}
+
+unsafe fn sum(xs: *const i32, n: usize) -> i32 {
+    let mut sum: i32 = 0;
+    for_each(xs, n, |a0| unsafe { add(a0, &mut sum as *mut i32) });
+    sum
+}
+
+fn main() {}
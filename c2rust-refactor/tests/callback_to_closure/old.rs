@@ -0,0 +1,25 @@
+use std::ffi::c_void;
+
+unsafe fn for_each(xs: *const i32,
+                   n: usize,
+                   cb: Option<unsafe extern "C" fn(i32, *mut c_void)>,
+                   data: *mut c_void) {
+    if cb.is_none() {
+        return;
+    }
+    for i in 0..n {
+        cb.expect("non-null function pointer")(*xs.add(i), data);
+    }
+}
+
+unsafe extern "C" fn add(x: i32, data: *mut c_void) {
+    *(data as *mut i32) += x;
+}
+
+unsafe fn sum(xs: *const i32, n: usize) -> i32 {
+    let mut sum: i32 = 0;
+    for_each(xs, n, Some(add), &mut sum as *mut i32 as *mut c_void);
+    sum
+}
+
+fn main() {}
@@ -0,0 +1,47 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn strcat(_: *mut i8, _: *const i8) -> *mut i8;
+    fn sprintf(_: *mut i8, _: *const i8, ...) -> i32;
+    fn puts(_: *const i8) -> i32;
+}
+
+struct Widget {
+    id: i32,
+    label: String,
+}
+
+unsafe fn greet(suffix: *const i8) -> u64 {
+    let mut n: u64 = 0;
+    let mut name: String = String::new();
+    name.clear();
+    name.push_str("abc");
+    name.push_str(::std::ffi::CStr::from_ptr(suffix).to_str().unwrap());
+    if name == "abcdef" {
+        n = name.len() as u64;
+    }
+    puts(::std::ffi::CString::new(name.as_str()).unwrap().as_ptr() as *mut _);
+    name.clear();
+    n
+}
+
+unsafe fn make_widget(id: i32) -> Widget {
+    let mut w = Widget {
+        id: id,
+        label: String::new(),
+    };
+    w.label = format!("widget {:}", w.id);
+    if w.label.is_empty() {
+        w.id = -1;
+    }
+    w
+}
+
+fn main() {
+    let suffix = ::std::ffi::CString::new("def").unwrap();
+    unsafe {
+        println!("{}", greet(suffix.as_ptr()));
+        make_widget(7);
+    }
+}
@@ -0,0 +1,50 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn strcat(_: *mut i8, _: *const i8) -> *mut i8;
+    fn sprintf(_: *mut i8, _: *const i8, ...) -> i32;
+    fn puts(_: *const i8) -> i32;
+}
+
+struct Widget {
+    id: i32,
+    label: [i8; 16],
+}
+
+unsafe fn greet(suffix: *const i8) -> u64 {
+    let mut n: u64 = 0;
+    let mut name: [i8; 32] = [0; 32];
+    strcpy(name.as_mut_ptr(), b"abc\0" as *const u8 as *const i8);
+    strcat(name.as_mut_ptr(), suffix);
+    if strcmp(name.as_mut_ptr(), b"abcdef\0" as *const u8 as *const i8) == 0 {
+        n = strlen(name.as_mut_ptr());
+    }
+    puts(name.as_mut_ptr());
+    name[0] = 0;
+    n
+}
+
+unsafe fn make_widget(id: i32) -> Widget {
+    let mut w = Widget {
+        id: id,
+        label: [0; 16],
+    };
+    sprintf(
+        w.label.as_mut_ptr(),
+        b"widget %d\0" as *const u8 as *const i8,
+        w.id,
+    );
+    if w.label[0] as i32 == 0 {
+        w.id = -1;
+    }
+    w
+}
+
+fn main() {
+    let suffix = ::std::ffi::CString::new("def").unwrap();
+    unsafe {
+        println!("{}", greet(suffix.as_ptr()));
+        make_widget(7);
+    }
+}
@@ -0,0 +1,22 @@
+const SIZE: u32 = 4 * 1024;
+
+fn hash(s: &[u32]) -> u32 {
+    let mut h: u32 = 5381;
+    let mut i = 0;
+    while i < s.len() {
+        h = h * 33 + s[i];
+        i += 1;
+    }
+    h
+}
+
+fn countdown(mut n: u32, mut m: i32) -> i32 {
+    n -= 1;
+    m -= 1;
+    (n as i32) + m
+}
+
+fn main() {
+    hash(&[1, 2, 3]);
+    countdown(SIZE, 0);
+}
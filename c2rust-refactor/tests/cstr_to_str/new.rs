@@ -0,0 +1,49 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn puts(_: *const i8) -> i32;
+}
+
+struct Button {
+    label: &'static str,
+    width: u64,
+}
+
+unsafe fn greet(name: &str, buf: *mut i8) -> u64 {
+    if name
+        .as_bytes()
+        .cmp(::std::ffi::CStr::from_ptr(b"admin\0" as *const u8 as *const i8).to_bytes())
+        as i32
+        == 0
+    {
+        return 0;
+    }
+    {
+        let dest = buf;
+        let src = name.as_bytes();
+        ::std::ptr::copy_nonoverlapping(src.as_ptr() as *const _, dest, src.len());
+        *dest.add(src.len()) = 0;
+        dest
+    };
+    puts(::std::ffi::CString::new(name).unwrap().as_ptr());
+    name.len() as u64
+}
+
+unsafe fn button(other: *const i8) -> Button {
+    let mut b = Button {
+        label: "OK",
+        width: 0,
+    };
+    b.label = ::std::ffi::CStr::from_ptr(other).to_str().unwrap();
+    b.width = b.label.len() as u64;
+    b
+}
+
+fn main() {
+    let mut buf = [0i8; 16];
+    unsafe {
+        greet("world", buf.as_mut_ptr());
+        button(buf.as_ptr());
+    }
+}
@@ -0,0 +1,38 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn puts(_: *const i8) -> i32;
+}
+
+struct Button {
+    label: *const i8,
+    width: u64,
+}
+
+unsafe fn greet(name: *const i8, buf: *mut i8) -> u64 {
+    if strcmp(name, b"admin\0" as *const u8 as *const i8) == 0 {
+        return 0;
+    }
+    strcpy(buf, name);
+    puts(name);
+    strlen(name)
+}
+
+unsafe fn button(other: *const i8) -> Button {
+    let mut b = Button {
+        label: b"OK\0" as *const u8 as *const i8,
+        width: 0,
+    };
+    b.label = other;
+    b.width = strlen(b.label);
+    b
+}
+
+fn main() {
+    let mut buf = [0i8; 16];
+    unsafe {
+        greet(b"world\0" as *const u8 as *const i8, buf.as_mut_ptr());
+        button(buf.as_ptr());
+    }
+}
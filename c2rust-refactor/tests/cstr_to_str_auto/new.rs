@@ -0,0 +1,21 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn read(_: i32, _: *mut i8, _: u64) -> i64;
+}
+
+unsafe fn name_len(name: &str) -> u64 {
+    name.len() as u64
+}
+
+unsafe fn data_len(data: &::std::ffi::CStr) -> u64 {
+    data.to_bytes().len() as u64
+}
+
+fn main() {
+    let mut buf = [0i8; 16];
+    unsafe {
+        read(0, buf.as_mut_ptr(), 15);
+        name_len("world");
+        data_len(::std::ffi::CStr::from_ptr(buf.as_ptr()));
+    }
+}
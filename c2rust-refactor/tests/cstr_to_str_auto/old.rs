@@ -0,0 +1,21 @@
+extern "C" {
+    fn strlen(_: *const i8) -> u64;
+    fn read(_: i32, _: *mut i8, _: u64) -> i64;
+}
+
+unsafe fn name_len(name: *const i8) -> u64 {
+    strlen(name)
+}
+
+unsafe fn data_len(data: *const i8) -> u64 {
+    strlen(data)
+}
+
+fn main() {
+    let mut buf = [0i8; 16];
+    unsafe {
+        read(0, buf.as_mut_ptr(), 15);
+        name_len(b"world\0" as *const u8 as *const i8);
+        data_len(buf.as_ptr());
+    }
+}
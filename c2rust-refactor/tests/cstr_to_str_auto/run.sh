@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    analyze_string_provenance \; \
+    select target 'crate; desc(arg && (any_child(match_pat(name)) || any_child(match_pat(data))));' \; \
+    cstr_to_str auto \
+    -- old.rs $rustflags
@@ -0,0 +1,41 @@
+extern "C" {
+    fn dlopen(__file: *const i8, __mode: i32) -> *mut ::std::ffi::c_void;
+    fn dlsym(__handle: *mut ::std::ffi::c_void, __name: *const i8) -> *mut ::std::ffi::c_void;
+    fn dlclose(__handle: *mut ::std::ffi::c_void) -> i32;
+}
+
+unsafe fn run_plugin(mut path: *const i8) -> i32 {
+    let mut lib: Option<::libloading::Library> = ::libloading::os::unix::Library::open(
+        Some(
+            <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(
+                ::std::ffi::CStr::from_ptr(path).to_bytes(),
+            ),
+        ),
+        0x2 as i32,
+    )
+    .map(::libloading::Library::from)
+    .ok();
+    if lib.is_none() {
+        return -(1 as i32);
+    }
+    let mut init: Option<unsafe extern "C" fn(i32) -> i32> = lib
+        .as_ref()
+        .and_then(|library| {
+            library
+                .get::<unsafe extern "C" fn(i32) -> i32>(b"plugin_init\0")
+                .ok()
+        })
+        .map(|s| *s);
+    let mut version: *mut i32 = lib
+        .as_ref()
+        .and_then(|library| library.get::<*mut i32>(b"plugin_version\0").ok())
+        .map_or(::std::ptr::null_mut(), |s| *s);
+    let mut ret: i32 = -(1 as i32);
+    if init.is_some() && !version.is_null() {
+        ret = init.expect("non-null function pointer")(*version);
+    }
+    lib = None;
+    return ret;
+}
+
+fn main() {}
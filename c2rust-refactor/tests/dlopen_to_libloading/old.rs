@@ -0,0 +1,26 @@
+extern "C" {
+    fn dlopen(__file: *const i8, __mode: i32) -> *mut ::std::ffi::c_void;
+    fn dlsym(__handle: *mut ::std::ffi::c_void, __name: *const i8) -> *mut ::std::ffi::c_void;
+    fn dlclose(__handle: *mut ::std::ffi::c_void) -> i32;
+}
+
+unsafe fn run_plugin(mut path: *const i8) -> i32 {
+    let mut lib: *mut ::std::ffi::c_void = dlopen(path, 0x2 as i32);
+    if lib.is_null() {
+        return -(1 as i32);
+    }
+    let mut init: Option<unsafe extern "C" fn(i32) -> i32> =
+        ::std::mem::transmute::<*mut ::std::ffi::c_void, Option<unsafe extern "C" fn(i32) -> i32>>(
+            dlsym(lib, b"plugin_init\0" as *const u8 as *const i8),
+        );
+    let mut version: *mut i32 =
+        dlsym(lib, b"plugin_version\0" as *const u8 as *const i8) as *mut i32;
+    let mut ret: i32 = -(1 as i32);
+    if init.is_some() && !version.is_null() {
+        ret = init.expect("non-null function pointer")(*version);
+    }
+    dlclose(lib);
+    return ret;
+}
+
+fn main() {}
@@ -0,0 +1,59 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Error {
+    Parse,
+    Load,
+}
+
+fn parse(s: &str) -> Result<i32, Error> {
+    s.parse().map_err(|_| Error::Parse)
+}
+
+fn load(n: i32) -> Result<(), Error> {
+    if n < 0 {
+        return Err(Error::Load);
+    }
+    Ok(())
+}
+
+fn init(s: &str) -> Result<i32, Error> {
+    parse(s).map_err(|_| Error::Parse)?;
+    load(1)?;
+    load(2).map_err(|_| Error::Load)?;
+    let n = parse(s)?;
+    Ok(n)
+}
+
+fn sum(a: &str, b: &str) -> Result<i32, Error> {
+    let mut total = 0;
+    let n = parse(a).map_err(|_| Error::Parse)?;
+    total += n;
+    let m = parse(b).map_err(|_| Error::Load)?;
+    total += m;
+    Ok(total)
+}
+
+fn scoped(a: &str) -> Result<i32, Error> {
+    let n = 1;
+    // Not flattened: `n` in the body would shadow the `n` used below.
+    if parse(a).is_ok() {
+        let n = 2;
+        load(n)?;
+    } else {
+        return Err(Error::Parse);
+    }
+    // Not converted: code inside closures is left alone.
+    let check = |s: &str| {
+        if parse(s).is_err() {
+            return Err(Error::Parse);
+        }
+        Ok(())
+    };
+    check(a)?;
+    Ok(n)
+}
+
+fn main() {
+    println!("{:?}", init("1"));
+    println!("{:?}", sum("1", "2"));
+    println!("{:?}", scoped("1"));
+}
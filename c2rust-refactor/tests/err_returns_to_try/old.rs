@@ -0,0 +1,75 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Error {
+    Parse,
+    Load,
+}
+
+fn parse(s: &str) -> Result<i32, Error> {
+    s.parse().map_err(|_| Error::Parse)
+}
+
+fn load(n: i32) -> Result<(), Error> {
+    if n < 0 {
+        return Err(Error::Load);
+    }
+    Ok(())
+}
+
+fn init(s: &str) -> Result<i32, Error> {
+    if parse(s).is_ok() {
+        if let Err(e) = load(1) {
+            return Err(e);
+        }
+        if load(2).is_err() {
+            return Err(Error::Load);
+        }
+        let n = match parse(s) {
+            Ok(n) => n,
+            Err(e) => return Err(e),
+        };
+        Ok(n)
+    } else {
+        return Err(Error::Parse);
+    }
+}
+
+fn sum(a: &str, b: &str) -> Result<i32, Error> {
+    let mut total = 0;
+    if let Ok(n) = parse(a) {
+        total += n;
+        if let Ok(m) = parse(b) {
+            total += m;
+            Ok(total)
+        } else {
+            return Err(Error::Load);
+        }
+    } else {
+        return Err(Error::Parse);
+    }
+}
+
+fn scoped(a: &str) -> Result<i32, Error> {
+    let n = 1;
+    // Not flattened: `n` in the body would shadow the `n` used below.
+    if parse(a).is_ok() {
+        let n = 2;
+        load(n)?;
+    } else {
+        return Err(Error::Parse);
+    }
+    // Not converted: code inside closures is left alone.
+    let check = |s: &str| {
+        if parse(s).is_err() {
+            return Err(Error::Parse);
+        }
+        Ok(())
+    };
+    check(a)?;
+    Ok(n)
+}
+
+fn main() {
+    println!("{:?}", init("1"));
+    println!("{:?}", sum("1", "2"));
+    println!("{:?}", scoped("1"));
+}
@@ -0,0 +1,56 @@
+extern "C" {
+    fn __errno_location() -> *mut i32;
+    fn close(fd: i32) -> i32;
+}
+
+const EINTR: i32 = 4;
+const EACCES: i32 = 13;
+
+/// A stand-in for the `c2rust-errno` crate.
+mod c2rust_errno {
+    use std::cell::Cell;
+    use std::io;
+    thread_local! {
+        static ERRNO: Cell<i32> = Cell::new(0);
+    }
+    pub fn errno_location() -> *mut i32 {
+        ERRNO.with(|errno| errno.as_ptr())
+    }
+    pub fn errno() -> i32 {
+        ERRNO.with(|errno| errno.get())
+    }
+    pub fn set_errno(value: i32) {
+        ERRNO.with(|errno| errno.set(value))
+    }
+    pub fn last_os_error() -> io::Error {
+        io::Error::from_raw_os_error(errno())
+    }
+}
+
+unsafe fn check_libc() -> i32 {
+    *__errno_location() = 0;
+    close(-1);
+    if *__errno_location() == EINTR {
+        return 1;
+    }
+    if *__errno_location() == EACCES {
+        return 2;
+    }
+    *__errno_location()
+}
+
+unsafe fn check_shim() -> i32 {
+    *c2rust_errno::errno_location() = EINTR;
+    if *c2rust_errno::errno_location() != EINTR {
+        return -1;
+    }
+    let saved = *c2rust_errno::errno_location();
+    *c2rust_errno::errno_location() = saved + 1;
+    *c2rust_errno::errno_location()
+}
+
+fn main() {
+    unsafe {
+        println!("{} {}", check_libc(), check_shim());
+    }
+}
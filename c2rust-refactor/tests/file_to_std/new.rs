@@ -0,0 +1,108 @@
+pub enum FILE {}
+
+extern "C" {
+    fn fopen(_: *const i8, _: *const i8) -> *mut FILE;
+    fn fclose(_: *mut FILE) -> i32;
+    fn fread(_: *mut ::std::ffi::c_void, _: u64, _: u64, _: *mut FILE) -> u64;
+    fn fwrite(_: *const ::std::ffi::c_void, _: u64, _: u64, _: *mut FILE) -> u64;
+    fn fgets(_: *mut i8, _: i32, _: *mut FILE) -> *mut i8;
+    fn fseek(_: *mut FILE, _: i64, _: i32) -> i32;
+    fn fprintf(_: *mut FILE, _: *const i8, ...) -> i32;
+}
+
+unsafe fn write_data(n: i32) {
+    let mut out: Option<::std::io::BufWriter<::std::fs::File>> =
+        ::std::fs::File::create("data.txt")
+            .ok()
+            .map(::std::io::BufWriter::new);
+    if out.is_none() {
+        return;
+    }
+    let _ = ::std::io::Write::write_fmt(
+        out.as_mut().unwrap(),
+        format_args!("n = {:}\n", n as libc::c_int),
+    );
+    let words: [u32; 2] = [1, 2];
+    {
+        let (file, ptr, size, count) = (
+            out.as_mut().unwrap(),
+            words.as_ptr() as *const ::std::ffi::c_void as *const u8,
+            ::std::mem::size_of::<u32>() as u64 as usize,
+            2 as usize,
+        );
+        let buf = ::std::slice::from_raw_parts(ptr, size * count);
+        if ::std::io::Write::write_all(file, buf).is_ok() {
+            count as u64
+        } else {
+            0
+        }
+    };
+    out = None;
+}
+
+unsafe fn read_data(path: *const i8) -> u64 {
+    let mut inp: Option<::std::io::BufReader<::std::fs::File>> = ::std::fs::File::open(
+        <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(
+            ::std::ffi::CStr::from_ptr(path).to_bytes(),
+        ),
+    )
+    .ok()
+    .map(::std::io::BufReader::new);
+    if inp.is_none() {
+        return 0;
+    }
+    let mut line: [i8; 32] = [0; 32];
+    {
+        let (buf, file, limit) = (
+            line.as_mut_ptr(),
+            inp.as_mut().unwrap(),
+            (32 as i64 - 1).max(0) as u64,
+        );
+        let mut line = Vec::new();
+        match ::std::io::BufRead::read_until(
+            &mut ::std::io::Read::take(file, limit),
+            b'\n',
+            &mut line,
+        ) {
+            Ok(0) | Err(_) => ::std::ptr::null_mut(),
+            Ok(_) => {
+                line.push(0);
+                ::std::ptr::copy_nonoverlapping(line.as_ptr() as *const _, buf, line.len());
+                buf
+            }
+        }
+    };
+    let mut words: [u32; 2] = [0; 2];
+    let n = {
+        let (file, ptr, size, count) = (
+            inp.as_mut().unwrap(),
+            words.as_mut_ptr() as *mut ::std::ffi::c_void as *mut u8,
+            4 as usize,
+            2 as usize,
+        );
+        let buf = ::std::slice::from_raw_parts_mut(ptr, size * count);
+        let mut len = 0;
+        while len < buf.len() {
+            match ::std::io::Read::read(&mut *file, &mut buf[len..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => len += n,
+            }
+        }
+        if size == 0 {
+            0
+        } else {
+            (len / size) as u64
+        }
+    };
+    ::std::io::Seek::seek(inp.as_mut().unwrap(), ::std::io::SeekFrom::Start(0 as u64))
+        .map_or(-1, |_| 0);
+    inp = None;
+    n
+}
+
+fn main() {
+    unsafe {
+        write_data(7);
+        println!("{}", read_data(b"data.txt\0" as *const u8 as *const i8));
+    }
+}
@@ -0,0 +1,45 @@
+pub enum FILE {}
+
+extern "C" {
+    fn fopen(_: *const i8, _: *const i8) -> *mut FILE;
+    fn fclose(_: *mut FILE) -> i32;
+    fn fread(_: *mut ::std::ffi::c_void, _: u64, _: u64, _: *mut FILE) -> u64;
+    fn fwrite(_: *const ::std::ffi::c_void, _: u64, _: u64, _: *mut FILE) -> u64;
+    fn fgets(_: *mut i8, _: i32, _: *mut FILE) -> *mut i8;
+    fn fseek(_: *mut FILE, _: i64, _: i32) -> i32;
+    fn fprintf(_: *mut FILE, _: *const i8, ...) -> i32;
+}
+
+unsafe fn write_data(n: i32) {
+    let mut out: *mut FILE = fopen(b"data.txt\0" as *const u8 as *const i8,
+                                   b"w\0" as *const u8 as *const i8);
+    if out.is_null() {
+        return;
+    }
+    fprintf(out, b"n = %d\n\0" as *const u8 as *const i8, n);
+    let words: [u32; 2] = [1, 2];
+    fwrite(words.as_ptr() as *const ::std::ffi::c_void, ::std::mem::size_of::<u32>() as u64, 2,
+           out);
+    fclose(out);
+}
+
+unsafe fn read_data(path: *const i8) -> u64 {
+    let mut inp: *mut FILE = fopen(path, b"rb\0" as *const u8 as *const i8);
+    if inp.is_null() {
+        return 0;
+    }
+    let mut line: [i8; 32] = [0; 32];
+    fgets(line.as_mut_ptr(), 32, inp);
+    let mut words: [u32; 2] = [0; 2];
+    let n = fread(words.as_mut_ptr() as *mut ::std::ffi::c_void, 4, 2, inp);
+    fseek(inp, 0, 0);
+    fclose(inp);
+    n
+}
+
+fn main() {
+    unsafe {
+        write_data(7);
+        println!("{}", read_data(b"data.txt\0" as *const u8 as *const i8));
+    }
+}
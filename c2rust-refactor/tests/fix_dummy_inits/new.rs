@@ -0,0 +1,31 @@
+#[derive(Copy, Clone)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn sum(n: i32) -> i32 {
+    let mut total: i32;
+    let mut i: i32 = 0;
+    total = n;
+    while i < n {
+        total += i;
+        i += 1;
+    }
+    total
+}
+
+unsafe fn origin() -> Point {
+    let mut p = ::std::mem::MaybeUninit::<Point>::uninit();
+    (*p.as_mut_ptr()).x = 0;
+    (*p.as_mut_ptr()).y = 0;
+    let mut p = p.assume_init();
+    p
+}
+
+fn main() {
+    sum(3);
+    unsafe {
+        origin();
+    }
+}
@@ -0,0 +1,30 @@
+#[derive(Copy, Clone)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn sum(n: i32) -> i32 {
+    let mut total: i32 = 0;
+    let mut i: i32 = 0;
+    total = n;
+    while i < n {
+        total += i;
+        i += 1;
+    }
+    total
+}
+
+unsafe fn origin() -> Point {
+    let mut p: Point = ::std::mem::zeroed();
+    p.x = 0;
+    p.y = 0;
+    p
+}
+
+fn main() {
+    sum(3);
+    unsafe {
+        origin();
+    }
+}
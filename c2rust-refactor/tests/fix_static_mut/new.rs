@@ -0,0 +1,79 @@
+struct Log {
+    len: usize,
+}
+
+struct Walk {
+    depth: i32,
+}
+
+struct Config {
+    verbose: i32,
+}
+
+struct State {
+    len: usize,
+    total: i32,
+}
+
+static COUNT: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+static CONFIG: ::std::sync::OnceLock<Config> = ::std::sync::OnceLock::new();
+static STATE: ::std::sync::Mutex<State> = ::std::sync::Mutex::new(State { len: 0, total: 0 });
+static mut LOG: Log = Log { len: 0 };
+static mut WALK: Walk = Walk { depth: 0 };
+
+unsafe fn init(verbose: i32) {
+    CONFIG
+        .set(Config { verbose: verbose })
+        .unwrap_or_else(|_| panic!("`CONFIG` initialized twice"));
+}
+
+unsafe fn push(x: i32) {
+    COUNT.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    (*STATE.lock().unwrap()).len += 1;
+    {
+        let mut state_guard = STATE.lock().unwrap();
+        (*state_guard).total = (*state_guard).total + x;
+    }
+    if (*CONFIG.get().expect("`CONFIG` used before initialization")).verbose != 0 {
+        println!("{}", COUNT.load(::std::sync::atomic::Ordering::SeqCst));
+    }
+}
+
+unsafe fn average() -> i32 {
+    let avg = {
+        let mut state_guard = STATE.lock().unwrap();
+        (*state_guard).total / (*state_guard).len as i32
+    };
+    avg
+}
+
+unsafe fn log_len() -> usize {
+    LOG.len
+}
+
+// `log_len` locks `LOG` while this statement holds its lock, so `LOG` is left unchanged.
+unsafe fn log(n: usize) {
+    LOG.len = n + log_len();
+}
+
+// The recursive call is made while the `match` holds the lock on `WALK`.
+unsafe fn walk() {
+    match WALK.depth {
+        0 => {}
+        _ => {
+            WALK.depth -= 1;
+            walk();
+        }
+    }
+}
+
+fn main() {
+    unsafe {
+        init(1);
+        push(3);
+        push(5);
+        println!("{}", average());
+        log(2);
+        walk();
+    }
+}
@@ -0,0 +1,71 @@
+struct Log {
+    len: usize,
+}
+
+struct Walk {
+    depth: i32,
+}
+
+struct Config {
+    verbose: i32,
+}
+
+struct State {
+    len: usize,
+    total: i32,
+}
+
+static mut COUNT: i32 = 0;
+static mut CONFIG: Config = Config { verbose: 0 };
+static mut STATE: State = State { len: 0, total: 0 };
+static mut LOG: Log = Log { len: 0 };
+static mut WALK: Walk = Walk { depth: 0 };
+
+unsafe fn init(verbose: i32) {
+    CONFIG = Config { verbose: verbose };
+}
+
+unsafe fn push(x: i32) {
+    COUNT += 1;
+    STATE.len += 1;
+    STATE.total = STATE.total + x;
+    if CONFIG.verbose != 0 {
+        println!("{}", COUNT);
+    }
+}
+
+unsafe fn average() -> i32 {
+    let avg = STATE.total / STATE.len as i32;
+    avg
+}
+
+unsafe fn log_len() -> usize {
+    LOG.len
+}
+
+// `log_len` locks `LOG` while this statement holds its lock, so `LOG` is left unchanged.
+unsafe fn log(n: usize) {
+    LOG.len = n + log_len();
+}
+
+// The recursive call is made while the `match` holds the lock on `WALK`.
+unsafe fn walk() {
+    match WALK.depth {
+        0 => {}
+        _ => {
+            WALK.depth -= 1;
+            walk();
+        }
+    }
+}
+
+fn main() {
+    unsafe {
+        init(1);
+        push(3);
+        push(5);
+        println!("{}", average());
+        log(2);
+        walk();
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(static && (name("COUNT") || name("CONFIG") || name("STATE") ||
+                                   name("LOG") || name("WALK")));' \; \
+    fix_static_mut \
+    -- old.rs $rustflags
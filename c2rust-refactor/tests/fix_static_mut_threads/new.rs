@@ -0,0 +1,48 @@
+#![allow(non_camel_case_types)]
+
+pub type pthread_t = u64;
+
+extern "C" {
+    fn pthread_create(
+        thread: *mut pthread_t,
+        attr: *const u8,
+        start: Option<unsafe extern "C" fn(*mut u8) -> *mut u8>,
+        arg: *mut u8,
+    ) -> i32;
+    fn pthread_join(thread: pthread_t, retval: *mut *mut u8) -> i32;
+}
+
+static SCALE: ::std::sync::Mutex<i32> = ::std::sync::Mutex::new(1);
+static HITS: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+
+unsafe extern "C" fn worker(_arg: *mut u8) -> *mut u8 {
+    {
+        let mut scale_guard = SCALE.lock().unwrap();
+        (*scale_guard) = (*scale_guard) * 2;
+    }
+    HITS.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    0 as *mut u8
+}
+
+unsafe fn run() -> i32 {
+    let mut t: pthread_t = 0;
+    pthread_create(
+        &mut t,
+        0 as *const u8,
+        Some(worker as unsafe extern "C" fn(*mut u8) -> *mut u8),
+        0 as *mut u8,
+    );
+    {
+        let mut scale_guard = SCALE.lock().unwrap();
+        (*scale_guard) = (*scale_guard) * 3;
+    }
+    HITS.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+    pthread_join(t, 0 as *mut *mut u8);
+    (*SCALE.lock().unwrap()) + HITS.load(::std::sync::atomic::Ordering::SeqCst)
+}
+
+fn main() {
+    unsafe {
+        println!("{}", run());
+    }
+}
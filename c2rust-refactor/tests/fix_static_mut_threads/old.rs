@@ -0,0 +1,42 @@
+#![allow(non_camel_case_types)]
+
+pub type pthread_t = u64;
+
+extern "C" {
+    fn pthread_create(
+        thread: *mut pthread_t,
+        attr: *const u8,
+        start: Option<unsafe extern "C" fn(*mut u8) -> *mut u8>,
+        arg: *mut u8,
+    ) -> i32;
+    fn pthread_join(thread: pthread_t, retval: *mut *mut u8) -> i32;
+}
+
+static mut SCALE: i32 = 1;
+static mut HITS: i32 = 0;
+
+unsafe extern "C" fn worker(_arg: *mut u8) -> *mut u8 {
+    SCALE = SCALE * 2;
+    HITS += 1;
+    0 as *mut u8
+}
+
+unsafe fn run() -> i32 {
+    let mut t: pthread_t = 0;
+    pthread_create(
+        &mut t,
+        0 as *const u8,
+        Some(worker as unsafe extern "C" fn(*mut u8) -> *mut u8),
+        0 as *mut u8,
+    );
+    SCALE = SCALE * 3;
+    HITS += 1;
+    pthread_join(t, 0 as *mut *mut u8);
+    SCALE + HITS
+}
+
+fn main() {
+    unsafe {
+        println!("{}", run());
+    }
+}
@@ -0,0 +1,51 @@
+trait Ops {
+    unsafe fn open(&self, arg0: i32) -> i32;
+    unsafe fn close(&self);
+}
+
+unsafe fn file_open(mode: i32) -> i32 {
+    mode + 1
+}
+
+unsafe fn file_close() {
+    println!("closed");
+}
+
+unsafe fn null_open(_mode: i32) -> i32 {
+    0
+}
+
+struct FileOps;
+impl Ops for FileOps {
+    unsafe fn open(&self, arg0: i32) -> i32 {
+        file_open(arg0)
+    }
+    unsafe fn close(&self) {
+        file_close()
+    }
+}
+static FILE_OPS: FileOps = FileOps;
+
+struct NullOps;
+impl Ops for NullOps {
+    unsafe fn open(&self, arg0: i32) -> i32 {
+        null_open(arg0)
+    }
+    unsafe fn close(&self) {
+        panic!("`close` is not implemented by `NULL_OPS`")
+    }
+}
+static NULL_OPS: NullOps = NullOps;
+
+unsafe fn run(ops: *const dyn Ops) -> i32 {
+    let fd = (*ops).open(1);
+    (*ops).close();
+    fd
+}
+
+fn main() {
+    unsafe {
+        println!("{}", run(&FILE_OPS));
+        println!("{}", NULL_OPS.open(2));
+    }
+}
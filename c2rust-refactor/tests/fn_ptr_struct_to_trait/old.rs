@@ -0,0 +1,39 @@
+struct Ops {
+    open: Option<unsafe fn(i32) -> i32>,
+    close: Option<unsafe fn()>,
+}
+
+unsafe fn file_open(mode: i32) -> i32 {
+    mode + 1
+}
+
+unsafe fn file_close() {
+    println!("closed");
+}
+
+unsafe fn null_open(_mode: i32) -> i32 {
+    0
+}
+
+static FILE_OPS: Ops = Ops {
+    open: Some(file_open),
+    close: Some(file_close),
+};
+
+static NULL_OPS: Ops = Ops {
+    open: Some(null_open),
+    close: None,
+};
+
+unsafe fn run(ops: *const Ops) -> i32 {
+    let fd = (*ops).open.unwrap()(1);
+    (*ops).close.expect("non-null function pointer")();
+    fd
+}
+
+fn main() {
+    unsafe {
+        println!("{}", run(&FILE_OPS));
+        println!("{}", (NULL_OPS.open.unwrap())(2));
+    }
+}
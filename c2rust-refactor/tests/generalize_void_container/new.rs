@@ -0,0 +1,30 @@
+#![allow(non_camel_case_types)]
+use std::ffi::c_void;
+
+extern "C" {
+    fn malloc(_: u64) -> *mut c_void;
+}
+
+pub struct list<T> {
+    pub data: *mut T,
+    pub next: *mut list<T>,
+}
+
+unsafe fn list_push<T>(l: *mut list<T>, data: *mut T) -> *mut list<T> {
+    let node: *mut list<T> = malloc(::std::mem::size_of::<list<T>>() as u64) as *mut list<T>;
+    (*node).data = data;
+    (*node).next = l;
+    node
+}
+
+unsafe fn list_head<T>(l: *mut list<T>) -> *mut T {
+    (*l).data
+}
+
+unsafe fn sum_heads(mut x: i32) -> i32 {
+    let mut l: *mut list<i32> = 0 as *mut list<i32>;
+    l = list_push(l, &mut x as *mut i32 as *mut i32);
+    *(list_head(l) as *mut i32)
+}
+
+fn main() {}
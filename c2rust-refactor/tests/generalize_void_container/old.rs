@@ -0,0 +1,30 @@
+#![allow(non_camel_case_types)]
+use std::ffi::c_void;
+
+extern "C" {
+    fn malloc(_: u64) -> *mut c_void;
+}
+
+pub struct list {
+    pub data: *mut c_void,
+    pub next: *mut list,
+}
+
+unsafe fn list_push(l: *mut list, data: *mut c_void) -> *mut list {
+    let node: *mut list = malloc(::std::mem::size_of::<list>() as u64) as *mut list;
+    (*node).data = data;
+    (*node).next = l;
+    node
+}
+
+unsafe fn list_head(l: *mut list) -> *mut c_void {
+    (*l).data
+}
+
+unsafe fn sum_heads(mut x: i32) -> i32 {
+    let mut l: *mut list = 0 as *mut list;
+    l = list_push(l, &mut x as *mut i32 as *mut c_void);
+    *(list_head(l) as *mut i32)
+}
+
+fn main() {}
@@ -0,0 +1,137 @@
+#[repr(C)]
+pub struct option {
+    pub name: *const i8,
+    pub has_arg: i32,
+    pub flag: *mut i32,
+    pub val: i32,
+}
+
+extern "C" {
+    static mut optarg: *mut i8;
+    fn getopt(_: i32, _: *const *mut i8, _: *const i8) -> i32;
+    fn getopt_long(_: i32, _: *const *mut i8, _: *const i8, _: *const option, _: *mut i32) -> i32;
+    fn atoi(_: *const i8) -> i32;
+}
+
+static mut long_options: [option; 3] = [
+    option {
+        name: b"count\0" as *const u8 as *const i8,
+        has_arg: 1 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 'n' as i32,
+    },
+    option {
+        name: b"quiet\0" as *const u8 as *const i8,
+        has_arg: 0 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 256 as i32,
+    },
+    option {
+        name: 0 as *const i8,
+        has_arg: 0 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 0 as i32,
+    },
+];
+
+unsafe fn parse_short(mut argc: i32, mut argv: *mut *mut i8) -> i32 {
+    let mut verbose: i32 = 0;
+    let mut c: i32 = 0;
+    let matches = ::clap::Command::new(env!("CARGO_PKG_NAME"))
+        .disable_help_flag(true)
+        .arg(
+            ::clap::Arg::new("opt0")
+                .short('v')
+                .multiple_occurrences(true),
+        )
+        .arg(::clap::Arg::new("args").multiple_values(true).hide(true))
+        .get_matches_from((0..argc as isize).map(|i| {
+            <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(
+                ::std::ffi::CStr::from_ptr(*argv.offset(i)).to_bytes(),
+            )
+            .to_os_string()
+        }));
+    let mut opts: Vec<(usize, _, Option<::std::ffi::CString>)> = Vec::new();
+    if let Some(indices) = matches.indices_of("opt0") {
+        opts.extend(indices.map(|i| (i, 118, None)));
+    }
+    opts.sort_by_key(|&(i, _, _)| i);
+    for &(_, opt, _) in &opts {
+        c = opt;
+        match c {
+            118 => verbose += 1,
+            _ => return -1,
+        }
+    }
+    return verbose;
+}
+
+unsafe fn parse_long(mut argc: i32, mut argv: *mut *mut i8) -> i32 {
+    let mut count: i32 = 1;
+    let mut quiet: i32 = 0;
+    let mut c: i32 = 0;
+    let matches = ::clap::Command::new(env!("CARGO_PKG_NAME"))
+        .disable_help_flag(true)
+        .arg(
+            ::clap::Arg::new("opt0")
+                .short('n')
+                .long("count")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            ::clap::Arg::new("opt1")
+                .short('q')
+                .multiple_occurrences(true),
+        )
+        .arg(
+            ::clap::Arg::new("opt2")
+                .long("quiet")
+                .multiple_occurrences(true),
+        )
+        .arg(::clap::Arg::new("args").multiple_values(true).hide(true))
+        .get_matches_from((0..argc as isize).map(|i| {
+            <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::from_bytes(
+                ::std::ffi::CStr::from_ptr(*argv.offset(i)).to_bytes(),
+            )
+            .to_os_string()
+        }));
+    let mut opts: Vec<(usize, _, Option<::std::ffi::CString>)> = Vec::new();
+    if let (Some(indices), Some(values)) =
+        (matches.indices_of("opt0"), matches.values_of_os("opt0"))
+    {
+        opts.extend(indices.zip(values).map(|(i, v)| {
+            (
+                i,
+                110,
+                Some(
+                    ::std::ffi::CString::new(
+                        <::std::ffi::OsStr as ::std::os::unix::ffi::OsStrExt>::as_bytes(v),
+                    )
+                    .unwrap(),
+                ),
+            )
+        }));
+    }
+    if let Some(indices) = matches.indices_of("opt1") {
+        opts.extend(indices.map(|i| (i, 113, None)));
+    }
+    if let Some(indices) = matches.indices_of("opt2") {
+        opts.extend(indices.map(|i| (i, 256, None)));
+    }
+    opts.sort_by_key(|&(i, _, _)| i);
+    for &(_, opt, ref arg) in &opts {
+        c = opt;
+        optarg = arg
+            .as_ref()
+            .map_or(::std::ptr::null_mut(), |a| a.as_ptr() as *mut _);
+        match c {
+            110 => count = atoi(optarg),
+            113 | 256 => quiet = 1,
+            _ => return -1,
+        }
+    }
+    return if quiet != 0 { 0 } else { count };
+}
+
+fn main() {}
@@ -0,0 +1,77 @@
+#[repr(C)]
+pub struct option {
+    pub name: *const i8,
+    pub has_arg: i32,
+    pub flag: *mut i32,
+    pub val: i32,
+}
+
+extern "C" {
+    static mut optarg: *mut i8;
+    fn getopt(_: i32, _: *const *mut i8, _: *const i8) -> i32;
+    fn getopt_long(_: i32, _: *const *mut i8, _: *const i8, _: *const option, _: *mut i32) -> i32;
+    fn atoi(_: *const i8) -> i32;
+}
+
+static mut long_options: [option; 3] = [
+    option {
+        name: b"count\0" as *const u8 as *const i8,
+        has_arg: 1 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 'n' as i32,
+    },
+    option {
+        name: b"quiet\0" as *const u8 as *const i8,
+        has_arg: 0 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 256 as i32,
+    },
+    option {
+        name: 0 as *const i8,
+        has_arg: 0 as i32,
+        flag: 0 as *const i32 as *mut i32,
+        val: 0 as i32,
+    },
+];
+
+unsafe fn parse_short(mut argc: i32, mut argv: *mut *mut i8) -> i32 {
+    let mut verbose: i32 = 0;
+    let mut c: i32 = 0;
+    loop {
+        c = getopt(argc, argv as *const *mut i8, b"v\0" as *const u8 as *const i8);
+        if !(c != -(1 as i32)) {
+            break;
+        }
+        match c {
+            118 => verbose += 1,
+            _ => return -1,
+        }
+    }
+    return verbose;
+}
+
+unsafe fn parse_long(mut argc: i32, mut argv: *mut *mut i8) -> i32 {
+    let mut count: i32 = 1;
+    let mut quiet: i32 = 0;
+    let mut c: i32 = 0;
+    loop {
+        c = getopt_long(
+            argc,
+            argv as *const *mut i8,
+            b"n:q\0" as *const u8 as *const i8,
+            long_options.as_mut_ptr(),
+            0 as *mut i32,
+        );
+        if !(c != -(1 as i32)) {
+            break;
+        }
+        match c {
+            110 => count = atoi(optarg),
+            113 | 256 => quiet = 1,
+            _ => return -1,
+        }
+    }
+    return if quiet != 0 { 0 } else { count };
+}
+
+fn main() {}
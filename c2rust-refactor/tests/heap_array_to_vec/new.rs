@@ -0,0 +1,55 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn calloc(_: u64, _: u64) -> *mut ::std::ffi::c_void;
+    fn realloc(_: *mut ::std::ffi::c_void, _: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+}
+
+struct Stack {
+    data: Vec<i32>,
+    len: usize,
+}
+
+unsafe fn sum(p: *const i32, n: usize) -> i32 {
+    let mut total = 0;
+    for i in 0..n {
+        total += *p.add(i);
+    }
+    total
+}
+
+unsafe fn buffers(n: i32) -> i32 {
+    let mut buf: Vec<i32> = {
+        let n = n as usize;
+        let mut v = Vec::with_capacity(n);
+        v.resize(n, ::std::mem::zeroed::<i32>());
+        v
+    };
+    if buf.is_empty() {
+        return -1;
+    }
+    buf[0 as usize] = 1;
+    buf[1 as usize] = 2;
+    buf.resize((2 * n as u64) as usize, ::std::mem::zeroed::<i32>());
+    let total = sum(buf.as_mut_ptr(), 2);
+    total
+}
+
+unsafe fn stack(n: u64) -> i32 {
+    let mut s = Stack {
+        data: Vec::new(),
+        len: 0,
+    };
+    s.data = {
+        let n = n as usize;
+        let mut v = Vec::with_capacity(n);
+        v.resize(n, ::std::mem::zeroed::<i32>());
+        v
+    };
+    s.data[0 as usize] = 7;
+    s.len = 1;
+    let top = s.data[0 as usize];
+    top
+}
+
+fn main() {}
@@ -0,0 +1,49 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn calloc(_: u64, _: u64) -> *mut ::std::ffi::c_void;
+    fn realloc(_: *mut ::std::ffi::c_void, _: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+}
+
+struct Stack {
+    data: *mut i32,
+    len: usize,
+}
+
+unsafe fn sum(p: *const i32, n: usize) -> i32 {
+    let mut total = 0;
+    for i in 0..n {
+        total += *p.add(i);
+    }
+    total
+}
+
+unsafe fn buffers(n: i32) -> i32 {
+    let mut buf: *mut i32 =
+        malloc((n as u64).wrapping_mul(::std::mem::size_of::<i32>() as u64)) as *mut i32;
+    if buf.is_null() {
+        return -1;
+    }
+    *buf = 1;
+    *buf.offset(1) = 2;
+    buf = realloc(buf as *mut ::std::ffi::c_void,
+                  (2 * n as u64).wrapping_mul(::std::mem::size_of::<i32>() as u64)) as *mut i32;
+    let total = sum(buf, 2);
+    free(buf as *mut ::std::ffi::c_void);
+    total
+}
+
+unsafe fn stack(n: u64) -> i32 {
+    let mut s = Stack {
+        data: 0 as *mut i32,
+        len: 0,
+    };
+    s.data = calloc(n, ::std::mem::size_of::<i32>() as u64) as *mut i32;
+    *s.data.offset(0) = 7;
+    s.len = 1;
+    let top = *s.data;
+    free(s.data as *mut ::std::ffi::c_void);
+    top
+}
+
+fn main() {}
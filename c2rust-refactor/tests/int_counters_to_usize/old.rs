@@ -0,0 +1,42 @@
+unsafe fn clear(buf: *mut u8, n: i32) {
+    if n <= 0 {
+        return;
+    }
+    let mut i: i32 = 0;
+    while i < n {
+        *buf.offset(i as isize) = 0;
+        i += 1;
+    }
+}
+
+fn sum(arr: &[i32; 16]) -> i32 {
+    let mut total: i32 = 0;
+    let mut i: i32 = 0;
+    while i < 16 {
+        total += arr[i as usize];
+        i = i + 1;
+    }
+    total
+}
+
+fn count_down(arr: &[i32; 16]) -> i32 {
+    let mut total: i32 = 0;
+    let mut i: i32 = 15;
+    while i >= 0 {
+        total += arr[i as usize];
+        i -= 1;
+    }
+    total
+}
+
+fn unguarded(arr: &[i32], n: i32) -> i32 {
+    let mut total: i32 = 0;
+    let mut i: i32 = 0;
+    while i < n {
+        total += arr[i as usize];
+        i += 1;
+    }
+    total
+}
+
+fn main() {}
@@ -0,0 +1,37 @@
+extern "C" {
+    fn report(_: i32);
+}
+
+struct Options {
+    enabled: bool,
+    level: i32,
+}
+
+fn is_big(x: i32) -> bool {
+    if x > 10 {
+        return true;
+    }
+    false
+}
+
+unsafe fn run(opts: &Options, verbose: bool) {
+    let mut done: bool = false;
+    let mut x = 0;
+    while !done {
+        x += opts.level;
+        done = is_big(x);
+    }
+    if verbose && opts.enabled {
+        report(done as i32);
+    }
+}
+
+fn main() {
+    let opts = Options {
+        enabled: true,
+        level: 3,
+    };
+    unsafe {
+        run(&opts, opts.level > 2);
+    }
+}
@@ -0,0 +1,37 @@
+extern "C" {
+    fn report(_: i32);
+}
+
+struct Options {
+    enabled: i32,
+    level: i32,
+}
+
+fn is_big(x: i32) -> i32 {
+    if x > 10 {
+        return 1;
+    }
+    0
+}
+
+unsafe fn run(opts: &Options, verbose: i32) {
+    let mut done: i32 = 0;
+    let mut x = 0;
+    while done == 0 {
+        x += opts.level;
+        done = is_big(x);
+    }
+    if verbose != 0 && opts.enabled == 1 {
+        report(done);
+    }
+}
+
+fn main() {
+    let opts = Options {
+        enabled: 1,
+        level: 3,
+    };
+    unsafe {
+        run(&opts, (opts.level > 2) as i32);
+    }
+}
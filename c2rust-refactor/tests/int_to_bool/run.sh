@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_pat(done) || (field && name("enabled")) || (fn && name("is_big")) || (arg && any_child(match_pat(verbose))));' \; \
+    int_to_bool \
+    -- old.rs $rustflags
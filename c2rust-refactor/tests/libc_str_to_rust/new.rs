@@ -0,0 +1,28 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn strcat(_: *mut i8, _: *const i8) -> *mut i8;
+}
+
+unsafe fn greet(suffix: &str) -> u64 {
+    let mut n: u64 = 0;
+    let mut name: String = String::new();
+    name.replace_range(.., "abc");
+    name.push_str(suffix);
+    if name == "abcdef" {
+        n = name.len() as u64;
+    }
+    name = String::new();
+    n
+}
+
+fn is_empty(s: &str) -> bool {
+    s == ""
+}
+
+fn main() {
+    println!("{} {}", unsafe { greet("def") }, is_empty(""));
+}
@@ -0,0 +1,28 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+    fn strlen(_: *const i8) -> u64;
+    fn strcmp(_: *const i8, _: *const i8) -> i32;
+    fn strcpy(_: *mut i8, _: *const i8) -> *mut i8;
+    fn strcat(_: *mut i8, _: *const i8) -> *mut i8;
+}
+
+unsafe fn greet(suffix: &str) -> u64 {
+    let mut n: u64 = 0;
+    let mut name: *mut i8 = malloc(16) as *mut i8;
+    strcpy(name, b"abc\0" as *const u8 as *const i8);
+    strcat(name, ::std::ffi::CString::new(suffix).unwrap().as_ptr());
+    if strcmp(name, b"abcdef\0" as *const u8 as *const i8) == 0 {
+        n = strlen(name);
+    }
+    free(name as *mut ::std::ffi::c_void);
+    n
+}
+
+fn is_empty(s: &str) -> bool {
+    s.as_bytes().cmp("".as_bytes()) as i32 == 0
+}
+
+fn main() {
+    println!("{} {}", unsafe { greet("def") }, is_empty(""));
+}
@@ -0,0 +1,49 @@
+fn sum(a: &[i32], n: usize) -> i32 {
+    let mut s = 0;
+    for elem in a[..n].iter() {
+        s += *elem;
+    }
+    s
+}
+
+fn scale(a: &mut [i32], n: i32, k: i32) {
+    let mut i: i32;
+    for elem in a[..n as usize].iter_mut() {
+        *elem *= k;
+    }
+}
+
+fn weighted(a: &[i32], n: usize) -> usize {
+    let mut s = 0;
+    for (i, elem) in a[..n].iter().enumerate() {
+        s += i * *elem as usize;
+    }
+    s
+}
+
+fn clear(buf: &mut [u8; 16]) {
+    buf[..16].fill(0);
+}
+
+fn copy(dst: &mut [u8], src: &[u8], n: usize) {
+    dst[..n].copy_from_slice(&src[..n]);
+}
+
+unsafe fn total(mut p: *const i32, end: *const i32) -> i32 {
+    let mut s = 0;
+    for elem in ::std::slice::from_raw_parts(p, end.offset_from(p).max(0) as usize) {
+        s += *elem;
+    }
+    s
+}
+
+fn main() {
+    let mut a = [1, 2, 3, 4];
+    scale(&mut a, 4, 2);
+    let mut buf = [1; 16];
+    clear(&mut buf);
+    let mut dst = [0u8; 4];
+    copy(&mut dst, &[1, 2, 3, 4], 4);
+    let t = unsafe { total(a.as_ptr(), a.as_ptr().offset(4)) };
+    println!("{} {} {} {}", sum(&a, 4), weighted(&a, 4), buf[0], t);
+}
@@ -0,0 +1,56 @@
+fn sum(a: &[i32], n: usize) -> i32 {
+    let mut s = 0;
+    for i in 0..n {
+        s += a[i];
+    }
+    s
+}
+
+fn scale(a: &mut [i32], n: i32, k: i32) {
+    let mut i: i32;
+    i = 0;
+    while i < n {
+        a[i as usize] *= k;
+        i += 1;
+    }
+}
+
+fn weighted(a: &[i32], n: usize) -> usize {
+    let mut s = 0;
+    for i in 0..n {
+        s += i * a[i] as usize;
+    }
+    s
+}
+
+fn clear(buf: &mut [u8; 16]) {
+    for i in 0..16 {
+        buf[i] = 0;
+    }
+}
+
+fn copy(dst: &mut [u8], src: &[u8], n: usize) {
+    for i in 0..n {
+        dst[i] = src[i];
+    }
+}
+
+unsafe fn total(mut p: *const i32, end: *const i32) -> i32 {
+    let mut s = 0;
+    while p < end {
+        s += *p;
+        p = p.offset(1);
+    }
+    s
+}
+
+fn main() {
+    let mut a = [1, 2, 3, 4];
+    scale(&mut a, 4, 2);
+    let mut buf = [1; 16];
+    clear(&mut buf);
+    let mut dst = [0u8; 4];
+    copy(&mut dst, &[1, 2, 3, 4], 4);
+    let t = unsafe { total(a.as_ptr(), a.as_ptr().offset(4)) };
+    println!("{} {} {} {}", sum(&a, 4), weighted(&a, 4), buf[0], t);
+}
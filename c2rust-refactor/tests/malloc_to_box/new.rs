@@ -0,0 +1,63 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn calloc(_: u64, _: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+}
+
+#[derive(Copy, Clone)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct Node {
+    value: i32,
+    next: Option<Box<Node>>,
+}
+
+unsafe fn use_point(p: *mut Point) -> i32 {
+    (*p).x + (*p).y
+}
+
+unsafe fn points() -> i32 {
+    let mut p: Box<Point> = Box::new(::std::mem::zeroed::<Point>());
+    (*p).x = 1;
+    (*p).y = 2;
+    let sum = use_point(&mut *p as *mut Point);
+    ::std::mem::drop(p);
+
+    let mut q: Option<Box<Point>> = None;
+    if q.is_none() {
+        q = Some(Box::new(::std::mem::zeroed::<Point>()));
+    }
+    (**q.as_mut().unwrap()).x = sum;
+    let x = (**q.as_ref().unwrap()).x;
+    q = None;
+    x
+}
+
+unsafe fn nodes() {
+    let mut n = Node {
+        value: 1,
+        next: None,
+    };
+    n.next = Some(Box::new(::std::mem::zeroed::<Node>()));
+    (**n.next.as_mut().unwrap()).value = 2;
+    n.next = None;
+}
+
+unsafe fn arrays_and_aliases(other: *mut Point) {
+    let mut a: *mut Point = malloc(4 * ::std::mem::size_of::<Point>() as u64) as *mut Point;
+    (*a).x = 1;
+    free(a as *mut ::std::ffi::c_void);
+
+    let mut b: *mut Point = other;
+    (*b).y = 2;
+
+    let mut c: Box<Point> = Box::new(::std::mem::zeroed::<Point>());
+    let mut d: Box<Point> = c;
+    (*d).x = 3;
+    ::std::mem::drop(d);
+}
+
+fn main() {}
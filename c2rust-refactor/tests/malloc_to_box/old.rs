@@ -0,0 +1,60 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn calloc(_: u64, _: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+}
+
+#[derive(Copy, Clone)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+struct Node {
+    value: i32,
+    next: *mut Node,
+}
+
+unsafe fn use_point(p: *mut Point) -> i32 {
+    (*p).x + (*p).y
+}
+
+unsafe fn points() -> i32 {
+    let mut p: *mut Point = malloc(::std::mem::size_of::<Point>() as u64) as *mut Point;
+    (*p).x = 1;
+    (*p).y = 2;
+    let sum = use_point(p);
+    free(p as *mut ::std::ffi::c_void);
+
+    let mut q: *mut Point = 0 as *mut Point;
+    if q.is_null() {
+        q = calloc(1, ::std::mem::size_of::<Point>() as u64) as *mut Point;
+    }
+    (*q).x = sum;
+    let x = (*q).x;
+    free(q as *mut ::std::ffi::c_void);
+    x
+}
+
+unsafe fn nodes() {
+    let mut n = Node { value: 1, next: 0 as *mut Node };
+    n.next = malloc(::std::mem::size_of::<Node>() as u64) as *mut Node;
+    (*n.next).value = 2;
+    free(n.next as *mut ::std::ffi::c_void);
+}
+
+unsafe fn arrays_and_aliases(other: *mut Point) {
+    let mut a: *mut Point = malloc(4 * ::std::mem::size_of::<Point>() as u64) as *mut Point;
+    (*a).x = 1;
+    free(a as *mut ::std::ffi::c_void);
+
+    let mut b: *mut Point = other;
+    (*b).y = 2;
+
+    let mut c: *mut Point = malloc(::std::mem::size_of::<Point>() as u64) as *mut Point;
+    let mut d: *mut Point = c;
+    (*d).x = 3;
+    free(d as *mut ::std::ffi::c_void);
+}
+
+fn main() {}
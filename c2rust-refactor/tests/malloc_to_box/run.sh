@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(match_pat(p) || match_pat(q) ||
+        match_pat(a) || match_pat(b) || match_pat(c) || match_pat(d) || (field && name("next")));' \; \
+    malloc_to_box \
+    -- old.rs $rustflags
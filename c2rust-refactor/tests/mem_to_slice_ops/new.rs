@@ -0,0 +1,32 @@
+extern "C" {
+    fn memcpy(_: *mut ::std::ffi::c_void, _: *const ::std::ffi::c_void, _: u64)
+              -> *mut ::std::ffi::c_void;
+    fn memmove(_: *mut ::std::ffi::c_void, _: *const ::std::ffi::c_void, _: u64)
+               -> *mut ::std::ffi::c_void;
+    fn memset(_: *mut ::std::ffi::c_void, _: i32, _: u64) -> *mut ::std::ffi::c_void;
+}
+
+unsafe fn copy(dst: &mut [i32], src: &[i32], n: i32) {
+    dst[..n as usize].copy_from_slice(&src[..n as usize]);
+}
+
+unsafe fn shift(buf: &mut [i32; 8]) {
+    buf.copy_within(..7u64 as usize, 1);
+}
+
+unsafe fn clear(bytes: &mut [u8; 16], words: &mut [u32; 4]) {
+    bytes[..].fill(0xff as u8);
+    words[..].fill(0);
+}
+
+fn main() {
+    let mut a = [0; 8];
+    let mut bytes = [0; 16];
+    let mut words = [1; 4];
+    unsafe {
+        copy(&mut a, &[1, 2, 3], 3);
+        shift(&mut a);
+        clear(&mut bytes, &mut words);
+    }
+    println!("{:?} {:?} {:?}", a, bytes, words);
+}
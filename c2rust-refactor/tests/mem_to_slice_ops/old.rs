@@ -0,0 +1,38 @@
+extern "C" {
+    fn memcpy(_: *mut ::std::ffi::c_void, _: *const ::std::ffi::c_void, _: u64)
+              -> *mut ::std::ffi::c_void;
+    fn memmove(_: *mut ::std::ffi::c_void, _: *const ::std::ffi::c_void, _: u64)
+               -> *mut ::std::ffi::c_void;
+    fn memset(_: *mut ::std::ffi::c_void, _: i32, _: u64) -> *mut ::std::ffi::c_void;
+}
+
+unsafe fn copy(dst: &mut [i32], src: &[i32], n: i32) {
+    memcpy(dst.as_mut_ptr() as *mut ::std::ffi::c_void,
+           src.as_ptr() as *const ::std::ffi::c_void,
+           (n as u64).wrapping_mul(::std::mem::size_of::<i32>() as u64));
+}
+
+unsafe fn shift(buf: &mut [i32; 8]) {
+    memmove(&mut buf[1] as *mut i32 as *mut ::std::ffi::c_void,
+            buf.as_mut_ptr() as *const ::std::ffi::c_void,
+            (7u64).wrapping_mul(::std::mem::size_of::<i32>() as u64));
+}
+
+unsafe fn clear(bytes: &mut [u8; 16], words: &mut [u32; 4]) {
+    memset(bytes as *mut [u8; 16] as *mut ::std::ffi::c_void, 0xff,
+           ::std::mem::size_of::<[u8; 16]>() as u64);
+    memset(words.as_mut_ptr() as *mut ::std::ffi::c_void, 0,
+           ::std::mem::size_of::<[u32; 4]>() as u64);
+}
+
+fn main() {
+    let mut a = [0; 8];
+    let mut bytes = [0; 16];
+    let mut words = [1; 4];
+    unsafe {
+        copy(&mut a, &[1, 2, 3], 3);
+        shift(&mut a);
+        clear(&mut bytes, &mut words);
+    }
+    println!("{:?} {:?} {:?}", a, bytes, words);
+}
@@ -0,0 +1,41 @@
+extern "C" {
+    fn mmap(
+        __addr: *mut ::std::ffi::c_void,
+        __len: u64,
+        __prot: i32,
+        __flags: i32,
+        __fd: i32,
+        __offset: i64,
+    ) -> *mut ::std::ffi::c_void;
+    fn munmap(__addr: *mut ::std::ffi::c_void, __len: u64) -> i32;
+}
+
+unsafe fn checksum(mut fd: i32, mut len: u64) -> u32 {
+    let mut data: Option<::memmap2::Mmap> =
+        ::memmap2::MmapOptions::new().len(len as usize).map(fd).ok();
+    if data.is_none() {
+        return 0 as i32 as u32;
+    }
+    let mut sum: u32 = 0 as i32 as u32;
+    let mut i: u64 = 0 as i32 as u64;
+    while i < len {
+        sum = sum.wrapping_add(data.as_ref().unwrap()[i as usize] as u32);
+        i = i.wrapping_add(1)
+    }
+    data = None;
+    return sum;
+}
+
+unsafe fn scratch(mut len: u64) -> i32 {
+    let mut buf: Vec<u8> = vec![0u8; len as usize];
+    if false {
+        return -(1 as i32);
+    }
+    buf[0] = 'x' as i32 as i8 as u8;
+    buf[1 as usize] = (buf[0] as i8) as u8;
+    let mut first: i32 = (buf[0] as i8) as i32;
+    buf = Vec::new();
+    return first;
+}
+
+fn main() {}
@@ -0,0 +1,54 @@
+extern "C" {
+    fn mmap(
+        __addr: *mut ::std::ffi::c_void,
+        __len: u64,
+        __prot: i32,
+        __flags: i32,
+        __fd: i32,
+        __offset: i64,
+    ) -> *mut ::std::ffi::c_void;
+    fn munmap(__addr: *mut ::std::ffi::c_void, __len: u64) -> i32;
+}
+
+unsafe fn checksum(mut fd: i32, mut len: u64) -> u32 {
+    let mut data: *mut u8 = mmap(
+        0 as *mut ::std::ffi::c_void,
+        len,
+        0x1 as i32,
+        0x2 as i32,
+        fd,
+        0 as i32 as i64,
+    ) as *mut u8;
+    if data as *mut ::std::ffi::c_void == -(1 as i32) as *mut ::std::ffi::c_void {
+        return 0 as i32 as u32;
+    }
+    let mut sum: u32 = 0 as i32 as u32;
+    let mut i: u64 = 0 as i32 as u64;
+    while i < len {
+        sum = sum.wrapping_add(*data.offset(i as isize) as u32);
+        i = i.wrapping_add(1)
+    }
+    munmap(data as *mut ::std::ffi::c_void, len);
+    return sum;
+}
+
+unsafe fn scratch(mut len: u64) -> i32 {
+    let mut buf: *mut i8 = mmap(
+        0 as *mut ::std::ffi::c_void,
+        len,
+        0x1 as i32 | 0x2 as i32,
+        0x2 as i32 | 0x20 as i32,
+        -(1 as i32),
+        0 as i32 as i64,
+    ) as *mut i8;
+    if buf as *mut ::std::ffi::c_void == -(1 as i32) as *mut ::std::ffi::c_void {
+        return -(1 as i32);
+    }
+    *buf = 'x' as i32 as i8;
+    *buf.offset(1 as i32 as isize) = *buf;
+    let mut first: i32 = *buf as i32;
+    munmap(buf as *mut ::std::ffi::c_void, len);
+    return first;
+}
+
+fn main() {}
@@ -0,0 +1,38 @@
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+
+extern "C" {
+    #[link_name = "mylib_getLimit"]
+    fn get_limit() -> i32;
+}
+
+pub const MAX_SIZE: usize = 16;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct BufferT {
+    pub len: usize,
+}
+
+/// Create a `BufferT` of at most `MAX_SIZE` bytes.
+#[export_name = "mylib_newBuffer"]
+pub unsafe extern "C" fn new_buffer(len: usize) -> BufferT {
+    let limit = get_limit() as usize;
+    BufferT {
+        len: if len > MAX_SIZE || len > limit {
+            MAX_SIZE
+        } else {
+            len
+        },
+    }
+}
+
+fn HTTPServerStart() {}
+
+fn http_server_start() {}
+
+fn main() {
+    HTTPServerStart();
+    http_server_start();
+}
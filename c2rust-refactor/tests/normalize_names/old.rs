@@ -0,0 +1,33 @@
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+
+extern "C" {
+    fn mylib_getLimit() -> i32;
+}
+
+pub const mylib_maxSize: usize = 16;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct mylib_buffer_t {
+    pub len: usize,
+}
+
+/// Create a `mylib_buffer_t` of at most `mylib_maxSize` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mylib_newBuffer(len: usize) -> mylib_buffer_t {
+    let limit = mylib_getLimit() as usize;
+    mylib_buffer_t {
+        len: if len > mylib_maxSize || len > limit { mylib_maxSize } else { len },
+    }
+}
+
+fn HTTPServerStart() {}
+
+fn http_server_start() {}
+
+fn main() {
+    HTTPServerStart();
+    http_server_start();
+}
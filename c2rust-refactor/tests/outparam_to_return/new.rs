@@ -0,0 +1,27 @@
+unsafe fn div(a: i32, b: i32) -> (i32, i32) {
+    let mut rem: i32 = ::std::mem::zeroed();
+    rem = a % b;
+    (a / b, rem)
+}
+
+unsafe fn get() -> i32 {
+    let mut out: i32 = ::std::mem::zeroed();
+    if (&mut out as *mut i32).is_null() {
+        return out;
+    }
+    out = 42;
+    out
+}
+
+fn main() {
+    unsafe {
+        let mut r = 0;
+        let q = {
+            let (ret_, out0_) = div(7, 2);
+            r = out0_;
+            ret_
+        };
+        r = get();
+        println!("{} {}", q, r);
+    }
+}
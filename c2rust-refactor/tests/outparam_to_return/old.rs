@@ -0,0 +1,20 @@
+unsafe fn div(a: i32, b: i32, rem: *mut i32) -> i32 {
+    *rem = a % b;
+    a / b
+}
+
+unsafe fn get(out: *mut i32) {
+    if out.is_null() {
+        return;
+    }
+    *out = 42;
+}
+
+fn main() {
+    unsafe {
+        let mut r = 0;
+        let q = div(7, 2, &mut r);
+        get(&mut r as *mut i32);
+        println!("{} {}", q, r);
+    }
+}
@@ -0,0 +1,50 @@
+unsafe fn sum(arr: &[i32; 8]) -> i32 {
+    let mut total = 0;
+    for i in 0..8 {
+        // SAFETY: `i` is at most 7, and `arr` has 8 elements
+        total += *arr.get_unchecked(i as usize);
+    }
+    total
+}
+
+unsafe fn sum_first(arr: &[i32; 8], n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < n && i < 8 {
+        // SAFETY: `i` is between 0 and 7, and `arr` has 8 elements
+        total += *arr.get_unchecked(i as usize);
+        i += 1;
+    }
+    total
+}
+
+unsafe fn clear(buf: &mut [u8]) {
+    for i in 0..buf.len() {
+        // SAFETY: `i` is less than `buf.len()`
+        *buf.get_unchecked_mut(i) = 0;
+    }
+}
+
+unsafe fn get(arr: &[i32; 4], i: usize) -> i32 {
+    // SAFETY: 3 is less than 4, the length of `arr`
+    arr[i] + *arr.get_unchecked(3)
+}
+
+unsafe fn copy_str(src: &[u8; 16], dst: &mut [u8; 16]) {
+    let mut p_idx: usize = 0;
+    let mut q_idx: usize = 0;
+    while src[p_idx] != 0 {
+        dst[q_idx] = src[p_idx];
+        p_idx += 1;
+        q_idx += 1;
+    }
+    dst[q_idx] = 0;
+}
+
+unsafe fn skip_back(src: &[u8; 16]) -> u8 {
+    let mut p = src.as_ptr().offset(8);
+    p = p.offset(-1);
+    *p
+}
+
+fn main() {}
@@ -0,0 +1,46 @@
+unsafe fn sum(arr: &[i32; 8]) -> i32 {
+    let mut total = 0;
+    for i in 0..8 {
+        total += *arr.as_ptr().offset(i as isize);
+    }
+    total
+}
+
+unsafe fn sum_first(arr: &[i32; 8], n: i32) -> i32 {
+    let mut total = 0;
+    let mut i = 0;
+    while i < n && i < 8 {
+        total += *arr.as_ptr().offset(i as isize);
+        i += 1;
+    }
+    total
+}
+
+unsafe fn clear(buf: &mut [u8]) {
+    for i in 0..buf.len() {
+        *buf.as_mut_ptr().add(i) = 0;
+    }
+}
+
+unsafe fn get(arr: &[i32; 4], i: usize) -> i32 {
+    *arr.as_ptr().add(i) + *arr.as_ptr().offset(3)
+}
+
+unsafe fn copy_str(src: &[u8; 16], dst: &mut [u8; 16]) {
+    let mut p = src.as_ptr();
+    let mut q = dst.as_mut_ptr();
+    while *p != 0 {
+        *q = *p;
+        p = p.offset(1);
+        q = q.add(1);
+    }
+    *q = 0;
+}
+
+unsafe fn skip_back(src: &[u8; 16]) -> u8 {
+    let mut p = src.as_ptr().offset(8);
+    p = p.offset(-1);
+    *p
+}
+
+fn main() {}
@@ -0,0 +1,31 @@
+extern "C" {
+    fn consume(_: *const i32);
+}
+
+unsafe fn sum(p: &[i32]) -> i32 {
+    let mut total = 0;
+    for i in 0..p.len() as usize {
+        total += p[i as usize];
+    }
+    consume(p.as_ptr());
+    total
+}
+
+unsafe fn fill(dst: &mut [u8], val: u8) {
+    let mut i = 0;
+    while i < dst.len() as u32 {
+        dst[i as usize] = val;
+        i += 1;
+    }
+    dst[0 as usize] = 0;
+}
+
+fn main() {
+    let mut arr = [1, 2, 3, 4];
+    let mut buf = [0u8; 8];
+    let raw = buf.as_mut_ptr();
+    unsafe {
+        sum(&arr[..4 as usize]);
+        fill(::std::slice::from_raw_parts_mut(raw, 8 as usize), 1);
+    }
+}
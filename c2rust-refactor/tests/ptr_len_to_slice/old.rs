@@ -0,0 +1,31 @@
+extern "C" {
+    fn consume(_: *const i32);
+}
+
+unsafe fn sum(p: *const i32, n: usize) -> i32 {
+    let mut total = 0;
+    for i in 0..n {
+        total += *p.offset(i as isize);
+    }
+    consume(p);
+    total
+}
+
+unsafe fn fill(dst: *mut u8, n: u32, val: u8) {
+    let mut i = 0;
+    while i < n {
+        *dst.add(i as usize) = val;
+        i += 1;
+    }
+    *dst = 0;
+}
+
+fn main() {
+    let mut arr = [1, 2, 3, 4];
+    let mut buf = [0u8; 8];
+    let raw = buf.as_mut_ptr();
+    unsafe {
+        sum(arr.as_ptr(), 4);
+        fill(raw, 8, 1);
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc(arg && any_child(match_pat(p) || match_pat(dst)));' \; \
+    select len 'crate; desc(arg && any_child(match_pat(n)));' \; \
+    ptr_len_to_slice \
+    -- old.rs $rustflags
@@ -0,0 +1,44 @@
+struct Buffer {
+    pos: i32,
+}
+
+struct Cursor<'a> {
+    buf: &'a mut Buffer,
+}
+
+struct Reader<'a> {
+    cur: Cursor<'a>,
+    len: i32,
+}
+
+impl<'a> Reader<'a> {
+    fn len(&self) -> i32 {
+        self.len
+    }
+}
+
+unsafe fn advance(c: &mut Cursor) {
+    (*(*c).buf).pos += 1;
+}
+
+unsafe fn peek(b: &Buffer) -> i32 {
+    if false {
+        return -1;
+    }
+    (*b).pos
+}
+
+unsafe fn make_cursor<'a>(buf: *mut Buffer) -> Cursor<'a> {
+    Cursor { buf: &mut *buf }
+}
+
+fn main() {
+    let mut b = Buffer { pos: 0 };
+    unsafe {
+        let mut c = make_cursor(&mut b);
+        advance(&mut c);
+        peek(&*(c.buf as *mut Buffer as *const Buffer));
+        let r = Reader { cur: c, len: 1 };
+        r.len();
+    }
+}
@@ -0,0 +1,44 @@
+struct Buffer {
+    pos: i32,
+}
+
+struct Cursor {
+    buf: *mut Buffer,
+}
+
+struct Reader {
+    cur: Cursor,
+    len: i32,
+}
+
+impl Reader {
+    fn len(&self) -> i32 {
+        self.len
+    }
+}
+
+unsafe fn advance(c: *mut Cursor) {
+    (*(*c).buf).pos += 1;
+}
+
+unsafe fn peek(b: *const Buffer) -> i32 {
+    if b.is_null() {
+        return -1;
+    }
+    (*b).pos
+}
+
+unsafe fn make_cursor(buf: *mut Buffer) -> Cursor {
+    Cursor { buf: buf }
+}
+
+fn main() {
+    let mut b = Buffer { pos: 0 };
+    unsafe {
+        let mut c = make_cursor(&mut b);
+        advance(&mut c as *mut Cursor);
+        peek(c.buf as *const Buffer);
+        let r = Reader { cur: c, len: 1 };
+        r.len();
+    }
+}
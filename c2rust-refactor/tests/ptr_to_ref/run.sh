@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc((field && name("buf")) || (arg && any_child(match_pat(c) || match_pat(b))));' \; \
+    ptr_to_ref \
+    -- old.rs $rustflags
@@ -0,0 +1,34 @@
+extern "C" {
+    fn qsort(_: *mut ::std::ffi::c_void, _: u64, _: u64,
+             _: Option<unsafe extern "C" fn(*const ::std::ffi::c_void,
+                                            *const ::std::ffi::c_void) -> i32>);
+    fn bsearch(_: *const ::std::ffi::c_void, _: *const ::std::ffi::c_void, _: u64, _: u64,
+               _: Option<unsafe extern "C" fn(*const ::std::ffi::c_void,
+                                              *const ::std::ffi::c_void) -> i32>)
+               -> *mut ::std::ffi::c_void;
+}
+
+unsafe extern "C" fn cmp_int(a: *const ::std::ffi::c_void, b: *const ::std::ffi::c_void) -> i32 {
+    *(a as *const i32) - *(b as *const i32)
+}
+
+unsafe fn sort(xs: &mut [i32; 6], n: i32) {
+    qsort(xs.as_mut_ptr() as *mut ::std::ffi::c_void, n as u64,
+          ::std::mem::size_of::<i32>() as u64,
+          Some(cmp_int as unsafe extern "C" fn(*const ::std::ffi::c_void,
+                                               *const ::std::ffi::c_void) -> i32));
+}
+
+unsafe fn find(xs: &[i32; 6], mut key: i32) -> *mut i32 {
+    bsearch(&mut key as *mut i32 as *const ::std::ffi::c_void,
+            xs.as_ptr() as *const ::std::ffi::c_void, 6,
+            ::std::mem::size_of::<i32>() as u64, Some(cmp_int)) as *mut i32
+}
+
+fn main() {
+    let mut xs = [5, 3, 9, 1, 7, 2];
+    unsafe {
+        sort(&mut xs, 6);
+        println!("{:?} {} {}", xs, *find(&xs, 7), find(&xs, 4).is_null());
+    }
+}
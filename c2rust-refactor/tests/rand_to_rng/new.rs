@@ -0,0 +1,22 @@
+static mut C2RUST_RNG: Option<::rand::rngs::StdRng> = None;
+extern "C" {
+    fn rand() -> i32;
+    fn srand(__seed: u32);
+}
+
+unsafe fn roll_dice(seed: i32, n: i32) -> i32 {
+    C2RUST_RNG = Some(::rand::SeedableRng::seed_from_u64(seed as u32 as u64));
+    let mut total: i32 = 0;
+    let mut i: i32 = 0;
+    while i < n {
+        total += ::rand::Rng::gen_range::<i32, _>(
+            C2RUST_RNG.get_or_insert_with(|| ::rand::SeedableRng::seed_from_u64(1)),
+            0..=2147483647,
+        ) % 6 as i32
+            + 1 as i32;
+        i += 1
+    }
+    return total;
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+extern "C" {
+    fn rand() -> i32;
+    fn srand(__seed: u32);
+}
+
+unsafe fn roll_dice(seed: i32, n: i32) -> i32 {
+    srand(seed as u32);
+    let mut total: i32 = 0;
+    let mut i: i32 = 0;
+    while i < n {
+        total += rand() % 6 as i32 + 1 as i32;
+        i += 1
+    }
+    return total;
+}
+
+fn main() {}
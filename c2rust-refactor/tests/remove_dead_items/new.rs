@@ -0,0 +1,33 @@
+#![allow(non_camel_case_types)]
+
+extern "C" {
+    fn puts(_: *const i8) -> i32;
+}
+
+pub type size_t = u64;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct counter {
+    pub count: size_t,
+}
+
+impl counter {
+    fn bump(&mut self) {
+        self.count += 1;
+    }
+}
+
+static mut GLOBAL: counter = counter { count: 0 };
+
+unsafe fn greet() {
+    puts(b"hi\0".as_ptr() as *const i8);
+    GLOBAL.bump();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn api_entry() {
+    greet();
+}
+
+fn main() {}
@@ -0,0 +1,49 @@
+#![allow(non_camel_case_types)]
+
+extern "C" {
+    fn puts(_: *const i8) -> i32;
+    fn abort() -> !;
+    fn getenv(_: *const i8) -> *mut i8;
+}
+
+pub type size_t = u64;
+pub type ssize_t = i64;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct counter {
+    pub count: size_t,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct unused_t {
+    pub x: ssize_t,
+}
+
+impl counter {
+    fn bump(&mut self) {
+        self.count += 1;
+    }
+}
+
+static mut GLOBAL: counter = counter { count: 0 };
+
+static mut UNUSED_GLOBAL: i32 = 0;
+
+unsafe fn greet() {
+    puts(b"hi\0".as_ptr() as *const i8);
+    GLOBAL.bump();
+}
+
+unsafe fn never_called() {
+    UNUSED_GLOBAL += 1;
+    abort();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn api_entry() {
+    greet();
+}
+
+fn main() {}
@@ -0,0 +1,38 @@
+extern "C" {
+    fn abs(_: i32) -> i32;
+}
+
+static mut COUNTER: i32 = 0;
+
+fn safe_block(x: i32) -> i32 {
+    { x + 1 }
+}
+
+fn partly_unsafe(p: *const i32) -> i32 {
+    let mut total = 0;
+    total += 1;
+    unsafe {
+        let y = *p;
+        total += y;
+    }
+    unsafe {
+        COUNTER += 1;
+    }
+    total *= 2;
+    total -= 1;
+    total
+}
+
+fn nested(x: i32) -> i32 {
+    {
+        let a = x * 2;
+        unsafe { abs(a) }
+    }
+}
+
+fn main() {
+    let x = 5;
+    safe_block(x);
+    partly_unsafe(&x);
+    nested(x);
+}
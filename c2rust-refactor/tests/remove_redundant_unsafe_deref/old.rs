@@ -0,0 +1,18 @@
+fn local_ptr() -> i32 {
+    let mut x = 1;
+    let p = &mut x as *mut i32;
+    unsafe {
+        *p += 1;
+    }
+    unsafe { *p * 2 }
+}
+
+fn passed_ptr(p: *mut i32) -> i32 {
+    unsafe { *p }
+}
+
+fn main() {
+    let mut y = 3;
+    local_ptr();
+    passed_ptr(&mut y as *mut i32);
+}
@@ -0,0 +1,46 @@
+#![feature(rustc_private)]
+#![register_tool(c2rust)]
+#![allow(non_camel_case_types)]
+#![allow(dead_code)]
+
+pub mod server {
+    #[c2rust::header_src = "/home/user/proj/include/util.h:3"]
+    pub mod util_h {
+        pub type size_t = u64;
+        #[derive(Copy, Clone)]
+        #[repr(C)]
+        pub struct point {
+            pub x: i32,
+            pub y: i32,
+        }
+    }
+
+    #[c2rust::header_src = "/home/user/proj/include/net/sock.h:4"]
+    pub mod sock_h {
+        use super::util_h::size_t;
+        pub type sock_id = i32;
+        pub const SOCK_BUF: size_t = 4096;
+    }
+
+    use self::sock_h::sock_id;
+    use self::util_h::point;
+
+    pub fn origin(_s: sock_id) -> point {
+        point { x: 0, y: 0 }
+    }
+}
+
+pub mod client {
+    #[c2rust::header_src = "/home/user/proj/include/util.h:1"]
+    pub mod util_h {
+        pub type size_t = u64;
+    }
+
+    use self::util_h::size_t;
+
+    pub fn zero() -> size_t {
+        0
+    }
+}
+
+fn main() {}
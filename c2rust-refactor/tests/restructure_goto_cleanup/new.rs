@@ -0,0 +1,51 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+    fn fill(_: *mut u8, _: *mut u8) -> i32;
+}
+
+unsafe fn process() -> i32 {
+    let mut ret: i32 = -1;
+    let mut a: *mut u8 = 0 as *mut u8;
+    let mut b: *mut u8 = 0 as *mut u8;
+    let cleanup_fail = |b: *mut u8, a: *mut u8| {
+        free(b as *mut ::std::ffi::c_void);
+        free(a as *mut ::std::ffi::c_void);
+    };
+    {
+        a = malloc(16) as *mut u8;
+        if a.is_null() {
+            cleanup_fail(b, a);
+            return ret;
+        }
+        b = malloc(16) as *mut u8;
+        if b.is_null() {
+            cleanup_fail(b, a);
+            return ret;
+        }
+        if fill(a, b) != 0 {
+            cleanup_fail(b, a);
+            return ret;
+        }
+        ret = 0;
+    }
+    free(b as *mut ::std::ffi::c_void);
+    free(a as *mut ::std::ffi::c_void);
+    return ret;
+}
+
+unsafe fn reset(p: *mut u8) {
+    let cleanup_done = |p: *mut u8| {
+        free(p as *mut ::std::ffi::c_void);
+    };
+    {
+        if p.is_null() {
+            cleanup_done(p);
+            return;
+        }
+        *p = 0;
+    }
+    free(p as *mut ::std::ffi::c_void);
+}
+
+fn main() {}
@@ -0,0 +1,40 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+    fn free(_: *mut ::std::ffi::c_void);
+    fn fill(_: *mut u8, _: *mut u8) -> i32;
+}
+
+unsafe fn process() -> i32 {
+    let mut ret: i32 = -1;
+    let mut a: *mut u8 = 0 as *mut u8;
+    let mut b: *mut u8 = 0 as *mut u8;
+    'fail: {
+        a = malloc(16) as *mut u8;
+        if a.is_null() {
+            break 'fail;
+        }
+        b = malloc(16) as *mut u8;
+        if b.is_null() {
+            break 'fail;
+        }
+        if fill(a, b) != 0 {
+            break 'fail;
+        }
+        ret = 0;
+    }
+    free(b as *mut ::std::ffi::c_void);
+    free(a as *mut ::std::ffi::c_void);
+    return ret;
+}
+
+unsafe fn reset(p: *mut u8) {
+    'done: {
+        if p.is_null() {
+            break 'done;
+        }
+        *p = 0;
+    }
+    free(p as *mut ::std::ffi::c_void);
+}
+
+fn main() {}
@@ -0,0 +1,56 @@
+const EINVAL: i32 = 22;
+const ENOMEM: i32 = 12;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ParseError {
+    Einval,
+    Enomem,
+}
+impl ParseError {
+    fn code(self) -> i32 {
+        match self {
+            ParseError::Einval => -EINVAL,
+            ParseError::Enomem => -ENOMEM,
+        }
+    }
+}
+unsafe fn parse(s: *const i8) -> Result<(), ParseError> {
+    if s.is_null() {
+        return Err(ParseError::Einval);
+    }
+    if *s == 0 {
+        return Err(ParseError::Enomem);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum LoadError {
+    Error1,
+}
+impl LoadError {
+    fn code(self) -> i32 {
+        match self {
+            LoadError::Error1 => -1,
+        }
+    }
+}
+unsafe fn load(s: *const i8) -> Result<(), LoadError> {
+    parse(s).map_err(|_| LoadError::Error1)?;
+    Ok(())
+}
+
+fn main() {
+    unsafe {
+        let s = b"x\0".as_ptr() as *const i8;
+        if load(s).is_err() {
+            return;
+        }
+        let rc = match parse(s) {
+            Ok(()) => 0,
+            Err(e) => e.code(),
+        };
+        parse(s);
+        println!("{}", rc);
+    }
+}
@@ -0,0 +1,31 @@
+const EINVAL: i32 = 22;
+const ENOMEM: i32 = 12;
+
+unsafe fn parse(s: *const i8) -> i32 {
+    if s.is_null() {
+        return -EINVAL;
+    }
+    if *s == 0 {
+        return -ENOMEM;
+    }
+    0
+}
+
+unsafe fn load(s: *const i8) -> i32 {
+    if parse(s) < 0 {
+        return -1;
+    }
+    0
+}
+
+fn main() {
+    unsafe {
+        let s = b"x\0".as_ptr() as *const i8;
+        if load(s) != 0 {
+            return;
+        }
+        let rc = parse(s);
+        parse(s);
+        println!("{}", rc);
+    }
+}
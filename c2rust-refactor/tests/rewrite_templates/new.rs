@@ -0,0 +1,9 @@
+fn main() {
+    let a = 1u8;
+    let b = 2u16;
+    let mut x = 0u32;
+    x += 2;
+    let y = u32::from(a) + b as u32;
+    let z = 0 + 0;
+    println!("{} {} {} {} {}", a, b, x, y, z);
+}
@@ -0,0 +1,10 @@
+fn main() {
+    let a = 1u8;
+    let b = 2u16;
+    let mut x = 0u32;
+    x = x + 1;
+    x = x + 1;
+    let y = a as u32 + b as u32 + 0;
+    let z = 0 + 0;
+    println!("{} {} {} {} {}", a, b, x, y, z);
+}
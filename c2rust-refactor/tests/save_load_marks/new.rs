@@ -0,0 +1,13 @@
+fn renamed_helper() -> i32 {
+    1
+}
+
+mod inner {
+    pub fn renamed_helper() -> i32 {
+        2
+    }
+}
+
+fn main() {
+    println!("{}", renamed_helper() + inner::renamed_helper());
+}
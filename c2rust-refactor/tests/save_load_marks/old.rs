@@ -0,0 +1,13 @@
+fn helper() -> i32 {
+    1
+}
+
+mod inner {
+    pub fn helper() -> i32 {
+        2
+    }
+}
+
+fn main() {
+    println!("{}", helper() + inner::helper());
+}
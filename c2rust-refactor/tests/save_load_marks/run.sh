@@ -0,0 +1,19 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+marks=${TMPDIR:-/tmp}/save_load_marks.$$.json
+
+$refactor \
+    select target 'crate; desc(fn && name("helper"));' \; \
+    save_marks $marks \; \
+    clear_marks \; \
+    load_marks $marks \; \
+    rename_items_regex 'helper' 'renamed_helper' target \
+    -- old.rs $rustflags
+status=$?
+rm -f $marks
+exit $status
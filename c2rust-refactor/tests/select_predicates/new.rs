@@ -0,0 +1,31 @@
+#![allow(non_camel_case_types, non_upper_case_globals)]
+
+type c_int = i32;
+type c_char = i8;
+
+extern "C" {
+    fn free(_: *mut ::std::ffi::c_void);
+}
+
+static mut sel_NAME: *mut c_char = 0 as *mut c_char;
+static mut COUNT: c_int = 0;
+
+unsafe fn sel_release(p: *mut c_char) -> c_int {
+    free(p as *mut ::std::ffi::c_void);
+    0
+}
+
+unsafe fn count() -> c_int {
+    COUNT
+}
+
+unsafe fn clear() {
+    free(sel_NAME as *mut ::std::ffi::c_void);
+}
+
+fn main() {
+    unsafe {
+        COUNT = sel_release(sel_NAME) + count();
+        clear();
+    }
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+
+# work around System Integrity Protection on macOS
+if [ `uname` = 'Darwin' ]; then
+    export LD_LIBRARY_PATH=$not_LD_LIBRARY_PATH
+fi
+
+$refactor \
+    select target 'crate; desc((fn && returns(c_int) && calls(free)) || (static && typed(*mut c_char) && in_file("old.c")));' \; \
+    rename_items_regex '^(.*)$' 'sel_$1' target \
+    -- old.rs $rustflags
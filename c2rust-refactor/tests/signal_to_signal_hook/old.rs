@@ -0,0 +1,47 @@
+pub type sig_atomic_t = i32;
+pub type __sighandler_t = Option<unsafe extern "C" fn(i32) -> ()>;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub union C2RustUnnamed {
+    pub sa_handler: __sighandler_t,
+    pub sa_sigaction: usize,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct sigaction {
+    pub __sigaction_handler: C2RustUnnamed,
+    pub sa_flags: i32,
+}
+
+extern "C" {
+    fn signal(__sig: i32, __handler: __sighandler_t) -> __sighandler_t;
+    fn sigaction(__sig: i32, __act: *const sigaction, __oact: *mut sigaction) -> i32;
+    fn pause() -> i32;
+}
+
+static mut got_signal: sig_atomic_t = 0 as i32;
+static mut last_signal: sig_atomic_t = 0 as i32;
+
+unsafe extern "C" fn on_signal(mut sig: i32) {
+    ::std::ptr::write_volatile(&mut got_signal as *mut sig_atomic_t, 1 as i32);
+    last_signal = sig;
+}
+
+unsafe fn wait_for_signal() -> i32 {
+    signal(2 as i32, Some(on_signal as unsafe extern "C" fn(i32) -> ()));
+    let mut act: sigaction = sigaction {
+        __sigaction_handler: C2RustUnnamed { sa_sigaction: 0 },
+        sa_flags: 0,
+    };
+    act.__sigaction_handler.sa_handler = Some(on_signal as unsafe extern "C" fn(i32) -> ());
+    act.sa_flags = 0 as i32;
+    sigaction(15 as i32, &mut act, 0 as *mut sigaction);
+    while ::std::ptr::read_volatile::<sig_atomic_t>(&got_signal as *const sig_atomic_t) == 0 {
+        pause();
+    }
+    return last_signal;
+}
+
+fn main() {}
@@ -0,0 +1,37 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+unsafe fn make_points(n: i32) -> *mut Point {
+    malloc_array::<Point>(n as usize)
+}
+
+unsafe fn make_point() -> *mut Point {
+    malloc_array::<Point>(1)
+}
+
+unsafe fn make_bytes(n: u64) -> *mut u8 {
+    malloc(n) as *mut u8
+}
+
+fn sizes(buf: &[u32], bytes: &[u8]) -> u64 {
+    let count = 8 as u64;
+    let total = 64 as u64;
+    let len = bytes.len() as u64;
+    let elems = (*buf).len();
+    let ptrs = (4 as u64).wrapping_mul(::std::mem::size_of::<usize>() as u64);
+    count + total + len + elems as u64 + ptrs
+}
+
+fn main() {}
+/// Allocate space for `n` values of type `T` with `malloc`.
+unsafe fn malloc_array<T>(n: usize) -> *mut T {
+    crate::malloc(n.wrapping_mul(::std::mem::size_of::<T>()) as _) as *mut T
+}
@@ -0,0 +1,34 @@
+extern "C" {
+    fn malloc(_: u64) -> *mut ::std::ffi::c_void;
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+unsafe fn make_points(n: i32) -> *mut Point {
+    malloc((n as u64).wrapping_mul(::std::mem::size_of::<Point>() as u64)) as *mut Point
+}
+
+unsafe fn make_point() -> *mut Point {
+    malloc(::std::mem::size_of::<Point>() as u64) as *mut Point
+}
+
+unsafe fn make_bytes(n: u64) -> *mut u8 {
+    malloc(n) as *mut u8
+}
+
+fn sizes(buf: &[u32], bytes: &[u8]) -> u64 {
+    let count = (::std::mem::size_of::<[i32; 8]>() as u64)
+        .wrapping_div(::std::mem::size_of::<i32>() as u64);
+    let total = (16 as u64).wrapping_mul(::std::mem::size_of::<u32>() as u64);
+    let len = (bytes.len() as u64).wrapping_mul(::std::mem::size_of::<u8>() as u64);
+    let elems = ::std::mem::size_of_val(&*buf) / ::std::mem::size_of::<u32>();
+    let ptrs = (4 as u64).wrapping_mul(::std::mem::size_of::<usize>() as u64);
+    count + total + len + elems as u64 + ptrs
+}
+
+fn main() {}
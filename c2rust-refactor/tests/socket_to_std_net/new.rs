@@ -0,0 +1,153 @@
+#![allow(non_camel_case_types)]
+
+pub type socklen_t = u32;
+
+#[repr(C)]
+pub struct sockaddr {
+    pub sa_family: u16,
+    pub sa_data: [i8; 14],
+}
+
+#[repr(C)]
+pub struct in_addr {
+    pub s_addr: u32,
+}
+
+#[repr(C)]
+pub struct sockaddr_in {
+    pub sin_family: u16,
+    pub sin_port: u16,
+    pub sin_addr: in_addr,
+    pub sin_zero: [u8; 8],
+}
+
+pub const SOCK_STREAM: u32 = 1;
+
+extern "C" {
+    fn socket(_: i32, _: i32, _: i32) -> i32;
+    fn bind(_: i32, _: *const sockaddr, _: socklen_t) -> i32;
+    fn listen(_: i32, _: i32) -> i32;
+    fn accept(_: i32, _: *mut sockaddr, _: *mut socklen_t) -> i32;
+    fn connect(_: i32, _: *const sockaddr, _: socklen_t) -> i32;
+    fn recv(_: i32, _: *mut ::std::ffi::c_void, _: usize, _: i32) -> isize;
+    fn send(_: i32, _: *const ::std::ffi::c_void, _: usize, _: i32) -> isize;
+    fn close(_: i32) -> i32;
+}
+
+unsafe fn serve(mut addr: sockaddr_in) -> i32 {
+    let mut server: Option<::std::net::TcpListener> = None;
+    if false {
+        return -1;
+    }
+    if {
+        server = ::std::net::TcpListener::bind(::std::net::SocketAddr::from(
+            ::std::net::SocketAddrV4::new(
+                ::std::net::Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            ),
+        ))
+        .ok();
+        if server.is_some() {
+            0
+        } else {
+            -1
+        }
+    } < 0 as i32
+    {
+        server = None;
+        return -1;
+    }
+    if server.is_some() {
+        0
+    } else {
+        -1
+    };
+    let mut conn: Option<::std::net::TcpStream> = server
+        .as_ref()
+        .and_then(|l| l.accept().ok())
+        .map(|(s, _)| s);
+    if conn.is_none() {
+        server = None;
+        return -1;
+    }
+    let mut buf: [u8; 64] = [0; 64];
+    let n: isize = {
+        let (sock, buf) = (
+            conn.as_mut(),
+            ::std::slice::from_raw_parts_mut(
+                buf.as_mut_ptr() as *mut ::std::ffi::c_void as *mut u8,
+                64 as usize,
+            ),
+        );
+        sock.map_or(-1, |s| {
+            ::std::io::Read::read(s, buf).map_or(-1, |n| n as isize)
+        })
+    };
+    {
+        let (sock, buf) = (
+            conn.as_mut(),
+            ::std::slice::from_raw_parts(
+                buf.as_ptr() as *const ::std::ffi::c_void as *const u8,
+                n as usize as usize,
+            ),
+        );
+        sock.map_or(-1, |s| {
+            ::std::io::Write::write(s, buf).map_or(-1, |n| n as isize)
+        })
+    };
+    conn = None;
+    server = None;
+    n as i32
+}
+
+unsafe fn ping(mut addr: sockaddr_in) -> isize {
+    let mut client: Option<::std::net::TcpStream> = None;
+    if {
+        client = ::std::net::TcpStream::connect(::std::net::SocketAddr::from(
+            ::std::net::SocketAddrV4::new(
+                ::std::net::Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            ),
+        ))
+        .ok();
+        if client.is_some() {
+            0
+        } else {
+            -1
+        }
+    } != 0
+    {
+        return -1;
+    }
+    let msg = b"ping";
+    let n = {
+        let (sock, buf) = (
+            client.as_mut(),
+            ::std::slice::from_raw_parts(
+                msg.as_ptr() as *const ::std::ffi::c_void as *const u8,
+                4 as usize,
+            ),
+        );
+        sock.map_or(-1, |s| {
+            ::std::io::Write::write(s, buf).map_or(-1, |n| n as isize)
+        })
+    };
+    client = None;
+    n
+}
+
+fn main() {
+    let addr = || sockaddr_in {
+        sin_family: 2,
+        sin_port: 40123u16.to_be(),
+        sin_addr: in_addr {
+            s_addr: 0x7f000001u32.to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    let server = ::std::thread::spawn(move || unsafe { serve(addr()) });
+    ::std::thread::sleep(::std::time::Duration::from_millis(100));
+    unsafe {
+        println!("{} {}", ping(addr()), server.join().unwrap());
+    }
+}
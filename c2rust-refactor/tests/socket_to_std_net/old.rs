@@ -0,0 +1,85 @@
+#![allow(non_camel_case_types)]
+
+pub type socklen_t = u32;
+
+#[repr(C)]
+pub struct sockaddr {
+    pub sa_family: u16,
+    pub sa_data: [i8; 14],
+}
+
+#[repr(C)]
+pub struct in_addr {
+    pub s_addr: u32,
+}
+
+#[repr(C)]
+pub struct sockaddr_in {
+    pub sin_family: u16,
+    pub sin_port: u16,
+    pub sin_addr: in_addr,
+    pub sin_zero: [u8; 8],
+}
+
+pub const SOCK_STREAM: u32 = 1;
+
+extern "C" {
+    fn socket(_: i32, _: i32, _: i32) -> i32;
+    fn bind(_: i32, _: *const sockaddr, _: socklen_t) -> i32;
+    fn listen(_: i32, _: i32) -> i32;
+    fn accept(_: i32, _: *mut sockaddr, _: *mut socklen_t) -> i32;
+    fn connect(_: i32, _: *const sockaddr, _: socklen_t) -> i32;
+    fn recv(_: i32, _: *mut ::std::ffi::c_void, _: usize, _: i32) -> isize;
+    fn send(_: i32, _: *const ::std::ffi::c_void, _: usize, _: i32) -> isize;
+    fn close(_: i32) -> i32;
+}
+
+unsafe fn serve(mut addr: sockaddr_in) -> i32 {
+    let mut server: i32 = socket(2 as i32, SOCK_STREAM as i32, 0 as i32);
+    if server < 0 as i32 {
+        return -1;
+    }
+    if bind(server, &mut addr as *mut sockaddr_in as *mut sockaddr,
+            ::std::mem::size_of::<sockaddr_in>() as socklen_t) < 0 as i32 {
+        close(server);
+        return -1;
+    }
+    listen(server, 5 as i32);
+    let mut conn: i32 = accept(server, 0 as *mut sockaddr, 0 as *mut socklen_t);
+    if conn == -(1 as i32) {
+        close(server);
+        return -1;
+    }
+    let mut buf: [u8; 64] = [0; 64];
+    let n: isize = recv(conn, buf.as_mut_ptr() as *mut ::std::ffi::c_void, 64, 0 as i32);
+    send(conn, buf.as_ptr() as *const ::std::ffi::c_void, n as usize, 0 as i32);
+    close(conn);
+    close(server);
+    n as i32
+}
+
+unsafe fn ping(mut addr: sockaddr_in) -> isize {
+    let mut client: i32 = socket(2 as i32, SOCK_STREAM as i32, 0 as i32);
+    if connect(client, &mut addr as *mut sockaddr_in as *const sockaddr,
+               ::std::mem::size_of::<sockaddr_in>() as socklen_t) != 0 {
+        return -1;
+    }
+    let msg = b"ping";
+    let n = send(client, msg.as_ptr() as *const ::std::ffi::c_void, 4, 0 as i32);
+    close(client);
+    n
+}
+
+fn main() {
+    let addr = || sockaddr_in {
+        sin_family: 2,
+        sin_port: 40123u16.to_be(),
+        sin_addr: in_addr { s_addr: 0x7f000001u32.to_be() },
+        sin_zero: [0; 8],
+    };
+    let server = ::std::thread::spawn(move || unsafe { serve(addr()) });
+    ::std::thread::sleep(::std::time::Duration::from_millis(100));
+    unsafe {
+        println!("{} {}", ping(addr()), server.join().unwrap());
+    }
+}
@@ -0,0 +1,36 @@
+struct Stack {
+    data: [i32; 16],
+    len: usize,
+}
+impl Stack {
+    unsafe fn init(&mut self) {
+        (*self).len = 0;
+    }
+
+    unsafe fn push(&mut self, x: i32) {
+        (*self).data[(*self).len] = x;
+        (*self).len += 1;
+    }
+
+    unsafe fn len(&self) -> usize {
+        (*self).len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    unsafe fn push_twice(&mut self, x: i32) {
+        self.push(x);
+        self.push(x);
+    }
+}
+
+fn main() {
+    let mut s = Stack { data: [0; 16], len: 0 };
+    unsafe {
+        s.init();
+        s.push_twice(3);
+        println!("{} {}", s.len(), s.is_empty());
+    }
+}
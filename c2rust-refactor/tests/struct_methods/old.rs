@@ -0,0 +1,35 @@
+struct Stack {
+    data: [i32; 16],
+    len: usize,
+}
+
+unsafe fn stack_init(mut s: *mut Stack) {
+    (*s).len = 0;
+}
+
+unsafe fn stack_push(mut s: *mut Stack, x: i32) {
+    (*s).data[(*s).len] = x;
+    (*s).len += 1;
+}
+
+unsafe fn stack_len(s: *const Stack) -> usize {
+    (*s).len
+}
+
+fn stack_is_empty(s: &Stack) -> bool {
+    s.len == 0
+}
+
+unsafe fn push_twice(s: *mut Stack, x: i32) {
+    stack_push(s, x);
+    stack_push(s, x);
+}
+
+fn main() {
+    let mut s = Stack { data: [0; 16], len: 0 };
+    unsafe {
+        stack_init(&mut s);
+        push_twice(&mut s, 3);
+        println!("{} {}", stack_len(&s), stack_is_empty(&s));
+    }
+}
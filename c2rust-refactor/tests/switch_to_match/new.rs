@@ -0,0 +1,23 @@
+fn classify(c: i32) -> i32 {
+    let mut n = 0;
+    match c {
+        1 => {
+            n += 1;
+            n += 10;
+        }
+        2 => {
+            n += 10;
+        }
+        3 => {
+            return -1;
+        }
+        _ => {
+            n += 100;
+        }
+    }
+    n
+}
+
+fn main() {
+    println!("{} {} {}", classify(1), classify(2), classify(4));
+}
@@ -0,0 +1,32 @@
+fn classify(c: i32) -> i32 {
+    let mut n = 0;
+    let mut current_block: u64;
+    match c {
+        1 => {
+            n += 1;
+            current_block = 7;
+        }
+        2 => {
+            current_block = 7;
+        }
+        3 => {
+            return -1;
+        }
+        _ => {
+            current_block = 9;
+        }
+    }
+    match current_block {
+        7 => {
+            n += 10;
+        }
+        _ => {
+            n += 100;
+        }
+    }
+    n
+}
+
+fn main() {
+    println!("{} {} {}", classify(1), classify(2), classify(4));
+}
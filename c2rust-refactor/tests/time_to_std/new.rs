@@ -0,0 +1,53 @@
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timeval {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+
+extern "C" {
+    fn clock_gettime(__clock_id: i32, __tp: *mut timespec) -> i32;
+    fn gettimeofday(__tv: *mut timeval, __tz: *mut ::std::ffi::c_void) -> i32;
+    fn time(__timer: *mut i64) -> i64;
+    fn difftime(__time1: i64, __time0: i64) -> f64;
+}
+
+unsafe fn elapsed() -> f64 {
+    let mut start: ::std::time::Instant = ::std::time::Instant::now();
+    let mut end: ::std::time::Instant = ::std::time::Instant::now();
+    start = ::std::time::Instant::now();
+    end = ::std::time::Instant::now();
+    return (end.duration_since(start).as_secs() as i64) as f64
+        + (end.duration_since(start).subsec_nanos() as i64) as f64 / 1e9;
+}
+
+unsafe fn timestamp_ms() -> i64 {
+    let mut now: ::std::time::SystemTime = ::std::time::UNIX_EPOCH;
+    now = ::std::time::SystemTime::now();
+    return (now
+        .duration_since(::std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64)
+        * 1000 as i64
+        + (now
+            .duration_since(::std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_micros() as i64)
+            / 1000 as i64;
+}
+
+unsafe fn seconds_since(start: i64) -> f64 {
+    return (::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH)
+        .map_or(-1, |d| d.as_secs() as i64)
+        - start) as f64;
+}
+
+fn main() {}
@@ -0,0 +1,40 @@
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct timeval {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+
+extern "C" {
+    fn clock_gettime(__clock_id: i32, __tp: *mut timespec) -> i32;
+    fn gettimeofday(__tv: *mut timeval, __tz: *mut ::std::ffi::c_void) -> i32;
+    fn time(__timer: *mut i64) -> i64;
+    fn difftime(__time1: i64, __time0: i64) -> f64;
+}
+
+unsafe fn elapsed() -> f64 {
+    let mut start: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    let mut end: timespec = timespec { tv_sec: 0, tv_nsec: 0 };
+    clock_gettime(1 as i32, &mut start);
+    clock_gettime(1 as i32, &mut end);
+    return (end.tv_sec - start.tv_sec) as f64 + (end.tv_nsec - start.tv_nsec) as f64 / 1e9;
+}
+
+unsafe fn timestamp_ms() -> i64 {
+    let mut now: timeval = timeval { tv_sec: 0, tv_usec: 0 };
+    gettimeofday(&mut now, 0 as *mut ::std::ffi::c_void);
+    return now.tv_sec * 1000 as i64 + now.tv_usec / 1000 as i64;
+}
+
+unsafe fn seconds_since(start: i64) -> f64 {
+    return difftime(time(0 as *mut i64), start);
+}
+
+fn main() {}
@@ -0,0 +1,39 @@
+struct Node {
+    value: i32,
+    next: *mut Node,
+}
+
+unsafe fn first(n: Option<&Node>) -> i32 {
+    if n.is_none() {
+        return -1;
+    }
+    (*n.unwrap()).value
+}
+
+unsafe fn bump(mut n: Option<&mut Node>) {
+    if let Some(n) = n.as_deref_mut() {
+        (*n).value += 1;
+    }
+}
+
+unsafe fn last(head: *const Node) -> *const Node {
+    let mut cur: Option<&Node> = None;
+    let mut n: Option<&Node> = head.as_ref();
+    while n.is_some() {
+        cur = n;
+        n = (*n.map_or(::std::ptr::null(), |p| p as *const Node)).next.as_ref();
+    }
+    cur.map_or(::std::ptr::null(), |p| p as *const Node)
+}
+
+fn main() {
+    let mut a = Node {
+        value: 1,
+        next: 0 as *mut Node,
+    };
+    unsafe {
+        bump(Some(&mut a));
+        bump(None);
+        first(last(&mut a).as_ref());
+    }
+}
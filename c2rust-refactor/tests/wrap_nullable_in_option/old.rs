@@ -0,0 +1,39 @@
+struct Node {
+    value: i32,
+    next: *mut Node,
+}
+
+unsafe fn first(n: *const Node) -> i32 {
+    if n.is_null() {
+        return -1;
+    }
+    (*n).value
+}
+
+unsafe fn bump(n: *mut Node) {
+    if !n.is_null() {
+        (*n).value += 1;
+    }
+}
+
+unsafe fn last(head: *const Node) -> *const Node {
+    let mut cur: *const Node = 0 as *const Node;
+    let mut n: *const Node = head;
+    while !n.is_null() {
+        cur = n;
+        n = (*n).next;
+    }
+    cur
+}
+
+fn main() {
+    let mut a = Node {
+        value: 1,
+        next: 0 as *mut Node,
+    };
+    unsafe {
+        bump(&mut a as *mut Node);
+        bump(0 as *mut Node);
+        first(last(&mut a));
+    }
+}
@@ -9,12 +9,20 @@ use pathdiff::diff_paths;
 use serde_derive::Serialize;
 use serde_json::json;
 
+use self::system_libs::{pkg_config_package, LinkedLibs};
 use super::compile_cmds::LinkCmd;
 use super::TranspilerConfig;
+use crate::c_sources::CSource;
 use crate::get_module_name;
+use crate::str_to_ident;
 use crate::CrateSet;
 use crate::ExternCrateDetails;
 use crate::PragmaSet;
+use crate::WasmTarget;
+
+mod system_libs;
+
+pub use self::system_libs::SystemLibs;
 
 #[derive(Debug, Copy, Clone)]
 pub enum BuildDirectoryContents {
@@ -61,6 +69,10 @@ pub struct CrateConfig<'lcmd> {
     pub modules: Vec<PathBuf>,
     pub pragmas: PragmaSet,
     pub crates: CrateSet,
+    /// Names of the other crates of the workspace this one depends on
+    pub crate_deps: Vec<String>,
+    /// The C files `build.rs` compiles instead of translating them
+    pub c_sources: Vec<CSource>,
     pub link_cmd: &'lcmd LinkCmd,
 }
 
@@ -73,26 +85,40 @@ pub fn emit_build_files<'lcmd>(
     crate_cfg: Option<CrateConfig<'lcmd>>,
     workspace_members: Option<Vec<String>>,
 ) -> Option<PathBuf> {
-    let mut reg = Handlebars::new();
-
-    reg.register_template_string("Cargo.toml", include_str!("Cargo.toml.hbs"))
-        .unwrap();
-    reg.register_template_string("lib.rs", include_str!("lib.rs.hbs"))
-        .unwrap();
-    reg.register_template_string("build.rs", include_str!("build.rs.hbs"))
-        .unwrap();
+    let reg = templates();
 
     if !build_dir.exists() {
         fs::create_dir_all(&build_dir)
             .unwrap_or_else(|_| panic!("couldn't create build directory: {}", build_dir.display()));
     }
 
-    emit_cargo_toml(tcfg, &reg, build_dir, &crate_cfg, workspace_members);
+    let libs = crate_cfg.as_ref().map_or_else(LinkedLibs::default, |ccfg| {
+        let mut libs = LinkedLibs::new(tcfg.system_libs, &ccfg.link_cmd.libs, pkg_config_package);
+        // The translation may already depend on a `-sys` crate for its declarations.
+        libs.sys_crates.retain(|sys_crate| {
+            !ccfg
+                .crates
+                .iter()
+                .any(|&dep| ExternCrateDetails::from(dep).name == sys_crate.name)
+        });
+        libs
+    });
+    emit_cargo_toml(tcfg, &reg, build_dir, &crate_cfg, &libs, workspace_members);
     if tcfg.translate_valist {
         emit_rust_toolchain(tcfg, build_dir);
     }
+    if let Some(target) = tcfg.wasm_target {
+        emit_cargo_config(tcfg, build_dir, target);
+    }
     crate_cfg.and_then(|ccfg| {
-        emit_build_rs(tcfg, &reg, build_dir, ccfg.link_cmd);
+        let capi_header = capi_header(tcfg, &ccfg);
+        if let Some(ref header) = capi_header {
+            emit_cbindgen_toml(tcfg, &reg, build_dir, header);
+        }
+        emit_build_rs(tcfg, &reg, build_dir, &libs, &ccfg.c_sources, capi_header);
+        if let Some(ref workspace) = tcfg.bazel_workspace {
+            emit_bazel_build(tcfg, &reg, build_dir, workspace, &ccfg);
+        }
         emit_lib_rs(
             tcfg,
             &reg,
@@ -100,10 +126,64 @@ pub fn emit_build_files<'lcmd>(
             ccfg.modules,
             ccfg.pragmas,
             &ccfg.crates,
+            &ccfg.crate_deps,
+            &libs,
         )
     })
 }
 
+/// Emit `Cargo.toml` for a binary crate of a workspace, whose `main` function is in `module` of
+/// the library crate `lib`.  The binary crate is emitted next to `lib` in `workspace_dir`, and
+/// depends on `crate_deps`.  Returns the name of the binary crate.
+pub fn emit_binary_crate(
+    tcfg: &TranspilerConfig,
+    workspace_dir: &Path,
+    lib: &CrateConfig,
+    module: &Path,
+    crate_deps: Vec<String>,
+) -> String {
+    let mut crate_name = TranspilerConfig::binary_name_from_path(module);
+    if crate_name == lib.crate_name {
+        crate_name.push_str("_bin");
+    }
+    let build_dir = workspace_dir.join(&crate_name);
+    fs::create_dir_all(&build_dir)
+        .unwrap_or_else(|_| panic!("couldn't create build directory: {}", build_dir.display()));
+
+    let json = json!({
+        "is_workspace": false,
+        "is_crate": true,
+        "crate_name": crate_name,
+        "crate_rust_name": crate_name.replace('-', "_"),
+        "is_library": false,
+        "lib_rs_file": diff_paths(module, &build_dir).unwrap(),
+        "binaries": [],
+        "dependencies": convert_dependencies_list(lib.crates.clone()),
+        "crate_deps": crate_deps,
+        "features": cargo_features(tcfg),
+    });
+    let output = templates().render("Cargo.toml", &json).unwrap();
+    let output_path = build_dir.join("Cargo.toml");
+    maybe_write_to_file(&output_path, output, tcfg.overwrite_existing);
+    crate_name
+}
+
+fn templates() -> Handlebars<'static> {
+    let mut reg = Handlebars::new();
+
+    reg.register_template_string("Cargo.toml", include_str!("Cargo.toml.hbs"))
+        .unwrap();
+    reg.register_template_string("lib.rs", include_str!("lib.rs.hbs"))
+        .unwrap();
+    reg.register_template_string("build.rs", include_str!("build.rs.hbs"))
+        .unwrap();
+    reg.register_template_string("BUILD.bazel", include_str!("BUILD.bazel.hbs"))
+        .unwrap();
+    reg.register_template_string("cbindgen.toml", include_str!("cbindgen.toml.hbs"))
+        .unwrap();
+    reg
+}
+
 #[derive(Serialize)]
 struct Module {
     path: Option<String>,
@@ -213,21 +293,96 @@ fn get_lib_rs_file_name(tcfg: &TranspilerConfig) -> &str {
     }
 }
 
-/// Emit `build.rs` to make it easier to link in native libraries
+/// Emit `build.rs` to make it easier to link in native libraries, and to compile the C files
+/// that weren't translated
 fn emit_build_rs(
     tcfg: &TranspilerConfig,
     reg: &Handlebars,
     build_dir: &Path,
-    link_cmd: &LinkCmd,
+    libs: &LinkedLibs,
+    c_sources: &[CSource],
+    capi_header: Option<String>,
 ) -> Option<PathBuf> {
     let json = json!({
-        "libraries": link_cmd.libs,
+        "libraries": libs.link,
+        "pkg_config": libs.pkg_config,
+        "c_sources": c_sources,
+        "capi_header": capi_header,
     });
     let output = reg.render("build.rs", &json).unwrap();
     let output_path = build_dir.join("build.rs");
     maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
 }
 
+/// The name of the C header of the crate's C API, if it's a library with one.
+fn capi_header(tcfg: &TranspilerConfig, ccfg: &CrateConfig) -> Option<String> {
+    if tcfg.emit_capi && ccfg.link_cmd.r#type.is_library() {
+        Some(format!("{}.h", ccfg.crate_name.replace('-', "_")))
+    } else {
+        None
+    }
+}
+
+/// Emit the `cbindgen.toml` that `build.rs` generates the C header `header` with.
+fn emit_cbindgen_toml(
+    tcfg: &TranspilerConfig,
+    reg: &Handlebars,
+    build_dir: &Path,
+    header: &str,
+) -> Option<PathBuf> {
+    let include_guard = str_to_ident(header).to_uppercase();
+    let json = json!({
+        "header": header,
+        "include_guard": include_guard,
+    });
+    let output = reg.render("cbindgen.toml", &json).unwrap();
+    let output_path = build_dir.join("cbindgen.toml");
+    maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
+}
+
+/// Emit `BUILD.bazel` with a `rules_rust` rule for the crate, so that it can be built by Bazel
+/// in `workspace` alongside the C code it was translated from.
+fn emit_bazel_build(
+    tcfg: &TranspilerConfig,
+    reg: &Handlebars,
+    build_dir: &Path,
+    workspace: &Path,
+    ccfg: &CrateConfig,
+) -> Option<PathBuf> {
+    // Other crates are in sibling directories, so they're in sibling packages.
+    let parent_package = build_dir
+        .parent()
+        .and_then(|dir| diff_paths(dir, workspace))
+        .filter(|dir| !dir.starts_with(".."))
+        .unwrap_or_else(|| {
+            eprintln!(
+                "{} is outside the Bazel workspace; labels in BUILD.bazel need to be updated",
+                build_dir.display()
+            );
+            PathBuf::new()
+        });
+    let package_prefix = match parent_package.to_str().unwrap() {
+        "" => String::new(),
+        package => format!("{}/", package),
+    };
+    let rule = if ccfg.link_cmd.r#type.is_library() {
+        "rust_library"
+    } else {
+        "rust_binary"
+    };
+    let json = json!({
+        "rule": rule,
+        "crate_rust_name": ccfg.crate_name.replace('-', "_"),
+        "lib_rs_file": get_lib_rs_file_name(tcfg),
+        "dependencies": convert_dependencies_list(ccfg.crates.clone()),
+        "crate_deps": ccfg.crate_deps,
+        "package_prefix": package_prefix,
+    });
+    let output = reg.render("BUILD.bazel", &json).unwrap();
+    let output_path = build_dir.join("BUILD.bazel");
+    maybe_write_to_file(&output_path, output, tcfg.overwrite_existing)
+}
+
 /// Emit lib.rs (main.rs) for a library (binary). Returns `Some(path)`
 /// to the generated file or `None` if the output file exists.
 fn emit_lib_rs(
@@ -237,6 +392,8 @@ fn emit_lib_rs(
     modules: Vec<PathBuf>,
     pragmas: PragmaSet,
     crates: &CrateSet,
+    crate_deps: &[String],
+    libs: &LinkedLibs,
 ) -> Option<PathBuf> {
     let modules = convert_module_list(tcfg, build_dir, modules, ModuleSubset::Libraries);
     let crates = convert_dependencies_list(crates.clone());
@@ -248,6 +405,9 @@ fn emit_lib_rs(
         "modules": modules,
         "pragmas": pragmas,
         "crates": crates,
+        "crate_deps": crate_deps,
+        "sys_crates": libs.sys_crates,
+        "wasm_libc": emit_wasm_libc(tcfg, build_dir),
     });
 
     let output_path = build_dir.join(file_name);
@@ -264,11 +424,70 @@ fn emit_rust_toolchain(tcfg: &TranspilerConfig, build_dir: &Path) {
     maybe_write_to_file(&output_path, output, tcfg.overwrite_existing);
 }
 
+/// Emit `.cargo/config.toml`, making the wasm target the default target of `cargo build`.
+fn emit_cargo_config(tcfg: &TranspilerConfig, build_dir: &Path, target: WasmTarget) {
+    let config_dir = build_dir.join(".cargo");
+    fs::create_dir_all(&config_dir)
+        .unwrap_or_else(|_| panic!("couldn't create directory: {}", config_dir.display()));
+    let output = format!("[build]\ntarget = \"{}\"\n", target.triple());
+    maybe_write_to_file(
+        &config_dir.join("config.toml"),
+        output,
+        tcfg.overwrite_existing,
+    );
+}
+
+/// On `wasm32-unknown-unknown`, emit the `libc` module the translated code uses in place of the
+/// `libc` crate.  Returns whether `lib.rs` should declare it.
+fn emit_wasm_libc(tcfg: &TranspilerConfig, build_dir: &Path) -> bool {
+    if tcfg.wasm_target != Some(WasmTarget::Unknown) {
+        return false;
+    }
+    let output = include_str!("wasm_libc.rs").to_string();
+    maybe_write_to_file(
+        &build_dir.join("wasm_libc.rs"),
+        output,
+        tcfg.overwrite_existing,
+    );
+    true
+}
+
+/// The Cargo features that select the `cfg_variants` of the translated code.
+fn cargo_features(tcfg: &TranspilerConfig) -> Vec<&str> {
+    let mut features = Vec::new();
+    for feature in tcfg.cfg_variants.iter().filter_map(|v| v.feature()) {
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+    features
+}
+
+/// The crates `build.rs` needs, with their versions.
+fn build_dependencies(
+    tcfg: &TranspilerConfig,
+    ccfg: &CrateConfig,
+    libs: &LinkedLibs,
+) -> Vec<serde_json::Value> {
+    let mut deps = vec![];
+    if !ccfg.c_sources.is_empty() {
+        deps.push(json!({ "name": "cc", "version": "1.0" }));
+    }
+    if !libs.pkg_config.is_empty() {
+        deps.push(json!({ "name": "pkg-config", "version": "0.3" }));
+    }
+    if capi_header(tcfg, ccfg).is_some() {
+        deps.push(json!({ "name": "cbindgen", "version": "0.26" }));
+    }
+    deps
+}
+
 fn emit_cargo_toml<'lcmd>(
     tcfg: &TranspilerConfig,
     reg: &Handlebars,
     build_dir: &Path,
     crate_cfg: &Option<CrateConfig<'lcmd>>,
+    libs: &LinkedLibs,
     workspace_members: Option<Vec<String>>,
 ) {
     // rust_checks_path is gone because we don't want to refer to the source
@@ -279,21 +498,36 @@ fn emit_cargo_toml<'lcmd>(
         "workspace_members": workspace_members.unwrap_or_default(),
     });
     if let Some(ccfg) = crate_cfg {
-        let binaries = convert_module_list(
-            tcfg,
-            build_dir,
-            ccfg.modules.to_owned(),
-            ModuleSubset::Binaries,
-        );
+        // In a workspace, binaries are crates of their own.
+        let binaries = if tcfg.emit_workspace {
+            vec![]
+        } else {
+            convert_module_list(
+                tcfg,
+                build_dir,
+                ccfg.modules.to_owned(),
+                ModuleSubset::Binaries,
+            )
+        };
         let dependencies = convert_dependencies_list(ccfg.crates.clone());
+        // A C API is built into libraries C code can link.
+        let crate_types = if capi_header(tcfg, ccfg).is_some() {
+            "\"staticlib\", \"cdylib\", \"rlib\""
+        } else {
+            ccfg.link_cmd.r#type.as_cargo_types()
+        };
         let crate_json = json!({
             "crate_name": ccfg.crate_name,
             "crate_rust_name": ccfg.crate_name.replace('-', "_"),
-            "crate_types": ccfg.link_cmd.r#type.as_cargo_types(),
+            "crate_types": crate_types,
             "is_library": ccfg.link_cmd.r#type.is_library(),
             "lib_rs_file": get_lib_rs_file_name(tcfg),
             "binaries": binaries,
             "dependencies": dependencies,
+            "crate_deps": ccfg.crate_deps,
+            "sys_crates": libs.sys_crates,
+            "build_dependencies": build_dependencies(tcfg, ccfg, libs),
+            "features": cargo_features(tcfg),
         });
         json.as_object_mut().unwrap().extend(
             crate_json
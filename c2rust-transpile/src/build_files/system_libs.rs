@@ -0,0 +1,156 @@
+//! Linking the generated crates with the system libraries the C code was linked with.
+
+use std::process::Command;
+
+use serde_derive::Serialize;
+
+/// How the generated crates link in the system libraries of their link commands, like `-lz`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SystemLibs {
+    /// Link them with `cargo:rustc-link-lib` directives in `build.rs`
+    Link,
+    /// Find the ones `pkg-config` knows with the `pkg-config` crate from `build.rs`, and link the
+    /// others directly
+    PkgConfig,
+    /// Depend on the `-sys` crates of the ones that have one, and find the others like
+    /// `PkgConfig` does
+    SysCrates,
+}
+
+/// Libraries and the `-sys` crates linking them, with the versions we depend on.
+const SYS_CRATES: &[(&str, &str, &str)] = &[
+    ("z", "libz-sys", "1.1"),
+    ("ssl", "openssl-sys", "0.9"),
+    ("crypto", "openssl-sys", "0.9"),
+    ("curl", "curl-sys", "0.4"),
+    ("sqlite3", "libsqlite3-sys", "0.26"),
+    ("bz2", "bzip2-sys", "0.1"),
+    ("lzma", "lzma-sys", "0.1"),
+    ("zstd", "zstd-sys", "2.0"),
+    ("git2", "libgit2-sys", "0.15"),
+    ("ssh2", "libssh2-sys", "0.3"),
+    ("ffi", "libffi-sys", "2.3"),
+    ("pcre2-8", "pcre2-sys", "0.2"),
+    ("dbus-1", "libdbus-sys", "0.2"),
+    ("udev", "libudev-sys", "0.1"),
+    ("usb-1.0", "libusb1-sys", "0.6"),
+    ("SDL2", "sdl2-sys", "0.35"),
+];
+
+/// Libraries that are part of the C library on some systems, which have no `pkg-config`
+/// packages, and are linked directly.
+const LIBC_LIBS: &[&str] = &["c", "m", "pthread", "dl", "rt", "util"];
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct SysCrate {
+    pub name: &'static str,
+    pub ident: String,
+    pub version: &'static str,
+}
+
+/// The system libraries of a crate, by how they're linked in.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct LinkedLibs {
+    /// Libraries linked with `cargo:rustc-link-lib`
+    pub link: Vec<String>,
+    /// `pkg-config` packages found from `build.rs`
+    pub pkg_config: Vec<String>,
+    pub sys_crates: Vec<SysCrate>,
+}
+
+impl LinkedLibs {
+    /// Decide how to link in `libs`, using `pkg_config` to find the `pkg-config` package of a
+    /// library, if it has one.
+    pub fn new(
+        mode: SystemLibs,
+        libs: &[String],
+        pkg_config: impl Fn(&str) -> Option<String>,
+    ) -> LinkedLibs {
+        let mut linked = LinkedLibs::default();
+        for lib in libs {
+            let sys_crate = SYS_CRATES.iter().find(|(name, _, _)| *name == lib.as_str());
+            if let (SystemLibs::SysCrates, Some(&(_, name, version))) = (mode, sys_crate) {
+                if !linked.sys_crates.iter().any(|c| c.name == name) {
+                    linked.sys_crates.push(SysCrate {
+                        name,
+                        ident: name.replace('-', "_"),
+                        version,
+                    });
+                }
+                continue;
+            }
+            let package = match mode {
+                SystemLibs::Link => None,
+                _ if LIBC_LIBS.contains(&lib.as_str()) => None,
+                _ => pkg_config(lib),
+            };
+            match package {
+                Some(package) if !linked.pkg_config.contains(&package) => {
+                    linked.pkg_config.push(package)
+                }
+                Some(_) => {}
+                None => linked.link.push(lib.clone()),
+            }
+        }
+        linked
+    }
+}
+
+/// Find the `pkg-config` package that links in `lib` on this system, if there is one.
+pub fn pkg_config_package(lib: &str) -> Option<String> {
+    let link_arg = format!("-l{}", lib);
+    // Packages are usually named after the library, with or without the `lib` prefix.
+    let mut candidates = vec![lib.to_owned(), format!("lib{}", lib)];
+    if lib == "z" {
+        candidates.push("zlib".to_owned());
+    }
+    candidates.into_iter().find(|package| {
+        Command::new("pkg-config")
+            .args(["--libs", package])
+            .output()
+            .map_or(false, |output| {
+                output.status.success()
+                    && String::from_utf8_lossy(&output.stdout)
+                        .split_whitespace()
+                        .any(|arg| arg == link_arg)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn libs(libs: &[&str]) -> Vec<String> {
+        libs.iter().map(|&lib| lib.to_owned()).collect()
+    }
+
+    #[test]
+    fn links_libraries() {
+        let libs = libs(&["ssl", "crypto", "m", "xml2", "foo"]);
+        let pkg_config = |lib: &str| match lib {
+            "xml2" => Some("libxml-2.0".to_owned()),
+            "ssl" => Some("libssl".to_owned()),
+            _ => None,
+        };
+
+        let linked = LinkedLibs::new(SystemLibs::SysCrates, &libs, pkg_config);
+        assert_eq!(
+            linked.sys_crates,
+            [SysCrate {
+                name: "openssl-sys",
+                ident: "openssl_sys".to_owned(),
+                version: "0.9",
+            }]
+        );
+        assert_eq!(linked.pkg_config, ["libxml-2.0"]);
+        assert_eq!(linked.link, ["m", "foo"]);
+
+        let linked = LinkedLibs::new(SystemLibs::PkgConfig, &libs, pkg_config);
+        assert_eq!(linked.pkg_config, ["libssl", "libxml-2.0"]);
+        assert_eq!(linked.link, ["crypto", "m", "foo"]);
+
+        let linked = LinkedLibs::new(SystemLibs::Link, &libs, pkg_config);
+        assert_eq!(linked.link, libs);
+    }
+}
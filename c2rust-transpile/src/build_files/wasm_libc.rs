@@ -0,0 +1,100 @@
+//! The parts of the C library the translated code needs on `wasm32-unknown-unknown`, where the
+//! `libc` crate is empty.  Generated by c2rust.
+#![allow(non_camel_case_types)]
+
+use std::alloc::{self, Layout};
+use std::mem;
+
+pub use core::ffi::{
+    c_char, c_double, c_float, c_int, c_long, c_longlong, c_schar, c_short, c_uchar, c_uint,
+    c_ulong, c_ulonglong, c_ushort, c_void,
+};
+
+pub type size_t = usize;
+pub type ssize_t = isize;
+pub type ptrdiff_t = isize;
+pub type intptr_t = isize;
+pub type uintptr_t = usize;
+
+// Provided by `compiler_builtins`.
+extern "C" {
+    pub fn memcpy(dest: *mut c_void, src: *const c_void, n: size_t) -> *mut c_void;
+    pub fn memmove(dest: *mut c_void, src: *const c_void, n: size_t) -> *mut c_void;
+    pub fn memset(s: *mut c_void, c: c_int, n: size_t) -> *mut c_void;
+    pub fn memcmp(s1: *const c_void, s2: *const c_void, n: size_t) -> c_int;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strlen(s: *const c_char) -> size_t {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+/// Allocations start with a header holding their size, which `free` and `realloc` need to
+/// rebuild the allocation's `Layout`.  The header is as large as C's `max_align_t`.
+const HEADER: usize = 16;
+
+fn layout(size: size_t) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: size_t) -> *mut c_void {
+    let layout = match layout(size) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
+    let ptr = alloc::alloc(layout);
+    if ptr.is_null() {
+        return ptr.cast();
+    }
+    *(ptr as *mut size_t) = size;
+    ptr.add(HEADER).cast()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn calloc(nmemb: size_t, size: size_t) -> *mut c_void {
+    let size = match nmemb.checked_mul(size) {
+        Some(size) => size,
+        None => return std::ptr::null_mut(),
+    };
+    let ptr = malloc(size);
+    if !ptr.is_null() {
+        memset(ptr, 0, size);
+    }
+    ptr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: size_t) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    let base = (ptr as *mut u8).sub(HEADER);
+    let old_size = *(base as *mut size_t);
+    let new_layout = match layout(size) {
+        Some(layout) => layout,
+        None => return std::ptr::null_mut(),
+    };
+    let base = alloc::realloc(base, layout(old_size).unwrap(), new_layout.size());
+    if base.is_null() {
+        return base.cast();
+    }
+    *(base as *mut size_t) = size;
+    base.add(HEADER).cast()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let base = (ptr as *mut u8).sub(HEADER);
+    let size = *(base as *mut size_t);
+    alloc::dealloc(base, layout(size).unwrap());
+}
+
+const _: () = assert!(mem::size_of::<size_t>() <= HEADER);
@@ -171,12 +171,14 @@ fn parse_attributes(attributes: Vec<Value>) -> IndexSet<Attribute> {
     let mut expect_section_value = false;
     let mut expect_alias_value = false;
     let mut expect_visibility_value = false;
+    let mut expect_annotate_value = false;
 
     for attr in attributes.into_iter() {
         let attr_str = from_value::<String>(attr).expect("Decl attributes should be strings");
 
         match attr_str.as_str() {
             "alias" => expect_alias_value = true,
+            "annotate" => expect_annotate_value = true,
             "always_inline" => {
                 attrs.insert(Attribute::AlwaysInline);
             }
@@ -209,6 +211,11 @@ fn parse_attributes(attributes: Vec<Value>) -> IndexSet<Attribute> {
 
                 expect_visibility_value = false;
             }
+            s if expect_annotate_value => {
+                attrs.insert(Attribute::Annotate(s.into()));
+
+                expect_annotate_value = false;
+            }
             _ => {}
         }
     }
@@ -254,6 +261,20 @@ impl ConversionContext {
 
         // This starts out as all of the top-level nodes, which we expect to be 'DECL's
         let mut visit_as: Vec<(ClangId, NodeType)> = Vec::new();
+
+        // C++ namespaces aren't top-level nodes, but the declarations in them are, so namespaces
+        // are visited after them, in source order.
+        let mut namespaces = untyped_context
+            .ast_nodes
+            .iter()
+            .filter(|(_, node)| node.tag == ASTEntryTag::TagNamespaceDecl)
+            .map(|(&id, node)| (node.loc, id))
+            .collect::<Vec<_>>();
+        namespaces.sort();
+        for &(_, namespace) in namespaces.iter().rev() {
+            visit_as.push((namespace, node_types::DECL));
+        }
+
         for top_node in untyped_context.top_nodes.iter().rev() {
             if untyped_context.ast_nodes.contains_key(top_node) {
                 visit_as.push((*top_node, node_types::DECL));
@@ -1156,7 +1177,25 @@ impl ConversionContext {
                     // Either we're able to evaluate the offsetof to an int constant expr
                     // or else we have to use the offset_of! macro from the memoffset crate
                     let offset_of = if let Ok(value) = from_value(node.extras[0].clone()) {
-                        let kind = OffsetOfKind::Constant(value);
+                        let kind = match node.extras.get(1) {
+                            Some(qty) => {
+                                let qty_int = from_value(qty.clone())
+                                    .expect("Expected offset of to have struct type");
+                                let qty = self.visit_qualified_type(qty_int);
+                                let fields = from_value::<Vec<Value>>(node.extras[2].clone())
+                                    .expect("Expected offset of fields");
+                                let field_ids = fields
+                                    .into_iter()
+                                    .map(|field| {
+                                        let field =
+                                            from_value(field).expect("Expected offset of field");
+                                        self.visit_decl(field)
+                                    })
+                                    .collect();
+                                OffsetOfKind::Field(value, qty, field_ids)
+                            }
+                            None => OffsetOfKind::Constant(value),
+                        };
 
                         CExprKind::OffsetOf(ty, kind)
                     } else {
@@ -1779,6 +1818,74 @@ impl ConversionContext {
                     self.expr_possibly_as_stmt(expected_ty, new_id, node, e)
                 }
 
+                ASTEntryTag::TagCXXThisExpr if expected_ty & (EXPR | STMT) != 0 => {
+                    let ty_old = node.type_id.expect("Expected expression to have type");
+                    let ty = self.visit_qualified_type(ty_old);
+
+                    self.expr_possibly_as_stmt(expected_ty, new_id, node, CExprKind::This(ty));
+                }
+
+                ASTEntryTag::TagCXXMemberCallExpr if expected_ty & (EXPR | STMT) != 0 => {
+                    let method_old = node.children[0].expect("Expected member function");
+                    let method = self.visit_decl(method_old);
+
+                    let object_old = node.children[1].expect("Expected object of member call");
+                    let object = self.visit_expr(object_old);
+
+                    let args: Vec<CExprId> = node
+                        .children
+                        .iter()
+                        .skip(2)
+                        .map(|id| {
+                            let arg_id = id.expect("Expected call expression argument");
+                            self.visit_expr(arg_id)
+                        })
+                        .collect();
+
+                    let member_kind = if from_value(node.extras[0].clone()).expect("is arrow") {
+                        MemberKind::Arrow
+                    } else {
+                        MemberKind::Dot
+                    };
+
+                    let ty_old = node.type_id.expect("Expected expression to have type");
+                    let ty = self.visit_qualified_type(ty_old);
+
+                    let call = CExprKind::MemberCall(ty, method, object, member_kind, args);
+
+                    self.expr_possibly_as_stmt(expected_ty, new_id, node, call);
+                }
+
+                ASTEntryTag::TagCXXNewExpr if expected_ty & (EXPR | STMT) != 0 => {
+                    let count = node.children[0].map(|id| self.visit_expr(id));
+                    let init = node.children[1].map(|id| self.visit_expr(id));
+
+                    let allocated_old =
+                        from_value(node.extras[0].clone()).expect("Expected allocated type");
+                    let allocated = self.visit_qualified_type(allocated_old);
+
+                    let ty_old = node.type_id.expect("Expected expression to have type");
+                    let ty = self.visit_qualified_type(ty_old);
+
+                    let new = CExprKind::New(ty, allocated, count, init);
+
+                    self.expr_possibly_as_stmt(expected_ty, new_id, node, new);
+                }
+
+                ASTEntryTag::TagCXXDeleteExpr if expected_ty & (EXPR | STMT) != 0 => {
+                    let ptr_old = node.children[0].expect("Expected deleted pointer");
+                    let ptr = self.visit_expr(ptr_old);
+
+                    let is_array = from_value(node.extras[0].clone()).expect("is array delete");
+
+                    let ty_old = node.type_id.expect("Expected expression to have type");
+                    let ty = self.visit_qualified_type(ty_old);
+
+                    let delete = CExprKind::Delete(ty, ptr, is_array);
+
+                    self.expr_possibly_as_stmt(expected_ty, new_id, node, delete);
+                }
+
                 // Declarations
                 ASTEntryTag::TagFunctionDecl if expected_ty & OTHER_DECL != 0 => {
                     let name = from_value::<String>(node.extras[0].clone())
@@ -1804,6 +1911,15 @@ impl ConversionContext {
                     let attributes = from_value::<Vec<Value>>(node.extras[7].clone())
                         .expect("Expected to find attributes");
                     let attrs = parse_attributes(attributes);
+                    let link_name = expect_opt_str(&node.extras[8])
+                        .expect("Expected to find link name")
+                        .map(str::to_string);
+                    let record = expect_opt_u64(&node.extras[9])
+                        .expect("Expected to find member function class")
+                        .map(|id| CDeclId(self.visit_node_type(id, RECORD_DECL)));
+                    let this_type = expect_opt_u64(&node.extras[10])
+                        .expect("Expected to find type of this")
+                        .map(|ty| self.visit_qualified_type(ty));
 
                     // The always_inline attribute implies inline even if the
                     // inline keyword is not present.
@@ -1840,6 +1956,9 @@ impl ConversionContext {
                         name,
                         parameters,
                         typ,
+                        record,
+                        this_type,
+                        link_name,
                     };
 
                     self.add_decl(new_id, located(node, function_decl));
@@ -1927,6 +2046,9 @@ impl ConversionContext {
                         .expect("Expected to find whether decl is definition");
                     let attributes = from_value::<Vec<Value>>(node.extras[5].clone())
                         .expect("Expected attribute array on var decl");
+                    let link_name = expect_opt_str(&node.extras[6])
+                        .expect("Expected to find link name")
+                        .map(str::to_string);
 
                     assert!(
                         has_static_duration || has_thread_duration || !is_externally_visible,
@@ -1958,6 +2080,7 @@ impl ConversionContext {
                         initializer,
                         typ,
                         attrs,
+                        link_name,
                     };
 
                     self.add_decl(new_id, located(node, variable_decl));
@@ -2149,6 +2272,32 @@ impl ConversionContext {
                     self.add_decl(new_id, located(node, static_assert));
                 }
 
+                ASTEntryTag::TagNamespaceDecl if expected_ty & OTHER_DECL != 0 => {
+                    let mut path = from_value::<Vec<String>>(node.extras[0].clone())
+                        .expect("Expected to find namespace path");
+                    let name = path.pop().expect("Expected to find namespace name");
+
+                    let decls = node
+                        .children
+                        .iter()
+                        .map(|id| {
+                            let decl = id.expect("Namespace declaration not found");
+                            let id = self.visit_decl(decl);
+                            self.typed_context.namespaces.insert(id, CDeclId(new_id));
+                            id
+                        })
+                        .collect();
+
+                    let namespace = CDeclKind::Namespace { name, path, decls };
+
+                    self.add_decl(new_id, located(node, namespace));
+                    self.processed_nodes.insert(new_id, OTHER_DECL);
+
+                    // Like macros, namespaces aren't top-level decls in clang,
+                    // but they're translated like them.
+                    self.typed_context.c_decls_top.push(CDeclId(new_id));
+                }
+
                 t => panic!("Could not translate node {:?} as type {}", t, expected_ty),
             }
         }
@@ -42,7 +42,7 @@ fn immediate_expr_children(kind: &CExprKind) -> Vec<SomeId> {
         BadExpr => vec![],
         DesignatedInitExpr(..) => vec![], // the relevant information will be found in the semantic initializer
         ShuffleVector(..) | ConvertVector(..) => vec![],
-        OffsetOf(..) | Literal(..) | ImplicitValueInit(..) => vec![],
+        OffsetOf(..) | Literal(..) | ImplicitValueInit(..) | This(..) => vec![],
         DeclRef(..) => vec![], // don't follow references back!
         Unary(_, _, subexpr, _) | ConstantExpr(_, subexpr, _) => intos![subexpr],
         UnaryType(_ty, _op, opt_expr_id, _) => opt_expr_id.iter().map(|&x| x.into()).collect(),
@@ -79,6 +79,15 @@ fn immediate_expr_children(kind: &CExprKind) -> Vec<SomeId> {
         | Predefined(_, e)
         | VAArg(_, e) => intos![e],
         Statements(_, s) => vec![s.into()],
+        MemberCall(_, _, obj, _, ref args) => {
+            let mut res = intos![obj];
+            for &a in args {
+                res.push(a.into())
+            }
+            res
+        }
+        New(_, _, count, init) => [count, init].iter().flatten().map(|&x| x.into()).collect(),
+        Delete(_, e, _) => intos![e],
     }
 }
 
@@ -90,10 +99,11 @@ fn immediate_expr_children_all_types(kind: &CExprKind) -> Vec<SomeId> {
         ShuffleVector(_, ref kids) | ConvertVector(_, ref kids) => {
             kids.iter().map(|&x| x.into()).collect()
         }
-        // We need to iterate the struct type if this offsetof is variable,
+        // We need to iterate the struct type if this offsetof names its field,
         // since it may not get instantiated
-        OffsetOf(_, OffsetOfKind::Variable(qty, _, _)) => intos![qty.ctype],
-        OffsetOf(..) | Literal(..) | ImplicitValueInit(..) => vec![],
+        OffsetOf(_, OffsetOfKind::Variable(qty, _, _))
+        | OffsetOf(_, OffsetOfKind::Field(_, qty, _)) => intos![qty.ctype],
+        OffsetOf(..) | Literal(..) | ImplicitValueInit(..) | This(..) => vec![],
         DeclRef(..) => vec![], // don't follow references back!
         Unary(_, _, subexpr, _) | ConstantExpr(_, subexpr, _) => intos![subexpr],
         UnaryType(_ty, _op, opt_expr_id, qty) => {
@@ -140,6 +150,24 @@ fn immediate_expr_children_all_types(kind: &CExprKind) -> Vec<SomeId> {
             intos![qty.ctype, e]
         }
         Statements(_, s) => vec![s.into()],
+        MemberCall(_, _, obj, _, ref args) => {
+            let mut res = intos![obj];
+            for &a in args {
+                res.push(a.into())
+            }
+            res
+        }
+        New(_, allocated, count, init) => {
+            let mut res = intos![allocated.ctype];
+            res.extend(
+                [count, init]
+                    .iter()
+                    .flatten()
+                    .map(|&x| -> SomeId { x.into() }),
+            );
+            res
+        }
+        Delete(_, e, _) => intos![e],
     }
 }
 
@@ -184,6 +212,7 @@ fn immediate_decl_children(kind: &CDeclKind) -> Vec<SomeId> {
         Field { typ, .. } => intos![typ.ctype],
         MacroObject { .. } | MacroFunction { .. } => vec![],
         NonCanonicalDecl { canonical_decl } => intos![canonical_decl],
+        Namespace { ref decls, .. } => decls.iter().map(|&x| x.into()).collect(),
         StaticAssert {
             assert_expr,
             message,
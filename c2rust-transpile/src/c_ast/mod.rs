@@ -56,6 +56,7 @@ pub struct TypedAstContext {
     pub c_decls_top: Vec<CDeclId>,
     pub c_main: Option<CDeclId>,
     pub parents: HashMap<CDeclId, CDeclId>, // record fields and enum constants
+    pub namespaces: HashMap<CDeclId, CDeclId>, // C++ declarations in named namespaces
 
     // Mapping from FileId to SrcFile. Deduplicated by file path.
     files: Vec<SrcFile>,
@@ -178,6 +179,7 @@ impl TypedAstContext {
             file_map,
             include_map,
             parents: HashMap::new(),
+            namespaces: HashMap::new(),
             macro_invocations: HashMap::new(),
             macro_expansions: HashMap::new(),
             macro_expansion_text: HashMap::new(),
@@ -244,6 +246,14 @@ impl TypedAstContext {
             .position(|f| f.path.as_ref().map_or(false, |p| p == path))
     }
 
+    /// Whether `located` is in the main file of the translation unit rather than in a file it
+    /// includes.
+    pub fn is_in_main_file<T>(&self, located: &Located<T>) -> bool {
+        self.file_id(located).map_or(false, |id| {
+            self.files[id].path.is_some() && self.include_map[id].is_empty()
+        })
+    }
+
     pub fn file_id<T>(&self, located: &Located<T>) -> Option<FileId> {
         located
             .loc
@@ -470,6 +480,27 @@ impl TypedAstContext {
         self.resolve_type_id(ty)
     }
 
+    /// Check if `qtype` is `const`-qualified, either directly or through type sugar like a
+    /// typedef of a `const` type (`typedef const char cchar;`), whose qualifiers `resolve_type`
+    /// drops.
+    pub fn is_const_qualified(&self, qtype: CQualTypeId) -> bool {
+        use CTypeKind::*;
+        if qtype.qualifiers.is_const {
+            return true;
+        }
+        match self.index(qtype.ctype).kind {
+            Attributed(ty, _) => self.is_const_qualified(ty),
+            Elaborated(ty) | TypeOf(ty) | Paren(ty) => {
+                self.is_const_qualified(CQualTypeId::new(ty))
+            }
+            Typedef(decl) => match self.index(decl).kind {
+                CDeclKind::Typedef { typ, .. } => self.is_const_qualified(typ),
+                _ => panic!("Typedef decl did not point to a typedef"),
+            },
+            _ => false,
+        }
+    }
+
     pub fn resolve_type(&self, typ: CTypeId) -> &CType {
         let resolved_typ_id = self.resolve_type_id(typ);
         self.index(resolved_typ_id)
@@ -495,12 +526,16 @@ impl TypedAstContext {
             Predefined(..) |
             Statements(..) | // TODO: more precision
             VAArg(..) |
-            Atomic{..} => false,
+            Atomic{..} |
+            MemberCall(..) |
+            New(..) |
+            Delete(..) => false,
 
             Literal(_, _) |
             DeclRef(_, _, _) |
             UnaryType(_, _, _, _) |
             OffsetOf(..) |
+            This(..) |
             ConstantExpr(..) => true,
 
             DesignatedInitExpr(_,_,e) |
@@ -544,7 +579,58 @@ impl TypedAstContext {
         }
     }
 
-    pub fn prune_unwanted_decls(&mut self, want_unused_functions: bool) {
+    /// Remove the statements and expressions of the body of the function `decl_id`, once it's
+    /// translated, to bound the memory used by large translation units.  The types and the
+    /// declarations of the body are kept, since they may be shared with other declarations.
+    pub fn release_function_body(&mut self, decl_id: CDeclId) {
+        let body = match self.c_decls.get(&decl_id).map(|decl| &decl.kind) {
+            Some(&CDeclKind::Function {
+                body: Some(body), ..
+            }) => body,
+            _ => return,
+        };
+
+        let mut to_walk = vec![SomeId::Stmt(body)];
+        let mut released = vec![];
+        while let Some(id) = to_walk.pop() {
+            let exists = match id {
+                SomeId::Stmt(stmt_id) => self.c_stmts.contains_key(&stmt_id),
+                SomeId::Expr(expr_id) => self.c_exprs.contains_key(&expr_id),
+                // Only local variables and static assertions have expressions of their own;
+                // other declarations, like local redeclarations of functions, may refer to
+                // declarations outside of the body.
+                SomeId::Decl(decl_id) => matches!(
+                    self.c_decls.get(&decl_id).map(|decl| &decl.kind),
+                    Some(CDeclKind::Variable { .. } | CDeclKind::StaticAssert { .. })
+                ),
+                SomeId::Type(_) => false,
+            };
+            if exists {
+                to_walk.extend(iterators::immediate_children_all_types(self, id));
+                released.push(id);
+            }
+        }
+
+        for id in released {
+            match id {
+                SomeId::Stmt(stmt_id) => {
+                    self.c_stmts.remove(&stmt_id);
+                }
+                SomeId::Expr(expr_id) => {
+                    self.c_exprs.remove(&expr_id);
+                    self.macro_invocations.remove(&expr_id);
+                    self.macro_expansion_text.remove(&expr_id);
+                }
+                SomeId::Decl(_) | SomeId::Type(_) => {}
+            }
+        }
+    }
+
+    pub fn prune_unwanted_decls(
+        &mut self,
+        want_unused_functions: bool,
+        want_main_file_decls: bool,
+    ) {
         // Starting from a set of root declarations, walk each one to find declarations it
         // depends on. Then walk each of those, recursively.
 
@@ -556,11 +642,13 @@ impl TypedAstContext {
         // Mark all the roots as wanted.  Roots are all top-level functions and variables that might
         // be visible from another compilation unit.
         //
-        // In addition, mark any other (unused) function wanted if configured.
+        // In addition, mark any other (unused) function wanted if configured, and every
+        // declaration of the main file if it's a header translated on its own.
         for &decl_id in &self.c_decls_top {
             let decl = self.index(decl_id);
             use CDeclKind::*;
             let is_wanted = match decl.kind {
+                _ if want_main_file_decls && self.is_in_main_file(decl) => true,
                 Function {
                     body: Some(_),
                     is_global: true,
@@ -624,7 +712,9 @@ impl TypedAstContext {
                                 }
                             }
                         }
-                        if let CExprKind::DeclRef(_, decl_id, _) = &expr.kind {
+                        if let CExprKind::DeclRef(_, decl_id, _)
+                        | CExprKind::MemberCall(_, decl_id, _, _, _) = &expr.kind
+                        {
                             if wanted.insert(*decl_id) {
                                 to_walk.push(*decl_id);
                             }
@@ -653,6 +743,13 @@ impl TypedAstContext {
             }
         }
 
+        // Keep the C++ namespaces, which the wanted declarations in them are translated in
+        for (&decl_id, decl) in &self.c_decls {
+            if let CDeclKind::Namespace { .. } = decl.kind {
+                wanted.insert(decl_id);
+            }
+        }
+
         // Unset c_main if we are not retaining its declaration
         if let Some(main_id) = self.c_main {
             if !wanted.contains(&main_id) {
@@ -903,6 +1000,12 @@ pub enum CDeclKind {
         parameters: Vec<CParamId>,
         body: Option<CStmtId>,
         attrs: IndexSet<Attribute>,
+        // The class of C++ member functions
+        record: Option<CRecordId>,
+        // The type of `this` in non-static C++ member functions
+        this_type: Option<CQualTypeId>,
+        // The mangled name of C++ functions
+        link_name: Option<String>,
     },
 
     // http://clang.llvm.org/doxygen/classclang_1_1VarDecl.html
@@ -915,6 +1018,8 @@ pub enum CDeclKind {
         initializer: Option<CExprId>,
         typ: CQualTypeId,
         attrs: IndexSet<Attribute>,
+        // The mangled name of C++ variables
+        link_name: Option<String>,
     },
 
     // Enum (http://clang.llvm.org/doxygen/classclang_1_1EnumDecl.html)
@@ -981,6 +1086,13 @@ pub enum CDeclKind {
         assert_expr: CExprId,
         message: Option<CExprId>,
     },
+
+    // C++ namespace, with the names of its enclosing namespaces in `path`
+    Namespace {
+        name: String,
+        path: Vec<String>,
+        decls: Vec<CDeclId>,
+    },
 }
 
 impl CDeclKind {
@@ -996,6 +1108,7 @@ impl CDeclKind {
             Union { name: Some(i), .. } => i,
             Field { name: i, .. } => i,
             MacroObject { name, .. } => name,
+            Namespace { name, .. } => name,
             _ => return None,
         })
     }
@@ -1006,6 +1119,10 @@ impl CDeclKind {
 pub enum OffsetOfKind {
     /// An Integer Constant Expr
     Constant(u64),
+    /// An Integer Constant Expr that is the offset of a field, or of a field
+    /// of a nested struct member, which can be an offset_of! macro invocation
+    /// Value, Struct Type, Field Decl Ids from outermost to innermost
+    Field(u64, CQualTypeId, Vec<CDeclId>),
     /// Contains more information to generate
     /// an offset_of! macro invocation
     /// Struct Type, Field Decl Id, Index Expr
@@ -1115,6 +1232,18 @@ pub enum CExprKind {
         weak: Option<CExprId>,
     },
 
+    // C++ `this`
+    This(CQualTypeId),
+
+    // Call of a non-static C++ member function on an object: member function, object, arguments
+    MemberCall(CQualTypeId, CDeclId, CExprId, MemberKind, Vec<CExprId>),
+
+    // C++ `new`: allocated type, element count of array `new`s, initializer
+    New(CQualTypeId, CQualTypeId, Option<CExprId>, Option<CExprId>),
+
+    // C++ `delete`, and whether it's `delete[]`
+    Delete(CQualTypeId, CExprId, bool),
+
     BadExpr,
 }
 
@@ -1163,7 +1292,11 @@ impl CExprKind {
             | CExprKind::ShuffleVector(ty, _)
             | CExprKind::ConvertVector(ty, _)
             | CExprKind::DesignatedInitExpr(ty, _, _)
-            | CExprKind::ConstantExpr(ty, _, _) => Some(ty),
+            | CExprKind::ConstantExpr(ty, _, _)
+            | CExprKind::This(ty)
+            | CExprKind::MemberCall(ty, _, _, _, _)
+            | CExprKind::New(ty, _, _, _)
+            | CExprKind::Delete(ty, _, _) => Some(ty),
             CExprKind::Choose(ty, _, _, _, _) | CExprKind::Atomic { typ: ty, .. } => Some(ty),
         }
     }
@@ -1727,6 +1860,8 @@ pub enum Attribute {
     Alias(String),
     /// __attribute__((always_inline, __always_inline__))
     AlwaysInline,
+    /// __attribute__((annotate("foo")))
+    Annotate(String),
     /// __attribute__((cold, __cold__))
     Cold,
     /// __attribute__((gnu_inline, __gnu_inline__))
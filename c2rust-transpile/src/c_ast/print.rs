@@ -116,6 +116,18 @@ impl<W: Write> Printer<W> {
                     Constant(val) => {
                         self.writer.write_fmt(format_args!("{}", val))?;
                     }
+                    Field(_, qty, decl_ids) => {
+                        self.writer.write_all(b"offsetof(")?;
+                        self.print_qtype(*qty, None, context)?;
+                        self.writer.write_all(b", ")?;
+                        for (i, decl_id) in decl_ids.iter().enumerate() {
+                            if i > 0 {
+                                self.writer.write_all(b".")?;
+                            }
+                            self.print_decl_name(*decl_id, context)?;
+                        }
+                        self.writer.write_all(b")")?;
+                    }
                     Variable(qty, decl_id, expr_id) => {
                         self.writer.write_all(b"offset_of!(")?;
                         self.print_qtype(*qty, None, context)?;
@@ -282,6 +294,55 @@ impl<W: Write> Printer<W> {
                 }
 
                 self.writer.write_all(b")")?;
+            }
+
+            This(..) => {
+                self.writer.write_all(b"this")?;
+            }
+
+            &MemberCall(_, method, obj, kind, ref args) => {
+                self.print_expr(obj, context)?;
+                self.writer.write_all(match kind {
+                    MemberKind::Arrow => b"->".as_ref(),
+                    MemberKind::Dot => b".".as_ref(),
+                })?;
+                self.print_decl_name(method, context)?;
+                self.writer.write_all(b"(")?;
+
+                let mut first: bool = true;
+                for arg in args {
+                    if !first {
+                        self.writer.write_all(b", ")?;
+                    }
+                    first = false;
+                    self.print_expr(*arg, context)?;
+                }
+
+                self.writer.write_all(b")")?;
+            }
+
+            &New(_, allocated, count, init) => {
+                self.writer.write_all(b"new ")?;
+                self.print_qtype(allocated, None, context)?;
+                if let Some(count) = count {
+                    self.writer.write_all(b"[")?;
+                    self.print_expr(count, context)?;
+                    self.writer.write_all(b"]")?;
+                }
+                if let Some(init) = init {
+                    self.writer.write_all(b"(")?;
+                    self.print_expr(init, context)?;
+                    self.writer.write_all(b")")?;
+                }
+            }
+
+            &Delete(_, ptr, is_array) => {
+                self.writer.write_all(if is_array {
+                    b"delete[] ".as_ref()
+                } else {
+                    b"delete ".as_ref()
+                })?;
+                self.print_expr(ptr, context)?;
             } // _ => unimplemented!("Printer::print_expr"),
         };
         Ok(())
@@ -668,6 +729,22 @@ impl<W: Write> Printer<W> {
 
             StaticAssert { .. } => {
                 self.writer.write_fmt(format_args!("static_assert(...)"))?;
+            }
+
+            Namespace { name, decls, .. } => {
+                self.writer
+                    .write_fmt(format_args!("namespace {} {{\n", name))?;
+                self.indent();
+                for decl in decls {
+                    self.pad()?;
+                    self.print_decl(*decl, true, true, context)?;
+                }
+                self.dedent();
+                self.pad()?;
+                self.writer.write_all(b"}")?;
+                if newline {
+                    self.writer.write_all(b"\n")?;
+                }
             } // _ => unimplemented!("Printer::print_decl"),
         };
 
@@ -0,0 +1,315 @@
+//! C files kept in C, for partial migrations.
+//!
+//! With `keep_c`, the C files it matches aren't translated.  Instead, the generated `build.rs`
+//! compiles them with the `cc` crate and links them into the crate, and their modules only
+//! declare what the C files define: their types, and `extern "C"` declarations of their
+//! exported functions and variables, so that the translated code can keep using them.
+//!
+//! When only some functions are translated, every C file is compiled, and the C definitions of
+//! the translated functions are made weak, so that they're overridden by their translations.
+
+use std::path::Path;
+
+use pathdiff::diff_paths;
+use serde_derive::Serialize;
+use syn::{
+    Attribute, FnArg, ForeignItem, ForeignItemFn, ForeignItemStatic, Item, Lit, Meta,
+    MetaNameValue, Pat,
+};
+
+use crate::compile_cmds::CompileCmd;
+use c2rust_ast_printer::pprust;
+
+/// A C file compiled by `build.rs`, with the arguments to `cc::Build` as Rust expressions.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct CSource {
+    pub file: String,
+    pub includes: Vec<String>,
+    pub defines: Vec<String>,
+    pub flags: Vec<String>,
+    /// The name of the static library the file is compiled into.
+    pub lib_name: String,
+    /// The functions whose definitions in the file are made weak.
+    pub weak_functions: Vec<String>,
+}
+
+impl CSource {
+    /// Get the `cc::Build` arguments for compiling `cmd` from the crate in `build_dir`, as its
+    /// `idx`th C file.  Only include paths, macro definitions and language and code generation
+    /// options (`-std`, `-f` and `-m`) are kept from the compile command.
+    pub fn new(cmd: &CompileCmd, build_dir: &Path, idx: usize) -> CSource {
+        let relative = |path: &str| {
+            let path = cmd.directory.join(path);
+            let path = diff_paths(&path, build_dir).unwrap_or(path);
+            format!("{:?}", path.display().to_string())
+        };
+
+        let mut includes = vec![];
+        let mut defines = vec![];
+        let mut flags = vec![];
+        let mut args = cmd.args().into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            // Flags that take a value take it either in the same argument or in the next one.
+            let mut value = |flag: &str| match &arg[flag.len()..] {
+                "" => args.next().unwrap_or_default(),
+                value => value.to_owned(),
+            };
+            if arg.starts_with("-I") {
+                includes.push(relative(&value("-I")));
+            } else if arg == "-isystem" {
+                includes.push(relative(&value("-isystem")));
+            } else if arg.starts_with("-D") {
+                let define = value("-D");
+                let (name, value) = match define.split_once('=') {
+                    Some((name, value)) => (name, format!("Some({:?})", value)),
+                    None => (define.as_str(), "None".to_owned()),
+                };
+                defines.push(format!("{:?}, {}", name, value));
+            } else if arg.starts_with("-std=") || arg.starts_with("-f") || arg.starts_with("-m") {
+                flags.push(format!("{:?}", arg));
+            }
+        }
+
+        let file = cmd.abs_file();
+        let stem = file.file_stem().unwrap().to_string_lossy();
+        CSource {
+            file: relative(file.to_str().unwrap()),
+            includes,
+            defines,
+            flags,
+            lib_name: format!("{:?}", format!("{}_{}", crate::str_to_ident(stem), idx)),
+            weak_functions: vec![],
+        }
+    }
+
+    /// Make the definitions of `functions` in the file weak.
+    pub fn weaken(&mut self, functions: &[String]) {
+        self.weak_functions
+            .extend(functions.iter().map(|name| format!("{:?}", name)));
+    }
+}
+
+/// The attributes to keep on the declaration of an item defined in C, or `None` if the item
+/// isn't exported: its docs, and its `export_name` as its `link_name`.
+fn exported_attrs(attrs: &[Attribute]) -> Option<Vec<Attribute>> {
+    let mut exported = false;
+    let mut kept = vec![];
+    for attr in attrs {
+        if attr.path.is_ident("no_mangle") {
+            exported = true;
+        } else if attr.path.is_ident("export_name") {
+            exported = true;
+            let mut attr = attr.clone();
+            attr.path = syn::parse_str("link_name").unwrap();
+            kept.push(attr);
+        } else if attr.path.is_ident("doc") {
+            kept.push(attr.clone());
+        }
+    }
+    if exported {
+        Some(kept)
+    } else {
+        None
+    }
+}
+
+/// The link names of the functions a translation exports, and `main` if it defines one.
+pub fn exported_functions(translation: &str) -> Result<Vec<String>, syn::Error> {
+    let file = syn::parse_file(translation)?;
+    let mut functions = vec![];
+    for item in file.items {
+        let f = match item {
+            Item::Fn(f) => f,
+            _ => continue,
+        };
+        let export_name = f.attrs.iter().find_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(MetaNameValue {
+                ref path,
+                lit: Lit::Str(ref name),
+                ..
+            })) if path.is_ident("export_name") => Some(name.value()),
+            _ => None,
+        });
+        if let Some(name) = export_name {
+            functions.push(name);
+        } else if f.sig.ident == "main"
+            || f.attrs.iter().any(|attr| attr.path.is_ident("no_mangle"))
+        {
+            functions.push(f.sig.ident.to_string());
+        }
+    }
+    Ok(functions)
+}
+
+/// Reduce the translation of a C file kept in C to its declarations: the items defining types
+/// and constants, and an `extern "C"` block declaring its exported functions and variables.
+pub fn extern_declarations(translation: &str) -> Result<String, syn::Error> {
+    let file = syn::parse_file(translation)?;
+    let mut items = vec![];
+    let mut foreign_items = vec![];
+    for item in file.items {
+        match item {
+            Item::Use(_)
+            | Item::Struct(_)
+            | Item::Union(_)
+            | Item::Enum(_)
+            | Item::Type(_)
+            | Item::Const(_) => items.push(item),
+            Item::Fn(f) => {
+                let attrs = match exported_attrs(&f.attrs) {
+                    Some(attrs) => attrs,
+                    None => continue,
+                };
+                let mut sig = f.sig;
+                sig.constness = None;
+                sig.unsafety = None;
+                sig.abi = None;
+                // Foreign functions can't have patterns, like `mut x`, as parameters.
+                for input in &mut sig.inputs {
+                    if let FnArg::Typed(arg) = input {
+                        if let Pat::Ident(ref mut ident) = *arg.pat {
+                            ident.by_ref = None;
+                            ident.mutability = None;
+                        }
+                    }
+                }
+                foreign_items.push(ForeignItem::Fn(ForeignItemFn {
+                    attrs,
+                    vis: f.vis,
+                    sig,
+                    semi_token: Default::default(),
+                }));
+            }
+            Item::Static(s) => {
+                let attrs = match exported_attrs(&s.attrs) {
+                    Some(attrs) => attrs,
+                    None => continue,
+                };
+                foreign_items.push(ForeignItem::Static(ForeignItemStatic {
+                    attrs,
+                    vis: s.vis,
+                    static_token: s.static_token,
+                    mutability: s.mutability,
+                    ident: s.ident,
+                    colon_token: s.colon_token,
+                    ty: s.ty,
+                    semi_token: s.semi_token,
+                }));
+            }
+            _ => {}
+        }
+    }
+    if !foreign_items.is_empty() {
+        items.push(Item::ForeignMod(syn::ItemForeignMod {
+            attrs: vec![],
+            abi: syn::parse_str("extern \"C\"")?,
+            brace_token: Default::default(),
+            items: foreign_items,
+        }));
+    }
+
+    Ok(pprust::to_string(|| syn::File {
+        shebang: None,
+        attrs: file.attrs,
+        items,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_compile_commands() {
+        let cmd = CompileCmd {
+            directory: "/proj/build".into(),
+            file: "/proj/src/io-util.c".into(),
+            arguments: [
+                "cc",
+                "-I../include",
+                "-D",
+                "HAVE_ZLIB",
+                "-DVERSION=\"1.0\"",
+                "-O2",
+                "-std=gnu99",
+                "-c",
+                "../src/io-util.c",
+            ]
+            .map(String::from)
+            .to_vec(),
+            ..CompileCmd::default()
+        };
+        assert_eq!(
+            CSource::new(&cmd, Path::new("/proj/out"), 2),
+            CSource {
+                file: "\"../src/io-util.c\"".to_owned(),
+                includes: vec!["\"../build/../include\"".to_owned()],
+                defines: vec![
+                    "\"HAVE_ZLIB\", None".to_owned(),
+                    "\"VERSION\", Some(\"\\\"1.0\\\"\")".to_owned(),
+                ],
+                flags: vec!["\"-std=gnu99\"".to_owned()],
+                lib_name: "\"io_util_2\"".to_owned(),
+                weak_functions: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn finds_exported_functions() {
+        let translation = "\
+unsafe extern \"C\" fn square(mut x: libc::c_int) -> libc::c_int {
+    x * x
+}
+#[no_mangle]
+pub unsafe extern \"C\" fn dist2(mut p: point) -> libc::c_int {
+    square(p.x) + square(p.y)
+}
+#[export_name = \"type\"]
+pub unsafe extern \"C\" fn type_0() {}
+pub fn main() {}
+";
+        assert_eq!(
+            exported_functions(translation).unwrap(),
+            ["dist2", "type", "main"]
+        );
+    }
+
+    #[test]
+    fn keeps_declarations() {
+        let translation = "\
+use ::libc;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+#[no_mangle]
+pub static mut origin: point = point { x: 0, y: 0 };
+unsafe extern \"C\" fn square(mut x: libc::c_int) -> libc::c_int {
+    x * x
+}
+#[no_mangle]
+pub unsafe extern \"C\" fn dist2(mut p: point) -> libc::c_int {
+    square(p.x) + square(p.y)
+}
+";
+        assert_eq!(
+            extern_declarations(translation).unwrap(),
+            "\
+use ::libc;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct point {
+    pub x: libc::c_int,
+    pub y: libc::c_int,
+}
+extern \"C\" {
+    pub static mut origin: point;
+    pub fn dist2(p: point) -> libc::c_int;
+}
+"
+        );
+    }
+}
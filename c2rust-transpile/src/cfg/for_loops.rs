@@ -0,0 +1,342 @@
+//! This module recovers Rust `for` loops over ranges from the `while` loops that counted C `for`
+//! loops are translated into.  A loop such as `for (int i = 0; i < n; i++) { ... }` comes out of
+//! the relooper as
+//!
+//! ```ignore
+//! let mut i: libc::c_int = 0 as libc::c_int;
+//! while i < n {
+//!     ...
+//!     i += 1;
+//! }
+//! ```
+//!
+//! and is rewritten into `for i in 0 as libc::c_int..n { ... }`, or
+//! `for i in (start..end).step_by(k) { ... }` for other constant steps.  This is only done when
+//! the two loops are guaranteed to behave the same:
+//!
+//!   * the counter is declared right before the loop, and isn't used after it,
+//!   * the loop body reads the counter, but never writes or borrows it, and doesn't `continue`
+//!     the loop (which would skip the increment),
+//!   * the bound is built from literals and locals, with no calls or dereferences, and the loop
+//!     body doesn't write any of those locals, which aren't borrowed anywhere in the function,
+//!   * the counter can't overflow: `<=` is only used with a literal bound and a signed counter (so
+//!     that the C loop can't run forever at the maximum value), and unsigned counters, which are
+//!     incremented with `wrapping_add`, are only stepped by one.
+use super::*;
+use proc_macro2::TokenTree;
+use std::collections::HashSet;
+use std::iter;
+use syn::{
+    BinOp, Block, ExprAssign, ExprAssignOp, ExprBinary, ExprForLoop, ExprMethodCall, ExprRange,
+    ExprWhile, Lit, Local, PatIdent, RangeLimits, UnOp,
+};
+
+use crate::rust_ast::traverse::{self, Traversal};
+
+/// What a piece of code does with the variables it mentions.
+#[derive(Default)]
+struct Accesses {
+    /// Variables that are mentioned at all.
+    used: HashSet<String>,
+    /// Variables that are assigned, in whole or in part.
+    written: HashSet<String>,
+    /// Variables that are borrowed, or mentioned in a macro.
+    borrowed: HashSet<String>,
+    /// Variables declared with `let`.
+    declared: HashSet<String>,
+    /// Whether there are calls, macros, or dereferences, which could read anything.
+    impure: bool,
+    /// The labels of `continue`s of the outermost loop, with `None` for unlabeled ones.
+    continues: Vec<Option<String>>,
+    loop_depth: usize,
+}
+
+impl Accesses {
+    fn of_stmts(stmts: &[Stmt]) -> Accesses {
+        let mut acc = Accesses::default();
+        for stmt in stmts {
+            acc.traverse_stmt(stmt.clone());
+        }
+        acc
+    }
+
+    fn of_expr(expr: &Expr) -> Accesses {
+        let mut acc = Accesses::default();
+        acc.traverse_expr(expr.clone());
+        acc
+    }
+}
+
+/// The variable that a place expression like `x`, `x.f` or `x[i]` is part of.
+fn root_var(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Path(e) if e.qself.is_none() => e.path.get_ident().map(|id| id.to_string()),
+        Expr::Field(e) => root_var(&e.base),
+        Expr::Index(e) => root_var(&e.expr),
+        Expr::Paren(e) => root_var(&e.expr),
+        _ => None,
+    }
+}
+
+impl Traversal for Accesses {
+    fn traverse_expr(&mut self, e: Expr) -> Expr {
+        match &e {
+            Expr::Path(_) => self.used.extend(root_var(&e)),
+            Expr::Assign(ExprAssign { left, .. }) | Expr::AssignOp(ExprAssignOp { left, .. }) => {
+                self.written.extend(root_var(left))
+            }
+            Expr::Reference(r) => self.borrowed.extend(root_var(&r.expr)),
+            Expr::MethodCall(m) => {
+                // `wrapping_*` methods on integers take `self` by value; other methods may
+                // borrow their receiver.
+                if !m.method.to_string().starts_with("wrapping_") {
+                    self.borrowed.extend(root_var(&m.receiver));
+                    self.impure = true;
+                }
+            }
+            Expr::Call(_) | Expr::Closure(_) => self.impure = true,
+            Expr::Unary(u) if matches!(u.op, UnOp::Deref(_)) => self.impure = true,
+            Expr::Macro(m) => {
+                self.impure = true;
+                let idents = m.mac.tokens.clone().into_iter().filter_map(|tt| match tt {
+                    TokenTree::Ident(id) => Some(id.to_string()),
+                    _ => None,
+                });
+                for id in idents {
+                    self.used.insert(id.clone());
+                    self.borrowed.insert(id);
+                }
+            }
+            Expr::Continue(c) => match c.label {
+                Some(ref label) => self.continues.push(Some(label.ident.to_string())),
+                None if self.loop_depth == 0 => self.continues.push(None),
+                None => {}
+            },
+            _ => {}
+        }
+
+        let is_loop = matches!(e, Expr::While(_) | Expr::Loop(_) | Expr::ForLoop(_));
+        if is_loop {
+            self.loop_depth += 1;
+        }
+        let e = traverse::traverse_expr_def(self, e);
+        if is_loop {
+            self.loop_depth -= 1;
+        }
+        e
+    }
+
+    fn traverse_local(&mut self, l: Local) -> Local {
+        self.declared
+            .extend(local_ident(&l).map(|p| p.ident.to_string()));
+        traverse::traverse_local_def(self, l)
+    }
+}
+
+fn is_var(expr: &Expr, var: &str) -> bool {
+    matches!(expr, Expr::Path(e) if e.qself.is_none() && e.path.is_ident(var))
+}
+
+/// The value of a positive integer literal, possibly with casts.
+fn positive_int_lit(expr: &Expr) -> Option<u64> {
+    match expr {
+        Expr::Cast(e) => positive_int_lit(&e.expr),
+        Expr::Paren(e) => positive_int_lit(&e.expr),
+        Expr::Lit(e) => match e.lit {
+            Lit::Int(ref i) => i.base10_parse().ok().filter(|&i| i > 0),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The step of an increment of `var`: `var += k`, `var = var + k` or
+/// `var = var.wrapping_add(k)`, and whether it wraps around.
+fn increment_step(stmt: &Stmt, var: &str) -> Option<(u64, bool)> {
+    let expr = match stmt {
+        Stmt::Expr(e) | Stmt::Semi(e, _) => e,
+        _ => return None,
+    };
+    let (step, wrapping) = match expr {
+        Expr::AssignOp(ExprAssignOp {
+            left,
+            op: BinOp::AddEq(_),
+            right,
+            ..
+        }) if is_var(left, var) => (&**right, false),
+        Expr::Assign(ExprAssign { left, right, .. }) if is_var(left, var) => match &**right {
+            Expr::Binary(ExprBinary {
+                left,
+                op: BinOp::Add(_),
+                right,
+                ..
+            }) if is_var(left, var) => (&**right, false),
+            Expr::MethodCall(ExprMethodCall {
+                receiver,
+                method,
+                args,
+                ..
+            }) if is_var(receiver, var) && method == "wrapping_add" && args.len() == 1 => {
+                (&args[0], true)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((positive_int_lit(step)?, wrapping))
+}
+
+/// The variable declared by `let var` or `let var: T`.
+fn local_ident(local: &Local) -> Option<&PatIdent> {
+    let pat = match &local.pat {
+        Pat::Type(p) => &*p.pat,
+        pat => pat,
+    };
+    match pat {
+        Pat::Ident(p) if p.by_ref.is_none() && p.subpat.is_none() => Some(p),
+        _ => None,
+    }
+}
+
+/// The counter variable and its initial value, for `let mut var: T = start;`.
+fn counter_decl(stmt: &Stmt) -> Option<(String, Expr)> {
+    let local = match stmt {
+        Stmt::Local(l) => l,
+        _ => return None,
+    };
+    let var = local_ident(local).filter(|p| p.mutability.is_some())?;
+    let (_, init) = local.init.as_ref()?;
+    Some((var.ident.to_string(), (**init).clone()))
+}
+
+/// Try to turn `decl` followed by `while_loop` into a `for` loop.  `rest` are the statements
+/// after the loop, `locals` are the local variables of the function, and `borrowed` are the ones
+/// that are borrowed anywhere in it.
+fn for_loop(
+    decl: &Stmt,
+    while_loop: &ExprWhile,
+    rest: &[Stmt],
+    locals: &HashSet<String>,
+    borrowed: &HashSet<String>,
+) -> Option<Expr> {
+    let (var, start) = counter_decl(decl)?;
+    let (inclusive, end) = match &*while_loop.cond {
+        Expr::Binary(ExprBinary {
+            left, op, right, ..
+        }) if is_var(left, &var) => match op {
+            BinOp::Lt(_) => (false, right),
+            BinOp::Le(_) if positive_int_lit(right).is_some() => (true, right),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let (incr, body) = while_loop.body.stmts.split_last()?;
+    let (step, wrapping) = increment_step(incr, &var)?;
+    if wrapping && (inclusive || step != 1) {
+        return None;
+    }
+
+    let body_acc = Accesses::of_stmts(body);
+    let end_acc = Accesses::of_expr(end);
+    let label = while_loop.label.as_ref().map(|l| l.name.ident.to_string());
+    let continues_loop = body_acc
+        .continues
+        .iter()
+        .any(|c| c.is_none() || *c == label);
+    let bound_is_invariant = !end_acc.impure
+        && end_acc.used.iter().all(|v| {
+            *v != var
+                && locals.contains(v)
+                && !borrowed.contains(v)
+                && !body_acc.written.contains(v)
+        });
+    if continues_loop
+        || !bound_is_invariant
+        || borrowed.contains(&var)
+        || body_acc.written.contains(&var)
+        || body_acc.declared.contains(&var)
+        || Accesses::of_stmts(rest).used.contains(&var)
+    {
+        return None;
+    }
+
+    let range = Expr::Range(ExprRange {
+        attrs: vec![],
+        from: Some(Box::new(start)),
+        limits: if inclusive {
+            RangeLimits::Closed(Default::default())
+        } else {
+            RangeLimits::HalfOpen(Default::default())
+        },
+        to: Some(end.clone()),
+    });
+    let range = if step == 1 {
+        Box::new(range)
+    } else {
+        mk().method_call_expr(
+            mk().paren_expr(Box::new(range)),
+            "step_by",
+            vec![mk().lit_expr(mk().int_unsuffixed_lit(step as u128))],
+        )
+    };
+
+    Some(Expr::ForLoop(ExprForLoop {
+        attrs: while_loop.attrs.clone(),
+        label: while_loop.label.clone(),
+        for_token: Default::default(),
+        pat: mk().ident_pat(var),
+        in_token: Default::default(),
+        expr: range,
+        body: Block {
+            stmts: body.to_vec(),
+            ..while_loop.body.clone()
+        },
+    }))
+}
+
+struct ForLoops {
+    locals: HashSet<String>,
+    borrowed: HashSet<String>,
+}
+
+impl ForLoops {
+    fn rewrite_stmts(&self, mut stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut i = 0;
+        while i + 1 < stmts.len() {
+            let new_loop = match &stmts[i + 1] {
+                Stmt::Expr(Expr::While(w)) | Stmt::Semi(Expr::While(w), _) => {
+                    for_loop(&stmts[i], w, &stmts[i + 2..], &self.locals, &self.borrowed)
+                }
+                _ => None,
+            };
+            if let Some(e) = new_loop {
+                stmts.splice(i..i + 2, iter::once(mk().expr_stmt(Box::new(e))));
+            }
+            i += 1;
+        }
+        stmts
+    }
+}
+
+impl Traversal for ForLoops {
+    fn traverse_block(&mut self, b: Block) -> Block {
+        let mut b = traverse::traverse_block_def(self, b);
+        b.stmts = self.rewrite_stmts(b.stmts);
+        b
+    }
+}
+
+/// Turn the counted `while` loops in the body of a function into `for` loops over ranges.
+/// `params` are the names of the function's parameters.
+pub fn reconstruct_for_loops(stmts: Vec<Stmt>, params: HashSet<String>) -> Vec<Stmt> {
+    let acc = Accesses::of_stmts(&stmts);
+    let mut locals = params;
+    locals.extend(acc.declared);
+    let mut walk = ForLoops {
+        locals,
+        borrowed: acc.borrowed,
+    };
+    let stmts = stmts.into_iter().map(|s| walk.traverse_stmt(s)).collect();
+    walk.rewrite_stmts(stmts)
+}
@@ -13,6 +13,7 @@
 //!   - place the declarations in the right place and produce a sequence of `Structure<Stmt>`s
 //!   - simplify that sequence of `Structure<Stmt>`s into another such sequence
 //!   - convert the `Vec<Structure<Stmt>>` back into a `Vec<Stmt>`
+//!   - optionally, turn counted `while` loops in that `Vec<Stmt>` into `for` loops over ranges
 //!
 
 use crate::c_ast::iterators::{DFExpr, SomeId};
@@ -46,6 +47,7 @@ use crate::translator::*;
 use crate::with_stmts::WithStmts;
 use c2rust_ast_builder::mk;
 
+pub mod for_loops;
 mod inc_cleanup;
 pub mod loops;
 pub mod multiples;
@@ -1411,7 +1413,7 @@ impl CfgBuilder {
             }
 
             CStmtKind::Return(expr) => {
-                let val = match expr.map(|i| translator.convert_expr(ctx.used(), i)) {
+                let val = match expr.map(|i| translator.convert_return_value(ctx.used(), i)) {
                     Some(r) => Some(r?),
                     None => None,
                 };
@@ -0,0 +1,357 @@
+//! Translation of several preprocessor configurations into one `#[cfg]`-guarded Rust file.
+//!
+//! With `cfg_variants`, each C file is translated once with its compile command as is (the base
+//! configuration), and once more for each variant with the variant's extra clang arguments,
+//! usually `-D` flags.  The translations are merged item by item: items that are the same in
+//! every configuration are emitted once, and the others are emitted once per distinct version,
+//! guarded by a `#[cfg]` attribute that selects the configurations that produced it:
+//!
+//! ```text
+//! --cfg-variant feature=use_ssl:-DUSE_SSL --cfg-variant target_os=windows:'-D_WIN32 -DWINVER=0x0601'
+//! ```
+//!
+//! Variant `i` is active when its `cfg` option is set and no earlier variant's is, and the base
+//! configuration is active when none of them are, so enabling two features whose code differs
+//! picks the first one listed on the command line instead of producing duplicate definitions.
+//! Variants with the `feature` key are listed in the `[features]` section of the generated
+//! `Cargo.toml`.
+
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+use log::warn;
+use proc_macro2::TokenStream;
+use syn::__private::ToTokens;
+use syn::{Attribute, ForeignItem, Item, ItemForeignMod};
+
+use c2rust_ast_printer::pprust;
+
+/// A preprocessor configuration, and the `cfg` option that selects it in Rust.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgVariant {
+    /// The name of the `cfg` option, like `feature` or `target_os`.
+    pub key: String,
+    pub value: String,
+    /// The extra clang arguments that select the configuration in C.
+    pub clang_args: Vec<String>,
+}
+
+impl CfgVariant {
+    /// The name of the Cargo feature that selects this configuration, if it's selected by one.
+    pub fn feature(&self) -> Option<&str> {
+        if self.key == "feature" {
+            Some(&self.value)
+        } else {
+            None
+        }
+    }
+
+    fn predicate(&self) -> String {
+        format!("{} = {:?}", self.key, self.value)
+    }
+}
+
+/// Parse `KEY=VALUE:ARGS`, where `ARGS` are whitespace-separated clang arguments.
+impl FromStr for CfgVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CfgVariant, String> {
+        let (cfg, args) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected KEY=VALUE:ARGS, found {:?}", s))?;
+        let (key, value) = cfg
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE before ':', found {:?}", cfg))?;
+        let is_ident = |s: &str| {
+            s.chars()
+                .next()
+                .map_or(false, |c| c.is_alphabetic() || c == '_')
+                && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+        };
+        if !is_ident(key) {
+            return Err(format!("{:?} is not a valid cfg option name", key));
+        }
+        if value.is_empty() || value.contains('"') {
+            return Err(format!("{:?} is not a valid cfg option value", value));
+        }
+        Ok(CfgVariant {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            clang_args: args.split_whitespace().map(String::from).collect(),
+        })
+    }
+}
+
+/// The `cfg` predicate that holds when configuration `config` is active, where configuration 0
+/// is the base configuration and configuration `i + 1` is `variants[i]`.
+fn active_predicate(variants: &[CfgVariant], config: usize) -> String {
+    let any = |vs: &[CfgVariant]| {
+        let preds = vs.iter().map(CfgVariant::predicate).collect::<Vec<_>>();
+        format!("any({})", preds.join(", "))
+    };
+    match config {
+        0 => format!("not({})", any(variants)),
+        1 => variants[0].predicate(),
+        i => format!(
+            "all({}, not({}))",
+            variants[i - 1].predicate(),
+            any(&variants[..i - 1])
+        ),
+    }
+}
+
+/// The `cfg` predicate that holds when one of `configs` is active, or `None` if that's always
+/// the case.
+fn configs_predicate(variants: &[CfgVariant], configs: &[bool]) -> Option<String> {
+    let (active, inactive): (Vec<usize>, Vec<usize>) =
+        (0..configs.len()).partition(|&config| configs[config]);
+    let (listed, negate) = match (active.len(), inactive.len()) {
+        (_, 0) => return None,
+        (a, i) if a <= i => (active, false),
+        _ => (inactive, true),
+    };
+    let preds = listed
+        .into_iter()
+        .map(|config| active_predicate(variants, config))
+        .collect::<Vec<_>>();
+    let pred = if preds.len() == 1 {
+        preds.into_iter().next().unwrap()
+    } else {
+        format!("any({})", preds.join(", "))
+    };
+    Some(if negate {
+        format!("not({})", pred)
+    } else {
+        pred
+    })
+}
+
+fn cfg_attr(pred: &str) -> Attribute {
+    let pred: TokenStream = pred.parse().unwrap();
+    syn::parse_quote!(#[cfg(#pred)])
+}
+
+/// The key under which versions of `item` from different configurations are compared: the kind
+/// and name of named items, and the whole item otherwise.
+fn item_key(item: &Item) -> String {
+    let named = |kind: &str, ident: &syn::Ident| format!("{} {}", kind, ident);
+    match item {
+        Item::Const(i) => named("const", &i.ident),
+        Item::Enum(i) => named("enum", &i.ident),
+        Item::Fn(i) => named("fn", &i.sig.ident),
+        Item::Mod(i) => named("mod", &i.ident),
+        Item::Static(i) => named("static", &i.ident),
+        Item::Struct(i) => named("struct", &i.ident),
+        Item::Type(i) => named("type", &i.ident),
+        Item::Union(i) => named("union", &i.ident),
+        Item::ForeignMod(ItemForeignMod { items, .. }) if items.len() == 1 => match &items[0] {
+            ForeignItem::Fn(i) => named("extern fn", &i.sig.ident),
+            ForeignItem::Static(i) => named("extern static", &i.ident),
+            ForeignItem::Type(i) => named("extern type", &i.ident),
+            _ => item.to_token_stream().to_string(),
+        },
+        _ => item.to_token_stream().to_string(),
+    }
+}
+
+fn item_attrs_mut(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+    Some(match item {
+        Item::Const(i) => &mut i.attrs,
+        Item::Enum(i) => &mut i.attrs,
+        Item::ExternCrate(i) => &mut i.attrs,
+        Item::Fn(i) => &mut i.attrs,
+        Item::ForeignMod(i) => &mut i.attrs,
+        Item::Impl(i) => &mut i.attrs,
+        Item::Macro(i) => &mut i.attrs,
+        Item::Macro2(i) => &mut i.attrs,
+        Item::Mod(i) => &mut i.attrs,
+        Item::Static(i) => &mut i.attrs,
+        Item::Struct(i) => &mut i.attrs,
+        Item::Trait(i) => &mut i.attrs,
+        Item::TraitAlias(i) => &mut i.attrs,
+        Item::Type(i) => &mut i.attrs,
+        Item::Union(i) => &mut i.attrs,
+        Item::Use(i) => &mut i.attrs,
+        _ => return None,
+    })
+}
+
+/// Split `extern` blocks into one block per foreign item, so that each foreign item can be
+/// compared and guarded separately.
+fn split_foreign_mods(items: Vec<Item>) -> Vec<Item> {
+    let mut split = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Item::ForeignMod(fm) if fm.items.len() > 1 => {
+                for fi in &fm.items {
+                    split.push(Item::ForeignMod(ItemForeignMod {
+                        items: vec![fi.clone()],
+                        ..fm.clone()
+                    }));
+                }
+            }
+            item => split.push(item),
+        }
+    }
+    split
+}
+
+/// One version of an item, and the configurations that produced it.
+struct Version {
+    tokens: String,
+    item: Item,
+    configs: Vec<bool>,
+}
+
+/// Merge `translations`, the translations of one C file in the base configuration and then in
+/// each of `variants`, into one file.
+pub fn merge_translations(
+    variants: &[CfgVariant],
+    translations: &[String],
+) -> Result<String, syn::Error> {
+    assert_eq!(translations.len(), variants.len() + 1);
+    let num_configs = translations.len();
+
+    let mut attrs = IndexMap::new();
+    let mut versions = IndexMap::<String, Vec<Version>>::new();
+    for (config, translation) in translations.iter().enumerate() {
+        let file = syn::parse_file(translation)?;
+        for attr in file.attrs {
+            // Keep the crate attributes, like `#![feature]`s, of every configuration.
+            attrs
+                .entry(attr.to_token_stream().to_string())
+                .or_insert(attr);
+        }
+        for item in split_foreign_mods(file.items) {
+            let tokens = item.to_token_stream().to_string();
+            let item_versions = versions.entry(item_key(&item)).or_default();
+            let version = match item_versions.iter().position(|v| v.tokens == tokens) {
+                Some(idx) => &mut item_versions[idx],
+                None => {
+                    item_versions.push(Version {
+                        tokens,
+                        item,
+                        configs: vec![false; num_configs],
+                    });
+                    item_versions.last_mut().unwrap()
+                }
+            };
+            version.configs[config] = true;
+        }
+    }
+
+    let mut items = Vec::new();
+    // The positions in `items` of the `extern` blocks that foreign items are regrouped into,
+    // by ABI and `cfg` predicate.
+    let mut foreign_mods = IndexMap::new();
+    for version in versions.into_values().flatten() {
+        let pred = configs_predicate(variants, &version.configs);
+        let mut item = version.item;
+        if let Item::ForeignMod(ref mut fm) = item {
+            let key = (fm.abi.to_token_stream().to_string(), pred.clone());
+            if let Some(&idx) = foreign_mods.get(&key) {
+                if let Item::ForeignMod(ref mut group) = items[idx] {
+                    group.items.append(&mut fm.items);
+                }
+                continue;
+            }
+            foreign_mods.insert(key, items.len());
+        }
+        if let Some(pred) = pred {
+            match item_attrs_mut(&mut item) {
+                Some(item_attrs) => item_attrs.insert(0, cfg_attr(&pred)),
+                None => warn!(
+                    "can't guard an item that differs between configurations: {}",
+                    version.tokens
+                ),
+            }
+        }
+        items.push(item);
+    }
+
+    Ok(pprust::to_string(|| syn::File {
+        shebang: None,
+        attrs: attrs.into_values().collect(),
+        items,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants() -> Vec<CfgVariant> {
+        vec![
+            "feature=fast:-DFAST".parse().unwrap(),
+            "target_os=windows:-D_WIN32 -DWINVER=0x0601"
+                .parse()
+                .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn parses_variants() {
+        assert_eq!(
+            variants()[1],
+            CfgVariant {
+                key: "target_os".to_owned(),
+                value: "windows".to_owned(),
+                clang_args: vec!["-D_WIN32".to_owned(), "-DWINVER=0x0601".to_owned()],
+            }
+        );
+        assert!("feature:-DFAST".parse::<CfgVariant>().is_err());
+        assert!("feature=fast".parse::<CfgVariant>().is_err());
+    }
+
+    #[test]
+    fn guards_differing_items() {
+        let base = "\
+pub static mut n: libc::c_int = 1;
+extern \"C\" {
+    fn a();
+}
+pub unsafe extern \"C\" fn f() {}
+";
+        let fast = "\
+pub static mut n: libc::c_int = 1;
+extern \"C\" {
+    fn a();
+    fn b();
+}
+pub unsafe extern \"C\" fn f() {
+    b();
+}
+";
+        let windows = "\
+pub static mut n: libc::c_int = 2;
+extern \"C\" {
+    fn a();
+}
+pub unsafe extern \"C\" fn f() {}
+";
+        let translations = [base, fast, windows].map(String::from);
+        let merged = merge_translations(&variants(), &translations).unwrap();
+        assert_eq!(
+            merged,
+            "\
+#[cfg(not(all(target_os = \"windows\", not(any(feature = \"fast\")))))]
+pub static mut n: libc::c_int = 1;
+#[cfg(all(target_os = \"windows\", not(any(feature = \"fast\"))))]
+pub static mut n: libc::c_int = 2;
+extern \"C\" {
+    fn a();
+}
+#[cfg(not(feature = \"fast\"))]
+pub unsafe extern \"C\" fn f() {}
+#[cfg(feature = \"fast\")]
+pub unsafe extern \"C\" fn f() {
+    b();
+}
+#[cfg(feature = \"fast\")]
+extern \"C\" {
+    fn b();
+}
+"
+        );
+    }
+}
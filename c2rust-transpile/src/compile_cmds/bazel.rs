@@ -0,0 +1,269 @@
+//! Compilation databases for Bazel workspaces, built from Bazel's action graph.
+//!
+//! `bazel aquery` gives the compile actions of each `cc_library` and `cc_binary` target, and
+//! `bazel query` the dependencies between those targets.  Each target gets a link command that
+//! lists the targets it depends on, so that each one becomes its own crate in the output
+//! workspace, depending on the crates of its dependencies.  With `bazel_workspace` set, a
+//! `BUILD.bazel` with a `rules_rust` rule is emitted for each crate as well.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use failure::{format_err, Error};
+use log::warn;
+use regex::Regex;
+use serde_derive::Deserialize;
+
+use super::{CompileCmd, LinkSpec};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActionGraph {
+    #[serde(default)]
+    actions: Vec<Action>,
+    #[serde(default)]
+    targets: Vec<Target>,
+    #[serde(default)]
+    rule_classes: Vec<RuleClass>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Action {
+    target_id: u64,
+    mnemonic: String,
+    #[serde(default)]
+    arguments: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Target {
+    id: u64,
+    label: String,
+    rule_class_id: u64,
+}
+
+#[derive(Deserialize)]
+struct RuleClass {
+    id: u64,
+    name: String,
+}
+
+/// The name of the crate made from the target `label`: `foo_bar_baz` for `//foo/bar:baz`.
+fn crate_name(label: &str) -> String {
+    let name = label
+        .trim_start_matches('@')
+        .trim_start_matches("//")
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    name.trim_start_matches('_').to_owned()
+}
+
+/// Run `bazel` in `workspace`, and get its standard output.
+fn bazel(workspace: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("bazel")
+        .args(args)
+        .current_dir(workspace)
+        .output()
+        .map_err(|e| format_err!("couldn't run bazel: {}", e))?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "bazel {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Parse the edges of `bazel query --output=graph --nograph:factored`, as a map from each target
+/// to the targets it depends on.
+fn parse_dependency_graph(graph: &str) -> HashMap<String, Vec<String>> {
+    let edge = Regex::new(r#"^\s*"([^"]+)"\s*->\s*"([^"]+)""#).unwrap();
+    let mut deps = HashMap::<_, Vec<_>>::new();
+    for caps in graph.lines().filter_map(|line| edge.captures(line)) {
+        deps.entry(caps[1].to_owned())
+            .or_default()
+            .push(caps[2].to_owned());
+    }
+    deps
+}
+
+/// Convert the C compile actions in `graph` into compile commands run in `exec_root`, followed by
+/// a link command for each target.
+fn convert_action_graph(
+    graph: &ActionGraph,
+    deps: &HashMap<String, Vec<String>>,
+    exec_root: &Path,
+) -> Vec<CompileCmd> {
+    let rule_classes = graph
+        .rule_classes
+        .iter()
+        .map(|rc| (rc.id, rc.name.as_str()))
+        .collect::<HashMap<_, _>>();
+    let mut cmds = vec![];
+    let mut links = vec![];
+    for target in &graph.targets {
+        let link_type = match rule_classes.get(&target.rule_class_id) {
+            Some(&"cc_library") => "static",
+            Some(&"cc_binary") => "exe",
+            _ => continue,
+        };
+
+        let mut target_cmds = vec![];
+        let actions = graph
+            .actions
+            .iter()
+            .filter(|a| a.target_id == target.id && a.mnemonic == "CppCompile");
+        for action in actions {
+            let mut args = action.arguments.iter();
+            let (mut file, mut output) = (None, None);
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "-c" => file = args.next(),
+                    "-o" => output = args.next(),
+                    _ => {}
+                }
+            }
+            let file = match file {
+                Some(file) if file.ends_with(".c") => file,
+                _ => continue,
+            };
+            // The compiler is Bazel's wrapper script, which only works inside its sandbox.
+            let mut arguments = vec!["clang".to_owned()];
+            arguments.extend(action.arguments.iter().skip(1).cloned());
+            target_cmds.push(CompileCmd {
+                directory: exec_root.to_owned(),
+                file: exec_root.join(file),
+                command: None,
+                arguments,
+                output: output.cloned(),
+            });
+        }
+
+        // Libraries without C sources, like header-only ones, have no crate to depend on.
+        if target_cmds.is_empty() {
+            warn!("Skipping Bazel target {} without C sources", target.label);
+            continue;
+        }
+        let name = crate_name(&target.label);
+        let mut link = LinkSpec::new(link_type, &target_cmds);
+        link.deps = deps
+            .get(&target.label)
+            .into_iter()
+            .flatten()
+            .map(|dep| crate_name(dep))
+            .collect();
+        links.push((link, name));
+        cmds.extend(target_cmds);
+    }
+
+    // Only depend on the targets that became crates.
+    let crates = links
+        .iter()
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<_>>();
+    for (mut link, name) in links {
+        link.deps.retain(|dep| crates.contains(dep));
+        cmds.push(link.into_compile_cmd(exec_root.to_owned(), name));
+    }
+    cmds
+}
+
+/// Build the `cc_library` and `cc_binary` targets matching `pattern` in the Bazel `workspace`,
+/// so that their generated headers exist, and write a compilation database for them to
+/// `workspace/c2rust_compile_commands.json`.  Returns the path to the database.
+pub fn bazel_compile_commands(workspace: &Path, pattern: &str) -> Result<PathBuf, Error> {
+    let cc_targets = format!("kind(\"cc_(library|binary) rule\", {})", pattern);
+    bazel(workspace, &["build", pattern])?;
+    let exec_root = bazel(workspace, &["info", "execution_root"])?;
+    let exec_root = PathBuf::from(exec_root.trim());
+
+    let actions = bazel(
+        workspace,
+        &[
+            "aquery",
+            "--output=jsonproto",
+            &format!("mnemonic(\"CppCompile\", {})", cc_targets),
+        ],
+    )?;
+    let graph: ActionGraph = serde_json::from_str(&actions)?;
+    let deps = bazel(
+        workspace,
+        &["query", "--output=graph", "--nograph:factored", &cc_targets],
+    )?;
+    let deps = parse_dependency_graph(&deps);
+
+    let cmds = convert_action_graph(&graph, &deps, &exec_root);
+    let cc_db = workspace.join("c2rust_compile_commands.json");
+    fs::write(&cc_db, serde_json::to_string_pretty(&cmds)?)?;
+    Ok(cc_db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_cmds::decode_link_cmd;
+
+    #[test]
+    fn groups_actions_by_target() {
+        let graph: ActionGraph = serde_json::from_str(
+            r#"{
+                "actions": [
+                    {
+                        "targetId": 1,
+                        "mnemonic": "CppCompile",
+                        "arguments": [
+                            "external/local_config_cc/cc_wrapper.sh", "-iquote", ".",
+                            "-c", "lib/util.c", "-o", "bazel-out/k8/bin/lib/_objs/util/util.o"
+                        ]
+                    },
+                    {
+                        "targetId": 2,
+                        "mnemonic": "CppCompile",
+                        "arguments": ["cc_wrapper.sh", "-c", "app/main.c", "-o", "main.o"]
+                    },
+                    { "targetId": 2, "mnemonic": "CppLink", "arguments": ["ld"] }
+                ],
+                "targets": [
+                    { "id": 1, "label": "//lib:util", "ruleClassId": 1 },
+                    { "id": 2, "label": "//app:main", "ruleClassId": 2 },
+                    { "id": 3, "label": "//lib:headers", "ruleClassId": 1 }
+                ],
+                "ruleClasses": [
+                    { "id": 1, "name": "cc_library" },
+                    { "id": 2, "name": "cc_binary" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let deps = parse_dependency_graph(
+            "digraph mygraph {\n  \"//app:main\"\n  \"//app:main\" -> \"//lib:util\"\n  \
+             \"//app:main\" -> \"//lib:headers\"\n}\n",
+        );
+        let cmds = convert_action_graph(&graph, &deps, Path::new("/execroot/ws"));
+        assert_eq!(cmds.len(), 4);
+        assert_eq!(cmds[0].file, Path::new("/execroot/ws/lib/util.c"));
+        assert_eq!(
+            cmds[0].arguments,
+            [
+                "clang",
+                "-iquote",
+                ".",
+                "-c",
+                "lib/util.c",
+                "-o",
+                "bazel-out/k8/bin/lib/_objs/util/util.o"
+            ]
+        );
+
+        let link = decode_link_cmd(&cmds[3].file).unwrap().unwrap();
+        assert_eq!(link.inputs, ["main.o"]);
+        assert_eq!(link.deps, ["lib_util"]);
+        assert_eq!(cmds[3].output.as_deref(), Some("app_main"));
+    }
+}
@@ -0,0 +1,296 @@
+//! Compilation databases for CMake projects, built from the CMake file API.
+//!
+//! Instead of `compile_commands.json`, which doesn't say which sources go into which target, we
+//! ask CMake for its code model (`codemodel-v2`) while configuring the project.  Each C source
+//! of each executable and library target gets a compile command with the target's include paths,
+//! defines and flags, and each target gets a link command in the `/c2rust/link/` form that
+//! `build_link_commands` reads, so that every target becomes its own crate in the output
+//! workspace.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use failure::{format_err, Error};
+use log::warn;
+use serde_derive::Deserialize;
+
+use super::{CompileCmd, LinkSpec};
+
+#[derive(Deserialize)]
+struct Index {
+    reply: IndexReply,
+}
+
+#[derive(Deserialize)]
+struct IndexReply {
+    #[serde(rename = "codemodel-v2")]
+    codemodel: Option<ReplyFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplyFile {
+    json_file: String,
+}
+
+#[derive(Deserialize)]
+struct Codemodel {
+    paths: CodemodelPaths,
+    configurations: Vec<Configuration>,
+}
+
+#[derive(Deserialize)]
+struct CodemodelPaths {
+    source: PathBuf,
+    build: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Configuration {
+    targets: Vec<ReplyFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Target {
+    name: String,
+    r#type: String,
+    #[serde(default)]
+    sources: Vec<Source>,
+    #[serde(default)]
+    compile_groups: Vec<CompileGroup>,
+    link: Option<Link>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Source {
+    path: PathBuf,
+    compile_group_index: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompileGroup {
+    language: String,
+    #[serde(default)]
+    compile_command_fragments: Vec<Fragment>,
+    #[serde(default)]
+    includes: Vec<Include>,
+    #[serde(default)]
+    defines: Vec<Define>,
+}
+
+#[derive(Deserialize)]
+struct Fragment {
+    fragment: String,
+    role: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Include {
+    path: PathBuf,
+    #[serde(default)]
+    is_system: bool,
+}
+
+#[derive(Deserialize)]
+struct Define {
+    define: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Link {
+    command_fragments: Vec<Fragment>,
+}
+
+/// Convert `target` into compile commands for its C sources, followed by its link command.
+fn target_commands(target: &Target, paths: &CodemodelPaths) -> Vec<CompileCmd> {
+    let link_type = match target.r#type.as_str() {
+        "EXECUTABLE" => "exe",
+        "STATIC_LIBRARY" => "static",
+        "SHARED_LIBRARY" | "MODULE_LIBRARY" => "shared",
+        // Object libraries, interface libraries and custom targets don't link anything.
+        _ => return vec![],
+    };
+
+    let mut cmds = vec![];
+    for (idx, source) in target.sources.iter().enumerate() {
+        let group = match source.compile_group_index {
+            Some(group) => &target.compile_groups[group],
+            None => continue,
+        };
+        if group.language != "C" {
+            continue;
+        }
+        let file = paths.source.join(&source.path);
+
+        let mut arguments = vec!["clang".to_owned()];
+        for fragment in &group.compile_command_fragments {
+            arguments.extend(fragment.fragment.split_whitespace().map(String::from));
+        }
+        for include in &group.includes {
+            let flag = if include.is_system { "-isystem" } else { "-I" };
+            arguments.push(flag.to_owned());
+            arguments.push(include.path.display().to_string());
+        }
+        for define in &group.defines {
+            arguments.push(format!("-D{}", define.define));
+        }
+        arguments.push("-c".to_owned());
+        arguments.push(file.display().to_string());
+
+        cmds.push(CompileCmd {
+            directory: paths.build.clone(),
+            file,
+            command: None,
+            arguments,
+            output: Some(format!("{}-{}.o", target.name, idx)),
+        });
+    }
+    if cmds.is_empty() {
+        warn!("Skipping CMake target {} without C sources", target.name);
+        return cmds;
+    }
+
+    let mut link = LinkSpec::new(link_type, &cmds);
+    let fragments = target.link.iter().flat_map(|link| &link.command_fragments);
+    for fragment in fragments {
+        match fragment.role.as_deref() {
+            // Libraries built by the project itself, which are relative to the build directory,
+            // become dependencies on their crates in `build_link_commands`.
+            Some("libraries") | Some("libraryPath") => link.add_link_arg(fragment.fragment.trim()),
+            _ => {}
+        }
+    }
+    cmds.push(link.into_compile_cmd(paths.build.clone(), target.name.clone()));
+    cmds
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format_err!("couldn't read {}: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Configure the CMake project in `source_dir` in `build_dir`, passing it `cmake_args`, and write
+/// a compilation database for its targets to `build_dir/c2rust_compile_commands.json`.  Returns
+/// the path to the database.
+pub fn cmake_compile_commands(
+    source_dir: &Path,
+    build_dir: &Path,
+    cmake_args: &[String],
+) -> Result<PathBuf, Error> {
+    // A stateless query: asking for the code model by creating an empty file.
+    let api_dir = build_dir.join(".cmake/api/v1");
+    fs::create_dir_all(api_dir.join("query"))?;
+    fs::write(api_dir.join("query/codemodel-v2"), "")?;
+
+    let status = Command::new("cmake")
+        .arg("-S")
+        .arg(source_dir)
+        .arg("-B")
+        .arg(build_dir)
+        .args(cmake_args)
+        .status()
+        .map_err(|e| format_err!("couldn't run cmake: {}", e))?;
+    if !status.success() {
+        return Err(format_err!("cmake failed with {}", status));
+    }
+
+    // The index with the latest name is the one written by this run.
+    let reply_dir = api_dir.join("reply");
+    let index = fs::read_dir(&reply_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("index-") && name.ends_with(".json")
+        })
+        .max()
+        .ok_or_else(|| format_err!("cmake didn't reply in {}", reply_dir.display()))?;
+    let index: Index = read_json(&index)?;
+    let codemodel = index
+        .reply
+        .codemodel
+        .ok_or_else(|| format_err!("cmake didn't reply with a code model"))?;
+    let codemodel: Codemodel = read_json(&reply_dir.join(codemodel.json_file))?;
+
+    // Multi-config generators reply with every configuration; translate the first.
+    let config = codemodel
+        .configurations
+        .first()
+        .ok_or_else(|| format_err!("cmake's code model has no configurations"))?;
+    let mut cmds = vec![];
+    for target in &config.targets {
+        let target: Target = read_json(&reply_dir.join(&target.json_file))?;
+        cmds.extend(target_commands(&target, &codemodel.paths));
+    }
+
+    let cc_db = build_dir.join("c2rust_compile_commands.json");
+    fs::write(&cc_db, serde_json::to_string_pretty(&cmds)?)?;
+    Ok(cc_db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_cmds::decode_link_cmd;
+
+    #[test]
+    fn converts_targets() {
+        let target: Target = serde_json::from_str(
+            r#"{
+                "name": "tool",
+                "type": "EXECUTABLE",
+                "sources": [
+                    { "path": "src/main.c", "compileGroupIndex": 0 },
+                    { "path": "src/util.h" }
+                ],
+                "compileGroups": [{
+                    "language": "C",
+                    "compileCommandFragments": [{ "fragment": "-O2 -Wall" }],
+                    "includes": [{ "path": "/proj/include" }],
+                    "defines": [{ "define": "HAVE_ZLIB=1" }]
+                }],
+                "link": {
+                    "language": "C",
+                    "commandFragments": [
+                        { "fragment": "-rdynamic", "role": "flags" },
+                        { "fragment": "/usr/lib/libz.so", "role": "libraries" },
+                        { "fragment": "-lm", "role": "libraries" }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+        let paths = CodemodelPaths {
+            source: "/proj".into(),
+            build: "/proj/build".into(),
+        };
+        let cmds = target_commands(&target, &paths);
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].file, Path::new("/proj/src/main.c"));
+        assert_eq!(
+            cmds[0].arguments,
+            [
+                "clang",
+                "-O2",
+                "-Wall",
+                "-I",
+                "/proj/include",
+                "-DHAVE_ZLIB=1",
+                "-c",
+                "/proj/src/main.c"
+            ]
+        );
+
+        let link = decode_link_cmd(&cmds[1].file).unwrap().unwrap();
+        assert_eq!(link.inputs, ["tool-0.o"]);
+        assert_eq!(link.libs, ["z", "m"]);
+        assert_eq!(link.lib_dirs, [Path::new("/usr/lib")]);
+        assert_eq!(cmds[1].output.as_deref(), Some("tool"));
+    }
+}
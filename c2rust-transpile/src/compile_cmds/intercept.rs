@@ -0,0 +1,238 @@
+//! Compilation databases for Autotools and other Make-based projects, built by intercepting the
+//! compiler.
+//!
+//! `autotools_compile_commands` configures the project, if it hasn't been, and then rebuilds it
+//! with `make -B CC=<this executable>`.  With `C2RUST_INTERCEPT_LOG` set, the transpiler runs as
+//! a compiler wrapper instead (see `run_as_compiler_wrapper`): it appends its arguments to the log
+//! and runs the real compiler, so the build still succeeds.  The log is then converted into
+//! compile commands and link commands like `scripts/convert_build_commands.py` does for the
+//! `cc-wrappers` scripts, without needing Python or `bear`.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use failure::{format_err, Error};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{CompileCmd, LinkSpec};
+
+/// The environment variable giving the file to log compiler invocations to.
+const LOG_VAR: &str = "C2RUST_INTERCEPT_LOG";
+/// The environment variable giving the real compiler.
+const CC_VAR: &str = "C2RUST_INTERCEPT_CC";
+
+/// One compiler invocation.
+#[derive(Serialize, Deserialize, Debug)]
+struct Invocation {
+    directory: PathBuf,
+    arguments: Vec<String>,
+}
+
+/// If this process was started as a compiler wrapper by `autotools_compile_commands`, log the
+/// invocation, run the real compiler, and return its exit code.
+pub fn run_as_compiler_wrapper() -> Option<i32> {
+    let log = env::var_os(LOG_VAR)?;
+    let cc = env::var(CC_VAR).unwrap_or_else(|_| "cc".to_owned());
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let invocation = Invocation {
+        directory: env::current_dir().unwrap(),
+        arguments: [cc.clone()]
+            .into_iter()
+            .chain(args.iter().cloned())
+            .collect(),
+    };
+    let mut line = serde_json::to_string(&invocation).unwrap();
+    line.push('\n');
+    // A single append of the whole line, so that parallel builds don't interleave entries.
+    let logged = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log)
+        .and_then(|mut f| f.write_all(line.as_bytes()));
+    if let Err(e) = logged {
+        eprintln!("c2rust: couldn't log compiler invocation: {}", e);
+    }
+
+    // `CC` may include arguments, like `gcc -std=gnu99`.
+    let mut cc = cc.split_whitespace();
+    let status = Command::new(cc.next().unwrap_or("cc"))
+        .args(cc)
+        .args(&args)
+        .status();
+    Some(match status {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            eprintln!("c2rust: couldn't run the compiler: {}", e);
+            1
+        }
+    })
+}
+
+/// Convert compiler invocations into compile commands for the C files they compile, and link
+/// commands for the ones that link.
+fn convert_invocations(invocations: Vec<Invocation>) -> Vec<CompileCmd> {
+    let mut cmds = vec![];
+    let mut links = vec![];
+    for inv in invocations {
+        let mut args = inv.arguments.into_iter();
+        let mut new_args = args.next().into_iter().collect::<Vec<_>>();
+        let mut c_inputs = vec![];
+        let mut other_inputs = vec![];
+        let mut link_args = vec![];
+        let mut compile_only = false;
+        let mut shared = false;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-D" | "-U" | "-I" | "-include" | "-isystem" => {
+                    new_args.push(arg);
+                    new_args.extend(args.next());
+                }
+                "-c" => compile_only = true,
+                "-o" => output = args.next(),
+                "-shared" => shared = true,
+                "-l" | "-L" => link_args.extend(args.next().map(|next| arg + &next)),
+                _ if arg.starts_with("-o") => output = Some(arg[2..].to_owned()),
+                _ if arg.starts_with("-l") || arg.starts_with("-L") => link_args.push(arg),
+                // `-pthread` implicitly adds `-lpthread`
+                "-pthread" => {
+                    link_args.push("-lpthread".to_owned());
+                    new_args.push(arg);
+                }
+                _ if arg.starts_with('-') => new_args.push(arg),
+                _ if arg.ends_with(".c") => c_inputs.push(arg),
+                _ => other_inputs.push(arg),
+            }
+        }
+
+        let mut inputs = vec![];
+        for c_input in c_inputs {
+            let file = inv.directory.join(&c_input);
+            let object = match (&output, compile_only) {
+                (Some(output), true) => output.clone(),
+                _ => format!("{}-{}.o", c_input.trim_end_matches(".c"), cmds.len()),
+            };
+            inputs.push(object.clone());
+
+            let mut arguments = new_args.clone();
+            arguments.push("-c".to_owned());
+            arguments.push(c_input);
+            cmds.push(CompileCmd {
+                directory: inv.directory.clone(),
+                file,
+                command: None,
+                arguments,
+                output: Some(object),
+            });
+        }
+        if compile_only {
+            continue;
+        }
+
+        // Object files from earlier compile commands; `build_link_commands` ignores the rest.
+        inputs.extend(other_inputs);
+        let mut link = LinkSpec {
+            inputs,
+            ..LinkSpec::new(if shared { "shared" } else { "exe" }, &[])
+        };
+        for arg in &link_args {
+            link.add_link_arg(arg);
+        }
+        let output = output.unwrap_or_else(|| "a.out".to_owned());
+        let name = Path::new(&output)
+            .file_stem()
+            .map_or(output.clone(), |stem| stem.to_string_lossy().into_owned());
+        links.push(link.into_compile_cmd(inv.directory, name));
+    }
+    cmds.extend(links);
+    cmds
+}
+
+/// Configure the Autotools (or plain Make) project in `source_dir` with `configure_args` if it
+/// has a `configure` script and no `Makefile`, rebuild it while intercepting the compiler, and
+/// write a compilation database for what was built to `source_dir/c2rust_compile_commands.json`.
+/// Returns the path to the database.
+pub fn autotools_compile_commands(
+    source_dir: &Path,
+    configure_args: &[String],
+) -> Result<PathBuf, Error> {
+    if !source_dir.join("Makefile").exists() && source_dir.join("configure").exists() {
+        let status = Command::new("./configure")
+            .args(configure_args)
+            .current_dir(source_dir)
+            .status()
+            .map_err(|e| format_err!("couldn't run configure: {}", e))?;
+        if !status.success() {
+            return Err(format_err!("configure failed with {}", status));
+        }
+    }
+
+    let log = source_dir.join("c2rust_compiler_invocations.jsonl");
+    if log.exists() {
+        fs::remove_file(&log)?;
+    }
+    let wrapper = env::current_exe()?;
+    let status = Command::new("make")
+        .arg("-B")
+        .arg(format!("CC={}", wrapper.display()))
+        .current_dir(source_dir)
+        .env(LOG_VAR, &log)
+        .env(CC_VAR, env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .status()
+        .map_err(|e| format_err!("couldn't run make: {}", e))?;
+    if !status.success() {
+        warn!("make failed with {}; translating what was built", status);
+    }
+
+    let invocations = fs::read_to_string(&log)
+        .map_err(|e| format_err!("make didn't run the compiler: {}", e))?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Invocation>, _>>()?;
+    let cmds = convert_invocations(invocations);
+
+    let cc_db = source_dir.join("c2rust_compile_commands.json");
+    fs::write(&cc_db, serde_json::to_string_pretty(&cmds)?)?;
+    Ok(cc_db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_cmds::decode_link_cmd;
+
+    fn invocation(args: &str) -> Invocation {
+        Invocation {
+            directory: "/proj".into(),
+            arguments: args.split_whitespace().map(String::from).collect(),
+        }
+    }
+
+    #[test]
+    fn converts_invocations() {
+        let cmds = convert_invocations(vec![
+            invocation("cc -DHAVE_CONFIG_H -I . -O2 -c -o util.o util.c"),
+            invocation("cc -O2 -o tool main.c util.o -lm -L/opt/lib -lz"),
+        ]);
+        assert_eq!(cmds.len(), 3);
+        assert_eq!(cmds[0].file, Path::new("/proj/util.c"));
+        assert_eq!(
+            cmds[0].arguments,
+            ["cc", "-DHAVE_CONFIG_H", "-I", ".", "-O2", "-c", "util.c"]
+        );
+        assert_eq!(cmds[0].output.as_deref(), Some("util.o"));
+        assert_eq!(cmds[1].file, Path::new("/proj/main.c"));
+        assert_eq!(cmds[1].output.as_deref(), Some("main-1.o"));
+
+        let link = decode_link_cmd(&cmds[2].file).unwrap().unwrap();
+        assert_eq!(link.inputs, ["main-1.o", "util.o"]);
+        assert_eq!(link.libs, ["m", "z"]);
+        assert_eq!(link.lib_dirs, [Path::new("/opt/lib")]);
+        assert_eq!(cmds[2].output.as_deref(), Some("tool"));
+    }
+}
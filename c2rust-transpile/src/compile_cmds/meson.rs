@@ -0,0 +1,119 @@
+//! Compilation databases for Meson projects, built from Meson's introspection files.
+//!
+//! Meson writes `compile_commands.json` itself, but like CMake's it doesn't say which sources
+//! go into which target.  `meson-info/intro-targets.json` in the build directory does: each
+//! target lists its sources with the compiler arguments for them, and since Meson 1.2, its
+//! linker arguments.  As with CMake projects, each executable and library becomes its own crate.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use failure::{format_err, Error};
+use log::warn;
+use serde_derive::Deserialize;
+
+use super::{CompileCmd, LinkSpec};
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    r#type: String,
+    #[serde(default)]
+    target_sources: Vec<TargetSources>,
+}
+
+/// A group of sources compiled with the same arguments, or the target's linker.
+#[derive(Deserialize)]
+struct TargetSources {
+    language: Option<String>,
+    linker: Option<Vec<String>>,
+    #[serde(default)]
+    parameters: Vec<String>,
+    #[serde(default)]
+    sources: Vec<PathBuf>,
+}
+
+/// Convert `target` into compile commands for its C sources, followed by its link command.
+fn target_commands(target: &Target, build_dir: &Path) -> Vec<CompileCmd> {
+    let link_type = match target.r#type.as_str() {
+        "executable" => "exe",
+        "static library" => "static",
+        "shared library" | "shared module" => "shared",
+        // Custom targets, run targets and aliases don't link anything.
+        _ => return vec![],
+    };
+
+    let mut cmds = vec![];
+    for group in &target.target_sources {
+        if group.language.as_deref() != Some("c") {
+            continue;
+        }
+        for file in &group.sources {
+            let file = build_dir.join(file);
+            let mut arguments = vec!["clang".to_owned()];
+            arguments.extend(group.parameters.iter().cloned());
+            arguments.push("-c".to_owned());
+            arguments.push(file.display().to_string());
+            cmds.push(CompileCmd {
+                directory: build_dir.to_owned(),
+                file,
+                command: None,
+                arguments,
+                output: Some(format!("{}-{}.o", target.name, cmds.len())),
+            });
+        }
+    }
+    if cmds.is_empty() {
+        warn!("Skipping Meson target {} without C sources", target.name);
+        return cmds;
+    }
+
+    let mut link = LinkSpec::new(link_type, &cmds);
+    let linker_args = target
+        .target_sources
+        .iter()
+        .filter(|group| group.linker.is_some())
+        .flat_map(|group| &group.parameters);
+    for arg in linker_args {
+        link.add_link_arg(arg);
+    }
+    cmds.push(link.into_compile_cmd(build_dir.to_owned(), target.name.clone()));
+    cmds
+}
+
+/// Read the targets of the Meson project configured in `build_dir`, configuring the project in
+/// `source_dir` there first if it isn't already, passing it `meson_args`.  Writes a compilation
+/// database for the targets to `build_dir/c2rust_compile_commands.json`, and returns its path.
+pub fn meson_compile_commands(
+    source_dir: &Path,
+    build_dir: &Path,
+    meson_args: &[String],
+) -> Result<PathBuf, Error> {
+    let targets_file = build_dir.join("meson-info/intro-targets.json");
+    if !targets_file.exists() {
+        let status = Command::new("meson")
+            .arg("setup")
+            .args(meson_args)
+            .arg(build_dir)
+            .arg(source_dir)
+            .status()
+            .map_err(|e| format_err!("couldn't run meson: {}", e))?;
+        if !status.success() {
+            return Err(format_err!("meson failed with {}", status));
+        }
+    }
+
+    let json = fs::read_to_string(&targets_file)
+        .map_err(|e| format_err!("couldn't read {}: {}", targets_file.display(), e))?;
+    let targets: Vec<Target> = serde_json::from_str(&json)?;
+    let build_dir = fs::canonicalize(build_dir)?;
+    let cmds = targets
+        .iter()
+        .flat_map(|target| target_commands(target, &build_dir))
+        .collect::<Vec<_>>();
+
+    let cc_db = build_dir.join("c2rust_compile_commands.json");
+    fs::write(&cc_db, serde_json::to_string_pretty(&cmds)?)?;
+    Ok(cc_db)
+}
@@ -1,9 +1,14 @@
+pub mod bazel;
+pub mod cmake;
+pub mod intercept;
+pub mod meson;
+
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use failure::Error;
+use failure::{format_err, Error};
 use log::warn;
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
@@ -22,10 +27,10 @@ pub struct CompileCmd {
     /// to rerun the exact compilation step for the translation unit in the environment
     /// the build system uses. Parameters use shell quoting and shell escaping of quotes,
     /// with ‘"’ and ‘\’ being the only special characters. Shell expansion is not supported.
-    #[serde(skip_deserializing)]
+    #[serde(default)]
     pub command: Option<String>,
     /// The compile command executed as list of strings. Either arguments or command is required.
-    #[serde(default, skip_deserializing)]
+    #[serde(default)]
     pub arguments: Vec<String>,
     /// The name of the output created by this compilation step. This field is optional. It can
     /// be used to distinguish different processing modes of the same input file.
@@ -43,6 +48,37 @@ impl CompileCmd {
             }
         }
     }
+
+    /// The compile command as a list of arguments, from `arguments` or else from `command`.
+    pub fn args(&self) -> Vec<String> {
+        match &self.command {
+            Some(command) if self.arguments.is_empty() => split_command(command),
+            _ => self.arguments.clone(),
+        }
+    }
+}
+
+/// Split a `command` into arguments, undoing its shell quoting.
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg = None::<String>;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => arg.get_or_insert_with(String::new).push(c),
+            ('\\', _) => arg.get_or_insert_with(String::new).extend(chars.next()),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => args.extend(arg.take()),
+            (c, _) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -51,6 +87,8 @@ pub enum LinkType {
     Exe,
     Shared,
     Static,
+    /// A Rust library only used by the other crates of the output, not by C code
+    Rlib,
 }
 
 impl LinkType {
@@ -59,6 +97,7 @@ impl LinkType {
             LinkType::Exe => false,
             LinkType::Shared => true,
             LinkType::Static => true,
+            LinkType::Rlib => true,
         }
     }
 
@@ -67,6 +106,7 @@ impl LinkType {
             LinkType::Exe => "\"rlib\"",
             LinkType::Shared => "\"cdylib\"",
             LinkType::Static => "\"staticlib\", \"rlib\"",
+            LinkType::Rlib => "\"rlib\"",
         }
     }
 }
@@ -84,6 +124,9 @@ pub struct LinkCmd {
     pub lib_dirs: Vec<PathBuf>,
     /// What type of binary we're building
     pub r#type: LinkType,
+    /// Names of the other crates this one depends on
+    #[serde(default)]
+    pub deps: Vec<String>,
     /// Input files in `CompileCmd` form
     #[serde(default)]
     pub cmd_inputs: Vec<Rc<CompileCmd>>,
@@ -91,6 +134,107 @@ pub struct LinkCmd {
     pub top_level: bool,
 }
 
+impl LinkCmd {
+    /// The name of the crate made from this link command, if it isn't the top-level one.
+    pub fn crate_name(&self) -> Option<String> {
+        let output = Path::new(self.output.as_ref()?);
+        Some(output.file_stem()?.to_str()?.to_owned())
+    }
+}
+
+/// A link command, in the form `LinkCmd` is deserialized from.
+#[derive(Serialize, Debug)]
+struct LinkSpec {
+    inputs: Vec<String>,
+    libs: Vec<String>,
+    lib_dirs: Vec<PathBuf>,
+    r#type: &'static str,
+    deps: Vec<String>,
+}
+
+impl LinkSpec {
+    /// A link command of type `link_type` (`exe`, `shared` or `static`) for the outputs of
+    /// `inputs`.
+    fn new(link_type: &'static str, inputs: &[CompileCmd]) -> LinkSpec {
+        LinkSpec {
+            inputs: inputs.iter().filter_map(|cmd| cmd.output.clone()).collect(),
+            libs: vec![],
+            lib_dirs: vec![],
+            r#type: link_type,
+            deps: vec![],
+        }
+    }
+
+    /// Record the library or library directory named by a linker argument, like `-lm`,
+    /// `/usr/lib/libz.so` or `-L/opt/lib`.  Other arguments are ignored.
+    fn add_link_arg(&mut self, arg: &str) {
+        if let Some(dir) = arg.strip_prefix("-L") {
+            self.add_lib_dir(PathBuf::from(dir));
+            return;
+        }
+        let name = match library_name(arg) {
+            Some(name) => name.to_owned(),
+            None => return,
+        };
+        if !self.libs.contains(&name) {
+            self.libs.push(name);
+        }
+        if let Some(dir) = Path::new(arg).parent().filter(|dir| dir.is_absolute()) {
+            self.add_lib_dir(dir.to_owned());
+        }
+    }
+
+    fn add_lib_dir(&mut self, dir: PathBuf) {
+        if !self.lib_dirs.contains(&dir) {
+            self.lib_dirs.push(dir);
+        }
+    }
+
+    /// Make the compilation database entry that `build_link_commands` reads this link command
+    /// from.  The output is the name of the crate made from it.
+    fn into_compile_cmd(self, directory: PathBuf, output: String) -> CompileCmd {
+        // See `scripts/convert_build_commands.py`.
+        let link_spec = serde_bencode::to_string(&self).unwrap();
+        CompileCmd {
+            directory,
+            file: PathBuf::from(format!("/c2rust/link/{}", link_spec)),
+            command: None,
+            arguments: vec![],
+            output: Some(output),
+        }
+    }
+}
+
+/// Get the library named by a linker argument: `m` for `-lm` or `/usr/lib/libm.so.6`.
+fn library_name(arg: &str) -> Option<&str> {
+    if let Some(lib) = arg.strip_prefix("-l") {
+        return Some(lib);
+    }
+    if arg.starts_with('-') {
+        return None;
+    }
+    let file_name = Path::new(arg).file_name()?.to_str()?;
+    let name = file_name.strip_prefix("lib")?;
+    let end = [".so", ".a", ".dylib"]
+        .iter()
+        .filter_map(|ext| name.find(ext))
+        .min()?;
+    Some(&name[..end])
+}
+
+/// Decode the link command that `LinkSpec::into_compile_cmd` stored in the file name `file`, or
+/// get `None` if `file` is a source file.
+fn decode_link_cmd(file: &Path) -> Result<Option<LinkCmd>, Error> {
+    let link_spec = match file.strip_prefix("/c2rust/link/") {
+        Ok(link_spec) => link_spec,
+        Err(_) => return Ok(None),
+    };
+    let link_spec = link_spec
+        .to_str()
+        .ok_or_else(|| format_err!("link command isn't UTF-8: {}", file.display()))?;
+    Ok(Some(serde_bencode::from_str(link_spec)?))
+}
+
 /// Convert a linear vector of `CompileCmd`s into a DAG of `LinkCmd`s and `CompileCmd`s
 fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error> {
     let mut output_map = HashMap::new();
@@ -103,11 +247,10 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
     let mut seen_ccmds = HashSet::new();
     let mut res = vec![];
     for (idx, ccmd) in v.iter().enumerate() {
-        let lcmd = match ccmd.file.strip_prefix("/c2rust/link/") {
-            Ok(lcmd) => lcmd.to_str().unwrap(),
-            Err(_) => continue,
+        let mut lcmd = match decode_link_cmd(&ccmd.file)? {
+            Some(lcmd) => lcmd,
+            None => continue,
         };
-        let mut lcmd: LinkCmd = serde_bencode::from_str(lcmd)?;
 
         lcmd.output = ccmd.output.clone();
         for inp in &lcmd.inputs {
@@ -121,6 +264,7 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
         res.push(lcmd);
         seen_ccmds.insert(idx);
     }
+    link_library_crates(&mut res);
 
     // TODO: add binaries
 
@@ -139,6 +283,7 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
             libs: vec![],
             lib_dirs: vec![],
             r#type: LinkType::Static,
+            deps: vec![],
             cmd_inputs: v,
             top_level: true,
         };
@@ -148,6 +293,35 @@ fn build_link_commands(mut v: Vec<Rc<CompileCmd>>) -> Result<Vec<LinkCmd>, Error
     Ok(res)
 }
 
+/// Make the crates of link commands that link in libraries built by other link commands
+/// depend on the crates of those libraries, instead of linking in the C libraries.
+fn link_library_crates(lcmds: &mut [LinkCmd]) {
+    let mut library_crates = HashMap::new();
+    for lcmd in lcmds.iter().filter(|lcmd| lcmd.r#type.is_library()) {
+        let (output, crate_name) = match (&lcmd.output, lcmd.crate_name()) {
+            (Some(output), Some(crate_name)) => (output.clone(), crate_name),
+            _ => continue,
+        };
+        // Linked in either by path, like `libfoo.a`, or by name, like `-lfoo`.
+        let name = library_name(&output).unwrap_or(&crate_name).to_owned();
+        library_crates.insert(output, crate_name.clone());
+        library_crates.insert(name, crate_name);
+    }
+
+    for lcmd in lcmds {
+        let own_crate = lcmd.crate_name();
+        let mut deps = std::mem::take(&mut lcmd.deps);
+        let linked = lcmd.inputs.iter().chain(&lcmd.libs);
+        for crate_name in linked.filter_map(|lib| library_crates.get(lib)) {
+            if Some(crate_name) != own_crate.as_ref() && !deps.contains(crate_name) {
+                deps.push(crate_name.clone());
+            }
+        }
+        lcmd.libs.retain(|lib| !library_crates.contains_key(lib));
+        lcmd.deps = deps;
+    }
+}
+
 /// some build scripts repeatedly compile the same input file with different
 /// command line flags thus creating multiple outputs. We remove any duplicates
 /// in the order we see them and warn the user.
@@ -208,3 +382,45 @@ pub fn get_compile_commands(
 
     Ok(lcmds)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_library_crates() {
+        let util = LinkSpec {
+            inputs: vec!["util.o".to_owned()],
+            ..LinkSpec::new("static", &[])
+        };
+        let mut tool = LinkSpec {
+            inputs: vec!["main.o".to_owned(), "libutil.a".to_owned()],
+            ..LinkSpec::new("exe", &[])
+        };
+        tool.add_link_arg("-lm");
+        let cmds = vec![
+            util.into_compile_cmd("/proj".into(), "libutil.a".to_owned()),
+            tool.into_compile_cmd("/proj".into(), "tool".to_owned()),
+        ];
+        let lcmds = build_link_commands(cmds.into_iter().map(Rc::new).collect()).unwrap();
+        assert_eq!(lcmds[0].crate_name().as_deref(), Some("libutil"));
+        assert_eq!(lcmds[1].deps, ["libutil"]);
+        assert_eq!(lcmds[1].libs, ["m"]);
+    }
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(
+            split_command(r#"cc -DNAME=\"c2rust\" -I 'my include' "" -c a.c"#),
+            [
+                "cc",
+                "-DNAME=\"c2rust\"",
+                "-I",
+                "my include",
+                "",
+                "-c",
+                "a.c"
+            ]
+        );
+    }
+}
@@ -261,7 +261,7 @@ impl TypeConverter {
         ctxt: &TypedAstContext,
         qtype: CQualTypeId,
     ) -> TranslationResult<Box<Type>> {
-        let mutbl = if qtype.qualifiers.is_const {
+        let mutbl = if ctxt.is_const_qualified(qtype) {
             Mutability::Immutable
         } else {
             Mutability::Mutable
@@ -333,6 +333,17 @@ impl TypeConverter {
 
             CTypeKind::Pointer(qtype) => self.convert_pointer(ctxt, qtype),
 
+            // C++ references become Rust references, with the mutability of their referent
+            CTypeKind::Reference(qtype) => {
+                let mutbl = if ctxt.is_const_qualified(qtype) {
+                    Mutability::Immutable
+                } else {
+                    Mutability::Mutable
+                };
+                let referent = self.convert(ctxt, qtype.ctype)?;
+                Ok(mk().set_mutbl(mutbl).ref_ty(referent))
+            }
+
             CTypeKind::Elaborated(ref ctype) => self.convert(ctxt, *ctype),
             CTypeKind::Decayed(ref ctype) => self.convert(ctxt, *ctype),
             CTypeKind::Paren(ref ctype) => self.convert(ctxt, *ctype),
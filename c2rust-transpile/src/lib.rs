@@ -4,15 +4,22 @@ mod diagnostics;
 
 pub mod build_files;
 pub mod c_ast;
+mod c_sources;
 pub mod cfg;
+pub mod cfg_variants;
 mod compile_cmds;
 pub mod convert_type;
+pub mod rename_map;
 pub mod renamer;
 pub mod rust_ast;
+mod shared_types;
+#[cfg(feature = "snapshot-tests")]
+pub mod snapshots;
+pub mod source_map;
 pub mod translator;
 pub mod with_stmts;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
@@ -23,6 +30,7 @@ use crate::compile_cmds::CompileCmd;
 use failure::Error;
 use itertools::Itertools;
 use log::{info, warn};
+use rayon::prelude::*;
 use regex::Regex;
 use serde_derive::Serialize;
 
@@ -31,16 +39,27 @@ use crate::c_ast::*;
 pub use crate::diagnostics::Diagnostic;
 use c2rust_ast_exporter as ast_exporter;
 
-use crate::build_files::{emit_build_files, get_build_dir, CrateConfig};
-use crate::compile_cmds::get_compile_commands;
+pub use crate::build_files::SystemLibs;
+use crate::build_files::{emit_binary_crate, emit_build_files, get_build_dir, CrateConfig};
+use crate::c_sources::CSource;
+use crate::cfg_variants::CfgVariant;
+pub use crate::compile_cmds::bazel::bazel_compile_commands;
+pub use crate::compile_cmds::cmake::cmake_compile_commands;
+pub use crate::compile_cmds::intercept::{autotools_compile_commands, run_as_compiler_wrapper};
+pub use crate::compile_cmds::meson::meson_compile_commands;
+use crate::compile_cmds::{get_compile_commands, LinkCmd, LinkType};
 use crate::convert_type::RESERVED_NAMES;
-pub use crate::translator::ReplaceMode;
+use crate::rename_map::{Rename, RenameMap};
+use crate::source_map::SourceMap;
+pub use crate::translator::{ReplaceMode, WasmTarget};
 use std::prelude::v1::Vec;
 
 type PragmaVec = Vec<(&'static str, Vec<&'static str>)>;
 type PragmaSet = indexmap::IndexSet<(&'static str, &'static str)>;
 type CrateSet = indexmap::IndexSet<ExternCrate>;
-type TranspileResult = Result<(PathBuf, PragmaVec, CrateSet), ()>;
+/// The output file of a translation, its pragmas and crates, and the functions it translated from
+/// a file whose other functions are left in C.
+type TranspileResult = Result<(PathBuf, PragmaVec, CrateSet, Vec<String>), ()>;
 
 /// Configuration settings for the translation process
 #[derive(Debug)]
@@ -60,6 +79,18 @@ pub struct TranspilerConfig {
     pub incremental_relooper: bool,
     pub fail_on_multiple: bool,
     pub filter: Option<Regex>,
+    /// Compile the C files matching this regex from `build.rs` instead of translating them,
+    /// translating only their declarations
+    pub keep_c: Option<Regex>,
+    /// Keep Objective-C files in Objective-C like the files matching `keep_c`, instead of
+    /// skipping them
+    pub skip_objc: bool,
+    /// Only translate the functions whose names match this regex, leaving the other functions
+    /// and the variables defined in C to be compiled from `build.rs` and linked in
+    pub filter_functions: Option<Regex>,
+    /// Only translate the functions annotated with `__attribute__((annotate("c2rust_translate")))`,
+    /// like `filter_functions`
+    pub filter_annotated_functions: bool,
     pub debug_relooper_labels: bool,
     pub prefix_function_names: Option<String>,
     pub translate_asm: bool,
@@ -79,9 +110,42 @@ pub struct TranspilerConfig {
     pub output_dir: Option<PathBuf>,
     pub translate_const_macros: bool,
     pub translate_fn_macros: bool,
+    /// Turn counted C `for` loops into Rust `for` loops over ranges
+    pub translate_for_loops: bool,
+    /// Pass the arguments and environment to the C `main` as owned buffers collected from
+    /// `std::env::args_os` and `std::env::vars_os`
+    pub idiomatic_main: bool,
+    /// Also generate a `run(args: &[&str]) -> i32` function that runs the C `main`
+    pub emit_main_run_fn: bool,
+    /// Back `errno` with the thread-local in `c2rust-errno` instead of the C library's
+    pub errno_shim: bool,
+    /// Translate calls to C library functions with exact `std` equivalents, like `abs` and
+    /// `sqrt`, into calls to those equivalents
+    pub translate_libc_idioms: bool,
+    /// Import the declarations of the zlib, OpenSSL, SQLite and libcurl headers from the
+    /// libraries' `-sys` crates instead of translating them
+    pub use_sys_crates: bool,
+    /// Name the types of anonymous struct and union members after their enclosing records, and
+    /// give the records accessor methods for the fields of their anonymous members
+    pub flatten_anonymous_members: bool,
     pub disable_refactoring: bool,
     pub preserve_unused_functions: bool,
+    /// Translate headers on their own: keep every declaration of each input header, including
+    /// its `static inline` functions, and make them all public
+    pub header_only: bool,
     pub log_level: log::LevelFilter,
+    /// Mark translated items with their C source locations and write a `.rs.map` source map
+    /// next to each output file
+    pub emit_source_map: bool,
+    /// Write a `.renames.json` manifest of the renamed C identifiers next to each output file
+    pub emit_rename_map: bool,
+    /// Format the translated code with `rustfmt`
+    pub format: bool,
+    /// Also translate each file with the extra clang arguments of each of these configurations,
+    /// and guard the items that differ between configurations with `#[cfg]` attributes
+    pub cfg_variants: Vec<CfgVariant>,
+    /// Translate for this WebAssembly target instead of the host
+    pub wasm_target: Option<WasmTarget>,
 
     // Options that control build files
     /// Emit `Cargo.toml` and `lib.rs`
@@ -89,6 +153,21 @@ pub struct TranspilerConfig {
     /// Names of translation units containing main functions that we should make
     /// into binaries
     pub binaries: Vec<String>,
+    /// How to link in the system libraries the C code was linked with
+    pub system_libs: SystemLibs,
+    /// Emit a Cargo workspace with a crate for each library and binary, and a crate for the types
+    /// they share, instead of making the top-level crate the workspace's root package
+    pub emit_workspace: bool,
+    /// Build library crates as `staticlib` and `cdylib` too, with a `build.rs` generating a C
+    /// header for their exported functions and variables with `cbindgen`
+    pub emit_capi: bool,
+    /// Also emit a `BUILD.bazel` for each crate, with labels relative to this Bazel workspace
+    pub bazel_workspace: Option<PathBuf>,
+
+    /// The number of files to translate at a time, or 0 for one per CPU
+    pub jobs: usize,
+    /// Free the statements and expressions of each function once it's translated
+    pub low_memory: bool,
 }
 
 impl TranspilerConfig {
@@ -102,6 +181,20 @@ impl TranspilerConfig {
         self.binaries.contains(&module_name)
     }
 
+    fn keeps_c(&self, file: &Path) -> bool {
+        (self.skip_objc && is_objc(file))
+            || self
+                .keep_c
+                .as_ref()
+                .map_or(false, |re| re.is_match(file.to_str().unwrap()))
+    }
+
+    /// Whether only some functions are translated, with `filter_functions` or
+    /// `filter_annotated_functions`
+    fn filters_functions(&self) -> bool {
+        self.filter_functions.is_some() || self.filter_annotated_functions
+    }
+
     fn check_if_all_binaries_used(
         &self,
         transpiled_modules: impl IntoIterator<Item = impl AsRef<Path>>,
@@ -132,7 +225,7 @@ impl TranspilerConfig {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ExternCrate {
     C2RustBitfields,
     C2RustAsmCasts,
@@ -140,6 +233,11 @@ pub enum ExternCrate {
     NumTraits,
     Memoffset,
     Libc,
+    C2RustErrno,
+    LibzSys,
+    OpensslSys,
+    Libsqlite3Sys,
+    CurlSys,
 }
 
 #[derive(Serialize)]
@@ -170,6 +268,11 @@ impl From<ExternCrate> for ExternCrateDetails {
             ExternCrate::NumTraits => Self::new("num-traits", "0.2", true),
             ExternCrate::Memoffset => Self::new("memoffset", "0.5", true),
             ExternCrate::Libc => Self::new("libc", "0.2", false),
+            ExternCrate::C2RustErrno => Self::new("c2rust-errno", "0.18", false),
+            ExternCrate::LibzSys => Self::new("libz-sys", "1.1", false),
+            ExternCrate::OpensslSys => Self::new("openssl-sys", "0.9", false),
+            ExternCrate::Libsqlite3Sys => Self::new("libsqlite3-sys", "0.26", false),
+            ExternCrate::CurlSys => Self::new("curl-sys", "0.4", false),
         }
     }
 }
@@ -201,6 +304,11 @@ fn str_to_ident_checked(filename: &Option<String>, check_reserved: bool) -> Opti
     })
 }
 
+/// Whether `file` is an Objective-C source file.
+fn is_objc(file: &Path) -> bool {
+    file.extension().map_or(false, |ext| ext == "m")
+}
+
 fn get_module_name(
     file: &Path,
     check_reserved: bool,
@@ -270,32 +378,35 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
         )
     });
 
-    // Specify path to system include dir on macOS 10.14 and later. Disable the blocks extension.
-    let clang_args: Vec<String> = get_extra_args_macos();
+    // Parse the C code for the wasm target, if there is one.  Otherwise, specify path to system
+    // include dir on macOS 10.14 and later, and disable the blocks extension.
+    let clang_args: Vec<String> = match tcfg.wasm_target {
+        Some(target) => vec![format!("--target={}", target.triple())],
+        None => get_extra_args_macos(),
+    };
     let mut clang_args: Vec<&str> = clang_args.iter().map(AsRef::as_ref).collect();
     clang_args.extend_from_slice(extra_clang_args);
 
+    // Files are translated in parallel, and each of them is exported by clang, converted, translated,
+    // printed and written on its own thread.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(tcfg.jobs)
+        .build()
+        .expect("Failed to start the translation threads");
+
     let mut top_level_ccfg = None;
     let mut workspace_members = vec![];
+    // With `emit_workspace`, crates are only emitted once all of them are translated.
+    let mut workspace_crates = vec![];
     let mut num_transpiled_files = 0;
     let mut transpiled_modules = Vec::new();
 
     for lcmd in &lcmds {
         let cmds = &lcmd.cmd_inputs;
-        let lcmd_name = lcmd
-            .output
-            .as_ref()
-            .map(|output| {
-                let output_path = Path::new(output);
-                output_path
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_owned()
-            })
-            .unwrap_or_else(|| tcfg.crate_name());
-        let build_dir = if lcmd.top_level {
+        let lcmd_name = lcmd.crate_name().unwrap_or_else(|| tcfg.crate_name());
+        // In a workspace, the top-level crate is a member like the others.
+        let top_level = lcmd.top_level && !tcfg.emit_workspace;
+        let build_dir = if top_level {
             build_dir.to_path_buf()
         } else {
             build_dir.join(&lcmd_name)
@@ -322,28 +433,34 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
             }
         }
 
-        let results = cmds
-            .iter()
-            .map(|cmd| {
-                transpile_single(
-                    &tcfg,
-                    cmd.abs_file(),
-                    &ancestor_path,
-                    &build_dir,
-                    &cc_db,
-                    &clang_args,
-                )
-            })
-            .collect::<Vec<TranspileResult>>();
+        // The commands are shared through `Rc`s, so only their paths are sent to the threads.
+        let input_paths = cmds.iter().map(|cmd| cmd.abs_file()).collect::<Vec<_>>();
+        let results = pool.install(|| {
+            input_paths
+                .par_iter()
+                .map(|input_path| {
+                    transpile_single(
+                        &tcfg,
+                        input_path.clone(),
+                        &ancestor_path,
+                        &build_dir,
+                        &cc_db,
+                        &clang_args,
+                    )
+                })
+                .collect::<Vec<TranspileResult>>()
+        });
         let mut modules = vec![];
         let mut modules_skipped = false;
         let mut pragmas = PragmaSet::new();
         let mut crates = CrateSet::new();
-        for res in results {
+        let mut translated_functions = HashMap::new();
+        for (cmd, res) in cmds.iter().zip(results) {
             match res {
-                Ok((module, pragma_vec, crate_set)) => {
+                Ok((module, pragma_vec, crate_set, functions)) => {
                     modules.push(module);
                     crates.extend(crate_set);
+                    translated_functions.insert(cmd.abs_file(), functions);
 
                     num_transpiled_files += 1;
                     for (key, vals) in pragma_vec {
@@ -359,6 +476,18 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
         }
         pragmas.sort();
         crates.sort();
+        let c_sources = cmds
+            .iter()
+            .filter(|cmd| tcfg.keeps_c(&cmd.abs_file()) || tcfg.filters_functions())
+            .enumerate()
+            .map(|(idx, cmd)| {
+                let mut c_source = CSource::new(cmd, &build_dir, idx);
+                if let Some(functions) = translated_functions.remove(&cmd.abs_file()) {
+                    c_source.weaken(&functions);
+                }
+                c_source
+            })
+            .collect::<Vec<_>>();
 
         transpiled_modules.extend(modules.iter().cloned());
 
@@ -374,10 +503,14 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
                 modules,
                 pragmas,
                 crates,
+                crate_deps: lcmd.deps.clone(),
+                c_sources,
                 link_cmd: lcmd,
             };
-            if lcmd.top_level {
+            if top_level {
                 top_level_ccfg = Some(ccfg);
+            } else if tcfg.emit_workspace {
+                workspace_crates.push((build_dir, ccfg));
             } else {
                 let crate_file = emit_build_files(&tcfg, &build_dir, Some(ccfg), None);
                 reorganize_definitions(&tcfg, &build_dir, crate_file)
@@ -393,6 +526,9 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
     }
 
     if tcfg.emit_build_files {
+        if tcfg.emit_workspace {
+            emit_workspace_crates(&tcfg, &build_dir, workspace_crates, &mut workspace_members);
+        }
         let crate_file =
             emit_build_files(&tcfg, &build_dir, top_level_ccfg, Some(workspace_members));
         reorganize_definitions(&tcfg, &build_dir, crate_file)
@@ -402,6 +538,98 @@ pub fn transpile(tcfg: TranspilerConfig, cc_db: &Path, extra_clang_args: &[&str]
     tcfg.check_if_all_binaries_used(&transpiled_modules);
 }
 
+/// Emit the member crates of the workspace in `build_dir`, adding them to `members`.  The types
+/// shared by the crates, counting each binary as a crate of its own, are first hoisted into a
+/// common `<crate name>_types` crate, which the crates using them depend on.
+fn emit_workspace_crates(
+    tcfg: &TranspilerConfig,
+    build_dir: &Path,
+    mut crates: Vec<(PathBuf, CrateConfig)>,
+    members: &mut Vec<String>,
+) {
+    // The library modules of each crate, followed by each of its binaries.
+    let mut groups = vec![];
+    let mut group_owners = vec![];
+    for (idx, (_, ccfg)) in crates.iter().enumerate() {
+        let (binaries, modules) = ccfg
+            .modules
+            .iter()
+            .cloned()
+            .partition::<Vec<_>, _>(|m| tcfg.is_binary(m));
+        groups.push(modules);
+        group_owners.push((idx, None));
+        for binary in binaries {
+            groups.push(vec![binary.clone()]);
+            group_owners.push((idx, Some(binary)));
+        }
+    }
+
+    let types_crate = format!("{}_types", tcfg.crate_name());
+    let types_dir = build_dir.join(&types_crate);
+    let types_module = types_dir.join("types.rs");
+    let users = shared_types::hoist_shared_type_files(&groups, &types_crate, &types_module)
+        .unwrap_or_else(|e| {
+            warn!("Hoisting shared types failed: {}", e);
+            None
+        })
+        .unwrap_or_default();
+    let has_types = users.contains(&true);
+    let types_link_cmd = LinkCmd {
+        inputs: vec![],
+        output: None,
+        libs: vec![],
+        lib_dirs: vec![],
+        r#type: LinkType::Rlib,
+        deps: vec![],
+        cmd_inputs: vec![],
+        top_level: false,
+    };
+    let mut types_ccfg = CrateConfig {
+        crate_name: types_crate.clone(),
+        modules: vec![types_module],
+        pragmas: PragmaSet::new(),
+        crates: CrateSet::new(),
+        crate_deps: vec![],
+        c_sources: vec![],
+        link_cmd: &types_link_cmd,
+    };
+    let mut binary_types = vec![];
+    for ((idx, binary), uses_types) in group_owners.into_iter().zip(users) {
+        if !uses_types {
+            continue;
+        }
+        let ccfg = &mut crates[idx].1;
+        types_ccfg.pragmas.extend(ccfg.pragmas.iter().cloned());
+        types_ccfg.crates.extend(ccfg.crates.iter().cloned());
+        match binary {
+            Some(binary) => binary_types.push(binary),
+            None => ccfg.crate_deps.push(types_crate.clone()),
+        }
+    }
+    if has_types {
+        types_ccfg.pragmas.sort();
+        types_ccfg.crates.sort();
+        emit_build_files(tcfg, &types_dir, Some(types_ccfg), None);
+        members.push(types_crate.clone());
+    }
+
+    for (crate_dir, ccfg) in crates {
+        for binary in ccfg.modules.iter().filter(|m| tcfg.is_binary(m)) {
+            let mut crate_deps = vec![ccfg.crate_name.clone()];
+            if binary_types.contains(binary) {
+                crate_deps.push(types_crate.clone());
+            }
+            let binary_crate = emit_binary_crate(tcfg, build_dir, &ccfg, binary, crate_deps);
+            members.push(binary_crate);
+        }
+        let crate_name = ccfg.crate_name.clone();
+        let crate_file = emit_build_files(tcfg, &crate_dir, Some(ccfg), None);
+        reorganize_definitions(tcfg, &crate_dir, crate_file)
+            .unwrap_or_else(|e| warn!("Reorganizing definitions failed: {}", e));
+        members.push(crate_name);
+    }
+}
+
 /// Ensure that clang can locate the system headers on macOS 10.14+.
 ///
 /// MacOS 10.14 does not have a `/usr/include` folder even if Xcode
@@ -434,6 +662,25 @@ fn get_extra_args_macos() -> Vec<String> {
     args
 }
 
+/// Format `src` with `rustfmt`, using the edition of the emitted crates.
+fn rustfmt(src: &str) -> Result<String, Error> {
+    let mut child = process::Command::new("rustfmt")
+        .args(["--edition", "2021", "--emit", "stdout"])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    // `rustfmt` reads all of its input before writing anything, so this can't deadlock.
+    child.stdin.take().unwrap().write_all(src.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(failure::err_msg(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
 fn invoke_refactor(_build_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
@@ -474,7 +721,6 @@ fn transpile_single(
         return Err(());
     }
 
-    let file = input_path.file_name().unwrap().to_str().unwrap();
     if !input_path.exists() {
         warn!(
             "Input C file {} does not exist, skipping!",
@@ -483,13 +729,148 @@ fn transpile_single(
         return Err(());
     }
 
+    if is_objc(&input_path) && !tcfg.skip_objc {
+        warn!(
+            "Skipping Objective-C file {}; pass --skip-objc to declare the C functions it defines",
+            input_path.display()
+        );
+        return Err(());
+    }
+
+    // Perform the translation
+    let (mut translated_string, mut pragmas, mut crates, renames) =
+        translate_config(tcfg, &input_path, cc_db, extra_clang_args)?;
+
+    if !tcfg.cfg_variants.is_empty() {
+        let mut translations = vec![translated_string];
+        for variant in &tcfg.cfg_variants {
+            let mut clang_args = extra_clang_args.to_vec();
+            clang_args.extend(variant.clang_args.iter().map(|s| s.as_str()));
+            let (translation, variant_pragmas, variant_crates, _) =
+                translate_config(tcfg, &input_path, cc_db, &clang_args)?;
+            translations.push(translation);
+            pragmas.extend(variant_pragmas);
+            crates.extend(variant_crates);
+        }
+        translated_string =
+            match cfg_variants::merge_translations(&tcfg.cfg_variants, &translations) {
+                Ok(merged) => merged,
+                Err(e) => {
+                    warn!(
+                        "Error: {}. Skipping {}; couldn't merge its configurations",
+                        e,
+                        input_path.display()
+                    );
+                    return Err(());
+                }
+            };
+    }
+
+    if tcfg.keeps_c(&input_path) {
+        translated_string = match c_sources::extern_declarations(&translated_string) {
+            Ok(declarations) => declarations,
+            Err(e) => {
+                warn!(
+                    "Error: {}. Skipping {}; couldn't extract its declarations",
+                    e,
+                    input_path.display()
+                );
+                return Err(());
+            }
+        };
+    }
+
+    // The C definitions of the functions translated from a file left partly in C are overridden
+    // by their translations.
+    let translated_functions = if tcfg.filters_functions() && !tcfg.keeps_c(&input_path) {
+        match c_sources::exported_functions(&translated_string) {
+            Ok(functions) => functions,
+            Err(e) => {
+                warn!(
+                    "Error: {}. Skipping {}; couldn't find its translated functions",
+                    e,
+                    input_path.display()
+                );
+                return Err(());
+            }
+        }
+    } else {
+        vec![]
+    };
+
+    // Format before building the source map, so that its line numbers match the output.
+    if tcfg.format {
+        match rustfmt(&translated_string) {
+            Ok(formatted) => translated_string = formatted,
+            Err(e) => warn!(
+                "rustfmt failed, {} may not be well-formatted: {}",
+                output_path.display(),
+                e
+            ),
+        }
+    }
+
+    let mut file = match File::create(&output_path) {
+        Ok(file) => file,
+        Err(e) => panic!(
+            "Unable to open file {} for writing: {}",
+            output_path.display(),
+            e
+        ),
+    };
+
+    match file.write_all(translated_string.as_bytes()) {
+        Ok(()) => (),
+        Err(e) => panic!(
+            "Unable to write translation to file {}: {}",
+            output_path.display(),
+            e
+        ),
+    };
+
+    if tcfg.emit_source_map {
+        let file_name = output_path.file_name().unwrap().to_string_lossy();
+        let source_map = SourceMap::from_translation(&file_name, &translated_string);
+        let map_path = output_path.with_extension("rs.map");
+        if let Err(e) = source_map.write(&map_path) {
+            panic!(
+                "Unable to write source map to file {}: {}",
+                map_path.display(),
+                e
+            );
+        }
+    }
+
+    if tcfg.emit_rename_map {
+        let file_name = output_path.file_name().unwrap().to_string_lossy();
+        let rename_map = RenameMap::new(&file_name, renames);
+        let map_path = output_path.with_extension("renames.json");
+        if let Err(e) = rename_map.write(&map_path) {
+            panic!(
+                "Unable to write rename map to file {}: {}",
+                map_path.display(),
+                e
+            );
+        }
+    }
+
+    Ok((output_path, pragmas, crates, translated_functions))
+}
+
+/// Translate `input_path` in the preprocessor configuration given by `extra_clang_args`.
+fn translate_config(
+    tcfg: &TranspilerConfig,
+    input_path: &Path,
+    cc_db: &Path,
+    extra_clang_args: &[&str],
+) -> Result<(String, PragmaVec, CrateSet, Vec<Rename>), ()> {
     if tcfg.verbose {
         println!("Additional Clang arguments: {}", extra_clang_args.join(" "));
     }
 
     // Extract the untyped AST from the CBOR file
     let untyped_context = match ast_exporter::get_untyped_ast(
-        input_path.as_path(),
+        input_path,
         cc_db,
         extra_clang_args,
         tcfg.debug_ast_exporter,
@@ -505,6 +886,7 @@ fn transpile_single(
         Ok(cxt) => cxt,
     };
 
+    let file = input_path.file_name().unwrap().to_str().unwrap();
     println!("Transpiling {}", file);
 
     if tcfg.dump_untyped_context {
@@ -520,6 +902,8 @@ fn transpile_single(
         }
         conv.typed_context
     };
+    // Free the untyped AST before translating, so that both ASTs aren't held at once.
+    drop(untyped_context);
 
     if tcfg.dump_typed_context {
         println!("Clang AST");
@@ -531,29 +915,11 @@ fn transpile_single(
         println!("{:#?}", Printer::new(io::stdout()).print(&typed_context));
     }
 
-    // Perform the translation
-    let (translated_string, pragmas, crates) =
-        translator::translate(typed_context, tcfg, input_path);
-
-    let mut file = match File::create(&output_path) {
-        Ok(file) => file,
-        Err(e) => panic!(
-            "Unable to open file {} for writing: {}",
-            output_path.display(),
-            e
-        ),
-    };
-
-    match file.write_all(translated_string.as_bytes()) {
-        Ok(()) => (),
-        Err(e) => panic!(
-            "Unable to write translation to file {}: {}",
-            output_path.display(),
-            e
-        ),
-    };
-
-    Ok((output_path, pragmas, crates))
+    Ok(translator::translate(
+        typed_context,
+        tcfg,
+        input_path.to_path_buf(),
+    ))
 }
 
 fn get_output_path(
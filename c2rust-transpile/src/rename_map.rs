@@ -0,0 +1,67 @@
+//! Manifests of the C identifiers that were renamed during translation.
+//!
+//! The translator renames identifiers that are Rust keywords (`type` becomes `type_0`) or that
+//! collide with other names.  With `emit_rename_map`, `NAME.renames.json` is written next to each
+//! output file `NAME.rs`, listing every renamed top-level item, enum constant, and field:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "foo.rs",
+//!   "renames": [
+//!     {
+//!       "kind": "function",
+//!       "c_name": "match",
+//!       "rust_name": "match_0",
+//!       "link_name": "match",
+//!       "source": "/path/to/foo.c:3:1"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `link_name` is the symbol name of externally visible functions and variables, which keeps the
+//! C spelling through `#[export_name]` or `#[link_name]` attributes on the translated item.
+//! Renamed locals aren't listed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rename {
+    /// The kind of declaration: `function`, `variable`, `struct`, `union`, `enum`, `typedef`,
+    /// `enum_constant`, `field`, or `macro`.
+    pub kind: &'static str,
+    pub c_name: String,
+    pub rust_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenameMap {
+    pub version: u32,
+    /// The name of the Rust file.
+    pub file: String,
+    pub renames: Vec<Rename>,
+}
+
+impl RenameMap {
+    pub fn new(file: &str, renames: Vec<Rename>) -> RenameMap {
+        RenameMap {
+            version: 1,
+            file: file.to_owned(),
+            renames,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+}
@@ -0,0 +1,271 @@
+//! Conversion of Doxygen-style C doc comments into rustdoc comments.
+//!
+//! C doc comments (`/** ... */`, `/*! ... */`, `/// ...` and `//! ...`) on declarations that
+//! become Rust items are emitted as `#[doc]` attributes, which print as `///` comments.  The
+//! Doxygen `\param` and `\return` commands (or `@param` and `@return`) are collected into
+//! `# Arguments` and `# Returns` sections, `\brief` is dropped, and `\code` blocks become Markdown
+//! code blocks.  Other commands are kept as they are.
+
+/// Strip the comment markers from a C doc comment and return its lines, or `None` if `comment`
+/// isn't a doc comment.
+fn doc_comment_lines(comment: &str) -> Option<Vec<&str>> {
+    let comment = comment.trim();
+    if let Some(body) = comment
+        .strip_prefix("/**")
+        .or_else(|| comment.strip_prefix("/*!"))
+    {
+        // `/***` starts a banner, `/**/` is empty, and `/**<` documents the previous member.
+        if body.starts_with(|c| matches!(c, '*' | '/' | '<')) {
+            return None;
+        }
+        let body = body.strip_suffix("*/").unwrap_or(body);
+        Some(
+            body.lines()
+                .map(|line| {
+                    let line = line.trim();
+                    line.strip_prefix('*').unwrap_or(line).trim()
+                })
+                .collect(),
+        )
+    } else {
+        comment
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                let body = line
+                    .strip_prefix("///")
+                    .or_else(|| line.strip_prefix("//!"))?;
+                if body.starts_with(|c| matches!(c, '/' | '<')) {
+                    return None;
+                }
+                Some(body.trim())
+            })
+            .collect()
+    }
+}
+
+/// Split a line starting with a Doxygen command into the command name and the rest of the line.
+fn split_command(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix(|c| c == '\\' || c == '@')?;
+    let len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    if len == 0 {
+        return None;
+    }
+    Some((&rest[..len], rest[len..].trim()))
+}
+
+/// Split the first word, such as a parameter name, off of `s`.
+fn split_word(s: &str) -> (&str, &str) {
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim()),
+        None => (s, ""),
+    }
+}
+
+fn list_item(name: &str, desc: &str) -> String {
+    if desc.is_empty() {
+        format!("* `{}`", name)
+    } else {
+        format!("* `{}` - {}", name, desc)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Section {
+    Description,
+    Param,
+    Return,
+}
+
+/// Convert a C doc comment into the lines of a rustdoc comment, without the leading `///`.
+/// Returns `None` if `comment` isn't a doc comment or has no text.
+pub fn to_rustdoc(comment: &str) -> Option<Vec<String>> {
+    let mut description: Vec<String> = Vec::new();
+    let mut params: Vec<String> = Vec::new();
+    let mut returns: Vec<String> = Vec::new();
+    let mut section = Section::Description;
+    let mut in_code = false;
+    let mut in_fence = false;
+
+    for line in doc_comment_lines(comment)? {
+        if in_code {
+            if let Some(("endcode", _)) = split_command(line) {
+                in_code = false;
+                description.push("```".to_owned());
+            } else {
+                description.push(line.to_owned());
+            }
+            continue;
+        }
+
+        match split_command(line) {
+            Some(("brief", rest)) | Some(("short", rest)) => {
+                section = Section::Description;
+                if !rest.is_empty() {
+                    description.push(rest.to_owned());
+                }
+                continue;
+            }
+            Some(("param", rest)) => {
+                // Drop the direction in `\param[in] name`.
+                let rest = match rest.strip_prefix('[') {
+                    Some(rest) => rest.split_once(']').map_or(rest, |(_, rest)| rest.trim()),
+                    None => rest,
+                };
+                let (name, desc) = split_word(rest);
+                params.push(list_item(name, desc));
+                section = Section::Param;
+                continue;
+            }
+            Some(("return", rest)) | Some(("returns", rest)) | Some(("result", rest)) => {
+                if !rest.is_empty() {
+                    returns.push(rest.to_owned());
+                }
+                section = Section::Return;
+                continue;
+            }
+            Some(("retval", rest)) => {
+                let (value, desc) = split_word(rest);
+                returns.push(list_item(value, desc));
+                section = Section::Return;
+                continue;
+            }
+            Some(("code", _)) => {
+                section = Section::Description;
+                in_code = true;
+                // C code isn't a Rust doctest.
+                description.push("```c".to_owned());
+                continue;
+            }
+            _ => {}
+        }
+
+        if line.is_empty() {
+            section = Section::Description;
+            description.push(String::new());
+            continue;
+        }
+
+        let target = match section {
+            Section::Description => {
+                // Fenced code blocks are Rust doctests by default.
+                if line.starts_with("```") {
+                    if !in_fence && line == "```" {
+                        description.push("```text".to_owned());
+                    } else {
+                        description.push(line.to_owned());
+                    }
+                    in_fence = !in_fence;
+                } else {
+                    description.push(line.to_owned());
+                }
+                continue;
+            }
+            Section::Param => &mut params,
+            Section::Return => &mut returns,
+        };
+        match target.last_mut() {
+            Some(last) if section == Section::Param || last.starts_with("* ") => {
+                last.push(' ');
+                last.push_str(line);
+            }
+            _ => target.push(line.to_owned()),
+        }
+    }
+    if in_code {
+        description.push("```".to_owned());
+    }
+
+    // Collapse runs of blank lines, and drop them from the start and end.
+    let mut lines: Vec<String> = Vec::new();
+    for line in description {
+        if !line.is_empty() || lines.last().map_or(false, |l| !l.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().map_or(false, |l| l.is_empty()) {
+        lines.pop();
+    }
+    for (heading, items) in [("# Arguments", params), ("# Returns", returns)] {
+        if items.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(heading.to_owned());
+        lines.push(String::new());
+        lines.extend(items);
+    }
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(comment: &str) -> Option<String> {
+        to_rustdoc(comment).map(|lines| lines.join("\n"))
+    }
+
+    #[test]
+    fn not_doc_comments() {
+        assert_eq!(doc("// plain"), None);
+        assert_eq!(doc("/* plain */"), None);
+        assert_eq!(doc("/**********/"), None);
+        assert_eq!(doc("/**< member */"), None);
+        assert_eq!(doc("//// separator"), None);
+        assert_eq!(doc("/** */"), None);
+    }
+
+    #[test]
+    fn block_comment() {
+        let c = "/**\n * Add two numbers.\n *\n * More detail.\n */";
+        assert_eq!(doc(c).unwrap(), "Add two numbers.\n\nMore detail.");
+    }
+
+    #[test]
+    fn line_comments() {
+        let c = "/// \\brief Frobnicate.\n/// Really.";
+        assert_eq!(doc(c).unwrap(), "Frobnicate.\nReally.");
+    }
+
+    #[test]
+    fn sections() {
+        let c = "/**\n * @brief Copy a buffer.\n *\n * \\param[out] dst the destination\n \
+                 * \\param src the source,\n *            which isn't modified\n \
+                 * \\return the number of bytes\n *         copied\n */";
+        assert_eq!(
+            doc(c).unwrap(),
+            "Copy a buffer.\n\
+             \n\
+             # Arguments\n\
+             \n\
+             * `dst` - the destination\n\
+             * `src` - the source, which isn't modified\n\
+             \n\
+             # Returns\n\
+             \n\
+             the number of bytes\n\
+             copied"
+        );
+    }
+
+    #[test]
+    fn fenced_code_blocks() {
+        let c = "/**\n * ```\n * f(1);\n * ```\n */";
+        assert_eq!(doc(c).unwrap(), "```text\nf(1);\n```");
+    }
+
+    #[test]
+    fn code_blocks() {
+        let c = "/**\n * Example:\n * \\code\n * f(1);\n * \\endcode\n */";
+        assert_eq!(doc(c).unwrap(), "Example:\n```c\nf(1);\n```");
+    }
+}
@@ -1,4 +1,5 @@
 pub mod comment_store;
+pub mod doc_comments;
 pub mod item_store;
 pub mod set_span;
 pub mod traverse;
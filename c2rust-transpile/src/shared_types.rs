@@ -0,0 +1,291 @@
+//! Hoisting of the types shared by the crates of a workspace into a crate of their own.
+//!
+//! Each translation unit gets its own copy of the types declared in the headers it includes, so
+//! a library and the programs using it are translated into crates with distinct, if identical,
+//! versions of the same structs, which can't be passed from one crate to the other.  With
+//! `emit_workspace`, each type defined identically by more than one crate is moved into the
+//! `types` module of a common crate, and the modules that defined it import it from there.
+//!
+//! Types with `impl` blocks, types defined differently by different crates, and types referring
+//! to types that stay behind aren't hoisted.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use indexmap::IndexMap;
+use proc_macro2::{TokenStream, TokenTree};
+use syn::__private::ToTokens;
+use syn::{Item, Visibility};
+
+use c2rust_ast_printer::pprust;
+
+/// The name of the type defined by `item`, if it defines one that can be hoisted.
+fn type_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Struct(s) => Some(s.ident.to_string()),
+        Item::Union(u) => Some(u.ident.to_string()),
+        Item::Enum(e) => Some(e.ident.to_string()),
+        Item::Type(t) => Some(t.ident.to_string()),
+        _ => None,
+    }
+}
+
+fn type_vis(item: &Item) -> Option<&Visibility> {
+    match item {
+        Item::Struct(s) => Some(&s.vis),
+        Item::Union(u) => Some(&u.vis),
+        Item::Enum(e) => Some(&e.vis),
+        Item::Type(t) => Some(&t.vis),
+        _ => None,
+    }
+}
+
+fn collect_idents(tokens: TokenStream, idents: &mut HashSet<String>) {
+    for tt in tokens {
+        match tt {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
+}
+
+/// The definitions of one type name across all crates.
+#[derive(Default)]
+struct Definitions {
+    tokens: Vec<String>,
+    crates: HashSet<usize>,
+    idents: HashSet<String>,
+}
+
+/// Move the types that more than one of `crates`, each the translated modules of a crate, define
+/// identically into a `types` module of `types_crate`, importing them in their place.  Returns
+/// the `types` module and which crates now use it, or `None` if no types are shared.
+pub fn hoist_shared_types(
+    crates: &mut [Vec<syn::File>],
+    types_crate: &str,
+) -> Option<(syn::File, Vec<bool>)> {
+    let mut defs = IndexMap::<String, Definitions>::new();
+    let mut has_impls = HashSet::new();
+    for (krate, files) in crates.iter().enumerate() {
+        for item in files.iter().flat_map(|file| &file.items) {
+            if let Item::Impl(imp) = item {
+                collect_idents(imp.self_ty.to_token_stream(), &mut has_impls);
+            }
+            let name = match type_name(item) {
+                Some(name) => name,
+                None => continue,
+            };
+            let tokens = item.to_token_stream();
+            let def = defs.entry(name).or_default();
+            collect_idents(tokens.clone(), &mut def.idents);
+            def.tokens.push(tokens.to_string());
+            def.crates.insert(krate);
+        }
+    }
+
+    let mut hoisted = defs
+        .iter()
+        .filter(|(name, def)| {
+            def.crates.len() > 1
+                && def.tokens.iter().all(|tokens| *tokens == def.tokens[0])
+                && !has_impls.contains(*name)
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<HashSet<_>>();
+    // Types referring to types that stay behind have to stay behind as well.
+    loop {
+        let stays = |ident: &String| defs.contains_key(ident) && !hoisted.contains(ident);
+        let unhoisted = hoisted
+            .iter()
+            .filter(|name| defs[*name].idents.iter().any(stays))
+            .cloned()
+            .collect::<Vec<_>>();
+        if unhoisted.is_empty() {
+            break;
+        }
+        for name in unhoisted {
+            hoisted.remove(&name);
+        }
+    }
+    if hoisted.is_empty() {
+        return None;
+    }
+
+    let mut uses = IndexMap::new();
+    let mut types = IndexMap::new();
+    let mut users = vec![false; crates.len()];
+    for (krate, files) in crates.iter_mut().enumerate() {
+        for file in files {
+            let hoists_from_file = file
+                .items
+                .iter()
+                .any(|item| type_name(item).map_or(false, |name| hoisted.contains(&name)));
+            if !hoists_from_file {
+                continue;
+            }
+            users[krate] = true;
+            for item in &mut file.items {
+                if let Item::Use(_) = item {
+                    // The `types` module needs the imports of the types, like `libc`.
+                    uses.entry(item.to_token_stream().to_string())
+                        .or_insert_with(|| item.clone());
+                    continue;
+                }
+                let name = match type_name(item).filter(|name| hoisted.contains(name)) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let vis = type_vis(item).unwrap().to_token_stream();
+                let import =
+                    syn::parse_str(&format!("{} use ::{}::types::{};", vis, types_crate, name))
+                        .unwrap();
+                let item = std::mem::replace(item, import);
+                types.entry(name).or_insert(item);
+            }
+        }
+    }
+
+    let module = syn::File {
+        shebang: None,
+        attrs: vec![],
+        items: uses.into_values().chain(types.into_values()).collect(),
+    };
+    Some((module, users))
+}
+
+/// Hoist the types shared by `crates`, each the paths of the translated modules of a crate, into
+/// the `types` module of `types_crate` at `types_path`, rewriting the modules that defined them.
+/// Returns which crates use the `types` module, or `None` if no types are shared.
+pub fn hoist_shared_type_files(
+    crates: &[Vec<PathBuf>],
+    types_crate: &str,
+    types_path: &Path,
+) -> Result<Option<Vec<bool>>, Error> {
+    let mut files = vec![];
+    for modules in crates {
+        let mut crate_files = vec![];
+        for module in modules {
+            crate_files.push(syn::parse_file(&fs::read_to_string(module)?)?);
+        }
+        files.push(crate_files);
+    }
+    let originals = files
+        .iter()
+        .map(|crate_files| {
+            crate_files
+                .iter()
+                .map(|file| file.to_token_stream().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let (types, users) = match hoist_shared_types(&mut files, types_crate) {
+        Some(hoisted) => hoisted,
+        None => return Ok(None),
+    };
+    for (krate, crate_files) in files.iter().enumerate() {
+        for (idx, file) in crate_files.iter().enumerate() {
+            if file.to_token_stream().to_string() != originals[krate][idx] {
+                fs::write(&crates[krate][idx], pprust::to_string(|| file.clone()))?;
+            }
+        }
+    }
+    if let Some(dir) = types_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(types_path, pprust::to_string(|| types))?;
+    Ok(Some(users))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> syn::File {
+        syn::parse_file(src).unwrap()
+    }
+
+    #[test]
+    fn hoists_identical_types() {
+        let lib = parse(
+            "\
+use ::libc;
+pub type size_t = libc::c_ulong;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct buf {
+    pub len: size_t,
+    pub data: *mut libc::c_char,
+}
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct state {
+    pub b: buf,
+    pub n: libc::c_int,
+}
+pub unsafe extern \"C\" fn buf_len(b: *const buf) -> size_t {
+    (*b).len
+}
+",
+        );
+        let main = parse(
+            "\
+use ::libc;
+pub type size_t = libc::c_ulong;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct buf {
+    pub len: size_t,
+    pub data: *mut libc::c_char,
+}
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct state {
+    pub b: buf,
+}
+",
+        );
+        let mut crates = vec![vec![lib], vec![main]];
+        let (types, users) = hoist_shared_types(&mut crates, "out_types").unwrap();
+        assert_eq!(users, [true, true]);
+        assert_eq!(
+            pprust::to_string(|| types),
+            "\
+use ::libc;
+pub type size_t = libc::c_ulong;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct buf {
+    pub len: size_t,
+    pub data: *mut libc::c_char,
+}
+"
+        );
+        assert_eq!(
+            pprust::to_string(|| crates[1][0].clone()),
+            "\
+use ::libc;
+pub use ::out_types::types::size_t;
+pub use ::out_types::types::buf;
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct state {
+    pub b: buf,
+}
+"
+        );
+    }
+
+    #[test]
+    fn keeps_types_referring_to_differing_types() {
+        let a = parse("pub struct inner { pub x: i32 }\npub struct outer { pub i: inner }\n");
+        let b = parse("pub struct inner { pub x: i64 }\npub struct outer { pub i: inner }\n");
+        let mut crates = vec![vec![a], vec![b]];
+        assert!(hoist_shared_types(&mut crates, "out_types").is_none());
+    }
+}
@@ -0,0 +1,390 @@
+//! Snapshot tests for the transpiler, built with the `snapshot-tests` feature.
+//!
+//! Each fixture is a C file with a `main` function.  Checking a fixture transpiles it into a
+//! binary crate, builds and runs that crate with `cargo run`, and compares its exit status and
+//! standard output with the snapshot stored next to the fixture (`NAME.snap` for `NAME.c`).  In
+//! bless mode, the snapshot is written instead, so adding coverage for a new C construct only
+//! takes a C file and one run with `--bless`.
+//!
+//! A fixture can start with comment lines of directives for the harness:
+//!
+//! - `// flags: --translate-for-loops` turns on the transpiler options the fixture exercises,
+//!   spelled like the `c2rust transpile` flags.  Fixtures are translated with the default options
+//!   otherwise.
+//! - `// CHECK: text` requires the generated Rust source to contain `text`, after the text of the
+//!   previous `CHECK` line, like FileCheck's `CHECK`.
+//! - `// CHECK-NOT: text` requires the generated source not to contain `text` anywhere.
+//!
+//! Comments in the generated source are skipped by the checks, since the C comments, including
+//! the directives themselves, are carried over into it.
+//!
+//! A header whose flags include `--header-only` is a fixture too, translated into a library
+//! crate.  There's no `main` to run, so its snapshot only records that the crate builds.  Other
+//! headers in the fixtures directory are only included by the C fixtures.
+
+use std::collections::HashSet;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::compile_cmds::CompileCmd;
+use crate::{transpile, ReplaceMode, SystemLibs, TranspilerConfig};
+
+/// Options for a snapshot test run.
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    /// The directory holding the `.c` and `.h` fixtures and their `.snap` snapshots.
+    pub fixtures_dir: PathBuf,
+    /// A scratch directory for the transpiled crates.  Build artifacts are shared between
+    /// fixtures through `work_dir/target`.
+    pub work_dir: PathBuf,
+    /// Write the snapshots instead of comparing against them.
+    pub bless: bool,
+    /// Only check the fixtures whose names contain this string.
+    pub filter: Option<String>,
+}
+
+/// The result of checking one fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The snapshot was created or updated in bless mode.
+    Blessed,
+    Failed(String),
+}
+
+/// Get the fixtures in `opts.fixtures_dir` that match `opts.filter`, in order by name.
+pub fn fixtures(opts: &SnapshotOptions) -> Vec<PathBuf> {
+    let entries = fs::read_dir(&opts.fixtures_dir).unwrap_or_else(|e| {
+        panic!(
+            "couldn't read fixtures directory {}: {}",
+            opts.fixtures_dir.display(),
+            e
+        )
+    });
+    let mut fixtures = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| match path.extension() {
+            Some(ext) if ext == "c" => true,
+            Some(ext) if ext == "h" => is_header_fixture(path),
+            _ => false,
+        })
+        .filter(|path| match opts.filter {
+            Some(ref filter) => fixture_name(path).contains(filter.as_str()),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    fixtures
+}
+
+fn is_header(c_file: &Path) -> bool {
+    c_file.extension().map_or(false, |ext| ext == "h")
+}
+
+/// Check if the header `h_file` is a fixture rather than a header included by other fixtures.
+fn is_header_fixture(h_file: &Path) -> bool {
+    let source = fs::read_to_string(h_file).unwrap_or_default();
+    let is_fixture = directives(&source, "flags")
+        .any(|flags| flags.split_whitespace().any(|flag| flag == "--header-only"));
+    is_fixture
+}
+
+fn fixture_name(c_file: &Path) -> String {
+    c_file
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .replace('-', "_")
+}
+
+/// Get the arguments of the `// NAME: ...` directives in `source`.
+fn directives<'a>(source: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> {
+    source.lines().filter_map(move |line| {
+        line.trim_start()
+            .strip_prefix("//")?
+            .trim_start()
+            .strip_prefix(name)?
+            .strip_prefix(':')
+            .map(str::trim)
+    })
+}
+
+/// Turn on the transpiler option of the `c2rust transpile` flag `flag`.
+fn apply_flag(tcfg: &mut TranspilerConfig, flag: &str) -> Result<(), String> {
+    match flag {
+        "--translate-for-loops" => tcfg.translate_for_loops = true,
+        "--idiomatic-main" => tcfg.idiomatic_main = true,
+        "--emit-main-run-fn" => tcfg.emit_main_run_fn = true,
+        "--translate-libc-idioms" => tcfg.translate_libc_idioms = true,
+        "--flatten-anonymous-members" => tcfg.flatten_anonymous_members = true,
+        "--translate-const-macros" => tcfg.translate_const_macros = true,
+        "--header-only" => {
+            tcfg.header_only = true;
+            tcfg.translate_const_macros = true;
+        }
+        _ => return Err(format!("the fixture uses the unknown flag `{}`", flag)),
+    }
+    Ok(())
+}
+
+fn fixture_config(output_dir: PathBuf, binaries: Vec<String>) -> TranspilerConfig {
+    TranspilerConfig {
+        dump_untyped_context: false,
+        dump_typed_context: false,
+        pretty_typed_context: false,
+        dump_function_cfgs: false,
+        json_function_cfgs: false,
+        dump_cfg_liveness: false,
+        dump_structures: false,
+        verbose: false,
+        debug_ast_exporter: false,
+
+        incremental_relooper: true,
+        fail_on_multiple: false,
+        filter: None,
+        keep_c: None,
+        skip_objc: false,
+        filter_functions: None,
+        filter_annotated_functions: false,
+        debug_relooper_labels: false,
+        prefix_function_names: None,
+        translate_asm: true,
+        use_c_loop_info: true,
+        use_c_multiple_info: true,
+        simplify_structures: true,
+        panic_on_translator_failure: false,
+        emit_modules: true,
+        fail_on_error: true,
+        replace_unsupported_decls: ReplaceMode::Extern,
+        translate_valist: true,
+        overwrite_existing: true,
+        reduce_type_annotations: false,
+        reorganize_definitions: false,
+        enabled_warnings: HashSet::new(),
+        emit_no_std: false,
+        output_dir: Some(output_dir),
+        translate_const_macros: false,
+        translate_fn_macros: false,
+        translate_for_loops: false,
+        idiomatic_main: false,
+        emit_main_run_fn: false,
+        errno_shim: false,
+        translate_libc_idioms: false,
+        use_sys_crates: false,
+        flatten_anonymous_members: false,
+        disable_refactoring: true,
+        preserve_unused_functions: false,
+        header_only: false,
+        log_level: log::LevelFilter::Warn,
+        emit_source_map: false,
+        emit_rename_map: false,
+        format: false,
+        cfg_variants: Vec::new(),
+        wasm_target: None,
+
+        emit_build_files: true,
+        binaries,
+        system_libs: SystemLibs::Link,
+        emit_workspace: false,
+        emit_capi: false,
+        bazel_workspace: None,
+
+        jobs: 1,
+        low_memory: false,
+    }
+}
+
+/// Transpile `c_file`, whose contents are `source`, into a crate in `crate_dir`, and return the
+/// generated Rust source.  Headers are translated into library crates, and C files into binary
+/// crates.
+fn transpile_fixture(c_file: &Path, source: &str, crate_dir: &Path) -> Result<String, String> {
+    let c_file = fs::canonicalize(c_file).map_err(|e| e.to_string())?;
+    let cmd = CompileCmd {
+        directory: c_file.parent().unwrap().to_path_buf(),
+        file: c_file.clone(),
+        arguments: vec!["clang".to_owned(), c_file.display().to_string()],
+        command: None,
+        output: None,
+    };
+    let cc_db = crate_dir.join("compile_commands.json");
+    let json = serde_json::to_string(&[cmd]).map_err(|e| e.to_string())?;
+    fs::write(&cc_db, json).map_err(|e| e.to_string())?;
+
+    let name = fixture_name(&c_file);
+    let binaries = if is_header(&c_file) {
+        vec![]
+    } else {
+        vec![name.clone()]
+    };
+    let mut tcfg = fixture_config(crate_dir.to_path_buf(), binaries);
+    for flags in directives(source, "flags") {
+        for flag in flags.split_whitespace() {
+            apply_flag(&mut tcfg, flag)?;
+        }
+    }
+    panic::catch_unwind(AssertUnwindSafe(|| transpile(tcfg, &cc_db, &[])))
+        .map_err(|_| "the transpiler panicked".to_owned())?;
+    if !crate_dir.join("Cargo.toml").exists() {
+        return Err("the transpiler didn't emit a crate".to_owned());
+    }
+    let rs_file = crate_dir.join("src").join(format!("{}.rs", name));
+    fs::read_to_string(&rs_file).map_err(|e| format!("couldn't read {}: {}", rs_file.display(), e))
+}
+
+/// Check the generated Rust source `rust` against the `CHECK` and `CHECK-NOT` directives of the
+/// fixture `source`.
+fn check_source(source: &str, rust: &str) -> Result<(), String> {
+    let code = rust
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut rest = code.as_str();
+    for pattern in directives(source, "CHECK") {
+        match rest.find(pattern) {
+            Some(pos) => rest = &rest[pos + pattern.len()..],
+            None => {
+                return Err(format!(
+                    "the generated source doesn't contain `{}` where expected\n--- source\n{}",
+                    pattern, rust
+                ))
+            }
+        }
+    }
+    for pattern in directives(source, "CHECK-NOT") {
+        if code.contains(pattern) {
+            return Err(format!(
+                "the generated source contains `{}`\n--- source\n{}",
+                pattern, rust
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run `cargo` with `args` on the crate in `crate_dir`, which is `cargo run` of the binary for
+/// C fixtures and `cargo build` of the library for headers, and render its exit status and output
+/// in the snapshot format.
+fn run_fixture(crate_dir: &Path, target_dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("cargo")
+        .args(args)
+        .args(["--quiet", "--manifest-path"])
+        .arg(crate_dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", target_dir)
+        .output()
+        .map_err(|e| format!("failed to run cargo: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = match output.status.code() {
+        Some(code) => code.to_string(),
+        None => return Err(format!("the program was killed by a signal\n{}", stderr)),
+    };
+    // Cargo exits with 101 when the build fails, but so does a program that panics.
+    if output.status.code() == Some(101) && stderr.contains("error: could not compile") {
+        return Err(format!("the translation failed to compile\n{}", stderr));
+    }
+    Ok(format!(
+        "exit status: {}\n--- stdout\n{}",
+        status,
+        String::from_utf8_lossy(&output.stdout)
+    ))
+}
+
+fn describe_mismatch(expected: &str, actual: &str) -> String {
+    let mut msg = String::from("the output doesn't match the snapshot\n");
+    for (i, (e, a)) in expected.lines().zip(actual.lines()).enumerate() {
+        if e != a {
+            msg.push_str(&format!("first difference at line {}:\n", i + 1));
+            msg.push_str(&format!("  expected: {}\n  actual:   {}\n", e, a));
+            break;
+        }
+    }
+    msg.push_str(&format!("--- expected\n{}--- actual\n{}", expected, actual));
+    msg
+}
+
+/// Transpile, build, and run the fixture `c_file`, and compare the result with its snapshot.
+/// Header fixtures are only built.
+pub fn check_fixture(opts: &SnapshotOptions, c_file: &Path) -> Outcome {
+    let name = fixture_name(c_file);
+    let crate_dir = opts.work_dir.join(&name);
+    if crate_dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&crate_dir) {
+            return Outcome::Failed(format!("couldn't clean {}: {}", crate_dir.display(), e));
+        }
+    }
+    if let Err(e) = fs::create_dir_all(&crate_dir) {
+        return Outcome::Failed(format!("couldn't create {}: {}", crate_dir.display(), e));
+    }
+
+    let source = match fs::read_to_string(c_file) {
+        Ok(source) => source,
+        Err(e) => return Outcome::Failed(format!("couldn't read {}: {}", c_file.display(), e)),
+    };
+    let cargo_args = if is_header(c_file) {
+        vec!["build", "--lib"]
+    } else {
+        vec!["run", "--bin", name.as_str()]
+    };
+    let actual = match transpile_fixture(c_file, &source, &crate_dir)
+        .and_then(|rust| check_source(&source, &rust))
+        .and_then(|()| run_fixture(&crate_dir, &opts.work_dir.join("target"), &cargo_args))
+    {
+        Ok(actual) => actual,
+        Err(e) => return Outcome::Failed(e),
+    };
+
+    let snap_file = c_file.with_extension("snap");
+    let expected = fs::read_to_string(&snap_file).ok();
+    if expected.as_deref() == Some(actual.as_str()) {
+        return Outcome::Passed;
+    }
+    if opts.bless {
+        return match fs::write(&snap_file, &actual) {
+            Ok(()) => Outcome::Blessed,
+            Err(e) => Outcome::Failed(format!("couldn't write {}: {}", snap_file.display(), e)),
+        };
+    }
+    match expected {
+        Some(expected) => Outcome::Failed(describe_mismatch(&expected, &actual)),
+        None => Outcome::Failed(format!(
+            "{} doesn't exist; run with --bless to create it",
+            snap_file.display()
+        )),
+    }
+}
+
+/// Check every fixture selected by `opts`, printing the result of each one.  Returns the number
+/// of fixtures that failed.
+pub fn run_snapshots(opts: &SnapshotOptions) -> usize {
+    let fixtures = fixtures(opts);
+    println!("running {} snapshot tests", fixtures.len());
+    let mut failures = Vec::new();
+    let mut blessed = 0;
+    for c_file in &fixtures {
+        let name = fixture_name(c_file);
+        match check_fixture(opts, c_file) {
+            Outcome::Passed => println!("snapshot {} ... ok", name),
+            Outcome::Blessed => {
+                println!("snapshot {} ... blessed", name);
+                blessed += 1;
+            }
+            Outcome::Failed(msg) => {
+                println!("snapshot {} ... FAILED", name);
+                failures.push((name, msg));
+            }
+        }
+    }
+
+    for (name, msg) in &failures {
+        println!("\n---- {} ----\n{}", name, msg);
+    }
+    println!(
+        "\nsnapshot result: {} passed; {} blessed; {} failed",
+        fixtures.len() - blessed - failures.len(),
+        blessed,
+        failures.len()
+    );
+    failures.len()
+}
@@ -0,0 +1,125 @@
+//! Source maps relating the translated Rust code to the C code it came from.
+//!
+//! With `emit_source_map`, the translator marks each top-level item with a
+//! `#[c2rust::src = "FILE:LINE:COL"]` attribute giving the location of its C declaration, and
+//! `NAME.rs.map` is written next to each output file `NAME.rs`.  The map is built from those
+//! attributes after pretty-printing, so it always agrees with the emitted code:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "foo.rs",
+//!   "mappings": [
+//!     { "line": 12, "source": "foo.c", "source_line": 3, "source_column": 1 }
+//!   ]
+//! }
+//! ```
+//!
+//! `line` is the 1-based line of the item itself, after its attributes and doc comments.  Source
+//! paths are relative to the directory of the translated C file when they're inside it.
+//! Statements aren't mapped.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mapping {
+    /// The line of the Rust item, starting from 1.
+    pub line: usize,
+    pub source: String,
+    pub source_line: u64,
+    pub source_column: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceMap {
+    pub version: u32,
+    /// The name of the Rust file.
+    pub file: String,
+    pub mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// Build the source map of `translation`, the contents of the Rust file `file`, from its
+    /// `c2rust::src` attributes.
+    pub fn from_translation(file: &str, translation: &str) -> SourceMap {
+        let src_attr = Regex::new(r#"^\s*#\[c2rust::src = "(.*):(\d+):(\d+)"\]\s*$"#).unwrap();
+        let mut mappings = Vec::new();
+        let mut pending = None;
+        for (i, line) in translation.lines().enumerate() {
+            if let Some(caps) = src_attr.captures(line) {
+                pending = Some((
+                    caps[1].to_owned(),
+                    caps[2].parse().unwrap(),
+                    caps[3].parse().unwrap(),
+                ));
+                continue;
+            }
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("#[") || trimmed.starts_with("///") {
+                continue;
+            }
+            if let Some((source, source_line, source_column)) = pending.take() {
+                mappings.push(Mapping {
+                    line: i + 1,
+                    source,
+                    source_line,
+                    source_column,
+                });
+            }
+        }
+        SourceMap {
+            version: 1,
+            file: file.to_owned(),
+            mappings,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_items_after_attributes() {
+        let translation = "\
+use ::libc;
+#[no_mangle]
+#[c2rust::src = \"foo.c:3:1\"]
+pub unsafe extern \"C\" fn f() {}
+/// Docs.
+#[c2rust::src = \"include/foo.h:10:5\"]
+#[derive(Copy, Clone)]
+pub struct S {
+    pub x: libc::c_int,
+}
+";
+        let map = SourceMap::from_translation("foo.rs", translation);
+        assert_eq!(
+            map.mappings,
+            vec![
+                Mapping {
+                    line: 4,
+                    source: "foo.c".to_owned(),
+                    source_line: 3,
+                    source_column: 1,
+                },
+                Mapping {
+                    line: 8,
+                    source: "include/foo.h".to_owned(),
+                    source_line: 10,
+                    source_column: 5,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Translation of anonymous struct and union members, enabled by `flatten_anonymous_members`.
+//!
+//! C lets a struct or union contain an unnamed struct or union whose fields are accessed as if
+//! they were fields of the enclosing record.  Rust has no such members, so the anonymous member
+//! becomes a field named `c2rust_unnamed` of a type named `C2RustUnnamed_N`, and `s.x` becomes
+//! `s.c2rust_unnamed.x`.  The translated code is correct, but Rust code written against it has to
+//! spell out the placeholders, whose numbers change whenever anonymous types are added elsewhere.
+//!
+//! With the option, the types of anonymous members (and of named fields whose struct or union
+//! type has no name) are named after the enclosing record instead: `outer_anon` for an anonymous
+//! member of `struct outer` and `outer_field` for `struct { ... } field`.  Every record with
+//! anonymous members also gets a pair of accessor methods for each field reached through them,
+//! `x(&self) -> &T` and `x_mut(&mut self) -> &mut T`, which are `unsafe` when the path to the field
+//! goes through a union.  A struct whose only anonymous member is a struct dereferences to it as
+//! well, so `s.x` keeps working.
+//!
+//! Records that are packed, split for alignment or hold a `va_list` get no accessors, since their
+//! fields can't be borrowed or the `impl` would need a lifetime.
+
+use super::*;
+
+/// A field of an anonymous member, and the path of fields to it from the enclosing record.
+struct FlattenedField {
+    path: Vec<String>,
+    typ: CQualTypeId,
+    /// The path goes through a union, so following it is unsafe.
+    through_union: bool,
+}
+
+impl<'c> Translation<'c> {
+    /// Get the fields of the struct or union `decl_id`, and whether it's a union.
+    fn record_fields(&self, decl_id: CDeclId) -> Option<(&[CFieldId], bool)> {
+        match self.ast_context[decl_id].kind {
+            CDeclKind::Struct {
+                fields: Some(ref fields),
+                ..
+            } => Some((fields, false)),
+            CDeclKind::Union {
+                fields: Some(ref fields),
+                ..
+            } => Some((fields, true)),
+            _ => None,
+        }
+    }
+
+    /// Get the struct or union without a name that `typ` is.
+    fn unnamed_record(&self, typ: CTypeId) -> Option<CDeclId> {
+        let decl_id = match self.ast_context.resolve_type(typ).kind {
+            CTypeKind::Struct(decl_id) | CTypeKind::Union(decl_id) => decl_id,
+            _ => return None,
+        };
+        let is_prenamed = self
+            .ast_context
+            .prenamed_decls
+            .values()
+            .any(|&id| id == decl_id);
+        match self.ast_context[decl_id].kind {
+            CDeclKind::Struct { name: None, .. } | CDeclKind::Union { name: None, .. }
+                if !is_prenamed =>
+            {
+                Some(decl_id)
+            }
+            _ => None,
+        }
+    }
+
+    /// Name the unnamed struct and union types of the fields of `decl_id` after `name`, the name
+    /// of `decl_id`, and the same for their own fields.
+    fn name_member_types(
+        &self,
+        decl_id: CDeclId,
+        name: &str,
+        names: &mut IndexMap<CDeclId, String>,
+    ) {
+        let fields = match self.record_fields(decl_id) {
+            Some((fields, _)) => fields,
+            None => return,
+        };
+        for &field_id in fields {
+            if let CDeclKind::Field {
+                name: ref field_name,
+                typ,
+                ..
+            } = self.ast_context[field_id].kind
+            {
+                let member_id = match self.unnamed_record(typ.ctype) {
+                    Some(member_id) if !names.contains_key(&member_id) => member_id,
+                    _ => continue,
+                };
+                let member_name = if field_name.is_empty() {
+                    format!("{}_anon", name)
+                } else {
+                    format!("{}_{}", name, field_name)
+                };
+                names.insert(member_id, member_name.clone());
+                self.name_member_types(member_id, &member_name, names);
+            }
+        }
+    }
+
+    /// Get the names of the unnamed struct and union types of fields, based on the names of the
+    /// records containing them.
+    pub(super) fn member_type_names(&self) -> IndexMap<CDeclId, String> {
+        let mut names = IndexMap::new();
+        if !self.tcfg.flatten_anonymous_members {
+            return names;
+        }
+        for (&decl_id, decl) in self.ast_context.iter_decls() {
+            let name = match decl.kind {
+                CDeclKind::Struct {
+                    name: Some(ref name),
+                    ..
+                }
+                | CDeclKind::Union {
+                    name: Some(ref name),
+                    ..
+                } => name,
+                // Records named by typedefs take the typedef's name
+                CDeclKind::Typedef { ref name, .. }
+                    if self.ast_context.prenamed_decls.contains_key(&decl_id) =>
+                {
+                    let record_id = self.ast_context.prenamed_decls[&decl_id];
+                    self.name_member_types(record_id, name, &mut names);
+                    continue;
+                }
+                _ => continue,
+            };
+            self.name_member_types(decl_id, name, &mut names);
+        }
+        names
+    }
+
+    /// Collect the fields reached through the anonymous members of `decl_id`, which is reached by
+    /// `path` from the record the accessors are for.  Returns `None` if there's a field that
+    /// accessors can't be generated for.
+    fn flattened_fields(
+        &self,
+        decl_id: CDeclId,
+        path: &[String],
+        through_union: bool,
+        out: &mut Vec<FlattenedField>,
+    ) -> Option<()> {
+        let (fields, is_union) = self.record_fields(decl_id)?;
+        if self.ast_context.is_packed_struct_decl(decl_id)
+            || self.ast_context.has_inner_struct_decl(decl_id)
+        {
+            return None;
+        }
+        let through_union = through_union || is_union;
+        for &field_id in fields {
+            let (field_name, typ, bitfield_width) = match self.ast_context[field_id].kind {
+                CDeclKind::Field {
+                    ref name,
+                    typ,
+                    bitfield_width,
+                    ..
+                } => (name, typ, bitfield_width),
+                _ => return None,
+            };
+            if self.ast_context.is_va_list(typ.ctype) {
+                return None;
+            }
+            let rust_name = self
+                .type_converter
+                .borrow()
+                .resolve_field_name(Some(decl_id), field_id)?;
+            let mut field_path = path.to_vec();
+            field_path.push(rust_name);
+
+            match self.unnamed_record(typ.ctype) {
+                Some(member_id) if field_name.is_empty() => {
+                    self.flattened_fields(member_id, &field_path, through_union, out)?;
+                }
+                // Fields of the record itself need no accessors
+                _ if path.is_empty() => {}
+                _ if bitfield_width.is_some() => {}
+                _ => out.push(FlattenedField {
+                    path: field_path,
+                    typ,
+                    through_union,
+                }),
+            }
+        }
+        Some(())
+    }
+
+    /// Generate the accessor methods, and the `Deref` impls if there's a single anonymous struct
+    /// member, of the struct or union `decl_id`.
+    fn convert_anonymous_member_accessors(&self, decl_id: CDeclId) -> TranslationResult<Vec<Item>> {
+        let (fields, is_union) = match self.record_fields(decl_id) {
+            Some(record) => record,
+            None => return Ok(vec![]),
+        };
+        let anonymous_members = fields
+            .iter()
+            .filter_map(|&field_id| match self.ast_context[field_id].kind {
+                CDeclKind::Field { ref name, typ, .. } if name.is_empty() => {
+                    Some((field_id, self.unnamed_record(typ.ctype)?))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if anonymous_members.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut flattened = Vec::new();
+        if self
+            .flattened_fields(decl_id, &[], false, &mut flattened)
+            .is_none()
+        {
+            return Ok(vec![]);
+        }
+        let name = match self.type_converter.borrow().resolve_decl_name(decl_id) {
+            Some(name) => name,
+            None => return Ok(vec![]),
+        };
+        let record_ty = mk().path_ty(vec![name.as_str()]);
+
+        let mut method_names = IndexSet::new();
+        let mut methods: Vec<ImplItem> = Vec::new();
+        for field in flattened {
+            let getter = mk().ident(field.path.last().unwrap());
+            let setter = mk().ident(format!("{}_mut", field.path.last().unwrap()));
+            if !method_names.insert(getter.to_string()) || !method_names.insert(setter.to_string())
+            {
+                warn!(
+                    "Skipping the accessors of `{}` in `{}`, whose names are taken",
+                    getter, name
+                );
+                continue;
+            }
+            let path = field
+                .path
+                .iter()
+                .map(|name| mk().ident(name))
+                .collect::<Vec<_>>();
+            let ty = self.convert_type(field.typ.ctype)?;
+            let unsafety = if field.through_union {
+                Some(syn::token::Unsafe::default())
+            } else {
+                None
+            };
+            methods.push(syn::parse_quote! {
+                pub #unsafety fn #getter(&self) -> &#ty {
+                    &self.#(#path).*
+                }
+            });
+            methods.push(syn::parse_quote! {
+                pub #unsafety fn #setter(&mut self) -> &mut #ty {
+                    &mut self.#(#path).*
+                }
+            });
+        }
+
+        let mut items = vec![];
+        if !methods.is_empty() {
+            items.push(*mk().impl_item(record_ty.clone(), methods));
+        }
+
+        if let [(field_id, member_id)] = anonymous_members[..] {
+            let member_is_struct =
+                matches!(self.ast_context[member_id].kind, CDeclKind::Struct { .. });
+            let field_name = self
+                .type_converter
+                .borrow()
+                .resolve_field_name(Some(decl_id), field_id);
+            let member_name = self.type_converter.borrow().resolve_decl_name(member_id);
+            if let (false, true, Some(field_name), Some(member_name)) =
+                (is_union, member_is_struct, field_name, member_name)
+            {
+                let field = mk().ident(field_name);
+                let member_ty = mk().path_ty(vec![member_name]);
+                let deref: ItemImpl = syn::parse_quote! {
+                    impl ::core::ops::Deref for #record_ty {
+                        type Target = #member_ty;
+                        fn deref(&self) -> &#member_ty {
+                            &self.#field
+                        }
+                    }
+                };
+                let deref_mut: ItemImpl = syn::parse_quote! {
+                    impl ::core::ops::DerefMut for #record_ty {
+                        fn deref_mut(&mut self) -> &mut #member_ty {
+                            &mut self.#field
+                        }
+                    }
+                };
+                items.push(Item::Impl(deref));
+                items.push(Item::Impl(deref_mut));
+            }
+        }
+        Ok(items)
+    }
+
+    /// Add the accessors of the records with anonymous members to the items of the files
+    /// declaring the records.
+    pub(super) fn insert_anonymous_member_accessors(&self) {
+        if !self.tcfg.flatten_anonymous_members {
+            return;
+        }
+        for (&decl_id, decl) in self.ast_context.iter_decls() {
+            if !matches!(
+                decl.kind,
+                CDeclKind::Struct { .. } | CDeclKind::Union { .. }
+            ) {
+                continue;
+            }
+            match self.convert_anonymous_member_accessors(decl_id) {
+                Ok(items) => {
+                    let decl_file_id = self.ast_context.file_id(decl);
+                    for item in items {
+                        self.insert_item(Box::new(item), decl_id);
+                    }
+                    // The accessors of a record in a header submodule need the field types of
+                    // its anonymous members too
+                    if self.tcfg.reorganize_definitions {
+                        if let Some(file_id) = decl_file_id.filter(|&id| id != self.main_file) {
+                            self.import_member_types(decl_id, file_id);
+                        }
+                    }
+                }
+                Err(e) => warn!("Skipping the accessors of a record: {}", e),
+            }
+        }
+    }
+
+    /// Import the field types of the anonymous members of `decl_id` into the submodule of
+    /// `file_id`.
+    fn import_member_types(&self, decl_id: CDeclId, file_id: FileId) {
+        let fields = match self.record_fields(decl_id) {
+            Some((fields, _)) => fields,
+            None => return,
+        };
+        for &field_id in fields {
+            if let CDeclKind::Field { ref name, typ, .. } = self.ast_context[field_id].kind {
+                if let Some(member_id) = self.unnamed_record(typ.ctype) {
+                    if name.is_empty() {
+                        self.generate_submodule_imports(member_id, Some(file_id));
+                        self.import_member_types(member_id, file_id);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -652,6 +652,7 @@ impl<'c> Translation<'c> {
                 "Inline assembly translation not enabled.",
             ));
         }
+        self.check_wasm_arch("Inline assembly")?;
 
         let arch = match parse_arch(&self.ast_context.target) {
             Some(arch) => arch,
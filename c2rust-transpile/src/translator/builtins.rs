@@ -16,6 +16,49 @@ enum LibcFnArgType {
     Int,
 }
 
+/// A builtin that calls a method on its only argument
+#[derive(Copy, Clone)]
+struct MethodBuiltin {
+    /// The method, e.g. `leading_zeros` for `__builtin_clz`
+    method: &'static str,
+    /// Whether the method's result is cast to C `int`, for counts and predicates
+    int_result: bool,
+}
+
+/// Get the method a single-argument builtin translates to, if it's one of those.
+fn method_builtin(name: &str) -> Option<MethodBuiltin> {
+    let (method, int_result) = match name {
+        "__builtin_clz" | "__builtin_clzl" | "__builtin_clzll" | "__builtin_clzs" => {
+            ("leading_zeros", true)
+        }
+        "__builtin_ctz" | "__builtin_ctzl" | "__builtin_ctzll" | "__builtin_ctzs" => {
+            ("trailing_zeros", true)
+        }
+        "__builtin_popcount" | "__builtin_popcountl" | "__builtin_popcountll" => {
+            ("count_ones", true)
+        }
+        "__builtin_bswap16" | "__builtin_bswap32" | "__builtin_bswap64" => ("swap_bytes", false),
+        "__builtin_bitreverse8"
+        | "__builtin_bitreverse16"
+        | "__builtin_bitreverse32"
+        | "__builtin_bitreverse64" => ("reverse_bits", false),
+        // `abs(INT_MIN)` is undefined in C, so wrapping is as good as any other result
+        "__builtin_abs" | "__builtin_labs" | "__builtin_llabs" => ("wrapping_abs", false),
+        "__builtin_fabs" | "__builtin_fabsf" | "__builtin_fabsl" => ("abs", false),
+        "__builtin_sqrt" | "__builtin_sqrtf" => ("sqrt", false),
+        "__builtin_floor" | "__builtin_floorf" => ("floor", false),
+        "__builtin_ceil" | "__builtin_ceilf" => ("ceil", false),
+        "__builtin_trunc" | "__builtin_truncf" => ("trunc", false),
+        "__builtin_round" | "__builtin_roundf" => ("round", false),
+        "__builtin_isfinite" => ("is_finite", true),
+        "__builtin_isnan" => ("is_nan", true),
+        "__builtin_isinf" => ("is_infinite", true),
+        "__builtin_isnormal" => ("is_normal", true),
+        _ => return None,
+    };
+    Some(MethodBuiltin { method, int_result })
+}
+
 impl<'c> Translation<'c> {
     /// Convert a call to a builtin function to a Rust expression
     pub fn convert_builtin(
@@ -44,6 +87,18 @@ impl<'c> Translation<'c> {
             }
         };
 
+        if let Some(MethodBuiltin { method, int_result }) = method_builtin(builtin_name) {
+            let val = self.convert_expr(ctx.used(), args[0])?;
+            return Ok(val.map(|x| {
+                let call = mk().method_call_expr(x, method, vec![]);
+                if int_result {
+                    mk().cast_expr(call, mk().path_ty(vec!["i32"]))
+                } else {
+                    call
+                }
+            }));
+        }
+
         match builtin_name {
             "__builtin_huge_valf" => Ok(WithStmts::new_val(
                 mk().abs_path_expr(vec!["core", "f32", "INFINITY"]),
@@ -103,40 +158,48 @@ impl<'c> Translation<'c> {
                     mk().ifte_expr(cond, block, Some(zeros_plus1))
                 }))
             }
-            "__builtin_clz" | "__builtin_clzl" | "__builtin_clzll" => {
+            "__builtin_parity" | "__builtin_parityl" | "__builtin_parityll" => {
+                // parity(x) -> (x.count_ones() & 1) as i32
                 let val = self.convert_expr(ctx.used(), args[0])?;
                 Ok(val.map(|x| {
-                    let zeros = mk().method_call_expr(x, "leading_zeros", vec![]);
-                    mk().cast_expr(zeros, mk().path_ty(vec!["i32"]))
+                    let ones = mk().method_call_expr(x, "count_ones", vec![]);
+                    let one = mk().lit_expr(mk().int_lit(1, ""));
+                    let parity = mk().binary_expr(BinOp::BitAnd(Default::default()), ones, one);
+                    mk().cast_expr(parity, mk().path_ty(vec!["i32"]))
                 }))
             }
-            "__builtin_ctz" | "__builtin_ctzl" | "__builtin_ctzll" => {
+            "__builtin_clrsb" | "__builtin_clrsbl" | "__builtin_clrsbll" => {
+                // let v = x;
+                // (if v < 0 { !v } else { v }).leading_zeros() as i32 - 1
                 let val = self.convert_expr(ctx.used(), args[0])?;
-                Ok(val.map(|x| {
-                    let zeros = mk().method_call_expr(x, "trailing_zeros", vec![]);
-                    mk().cast_expr(zeros, mk().path_ty(vec!["i32"]))
-                }))
+                val.and_then(|x| {
+                    let name = self.renamer.borrow_mut().fresh();
+                    let v = || mk().ident_expr(&name);
+                    let local = mk().local(mk().ident_pat(&name), None, Some(x));
+                    let zero = mk().lit_expr(mk().int_lit(0, ""));
+                    let is_neg = mk().binary_expr(BinOp::Lt(Default::default()), v(), zero);
+                    let not_v = mk().unary_expr(UnOp::Not(Default::default()), v());
+                    let not_v_block = mk().block(vec![mk().expr_stmt(not_v)]);
+                    let positive = mk().ifte_expr(is_neg, not_v_block, Some(v()));
+                    let zeros =
+                        mk().method_call_expr(mk().paren_expr(positive), "leading_zeros", vec![]);
+                    let zeros = mk().cast_expr(zeros, mk().path_ty(vec!["i32"]));
+                    let one = mk().lit_expr(mk().int_lit(1, ""));
+                    let clrsb = mk().binary_expr(BinOp::Sub(Default::default()), zeros, one);
+                    Ok(WithStmts::new(
+                        vec![mk().local_stmt(Box::new(local))],
+                        clrsb,
+                    ))
+                })
             }
-            "__builtin_bswap16" | "__builtin_bswap32" | "__builtin_bswap64" => {
-                let val = self.convert_expr(ctx.used(), args[0])?;
-                Ok(val.map(|x| mk().method_call_expr(x, "swap_bytes", vec![])))
+            "__builtin_copysign" | "__builtin_copysignf" => {
+                self.convert_binary_method_builtin(ctx, "copysign", args)
             }
-            "__builtin_fabs" | "__builtin_fabsf" | "__builtin_fabsl" => {
-                let val = self.convert_expr(ctx.used(), args[0])?;
-                Ok(val.map(|x| mk().method_call_expr(x, "abs", vec![])))
+            "__builtin_fmax" | "__builtin_fmaxf" => {
+                self.convert_binary_method_builtin(ctx, "max", args)
             }
-            "__builtin_isfinite" | "__builtin_isnan" => {
-                let val = self.convert_expr(ctx.used(), args[0])?;
-
-                let seg = match builtin_name {
-                    "__builtin_isfinite" => "is_finite",
-                    "__builtin_isnan" => "is_nan",
-                    _ => panic!(),
-                };
-                Ok(val.map(|x| {
-                    let call = mk().method_call_expr(x, seg, vec![]);
-                    mk().cast_expr(call, mk().path_ty(vec!["i32"]))
-                }))
+            "__builtin_fmin" | "__builtin_fminf" => {
+                self.convert_binary_method_builtin(ctx, "min", args)
             }
             "__builtin_isinf_sign" => {
                 // isinf_sign(x) -> fabs(x) == infinity ? (signbit(x) ? -1 : 1) : 0
@@ -162,14 +225,17 @@ impl<'c> Translation<'c> {
                 // https://github.com/llvm-mirror/llvm/blob/master/lib/CodeGen/IntrinsicLowering.cpp#L470
                 Ok(WithStmts::new_val(mk().lit_expr(mk().int_lit(1, "i32"))))
             }
-            "__builtin_expect" => self.convert_expr(ctx.used(), args[0]),
+            "__builtin_expect" | "__builtin_expect_with_probability" => {
+                self.convert_expect(ctx, args[0], args[1])
+            }
+            "__builtin_assume" => {
+                self.use_feature("core_intrinsics");
 
-            "__builtin_popcount" | "__builtin_popcountl" | "__builtin_popcountll" => {
-                let val = self.convert_expr(ctx.used(), args[0])?;
-                Ok(val.map(|x| {
-                    let zeros = mk().method_call_expr(x, "count_ones", vec![]);
-                    mk().cast_expr(zeros, mk().path_ty(vec!["i32"]))
-                }))
+                // Emit `assume(cond)`
+                let assume = mk().abs_path_expr(vec!["core", "intrinsics", "assume"]);
+                let cond = self.convert_condition(ctx.used(), true, args[0])?;
+                let call = cond.map(|cond| mk().call_expr(assume, vec![cond]));
+                self.convert_side_effects_expr(ctx, call, "Builtin is not supposed to be used")
             }
             "__builtin_bzero" => {
                 let ptr_stmts = self.convert_expr(ctx.used(), args[0])?;
@@ -618,23 +684,43 @@ impl<'c> Translation<'c> {
             "__builtin_assume_aligned" => Ok(self.convert_expr(ctx.used(), args[0])?),
             // Skip over, there's no way to implement it in Rust
             "__builtin_unwind_init" => Ok(WithStmts::new_val(self.panic_or_err("no value"))),
+            // Reaching `__builtin_unreachable` is undefined behavior in C too, so it's translated
+            // into the same optimization hint.
             "__builtin_unreachable" => Ok(WithStmts::new(
-                vec![mk().semi_stmt(mk().mac_expr(mk().mac(
-                    mk().path(vec!["unreachable"]),
+                vec![mk().semi_stmt(mk().call_expr(
+                    mk().abs_path_expr(vec!["core", "hint", "unreachable_unchecked"]),
                     vec![],
-                    MacroDelimiter::Paren(Default::default()),
-                )))],
+                ))],
                 self.panic_or_err("unreachable stub"),
             )),
+            "__builtin_trap" => {
+                self.use_feature("core_intrinsics");
+                Ok(WithStmts::new(
+                    vec![mk().semi_stmt(mk().call_expr(
+                        mk().abs_path_expr(vec!["core", "intrinsics", "abort"]),
+                        vec![],
+                    ))],
+                    self.panic_or_err("trap stub"),
+                ))
+            }
 
             "__builtin_rotateleft8"
             | "__builtin_rotateleft16"
             | "__builtin_rotateleft32"
-            | "__builtin_rotateleft64" => {
+            | "__builtin_rotateleft64"
+            | "__builtin_rotateright8"
+            | "__builtin_rotateright16"
+            | "__builtin_rotateright32"
+            | "__builtin_rotateright64" => {
                 self.use_feature("core_intrinsics");
 
-                // Emit `rotate_left(arg0, arg1)`
-                let rotate_func = mk().abs_path_expr(vec!["core", "intrinsics", "rotate_left"]);
+                // Emit `rotate_left(arg0, arg1)` or `rotate_right(arg0, arg1)`
+                let rotate = if builtin_name.starts_with("__builtin_rotateleft") {
+                    "rotate_left"
+                } else {
+                    "rotate_right"
+                };
+                let rotate_func = mk().abs_path_expr(vec!["core", "intrinsics", rotate]);
                 let arg0 = self.convert_expr(ctx.used(), args[0])?;
                 let arg1 = self.convert_expr(ctx.used(), args[1])?;
                 arg0.and_then(|arg0| {
@@ -659,41 +745,171 @@ impl<'c> Translation<'c> {
 
     // This translation logic handles converting code that uses
     // https://gcc.gnu.org/onlinedocs/gcc/Integer-Overflow-Builtins.html
+    //
+    // The builtins compute `a op b` in infinite precision, store it into `*res` truncated to the
+    // type of `*res`, and return whether it didn't fit.  When `a`, `b` and `*res` have the same
+    // type, that's exactly what `a.overflowing_op(b)` does.  Otherwise (only the type-generic
+    // builtins allow that), the operation is done in `i128`, which holds the exact sum or
+    // difference of any two 64-bit operands and the low bits of their product, and the result
+    // overflowed if the `i128` operation did or truncating its result changed the value.
     fn convert_overflow_arith(
         &self,
         ctx: ExprContext,
         method_name: &str,
         args: &[CExprId],
     ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        if args.len() != 3 {
+            return Err(TranslationError::generic(
+                "`convert_overflow_arith` must have exactly 3 arguments",
+            ));
+        }
+        let arg_ty = |arg: CExprId| {
+            self.ast_context[arg]
+                .kind
+                .get_type()
+                .map(|ty| &self.ast_context.resolve_type(ty).kind)
+                .ok_or_else(|| format_err!("bad argument type for overflow builtin"))
+        };
+        let res_ty = match *arg_ty(args[2])? {
+            CTypeKind::Pointer(qty) => qty.ctype,
+            _ => {
+                return Err(TranslationError::generic(
+                    "result argument of overflow builtin is not a pointer",
+                ))
+            }
+        };
+        let res_kind = &self.ast_context.resolve_type(res_ty).kind;
+        let (a_kind, b_kind) = (arg_ty(args[0])?, arg_ty(args[1])?);
+        let wide_res_ty = if a_kind == res_kind && b_kind == res_kind {
+            None
+        } else if [a_kind, b_kind, res_kind]
+            .iter()
+            .any(|kind| matches!(kind, CTypeKind::Int128 | CTypeKind::UInt128))
+        {
+            return Err(TranslationError::generic(
+                "overflow builtins on 128-bit integers of different types are not supported",
+            ));
+        } else {
+            Some(self.convert_type(res_ty)?)
+        };
+
         let args = self.convert_exprs(ctx.used(), args)?;
         args.and_then(|args| {
             let [a, b, c]: [_; 3] = args
                 .try_into()
                 .map_err(|_| "`convert_overflow_arith` must have exactly 3 arguments")?;
-            let overflowing = mk().method_call_expr(a, method_name, vec![b]);
             let sum_name = self.renamer.borrow_mut().fresh();
             let over_name = self.renamer.borrow_mut().fresh();
-            let overflow_let = mk().local_stmt(Box::new(mk().local(
-                mk().tuple_pat(vec![
-                    mk().ident_pat(&sum_name),
-                    mk().ident_pat(over_name.clone()),
-                ]),
-                None,
-                Some(overflowing),
-            )));
+            let let_stmt =
+                |pat, value| mk().local_stmt(Box::new(mk().local(pat, None, Some(value))));
+
+            let mut stmts = vec![];
+            match wide_res_ty {
+                None => {
+                    // let (sum, over) = a.overflowing_add(b);
+                    let overflowing = mk().method_call_expr(a, method_name, vec![b]);
+                    let pat =
+                        mk().tuple_pat(vec![mk().ident_pat(&sum_name), mk().ident_pat(&over_name)]);
+                    stmts.push(let_stmt(pat, overflowing));
+                }
+                Some(res_ty) => {
+                    // let (wide, wide_over) = (a as i128).overflowing_add(b as i128);
+                    // let sum = wide as T;
+                    // let over = wide_over || sum as i128 != wide;
+                    let i128_ty = || mk().path_ty(vec!["i128"]);
+                    let wide_name = self.renamer.borrow_mut().fresh();
+                    let wide_over_name = self.renamer.borrow_mut().fresh();
+                    let wide_a = mk().paren_expr(mk().cast_expr(a, i128_ty()));
+                    let wide_b = mk().cast_expr(b, i128_ty());
+                    let overflowing = mk().method_call_expr(wide_a, method_name, vec![wide_b]);
+                    let pat = mk().tuple_pat(vec![
+                        mk().ident_pat(&wide_name),
+                        mk().ident_pat(&wide_over_name),
+                    ]);
+                    stmts.push(let_stmt(pat, overflowing));
+                    let sum = mk().cast_expr(mk().ident_expr(&wide_name), res_ty);
+                    stmts.push(let_stmt(mk().ident_pat(&sum_name), sum));
+                    let truncated = mk().binary_expr(
+                        BinOp::Ne(Default::default()),
+                        mk().cast_expr(mk().ident_expr(&sum_name), i128_ty()),
+                        mk().ident_expr(&wide_name),
+                    );
+                    let over = mk().binary_expr(
+                        BinOp::Or(Default::default()),
+                        mk().ident_expr(&wide_over_name),
+                        truncated,
+                    );
+                    stmts.push(let_stmt(mk().ident_pat(&over_name), over));
+                }
+            }
 
+            // *res = sum;
             let out_assign = mk().assign_expr(
                 mk().unary_expr(UnOp::Deref(Default::default()), c),
                 mk().ident_expr(&sum_name),
             );
+            stmts.push(mk().expr_stmt(out_assign));
 
-            Ok(WithStmts::new(
-                vec![overflow_let, mk().expr_stmt(out_assign)],
-                mk().ident_expr(over_name),
-            ))
+            Ok(WithStmts::new(stmts, mk().ident_expr(&over_name)))
         })
     }
 
+    /// Converts a two-argument builtin into a call of `method` on its first argument, e.g.
+    /// `__builtin_copysign(x, y)` into `x.copysign(y)`.
+    fn convert_binary_method_builtin(
+        &self,
+        ctx: ExprContext,
+        method: &str,
+        args: &[CExprId],
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let x = self.convert_expr(ctx.used(), args[0])?;
+        let y = self.convert_expr(ctx.used(), args[1])?;
+        x.and_then(|x| Ok(y.map(|y| mk().method_call_expr(x, method, vec![y]))))
+    }
+
+    /// Converts `__builtin_expect(val, expected)`.  When `val` is always 0 or 1 and `expected`
+    /// is a constant, the expectation becomes a `likely` or `unlikely` hint on `val` as a
+    /// condition.  Otherwise the call is just `val`.
+    fn convert_expect(
+        &self,
+        ctx: ExprContext,
+        val_id: CExprId,
+        expected_id: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let expected = match *self.ast_context.resolve_expr(expected_id).1 {
+            CExprKind::Literal(_, CLiteral::Integer(i, _)) => Some(i != 0),
+            _ => None,
+        };
+        let ty = self.ast_context[val_id].kind.get_type();
+        match (expected, ty) {
+            (Some(expected), Some(ty)) if self.is_boolean_valued(val_id) => {
+                self.use_feature("core_intrinsics");
+
+                // Emit `likely(val != 0) as c_long`
+                let hint = if expected { "likely" } else { "unlikely" };
+                let hint = mk().abs_path_expr(vec!["core", "intrinsics", hint]);
+                let ty = self.convert_type(ty)?;
+                let cond = self.convert_condition(ctx.used(), true, val_id)?;
+                Ok(cond.map(|cond| mk().cast_expr(mk().call_expr(hint, vec![cond]), ty)))
+            }
+            _ => self.convert_expr(ctx.used(), val_id),
+        }
+    }
+
+    /// Whether the value of `expr_id` is always 0 or 1, because it's a comparison, a logical
+    /// operation, or a negation.
+    fn is_boolean_valued(&self, expr_id: CExprId) -> bool {
+        use crate::c_ast::BinOp::*;
+        match *self.ast_context.resolve_expr(expr_id).1 {
+            CExprKind::Unary(_, crate::c_ast::UnOp::Not, _, _) => true,
+            CExprKind::Binary(_, op, _, _, _, _) => matches!(
+                op,
+                Less | Greater | LessEqual | GreaterEqual | EqualEqual | NotEqual | And | Or
+            ),
+            _ => false,
+        }
+    }
+
     /// Converts a `__builtin_{mem|str}*` use by calling the equivalent libc fn.
     fn convert_libc_fns(
         &self,
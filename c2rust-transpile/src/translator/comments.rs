@@ -2,7 +2,8 @@ use super::Translation;
 use crate::c_ast::iterators::{NodeVisitor, SomeId};
 use crate::c_ast::{CDeclId, CDeclKind, CommentContext, SrcLoc, TypedAstContext};
 use crate::rust_ast::comment_store::CommentStore;
-use crate::rust_ast::{pos_to_span, SpanExt};
+use crate::rust_ast::{doc_comments, pos_to_span, BytePos, SpanExt};
+use c2rust_ast_builder::{mk, Builder};
 use log::debug;
 use proc_macro2::Span;
 use std::collections::{HashMap, HashSet};
@@ -12,6 +13,7 @@ struct CommentLocator<'c> {
     comment_context: &'c CommentContext,
     comment_store: &'c mut CommentStore,
     spans: &'c mut HashMap<SomeId, Span>,
+    doc_comments: &'c mut HashMap<CDeclId, Vec<String>>,
     top_decls: &'c HashSet<CDeclId>,
     last_id: Option<SomeId>,
 }
@@ -66,6 +68,57 @@ impl<'c> CommentLocator<'c> {
             }
         }
     }
+
+    /// Add the comments before the node `id`, at `pos` if given.  On declarations that become Rust
+    /// items, doc comments are converted into rustdoc and saved in `doc_comments` instead.
+    fn add_leading_comments(
+        &mut self,
+        id: SomeId,
+        comments: &[String],
+        pos: Option<BytePos>,
+    ) -> Option<BytePos> {
+        let decl_id = match id {
+            SomeId::Decl(decl_id) => decl_id,
+            _ => return self.comment_store.extend_existing_comments(comments, pos),
+        };
+        let is_item = matches!(
+            self.ast_context[decl_id].kind,
+            CDeclKind::Function { .. }
+                | CDeclKind::Typedef { .. }
+                | CDeclKind::Struct { .. }
+                | CDeclKind::Union { .. }
+                | CDeclKind::Enum { .. }
+                | CDeclKind::EnumConstant { .. }
+                | CDeclKind::Field { .. }
+                | CDeclKind::MacroObject { .. }
+                | CDeclKind::Variable {
+                    has_static_duration: true,
+                    ..
+                }
+                | CDeclKind::Variable {
+                    has_thread_duration: true,
+                    ..
+                }
+        );
+        if !is_item {
+            return self.comment_store.extend_existing_comments(comments, pos);
+        }
+
+        let mut others = Vec::new();
+        for comment in comments {
+            match doc_comments::to_rustdoc(comment) {
+                Some(lines) => {
+                    let docs = self.doc_comments.entry(decl_id).or_default();
+                    if !docs.is_empty() {
+                        docs.push(String::new());
+                    }
+                    docs.extend(lines);
+                }
+                None => others.push(comment.clone()),
+            }
+        }
+        self.comment_store.extend_existing_comments(&others, pos)
+    }
 }
 
 impl<'c> NodeVisitor for CommentLocator<'c> {
@@ -94,17 +147,13 @@ impl<'c> NodeVisitor for CommentLocator<'c> {
                     id = SomeId::Decl(*canonical_decl);
                 }
             }
-            if let Some(existing) = self.spans.get(&id) {
-                let new_pos = self.comment_store.extend_existing_comments(
-                    &comments,
-                    Some(existing.lo()),
-                    //CommentStyle::Isolated,
-                );
+            if let Some(existing) = self.spans.get(&id).copied() {
+                let new_pos = self.add_leading_comments(id, &comments, Some(existing.lo()));
                 debug!(
                     "Attaching more comments {:?} to id {:?} at pos {:?}",
                     comments, id, new_pos
                 );
-            } else if let Some(pos) = self.comment_store.add_comments(&comments) {
+            } else if let Some(pos) = self.add_leading_comments(id, &comments, None) {
                 debug!(
                     "Attaching comments {:?} to id {:?} at pos {:?}",
                     comments, id, pos
@@ -161,6 +210,7 @@ impl<'c> Translation<'c> {
         let mut top_decls: HashSet<CDeclId> =
             self.ast_context.c_decls_top.iter().copied().collect();
         let mut spans: HashMap<SomeId, Span> = HashMap::new();
+        let mut doc_comments: HashMap<CDeclId, Vec<String>> = HashMap::new();
         for decl_id in &self.ast_context.c_decls_top {
             top_decls.remove(decl_id);
             let mut visitor = CommentLocator {
@@ -168,15 +218,29 @@ impl<'c> Translation<'c> {
                 comment_context: &self.comment_context,
                 comment_store: &mut self.comment_store.borrow_mut(),
                 spans: &mut spans,
+                doc_comments: &mut doc_comments,
                 top_decls: &top_decls,
                 last_id: None,
             };
             visitor.visit_tree(&self.ast_context, SomeId::Decl(*decl_id));
         }
         self.spans = spans;
+        self.doc_comments = doc_comments;
     }
 
     pub fn get_span(&self, id: SomeId) -> Option<Span> {
         self.spans.get(&id).copied()
     }
+
+    /// Get a builder carrying `#[doc]` attributes for the doc comments on `decl_id`.
+    pub fn doc_attrs(&self, decl_id: CDeclId) -> Builder {
+        let lines = self.doc_comments.get(&decl_id).into_iter().flatten();
+        lines.fold(mk(), |b, line| {
+            if line.is_empty() {
+                b.str_attr("doc", "")
+            } else {
+                b.str_attr("doc", format!(" {}", line))
+            }
+        })
+    }
 }
@@ -0,0 +1,478 @@
+//! Translation of the C++ subset the AST exporter accepts.
+//!
+//! Namespaces become modules, whose items are re-exported by the module of the enclosing
+//! namespace, so code outside a namespace can keep using their (renamed) names unqualified.
+//! Member functions of classes become methods in `impl` blocks of their structs, taking `&mut
+//! self`, or `&self` when they're `const`, and keep their mangled names as their linkage names.
+//! References become Rust references, bound with `&mut` or `&` wherever C++ binds them implicitly,
+//! and `new` and `delete` allocate and free with the C library's `calloc` and `free`.
+
+use super::*;
+
+/// The name of the method translating a C++ member function named `name`.
+pub(super) fn method_name(name: &str) -> String {
+    // Keywords get a trailing underscore, like they do when the renamer names functions
+    if syn::parse_str::<Ident>(name).is_ok() {
+        name.to_owned()
+    } else {
+        format!("{}_", name)
+    }
+}
+
+fn pub_vis() -> Visibility {
+    Visibility::Public(VisPublic {
+        pub_token: Default::default(),
+    })
+}
+
+/// The `&self` or `&mut self` receiver of a method.
+fn self_ref_arg(mutbl: Mutability) -> FnArg {
+    FnArg::Receiver(Receiver {
+        attrs: vec![],
+        reference: Some((Default::default(), None)),
+        mutability: mutbl.to_token(),
+        self_token: Default::default(),
+    })
+}
+
+impl<'c> Translation<'c> {
+    /// The path of the namespace declaring `decl_id`, if a named namespace declares it.
+    pub(super) fn namespace_path(&self, decl_id: CDeclId) -> Option<Vec<String>> {
+        let namespace_id = self.ast_context.namespaces.get(&decl_id)?;
+        match self.ast_context[*namespace_id].kind {
+            CDeclKind::Namespace {
+                ref name, ref path, ..
+            } => {
+                let mut path = path.clone();
+                path.push(name.clone());
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn insert_namespace_item(&self, path: Vec<String>, mut item: Box<Item>) {
+        // Items are re-exported from their namespace's module, so they must be public
+        match *item {
+            Item::Fn(ItemFn { ref mut vis, .. })
+            | Item::Static(ItemStatic { ref mut vis, .. })
+            | Item::Const(ItemConst { ref mut vis, .. })
+            | Item::Struct(ItemStruct { ref mut vis, .. })
+            | Item::Union(ItemUnion { ref mut vis, .. })
+            | Item::Enum(ItemEnum { ref mut vis, .. })
+            | Item::Type(ItemType { ref mut vis, .. }) => *vis = pub_vis(),
+            _ => {}
+        }
+        self.namespace_items
+            .borrow_mut()
+            .entry(path)
+            .or_insert_with(ItemStore::new)
+            .add_item(item);
+    }
+
+    pub(super) fn insert_namespace_foreign_item(&self, path: Vec<String>, mut item: ForeignItem) {
+        match item {
+            ForeignItem::Fn(ForeignItemFn { ref mut vis, .. })
+            | ForeignItem::Static(ForeignItemStatic { ref mut vis, .. }) => *vis = pub_vis(),
+            _ => {}
+        }
+        self.namespace_items
+            .borrow_mut()
+            .entry(path)
+            .or_insert_with(ItemStore::new)
+            .add_foreign_item(item);
+    }
+
+    /// Build the modules of the namespaces directly in the namespace at `parent`, and the uses
+    /// re-exporting their items.
+    // The modules join the file's other items, which are all boxed.
+    #[allow(clippy::vec_box)]
+    pub(super) fn namespace_modules(&self, parent: &[String]) -> Vec<Box<Item>> {
+        let names = self
+            .namespace_items
+            .borrow()
+            .keys()
+            .filter(|path| path.len() > parent.len() && path.starts_with(parent))
+            .map(|path| path[parent.len()].clone())
+            .collect::<IndexSet<_>>();
+
+        let mut modules = vec![];
+        for name in names {
+            let mut path = parent.to_vec();
+            path.push(name.clone());
+
+            let (items, foreign_items, uses) =
+                match self.namespace_items.borrow_mut().get_mut(&path) {
+                    Some(store) => store.drain(),
+                    None => ItemStore::new().drain(),
+                };
+            let mut mod_items = vec![mk().use_glob_item(mk().path(vec!["super"]))];
+            mod_items.extend(uses.into_items());
+            if !foreign_items.is_empty() {
+                mod_items.push(mk().extern_("C").foreign_items(foreign_items));
+            }
+            mod_items.extend(items);
+            mod_items.extend(self.namespace_modules(&path));
+
+            modules.push(mk().pub_().mod_item(&name, Some(mk().mod_(mod_items))));
+            modules.push(
+                mk().pub_()
+                    .use_glob_item(mk().path(vec!["self".to_owned(), name])),
+            );
+        }
+        modules
+    }
+
+    /// Put the translation of a member function of the class `record_id` in an `impl` of its
+    /// struct, as a method taking `self` by reference if the member function takes `this`.
+    pub(super) fn convert_method(
+        &self,
+        record_id: CRecordId,
+        this_type: Option<CQualTypeId>,
+        converted: ConvertedDecl,
+    ) -> TranslationResult<ConvertedDecl> {
+        let record_name = self
+            .type_converter
+            .borrow()
+            .resolve_decl_name(record_id)
+            .ok_or_else(|| format_err!("Unknown class {:?}", record_id))?;
+        let record_ty = mk().path_ty(vec![record_name]);
+
+        let receiver = this_type.map(|this_type| {
+            let is_const = self
+                .ast_context
+                .get_pointee_qual_type(this_type.ctype)
+                .map_or(false, |pointee| {
+                    self.ast_context.is_const_qualified(pointee)
+                });
+            if is_const {
+                Mutability::Immutable
+            } else {
+                Mutability::Mutable
+            }
+        });
+
+        let method = match converted {
+            ConvertedDecl::Item(item) => match *item {
+                Item::Fn(ItemFn {
+                    attrs,
+                    mut sig,
+                    block,
+                    ..
+                }) => {
+                    if let Some(mutbl) = receiver {
+                        sig.inputs.insert(0, self_ref_arg(mutbl));
+                    }
+                    ImplItem::Method(ImplItemMethod {
+                        attrs,
+                        vis: pub_vis(),
+                        defaultness: None,
+                        sig,
+                        block: *block,
+                    })
+                }
+                _ => {
+                    return Err(TranslationError::generic(
+                        "Member function is not a function",
+                    ))
+                }
+            },
+            ConvertedDecl::ForeignItem(item) => {
+                self.convert_method_decl(record_ty.clone(), receiver, *item)?
+            }
+            converted => return Ok(converted),
+        };
+
+        Ok(ConvertedDecl::Item(mk().impl_item(record_ty, vec![method])))
+    }
+
+    /// Translate a member function defined elsewhere into a method calling it by its linkage
+    /// name, passing `self` as `this`.
+    fn convert_method_decl(
+        &self,
+        record_ty: Box<Type>,
+        receiver: Option<Mutability>,
+        item: ForeignItem,
+    ) -> TranslationResult<ImplItem> {
+        let mut decl = match item {
+            ForeignItem::Fn(decl) => decl,
+            _ => {
+                return Err(TranslationError::generic(
+                    "Member function is not a function",
+                ))
+            }
+        };
+        if decl.sig.variadic.is_some() {
+            return Err(TranslationError::generic(
+                "Variadic member functions defined elsewhere are not supported",
+            ));
+        }
+
+        // Name the parameters, so the method can pass them on
+        let mut args = vec![];
+        for (i, input) in decl.sig.inputs.iter_mut().enumerate() {
+            if let FnArg::Typed(arg) = input {
+                let name = match *arg.pat {
+                    Pat::Ident(ref pat) => pat.ident.to_string(),
+                    _ => {
+                        let name = format!("arg{}", i);
+                        arg.pat = Box::new(mk().ident_pat(&name));
+                        name
+                    }
+                };
+                args.push(mk().ident_expr(name));
+            }
+        }
+
+        let mut sig = decl.sig.clone();
+        sig.unsafety = Some(Default::default());
+        if let Some(mutbl) = receiver {
+            let this_ty = mk().set_mutbl(mutbl).ptr_ty(record_ty);
+            decl.sig
+                .inputs
+                .insert(0, mk().arg(this_ty, mk().ident_pat("this")));
+            sig.inputs.insert(0, self_ref_arg(mutbl));
+            args.insert(0, mk().path_expr(vec!["self"]));
+        }
+        decl.vis = Visibility::Inherited;
+
+        let call = mk().call_expr(mk().ident_expr(decl.sig.ident.clone()), args);
+        let extern_block = mk().extern_("C").foreign_items(vec![ForeignItem::Fn(decl)]);
+        Ok(ImplItem::Method(ImplItemMethod {
+            attrs: vec![],
+            vis: pub_vis(),
+            defaultness: None,
+            sig,
+            block: mk().block(vec![mk().item_stmt(extern_block), mk().expr_stmt(call)]),
+        }))
+    }
+
+    /// The path of a static member function, which is named by its class.
+    pub(super) fn convert_static_method_ref(&self, decl_id: CDeclId) -> Option<Box<Expr>> {
+        match self.ast_context[decl_id].kind {
+            CDeclKind::Function {
+                ref name,
+                record: Some(record_id),
+                ..
+            } => {
+                let record_name = self.type_converter.borrow().resolve_decl_name(record_id)?;
+                Some(mk().path_expr(vec![record_name, method_name(name)]))
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn is_reference_type(&self, type_id: CTypeId) -> bool {
+        matches!(
+            self.ast_context.resolve_type(type_id).kind,
+            CTypeKind::Reference(..)
+        )
+    }
+
+    /// Convert `expr_id` into a value of type `type_id`, which borrows it if `type_id` is a
+    /// reference type.
+    pub(super) fn convert_reference_binding(
+        &self,
+        ctx: ExprContext,
+        type_id: CTypeId,
+        expr_id: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        match self.ast_context.resolve_type(type_id).kind {
+            CTypeKind::Reference(referent) => {
+                let mutbl = if self.ast_context.is_const_qualified(referent) {
+                    Mutability::Immutable
+                } else {
+                    Mutability::Mutable
+                };
+                let val = self.convert_expr(ctx, expr_id)?;
+                Ok(val.map(|val| mk().set_mutbl(mutbl).addr_of_expr(val)))
+            }
+            _ => self.convert_expr(ctx, expr_id),
+        }
+    }
+
+    /// Convert the arguments of a call to a function with parameters of types `param_tys`.
+    // The arguments go to the builder's call expressions, which take them boxed.
+    #[allow(clippy::vec_box)]
+    pub(super) fn convert_call_args(
+        &self,
+        ctx: ExprContext,
+        param_tys: &[CQualTypeId],
+        args: &[CExprId],
+    ) -> TranslationResult<WithStmts<Vec<Box<Expr>>>> {
+        args.iter()
+            .enumerate()
+            .map(|(i, &arg)| match param_tys.get(i) {
+                Some(param_ty) => self.convert_reference_binding(ctx, param_ty.ctype, arg),
+                // Variadic arguments
+                None => self.convert_expr(ctx, arg),
+            })
+            .collect()
+    }
+
+    /// Convert the value returned by a `return` statement of the current function.
+    pub fn convert_return_value(
+        &self,
+        ctx: ExprContext,
+        expr_id: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let return_type = self.function_context.borrow().return_type;
+        match return_type {
+            Some(return_type) => self.convert_reference_binding(ctx, return_type.ctype, expr_id),
+            None => self.convert_expr(ctx, expr_id),
+        }
+    }
+
+    /// Convert `this` into a pointer to `self`.
+    pub(super) fn convert_this(&self, ty: CQualTypeId) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let ty = self.convert_type(ty.ctype)?;
+        Ok(WithStmts::new_val(
+            mk().cast_expr(mk().path_expr(vec!["self"]), ty),
+        ))
+    }
+
+    pub(super) fn convert_member_call(
+        &self,
+        ctx: ExprContext,
+        method: CDeclId,
+        object: CExprId,
+        kind: MemberKind,
+        args: &[CExprId],
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let (name, typ, parameters) = match self.ast_context[method].kind {
+            CDeclKind::Function {
+                ref name,
+                typ,
+                ref parameters,
+                ..
+            } => (name, typ, parameters),
+            _ => {
+                return Err(TranslationError::generic(
+                    "Member function is not a function",
+                ))
+            }
+        };
+        let param_tys = parameters
+            .iter()
+            .filter_map(|&param| match self.ast_context[param].kind {
+                CDeclKind::Variable { typ, .. } => Some(typ),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let returns_ref = match self.ast_context.resolve_type(typ).kind {
+            CTypeKind::Function(ret, ..) => self.is_reference_type(ret.ctype),
+            _ => false,
+        };
+
+        let object = match kind {
+            MemberKind::Dot => self.convert_expr(ctx.used(), object)?,
+            MemberKind::Arrow => match self.ast_context[object].kind {
+                // Call `this->f()` as `self.f()`
+                CExprKind::This(..) => WithStmts::new_val(mk().path_expr(vec!["self"])),
+                _ => self
+                    .convert_expr(ctx.used(), object)?
+                    .map(|ptr| mk().unary_expr(UnOp::Deref(Default::default()), ptr)),
+            },
+        };
+        let call = object.and_then(|object| {
+            let args = self.convert_call_args(ctx.used(), &param_tys, args)?;
+            let res: TranslationResult<_> =
+                Ok(args.map(|args| mk().method_call_expr(object, method_name(name), args)));
+            res
+        })?;
+
+        // Calls returning references are used as what they return
+        let call = if returns_ref {
+            call.map(|call| mk().unary_expr(UnOp::Deref(Default::default()), call))
+        } else {
+            call
+        };
+
+        self.convert_side_effects_expr(
+            ctx,
+            call,
+            "Member function call expression is not supposed to be used",
+        )
+    }
+
+    /// Convert `new T`, `new T(init)` and `new T[count]` into zeroed allocations with `calloc`,
+    /// initialized by assignment when there's an initializer.
+    pub(super) fn convert_new(
+        &self,
+        ctx: ExprContext,
+        ty: CQualTypeId,
+        allocated: CQualTypeId,
+        count: Option<CExprId>,
+        init: Option<CExprId>,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        // `calloc` zeroes the allocation, like value-initialization does
+        let init = init.filter(|&init| {
+            !matches!(
+                self.ast_context[init].kind,
+                CExprKind::ImplicitValueInit(..)
+            )
+        });
+        if count.is_some() && init.is_some() {
+            return Err(TranslationError::generic(
+                "Initializers of array new expressions are not supported",
+            ));
+        }
+
+        let size = self.compute_size_of_type(ctx, allocated.ctype)?;
+        let count = match count {
+            Some(count) => self.convert_expr(ctx.used(), count)?,
+            None => WithStmts::new_val(mk().lit_expr(mk().int_unsuffixed_lit(1))),
+        };
+        let ptr_ty = self.convert_type(ty.ctype)?;
+        let c_ulong = || mk().path_ty(vec!["libc", "c_ulong"]);
+        let mut alloc = count.and_then(|count| -> TranslationResult<_> {
+            Ok(size.map(|size| {
+                let args = vec![
+                    mk().cast_expr(count, c_ulong()),
+                    mk().cast_expr(size, c_ulong()),
+                ];
+                let calloc = mk().call_expr(mk().path_expr(vec!["libc", "calloc"]), args);
+                mk().cast_expr(calloc, ptr_ty)
+            }))
+        })?;
+        alloc.set_unsafe();
+
+        let init = match init {
+            Some(init) => self.convert_expr(ctx.used(), init)?,
+            None => return Ok(alloc),
+        };
+
+        // let fresh = calloc(..) as *mut T; *fresh = init; fresh
+        let fresh = self.renamer.borrow_mut().fresh();
+        alloc.and_then(|alloc| {
+            init.and_then(|init| -> TranslationResult<_> {
+                let local = mk().local(mk().ident_pat(&fresh), None, Some(alloc));
+                let lhs = mk().unary_expr(UnOp::Deref(Default::default()), mk().ident_expr(&fresh));
+                Ok(WithStmts::new(
+                    vec![
+                        mk().local_stmt(Box::new(local)),
+                        mk().semi_stmt(mk().assign_expr(lhs, init)),
+                    ],
+                    mk().ident_expr(&fresh),
+                ))
+            })
+        })
+    }
+
+    /// Convert `delete p` and `delete[] p` into `free`.
+    pub(super) fn convert_delete(
+        &self,
+        ctx: ExprContext,
+        ptr: CExprId,
+    ) -> TranslationResult<WithStmts<Box<Expr>>> {
+        let mut free = self.convert_expr(ctx.used(), ptr)?.map(|ptr| {
+            let ptr = mk().cast_expr(
+                ptr,
+                mk().mutbl().ptr_ty(mk().path_ty(vec!["libc", "c_void"])),
+            );
+            mk().call_expr(mk().path_expr(vec!["libc", "free"]), vec![ptr])
+        });
+        free.set_unsafe();
+        self.convert_side_effects_expr(ctx, free, "delete expression is not supposed to be used")
+    }
+}
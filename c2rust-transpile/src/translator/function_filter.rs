@@ -0,0 +1,53 @@
+//! Translation of some functions at a time.
+//!
+//! With `filter_functions` or `filter_annotated_functions`, only the functions they select are
+//! translated.  The other externally visible functions, and the externally visible variables, are
+//! left in C: they're declared in `extern "C"` blocks, and the generated `build.rs` compiles the C
+//! files and links them in, with the C definitions of the translated functions made weak so that
+//! the C code calls their translations.  Functions that can't be linked to, like `static` and
+//! `inline` functions, are still translated where they're used.
+
+use indexmap::IndexSet;
+
+use super::Translation;
+use crate::c_ast::{Attribute, CDeclId, CDeclKind};
+
+/// The annotation selecting a function for translation with `filter_annotated_functions`, as in
+/// `__attribute__((annotate("c2rust_translate")))`.
+const TRANSLATE_ANNOTATION: &str = "c2rust_translate";
+
+impl<'c> Translation<'c> {
+    fn selects_function(&self, name: &str, attrs: &IndexSet<Attribute>) -> bool {
+        let matches_filter = self
+            .tcfg
+            .filter_functions
+            .as_ref()
+            .map_or(false, |re| re.is_match(name));
+        let annotated = self.tcfg.filter_annotated_functions
+            && attrs.contains(&Attribute::Annotate(TRANSLATE_ANNOTATION.to_owned()));
+        matches_filter || annotated
+    }
+
+    /// Whether the C definition of `decl_id` is linked in instead of being translated.
+    pub(super) fn links_c_definition(&self, decl_id: CDeclId) -> bool {
+        if !self.tcfg.filters_functions() {
+            return false;
+        }
+        match self.ast_context[decl_id].kind {
+            CDeclKind::Function {
+                is_global: true,
+                is_inline: false,
+                body: Some(_),
+                ref name,
+                ref attrs,
+                ..
+            } => self.ast_context.c_main != Some(decl_id) && !self.selects_function(name, attrs),
+            CDeclKind::Variable {
+                is_externally_visible: true,
+                is_defn: true,
+                ..
+            } => self.ast_context.c_decls_top.contains(&decl_id),
+            _ => false,
+        }
+    }
+}
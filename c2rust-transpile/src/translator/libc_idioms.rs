@@ -0,0 +1,97 @@
+//! Translation of calls to C library functions that have exact equivalents in Rust's standard
+//! library, enabled by `translate_libc_idioms`.
+//!
+//! Only functions whose Rust equivalent behaves identically for every input are translated.
+//! Functions like `atoi` (which ignores trailing garbage and has undefined behavior on
+//! overflow) or `getenv` (which returns a pointer into the environment) stay as calls through
+//! `libc`, as do functions like `qsort` whose equivalents need their arguments restructured;
+//! the `c2rust-refactor` tool handles those once the surrounding code is safe enough.
+
+use super::*;
+
+/// The path of the Rust function that replaces a C library function.
+#[derive(Copy, Clone, Debug)]
+enum Idiom {
+    /// A function from `std`.
+    Function(&'static [&'static str]),
+    /// An associated function of a primitive type (or a `libc` alias for one), called with the
+    /// C function's arguments, e.g. `f64::sqrt(x)`.
+    Method(&'static [&'static str], &'static str),
+}
+
+/// Get the number of arguments of the C library function `name` and its Rust equivalent.
+fn libc_idiom(name: &str) -> Option<(usize, Idiom)> {
+    use self::Idiom::*;
+    const F64: &[&str] = &["f64"];
+    const F32: &[&str] = &["f32"];
+    let idiom = match name {
+        // `abs(INT_MIN)` is undefined in C, so wrapping is as good as any other result
+        "abs" => (1, Method(&["libc", "c_int"], "wrapping_abs")),
+        "labs" => (1, Method(&["libc", "c_long"], "wrapping_abs")),
+        "llabs" => (1, Method(&["libc", "c_longlong"], "wrapping_abs")),
+
+        // `std::process::exit` runs the C library's `exit`, so `atexit` handlers and stdio
+        // buffers behave as before
+        "exit" => (1, Function(&["std", "process", "exit"])),
+        "abort" => (0, Function(&["std", "process", "abort"])),
+
+        "fabs" => (1, Method(F64, "abs")),
+        "sqrt" => (1, Method(F64, "sqrt")),
+        "floor" => (1, Method(F64, "floor")),
+        "ceil" => (1, Method(F64, "ceil")),
+        "trunc" => (1, Method(F64, "trunc")),
+        "round" => (1, Method(F64, "round")),
+        "fmax" => (2, Method(F64, "max")),
+        "fmin" => (2, Method(F64, "min")),
+        "pow" => (2, Method(F64, "powf")),
+        "fabsf" => (1, Method(F32, "abs")),
+        "sqrtf" => (1, Method(F32, "sqrt")),
+        "floorf" => (1, Method(F32, "floor")),
+        "ceilf" => (1, Method(F32, "ceil")),
+        "truncf" => (1, Method(F32, "trunc")),
+        "roundf" => (1, Method(F32, "round")),
+        "fmaxf" => (2, Method(F32, "max")),
+        "fminf" => (2, Method(F32, "min")),
+        "powf" => (2, Method(F32, "powf")),
+        _ => return None,
+    };
+    Some(idiom)
+}
+
+impl<'c> Translation<'c> {
+    /// If `translate_libc_idioms` is enabled and `fexp` refers to a C library function that has an
+    /// exact Rust equivalent, called with `num_args` arguments, get the path of that equivalent.
+    ///
+    /// Only functions without a body in this translation unit are replaced, so that a program
+    /// defining its own `abs` keeps calling it.
+    pub fn convert_libc_idiom(&self, fexp: CExprId, num_args: usize) -> Option<Box<Expr>> {
+        if !self.tcfg.translate_libc_idioms {
+            return None;
+        }
+        let decl_id = match self.ast_context[fexp].kind {
+            CExprKind::DeclRef(_, decl_id, _) => decl_id,
+            _ => return None,
+        };
+        let name = match self.ast_context[decl_id].kind {
+            CDeclKind::Function {
+                ref name,
+                body: None,
+                ..
+            } => name,
+            _ => return None,
+        };
+        let (arity, idiom) = libc_idiom(name)?;
+        if arity != num_args {
+            return None;
+        }
+        let path = match idiom {
+            Idiom::Function(path) => mk().path_expr(path.to_vec()),
+            Idiom::Method(ty, method) => {
+                let mut path = ty.to_vec();
+                path.push(method);
+                mk().path_expr(path)
+            }
+        };
+        Some(path)
+    }
+}
@@ -15,6 +15,11 @@ impl<'c> Translation<'c> {
         val: u64,
         base: IntBase,
     ) -> TranslationResult<Box<Expr>> {
+        // C++ `true` and `false` are integer literals of type `bool`, which can't be cast to
+        if let CTypeKind::Bool = self.ast_context.resolve_type(ty.ctype).kind {
+            return Ok(mk().lit_expr(mk().bool_lit(val != 0)));
+        }
+
         let lit = match base {
             IntBase::Dec => mk().int_unsuffixed_lit(val.into()),
             IntBase::Hex => mk().float_unsuffixed_lit(&format!("0x{:x}", val)),
@@ -146,7 +151,7 @@ impl<'c> Translation<'c> {
                 let width_lit = mk().lit_expr(mk().int_unsuffixed_lit(val.len() as u128));
                 let array_ty = mk().array_ty(u8_ty, width_lit);
                 let source_ty = mk().ref_ty(array_ty);
-                let mutbl = if ty.qualifiers.is_const {
+                let mutbl = if self.ast_context.is_const_qualified(ty) {
                     Mutability::Immutable
                 } else {
                     Mutability::Mutable
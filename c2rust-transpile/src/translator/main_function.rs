@@ -2,13 +2,94 @@
 //! Translating main requires us to wrap the C implementation to
 //! a helper that can be called from a generated main function in
 //! Rust.
+//!
+//! By default, the generated main function builds leaked `argv` and `envp`
+//! arrays from `std::env::args` and `std::env::vars`.  With `idiomatic_main`,
+//! the arguments and environment are instead collected from
+//! `std::env::args_os` and `std::env::vars_os` into owned, NUL-terminated
+//! buffers, the C main is called from a safe helper, and `emit_main_run_fn`
+//! adds a `run(args: &[&str]) -> i32` function that runs the program with
+//! the given arguments, for use in tests.
 
 use super::*;
 use failure::format_err;
 use proc_macro2::{TokenStream, TokenTree};
 
+/// Build `let mut NAME: Vec<ELT> = Vec::new();`.
+fn vec_local(name: &str, elt: Box<Type>) -> Stmt {
+    mk().local_stmt(Box::new(mk().local(
+        mk().mutbl().ident_pat(name),
+        Some(mk().path_ty(vec![
+            mk().path_segment_with_args("Vec", mk().angle_bracketed_args(vec![elt])),
+        ])),
+        Some(mk().call_expr(mk().path_expr(vec!["Vec", "new"]), vec![])),
+    )))
+}
+
+/// Build `let mut NAME: Vec<u8> = std::os::unix::ffi::OsStringExt::into_vec(OS_STRING);`.
+fn os_string_bytes_local(name: &str, os_string: Box<Expr>) -> Stmt {
+    mk().local_stmt(Box::new(mk().local(
+        mk().mutbl().ident_pat(name),
+        Some(u8_vec_ty()),
+        Some(mk().call_expr(
+            mk().abs_path_expr(vec!["std", "os", "unix", "ffi", "OsStringExt", "into_vec"]),
+            vec![os_string],
+        )),
+    )))
+}
+
+/// Build `NAME.push(VAL);`.
+fn push_stmt(name: &str, val: Box<Expr>) -> Stmt {
+    mk().semi_stmt(mk().method_call_expr(mk().path_expr(vec![name]), "push", vec![val]))
+}
+
+fn u8_vec_ty() -> Box<Type> {
+    mk().path_ty(vec![mk().path_segment_with_args(
+        "Vec",
+        mk().angle_bracketed_args(vec![mk().path_ty(vec!["u8"])]),
+    )])
+}
+
+fn c_char_ptr_ty() -> Box<Type> {
+    mk().mutbl().ptr_ty(mk().path_ty(vec!["libc", "c_char"]))
+}
+
+/// Build the statements that collect pointers to the NUL-terminated buffers in `bufs` into a
+/// null-terminated array `ptrs`:
+///
+/// ```ignore
+/// let mut ptrs: Vec<*mut libc::c_char> = Vec::new();
+/// for buf in &mut bufs {
+///     ptrs.push(buf.as_mut_ptr() as *mut libc::c_char);
+/// }
+/// ptrs.push(core::ptr::null_mut());
+/// ```
+fn ptr_array_stmts(ptrs: &str, bufs: &str) -> Vec<Stmt> {
+    let buf_ptr = mk().cast_expr(
+        mk().method_call_expr(mk().path_expr(vec!["buf"]), "as_mut_ptr", vec![]),
+        c_char_ptr_ty(),
+    );
+    vec![
+        vec_local(ptrs, c_char_ptr_ty()),
+        mk().semi_stmt(mk().for_expr(
+            mk().ident_pat("buf"),
+            mk().mutbl().addr_of_expr(mk().path_expr(vec![bufs])),
+            mk().block(vec![push_stmt(ptrs, buf_ptr)]),
+            None::<Ident>,
+        )),
+        push_stmt(
+            ptrs,
+            mk().call_expr(mk().abs_path_expr(vec!["core", "ptr", "null_mut"]), vec![]),
+        ),
+    ]
+}
+
 impl<'c> Translation<'c> {
-    pub fn convert_main(&self, main_id: CDeclId) -> TranslationResult<Box<Item>> {
+    pub fn convert_main(&self, main_id: CDeclId) -> TranslationResult<Vec<Box<Item>>> {
+        if self.tcfg.idiomatic_main || self.tcfg.emit_main_run_fn {
+            let items = self.convert_main_idiomatic(main_id)?;
+            return Ok(items.into_iter().map(Box::new).collect());
+        }
         if let CDeclKind::Function {
             ref parameters,
             typ,
@@ -247,11 +328,222 @@ impl<'c> Translation<'c> {
             };
 
             let block = mk().block(stmts);
-            Ok(mk().pub_().fn_item(decl, block))
+            Ok(vec![mk().pub_().fn_item(decl, block)])
         } else {
             Err(TranslationError::generic(
                 "Cannot translate non-function main entry point",
             ))
         }
     }
+
+    /// Translate `main` into a safe helper that takes the arguments as owned, NUL-terminated
+    /// buffers and returns the exit code, a `main` that calls it with `std::env::args_os` and
+    /// exits with `std::process::exit`, and, if requested, a `run` function that calls it with
+    /// string arguments.
+    fn convert_main_idiomatic(&self, main_id: CDeclId) -> TranslationResult<Vec<Item>> {
+        let (parameters, typ) = match self.ast_context.index(main_id).kind {
+            CDeclKind::Function {
+                ref parameters,
+                typ,
+                ..
+            } => (parameters, typ),
+            _ => {
+                return Err(TranslationError::generic(
+                    "Cannot translate non-function main entry point",
+                ))
+            }
+        };
+        let ret = match self.ast_context.resolve_type(typ).kind {
+            CTypeKind::Function(ret, _, _, _, _) => ret,
+            ref k => {
+                return Err(format_err!(
+                    "Type of main function {:?} was not a function type, got {:?}",
+                    main_id,
+                    k
+                )
+                .into())
+            }
+        };
+        let n = parameters.len();
+        if n != 0 && n != 2 && n != 3 {
+            return Err(format_err!(
+                "Main function should have 0, 2, or 3 parameters, not {}.",
+                n
+            )
+            .into());
+        }
+        let param_ty = |i: usize, name: &str| -> TranslationResult<Box<Type>> {
+            match self.ast_context.index(parameters[i]).kind {
+                CDeclKind::Variable { ref typ, .. } => self.convert_type(typ.ctype),
+                _ => Err(
+                    format_err!("Cannot find type of '{}' argument in main function", name).into(),
+                ),
+            }
+        };
+
+        let main_fn_name = self
+            .renamer
+            .borrow()
+            .get(&main_id)
+            .expect("Could not find main function in renamer");
+        let helper_name = self.renamer.borrow_mut().pick_name("main_with_args");
+        let i32_ty = || mk().path_ty(vec!["i32"]);
+        let bufs_ty = || {
+            mk().path_ty(vec![mk().path_segment_with_args(
+                "Vec",
+                mk().angle_bracketed_args(vec![u8_vec_ty()]),
+            )])
+        };
+        let no_args: Vec<Box<Expr>> = vec![];
+
+        // fn main_with_args(mut args: Vec<Vec<u8>>) -> i32
+        let mut stmts: Vec<Stmt> = vec![];
+        let mut main_args: Vec<Box<Expr>> = vec![];
+        if n >= 2 {
+            stmts.extend(ptr_array_stmts("argv", "args"));
+            let argc = mk().binary_expr(
+                BinOp::Sub(Default::default()),
+                mk().method_call_expr(mk().path_expr(vec!["argv"]), "len", no_args.clone()),
+                mk().lit_expr(mk().int_lit(1, "")),
+            );
+            let argv = mk().method_call_expr(mk().path_expr(vec!["argv"]), "as_mut_ptr", no_args);
+            main_args.push(mk().cast_expr(argc, param_ty(0, "argc")?));
+            main_args.push(mk().cast_expr(argv, param_ty(1, "argv")?));
+        }
+        if n >= 3 {
+            // Each variable becomes a `NAME=VALUE` buffer.
+            stmts.push(vec_local("vars", u8_vec_ty()));
+            let mut body = vec![os_string_bytes_local(
+                "var",
+                mk().path_expr(vec!["var_name"]),
+            )];
+            body.push(push_stmt("var", mk().lit_expr(b'=')));
+            body.push(mk().semi_stmt(mk().method_call_expr(
+                mk().path_expr(vec!["var"]),
+                "extend",
+                vec![mk().call_expr(
+                    mk().abs_path_expr(vec!["std", "os", "unix", "ffi", "OsStringExt", "into_vec"]),
+                    vec![mk().path_expr(vec!["var_value"])],
+                )],
+            )));
+            body.push(push_stmt("var", mk().lit_expr(0u8)));
+            body.push(push_stmt("vars", mk().path_expr(vec!["var"])));
+            stmts.push(mk().semi_stmt(mk().for_expr(
+                mk().tuple_pat(vec![
+                    mk().ident_pat("var_name"),
+                    mk().ident_pat("var_value"),
+                ]),
+                mk().call_expr(mk().abs_path_expr(vec!["std", "env", "vars_os"]), vec![]),
+                mk().block(body),
+                None::<Ident>,
+            )));
+            stmts.extend(ptr_array_stmts("envp", "vars"));
+            let envp = mk().method_call_expr(mk().path_expr(vec!["envp"]), "as_mut_ptr", vec![]);
+            main_args.push(mk().cast_expr(envp, param_ty(2, "envp")?));
+        }
+        let call_main = mk().call_expr(mk().path_expr(vec![main_fn_name]), main_args);
+        let code = if let CTypeKind::Void = self.ast_context.resolve_type(ret.ctype).kind {
+            stmts.push(mk().semi_stmt(
+                mk().unsafe_block_expr(mk().unsafe_block(vec![mk().semi_stmt(call_main)])),
+            ));
+            mk().lit_expr(mk().int_lit(0, ""))
+        } else {
+            mk().unsafe_block_expr(
+                mk().unsafe_block(vec![mk().expr_stmt(mk().cast_expr(call_main, i32_ty()))]),
+            )
+        };
+        stmts.push(mk().expr_stmt(code));
+        let args_pat = if n >= 2 {
+            mk().mutbl().ident_pat("args")
+        } else {
+            mk().ident_pat("_args")
+        };
+        let helper = mk().fn_item(
+            mk().fn_decl(
+                &helper_name,
+                vec![mk().arg(bufs_ty(), args_pat)],
+                None,
+                ReturnType::Type(Default::default(), i32_ty()),
+            ),
+            mk().block(stmts),
+        );
+
+        // pub fn main()
+        let main_stmts = vec![
+            mk().local_stmt(Box::new(mk().local(
+                mk().mutbl().ident_pat("args"),
+                Some(bufs_ty()),
+                Some(mk().call_expr(mk().path_expr(vec!["Vec", "new"]), vec![])),
+            ))),
+            mk().semi_stmt(mk().for_expr(
+                mk().ident_pat("arg"),
+                mk().call_expr(mk().abs_path_expr(vec!["std", "env", "args_os"]), vec![]),
+                mk().block(vec![
+                    os_string_bytes_local("arg", mk().path_expr(vec!["arg"])),
+                    push_stmt("arg", mk().lit_expr(0u8)),
+                    push_stmt("args", mk().path_expr(vec!["arg"])),
+                ]),
+                None::<Ident>,
+            )),
+            mk().semi_stmt(mk().call_expr(
+                mk().abs_path_expr(vec!["std", "process", "exit"]),
+                vec![mk().call_expr(
+                    mk().path_expr(vec![&helper_name]),
+                    vec![mk().path_expr(vec!["args"])],
+                )],
+            )),
+        ];
+        let main = mk().pub_().fn_item(
+            mk().fn_decl("main", vec![], None, ReturnType::Default),
+            mk().block(main_stmts),
+        );
+
+        let mut items = vec![*helper, *main];
+        if self.tcfg.emit_main_run_fn {
+            // pub fn run(args: &[&str]) -> i32
+            let run_name = self.renamer.borrow_mut().pick_name("run");
+            let arg_bytes = mk().method_call_expr(
+                mk().method_call_expr(mk().path_expr(vec!["arg"]), "as_bytes", vec![]),
+                "to_vec",
+                vec![],
+            );
+            let run_stmts = vec![
+                mk().local_stmt(Box::new(mk().local(
+                    mk().mutbl().ident_pat("c_args"),
+                    Some(bufs_ty()),
+                    Some(mk().call_expr(mk().path_expr(vec!["Vec", "new"]), vec![])),
+                ))),
+                mk().semi_stmt(mk().for_expr(
+                    mk().ident_pat("arg"),
+                    mk().path_expr(vec!["args"]),
+                    mk().block(vec![
+                        mk().local_stmt(Box::new(mk().local(
+                            mk().mutbl().ident_pat("c_arg"),
+                            Some(u8_vec_ty()),
+                            Some(arg_bytes),
+                        ))),
+                        push_stmt("c_arg", mk().lit_expr(0u8)),
+                        push_stmt("c_args", mk().path_expr(vec!["c_arg"])),
+                    ]),
+                    None::<Ident>,
+                )),
+                mk().expr_stmt(mk().call_expr(
+                    mk().path_expr(vec![&helper_name]),
+                    vec![mk().path_expr(vec!["c_args"])],
+                )),
+            ];
+            let str_slice_ty = mk().ref_ty(mk().slice_ty(mk().ref_ty(mk().path_ty(vec!["str"]))));
+            let run = mk().pub_().fn_item(
+                mk().fn_decl(
+                    &run_name,
+                    vec![mk().arg(str_slice_ty, mk().ident_pat("args"))],
+                    None,
+                    ReturnType::Type(Default::default(), i32_ty()),
+                ),
+                mk().block(run_stmts),
+            );
+            items.push(*run);
+        }
+        Ok(items)
+    }
 }
@@ -1,6 +1,6 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::char;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::ops::Index;
 use std::path::{self, PathBuf};
@@ -30,24 +30,34 @@ use crate::c_ast::iterators::{DFExpr, SomeId};
 use crate::c_ast::*;
 use crate::cfg;
 use crate::convert_type::TypeConverter;
+use crate::rename_map::Rename;
 use crate::renamer::Renamer;
 use crate::with_stmts::WithStmts;
 use crate::{c_ast, format_translation_err};
 use crate::{ExternCrate, ExternCrateDetails, TranspilerConfig};
 use c2rust_ast_exporter::clang_ast::LRValue;
 
+mod anonymous_members;
 mod assembly;
 mod atomics;
 mod builtins;
 mod comments;
+mod cxx;
+mod function_filter;
+mod libc_idioms;
 mod literals;
 mod main_function;
 mod named_references;
+mod offset_of;
 mod operators;
 mod simd;
 mod structs;
+mod sys_crates;
+mod unnamed_types;
 mod variadic;
+mod wasm;
 
+pub use self::wasm::WasmTarget;
 pub use crate::diagnostics::{TranslationError, TranslationErrorKind};
 use crate::CrateSet;
 use crate::PragmaVec;
@@ -212,6 +222,8 @@ pub struct FuncContext {
     va_list_arg_name: Option<String>,
     /// The va_list decls that are either `va_start`ed or `va_copy`ed.
     va_list_decl_ids: Option<IndexSet<CDeclId>>,
+    /// The return type of the function, which returns a C++ reference when it's one.
+    return_type: Option<CQualTypeId>,
 }
 
 impl FuncContext {
@@ -223,6 +235,7 @@ impl FuncContext {
         self.name = Some(fn_name.to_string());
         self.va_list_arg_name = None;
         self.va_list_decl_ids = None;
+        self.return_type = None;
     }
 
     pub fn get_name(&self) -> &str {
@@ -248,6 +261,7 @@ pub struct Translation<'c> {
     pub features: RefCell<IndexSet<&'static str>>,
     sectioned_static_initializers: RefCell<Vec<Stmt>>,
     extern_crates: RefCell<CrateSet>,
+    uses_container_of: Cell<bool>,
 
     // Translation state and utilities
     type_converter: RefCell<TypeConverter>,
@@ -262,10 +276,14 @@ pub struct Translation<'c> {
     pub comment_store: RefCell<CommentStore>, // Outgoing comments
 
     spans: HashMap<SomeId, Span>,
+    doc_comments: HashMap<CDeclId, Vec<String>>,
 
     // Items indexed by file id of the source
     items: RefCell<IndexMap<FileId, ItemStore>>,
 
+    // Items of C++ namespaces, indexed by the path of the namespace
+    namespace_items: RefCell<IndexMap<Vec<String>, ItemStore>>,
+
     // Mod names to try to stop collisions from happening
     mod_names: RefCell<IndexMap<String, PathBuf>>,
 
@@ -491,7 +509,7 @@ pub fn translate(
     ast_context: TypedAstContext,
     tcfg: &TranspilerConfig,
     main_file: PathBuf,
-) -> (String, PragmaVec, CrateSet) {
+) -> (String, PragmaVec, CrateSet, Vec<Rename>) {
     let mut t = Translation::new(ast_context, tcfg, main_file.as_path());
     let ctx = ExprContext {
         used: true,
@@ -506,7 +524,9 @@ pub fn translate(
     };
 
     {
-        t.use_crate(ExternCrate::Libc);
+        if !t.uses_wasm_libc() {
+            t.use_crate(ExternCrate::Libc);
+        }
 
         // Sort the top-level declarations by file and source location so that we
         // preserve the ordering of all declarations in each file.
@@ -517,7 +537,7 @@ pub fn translate(
         // Headers often pull in declarations that are unused;
         // we simplify the translator output by omitting those.
         t.ast_context
-            .prune_unwanted_decls(tcfg.preserve_unused_functions);
+            .prune_unwanted_decls(tcfg.preserve_unused_functions, tcfg.header_only);
 
         enum Name<'a> {
             Var(&'a str),
@@ -601,16 +621,26 @@ pub fn translate(
                 || prenamed_decls.values().any(|id| *id == *decl_id)
         }
 
+        let member_type_names = t.member_type_names();
+
         // Populate renamer with top-level names
         for (&decl_id, decl) in t.ast_context.iter_decls() {
             use CDeclKind::*;
             let decl_name = match decl.kind {
                 _ if contains(&t.ast_context.prenamed_decls, &decl_id) => Name::None,
+                _ if member_type_names.contains_key(&decl_id) => {
+                    Name::Type(&member_type_names[&decl_id])
+                }
                 Struct { ref name, .. } => some_type_name(name.as_ref().map(String::as_str)),
                 Enum { ref name, .. } => some_type_name(name.as_ref().map(String::as_str)),
                 Union { ref name, .. } => some_type_name(name.as_ref().map(String::as_str)),
                 Typedef { ref name, .. } => Name::Type(name),
-                Function { ref name, .. } => Name::Var(name),
+                // Member functions are named within their class instead
+                Function {
+                    ref name,
+                    record: None,
+                    ..
+                } => Name::Var(name),
                 EnumConstant { ref name, .. } => Name::Var(name),
                 Variable { ref ident, .. } if t.ast_context.c_decls_top.contains(&decl_id) => {
                     Name::Var(ident)
@@ -621,9 +651,10 @@ pub fn translate(
             match decl_name {
                 Name::None => (),
                 Name::Anonymous => {
+                    let name = t.unnamed_type_name(decl_id);
                     t.type_converter
                         .borrow_mut()
-                        .declare_decl_name(decl_id, "C2RustUnnamed");
+                        .declare_decl_name(decl_id, &name);
                 }
                 Name::Type(name) => {
                     t.type_converter
@@ -652,14 +683,14 @@ pub fn translate(
                         use ConvertedDecl::*;
                         match converted_decl {
                             Item(item) => {
-                                t.insert_item(item, decl);
+                                t.insert_item(item, decl_id);
                             }
                             ForeignItem(item) => {
-                                t.insert_foreign_item(*item, decl);
+                                t.insert_foreign_item(*item, decl_id);
                             }
                             Items(items) => {
                                 for item in items {
-                                    t.insert_item(item, decl);
+                                    t.insert_item(item, decl_id);
                                 }
                             }
                             NoItem => {}
@@ -696,8 +727,16 @@ pub fn translate(
             }
         }
 
+        t.insert_anonymous_member_accessors();
+
+        // With `low_memory`, the bodies of functions are released once they're translated, unless
+        // macros are translated, since their expansions may be in the bodies of functions
+        // translated later.
+        let release_bodies =
+            tcfg.low_memory && !tcfg.translate_const_macros && !tcfg.translate_fn_macros;
+
         // Export top-level value declarations
-        for top_id in &t.ast_context.c_decls_top {
+        for top_id in &t.ast_context.c_decls_top.clone() {
             use CDeclKind::*;
             let needs_export = match t.ast_context[*top_id].kind {
                 Function { is_implicit, .. } => !is_implicit,
@@ -739,14 +778,14 @@ pub fn translate(
                         use ConvertedDecl::*;
                         match converted_decl {
                             Item(item) => {
-                                t.insert_item(item, decl);
+                                t.insert_item(item, *top_id);
                             }
                             ForeignItem(item) => {
-                                t.insert_foreign_item(*item, decl);
+                                t.insert_foreign_item(*item, *top_id);
                             }
                             Items(items) => {
                                 for item in items {
-                                    t.insert_item(item, decl);
+                                    t.insert_item(item, *top_id);
                                 }
                             }
                             NoItem => {}
@@ -760,13 +799,22 @@ pub fn translate(
                 {
                     t.generate_submodule_imports(*top_id, decl_file_id);
                 }
+
+                if release_bodies && t.ast_context.c_main != Some(*top_id) {
+                    t.ast_context.release_function_body(*top_id);
+                }
             }
         }
 
         // Add the main entry point
         if let Some(main_id) = t.ast_context.c_main {
             match t.convert_main(main_id) {
-                Ok(item) => t.items.borrow_mut()[&t.main_file].add_item(item),
+                Ok(items) => {
+                    let store = &mut t.items.borrow_mut()[&t.main_file];
+                    for item in items {
+                        store.add_item(item);
+                    }
+                }
                 Err(e) => {
                     let msg = format!("Failed to translate main: {}", e);
                     translate_failure(t.tcfg, &msg)
@@ -788,6 +836,8 @@ pub fn translate(
 
         let mut mod_items: Vec<Box<Item>> = Vec::new();
 
+        t.import_wasm_libc();
+
         // Keep track of new uses we need while building header submodules
         let mut new_uses = ItemStore::new();
 
@@ -878,6 +928,9 @@ pub fn translate(
             // Add the items accumulated
             all_items.extend(items);
 
+            // Add the modules of C++ namespaces
+            all_items.extend(t.namespace_modules(&[]));
+
             //s.print_remaining_comments();
             syn::File {
                 shebang: None,
@@ -885,7 +938,12 @@ pub fn translate(
                 items: all_items.into_iter().map(|x| *x).collect(),
             }
         });
-        (translation, pragmas, crates)
+        let renames = if tcfg.emit_rename_map {
+            t.collect_renames()
+        } else {
+            Vec::new()
+        };
+        (translation, pragmas, crates, renames)
     }
 }
 
@@ -1086,6 +1144,9 @@ fn arrange_header(t: &Translation, is_binary: bool) -> (Vec<syn::Attribute>, Vec
             out_items.push(mk().use_glob_item(mk().abs_path(vec![&t.tcfg.crate_name()])));
         }
     }
+    if t.uses_container_of.get() {
+        out_items.push(offset_of::container_of_macro());
+    }
     (out_attrs, out_items)
 }
 
@@ -1235,15 +1296,37 @@ impl<'c> Translation<'c> {
             comment_context,
             comment_store: RefCell::new(CommentStore::new()),
             spans: HashMap::new(),
+            doc_comments: HashMap::new(),
             sectioned_static_initializers: RefCell::new(Vec::new()),
             items: RefCell::new(items),
+            namespace_items: RefCell::new(IndexMap::new()),
             mod_names: RefCell::new(IndexMap::new()),
             main_file,
             extern_crates: RefCell::new(IndexSet::new()),
+            uses_container_of: Cell::new(false),
             cur_file: RefCell::new(None),
         }
     }
 
+    /// Check if `fexp` refers to the function that the C library's `errno` macro calls to get the
+    /// location of the current thread's `errno`.
+    fn is_errno_location(&self, fexp: CExprId) -> bool {
+        let decl_id = match self.ast_context[fexp].kind {
+            CExprKind::DeclRef(_, decl_id, _) => decl_id,
+            _ => return false,
+        };
+        match self.ast_context[decl_id].kind {
+            // glibc and musl, macOS and the BSDs, and Android and Solaris
+            CDeclKind::Function { ref name, .. } => {
+                matches!(
+                    &name[..],
+                    "__errno_location" | "__error" | "__errno" | "___errno"
+                )
+            }
+            _ => false,
+        }
+    }
+
     fn use_crate(&self, extern_crate: ExternCrate) {
         self.extern_crates.borrow_mut().insert(extern_crate);
     }
@@ -1564,6 +1647,34 @@ impl<'c> Translation<'c> {
     }
 
     fn convert_decl(&self, ctx: ExprContext, decl_id: CDeclId) -> TranslationResult<ConvertedDecl> {
+        if let Some(converted) = self.convert_sys_crate_decl(decl_id) {
+            return Ok(converted);
+        }
+        let mut converted = self.convert_decl_kind(ctx, decl_id)?;
+
+        // Attach the C doc comments to the (first) item.
+        let doc_attrs = self.doc_attrs(decl_id).into_attrs();
+        if !doc_attrs.is_empty() {
+            let attrs = match converted {
+                ConvertedDecl::Item(ref mut item) => item_attrs(item),
+                ConvertedDecl::Items(ref mut items) => {
+                    items.first_mut().and_then(|i| item_attrs(i))
+                }
+                ConvertedDecl::ForeignItem(ref mut item) => foreign_item_attrs(item),
+                ConvertedDecl::NoItem => None,
+            };
+            if let Some(attrs) = attrs {
+                attrs.splice(0..0, doc_attrs);
+            }
+        }
+        Ok(converted)
+    }
+
+    fn convert_decl_kind(
+        &self,
+        ctx: ExprContext,
+        decl_id: CDeclId,
+    ) -> TranslationResult<ConvertedDecl> {
         let decl = self
             .ast_context
             .get_decl(&decl_id)
@@ -1765,7 +1876,7 @@ impl<'c> Translation<'c> {
                                 .borrow_mut()
                                 .declare_field_name(decl_id, x, name);
                             let typ = self.convert_type(typ.ctype)?;
-                            field_syns.push(mk().pub_().struct_field(name, typ))
+                            field_syns.push(self.doc_attrs(x).pub_().struct_field(name, typ))
                         }
                         _ => {
                             return Err(TranslationError::generic(
@@ -1857,13 +1968,20 @@ impl<'c> Translation<'c> {
                 ref parameters,
                 body,
                 ref attrs,
+                record,
+                this_type,
+                ref link_name,
                 ..
             } => {
-                let new_name = &self
-                    .renamer
-                    .borrow()
-                    .get(&decl_id)
-                    .expect("Functions should already be renamed");
+                let new_name = &match record {
+                    // Member functions are named within the `impl` of their class
+                    Some(_) => cxx::method_name(name),
+                    None => self
+                        .renamer
+                        .borrow()
+                        .get(&decl_id)
+                        .expect("Functions should already be renamed"),
+                };
 
                 if self.import_simd_function(new_name)? {
                     return Ok(ConvertedDecl::NoItem);
@@ -1899,6 +2017,20 @@ impl<'c> Translation<'c> {
 
                 let is_main = self.ast_context.c_main == Some(decl_id);
 
+                if body.is_none() {
+                    self.check_wasm_import(decl_id, name);
+                }
+
+                // Functions left in C are only declared
+                let body = if self.links_c_definition(decl_id) {
+                    None
+                } else {
+                    body
+                };
+
+                // C++ functions are exported and imported by their mangled names
+                let name = link_name.as_ref().unwrap_or(name);
+
                 let converted_function = self.convert_function(
                     ctx,
                     span,
@@ -1915,24 +2047,31 @@ impl<'c> Translation<'c> {
                     attrs,
                 );
 
-                converted_function.or_else(|e| match self.tcfg.replace_unsupported_decls {
-                    ReplaceMode::Extern if body.is_none() => self.convert_function(
-                        ctx,
-                        span,
-                        is_global,
-                        false,
-                        is_main,
-                        is_variadic,
-                        is_extern,
-                        new_name,
-                        name,
-                        &args,
-                        ret,
-                        None,
-                        attrs,
-                    ),
-                    _ => Err(e),
-                })
+                let converted_function =
+                    converted_function.or_else(|e| match self.tcfg.replace_unsupported_decls {
+                        ReplaceMode::Extern if body.is_none() => self.convert_function(
+                            ctx,
+                            span,
+                            is_global,
+                            false,
+                            is_main,
+                            is_variadic,
+                            is_extern,
+                            new_name,
+                            name,
+                            &args,
+                            ret,
+                            None,
+                            attrs,
+                        ),
+                        _ => Err(e),
+                    });
+
+                match record {
+                    Some(record_id) => converted_function
+                        .and_then(|converted| self.convert_method(record_id, this_type, converted)),
+                    None => converted_function,
+                }
             }
 
             Typedef { ref typ, .. } => {
@@ -1962,24 +2101,25 @@ impl<'c> Translation<'c> {
                 ))
             }
 
-            // Externally-visible variable without initializer (definition elsewhere)
+            // Externally-visible variable without initializer (definition elsewhere, or left in C)
             Variable {
                 is_externally_visible: true,
                 has_static_duration,
                 has_thread_duration,
-                is_defn: false,
+                is_defn,
                 ref ident,
                 initializer,
                 typ,
                 ref attrs,
+                ref link_name,
                 ..
-            } => {
+            } if !is_defn || self.links_c_definition(decl_id) => {
                 assert!(
                     has_static_duration || has_thread_duration,
                     "An extern variable must be static or thread-local"
                 );
                 assert!(
-                    initializer.is_none(),
+                    initializer.is_none() || is_defn,
                     "An extern variable that isn't a definition can't have an initializer"
                 );
 
@@ -2000,7 +2140,8 @@ impl<'c> Translation<'c> {
                 } else {
                     ""
                 };
-                let mut extern_item = mk_linkage(true, &new_name, ident)
+                let link_name = link_name.as_deref().unwrap_or(ident);
+                let mut extern_item = mk_linkage(true, &new_name, link_name)
                     .span(span)
                     .set_mutbl(mutbl)
                     .vis(visibility);
@@ -2031,6 +2172,7 @@ impl<'c> Translation<'c> {
                 initializer,
                 typ,
                 ref attrs,
+                ref link_name,
                 ..
             } if has_static_duration || has_thread_duration => {
                 if has_thread_duration {
@@ -2090,7 +2232,8 @@ impl<'c> Translation<'c> {
                 };
 
                 let static_def = if is_externally_visible {
-                    mk_linkage(false, new_name, ident).pub_().extern_("C")
+                    let link_name = link_name.as_deref().unwrap_or(ident);
+                    mk_linkage(false, new_name, link_name).pub_().extern_("C")
                 } else if self.cur_file.borrow().is_some() {
                     mk().pub_()
                 } else {
@@ -2178,6 +2321,9 @@ impl<'c> Translation<'c> {
                 warn!("ignoring static assert during translation");
                 Ok(ConvertedDecl::NoItem)
             }
+
+            // The declarations of C++ namespaces are translated in their modules on their own.
+            Namespace { .. } => Ok(ConvertedDecl::NoItem),
         }
     }
 
@@ -2246,9 +2392,11 @@ impl<'c> Translation<'c> {
         attrs: &IndexSet<c_ast::Attribute>,
     ) -> TranslationResult<ConvertedDecl> {
         self.function_context.borrow_mut().enter_new(name);
+        self.function_context.borrow_mut().return_type = return_type;
 
         self.with_scope(|| {
             let mut args: Vec<FnArg> = vec![];
+            let mut param_names = HashSet::new();
 
             // handle regular (non-variadic) arguments
             for &(decl_id, ref var, typ) in arguments {
@@ -2275,6 +2423,7 @@ impl<'c> Translation<'c> {
                                 var, name
                             )
                         });
+                    param_names.insert(new_var.clone());
 
                     mk().set_mutbl(mutbl).ident_pat(new_var)
                 };
@@ -2350,6 +2499,9 @@ impl<'c> Translation<'c> {
                     _ => panic!("function body expects to be a compound statement"),
                 };
                 body_stmts.append(&mut self.convert_function_body(ctx, name, body_ids, ret)?);
+                if self.tcfg.translate_for_loops {
+                    body_stmts = cfg::for_loops::reconstruct_for_loops(body_stmts, param_names);
+                }
                 let mut block = stmts_block(body_stmts);
                 if let Some(span) = self.get_span(SomeId::Stmt(body)) {
                     block.set_span(span);
@@ -2365,7 +2517,7 @@ impl<'c> Translation<'c> {
                     mk()
                 } else if (is_global && !is_inline) || is_extern_inline {
                     mk_linkage(false, new_name, name).extern_("C").pub_()
-                } else if self.cur_file.borrow().is_some() {
+                } else if self.cur_file.borrow().is_some() || self.tcfg.header_only {
                     mk().extern_("C").pub_()
                 } else {
                     mk().extern_("C")
@@ -2412,8 +2564,9 @@ impl<'c> Translation<'c> {
             } else {
                 // Translating an extern function declaration
 
-                // When putting extern fns into submodules, they need to be public to be accessible
-                let visibility = if self.tcfg.reorganize_definitions {
+                // When putting extern fns into submodules, they need to be public to be
+                // accessible, and the bindings of a header are its crate's API
+                let visibility = if self.tcfg.reorganize_definitions || self.tcfg.header_only {
                     "pub"
                 } else {
                     ""
@@ -2907,7 +3060,7 @@ impl<'c> Translation<'c> {
         typ: CQualTypeId,
     ) -> TranslationResult<ConvertedVariable> {
         let init = match initializer {
-            Some(x) => self.convert_expr(ctx.used(), x),
+            Some(x) => self.convert_reference_binding(ctx.used(), typ.ctype, x),
             None => self.implicit_default_expr(typ.ctype, ctx.is_static),
         };
 
@@ -2925,7 +3078,7 @@ impl<'c> Translation<'c> {
             self.convert_type(typ.ctype)?
         };
 
-        let mutbl = if typ.qualifiers.is_const {
+        let mutbl = if self.ast_context.is_const_qualified(typ) {
             Mutability::Immutable
         } else {
             Mutability::Mutable
@@ -2956,7 +3109,7 @@ impl<'c> Translation<'c> {
         };
         let ty = self.convert_type(type_id)?;
         let mut zero = mk().lit_expr(mk().int_unsuffixed_lit(0));
-        if is_static && !pointee.qualifiers.is_const {
+        if is_static && !self.ast_context.is_const_qualified(pointee) {
             let mut qtype = pointee;
             qtype.qualifiers.is_const = true;
             let ty_ = self
@@ -2985,7 +3138,7 @@ impl<'c> Translation<'c> {
                 expr: e,
                 ..
             }) => {
-                if write == lhs_type.qualifiers.is_const {
+                if write == self.ast_context.is_const_qualified(lhs_type) {
                     let lhs_type = self.convert_type(lhs_type.ctype)?;
                     let ty = mk().set_mutbl(mutbl).ptr_ty(lhs_type);
 
@@ -3303,6 +3456,11 @@ impl<'c> Translation<'c> {
                     }
                 }
 
+                // Static member functions are named by their class
+                if let Some(path) = self.convert_static_method_ref(decl_id) {
+                    return Ok(WithStmts::new_val(path));
+                }
+
                 let varname = decl.get_name().expect("expected variable name").to_owned();
                 let rustname = self
                     .renamer
@@ -3323,6 +3481,13 @@ impl<'c> Translation<'c> {
 
                 let mut val = mk().path_expr(vec![rustname]);
 
+                // C++ references are used as what they refer to
+                if let CDeclKind::Variable { typ, .. } = decl {
+                    if self.is_reference_type(typ.ctype) {
+                        val = mk().unary_expr(UnOp::Deref(Default::default()), val);
+                    }
+                }
+
                 // If the variable is volatile and used as something that isn't an LValue, this
                 // constitutes a volatile read.
                 if lrvalue.is_rvalue() && qual_ty.qualifiers.is_volatile {
@@ -3388,6 +3553,16 @@ impl<'c> Translation<'c> {
                     *val,
                     IntBase::Dec,
                 )?)),
+                OffsetOfKind::Field(val, qty, field_ids) => {
+                    match self.convert_offset_of_fields(ctx, ty, *qty, field_ids)? {
+                        Some(offset) => Ok(WithStmts::new_val(offset)),
+                        None => Ok(WithStmts::new_val(self.mk_int_lit(
+                            ty,
+                            *val,
+                            IntBase::Dec,
+                        )?)),
+                    }
+                }
                 OffsetOfKind::Variable(qty, field_id, expr_id) => {
                     self.use_crate(ExternCrate::Memoffset);
 
@@ -3453,6 +3628,11 @@ impl<'c> Translation<'c> {
             ImplicitCast(ty, expr, kind, opt_field_id, _)
             | ExplicitCast(ty, expr, kind, opt_field_id, _) => {
                 let is_explicit = matches!(expr_kind, CExprKind::ExplicitCast(..));
+                if is_explicit {
+                    if let Some(val) = self.convert_container_of(ctx, ty, expr)? {
+                        return Ok(val);
+                    }
+                }
                 // A reference must be decayed if a bitcast is required. Const casts in
                 // LLVM 8 are now NoOp casts, so we need to include it as well.
                 match kind {
@@ -3720,9 +3900,13 @@ impl<'c> Translation<'c> {
                             })?,
                         )
                         .map(|ty| &self.ast_context.resolve_type(ty.ctype).kind);
-                let is_variadic = match fn_ty {
-                    Some(CTypeKind::Function(_, _, is_variadic, _, _)) => *is_variadic,
-                    _ => false,
+                let (param_tys, is_variadic, returns_ref) = match fn_ty {
+                    Some(CTypeKind::Function(ret, params, is_variadic, _, _)) => (
+                        params.as_slice(),
+                        *is_variadic,
+                        self.is_reference_type(ret.ctype),
+                    ),
+                    _ => (&[][..], false, false),
                 };
                 let func = match self.ast_context[func].kind {
                     // Direct function call
@@ -3731,7 +3915,16 @@ impl<'c> Translation<'c> {
                     // callee is a declref
                     if matches!(self.ast_context[fexp].kind, CExprKind::DeclRef(..)) =>
                         {
-                            self.convert_expr(ctx.used(), fexp)?
+                            if self.tcfg.errno_shim && self.is_errno_location(fexp) {
+                                self.use_crate(ExternCrate::C2RustErrno);
+                                WithStmts::new_val(
+                                    mk().path_expr(vec!["c2rust_errno", "errno_location"]),
+                                )
+                            } else if let Some(path) = self.convert_libc_idiom(fexp, args.len()) {
+                                WithStmts::new_val(path)
+                            } else {
+                                self.convert_expr(ctx.used(), fexp)?
+                            }
                         }
 
                     // Builtin function call
@@ -3784,12 +3977,19 @@ impl<'c> Translation<'c> {
                     // We want to decay refs only when function is variadic
                     ctx.decay_ref = DecayRef::from(is_variadic);
 
-                    let args = self.convert_exprs(ctx.used(), args)?;
+                    let args = self.convert_call_args(ctx.used(), param_tys, args)?;
 
                     let res: TranslationResult<_> = Ok(args.map(|args| mk().call_expr(func, args)));
                     res
                 })?;
 
+                // Calls returning C++ references are used as what they return
+                let call = if returns_ref {
+                    call.map(|call| mk().unary_expr(UnOp::Deref(Default::default()), call))
+                } else {
+                    call
+                };
+
                 self.convert_side_effects_expr(
                     ctx,
                     call,
@@ -3810,6 +4010,9 @@ impl<'c> Translation<'c> {
                                 // Special-case the `(&x)->field` pattern
                                 // Convert it directly into `x.field`
                                 self.convert_expr(ctx, subexpr_id)?
+                            } else if let CExprKind::This(..) = self.ast_context[expr].kind {
+                                // Convert `this->field` into `self.field`
+                                WithStmts::new_val(mk().path_expr(vec!["self"]))
                             } else {
                                 let val = self.convert_expr(ctx, expr)?;
                                 val.map(|v| mk().unary_expr(UnOp::Deref(Default::default()), v))
@@ -3916,6 +4119,16 @@ impl<'c> Translation<'c> {
                 weak,
                 ..
             } => self.convert_atomic(ctx, name, ptr, order, val1, order_fail, val2, weak),
+
+            This(ty) => self.convert_this(ty),
+
+            MemberCall(_, method, object, kind, ref args) => {
+                self.convert_member_call(ctx, method, object, kind, args)
+            }
+
+            New(ty, allocated, count, init) => self.convert_new(ctx, ty, allocated, count, init),
+
+            Delete(_, ptr, _) => self.convert_delete(ctx, ptr),
         }
     }
 
@@ -4318,7 +4531,7 @@ impl<'c> Translation<'c> {
                     _ => panic!("Dereferencing a non-pointer"),
                 };
 
-                let is_const = pointee.qualifiers.is_const;
+                let is_const = self.ast_context.is_const_qualified(pointee);
 
                 let expr_kind = expr.map(|e| &self.ast_context.index(e).kind);
                 match expr_kind {
@@ -4800,12 +5013,115 @@ impl<'c> Translation<'c> {
         result
     }
 
+    /// Get the C source location of `decl` as `file:line:col`.  The path is relative to the
+    /// directory of the main file if it's inside it.
+    fn src_loc_string(&self, decl: &CDecl) -> Option<String> {
+        let loc = decl.loc?;
+        let path = self.ast_context.get_source_path(decl)?;
+        let main_dir = self
+            .ast_context
+            .get_file_path(self.main_file)
+            .and_then(path::Path::parent);
+        let path = main_dir
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path);
+        Some(format!(
+            "{}:{}:{}",
+            path.display(),
+            loc.begin_line,
+            loc.begin_column
+        ))
+    }
+
+    /// Add a `c2rust::src = "file:line:col"` attribute giving the C source location of `decl`, for
+    /// the source map.
+    fn add_src_attr(&self, attrs: &mut Vec<syn::Attribute>, decl: &CDecl) {
+        let loc_str = match self.src_loc_string(decl) {
+            Some(loc_str) => loc_str,
+            None => return,
+        };
+        self.use_feature("register_tool");
+        let meta = mk().meta_namevalue(vec!["c2rust", "src"], loc_str);
+        let prepared = mk().prepare_meta(meta);
+        attrs.push(mk().attribute(AttrStyle::Outer, prepared.path, prepared.tokens));
+    }
+
+    /// List the top-level items, enum constants, and fields whose Rust names differ from their C
+    /// names, for the rename map.
+    fn collect_renames(&self) -> Vec<Rename> {
+        let renamer = self.renamer.borrow();
+        let type_converter = self.type_converter.borrow();
+        let mut renames = Vec::new();
+        for (&decl_id, decl) in self.ast_context.iter_decls() {
+            use CDeclKind::*;
+            let (kind, c_name, is_linked) = match decl.kind {
+                Function {
+                    ref name,
+                    is_global,
+                    ..
+                } => ("function", name, is_global),
+                Variable {
+                    ref ident,
+                    is_externally_visible,
+                    ..
+                } if self.ast_context.c_decls_top.contains(&decl_id) => {
+                    ("variable", ident, is_externally_visible)
+                }
+                EnumConstant { ref name, .. } => ("enum_constant", name, false),
+                MacroObject { ref name, .. } => ("macro", name, false),
+                Struct {
+                    name: Some(ref name),
+                    ..
+                } => ("struct", name, false),
+                Union {
+                    name: Some(ref name),
+                    ..
+                } => ("union", name, false),
+                Enum {
+                    name: Some(ref name),
+                    ..
+                } => ("enum", name, false),
+                Typedef { ref name, .. } => ("typedef", name, false),
+                Field { ref name, .. } if !name.is_empty() => ("field", name, false),
+                _ => continue,
+            };
+            let rust_name = match decl.kind {
+                Struct { .. } | Union { .. } | Enum { .. } | Typedef { .. } => {
+                    type_converter.resolve_decl_name(decl_id)
+                }
+                Field { .. } => type_converter.resolve_field_name(None, decl_id),
+                _ => renamer.get(&decl_id),
+            };
+            let rust_name = match rust_name {
+                Some(rust_name) if rust_name != *c_name => rust_name,
+                _ => continue,
+            };
+            renames.push(Rename {
+                kind,
+                c_name: c_name.clone(),
+                rust_name,
+                link_name: is_linked.then(|| c_name.clone()),
+                source: self.src_loc_string(decl),
+            });
+        }
+        renames
+    }
+
     /// If we're trying to organize item definitions into submodules, add them to a module
     /// scoped "namespace" if we have a path available, otherwise add it to the global "namespace"
-    fn insert_item(&self, mut item: Box<Item>, decl: &CDecl) {
+    fn insert_item(&self, mut item: Box<Item>, decl_id: CDeclId) {
+        let decl = &self.ast_context[decl_id];
         let decl_file_id = self.ast_context.file_id(decl);
 
-        if self.tcfg.reorganize_definitions {
+        if self.tcfg.emit_source_map {
+            if let Some(attrs) = item_attrs(&mut item) {
+                self.add_src_attr(attrs, decl);
+            }
+        }
+
+        if let Some(path) = self.namespace_path(decl_id) {
+            self.insert_namespace_item(path, item);
+        } else if self.tcfg.reorganize_definitions {
             self.use_feature("register_tool");
             let attrs = item_attrs(&mut item).expect("no attrs field on unexpected item variant");
             add_src_loc_attr(attrs, &decl.loc.as_ref().map(|x| x.begin()));
@@ -4822,10 +5138,19 @@ impl<'c> Translation<'c> {
 
     /// If we're trying to organize foreign item definitions into submodules, add them to a module
     /// scoped "namespace" if we have a path available, otherwise add it to the global "namespace"
-    fn insert_foreign_item(&self, mut item: ForeignItem, decl: &CDecl) {
+    fn insert_foreign_item(&self, mut item: ForeignItem, decl_id: CDeclId) {
+        let decl = &self.ast_context[decl_id];
         let decl_file_id = self.ast_context.file_id(decl);
 
-        if self.tcfg.reorganize_definitions {
+        if self.tcfg.emit_source_map {
+            if let Some(attrs) = foreign_item_attrs(&mut item) {
+                self.add_src_attr(attrs, decl);
+            }
+        }
+
+        if let Some(path) = self.namespace_path(decl_id) {
+            self.insert_namespace_foreign_item(path, item);
+        } else if self.tcfg.reorganize_definitions {
             self.use_feature("register_tool");
             let attrs = foreign_item_attrs(&mut item)
                 .expect("no attrs field on unexpected foreign item variant");
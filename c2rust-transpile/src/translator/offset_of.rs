@@ -0,0 +1,235 @@
+//! Translation of `offsetof` and the `container_of` idiom built on it.
+//!
+//! Clang evaluates `offsetof(T, f)` to a constant, but the constant is only right for the target
+//! the C code was parsed for, and a bare number hides which field the code is locating.  Constant
+//! offsets of named fields are translated to `offset_of!(T, f)` from the `memoffset` crate
+//! instead, summing one `offset_of!` per struct for nested members like `offsetof(T, a.b)`.
+//! Members of unions are always at offset 0, so they add nothing.  Offsets in packed structs stay
+//! numbers, since `offset_of!` would need a reference to an unaligned field, and so do offsets in
+//! statics and constants, where `offset_of!` can't be evaluated.
+//!
+//! `container_of(ptr, T, f)`, usually a macro expanding to
+//! `(T *)((char *)(ptr) - offsetof(T, f))`, recovers a pointer to a struct from a pointer to one
+//! of its fields.  Translated directly, it turns into pointer arithmetic on `c_char` with a
+//! hardcoded offset.  It's translated to a call of a `container_of!` macro instead, which is
+//! defined at the top of the file.  The macro steps back from the field with `wrapping_sub` on a
+//! byte pointer, so the result keeps the provenance of `ptr` rather than going through an
+//! integer.
+
+use super::*;
+
+impl<'c> Translation<'c> {
+    /// Get the struct whose field is at an offset within `record_ty`, if `offset_of!` can
+    /// name it.  `Ok(None)` means the record is a union, in which every field is at offset 0.
+    fn offset_of_struct(&self, record_ty: CTypeId) -> Result<Option<CDeclId>, ()> {
+        match self.ast_context.resolve_type(record_ty).kind {
+            CTypeKind::Struct(decl_id) if !self.ast_context.is_packed_struct_decl(decl_id) => {
+                Ok(Some(decl_id))
+            }
+            CTypeKind::Union(_) => Ok(None),
+            _ => Err(()),
+        }
+    }
+
+    /// Build `offset_of!(Struct, field)`.
+    fn mk_offset_of(&self, struct_id: CDeclId, field_id: CFieldId) -> Option<Box<Expr>> {
+        let struct_name = self.resolve_decl_inner_name(struct_id);
+        let field_name = self
+            .type_converter
+            .borrow()
+            .resolve_field_name(None, field_id)?;
+        let macro_body = vec![
+            TokenTree::Ident(mk().ident(struct_name)),
+            TokenTree::Punct(Punct::new(',', Alone)),
+            TokenTree::Ident(mk().ident(field_name)),
+        ];
+        Some(mk().mac_expr(mk().mac(
+            mk().path("offset_of"),
+            macro_body,
+            MacroDelimiter::Paren(Default::default()),
+        )))
+    }
+
+    /// Translate the constant `offsetof` of the field `field_ids` of `qty`, the last of which is
+    /// a field of the struct or union the previous one is.  Returns `None` if the offset can't be
+    /// written with `offset_of!`.
+    pub(super) fn convert_offset_of_fields(
+        &self,
+        ctx: ExprContext,
+        ty: CQualTypeId,
+        qty: CQualTypeId,
+        field_ids: &[CFieldId],
+    ) -> TranslationResult<Option<Box<Expr>>> {
+        if ctx.is_static || ctx.is_const {
+            return Ok(None);
+        }
+
+        let mut terms = Vec::new();
+        let mut record_ty = qty.ctype;
+        for &field_id in field_ids {
+            match self.offset_of_struct(record_ty) {
+                Ok(Some(struct_id)) => match self.mk_offset_of(struct_id, field_id) {
+                    Some(term) => terms.push(term),
+                    None => return Ok(None),
+                },
+                Ok(None) => {}
+                Err(()) => return Ok(None),
+            }
+            record_ty = match self.ast_context[field_id].kind {
+                CDeclKind::Field { typ, .. } => typ.ctype,
+                _ => return Ok(None),
+            };
+        }
+
+        // `offsetof` of a union member is 0, which is clearest as a number
+        let sum = match terms
+            .into_iter()
+            .reduce(|sum, term| mk().binary_expr(BinOp::Add(Default::default()), sum, term))
+        {
+            Some(sum) => sum,
+            None => return Ok(None),
+        };
+        self.use_crate(ExternCrate::Memoffset);
+
+        // offset_of!(Struct, field) as ty
+        let cast_ty = self.convert_type(ty.ctype)?;
+        Ok(Some(mk().cast_expr(sum, cast_ty)))
+    }
+
+    /// Match `(T *)((char *)ptr - offsetof(T, f))`, the `container_of` idiom, returning `ptr`,
+    /// the struct and the field.  `ptr` may also be a `char *` or `void *` without a cast.
+    fn match_container_of(
+        &self,
+        ty: CQualTypeId,
+        expr: CExprId,
+    ) -> Option<(CExprId, CDeclId, CFieldId)> {
+        let strip_parens = |mut expr: CExprId| {
+            while let CExprKind::Paren(_, subexpr) = self.ast_context[expr].kind {
+                expr = subexpr;
+            }
+            expr
+        };
+
+        let pointee = match self.ast_context.resolve_type(ty.ctype).kind {
+            CTypeKind::Pointer(pointee) => pointee,
+            _ => return None,
+        };
+        let struct_id = match self.offset_of_struct(pointee.ctype) {
+            Ok(Some(struct_id)) if !self.ast_context.has_inner_struct_decl(struct_id) => struct_id,
+            _ => return None,
+        };
+
+        let (lhs, rhs) = match self.ast_context[strip_parens(expr)].kind {
+            CExprKind::Binary(_, c_ast::BinOp::Subtract, lhs, rhs, _, _) => (lhs, rhs),
+            _ => return None,
+        };
+
+        let field_id = match self.ast_context.resolve_expr(rhs).1 {
+            CExprKind::OffsetOf(_, OffsetOfKind::Field(_, qty, field_ids))
+                if field_ids.len() == 1
+                    && self.offset_of_struct(qty.ctype) == Ok(Some(struct_id)) =>
+            {
+                field_ids[0]
+            }
+            _ => return None,
+        };
+
+        let is_byte_pointer = |expr: CExprId| {
+            let ty = match self.ast_context[expr].kind.get_type() {
+                Some(ty) => ty,
+                None => return false,
+            };
+            match self.ast_context.resolve_type(ty).kind {
+                CTypeKind::Pointer(pointee) => matches!(
+                    self.ast_context.resolve_type(pointee.ctype).kind,
+                    CTypeKind::Char | CTypeKind::SChar | CTypeKind::UChar | CTypeKind::Void
+                ),
+                _ => false,
+            }
+        };
+        let lhs = strip_parens(lhs);
+        if !is_byte_pointer(lhs) {
+            return None;
+        }
+        // Skip the cast to `char *`, which `container_of!` does itself
+        let ptr = match self.ast_context[lhs].kind {
+            CExprKind::ExplicitCast(_, subexpr, CastKind::BitCast, _, _) => subexpr,
+            _ => lhs,
+        };
+        Some((ptr, struct_id, field_id))
+    }
+
+    /// Translate the cast of `expr` to `ty` as `container_of!(ptr, T, f)` if it's the
+    /// `container_of` idiom.
+    pub(super) fn convert_container_of(
+        &self,
+        ctx: ExprContext,
+        ty: CQualTypeId,
+        expr: CExprId,
+    ) -> TranslationResult<Option<WithStmts<Box<Expr>>>> {
+        if ctx.is_static || ctx.is_const {
+            return Ok(None);
+        }
+        let (ptr, struct_id, field_id) = match self.match_container_of(ty, expr) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let field_name = match self
+            .type_converter
+            .borrow()
+            .resolve_field_name(None, field_id)
+        {
+            Some(field_name) => field_name,
+            None => return Ok(None),
+        };
+        let struct_name = self.resolve_decl_inner_name(struct_id);
+
+        self.use_crate(ExternCrate::Memoffset);
+        self.uses_container_of.set(true);
+
+        let is_const = match self.ast_context.resolve_type(ty.ctype).kind {
+            CTypeKind::Pointer(pointee) => self.ast_context.is_const_qualified(pointee),
+            _ => false,
+        };
+        let cast_ty = if is_const {
+            Some(self.convert_type(ty.ctype)?)
+        } else {
+            None
+        };
+
+        let ptr = self.convert_expr(ctx.used(), ptr)?;
+        Ok(Some(ptr.map(|ptr| {
+            use syn::__private::ToTokens;
+            let mut macro_body = ptr.to_token_stream();
+            macro_body.extend(vec![
+                TokenTree::Punct(Punct::new(',', Alone)),
+                TokenTree::Ident(mk().ident(struct_name)),
+                TokenTree::Punct(Punct::new(',', Alone)),
+                TokenTree::Ident(mk().ident(field_name)),
+            ]);
+            let mac = mk().mac_expr(mk().mac(
+                mk().path("container_of"),
+                macro_body,
+                MacroDelimiter::Paren(Default::default()),
+            ));
+            match cast_ty {
+                Some(cast_ty) => mk().cast_expr(mac, cast_ty),
+                None => mac,
+            }
+        })))
+    }
+}
+
+/// The definition of the `container_of!` macro, which files that use it start with.
+pub(super) fn container_of_macro() -> Box<Item> {
+    let mac: ItemMacro = syn::parse_quote! {
+        macro_rules! container_of {
+            ($ptr:expr, $type:ty, $field:ident) => {
+                ($ptr as *mut u8)
+                    .wrapping_sub(offset_of!($type, $field))
+                    .cast::<$type>()
+            };
+        }
+    };
+    Box::new(Item::Macro(mac))
+}
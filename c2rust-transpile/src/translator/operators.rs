@@ -848,7 +848,7 @@ impl<'c> Translation<'c> {
                                 TranslationError::generic("Address-of should return a pointer")
                             })?;
 
-                    let mutbl = if pointee_ty.qualifiers.is_const {
+                    let mutbl = if self.ast_context.is_const_qualified(pointee_ty) {
                         Mutability::Immutable
                     } else {
                         Mutability::Mutable
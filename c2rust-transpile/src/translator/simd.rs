@@ -92,6 +92,8 @@ impl<'c> Translation<'c> {
         Ok(match name {
             // Public API SIMD typedefs:
             "__m128i" | "__m128" | "__m128d" | "__m64" | "__m256" | "__m256d" | "__m256i" => {
+                self.check_wasm_arch(&format!("x86 SIMD type {}", name))?;
+
                 // __m64 and MMX support were removed from upstream Rust.
                 // See https://github.com/immunant/c2rust/issues/369
                 if name == "__m64" {
@@ -151,6 +153,8 @@ impl<'c> Translation<'c> {
     /// use statement is generated, `true` is returned, and no further processing will need to be done.
     pub fn import_simd_function(&self, name: &str) -> TranslationResult<bool> {
         if name.starts_with("_mm") {
+            self.check_wasm_arch(&format!("x86 SIMD intrinsic {}", name))?;
+
             // REVIEW: This will do a linear lookup against all SIMD fns. Could use a lazy static hashset
             if MISSING_SIMD_FUNCTIONS.contains(&name) {
                 return Err(format_err!(
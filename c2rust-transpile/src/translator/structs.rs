@@ -175,7 +175,10 @@ impl<'a> Translation<'a> {
                             })
                         }
 
-                        let field = mk().pub_().struct_field(field_name.clone(), ty);
+                        let field = self
+                            .doc_attrs(*field_id)
+                            .pub_()
+                            .struct_field(field_name.clone(), ty);
 
                         reorganized_fields.push(FieldType::Regular {
                             name: field_name,
@@ -0,0 +1,128 @@
+//! Imports of the declarations of well-known C libraries from their `-sys` crates.
+//!
+//! With `use_sys_crates`, the declarations of the headers of zlib, OpenSSL, SQLite and libcurl
+//! aren't translated into `extern` blocks and type definitions of their own.  They're imported
+//! from the library's published `-sys` crate instead, so every module of a project, and any
+//! other Rust code using the library, shares the same types.
+//!
+//! Declarations are imported by their C names: the functions the headers declare without
+//! defining them, their external variables, typedefs, named structs, unions and enums, enum
+//! constants, and object-like macros.  `static inline` functions defined in the headers are still
+//! translated, since the `-sys` crates don't export them.
+
+use std::path::Path;
+
+use super::{ConvertedDecl, Translation};
+use crate::c_ast::{CDeclId, CDeclKind};
+use crate::{ExternCrate, ExternCrateDetails};
+use c2rust_ast_builder::mk;
+
+/// The `-sys` crate that provides the declarations of the header at `path`, if there is one.
+fn header_sys_crate(path: &Path) -> Option<ExternCrate> {
+    let path = path.to_str()?;
+    let file_name = path.rsplit('/').next()?;
+    if file_name == "zlib.h" || file_name == "zconf.h" {
+        Some(ExternCrate::LibzSys)
+    } else if path.contains("/openssl/") {
+        Some(ExternCrate::OpensslSys)
+    } else if file_name == "sqlite3.h" {
+        Some(ExternCrate::Libsqlite3Sys)
+    } else if path.contains("/curl/") {
+        Some(ExternCrate::CurlSys)
+    } else {
+        None
+    }
+}
+
+impl<'c> Translation<'c> {
+    /// Import `decl_id` from the `-sys` crate of the header declaring it, if it's declared by a
+    /// header with one.
+    pub(super) fn convert_sys_crate_decl(&self, decl_id: CDeclId) -> Option<ConvertedDecl> {
+        if !self.tcfg.use_sys_crates {
+            return None;
+        }
+        let decl = self.ast_context.get_decl(&decl_id)?;
+        let path = self
+            .ast_context
+            .get_file_path(self.ast_context.file_id(decl)?)?;
+        let sys_crate = header_sys_crate(path)?;
+
+        let prenamed_by = self
+            .ast_context
+            .prenamed_decls
+            .iter()
+            .find(|&(_, &subdecl_id)| subdecl_id == decl_id)
+            .map(|(&typedef_id, _)| typedef_id);
+        let type_name = || self.type_converter.borrow().resolve_decl_name(decl_id);
+        let value_name = || self.renamer.borrow().get(&decl_id);
+
+        use CDeclKind::*;
+        let (c_name, rust_name) = match decl.kind {
+            Function {
+                body: None,
+                ref name,
+                ..
+            }
+            | EnumConstant { ref name, .. }
+            | MacroObject { ref name, .. } => (name.clone(), value_name()?),
+            Variable {
+                is_defn: false,
+                is_externally_visible: true,
+                ref ident,
+                ..
+            } if self.ast_context.c_decls_top.contains(&decl_id) => (ident.clone(), value_name()?),
+            // Typedefs of unnamed types are imported as the types they name.
+            Typedef { ref name, .. } if !self.ast_context.prenamed_decls.contains_key(&decl_id) => {
+                (name.clone(), type_name()?)
+            }
+            Struct { ref name, .. } | Union { ref name, .. } | Enum { ref name, .. } => {
+                let c_name = match prenamed_by {
+                    Some(typedef_id) => match self.ast_context[typedef_id].kind {
+                        Typedef { ref name, .. } => name.clone(),
+                        _ => return None,
+                    },
+                    None => name.clone()?,
+                };
+                (c_name, type_name()?)
+            }
+            _ => return None,
+        };
+
+        self.use_crate(sys_crate);
+        let crate_ident = ExternCrateDetails::from(sys_crate).ident;
+        let rename = if rust_name == c_name {
+            None
+        } else {
+            Some(rust_name)
+        };
+        let path = mk().abs_path(vec![crate_ident, c_name]);
+        Some(ConvertedDecl::Item(
+            mk().pub_().use_simple_item(path, rename),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_header_sys_crates() {
+        let sys_crate = |path: &str| header_sys_crate(Path::new(path));
+        assert_eq!(sys_crate("/usr/include/zlib.h"), Some(ExternCrate::LibzSys));
+        assert_eq!(
+            sys_crate("/usr/include/openssl/ssl.h"),
+            Some(ExternCrate::OpensslSys)
+        );
+        assert_eq!(
+            sys_crate("/opt/sqlite/include/sqlite3.h"),
+            Some(ExternCrate::Libsqlite3Sys)
+        );
+        assert_eq!(
+            sys_crate("/usr/include/x86_64-linux-gnu/curl/curl.h"),
+            Some(ExternCrate::CurlSys)
+        );
+        assert_eq!(sys_crate("/usr/include/stdio.h"), None);
+        assert_eq!(sys_crate("/proj/src/zlib_util.h"), None);
+    }
+}
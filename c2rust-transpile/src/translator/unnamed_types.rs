@@ -0,0 +1,124 @@
+//! Names of the structs, unions and enums that have no name in C.
+//!
+//! Rust types need names, so each unnamed type is named `C2RustUnnamed_` followed by a hash of
+//! the name of the file declaring it and of its structure: whether it's a struct, union or enum,
+//! and the names and types of its fields or the names of its enumerators.  Numbering the types in
+//! the order they're declared instead would rename every type after one added anywhere earlier,
+//! including in an included header, so re-translating a project after an unrelated change would
+//! produce a diff touching every use of them.  With the hash, a type's name only changes when the
+//! type itself does, and the refactoring tool, which recognizes these types by their
+//! `C2RustUnnamed` prefix, can refer to them from one translation to the next.
+//!
+//! Identical unnamed types declared by the same file get the same hash, and are told apart by
+//! the renamer's usual numeric suffix in declaration order.
+
+use std::fmt::Write as _;
+
+use super::*;
+
+/// The 32-bit FNV-1a hash of `s`, which, unlike `std`'s hashers, is the same for every build of
+/// the translator.
+fn fnv1a(s: &str) -> u32 {
+    s.bytes().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    })
+}
+
+impl<'c> Translation<'c> {
+    /// Describe `typ` in `out`, by name if it has one and by structure if it doesn't.
+    fn type_signature(&self, typ: CTypeId, out: &mut String) {
+        use CTypeKind::*;
+        match self.ast_context[typ].kind {
+            Pointer(pointee) | Reference(pointee) | BlockPointer(pointee) => {
+                self.type_signature(pointee.ctype, out);
+                out.push('*');
+            }
+            ConstantArray(elem, len) => {
+                self.type_signature(elem, out);
+                let _ = write!(out, "[{}]", len);
+            }
+            IncompleteArray(elem) | VariableArray(elem, _) => {
+                self.type_signature(elem, out);
+                out.push_str("[]");
+            }
+            Vector(elem, len) => {
+                self.type_signature(elem.ctype, out);
+                let _ = write!(out, "<{}>", len);
+            }
+            Complex(elem) => {
+                out.push_str("_Complex ");
+                self.type_signature(elem, out);
+            }
+            Elaborated(ty) | Decayed(ty) | Paren(ty) | TypeOf(ty) => self.type_signature(ty, out),
+            Attributed(ty, _) => self.type_signature(ty.ctype, out),
+            Function(ret, ref params, is_variadic, _, _) => {
+                out.push_str("fn(");
+                for param in params {
+                    self.type_signature(param.ctype, out);
+                    out.push(',');
+                }
+                if is_variadic {
+                    out.push_str("...");
+                }
+                out.push_str(")->");
+                self.type_signature(ret.ctype, out);
+            }
+            Typedef(decl_id) | Struct(decl_id) | Union(decl_id) | Enum(decl_id) => {
+                match self.ast_context[decl_id].kind.get_name() {
+                    Some(name) => out.push_str(name),
+                    None => self.decl_signature(decl_id, out),
+                }
+            }
+            TypeOfExpr(_) => out.push_str("typeof"),
+            BuiltinFn => out.push_str("builtin"),
+            UnhandledSveType => out.push_str("sve"),
+            ref kind => out.push_str(kind.as_str()),
+        }
+    }
+
+    /// Describe the structure of the struct, union or enum `decl_id` in `out`.
+    fn decl_signature(&self, decl_id: CDeclId, out: &mut String) {
+        let (keyword, members) = match self.ast_context[decl_id].kind {
+            CDeclKind::Struct { ref fields, .. } => ("struct", fields.as_deref()),
+            CDeclKind::Union { ref fields, .. } => ("union", fields.as_deref()),
+            CDeclKind::Enum { ref variants, .. } => ("enum", Some(&variants[..])),
+            _ => return,
+        };
+        out.push_str(keyword);
+        out.push('{');
+        for &member_id in members.unwrap_or_default() {
+            match self.ast_context[member_id].kind {
+                CDeclKind::Field {
+                    ref name,
+                    typ,
+                    bitfield_width,
+                    ..
+                } => {
+                    let _ = write!(out, "{}:", name);
+                    self.type_signature(typ.ctype, out);
+                    if let Some(width) = bitfield_width {
+                        let _ = write!(out, ":{}", width);
+                    }
+                }
+                CDeclKind::EnumConstant { ref name, .. } => out.push_str(name),
+                _ => {}
+            }
+            out.push(';');
+        }
+        out.push('}');
+    }
+
+    /// The name of the unnamed struct, union or enum `decl_id`.
+    pub(super) fn unnamed_type_name(&self, decl_id: CDeclId) -> String {
+        let mut signature = self
+            .ast_context
+            .file_id(&self.ast_context[decl_id])
+            .and_then(|file_id| self.ast_context.get_file_path(file_id))
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        signature.push(':');
+        self.decl_signature(decl_id, &mut signature);
+        format!("C2RustUnnamed_{:08x}", fnv1a(&signature))
+    }
+}
@@ -167,7 +167,7 @@ impl<'c> Translation<'c> {
                             is_variadic,
                         )?;
 
-                        let m = if p.qualifiers.is_const {
+                        let m = if self.ast_context.is_const_qualified(p) {
                             Mutability::Immutable
                         } else {
                             Mutability::Mutable
@@ -0,0 +1,98 @@
+//! Translation for WebAssembly targets, enabled by `wasm_target`.
+//!
+//! The C code is parsed for the wasm target instead of the host, so `long`, pointers and `size_t`
+//! are 32 bits wide and struct layouts, `sizeof`s and va_lists are the target's.  Inline assembly
+//! and x86 SIMD intrinsics have no wasm equivalent, so they're rejected with an error naming the
+//! target instead of being translated for an architecture the output will never run on.
+//!
+//! On `wasm32-wasi`, the `libc` crate and the C library (wasi-libc, which Rust's target links in)
+//! are complete, and stdio and the POSIX file functions are implemented on top of WASI.
+//!
+//! On `wasm32-unknown-unknown`, the `libc` crate is empty and there is no C library at all.  The
+//! translated modules import a `libc` module from the crate root instead, which `lib.rs` declares
+//! with the C types, the memory functions `compiler_builtins` provides, and an allocator.  Other C
+//! library functions become imports from the wasm host, so each one declared by an I/O header is
+//! reported: those are usually better served by translating for WASI.
+
+use super::*;
+
+/// A WebAssembly target to translate for instead of the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WasmTarget {
+    /// `wasm32-unknown-unknown`, which has no operating system or C library
+    Unknown,
+    /// `wasm32-wasi`, whose C library is wasi-libc
+    Wasi,
+}
+
+impl WasmTarget {
+    /// The target triple, which clang and rustc both use.
+    pub fn triple(self) -> &'static str {
+        match self {
+            WasmTarget::Unknown => "wasm32-unknown-unknown",
+            WasmTarget::Wasi => "wasm32-wasi",
+        }
+    }
+}
+
+/// Headers of C library functions that need an operating system to do anything.
+const IO_HEADERS: &[&str] = &[
+    "stdio.h", "unistd.h", "fcntl.h", "dirent.h", "stat.h", "socket.h", "mman.h", "time.h",
+];
+
+impl<'c> Translation<'c> {
+    fn wasm_target(&self) -> Option<WasmTarget> {
+        self.tcfg.wasm_target
+    }
+
+    /// Whether the `libc` crate is replaced by the crate's own `libc` module.
+    pub(super) fn uses_wasm_libc(&self) -> bool {
+        self.wasm_target() == Some(WasmTarget::Unknown)
+    }
+
+    /// Import the crate's `libc` module into the main file and every header submodule, so that
+    /// `libc::` paths refer to it instead of the `libc` crate.
+    pub(super) fn import_wasm_libc(&self) {
+        if !self.uses_wasm_libc() {
+            return;
+        }
+        for store in self.items.borrow_mut().values_mut() {
+            store.add_use(vec!["crate".into()], "libc");
+        }
+    }
+
+    /// Fail if the code needs an architecture the wasm target doesn't have, like inline assembly
+    /// or x86 intrinsics.  `what` describes the code.
+    pub(super) fn check_wasm_arch(&self, what: &str) -> TranslationResult<()> {
+        match self.wasm_target() {
+            Some(target) => Err(format_err!(
+                "{} can't be translated for {}, which has no equivalent",
+                what,
+                target.triple()
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Report a C library function declared by an I/O header that `wasm32-unknown-unknown` has
+    /// no implementation of, so the translated code imports it from the wasm host.
+    pub(super) fn check_wasm_import(&self, decl_id: CDeclId, name: &str) {
+        if !self.uses_wasm_libc() {
+            return;
+        }
+        let header = self
+            .ast_context
+            .file_id(&self.ast_context[decl_id])
+            .and_then(|file_id| self.ast_context.get_file_path(file_id))
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str());
+        if let Some(header) = header.filter(|header| IO_HEADERS.contains(header)) {
+            warn!(
+                "`{}` from {} has no implementation on wasm32-unknown-unknown and will be \
+                 imported from the host; translate for wasm32-wasi to use WASI instead",
+                name, header
+            );
+        }
+    }
+}
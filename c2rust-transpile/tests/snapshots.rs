@@ -0,0 +1,25 @@
+//! Runs the transpiler's snapshot tests.  Pass `--bless` (or set `C2RUST_BLESS`) to update the
+//! snapshots, and any other argument to only check the fixtures whose names contain it:
+//!
+//! ```sh
+//! cargo test -p c2rust-transpile --features snapshot-tests --test snapshots -- --bless
+//! ```
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use c2rust_transpile::snapshots::{run_snapshots, SnapshotOptions};
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let opts = SnapshotOptions {
+        fixtures_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots"),
+        work_dir: Path::new(env!("CARGO_TARGET_TMPDIR")).join("snapshots"),
+        bless: args.iter().any(|arg| arg == "--bless") || env::var_os("C2RUST_BLESS").is_some(),
+        filter: args.iter().find(|arg| !arg.starts_with('-')).cloned(),
+    };
+    if run_snapshots(&opts) > 0 {
+        process::exit(1);
+    }
+}
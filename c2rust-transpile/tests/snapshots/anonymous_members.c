@@ -0,0 +1,47 @@
+// flags: --flatten-anonymous-members
+// CHECK: pub c2rust_unnamed: shape_anon,
+// CHECK: pub meta: shape_meta,
+// CHECK: pub unsafe fn w(&self) -> &libc::c_int {
+// CHECK: &self.c2rust_unnamed.c2rust_unnamed.w
+// CHECK: pub unsafe fn w_mut(&mut self) -> &mut libc::c_int {
+// CHECK: pub unsafe fn r(&self) -> &libc::c_int {
+// CHECK: pub fn x(&self) -> &libc::c_short {
+// CHECK: impl ::core::ops::Deref for point {
+// CHECK: type Target = point_anon;
+
+#include <stdio.h>
+
+// Anonymous struct and union members, whose types are named after the enclosing records and
+// whose fields the records get accessors for.
+struct shape {
+    int kind;
+    union {
+        struct {
+            int w, h;
+        };
+        int r;
+    };
+    struct {
+        char label[8];
+    } meta;
+};
+
+typedef struct {
+    struct {
+        short x, y;
+    };
+} point;
+
+static int area(const struct shape *s) {
+    return s->kind == 0 ? s->w * s->h : 3 * s->r * s->r;
+}
+
+int main(void) {
+    struct shape rect = {.kind = 0, .w = 3, .h = 4, .meta = {"rect"}};
+    struct shape circle = {.kind = 1, .r = 2};
+    point p = {{5, 7}};
+    p.x += 1;
+    printf("%d %d %s\n", area(&rect), area(&circle), rect.meta.label);
+    printf("%d %d\n", p.x, p.y);
+    return p.x + p.y;
+}
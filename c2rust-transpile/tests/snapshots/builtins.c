@@ -0,0 +1,30 @@
+#include <stdio.h>
+
+// GCC builtins are translated into the equivalent Rust methods and intrinsics.
+static int classify(int x) {
+    if (__builtin_expect(x < 0, 0))
+        return -1;
+    switch (x & 1) {
+    case 0:
+        return 0;
+    case 1:
+        return 1;
+    }
+    __builtin_unreachable();
+}
+
+int main(void) {
+    unsigned x = 0x00f0u;
+    printf("%d %d %d %d\n", __builtin_popcount(x), __builtin_clz(x), __builtin_ctz(x),
+           __builtin_parity(x));
+    printf("%x %d %d\n", __builtin_bswap32(0x11223344u), __builtin_clrsb(-1),
+           __builtin_clrsb(1));
+    // The argument of `clrsb` is evaluated once.
+    int xs[] = {-2, 5}, *p = xs;
+    int c = __builtin_clrsb(*p++);
+    int d = __builtin_clrsb(*p++);
+    printf("%d %d %d\n", c, d, (int)(p - xs));
+    printf("%d %d %d\n", classify(-5), classify(4), classify(7));
+    printf("%.1f %d\n", __builtin_copysign(2.0, -1.0), __builtin_isinf(1.0 / 0.0));
+    return __builtin_expect(classify(3), 1);
+}
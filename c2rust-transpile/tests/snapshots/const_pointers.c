@@ -0,0 +1,41 @@
+// CHECK: pub name: *const cchar,
+// CHECK: pub value: *const cint,
+// CHECK: mut xs: *const cint
+// CHECK: n: cint
+// CHECK: let mut greeting: *const cchar
+// CHECK: let count: cint
+// CHECK-NOT: *mut cchar
+// CHECK-NOT: *mut cint
+// CHECK-NOT: mut n: cint
+// CHECK-NOT: mut count: cint
+
+#include <stdio.h>
+
+// `const` that only appears through a typedef still makes pointers `*const`, and variables
+// immutable.
+typedef const char cchar;
+typedef const int cint;
+
+struct entry {
+    cchar *name;
+    cint *value;
+};
+
+static cint values[] = {1, 2, 3};
+
+static int sum(cint *xs, cint n) {
+    int s = 0;
+    for (int i = 0; i < n; i++) {
+        s += xs[i];
+    }
+    return s;
+}
+
+int main(void) {
+    cchar *greeting = "hello";
+    cint local[3] = {4, 5, 6};
+    cint count = 3;
+    struct entry e = {greeting, &values[1]};
+    printf("%s %d %d %d\n", e.name, *e.value, sum(values, count), sum(local, count));
+    return 0;
+}
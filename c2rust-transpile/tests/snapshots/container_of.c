@@ -0,0 +1,41 @@
+#include <stddef.h>
+#include <stdio.h>
+
+// `offsetof` of named fields becomes `offset_of!`, and `container_of` steps back from a field to
+// its struct without losing the pointer's provenance.
+#define container_of(ptr, type, member) ((type *)((char *)(ptr) - offsetof(type, member)))
+
+struct list {
+    struct list *next;
+};
+
+struct point {
+    int x;
+    struct {
+        short tag;
+        double weight;
+    } meta;
+};
+
+struct item {
+    int value;
+    struct list link;
+};
+
+static const size_t link_offset = offsetof(struct item, link);
+
+int main(void) {
+    struct item a = {1, {NULL}};
+    struct item b = {2, {&a.link}};
+
+    int sum = 0;
+    for (struct list *l = &b.link; l; l = l->next) {
+        struct item *it = container_of(l, struct item, link);
+        sum = sum * 10 + it->value;
+    }
+    printf("%d\n", sum);
+
+    printf("%d %d\n", offsetof(struct item, link) == link_offset,
+           (int)offsetof(struct point, meta.weight) == (int)sizeof(double) * 2);
+    return sum == 21 ? 3 : 0;
+}
@@ -0,0 +1,77 @@
+// flags: --translate-for-loops
+// CHECK: for i in 0 as libc::c_int..n {
+// CHECK: in 0 as libc::c_int..10 as libc::c_int {
+// CHECK: in (1 as libc::c_int..=9 as libc::c_int).step_by(2) {
+// CHECK: for u in
+// CHECK: while
+
+#include <stdio.h>
+
+static int sum_to(int n) {
+    int sum = 0;
+    for (int i = 0; i < n; i++) {
+        sum += i;
+    }
+    return sum;
+}
+
+int main(void) {
+    int squares[10];
+    for (int i = 0; i < 10; i++) {
+        squares[i] = i * i;
+    }
+
+    // Stepped and inclusive ranges.
+    for (int i = 1; i <= 9; i += 2) {
+        printf("%d ", squares[i]);
+    }
+    printf("\n");
+
+    // Unsigned counters.
+    for (unsigned u = 3; u < 6; u++) {
+        printf("%u ", u);
+    }
+    printf("\n");
+
+    // The counter is written in the body, so this stays a while loop.
+    for (int i = 0; i < 10; i++) {
+        if (i % 3 == 0) {
+            i++;
+        }
+        printf("%d ", i);
+    }
+    printf("\n");
+
+    // The bound changes in the body.
+    int n = 5;
+    for (int i = 0; i < n; i++) {
+        if (i == 2) {
+            n = 3;
+        }
+        printf("%d ", i);
+    }
+    printf("\n");
+
+    // `continue` skips to the increment.
+    for (int i = 0; i < 6; i++) {
+        if (i % 2) {
+            continue;
+        }
+        printf("%d ", i);
+    }
+    printf("\n");
+
+    // Nested loops, with a `break` out of the inner one.
+    for (int i = 0; i < 3; i++) {
+        for (int j = 0; j < 3; j++) {
+            if (j > i) {
+                break;
+            }
+            printf("(%d,%d) ", i, j);
+        }
+    }
+    printf("\n");
+
+    printf("%d\n", sum_to(100));
+    return 0;
+}
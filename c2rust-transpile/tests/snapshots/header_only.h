@@ -0,0 +1,25 @@
+// flags: --header-only
+// A header translated on its own keeps all of its declarations, even its unused macros and
+// `static inline` functions, and makes them public, while the unused declarations of the headers
+// it includes are still left out.
+// CHECK: pub const SHAPE_MAX_SIDES
+// CHECK: pub struct shape {
+// CHECK: pub sides: base_len,
+// CHECK: pub fn shape_area(
+// CHECK: pub unsafe extern "C" fn shape_perimeter(
+// CHECK-NOT: unused_struct
+// CHECK-NOT: unused_helper
+#include "header_only_base.h"
+
+#define SHAPE_MAX_SIDES 8
+
+struct shape {
+    base_len sides;
+    base_len side_len;
+};
+
+double shape_area(const struct shape *s);
+
+static inline base_len shape_perimeter(const struct shape *s) {
+    return s->sides * s->side_len;
+}
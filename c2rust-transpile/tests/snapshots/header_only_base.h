@@ -0,0 +1,8 @@
+// Declarations of a header that `header_only.h` includes.  Only the ones it uses are translated.
+typedef unsigned int base_len;
+
+struct unused_struct {
+    int x;
+};
+
+static inline int unused_helper(int x) { return x + 1; }
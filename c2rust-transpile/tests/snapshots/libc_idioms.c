@@ -0,0 +1,26 @@
+// flags: --translate-libc-idioms
+// CHECK: libc::c_int::wrapping_abs(
+// CHECK: libc::c_long::wrapping_abs(
+// CHECK: f64::sqrt(
+// CHECK: f64::powf(
+// CHECK: f32::max(
+// CHECK: f32::round(
+// CHECK: f64::min(
+// CHECK: std::process::exit(
+// CHECK-NOT: = abs(
+// CHECK-NOT: = labs(
+// CHECK-NOT: = sqrt(
+
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+// Calls to C library functions with exact `std` equivalents are translated into those.
+int main(void) {
+    int a = abs(-7);
+    long b = labs(-8L);
+    double r = sqrt(16.0) + fabs(-0.5) + floor(2.7) + ceil(2.2) + pow(2.0, 3.0);
+    float f = fmaxf(1.5f, roundf(2.5f));
+    printf("%d %ld %.1f %.1f %.1f\n", a, b, r, f, fmin(-1.0, trunc(-1.9)));
+    exit(a - 4);
+}
@@ -0,0 +1,27 @@
+// flags: --emit-main-run-fn
+// CHECK: fn main_with_args(mut args: Vec<Vec<u8>>) -> i32 {
+// CHECK: for (var_name, var_value) in ::std::env::vars_os() {
+// CHECK: pub fn main() {
+// CHECK: for arg in ::std::env::args_os() {
+// CHECK: ::std::process::exit(main_with_args(args));
+// CHECK: pub fn run(args: &[&str]) -> i32 {
+// CHECK-NOT: ::std::env::args()
+
+#include <stdio.h>
+#include <string.h>
+
+int main(int argc, char **argv, char **envp) {
+    int vars = 0;
+    int well_formed = 1;
+    for (char **var = envp; *var; var++) {
+        vars++;
+        if (!strchr(*var, '=')) {
+            well_formed = 0;
+        }
+    }
+    // The strings are writable, as in C.
+    argv[0][0] = argv[0][0];
+    printf("argc=%d argv[argc]=%s env=%s\n", argc, argv[argc] ? "set" : "null",
+           vars > 0 && well_formed ? "ok" : "bad");
+    return argc + 2;
+}
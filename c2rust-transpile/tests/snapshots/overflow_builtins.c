@@ -0,0 +1,24 @@
+#include <limits.h>
+#include <stdio.h>
+
+// The checked arithmetic builtins return whether the result overflowed and store it truncated,
+// even when the operands and the result have different types.
+int main(void) {
+    int i;
+    unsigned u;
+    long l;
+    unsigned char c;
+    int o1 = __builtin_sadd_overflow(INT_MAX, 1, &i);
+    printf("%d %d\n", o1, i);
+    int o2 = __builtin_add_overflow(INT_MAX, 1, &l);
+    printf("%d %ld\n", o2, l);
+    int o3 = __builtin_sub_overflow(1, 2, &u);
+    printf("%d %u\n", o3, u);
+    int o4 = __builtin_mul_overflow(16, 16, &c);
+    printf("%d %d\n", o4, c);
+    int o5 = __builtin_mul_overflow(-3, 5u, &i);
+    printf("%d %d\n", o5, i);
+    if (__builtin_umul_overflow(UINT_MAX, 2u, &u))
+        return 1;
+    return 0;
+}
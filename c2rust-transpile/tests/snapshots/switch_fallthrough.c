@@ -0,0 +1,27 @@
+#include <stdio.h>
+
+static int classify(int x) {
+    int score = 0;
+    switch (x % 4) {
+    case 0:
+        score += 1;
+    case 1:
+        score += 10;
+        break;
+    case 2:
+        score += 100;
+    default:
+        score += 1000;
+    }
+    return score;
+}
+
+int main(void) {
+    int total = 0;
+    for (int i = 0; i < 8; i++) {
+        int s = classify(i);
+        printf("%d: %d\n", i, s);
+        total += s;
+    }
+    return total % 256;
+}
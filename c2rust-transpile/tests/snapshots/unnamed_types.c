@@ -0,0 +1,13 @@
+// CHECK: static mut pair: C2RustUnnamed_f6d190af =
+// CHECK: static mut first: C2RustUnnamed_1515eeae =
+// CHECK: static mut second: C2RustUnnamed_1515eeae_0 =
+
+#include <stdio.h>
+
+// Unnamed types are named after a hash of their structure and of the file declaring them.
+#include "unnamed_types.h"
+
+int main(void) {
+    printf("%d %d %d %d\n", pair.a, pair.b, first.x, second.x);
+    return 0;
+}
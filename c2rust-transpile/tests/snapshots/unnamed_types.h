@@ -0,0 +1,20 @@
+// Unnamed types declared by a header, which both `unnamed_types` fixtures include so that the
+// types are declared by the same file.
+#ifdef EXTRA_UNNAMED_TYPE
+static struct {
+    double scale;
+} extra = {2.0};
+#endif
+
+static struct {
+    int a;
+    int b;
+} pair = {1, 2};
+
+// Two unnamed types with the same structure, told apart by a numeric suffix.
+static struct {
+    int x;
+} first = {3};
+static struct {
+    int x;
+} second = {4};
@@ -0,0 +1,15 @@
+// CHECK: static mut extra: C2RustUnnamed_211bde32 =
+// CHECK: static mut pair: C2RustUnnamed_f6d190af =
+// CHECK: static mut first: C2RustUnnamed_1515eeae =
+// CHECK: static mut second: C2RustUnnamed_1515eeae_0 =
+
+#include <stdio.h>
+
+// With another unnamed type declared before them, the types of `unnamed_types` keep their names.
+#define EXTRA_UNNAMED_TYPE
+#include "unnamed_types.h"
+
+int main(void) {
+    printf("%.1f %d %d %d %d\n", extra.scale, pair.a, pair.b, first.x, second.x);
+    return 0;
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Number of lines shown before the first differing line of a divergence report.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Parser)]
+#[clap(
+name = "check-divergence",
+author = "- The C2Rust Project Developers <c2rust@immunant.com>",
+version,
+about = "Build a C program and its Rust translation, run the same test against both, and report where they diverge",
+long_about = None)]
+struct Args {
+    /// Directory of the original C project
+    #[clap(parse(from_os_str))]
+    c_dir: PathBuf,
+
+    /// Directory of the transpiled crate
+    #[clap(parse(from_os_str))]
+    crate_dir: PathBuf,
+
+    /// Test command, run with `sh -c` once per program; `{}` is replaced with the program's path
+    #[clap(short = 't', long = "test", value_name = "CMD")]
+    test: String,
+
+    /// The C program built by the C build command, relative to C_DIR
+    #[clap(long, value_name = "PATH")]
+    c_bin: PathBuf,
+
+    /// The Rust program, relative to CRATE_DIR (default: target/debug/PACKAGE_NAME)
+    #[clap(long, value_name = "PATH")]
+    rust_bin: Option<PathBuf>,
+
+    /// Command that builds the C program, run in C_DIR
+    #[clap(long, value_name = "CMD", default_value = "make")]
+    c_build: String,
+
+    /// Command that builds the Rust program, run in CRATE_DIR
+    #[clap(long, value_name = "CMD", default_value = "cargo build")]
+    rust_build: String,
+
+    /// Don't build either program, only run the test
+    #[clap(long)]
+    no_build: bool,
+
+    /// Directory to write the output of both runs to
+    #[clap(
+        short = 'o',
+        long,
+        value_name = "DIR",
+        default_value = "divergence-logs"
+    )]
+    log_dir: PathBuf,
+}
+
+/// One of the two programs being compared.
+struct Variant {
+    /// `c` or `rust`, as used in log file names and `C2RUST_CHECK_VARIANT`.
+    name: &'static str,
+    dir: PathBuf,
+    build: String,
+    bin: PathBuf,
+}
+
+fn run_shell(cmd: &str, dir: &Path, envs: &[(&str, &Path)]) -> anyhow::Result<Output> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd).current_dir(dir);
+    for &(key, value) in envs {
+        command.env(key, value);
+    }
+    command
+        .output()
+        .with_context(|| format!("failed to run `{}` in {}", cmd, dir.display()))
+}
+
+fn build(variant: &Variant) -> anyhow::Result<()> {
+    eprintln!(
+        "Building the {} program with `{}`",
+        variant.name, variant.build
+    );
+    let output = run_shell(&variant.build, &variant.dir, &[])?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!("`{}` failed ({})", variant.build, output.status);
+    }
+    if !variant.bin.exists() {
+        bail!(
+            "the {} build didn't produce {}",
+            variant.name,
+            variant.bin.display()
+        );
+    }
+    Ok(())
+}
+
+/// Get the package name from the crate's `Cargo.toml`, which is also the name of its binary.
+fn package_name(crate_dir: &Path) -> anyhow::Result<String> {
+    let manifest_path = crate_dir.join("Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+    let name_re = Regex::new(r#"(?m)^\s*name\s*=\s*"([^"]+)""#).unwrap();
+    name_re
+        .captures(&manifest)
+        .map(|c| c[1].to_owned())
+        .ok_or_else(|| anyhow!("no package name in {}", manifest_path.display()))
+}
+
+/// Run the test against `variant` and save its output in `log_dir`.
+fn run_test(test: &str, variant: &Variant, log_dir: &Path) -> anyhow::Result<Output> {
+    let cmd = test.replace("{}", &variant.bin.display().to_string());
+    let envs = [
+        ("C2RUST_CHECK_BIN", variant.bin.as_path()),
+        ("C2RUST_CHECK_VARIANT", Path::new(variant.name)),
+    ];
+    let cwd = std::env::current_dir()?;
+    let output = run_shell(&cmd, &cwd, &envs)?;
+    for (stream, contents) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        let path = log_dir.join(format!("{}.{}", variant.name, stream));
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(output)
+}
+
+/// Compare the `stream` output of the two runs, and print the first place where they differ.
+/// Returns `true` if they differ.
+fn report_stream(stream: &str, c: &[u8], rust: &[u8]) -> bool {
+    if c == rust {
+        return false;
+    }
+    let c = String::from_utf8_lossy(c);
+    let rust = String::from_utf8_lossy(rust);
+    let c_lines = c.lines().collect::<Vec<_>>();
+    let rust_lines = rust.lines().collect::<Vec<_>>();
+    let first = c_lines
+        .iter()
+        .zip(&rust_lines)
+        .take_while(|(c, rust)| c == rust)
+        .count();
+
+    println!("First divergence on {}, at line {}:", stream, first + 1);
+    for line in &c_lines[first.saturating_sub(CONTEXT_LINES)..first] {
+        println!("   {}", line);
+    }
+    match c_lines.get(first) {
+        Some(line) => println!("C  {}", line),
+        None => println!("C  <end of output>"),
+    }
+    match rust_lines.get(first) {
+        Some(line) => println!("RS {}", line),
+        None => println!("RS <end of output>"),
+    }
+    if c_lines.len() == rust_lines.len() && first == c_lines.len() {
+        // The lines are all the same, so the difference is in line endings.
+        println!("(the outputs differ only in line endings or a trailing newline)");
+    }
+    true
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let c_dir = fs::canonicalize(&args.c_dir)
+        .with_context(|| format!("failed to find {}", args.c_dir.display()))?;
+    let crate_dir = fs::canonicalize(&args.crate_dir)
+        .with_context(|| format!("failed to find {}", args.crate_dir.display()))?;
+    let rust_bin = match args.rust_bin {
+        Some(ref bin) => bin.clone(),
+        None => Path::new("target/debug").join(package_name(&crate_dir)?),
+    };
+
+    let variants = [
+        Variant {
+            name: "c",
+            bin: c_dir.join(&args.c_bin),
+            dir: c_dir,
+            build: args.c_build.clone(),
+        },
+        Variant {
+            name: "rust",
+            bin: crate_dir.join(rust_bin),
+            dir: crate_dir,
+            build: args.rust_build.clone(),
+        },
+    ];
+
+    if !args.no_build {
+        for variant in &variants {
+            build(variant)?;
+        }
+    }
+
+    fs::create_dir_all(&args.log_dir)
+        .with_context(|| format!("failed to create {}", args.log_dir.display()))?;
+    let c = run_test(&args.test, &variants[0], &args.log_dir)?;
+    let rust = run_test(&args.test, &variants[1], &args.log_dir)?;
+
+    let mut diverged = report_stream("stdout", &c.stdout, &rust.stdout);
+    diverged |= report_stream("stderr", &c.stderr, &rust.stderr);
+    if c.status != rust.status {
+        println!("Exit status differs: C {}, Rust {}", c.status, rust.status);
+        diverged = true;
+    }
+
+    if diverged {
+        println!("Output of both runs saved in {}", args.log_dir.display());
+        std::process::exit(1);
+    }
+    println!("No divergence: both programs produced the same output and exit status");
+    Ok(())
+}
@@ -0,0 +1,331 @@
+use anyhow::{bail, Context};
+use clap::Parser;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+
+#[derive(Debug, Parser)]
+#[clap(
+name = "coverage-report",
+author = "- The C2Rust Project Developers <c2rust@immunant.com>",
+version,
+about = "Report the translated functions that a test suite never executed, ranked by unsafe-operation density",
+long_about = None)]
+struct Args {
+    /// Coverage of the test suite, as an lcov tracefile (from grcov or `llvm-cov export --format=lcov`)
+    #[clap(parse(from_os_str))]
+    lcov: PathBuf,
+
+    /// Path to the transpiled crate
+    #[clap(parse(from_os_str))]
+    crate_dir: PathBuf,
+
+    /// Only list this many functions
+    #[clap(short = 'n', long, value_name = "N")]
+    top: Option<usize>,
+}
+
+/// Methods on raw pointers whose calls count as unsafe operations.
+const UNSAFE_METHODS: &[&str] = &[
+    "offset",
+    "offset_from",
+    "read",
+    "read_unaligned",
+    "read_volatile",
+    "write",
+    "write_unaligned",
+    "write_volatile",
+    "copy_from",
+    "copy_from_nonoverlapping",
+    "copy_to",
+    "copy_to_nonoverlapping",
+];
+
+/// Functions whose calls count as unsafe operations.
+const UNSAFE_CALLS: &[&str] = &[
+    "transmute",
+    "memcpy",
+    "memmove",
+    "memset",
+    "malloc",
+    "calloc",
+    "realloc",
+    "free",
+];
+
+/// A function defined in the transpiled crate.
+struct RustFn {
+    name: String,
+    file: PathBuf,
+    /// The first and last lines of the function, including its attributes.
+    lines: (usize, usize),
+    /// The first and last lines of the items nested in the function, which aren't part of it.
+    nested: Vec<(usize, usize)>,
+    unsafe_ops: usize,
+}
+
+impl RustFn {
+    /// Check whether `line` is in the function rather than in an item nested in it.
+    fn contains_line(&self, line: usize) -> bool {
+        let within = |lines: (usize, usize)| lines.0 <= line && line <= lines.1;
+        within(self.lines) && !self.nested.iter().any(|&lines| within(lines))
+    }
+
+    /// The number of lines of the function, without its nested items.
+    fn len(&self) -> usize {
+        let span_len = |lines: (usize, usize)| lines.1 - lines.0 + 1;
+        let nested: usize = self.nested.iter().map(|&lines| span_len(lines)).sum();
+        span_len(self.lines) - nested
+    }
+
+    fn density(&self) -> f64 {
+        self.unsafe_ops as f64 / self.len() as f64
+    }
+}
+
+/// Counts the unsafe operations in a function body: dereferences, pointer arithmetic and
+/// accesses, casts to raw pointers, calls to `transmute` and the C memory functions, and inline
+/// assembly.  Dereferences of references are counted too, since telling them apart needs type
+/// information, but translated code rarely has any.  Items nested in the body are skipped, since
+/// their functions are counted on their own, and their lines are recorded.
+#[derive(Default)]
+struct UnsafeOps {
+    count: usize,
+    nested: Vec<(usize, usize)>,
+}
+
+impl<'ast> Visit<'ast> for UnsafeOps {
+    fn visit_item(&mut self, item: &'ast syn::Item) {
+        let span = item.span();
+        self.nested.push((span.start().line, span.end().line));
+    }
+
+    fn visit_expr(&mut self, e: &'ast syn::Expr) {
+        let is_unsafe = match e {
+            syn::Expr::Unary(u) => matches!(u.op, syn::UnOp::Deref(_)),
+            syn::Expr::MethodCall(mc) => UNSAFE_METHODS.contains(&&*mc.method.to_string()),
+            syn::Expr::Cast(c) => matches!(*c.ty, syn::Type::Ptr(_)),
+            syn::Expr::Call(call) => match &*call.func {
+                syn::Expr::Path(p) => p
+                    .path
+                    .segments
+                    .last()
+                    .map_or(false, |seg| UNSAFE_CALLS.contains(&&*seg.ident.to_string())),
+                _ => false,
+            },
+            syn::Expr::Macro(m) => m.mac.path.is_ident("asm") || m.mac.path.is_ident("llvm_asm"),
+            _ => false,
+        };
+        if is_unsafe {
+            self.count += 1;
+        }
+        visit::visit_expr(self, e);
+    }
+}
+
+/// Collects the functions of one source file.
+struct FnCollector<'a> {
+    file: &'a Path,
+    fns: Vec<RustFn>,
+}
+
+impl FnCollector<'_> {
+    fn add(&mut self, ident: &syn::Ident, span: proc_macro2::Span, block: &syn::Block) {
+        let mut ops = UnsafeOps::default();
+        ops.visit_block(block);
+        self.fns.push(RustFn {
+            name: ident.to_string(),
+            file: self.file.to_owned(),
+            lines: (span.start().line, span.end().line),
+            nested: ops.nested,
+            unsafe_ops: ops.count,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for FnCollector<'_> {
+    fn visit_item_fn(&mut self, f: &'ast syn::ItemFn) {
+        self.add(&f.sig.ident, f.span(), &f.block);
+        visit::visit_item_fn(self, f);
+    }
+
+    fn visit_impl_item_method(&mut self, m: &'ast syn::ImplItemMethod) {
+        self.add(&m.sig.ident, m.span(), &m.block);
+        visit::visit_impl_item_method(self, m);
+    }
+}
+
+fn rust_files(dir: &Path, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().map_or(false, |name| name != "target") {
+                rust_files(&path, out)?;
+            }
+        } else if path.extension().map_or(false, |ext| ext == "rs") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn read_crate(crate_dir: &Path) -> anyhow::Result<Vec<RustFn>> {
+    let mut files = Vec::new();
+    rust_files(&crate_dir.join("src"), &mut files)?;
+    files.sort();
+
+    let mut fns = Vec::new();
+    for path in &files {
+        let src = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let ast =
+            syn::parse_file(&src).with_context(|| format!("failed to parse {}", path.display()))?;
+        let mut collector = FnCollector {
+            file: path,
+            fns: Vec::new(),
+        };
+        collector.visit_file(&ast);
+        fns.extend(collector.fns);
+    }
+    Ok(fns)
+}
+
+/// The functions recorded for one source file in an lcov tracefile.
+#[derive(Default)]
+struct FileCoverage {
+    /// The line each function starts on.
+    lines: HashMap<String, usize>,
+    /// How many times each function was called.
+    counts: HashMap<String, u64>,
+}
+
+impl FileCoverage {
+    /// Check whether any function recorded as starting within `f` was called.  Functions are
+    /// recorded under their mangled names, so they're matched by line instead of by name.
+    fn executed(&self, f: &RustFn) -> bool {
+        self.lines
+            .iter()
+            .filter(|&(_, &line)| f.contains_line(line))
+            .any(|(name, _)| self.counts.get(name).map_or(false, |&n| n > 0))
+    }
+}
+
+fn read_lcov(path: &Path) -> anyhow::Result<HashMap<PathBuf, FileCoverage>> {
+    let lcov =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut files = HashMap::new();
+    let mut cur: Option<(PathBuf, FileCoverage)> = None;
+    for line in lcov.lines() {
+        let (key, value) = line.split_once(':').unwrap_or((line, ""));
+        if key == "SF" {
+            // Paths that no longer exist can't be in the crate, so they're skipped.
+            cur = fs::canonicalize(value)
+                .ok()
+                .map(|file| (file, FileCoverage::default()));
+            continue;
+        }
+        if key == "end_of_record" {
+            if let Some((file, cov)) = cur.take() {
+                files.insert(file, cov);
+            }
+            continue;
+        }
+        let cov = match cur {
+            Some((_, ref mut cov)) => cov,
+            None => continue,
+        };
+        let (num, name) = match value.split_once(',') {
+            Some(x) => x,
+            None => continue,
+        };
+        match key {
+            "FN" => {
+                if let Ok(line) = num.parse() {
+                    cov.lines.insert(name.to_owned(), line);
+                }
+            }
+            "FNDA" => {
+                if let Ok(count) = num.parse::<u64>() {
+                    *cov.counts.entry(name.to_owned()).or_insert(0) += count;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(files)
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let crate_dir = fs::canonicalize(&args.crate_dir)
+        .with_context(|| format!("failed to find {}", args.crate_dir.display()))?;
+
+    let fns = read_crate(&crate_dir)?;
+    let coverage = read_lcov(&args.lcov)?;
+
+    let mut unexecuted = Vec::new();
+    let mut uncovered_files = Vec::new();
+    for f in &fns {
+        match coverage.get(&f.file) {
+            Some(cov) => {
+                if !cov.executed(f) {
+                    unexecuted.push(f);
+                }
+            }
+            None => {
+                if !uncovered_files.contains(&&f.file) {
+                    uncovered_files.push(&f.file);
+                }
+            }
+        }
+    }
+    // A crate without functions gets an empty report rather than an error.
+    if !fns.is_empty() && fns.iter().all(|f| !coverage.contains_key(&f.file)) {
+        bail!(
+            "{} has no coverage data for the files of {}",
+            args.lcov.display(),
+            crate_dir.display()
+        );
+    }
+
+    unexecuted.sort_by(|a, b| {
+        b.density()
+            .partial_cmp(&a.density())
+            .unwrap()
+            .then(b.unsafe_ops.cmp(&a.unsafe_ops))
+    });
+
+    println!(
+        "{} of {} translated functions were never executed:",
+        unexecuted.len(),
+        fns.len()
+    );
+    println!();
+    println!("{:>8} {:>7} {:>6}  function", "density", "unsafe", "lines");
+    for f in unexecuted.iter().take(args.top.unwrap_or(usize::MAX)) {
+        let file = f.file.strip_prefix(&crate_dir).unwrap_or(&f.file);
+        println!(
+            "{:>8.2} {:>7} {:>6}  {} ({}:{})",
+            f.density(),
+            f.unsafe_ops,
+            f.len(),
+            f.name,
+            file.display(),
+            f.lines.0
+        );
+    }
+
+    if !uncovered_files.is_empty() {
+        println!();
+        println!("No coverage data for these files, so their functions aren't listed:");
+        for file in uncovered_files {
+            println!(
+                "    {}",
+                file.strip_prefix(&crate_dir).unwrap_or(file).display()
+            );
+        }
+    }
+    Ok(())
+}
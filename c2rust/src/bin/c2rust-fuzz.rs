@@ -0,0 +1,730 @@
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The prefix given to the C definitions of exported symbols, so that they don't clash with the
+/// Rust definitions in the same binary.
+const C_PREFIX: &str = "c2rust_fuzz_c_";
+
+#[derive(Debug, Parser)]
+#[clap(
+name = "fuzz",
+author = "- The C2Rust Project Developers <c2rust@immunant.com>",
+version,
+about = "Generate fuzzing harnesses that compare C functions with their Rust translations",
+long_about = None)]
+struct Args {
+    /// Path to the compile_commands.json that the crate was transpiled from
+    #[clap(parse(from_os_str))]
+    compile_commands: PathBuf,
+
+    /// Path to the transpiled crate, which must be built as a library
+    #[clap(parse(from_os_str))]
+    crate_dir: PathBuf,
+
+    /// Generate a fuzz target for this exported function (default: every function with supported argument types)
+    #[clap(short = 'f', long = "function", multiple = true, number_of_values = 1)]
+    functions: Vec<String>,
+
+    /// Path to output directory (default: CRATE_DIR/fuzz)
+    #[clap(short = 'o', long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Maximum number of elements in the buffer passed for each pointer argument
+    #[clap(long, default_value_t = 256)]
+    max_buffer_len: usize,
+
+    /// Link the C code against this library (e.g. `m` for libm)
+    #[clap(short = 'l', long = "link-lib", multiple = true, number_of_values = 1)]
+    link_libs: Vec<String>,
+
+    /// Emit files even if it causes existing files to be overwritten
+    #[clap(long)]
+    overwrite_existing: bool,
+}
+
+/// How a value of some argument or return type is produced and compared.
+#[derive(Debug, Clone)]
+enum ArgKind {
+    /// A number or `bool`, with the type to declare it as.
+    Scalar { ty: String, is_int: bool },
+    /// A pointer to a buffer of `elem`s, with the type to declare the pointer as.
+    Buffer { ty: String, elem: String },
+}
+
+/// An exported function of the transpiled crate, as written in the Rust source.
+#[derive(Debug)]
+struct ExportedFn {
+    name: String,
+    params: Vec<(String, String)>,
+    ret: Option<String>,
+}
+
+/// What the harness generator needs to know about the transpiled crate.
+struct CrateInfo {
+    package_name: String,
+    lib_name: String,
+    /// Every `#[no_mangle]` function and static.
+    exports: Vec<String>,
+    functions: Vec<ExportedFn>,
+    /// The type aliases (`pub type size_t = libc::c_ulong;`) declared anywhere in the crate.
+    aliases: HashMap<String, String>,
+}
+
+/// A C source file and the flags needed to compile it.
+struct CSource {
+    file: PathBuf,
+    flags: Vec<String>,
+}
+
+fn rust_files(dir: &Path, out: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if path.file_name().map_or(false, |name| name != "target") {
+                rust_files(&path, out)?;
+            }
+        } else if path.extension().map_or(false, |ext| ext == "rs") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Split `s` at the commas that aren't nested inside brackets or parentheses.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' if !s[..i].ends_with('-') => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+fn read_crate(crate_dir: &Path) -> anyhow::Result<CrateInfo> {
+    let manifest_path = crate_dir.join("Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("failed to read {}", manifest_path.display()))?;
+    let name_re = Regex::new(r#"(?m)^\s*name\s*=\s*"([^"]+)""#).unwrap();
+    let mut names = name_re.captures_iter(&manifest).map(|c| c[1].to_owned());
+    let package_name = names
+        .next()
+        .ok_or_else(|| anyhow!("no package name in {}", manifest_path.display()))?;
+    // The transpiler emits the `[lib]` section, with the crate's Rust name, right after
+    // `[package]`.
+    let lib_name = match manifest.find("[lib]") {
+        Some(_) => names.next().unwrap_or_else(|| package_name.clone()),
+        None => package_name.replace('-', "_"),
+    };
+
+    let mut files = Vec::new();
+    rust_files(&crate_dir.join("src"), &mut files)?;
+    files.sort();
+
+    let export_re = Regex::new(
+        r#"#\[no_mangle\]\s*(?:#\[[^\]]*\]\s*)*pub\s+(?:static\s+mut\s+|static\s+|(?:unsafe\s+)?extern\s+"C"\s+fn\s+)(\w+)"#,
+    )
+    .unwrap();
+    let fn_re = Regex::new(
+        r#"#\[no_mangle\]\s*(?:#\[[^\]]*\]\s*)*pub\s+(?:unsafe\s+)?extern\s+"C"\s+fn\s+(\w+)\s*\(([^)]*)\)\s*(?:->\s*([^{]+?))?\s*\{"#,
+    )
+    .unwrap();
+    let alias_re = Regex::new(r"pub\s+type\s+(\w+)\s*=\s*([^;]+);").unwrap();
+
+    let mut exports = Vec::new();
+    let mut functions = Vec::new();
+    let mut aliases = HashMap::new();
+    for path in &files {
+        let src = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        exports.extend(export_re.captures_iter(&src).map(|c| c[1].to_owned()));
+        for c in alias_re.captures_iter(&src) {
+            aliases
+                .entry(c[1].to_owned())
+                .or_insert_with(|| c[2].trim().to_owned());
+        }
+        for c in fn_re.captures_iter(&src) {
+            let params = split_top_level(&c[2])
+                .into_iter()
+                .map(|param| {
+                    let param = param.strip_prefix("mut ").unwrap_or(param);
+                    match param.split_once(':') {
+                        Some((name, ty)) => (name.trim().to_owned(), ty.trim().to_owned()),
+                        None => (String::new(), param.to_owned()),
+                    }
+                })
+                .collect();
+            functions.push(ExportedFn {
+                name: c[1].to_owned(),
+                params,
+                ret: c.get(3).map(|m| m.as_str().trim().to_owned()),
+            });
+        }
+    }
+    exports.sort();
+    exports.dedup();
+
+    Ok(CrateInfo {
+        package_name,
+        lib_name,
+        exports,
+        functions,
+        aliases,
+    })
+}
+
+fn is_path(ty: &str) -> bool {
+    !ty.is_empty()
+        && ty
+            .trim_start_matches("::")
+            .split("::")
+            .all(|seg| !seg.is_empty() && seg.chars().all(|c| c.is_alphanumeric() || c == '_'))
+}
+
+fn last_segment(ty: &str) -> &str {
+    ty.rsplit("::").next().unwrap_or(ty)
+}
+
+/// Get the type to declare a scalar of type `name` as, and whether it is an integer.
+fn scalar(name: &str) -> Option<(String, bool)> {
+    match name {
+        "c_char" | "c_schar" | "c_uchar" | "c_short" | "c_ushort" | "c_int" | "c_uint"
+        | "c_long" | "c_ulong" | "c_longlong" | "c_ulonglong" | "size_t" | "ssize_t" => {
+            Some((format!("::libc::{}", name), true))
+        }
+        "c_float" | "c_double" => Some((format!("::libc::{}", name), false)),
+        "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {
+            Some((name.to_owned(), true))
+        }
+        "f32" | "f64" | "bool" => Some((name.to_owned(), false)),
+        _ => None,
+    }
+}
+
+/// Follow the type aliases in `aliases` from the path type `ty` to a type that isn't an alias.
+fn resolve_alias<'a>(mut ty: &'a str, aliases: &'a HashMap<String, String>) -> &'a str {
+    // Bound the number of steps, in case of aliases to themselves in different modules.
+    for _ in 0..16 {
+        let name = last_segment(ty);
+        if !is_path(ty) || scalar(name).is_some() || name == "c_void" {
+            break;
+        }
+        match aliases.get(name) {
+            Some(target) => ty = target,
+            None => break,
+        }
+    }
+    ty
+}
+
+/// Decide how to fuzz an argument of type `ty`.  Returns `None` for types that aren't supported:
+/// structs, pointers to pointers or structs, function pointers, and so on.
+fn classify(ty: &str, aliases: &HashMap<String, String>) -> Option<ArgKind> {
+    let ty = resolve_alias(ty.trim(), aliases);
+    let pointee = ty
+        .strip_prefix("*const ")
+        .map(|t| ("*const", t))
+        .or_else(|| ty.strip_prefix("*mut ").map(|t| ("*mut", t)));
+    if let Some((ptr, pointee)) = pointee {
+        let pointee = resolve_alias(pointee.trim(), aliases);
+        if !is_path(pointee) {
+            return None;
+        }
+        if last_segment(pointee) == "c_void" {
+            return Some(ArgKind::Buffer {
+                ty: format!("{} ::libc::c_void", ptr),
+                elem: "u8".to_owned(),
+            });
+        }
+        let (elem, _) = scalar(last_segment(pointee))?;
+        return Some(ArgKind::Buffer {
+            ty: format!("{} {}", ptr, elem),
+            elem,
+        });
+    }
+    if !is_path(ty) {
+        return None;
+    }
+    let (ty, is_int) = scalar(last_segment(ty))?;
+    Some(ArgKind::Scalar { ty, is_int })
+}
+
+/// Check if the argument `name` looks like it holds the length of the buffer before it.
+fn is_length_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "n"
+        || ["len", "size", "count", "num"]
+            .iter()
+            .any(|s| name.contains(s))
+}
+
+fn read_compile_commands(path: &Path) -> anyhow::Result<Vec<CSource>> {
+    let json =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let cmds: Vec<Value> = serde_json::from_str(&json)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let mut sources = Vec::new();
+    for cmd in &cmds {
+        let dir = PathBuf::from(cmd["directory"].as_str().unwrap_or("."));
+        let file = match cmd["file"].as_str() {
+            Some(file) => dir.join(file),
+            None => continue,
+        };
+        if file
+            .extension()
+            .map_or(false, |ext| ext == "s" || ext == "S")
+        {
+            continue;
+        }
+        let args = match (&cmd["arguments"], cmd["command"].as_str()) {
+            (Value::Array(args), _) => args
+                .iter()
+                .filter_map(|a| a.as_str().map(str::to_owned))
+                .collect(),
+            (_, Some(command)) => shlex::split(command)
+                .ok_or_else(|| anyhow!("failed to split command: {}", command))?,
+            _ => Vec::new(),
+        };
+
+        // Keep only the flags that affect how the source is preprocessed and parsed.  Include
+        // paths are made absolute, since the harness is built in a different directory.
+        let abs = |p: &str| dir.join(p).display().to_string();
+        let mut flags = Vec::new();
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-I" | "-isystem" | "-iquote" | "-include" => {
+                    if let Some(p) = args.next() {
+                        flags.push(arg.clone());
+                        flags.push(abs(p));
+                    }
+                }
+                "-D" | "-U" => {
+                    if let Some(d) = args.next() {
+                        flags.push(format!("{}{}", arg, d));
+                    }
+                }
+                _ => {
+                    if let Some(p) = arg.strip_prefix("-I") {
+                        flags.push(format!("-I{}", abs(p)));
+                    } else if ["-D", "-U", "-std="].iter().any(|f| arg.starts_with(f)) {
+                        flags.push(arg.clone());
+                    }
+                }
+            }
+        }
+        sources.push(CSource { file, flags });
+    }
+    Ok(sources)
+}
+
+fn write_file(path: &Path, contents: &str, overwrite: bool) -> anyhow::Result<()> {
+    if path.exists() && !overwrite {
+        eprintln!(
+            "Skipping existing file {} (use --overwrite-existing to replace it)",
+            path.display()
+        );
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn cargo_toml(info: &CrateInfo, crate_dir: &Path, targets: &[&ExportedFn]) -> String {
+    let mut s = String::new();
+    writeln!(s, "[package]").unwrap();
+    writeln!(s, "name = \"{}-fuzz\"", info.package_name).unwrap();
+    writeln!(s, "version = \"0.0.0\"").unwrap();
+    writeln!(s, "publish = false").unwrap();
+    writeln!(s, "edition = \"2021\"").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "[package.metadata]\ncargo-fuzz = true\n").unwrap();
+    writeln!(s, "[dependencies]").unwrap();
+    writeln!(s, "libc = \"0.2\"").unwrap();
+    writeln!(s, "libfuzzer-sys = \"0.4\"").unwrap();
+    writeln!(
+        s,
+        "{} = {{ path = {:?} }}",
+        info.package_name,
+        crate_dir.display()
+    )
+    .unwrap();
+    writeln!(s, "\n[build-dependencies]\ncc = \"1.0\"").unwrap();
+    writeln!(
+        s,
+        "\n# Prevent this from interfering with workspaces\n[workspace]\nmembers = [\".\"]"
+    )
+    .unwrap();
+    for f in targets {
+        writeln!(s, "\n[[bin]]").unwrap();
+        writeln!(s, "name = \"{}\"", f.name).unwrap();
+        writeln!(s, "path = \"fuzz_targets/{}.rs\"", f.name).unwrap();
+        writeln!(s, "test = false\ndoc = false").unwrap();
+    }
+    s
+}
+
+fn build_rs(info: &CrateInfo, sources: &[CSource], link_libs: &[String]) -> String {
+    let mut s = String::new();
+    writeln!(
+        s,
+        "// Compile the original C code, renaming each symbol that the Rust translation"
+    )
+    .unwrap();
+    writeln!(s, "// also exports by adding `{}` to its name.\n", C_PREFIX).unwrap();
+    let mut renamed = info.exports.clone();
+    if !renamed.iter().any(|name| name == "main") {
+        renamed.push("main".to_owned());
+    }
+    writeln!(s, "const RENAMED: &[&str] = &[").unwrap();
+    for name in &renamed {
+        writeln!(s, "    {:?},", name).unwrap();
+    }
+    writeln!(s, "];\n").unwrap();
+    writeln!(s, "const SOURCES: &[(&str, &[&str])] = &[").unwrap();
+    for src in sources {
+        writeln!(
+            s,
+            "    ({:?}, &{:?}),",
+            src.file.display().to_string(),
+            src.flags
+        )
+        .unwrap();
+    }
+    writeln!(s, "];\n").unwrap();
+    writeln!(s, "fn main() {{").unwrap();
+    writeln!(
+        s,
+        "    for (i, &(file, flags)) in SOURCES.iter().enumerate() {{"
+    )
+    .unwrap();
+    writeln!(s, "        let mut build = cc::Build::new();").unwrap();
+    writeln!(s, "        build.file(file).warnings(false);").unwrap();
+    writeln!(
+        s,
+        "        for flag in flags {{\n            build.flag(flag);\n        }}"
+    )
+    .unwrap();
+    writeln!(s, "        for name in RENAMED {{").unwrap();
+    writeln!(
+        s,
+        "            build.define(name, format!(\"{}{{}}\", name).as_str());",
+        C_PREFIX
+    )
+    .unwrap();
+    writeln!(s, "        }}").unwrap();
+    writeln!(
+        s,
+        "        build.compile(&format!(\"{}{{}}\", i));",
+        C_PREFIX
+    )
+    .unwrap();
+    writeln!(s, "    }}").unwrap();
+    for lib in link_libs {
+        writeln!(s, "    println!(\"cargo:rustc-link-lib={}\");", lib).unwrap();
+    }
+    writeln!(s, "}}").unwrap();
+    s
+}
+
+const LIB_RS: &str = r#"//! Helpers for the fuzz targets generated by `c2rust fuzz`.
+
+use std::fmt::Debug;
+
+/// The fuzzer's input, from which argument values are taken in order.  Once it runs out, every
+/// remaining value is zero.
+pub struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input { data }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut buf = [0; N];
+        let n = N.min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        buf
+    }
+
+    pub fn take<T: FromInput>(&mut self) -> T {
+        T::from_input(self)
+    }
+
+    /// Take a buffer of up to `max_len` elements, followed by a zero element so that functions
+    /// expecting a C string don't read past the end.
+    pub fn buffer<T: FromInput + Default>(&mut self, max_len: usize) -> Vec<T> {
+        let len = u16::from_le_bytes(self.bytes()) as usize % (max_len + 1);
+        let mut buf = (0..len).map(|_| self.take()).collect::<Vec<T>>();
+        buf.push(T::default());
+        buf
+    }
+}
+
+pub trait FromInput {
+    fn from_input(input: &mut Input) -> Self;
+}
+
+macro_rules! from_le_bytes {
+    ($($t:ty),*) => {
+        $(impl FromInput for $t {
+            fn from_input(input: &mut Input) -> Self {
+                <$t>::from_le_bytes(input.bytes())
+            }
+        })*
+    };
+}
+
+from_le_bytes!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl FromInput for bool {
+    fn from_input(input: &mut Input) -> Self {
+        input.bytes::<1>()[0] & 1 != 0
+    }
+}
+
+/// Panic if the C and Rust versions of some value differ.  Values are compared by their `Debug`
+/// representations, so that NaNs compare equal to each other.
+pub fn assert_same<T: Debug>(what: &str, c: &T, rust: &T) {
+    let (c, rust) = (format!("{:?}", c), format!("{:?}", rust));
+    assert!(c == rust, "{} differs between C and Rust:\n  C:    {}\n  Rust: {}", what, c, rust);
+}
+"#;
+
+fn fuzz_target(
+    info: &CrateInfo,
+    f: &ExportedFn,
+    kinds: &[ArgKind],
+    ret: Option<&ArgKind>,
+    max_buffer_len: usize,
+) -> String {
+    let params = f
+        .params
+        .iter()
+        .zip(kinds)
+        .enumerate()
+        .map(|(i, (_, kind))| match kind {
+            ArgKind::Scalar { ty, .. } | ArgKind::Buffer { ty, .. } => format!("a{}: {}", i, ty),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let ret_decl = match ret {
+        Some(ArgKind::Scalar { ty, .. }) => format!(" -> {}", ty),
+        _ => String::new(),
+    };
+
+    let mut s = String::new();
+    writeln!(s, "#![no_main]\n").unwrap();
+    writeln!(
+        s,
+        "use {}_fuzz::{{assert_same, Input}};",
+        info.package_name.replace('-', "_")
+    )
+    .unwrap();
+    writeln!(s, "use libfuzzer_sys::fuzz_target;\n").unwrap();
+    writeln!(
+        s,
+        "// Link the Rust translation, which exports `{}`.",
+        f.name
+    )
+    .unwrap();
+    writeln!(s, "use {} as _;\n", info.lib_name).unwrap();
+    writeln!(s, "extern \"C\" {{").unwrap();
+    writeln!(s, "    #[link_name = \"{}{}\"]", C_PREFIX, f.name).unwrap();
+    writeln!(s, "    fn c_{}({}){};\n", f.name, params, ret_decl).unwrap();
+    writeln!(s, "    #[link_name = \"{}\"]", f.name).unwrap();
+    writeln!(s, "    fn rust_{}({}){};", f.name, params, ret_decl).unwrap();
+    writeln!(s, "}}\n").unwrap();
+
+    writeln!(s, "fuzz_target!(|data: &[u8]| {{").unwrap();
+    writeln!(s, "    let mut input = Input::new(data);").unwrap();
+    let mut c_args = Vec::new();
+    let mut rust_args = Vec::new();
+    let mut buffers = Vec::new();
+    let mut prev_buffer: Option<usize> = None;
+    for (i, ((name, _), kind)) in f.params.iter().zip(kinds).enumerate() {
+        match kind {
+            ArgKind::Scalar { ty, is_int } => {
+                match prev_buffer {
+                    Some(b) if *is_int && is_length_name(name) => {
+                        writeln!(s, "    let a{} = (c_a{}.len() - 1) as {};", i, b, ty).unwrap()
+                    }
+                    _ => writeln!(s, "    let a{}: {} = input.take();", i, ty).unwrap(),
+                }
+                c_args.push(format!("a{}", i));
+                rust_args.push(format!("a{}", i));
+                prev_buffer = None;
+            }
+            ArgKind::Buffer { elem, .. } => {
+                writeln!(
+                    s,
+                    "    let mut c_a{}: Vec<{}> = input.buffer({});",
+                    i, elem, max_buffer_len
+                )
+                .unwrap();
+                writeln!(s, "    let mut rust_a{} = c_a{}.clone();", i, i).unwrap();
+                c_args.push(format!("c_a{}.as_mut_ptr() as _", i));
+                rust_args.push(format!("rust_a{}.as_mut_ptr() as _", i));
+                buffers.push((i, name));
+                prev_buffer = Some(i);
+            }
+        }
+    }
+    writeln!(s).unwrap();
+    let ret_bind = |side: &str| match ret {
+        Some(_) => format!("let {}_ret = ", side),
+        None => String::new(),
+    };
+    writeln!(
+        s,
+        "    {}unsafe {{ c_{}({}) }};",
+        ret_bind("c"),
+        f.name,
+        c_args.join(", ")
+    )
+    .unwrap();
+    writeln!(
+        s,
+        "    {}unsafe {{ rust_{}({}) }};\n",
+        ret_bind("rust"),
+        f.name,
+        rust_args.join(", ")
+    )
+    .unwrap();
+    if ret.is_some() {
+        writeln!(s, "    assert_same(\"return value\", &c_ret, &rust_ret);").unwrap();
+    }
+    for (i, name) in buffers {
+        writeln!(
+            s,
+            "    assert_same(\"buffer `{}` after the call\", &c_a{}, &rust_a{});",
+            name, i, i
+        )
+        .unwrap();
+    }
+    writeln!(s, "}});").unwrap();
+    s
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let crate_dir = fs::canonicalize(&args.crate_dir)
+        .with_context(|| format!("failed to find {}", args.crate_dir.display()))?;
+    let output_dir = args
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| crate_dir.join("fuzz"));
+
+    let info = read_crate(&crate_dir)?;
+    let sources = read_compile_commands(&args.compile_commands)?;
+
+    for name in &args.functions {
+        if !info.functions.iter().any(|f| f.name == *name) {
+            bail!(
+                "no exported function named `{}` in {}",
+                name,
+                crate_dir.display()
+            );
+        }
+    }
+
+    let mut targets = Vec::new();
+    let mut target_srcs = Vec::new();
+    for f in &info.functions {
+        let selected = args.functions.is_empty() || args.functions.contains(&f.name);
+        if !selected {
+            continue;
+        }
+        let kinds = f
+            .params
+            .iter()
+            .map(|(name, ty)| {
+                classify(ty, &info.aliases)
+                    .ok_or_else(|| format!("argument `{}` has unsupported type `{}`", name, ty))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let ret = match f.ret {
+            Some(ref ty) => match classify(ty, &info.aliases) {
+                Some(kind @ ArgKind::Scalar { .. }) => Ok(Some(kind)),
+                _ => Err(format!("return type `{}` is unsupported", ty)),
+            },
+            None => Ok(None),
+        };
+        match (kinds, ret) {
+            (Ok(kinds), Ok(ret)) => {
+                target_srcs.push(fuzz_target(
+                    &info,
+                    f,
+                    &kinds,
+                    ret.as_ref(),
+                    args.max_buffer_len,
+                ));
+                targets.push(f);
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                if !args.functions.is_empty() {
+                    bail!("can't fuzz `{}`: {}", f.name, e);
+                }
+                eprintln!("Skipping `{}`: {}", f.name, e);
+            }
+        }
+    }
+    if targets.is_empty() {
+        bail!("no functions to fuzz");
+    }
+
+    let overwrite = args.overwrite_existing;
+    write_file(
+        &output_dir.join("Cargo.toml"),
+        &cargo_toml(&info, &crate_dir, &targets),
+        overwrite,
+    )?;
+    write_file(
+        &output_dir.join("build.rs"),
+        &build_rs(&info, &sources, &args.link_libs),
+        overwrite,
+    )?;
+    write_file(&output_dir.join("src/lib.rs"), LIB_RS, overwrite)?;
+    write_file(
+        &output_dir.join(".gitignore"),
+        "target\ncorpus\nartifacts\ncoverage\n",
+        overwrite,
+    )?;
+    for (f, src) in targets.iter().zip(&target_srcs) {
+        let path = output_dir
+            .join("fuzz_targets")
+            .join(format!("{}.rs", f.name));
+        write_file(&path, src, overwrite)?;
+    }
+    eprintln!(
+        "Generated {} fuzz target(s) in {}; run one with `cargo fuzz run {}`",
+        targets.len(),
+        output_dir.display(),
+        targets[0].name
+    );
+    Ok(())
+}
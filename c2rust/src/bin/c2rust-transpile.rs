@@ -1,9 +1,12 @@
 use clap::{Parser, ValueEnum};
 use log::LevelFilter;
 use regex::Regex;
-use std::{fs, path::PathBuf};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::{fs, process};
 
-use c2rust_transpile::{Diagnostic, ReplaceMode, TranspilerConfig};
+use c2rust_transpile::cfg_variants::CfgVariant;
+use c2rust_transpile::{Diagnostic, ReplaceMode, SystemLibs, TranspilerConfig, WasmTarget};
 
 #[derive(Debug, Parser)]
 #[clap(
@@ -48,6 +51,34 @@ struct Args {
     #[clap(long)]
     translate_fn_macros: bool,
 
+    /// Translate counted C for loops into Rust for loops over ranges, where that doesn't change their behavior
+    #[clap(long)]
+    translate_for_loops: bool,
+
+    /// Generate a main function that passes the arguments and environment to the C main as owned buffers instead of leaked CStrings
+    #[clap(long)]
+    idiomatic_main: bool,
+
+    /// Generate a safe `run(args: &[&str]) -> i32` function that runs the C main with the given arguments (implies --idiomatic-main)
+    #[clap(long)]
+    emit_main_run_fn: bool,
+
+    /// Translate errno accesses into uses of a thread-local errno from the c2rust-errno crate instead of the C library's errno
+    #[clap(long)]
+    errno_shim: bool,
+
+    /// Translate calls to C library functions that have exact Rust std equivalents, like abs, exit and sqrt, into calls to those equivalents instead of libc
+    #[clap(long)]
+    translate_libc_idioms: bool,
+
+    /// Import the declarations of the zlib, OpenSSL, SQLite and libcurl headers from the libz-sys, openssl-sys, libsqlite3-sys and curl-sys crates instead of translating them
+    #[clap(long)]
+    use_sys_crates: bool,
+
+    /// Name the types of anonymous struct and union members after their enclosing types, and generate accessor methods (and a Deref impl, for a single anonymous struct) for their fields
+    #[clap(long)]
+    flatten_anonymous_members: bool,
+
     /// Disable relooping function bodies incrementally
     #[clap(long)]
     no_incremental_relooper: bool,
@@ -84,7 +115,7 @@ struct Args {
     #[clap(long = "ddebug-labels")]
     debug_labels: bool,
 
-    /// Path to compile_commands.json, or a list of source files
+    /// Path to compile_commands.json, a CMake, Meson, Autotools or Bazel project (its directory, or its CMakeLists.txt, meson.build, configure or WORKSPACE), or a list of source files
     #[clap(parse(from_os_str), multiple_values = true)]
     compile_commands: Vec<PathBuf>,
 
@@ -100,6 +131,26 @@ struct Args {
     #[clap(short = 'e', long)]
     emit_build_files: bool,
 
+    /// How to link in the system libraries the C code was linked with: with -sys crates for the ones that have one (sys-crates), with pkg-config from build.rs for the ones it knows (pkg-config, also used by sys-crates), or directly
+    #[clap(long, value_enum, default_value_t = SystemLibsMode::SysCrates)]
+    system_libs: SystemLibsMode,
+
+    /// Emit a Cargo workspace with a crate for each library and each binary, and a crate for the types they share (implies -e/--emit-build-files)
+    #[clap(long)]
+    emit_workspace: bool,
+
+    /// Build library crates as staticlibs and cdylibs that can replace the C libraries, with a build.rs generating a C header for their exported functions and variables with cbindgen (implies -e/--emit-build-files)
+    #[clap(long)]
+    emit_capi: bool,
+
+    /// Number of files to translate in parallel, or 0 for the number of CPUs
+    #[clap(short = 'j', long, value_name = "N", default_value = "0")]
+    jobs: usize,
+
+    /// Free the statements and expressions of each function once it's translated, to translate large files in less memory
+    #[clap(long)]
+    low_memory: bool,
+
     /// Path to output directory. Rust sources will be emitted in DIR/src/ and build files will be emitted in DIR/.
     #[clap(short = 'o', long, value_name = "DIR")]
     output_dir: Option<PathBuf>,
@@ -108,6 +159,22 @@ struct Args {
     #[clap(short = 'f', long)]
     filter: Option<Regex>,
 
+    /// Compile files matching this regex from the generated build.rs with the cc crate instead of translating them, translating only their declarations
+    #[clap(long, value_name = "REGEX")]
+    keep_c: Option<Regex>,
+
+    /// Compile Objective-C (.m) files from the generated build.rs instead of skipping them, declaring the C functions and variables they define
+    #[clap(long)]
+    skip_objc: bool,
+
+    /// Only translate the functions matching this regex, declaring the others and compiling the C files defining them from the generated build.rs
+    #[clap(long, value_name = "REGEX")]
+    filter_functions: Option<Regex>,
+
+    /// Only translate the functions annotated with __attribute__((annotate("c2rust_translate"))), like --filter-functions
+    #[clap(long)]
+    filter_annotated_functions: bool,
+
     /// Fail to translate a module when a portion is not able to be translated
     #[clap(long)]
     fail_on_error: bool,
@@ -148,6 +215,10 @@ struct Args {
     #[clap(long)]
     preserve_unused_functions: bool,
 
+    /// Translate the given headers on their own into a crate of all their types, constants, function bindings and static inline functions (implies --translate-const-macros)
+    #[clap(long)]
+    header_only: bool,
+
     /// Logging level
     #[clap(long, default_value_t = LevelFilter::Warn)]
     log_level: LevelFilter,
@@ -155,6 +226,70 @@ struct Args {
     /// Fail when the control-flow graph generates branching constructs
     #[clap(long)]
     fail_on_multiple: bool,
+
+    /// Mark translated items with #[c2rust::src] attributes giving their C source locations, and write a NAME.rs.map source map next to each NAME.rs
+    #[clap(long)]
+    emit_source_map: bool,
+
+    /// Write a NAME.renames.json manifest next to each NAME.rs, listing the C identifiers that were renamed (e.g. because they are Rust keywords) with their Rust and symbol names
+    #[clap(long)]
+    emit_rename_map: bool,
+
+    /// Format the translated code with rustfmt, which must be installed
+    #[clap(long)]
+    format: bool,
+
+    /// Also translate each file in another preprocessor configuration, given as KEY=VALUE:ARGS (e.g. feature=ssl:-DUSE_SSL or target_os=windows:-D_WIN32), and guard items that differ between configurations with #[cfg(KEY = "VALUE")]
+    #[clap(
+        long,
+        value_name = "KEY=VALUE:ARGS",
+        multiple = true,
+        number_of_values = 1
+    )]
+    cfg_variant: Vec<CfgVariant>,
+
+    /// Translate for WebAssembly instead of the host: wasm32-unknown-unknown (unknown), using a libc module of the crate's own, or wasm32-wasi (wasi). Pass a wasm C sysroot to clang after --, e.g. -- --sysroot=/opt/wasi-sysroot
+    #[clap(long, value_enum, value_name = "TARGET")]
+    wasm_target: Option<WasmTargetMode>,
+
+    /// Directory to configure a CMake project in [default: c2rust-build, next to CMakeLists.txt]
+    #[clap(long, value_name = "DIR")]
+    cmake_build_dir: Option<PathBuf>,
+
+    /// Extra argument to pass to cmake when configuring a CMake project, e.g. -DUSE_SSL=ON
+    #[clap(
+        long,
+        multiple = true,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    cmake_arg: Vec<String>,
+
+    /// Directory to configure a Meson project in [default: c2rust-build, next to meson.build]
+    #[clap(long, value_name = "DIR")]
+    meson_build_dir: Option<PathBuf>,
+
+    /// Extra argument to pass to meson setup when configuring a Meson project, e.g. -Dssl=enabled
+    #[clap(
+        long,
+        multiple = true,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    meson_arg: Vec<String>,
+
+    /// Extra argument to pass to ./configure when configuring an Autotools project, e.g. --with-ssl
+    #[clap(
+        long,
+        multiple = true,
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    configure_arg: Vec<String>,
+
+    /// Target pattern of the cc_library and cc_binary targets to translate in a Bazel workspace, which also get BUILD.bazel files with rules_rust rules
+    #[clap(long, value_name = "PATTERN", default_value = "//...")]
+    bazel_targets: String,
 }
 
 #[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
@@ -164,7 +299,79 @@ enum InvalidCodes {
     CompileError,
 }
 
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
+#[clap(rename_all = "kebab-case")]
+enum SystemLibsMode {
+    Link,
+    PkgConfig,
+    SysCrates,
+}
+
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
+#[clap(rename_all = "kebab-case")]
+enum WasmTargetMode {
+    Unknown,
+    Wasi,
+}
+
+/// Build systems whose projects we can get compile commands from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildSystem {
+    CMake,
+    Meson,
+    Autotools,
+    Bazel,
+}
+
+impl BuildSystem {
+    /// The files that mark a directory as a project using this build system
+    fn project_files(self) -> &'static [&'static str] {
+        match self {
+            BuildSystem::CMake => &["CMakeLists.txt"],
+            BuildSystem::Meson => &["meson.build"],
+            BuildSystem::Autotools => &["configure", "Makefile"],
+            BuildSystem::Bazel => &["MODULE.bazel", "WORKSPACE.bazel", "WORKSPACE"],
+        }
+    }
+
+    /// Find the build system and directory of the project at `path`, which is either the
+    /// project's directory or one of its project files
+    fn detect(path: &Path) -> Option<(BuildSystem, PathBuf)> {
+        let all = [
+            BuildSystem::CMake,
+            BuildSystem::Meson,
+            BuildSystem::Autotools,
+            BuildSystem::Bazel,
+        ];
+        let is_project_file = |bs: &BuildSystem| {
+            bs.project_files()
+                .iter()
+                .any(|&file| path.file_name() == Some(OsStr::new(file)))
+        };
+        if let Some(bs) = all.into_iter().find(is_project_file) {
+            let dir = path.parent().unwrap();
+            let dir = if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir.to_owned()
+            };
+            return Some((bs, dir));
+        }
+        let bs = all.into_iter().find(|bs| {
+            bs.project_files()
+                .iter()
+                .any(|file| path.join(file).is_file())
+        })?;
+        Some((bs, path.to_owned()))
+    }
+}
+
 fn main() {
+    // We're the compiler that `make` runs while getting compile commands for an Autotools project
+    if let Some(code) = c2rust_transpile::run_as_compiler_wrapper() {
+        process::exit(code);
+    }
+
     let args = Args::parse();
 
     // Build a TranspilerConfig from the command line
@@ -183,6 +390,10 @@ fn main() {
         fail_on_error: args.fail_on_error,
         fail_on_multiple: args.fail_on_multiple,
         filter: args.filter,
+        keep_c: args.keep_c,
+        skip_objc: args.skip_objc,
+        filter_functions: args.filter_functions,
+        filter_annotated_functions: args.filter_annotated_functions,
         debug_relooper_labels: args.debug_labels,
         prefix_function_names: args.prefix_function_names,
 
@@ -198,8 +409,16 @@ fn main() {
 
         translate_const_macros: args.translate_const_macros,
         translate_fn_macros: args.translate_fn_macros,
+        translate_for_loops: args.translate_for_loops,
+        idiomatic_main: args.idiomatic_main,
+        emit_main_run_fn: args.emit_main_run_fn,
+        errno_shim: args.errno_shim,
+        translate_libc_idioms: args.translate_libc_idioms,
+        use_sys_crates: args.use_sys_crates,
+        flatten_anonymous_members: args.flatten_anonymous_members,
         disable_refactoring: args.disable_refactoring,
         preserve_unused_functions: args.preserve_unused_functions,
+        header_only: args.header_only,
 
         use_c_loop_info: !args.ignore_c_loop_info,
         use_c_multiple_info: !args.ignore_c_multiple_info,
@@ -211,16 +430,38 @@ fn main() {
         emit_build_files: args.emit_build_files,
         output_dir: args.output_dir,
         binaries: args.binary.unwrap_or_default(),
+        system_libs: match args.system_libs {
+            SystemLibsMode::Link => SystemLibs::Link,
+            SystemLibsMode::PkgConfig => SystemLibs::PkgConfig,
+            SystemLibsMode::SysCrates => SystemLibs::SysCrates,
+        },
+        emit_workspace: args.emit_workspace,
+        emit_capi: args.emit_capi,
+        bazel_workspace: None,
+        jobs: args.jobs,
+        low_memory: args.low_memory,
         panic_on_translator_failure: args.invalid_code == InvalidCodes::Panic,
         replace_unsupported_decls: ReplaceMode::Extern,
         emit_no_std: args.emit_no_std,
         enabled_warnings: args.warn.into_iter().collect(),
         log_level: args.log_level,
+        emit_source_map: args.emit_source_map,
+        emit_rename_map: args.emit_rename_map,
+        format: args.format,
+        cfg_variants: args.cfg_variant,
+        wasm_target: args.wasm_target.map(|target| match target {
+            WasmTargetMode::Unknown => WasmTarget::Unknown,
+            WasmTargetMode::Wasi => WasmTarget::Wasi,
+        }),
     };
-    // binaries imply emit-build-files
-    if !tcfg.binaries.is_empty() {
+    // binaries, emit-workspace and emit-capi imply emit-build-files
+    if !tcfg.binaries.is_empty() || tcfg.emit_workspace || tcfg.emit_capi {
         tcfg.emit_build_files = true
     };
+    // header-only implies translate-const-macros
+    if tcfg.header_only {
+        tcfg.translate_const_macros = true
+    };
     // emit-build-files implies emit-modules
     if tcfg.emit_build_files {
         tcfg.emit_modules = true
@@ -228,7 +469,43 @@ fn main() {
 
     let mut created_temp_compile_commands = false;
 
-    let compile_commands = if args.compile_commands.len() == 1
+    let project = match &args.compile_commands[..] {
+        [path] => BuildSystem::detect(path),
+        _ => None,
+    };
+
+    let compile_commands = if let Some((build_system, source_dir)) = project {
+        // Configure the project, and translate what it builds
+        let source_dir = fs::canonicalize(&source_dir)
+            .unwrap_or_else(|e| panic!("Failed to canonicalize path: {:?}", e));
+        let build_dir =
+            |dir: Option<PathBuf>| dir.unwrap_or_else(|| source_dir.join("c2rust-build"));
+        let cc_db = match build_system {
+            BuildSystem::CMake => c2rust_transpile::cmake_compile_commands(
+                &source_dir,
+                &build_dir(args.cmake_build_dir),
+                &args.cmake_arg,
+            ),
+            BuildSystem::Meson => c2rust_transpile::meson_compile_commands(
+                &source_dir,
+                &build_dir(args.meson_build_dir),
+                &args.meson_arg,
+            ),
+            BuildSystem::Autotools => {
+                c2rust_transpile::autotools_compile_commands(&source_dir, &args.configure_arg)
+            }
+            BuildSystem::Bazel => {
+                tcfg.bazel_workspace = Some(source_dir.clone());
+                c2rust_transpile::bazel_compile_commands(&source_dir, &args.bazel_targets)
+            }
+        };
+        cc_db.unwrap_or_else(|e| {
+            panic!(
+                "Failed to get compile commands from {:?} project: {}",
+                build_system, e
+            )
+        })
+    } else if args.compile_commands.len() == 1
         && args.compile_commands[0].extension() == Some(std::ffi::OsStr::new("json"))
     {
         // Only one file provided and it's a JSON file